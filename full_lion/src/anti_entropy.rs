@@ -0,0 +1,189 @@
+use square_reed_solomon::bitfield::CellBitfield;
+use square_reed_solomon::verifier::digests_equal;
+
+use anyhow::{bail, Result};
+use lion_roars::dht::PeerId;
+use lion_roars::messages::LionMessages;
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Most heights a single sync summary covers; a peer further behind catches up
+/// over several rounds
+pub const MAX_SYNC_HEIGHTS: usize = 256;
+
+/// Heights past the local tip, or the latest signed header seen, a sync round
+/// asks about, so that no peer can move the tip arbitrarily far ahead
+pub const MAX_SYNC_AHEAD: u64 = 16;
+
+/// Which full nodes to sync with, and how often. Each round syncs with the next
+/// peer in turn over the heights from `depth` below the local tip onwards.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AntiEntropyPolicy {
+    pub peers: Vec<(SocketAddr, Option<PeerId>)>,
+    pub interval: Duration,
+    pub depth: u64,
+}
+
+/// What a full node holds at one height: the data root it committed to and
+/// the cells it can serve
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncEntry {
+    pub height: u64,
+    pub data_root: Vec<u8>,
+    pub held: CellBitfield,
+}
+
+/// How two full nodes' holdings over the same heights differ
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SyncDiff {
+    /// Heights the peer holds completely and this node holds only part of or never
+    /// stored. Heights this node pruned are not pulled again.
+    pub pull: Vec<u64>,
+    /// Heights this node holds completely and the peer does not
+    pub ahead: Vec<u64>,
+    /// Heights at which the two nodes hold squares under different data roots
+    pub conflicts: Vec<u64>,
+}
+
+impl SyncDiff {
+    pub fn compare(local: &[SyncEntry], remote: &[SyncEntry]) -> Self {
+        let mut local: BTreeMap<u64, &SyncEntry> =
+            local.iter().map(|entry| (entry.height, entry)).collect();
+        let mut diff = SyncDiff::default();
+        for theirs in remote {
+            match local.remove(&theirs.height) {
+                Some(ours) if !digests_equal(&ours.data_root, &theirs.data_root) => {
+                    diff.conflicts.push(theirs.height)
+                }
+                Some(ours) if is_complete(&ours.held) && !is_complete(&theirs.held) => {
+                    diff.ahead.push(theirs.height)
+                }
+                // partly held squares are completed, pruned ones are not
+                Some(ours)
+                    if ours.held.length() > 0
+                        && !is_complete(&ours.held)
+                        && is_complete(&theirs.held) =>
+                {
+                    diff.pull.push(theirs.height)
+                }
+                None if is_complete(&theirs.held) => diff.pull.push(theirs.height),
+                _ => {}
+            }
+        }
+        diff.ahead.extend(
+            local
+                .values()
+                .filter(|ours| is_complete(&ours.held))
+                .map(|ours| ours.height),
+        );
+        diff.pull.sort_unstable();
+        diff.ahead.sort_unstable();
+        diff.conflicts.sort_unstable();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pull.is_empty() && self.ahead.is_empty() && self.conflicts.is_empty()
+    }
+}
+
+/// Whether every cell of a square is held. Heights held without cells, e.g.
+/// pruned ones, are summarized with an empty square.
+fn is_complete(held: &CellBitfield) -> bool {
+    held.length() > 0 && held.is_full()
+}
+
+pub fn summary_message(entries: &[SyncEntry]) -> LionMessages {
+    LionMessages::SyncSummary {
+        heights: entries.iter().map(|entry| entry.height).collect(),
+        roots: entries
+            .iter()
+            .map(|entry| entry.data_root.clone())
+            .collect(),
+        bitfields: entries
+            .iter()
+            .map(|entry| entry.held.to_compressed())
+            .collect(),
+    }
+}
+
+/// Entries of a sync summary, refusing bitfields of squares wider than `max_length`
+pub fn summary_entries(msg: LionMessages, max_length: usize) -> Result<Vec<SyncEntry>> {
+    let (heights, roots, bitfields) = match msg {
+        LionMessages::SyncSummary {
+            heights,
+            roots,
+            bitfields,
+        } => (heights, roots, bitfields),
+        other => bail!("Unexpected reply to sync request: {:?}", other),
+    };
+    if roots.len() != heights.len() || bitfields.len() != heights.len() {
+        bail!("Sync summary lists differ in length");
+    }
+    if heights.len() > MAX_SYNC_HEIGHTS {
+        bail!("Sync summary covers more than {} heights", MAX_SYNC_HEIGHTS);
+    }
+    heights
+        .into_iter()
+        .zip(roots)
+        .zip(bitfields)
+        .map(|((height, data_root), bitfield)| {
+            let held = match CellBitfield::from_compressed(&bitfield, max_length) {
+                Ok(held) => held,
+                Err(_) => bail!("Malformed bitfield for height {} in sync summary", height),
+            };
+            Ok(SyncEntry {
+                height,
+                data_root,
+                held,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{summary_entries, summary_message, SyncDiff, SyncEntry};
+    use square_reed_solomon::bitfield::CellBitfield;
+
+    fn entry(height: u64, root: u8, held: CellBitfield) -> SyncEntry {
+        SyncEntry {
+            height,
+            data_root: vec![root; 32],
+            held,
+        }
+    }
+
+    #[test]
+    pub fn diffs_follow_roots_and_bitfields() {
+        let local = vec![
+            entry(1, 1, CellBitfield::full(4)),
+            entry(2, 2, CellBitfield::empty(0)),
+            entry(3, 3, CellBitfield::full(4)),
+            entry(5, 5, CellBitfield::full(4)),
+            entry(6, 6, CellBitfield::full(4)),
+        ];
+        let remote = vec![
+            entry(1, 1, CellBitfield::full(4)),
+            entry(2, 2, CellBitfield::full(4)),
+            entry(3, 9, CellBitfield::full(4)),
+            entry(4, 4, CellBitfield::full(4)),
+            entry(6, 6, CellBitfield::empty(0)),
+        ];
+        // height 2 was pruned locally and is not pulled back
+        let diff = SyncDiff::compare(&local, &remote);
+        assert_eq!(diff.pull, vec![4]);
+        assert_eq!(diff.ahead, vec![5, 6]);
+        assert_eq!(diff.conflicts, vec![3]);
+        assert!(SyncDiff::compare(&local, &local).is_empty());
+
+        assert_eq!(
+            summary_entries(summary_message(&remote), 4).unwrap(),
+            remote
+        );
+        assert!(summary_entries(summary_message(&remote), 2).is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use square_reed_solomon::verifier::digests_equal;
+
+use lion_roars::dht::PeerId;
+use lion_roars::messages::LionMessages;
+
+use std::collections::{HashMap, HashSet};
+
+/// Operations a peer or operator can invoke, each guarded by its own policy
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Method {
+    /// Request a cell sample
+    Sample,
+    /// Pull a whole square, as full nodes syncing with each other do
+    Square,
+    /// Submit a square for storage and serving
+    Submit,
+    /// Read the node's metrics
+    Metrics,
+}
+
+impl Method {
+    /// Method invoked by an incoming message, `None` for replies and handshakes
+    pub fn of(msg: &LionMessages) -> Option<Method> {
+        match msg {
+            LionMessages::SampleRequest { .. }
+            | LionMessages::CellsRequest { .. }
+            | LionMessages::LineRequest { .. }
+            | LionMessages::NamespaceRequest { .. }
+            | LionMessages::BisectionRequest { .. }
+            | LionMessages::AuditChallenge { .. }
+            | LionMessages::AvailabilityRequest { .. }
+            | LionMessages::SyncRequest { .. }
+            | LionMessages::BlobRequest { .. }
+            | LionMessages::StorageChallenge { .. }
+            | LionMessages::CommitmentsRequest { .. }
+            | LionMessages::HeaderRequest { .. } => Some(Method::Sample),
+            LionMessages::SquareRequest { .. } => Some(Method::Square),
+            LionMessages::ExtendedSquare { .. }
+            | LionMessages::OriginalShares { .. }
+            | LionMessages::CellBatch { .. } => Some(Method::Submit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Policy {
+    /// Anyone may call
+    Public,
+    /// Caller must have presented a configured token
+    Token,
+    /// Caller's authenticated peer id must be on the allow list
+    Key,
+    /// Either a token or an allowed key suffices
+    TokenOrKey,
+}
+
+/// What a caller has proven about itself on the current connection or request
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    /// Id authenticated by the transport handshake
+    pub peer: Option<PeerId>,
+    /// Token presented by the caller, only set once it has been validated
+    pub token: Option<Vec<u8>>,
+}
+
+/// Per-method access control. Sampling is public by default, everything else
+/// requires credentials.
+pub struct AuthLayer {
+    policies: HashMap<Method, Policy>,
+    tokens: Vec<Vec<u8>>,
+    keys: HashSet<PeerId>,
+}
+
+impl Default for AuthLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthLayer {
+    pub fn new() -> Self {
+        let policies = HashMap::from([
+            (Method::Sample, Policy::Public),
+            (Method::Square, Policy::TokenOrKey),
+            (Method::Submit, Policy::TokenOrKey),
+            (Method::Metrics, Policy::Token),
+        ]);
+        Self {
+            policies,
+            tokens: vec![],
+            keys: HashSet::new(),
+        }
+    }
+
+    pub fn set_policy(&mut self, method: Method, policy: Policy) {
+        self.policies.insert(method, policy);
+    }
+
+    pub fn add_token(&mut self, token: impl Into<Vec<u8>>) {
+        self.tokens.push(token.into());
+    }
+
+    pub fn authorize_key(&mut self, peer: PeerId) {
+        self.keys.insert(peer);
+    }
+
+    pub fn is_valid_token(&self, token: &[u8]) -> bool {
+        // check every token so timing does not reveal which prefix matched
+        self.tokens
+            .iter()
+            .fold(false, |valid, known| valid | digests_equal(known, token))
+    }
+
+    pub fn authorize(&self, method: Method, credentials: &Credentials) -> bool {
+        let has_token = credentials
+            .token
+            .as_deref()
+            .map_or(false, |token| self.is_valid_token(token));
+        let has_key = credentials
+            .peer
+            .map_or(false, |peer| self.keys.contains(&peer));
+        match self.policies.get(&method).copied().unwrap_or(Policy::Token) {
+            Policy::Public => true,
+            Policy::Token => has_token,
+            Policy::Key => has_key,
+            Policy::TokenOrKey => has_token || has_key,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthLayer, Credentials, Method};
+    use lion_roars::dht::PeerId;
+
+    #[test]
+    pub fn privileged_methods_need_credentials() {
+        let mut auth = AuthLayer::new();
+        auth.add_token("secret");
+        let producer = PeerId([1; 32]);
+        auth.authorize_key(producer);
+
+        let anonymous = Credentials::default();
+        assert!(auth.authorize(Method::Sample, &anonymous));
+        assert!(!auth.authorize(Method::Square, &anonymous));
+        assert!(!auth.authorize(Method::Submit, &anonymous));
+        assert!(!auth.authorize(Method::Metrics, &anonymous));
+
+        let keyed = Credentials {
+            peer: Some(producer),
+            token: None,
+        };
+        assert!(auth.authorize(Method::Square, &keyed));
+        assert!(auth.authorize(Method::Submit, &keyed));
+        assert!(!auth.authorize(Method::Metrics, &keyed));
+
+        let tokened = Credentials {
+            peer: None,
+            token: Some(b"secret".to_vec()),
+        };
+        assert!(auth.authorize(Method::Metrics, &tokened));
+        assert!(!auth.is_valid_token(b"secre"));
+    }
+}
@@ -0,0 +1,112 @@
+use square_reed_solomon::bitfield::CellBitfield;
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Which cells of each height every peer announced it holds, learnt from
+/// availability gossip. Only the heights closest to the tip are remembered.
+pub struct AvailabilityMap<K: Eq + Hash + Clone> {
+    heights: BTreeMap<u64, HashMap<K, CellBitfield>>,
+    /// Most heights remembered; announcements for those furthest from the tip are
+    /// forgotten first
+    capacity: usize,
+}
+
+impl<K: Eq + Hash + Clone> AvailabilityMap<K> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            heights: BTreeMap::new(),
+            capacity,
+        }
+    }
+
+    /// Remember that `peer` holds `bitfield` at `height`, replacing what it
+    /// announced before. Heights further than `capacity` from `tip` are refused, so
+    /// that announcements of made-up heights never push out real ones. Returns
+    /// whether the announcement was kept.
+    pub fn record(&mut self, peer: K, height: u64, bitfield: CellBitfield, tip: u64) -> bool {
+        if height.abs_diff(tip) > self.capacity as u64 {
+            return false;
+        }
+        self.heights
+            .entry(height)
+            .or_default()
+            .insert(peer, bitfield);
+        while self.heights.len() > self.capacity {
+            let furthest = self
+                .heights
+                .keys()
+                .copied()
+                .max_by_key(|known| known.abs_diff(tip))
+                .expect("More heights than the capacity are held");
+            self.heights.remove(&furthest);
+        }
+        self.heights.contains_key(&height)
+    }
+
+    /// Bitfield `peer` last announced for `height`
+    pub fn get(&self, peer: &K, height: u64) -> Option<&CellBitfield> {
+        self.heights.get(&height)?.get(peer)
+    }
+
+    /// Peers announcing cell (rid, cid) at `height`
+    pub fn holders(&self, height: u64, rid: usize, cid: usize) -> Vec<K> {
+        self.heights.get(&height).map_or(vec![], |peers| {
+            peers
+                .iter()
+                .filter(|(_, bitfield)| bitfield.contains(rid, cid))
+                .map(|(peer, _)| peer.clone())
+                .collect()
+        })
+    }
+
+    /// Cells of `height` held by at least one peer, `None` if no peer of a
+    /// matching square size announced any
+    pub fn combined(&self, height: u64, length: usize) -> Option<CellBitfield> {
+        self.heights
+            .get(&height)?
+            .values()
+            .filter(|bitfield| bitfield.length() == length)
+            .fold(None, |acc: Option<CellBitfield>, bitfield| {
+                Some(acc.map_or_else(|| bitfield.clone(), |acc| acc.union(bitfield)))
+            })
+    }
+
+    /// Forget everything `peer` announced, e.g. once it disconnects for good
+    pub fn forget(&mut self, peer: &K) {
+        for peers in self.heights.values_mut() {
+            peers.remove(peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AvailabilityMap;
+    use square_reed_solomon::bitfield::CellBitfield;
+
+    #[test]
+    pub fn holders_follow_announcements() {
+        let mut map = AvailabilityMap::<u8>::new(2);
+        let mut partial = CellBitfield::empty(4);
+        partial.set(1, 2);
+        assert!(map.record(1, 7, partial.clone(), 7));
+        assert!(map.record(2, 7, CellBitfield::full(4), 7));
+        assert_eq!(map.holders(7, 1, 2).len(), 2);
+        assert_eq!(map.holders(7, 0, 0), vec![2]);
+        assert!(map.combined(7, 4).unwrap().is_full());
+        assert_eq!(map.combined(7, 8), None);
+
+        map.forget(&2);
+        assert_eq!(map.combined(7, 4), Some(partial));
+        // only the two heights closest to the tip are remembered
+        assert!(map.record(1, 8, CellBitfield::empty(4), 9));
+        assert!(map.record(1, 9, CellBitfield::empty(4), 9));
+        assert_eq!(map.get(&1, 7), None);
+        assert!(map.get(&1, 9).is_some());
+        // far-off heights push nothing out
+        assert!(!map.record(2, u64::MAX, CellBitfield::full(4), 9));
+        assert!(!map.record(2, 12, CellBitfield::full(4), 9));
+        assert!(map.get(&1, 8).is_some());
+    }
+}
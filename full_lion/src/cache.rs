@@ -0,0 +1,132 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// Bounded map dropping the least recently used entry once more than `capacity`
+/// are held, counting how many lookups it answered
+pub struct LruCache<K: Eq + Hash + Clone, V> {
+    /// Every entry, with when it was last used
+    entries: HashMap<K, (V, u64)>,
+    /// Keys by when they were last used, oldest first
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+    capacity: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            capacity,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Value cached under `key`, now the most recently used
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some((value, used)) => {
+                self.recency.remove(used);
+                *used = self.clock;
+                self.recency.insert(self.clock, key.clone());
+                self.hits += 1;
+                Some(value)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Cache `value` under `key`, dropping the least recently used entries beyond
+    /// the capacity
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.clock)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.clock, key);
+        self.set_capacity(self.capacity);
+    }
+
+    /// Keep at most `capacity` entries from now on, dropping the least recently used
+    /// beyond it
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > self.capacity {
+            match self.recency.pop_first() {
+                Some((_, oldest)) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups answered from the cache since it was created
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Lookups the cache could not answer since it was created
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Hits, misses and entries held in Prometheus text exposition format
+    pub fn metrics(&self, prefix: &str) -> String {
+        format!(
+            "{prefix}_hits {}\n{prefix}_misses {}\n{prefix}_entries {}\n",
+            self.hits,
+            self.misses,
+            self.entries.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LruCache;
+
+    #[test]
+    pub fn least_recently_used_entries_are_dropped() {
+        let mut cache = LruCache::<u8, &str>::new(2);
+        cache.insert(0, "a");
+        cache.insert(1, "b");
+        assert_eq!(cache.get(&0).copied(), Some("a"));
+
+        // 1 was used least recently, so it makes way for 2
+        cache.insert(2, "c");
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(
+            (cache.get(&0).copied(), cache.get(&2).copied()),
+            (Some("a"), Some("c"))
+        );
+        cache.insert(2, "d");
+        assert_eq!((cache.len(), cache.get(&2).copied()), (2, Some("d")));
+        assert_eq!((cache.hits(), cache.misses()), (4, 1));
+        assert!(cache.metrics("rows").contains("rows_misses 1\n"));
+
+        cache.set_capacity(1);
+        assert_eq!((cache.len(), cache.get(&0)), (1, None));
+
+        let mut disabled = LruCache::<u8, &str>::new(0);
+        disabled.insert(0, "a");
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+use crate::store::{line_trees, setup_digest, StoredBlock};
+use square_reed_solomon::codec::{
+    commitment_to_bytes, decode, field_to_bytes, put_bytes, put_u64, take_bytes, take_hash,
+    Validation,
+};
+use square_reed_solomon::merkle::StoredTree;
+use square_reed_solomon::prover::RsSquareProver;
+use square_reed_solomon::verifier::RootVersion;
+
+use anyhow::Result;
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::Commitment;
+use rs_merkle::Hasher;
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::Path;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"LLCKPT04";
+
+/// Append-only log of the line commitments of one square, written as each is
+/// computed so that a node killed mid-commitment resumes where it stopped.
+///
+/// The log starts with the height, a digest of the shares, scale and setup, the
+/// side length and the root version. Row commitments follow in order, each with a
+/// checksum, then column commitments, then the row and column trees once every
+/// line is committed, so that a resumed square rehashes neither. A record cut
+/// short by a crash is dropped on reopening, and a log with a record failing its
+/// checksum is started over.
+///
+/// Storing the block still rehashes its data root from the commitments, a hash
+/// per tree node, to check them against the prover.
+pub struct CommitmentCheckpoint<E: Pairing, H: Hasher> {
+    file: File,
+    height: u64,
+    length: usize,
+    root_version: RootVersion,
+    row_commitments: Vec<Commitment<E>>,
+    col_commitments: Vec<Commitment<E>>,
+    /// Row and column trees, once every line is committed and the trees are logged
+    trees: Option<(StoredTree, StoredTree)>,
+    _hasher_phantom: PhantomData<H>,
+}
+
+impl<E: Pairing, H: Hasher> CommitmentCheckpoint<E, H> {
+    /// Resume the log at `path` if it belongs to this square at `height`, otherwise
+    /// start a fresh one in its place
+    pub fn open(
+        path: impl AsRef<Path>,
+        height: u64,
+        prover: &RsSquareProver<E, H>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let length = prover.shares().len() * prover.scale();
+        let mut preamble = vec![];
+        preamble.extend_from_slice(CHECKPOINT_MAGIC);
+        put_u64(&mut preamble, height);
+        let digest: Vec<u8> = square_digest(prover).into();
+        put_bytes(&mut preamble, &digest);
+        put_u64(&mut preamble, length as u64);
+        let root_version = prover.root_version();
+        put_u64(&mut preamble, root_version.id() as u64);
+
+        let existing = std::fs::read(path).unwrap_or_default();
+        if let Some(body) = existing.strip_prefix(preamble.as_slice()) {
+            if let Some((checkpoint, valid)) =
+                Self::replay(path, height, length, root_version, body)?
+            {
+                // drop whatever partial record follows the last complete one
+                checkpoint.file.set_len((preamble.len() + valid) as u64)?;
+                return Ok(checkpoint);
+            }
+        }
+
+        let mut file = File::create(path)?;
+        file.write_all(&preamble)?;
+        Ok(Self {
+            file: OpenOptions::new().append(true).open(path)?,
+            height,
+            length,
+            root_version,
+            row_commitments: vec![],
+            col_commitments: vec![],
+            trees: None,
+            _hasher_phantom: PhantomData,
+        })
+    }
+
+    /// Rebuild the state logged in `body`, with the number of bytes it spans.
+    /// `None` if the log is inconsistent and must be started over.
+    fn replay(
+        path: &Path,
+        height: u64,
+        length: usize,
+        root_version: RootVersion,
+        body: &[u8],
+    ) -> Result<Option<(Self, usize)>> {
+        let mut bytes = body;
+        let mut commitments = vec![];
+        let mut valid = 0;
+        while commitments.len() < 2 * length {
+            let (com, checksum) = match (take_bytes(&mut bytes), take_hash::<H>(&mut bytes)) {
+                (Ok(com), Ok(checksum)) => (com, checksum),
+                _ => break,
+            };
+            if H::hash(com) != checksum {
+                return Ok(None);
+            }
+            // the log is only ever written by this node
+            match decode::<Commitment<E>>(com, Validation::Trusted) {
+                Ok(com) => commitments.push(com),
+                Err(_) => return Ok(None),
+            }
+            valid = body.len() - bytes.len();
+        }
+
+        let mut trees = None;
+        if commitments.len() == 2 * length {
+            if let (Ok(row_tree), Ok(row_checksum), Ok(col_tree), Ok(col_checksum)) = (
+                take_bytes(&mut bytes),
+                take_hash::<H>(&mut bytes),
+                take_bytes(&mut bytes),
+                take_hash::<H>(&mut bytes),
+            ) {
+                if H::hash(row_tree) != row_checksum || H::hash(col_tree) != col_checksum {
+                    return Ok(None);
+                }
+                match (
+                    StoredTree::from_bytes(row_tree),
+                    StoredTree::from_bytes(col_tree),
+                ) {
+                    (Ok(row_tree), Ok(col_tree))
+                        if row_tree.leaves_len() == length && col_tree.leaves_len() == length =>
+                    {
+                        trees = Some((row_tree, col_tree))
+                    }
+                    _ => return Ok(None),
+                }
+                valid = body.len() - bytes.len();
+            }
+        }
+
+        let col_commitments = commitments.split_off(commitments.len().min(length));
+        Ok(Some((
+            Self {
+                file: OpenOptions::new().append(true).open(path)?,
+                height,
+                length,
+                root_version,
+                row_commitments: commitments,
+                col_commitments,
+                trees,
+                _hasher_phantom: PhantomData,
+            },
+            valid,
+        )))
+    }
+
+    /// Number of line commitments already logged
+    pub fn progress(&self) -> usize {
+        self.row_commitments.len() + self.col_commitments.len()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.trees.is_some()
+    }
+
+    /// Commit to the next line not yet logged, or log the trees once all are.
+    /// Returns `false` when there was nothing left to do.
+    pub fn commit_next(&mut self, prover: &RsSquareProver<E, H>) -> Result<bool> {
+        if self.trees.is_some() {
+            return Ok(false);
+        }
+        let mut record = vec![];
+        if self.row_commitments.len() < self.length {
+            let com = prover.commit_to_row(self.row_commitments.len());
+            put_record::<H>(&mut record, &commitment_to_bytes(&com));
+            self.file.write_all(&record)?;
+            self.row_commitments.push(com);
+        } else if self.col_commitments.len() < self.length {
+            let com = prover.commit_to_col(self.col_commitments.len());
+            put_record::<H>(&mut record, &commitment_to_bytes(&com));
+            self.file.write_all(&record)?;
+            self.col_commitments.push(com);
+        } else {
+            let (row_tree, col_tree) = line_trees::<E, H>(
+                self.root_version,
+                &self.row_commitments,
+                &self.col_commitments,
+            );
+            put_record::<H>(&mut record, &row_tree.to_bytes());
+            put_record::<H>(&mut record, &col_tree.to_bytes());
+            self.file.write_all(&record)?;
+            self.file.sync_data()?;
+            self.trees = Some((row_tree, col_tree));
+        }
+        Ok(true)
+    }
+
+    /// Commit to every line not yet logged, returning the block of `prover` built
+    /// from the logged commitments and trees
+    pub fn commit_all(mut self, prover: RsSquareProver<E, H>) -> Result<StoredBlock<E, H>> {
+        while self.commit_next(&prover)? {}
+        let trees = self
+            .trees
+            .expect("Trees are logged once every line is committed");
+        Ok(StoredBlock::from_trees(
+            self.height,
+            prover,
+            self.row_commitments,
+            self.col_commitments,
+            trees,
+        ))
+    }
+}
+
+/// Identifies the shares, scale and setup a log was written for
+fn square_digest<E: Pairing, H: Hasher>(prover: &RsSquareProver<E, H>) -> H::Hash {
+    let mut bytes = setup_digest::<E, H>(prover.params());
+    put_u64(&mut bytes, prover.scale() as u64);
+    for share in prover.shares() {
+        for val in share {
+            put_bytes(&mut bytes, &field_to_bytes(val));
+        }
+    }
+    H::hash(&bytes)
+}
+
+/// `data` followed by its checksum
+pub(crate) fn put_record<H: Hasher>(out: &mut Vec<u8>, data: &[u8]) {
+    let checksum: Vec<u8> = H::hash(data).into();
+    put_bytes(out, data);
+    put_bytes(out, &checksum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommitmentCheckpoint;
+    use crate::store::StoredBlock;
+    use square_reed_solomon::fixtures::counting_square;
+    use square_reed_solomon::prover::RsSquareProver;
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn interrupted_commitment_resumes() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let path = std::env::temp_dir().join(format!("lazy-lion-{}.ckpt", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut checkpoint = CommitmentCheckpoint::open(&path, 5, &prover).unwrap();
+        for _ in 0..5 {
+            assert!(checkpoint.commit_next(&prover).unwrap());
+        }
+        drop(checkpoint);
+        // a record torn by the crash is discarded
+        let mut log = std::fs::read(&path).unwrap();
+        log.extend([48, 0, 0, 0, 1, 2]);
+        std::fs::write(&path, log).unwrap();
+
+        let checkpoint = CommitmentCheckpoint::open(&path, 5, &prover).unwrap();
+        assert_eq!(checkpoint.progress(), 5);
+        let again =
+            RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, 2, prover.shared_params());
+        let fresh = StoredBlock::new(5, again);
+        let block = checkpoint.commit_all(prover).unwrap();
+        assert_eq!(block.row_commitments, fresh.row_commitments);
+        assert_eq!(block.col_commitments, fresh.col_commitments);
+        assert_eq!(block.header, fresh.header);
+
+        // a log holding the trees resumes complete, with the block's trees
+        let checkpoint = CommitmentCheckpoint::open(&path, 5, &block.prover).unwrap();
+        assert!(checkpoint.is_complete());
+        let resumed = checkpoint.commit_all(fresh.prover).unwrap();
+        assert_eq!(resumed.row_tree, block.row_tree);
+        assert_eq!(resumed.col_tree, block.col_tree);
+
+        // a record damaged on disk fails its checksum and the log is started over
+        let mut log = std::fs::read(&path).unwrap();
+        let damaged = log.len() - 100;
+        log[damaged] ^= 1;
+        std::fs::write(&path, log).unwrap();
+        let checkpoint = CommitmentCheckpoint::open(&path, 5, &block.prover).unwrap();
+        assert_eq!(checkpoint.progress(), 0);
+
+        // a log for another height is started over
+        let checkpoint = CommitmentCheckpoint::open(&path, 6, &block.prover).unwrap();
+        assert_eq!(checkpoint.progress(), 0);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,502 @@
+use crate::store::{PartialBlock, StoredBlock};
+use square_reed_solomon::bitfield::CellBitfield;
+use square_reed_solomon::codec::{
+    commitment_from_bytes, commitment_to_bytes, line_from_bytes, line_to_bytes,
+};
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::prover::RsSquareProver;
+use square_reed_solomon::rs_square::is_power_of_two;
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::verifier::{digests_equal, verifier_key, verify_cell_samples};
+
+use anyhow::{bail, Result};
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10;
+use lion_roars::header::Header;
+use lion_roars::messages::LionMessages;
+use rand::rngs::OsRng;
+use rs_merkle::Hasher;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisseminationMode {
+    /// Gossip every cell of the extended square
+    ExtendedSquare,
+    /// Gossip only the original shares and let receivers re-extend them,
+    /// cutting producer upload by a factor of scale^2
+    OriginalShares,
+}
+
+pub fn dissemination_message<E: Pairing, H: Hasher>(
+    prover: &RsSquareProver<E, H>,
+    height: u64,
+    mode: DisseminationMode,
+) -> LionMessages {
+    let scale = prover.scale();
+    let length = prover.shares().len() * scale;
+    let row_commitments = (0..length)
+        .map(|rid| commitment_to_bytes(&prover.commit_to_row(rid)))
+        .collect();
+    let col_commitments = (0..length)
+        .map(|cid| commitment_to_bytes(&prover.commit_to_col(cid)))
+        .collect();
+    let root: Vec<u8> = prover.root().into();
+
+    match mode {
+        DisseminationMode::ExtendedSquare => LionMessages::ExtendedSquare {
+            height,
+            scale,
+            rows: (0..length)
+                .map(|rid| line_to_bytes(&prover.square().row_vals(rid)))
+                .collect(),
+            row_commitments,
+            col_commitments,
+            root,
+        },
+        DisseminationMode::OriginalShares => LionMessages::OriginalShares {
+            height,
+            scale,
+            shares: prover
+                .shares()
+                .iter()
+                .map(|share| line_to_bytes(share))
+                .collect(),
+            row_commitments,
+            col_commitments,
+            root,
+        },
+    }
+}
+
+/// Dissemination message for a stored block, reusing its cached line commitments
+pub fn stored_message<E: Pairing, H: Hasher>(
+    block: &StoredBlock<E, H>,
+    mode: DisseminationMode,
+) -> LionMessages {
+    let prover = &block.prover;
+    let height = block.header.height;
+    let scale = prover.scale();
+    let row_commitments = block
+        .row_commitments
+        .iter()
+        .map(commitment_to_bytes)
+        .collect();
+    let col_commitments = block
+        .col_commitments
+        .iter()
+        .map(commitment_to_bytes)
+        .collect();
+    let root = block.header.data_root.clone();
+
+    match mode {
+        DisseminationMode::ExtendedSquare => LionMessages::ExtendedSquare {
+            height,
+            scale,
+            rows: (0..block.length())
+                .map(|rid| line_to_bytes(&prover.square().row_vals(rid)))
+                .collect(),
+            row_commitments,
+            col_commitments,
+            root,
+        },
+        DisseminationMode::OriginalShares => LionMessages::OriginalShares {
+            height,
+            scale,
+            shares: prover
+                .shares()
+                .iter()
+                .map(|share| line_to_bytes(share))
+                .collect(),
+            row_commitments,
+            col_commitments,
+            root,
+        },
+    }
+}
+
+/// Split of the cells of an extended square between the peers a producer
+/// disseminates to. Every cell goes to exactly one peer, so the producer uploads
+/// each cell once however many peers there are. Cells are striped along
+/// diagonals, giving every peer an even share of every row and column: any
+/// peers together holding the decoding threshold of each row can rebuild the
+/// square from each other.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisseminationPlan {
+    assignments: Vec<CellBitfield>,
+}
+
+impl DisseminationPlan {
+    pub fn new(length: usize, peers: usize) -> Self {
+        let mut assignments = vec![CellBitfield::empty(length); peers];
+        if peers > 0 {
+            for rid in 0..length {
+                for cid in 0..length {
+                    assignments[(rid + cid) % peers].set(rid, cid);
+                }
+            }
+        }
+        Self { assignments }
+    }
+
+    pub fn peers(&self) -> usize {
+        self.assignments.len()
+    }
+
+    pub fn assignment(&self, peer: usize) -> &CellBitfield {
+        &self.assignments[peer]
+    }
+
+    /// Cells assigned to `peer`, in row-major order
+    pub fn cells(&self, peer: usize) -> Vec<(usize, usize)> {
+        let assignment = &self.assignments[peer];
+        (0..assignment.length())
+            .flat_map(|rid| (0..assignment.length()).map(move |cid| (rid, cid)))
+            .filter(|(rid, cid)| assignment.contains(*rid, *cid))
+            .collect()
+    }
+
+    /// Whether the cells assigned to `peers` alone suffice to rebuild the square
+    /// when `n` cells decode a line
+    pub fn is_recoverable_from(&self, peers: &[usize], n: usize) -> bool {
+        peers
+            .iter()
+            .map(|peer| self.assignments[*peer].clone())
+            .reduce(|acc, assignment| acc.union(&assignment))
+            .is_some_and(|held| held.is_recoverable(n))
+    }
+}
+
+/// One peer's share of `block` under a `DisseminationPlan`, as self-verifying
+/// cell samples
+pub fn cell_batch<E: Pairing, H: Hasher>(
+    block: &StoredBlock<E, H>,
+    cells: &[(usize, usize)],
+) -> LionMessages {
+    LionMessages::CellBatch {
+        height: block.header.height,
+        length: block.length(),
+        scale: block.prover.scale(),
+        root: block.header.data_root.clone(),
+        samples: block
+            .sample_cells(cells)
+            .iter()
+            .map(CellSample::to_bytes)
+            .collect(),
+    }
+}
+
+/// Check every sample of a cell batch against `header`, which a producer signed,
+/// in one batched pairing check, and keep them as a partial block. Batches of
+/// squares wider than `max_length` or holding no cells are refused before any
+/// cell is read.
+pub fn accept_cell_batch<E: Pairing, H: Hasher>(
+    msg: LionMessages,
+    header: &Header,
+    params: &kzg10::UniversalParams<E>,
+    max_length: usize,
+) -> Result<PartialBlock> {
+    let (height, length, scale, root, encoded) = match msg {
+        LionMessages::CellBatch {
+            height,
+            length,
+            scale,
+            root,
+            samples,
+        } => (height, length, scale, root, samples),
+        _ => bail!("Not a cell batch"),
+    };
+    if !is_power_of_two(length) || !is_power_of_two(scale) || scale > length {
+        bail!("Square dimensions must be powers of 2");
+    }
+    if length > max_length {
+        bail!("Squares are at most {} wide, not {}", max_length, length);
+    }
+    if encoded.is_empty() || encoded.len() > length * length {
+        bail!("Cell batch holds {} cells", encoded.len());
+    }
+    if height != header.height || !digests_equal(&header.data_root, &root) {
+        bail!("Cell batch is not of the square its signed header commits to");
+    }
+    let data_root = match H::Hash::try_from(root.clone()) {
+        Ok(root) => root,
+        Err(_) => bail!("Root of cell batch is not a hash"),
+    };
+    let root_version = match SquareLayout::root_version_of(&header.layout) {
+        Some(version) => version,
+        None => bail!("Unknown root version at height {}", height),
+    };
+    let samples = encoded
+        .iter()
+        .map(|sample| CellSample::<E, H>::from_bytes(sample))
+        .collect::<Result<Vec<_>, _>>()?;
+    if samples
+        .iter()
+        .any(|sample| sample.rid >= length || sample.cid >= length)
+    {
+        bail!("Cell batch holds cells outside the square");
+    }
+    if !verify_cell_samples(
+        &verifier_key(params),
+        root_version,
+        data_root,
+        length,
+        &samples,
+        &mut OsRng,
+    ) {
+        bail!("Cell batch does not verify against its root");
+    }
+
+    Ok(PartialBlock {
+        header: header.clone(),
+        length,
+        scale,
+        samples: samples
+            .iter()
+            .map(|sample| (sample.rid, sample.cid))
+            .zip(encoded)
+            .collect::<BTreeMap<_, _>>(),
+    })
+}
+
+/// Height and root a dissemination message claims, before anything in it is checked
+pub fn dissemination_root(msg: &LionMessages) -> Option<(u64, &[u8])> {
+    match msg {
+        LionMessages::ExtendedSquare { height, root, .. }
+        | LionMessages::OriginalShares { height, root, .. }
+        | LionMessages::CellBatch { height, root, .. } => Some((*height, root)),
+        _ => None,
+    }
+}
+
+/// Rebuild a prover from a dissemination message. The original shares are
+/// re-extended locally, and the resulting square, commitments and root must match
+/// what the producer gossiped. Squares wider than `max_length` are refused before
+/// anything is extended.
+pub fn accept_dissemination<E: Pairing, H: Hasher>(
+    msg: LionMessages,
+    params: Arc<kzg10::UniversalParams<E>>,
+    max_length: usize,
+) -> Result<(u64, RsSquareProver<E, H>)> {
+    let (height, scale, shares, extended_rows, row_commitments, col_commitments, root) = match msg {
+        LionMessages::OriginalShares {
+            height,
+            scale,
+            shares,
+            row_commitments,
+            col_commitments,
+            root,
+        } => {
+            let shares = shares
+                .iter()
+                .map(|share| line_from_bytes::<E::ScalarField>(share))
+                .collect::<Result<Vec<_>, _>>()?;
+            (
+                height,
+                scale,
+                shares,
+                None,
+                row_commitments,
+                col_commitments,
+                root,
+            )
+        }
+        LionMessages::ExtendedSquare {
+            height,
+            scale,
+            rows,
+            row_commitments,
+            col_commitments,
+            root,
+        } => {
+            if scale == 0 {
+                bail!("Scale factor must be positive");
+            }
+            let rows = rows
+                .iter()
+                .map(|row| line_from_bytes::<E::ScalarField>(row))
+                .collect::<Result<Vec<_>, _>>()?;
+            // original shares sit at coordinates divisible by the scale
+            let shares = rows
+                .iter()
+                .step_by(scale)
+                .map(|row| row.iter().step_by(scale).cloned().collect())
+                .collect();
+            (
+                height,
+                scale,
+                shares,
+                Some(rows),
+                row_commitments,
+                col_commitments,
+                root,
+            )
+        }
+        _ => bail!("Not a dissemination message"),
+    };
+
+    if !is_power_of_two(shares.len()) || !is_power_of_two(scale) {
+        bail!("Square dimensions must be powers of 2");
+    }
+    if shares.iter().any(|share| share.len() != shares.len()) {
+        bail!("Shares do not form a square");
+    }
+
+    let length = match shares.len().checked_mul(scale) {
+        Some(length) if length <= max_length => length,
+        _ => bail!("Squares are at most {} wide", max_length),
+    };
+    if row_commitments.len() != length || col_commitments.len() != length {
+        bail!("Expected {} row and column commitments", length);
+    }
+
+    let prover = RsSquareProver::<E, H>::with_params(&shares, scale, params);
+
+    if let Some(rows) = extended_rows {
+        if rows.len() != length {
+            bail!("Expected {} extended rows, got {}", length, rows.len());
+        }
+        for (rid, row) in rows.iter().enumerate() {
+            if *row != prover.square().row_vals(rid) {
+                bail!("Extended row {} does not match local extension", rid);
+            }
+        }
+    }
+
+    for (rid, bytes) in row_commitments.iter().enumerate() {
+        if commitment_from_bytes::<E>(bytes)? != prover.commit_to_row(rid) {
+            bail!("Commitment to row {} does not match local extension", rid);
+        }
+    }
+    for (cid, bytes) in col_commitments.iter().enumerate() {
+        if commitment_from_bytes::<E>(bytes)? != prover.commit_to_col(cid) {
+            bail!(
+                "Commitment to column {} does not match local extension",
+                cid
+            );
+        }
+    }
+
+    let local_root: Vec<u8> = prover.root().into();
+    if !digests_equal(&local_root, &root) {
+        bail!("Root of locally extended square does not match gossiped root");
+    }
+
+    Ok((height, prover))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accept_cell_batch, accept_dissemination, cell_batch, stored_message, DisseminationMode,
+        DisseminationPlan,
+    };
+    use crate::store::{BlockStore, StoredBlock};
+    use square_reed_solomon::fixtures::counting_square;
+    use square_reed_solomon::prover::RsSquareProver;
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use lion_roars::messages::LionMessages;
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn every_cell_goes_to_exactly_one_peer() {
+        let plan = DisseminationPlan::new(8, 3);
+        let total: usize = (0..plan.peers()).map(|peer| plan.cells(peer).len()).sum();
+        assert_eq!(total, 64);
+        for peer in 0..plan.peers() {
+            for other in peer + 1..plan.peers() {
+                let overlap = plan.assignment(peer).union(plan.assignment(other)).count();
+                assert_eq!(
+                    overlap,
+                    plan.assignment(peer).count() + plan.assignment(other).count()
+                );
+            }
+        }
+
+        // with the square extended twofold, any two of the three peers suffice
+        assert!(plan.is_recoverable_from(&[0, 2], 4));
+        assert!(plan.is_recoverable_from(&[0, 1, 2], 4));
+        assert!(!plan.is_recoverable_from(&[1], 4));
+        assert!(!plan.is_recoverable_from(&[], 4));
+    }
+
+    #[test]
+    pub fn cell_batches_are_checked_against_their_signed_header() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let store = BlockStore::<Bls12_381, Sha256>::new(params);
+        let shares = counting_square(2);
+        let prover = RsSquareProver::with_params(&shares, 2, store.shared_params());
+        let block = StoredBlock::new(3, prover);
+        let batch = cell_batch(&block, &DisseminationPlan::new(4, 2).cells(0));
+        let accept = |msg: &LionMessages, max_length| {
+            accept_cell_batch::<Bls12_381, Sha256>(
+                msg.clone(),
+                &block.header,
+                store.params(),
+                max_length,
+            )
+        };
+
+        let partial = accept(&batch, 4).unwrap();
+        assert_eq!(partial.header, block.header);
+        assert_eq!(partial.samples.len(), 8);
+        // wider than this node handles
+        assert!(accept(&batch, 2).is_err());
+        // nothing to pin the root with
+        assert!(accept(&cell_batch(&block, &[]), 4).is_err());
+
+        // a claimed length is refused before anything is sized by it
+        let mut huge = batch.clone();
+        if let LionMessages::CellBatch { length, .. } = &mut huge {
+            *length = 1 << 40;
+        }
+        assert!(accept(&huge, 4).is_err());
+
+        // a root nobody signed
+        let mut forged = batch.clone();
+        if let LionMessages::CellBatch { root, .. } = &mut forged {
+            *root = vec![0; 32];
+        }
+        assert!(accept(&forged, 4).is_err());
+        let mut late = batch;
+        if let LionMessages::CellBatch { height, .. } = &mut late {
+            *height = 4;
+        }
+        assert!(accept(&late, 4).is_err());
+    }
+
+    #[test]
+    pub fn oversized_squares_are_refused() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let store = BlockStore::<Bls12_381, Sha256>::new(params);
+        let shares = counting_square(2);
+        let prover = RsSquareProver::with_params(&shares, 2, store.shared_params());
+        let block = StoredBlock::new(3, prover);
+        let msg = stored_message(&block, DisseminationMode::OriginalShares);
+        let accept = |msg: &LionMessages, max_length| {
+            accept_dissemination::<Bls12_381, Sha256>(
+                msg.clone(),
+                store.shared_params(),
+                max_length,
+            )
+        };
+
+        let (height, prover) = accept(&msg, 4).unwrap();
+        assert_eq!(height, 3);
+        assert_eq!(prover.root(), block.prover.root());
+        assert!(accept(&msg, 2).is_err());
+        // a scale whose product with the side overflows is an error, not a panic
+        let mut overflowing = msg;
+        if let LionMessages::OriginalShares { scale, .. } = &mut overflowing {
+            *scale = 1 << (usize::BITS - 1);
+        }
+        assert!(accept(&overflowing, usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+use crate::auth::{AuthLayer, Credentials, Method};
+
+use anyhow::Result;
+use lion_roars::shutdown::wait_for_request;
+use log::{error, warn};
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+
+/// How long a probe may take to send its request and read the answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Probes answered at once; connections beyond are closed unanswered
+const MAX_PROBES: usize = 64;
+
+/// State reported by the `/healthz` and `/readyz` probes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HealthReport {
+    /// KZG setup is loaded and usable
+    pub srs_loaded: bool,
+    /// Height of the most recent stored square, if any
+    pub latest_height: Option<u64>,
+    /// The most recent square has all of its row and column commitments
+    pub latest_committed: bool,
+    /// The snapshot location accepts writes (true when no snapshot path is set)
+    pub storage_writable: bool,
+    /// Number of currently open peer connections
+    pub peers_connected: usize,
+}
+
+impl HealthReport {
+    /// Liveness: the node is running with a usable setup
+    pub fn is_healthy(&self) -> bool {
+        self.srs_loaded
+    }
+
+    /// Readiness: the node can serve samples and persist state
+    pub fn is_ready(&self) -> bool {
+        self.srs_loaded && self.storage_writable && self.latest_committed
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"srs_loaded\":{},\"latest_height\":{},\"latest_committed\":{},\"storage_writable\":{},\"peers_connected\":{}}}",
+            self.srs_loaded,
+            self.latest_height
+                .map_or("null".to_string(), |height| height.to_string()),
+            self.latest_committed,
+            self.storage_writable,
+            self.peers_connected
+        )
+    }
+}
+
+/// Answer HTTP probes on `addr` until shutdown: `/healthz` and `/readyz` with the
+/// report produced by `report`, `/metrics` with the text produced by `metrics`.
+/// `/metrics` is subject to `auth`; a token is taken from an `Authorization: Bearer` header.
+/// Probes are answered concurrently, each within `PROBE_TIMEOUT`.
+pub async fn serve_health<F, M>(
+    addr: SocketAddr,
+    report: F,
+    metrics: M,
+    auth: Arc<AuthLayer>,
+    mut stop: watch::Receiver<bool>,
+) -> Result<()>
+where
+    F: Fn() -> HealthReport + Send + Sync + 'static,
+    M: Fn() -> String + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    let (report, metrics) = (Arc::new(report), Arc::new(metrics));
+    let mut probes = JoinSet::new();
+    loop {
+        tokio::select! {
+            _ = wait_for_request(&mut stop) => break,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) if probes.len() < MAX_PROBES => {
+                    let (report, metrics, auth) = (report.clone(), metrics.clone(), auth.clone());
+                    probes.spawn(async move {
+                        let answered = answer_probe(stream, &*report, &*metrics, &auth);
+                        match tokio::time::timeout(PROBE_TIMEOUT, answered).await {
+                            Ok(Err(e)) => warn!("Health probe failed: {}", e),
+                            Err(_) => warn!("Health probe timed out"),
+                            Ok(Ok(())) => {}
+                        }
+                    });
+                }
+                Ok(_) => warn!("Too many health probes in flight, closing connection"),
+                Err(e) => error!("Failed to accept health probe: {}", e),
+            },
+            Some(finished) = probes.join_next() => {
+                if let Err(e) = finished {
+                    error!("Health probe task failed: {}", e);
+                }
+            }
+        }
+    }
+    probes.shutdown().await;
+    Ok(())
+}
+
+async fn answer_probe(
+    mut stream: TcpStream,
+    report: &impl Fn() -> HealthReport,
+    metrics: &impl Fn() -> String,
+    auth: &AuthLayer,
+) -> Result<()> {
+    let mut request = [0u8; 1024];
+    let read = stream.read(&mut request).await?;
+    let request = String::from_utf8_lossy(&request[..read]);
+    let path = request.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, content_type, body) = match path {
+        "/healthz" | "/readyz" => {
+            let report = report();
+            let ok = match path {
+                "/healthz" => report.is_healthy(),
+                _ => report.is_ready(),
+            };
+            let status = if ok {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            (status, "application/json", report.to_json())
+        }
+        "/metrics" => {
+            let credentials = Credentials {
+                peer: None,
+                token: bearer_token(&request).map(|token| token.as_bytes().to_vec()),
+            };
+            if auth.authorize(Method::Metrics, &credentials) {
+                ("200 OK", "text/plain; version=0.0.4", metrics())
+            } else {
+                ("401 Unauthorized", "application/json", "{}".to_string())
+            }
+        }
+        _ => ("404 Not Found", "application/json", "{}".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+fn bearer_token(request: &str) -> Option<&str> {
+    request.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("authorization") {
+            return None;
+        }
+        value.trim().strip_prefix("Bearer ").map(str::trim)
+    })
+}
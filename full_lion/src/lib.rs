@@ -1,2 +1,18 @@
+pub mod anti_entropy;
+pub mod auth;
+pub mod availability;
+pub mod cache;
+pub mod checkpoint;
+pub mod dissemination;
+pub mod health;
 pub mod node;
-
+pub mod openings;
+pub mod pipeline;
+pub mod proposer;
+pub mod proving;
+pub mod quota;
+pub mod reconstruction;
+pub mod rows;
+pub mod store;
+pub mod tiering;
+pub mod wal;
@@ -1,36 +1,2385 @@
-use square_reed_solomon::{prover::RsSquareProver, rs_square::RsSquare};
+use crate::anti_entropy::{
+    summary_entries, summary_message, AntiEntropyPolicy, SyncDiff, SyncEntry, MAX_SYNC_AHEAD,
+    MAX_SYNC_HEIGHTS,
+};
+use crate::auth::{AuthLayer, Credentials, Method};
+use crate::availability::AvailabilityMap;
+use crate::checkpoint::CommitmentCheckpoint;
+use crate::dissemination::{
+    accept_cell_batch, accept_dissemination, cell_batch, dissemination_root, stored_message,
+    DisseminationMode, DisseminationPlan,
+};
+use crate::health::{serve_health, HealthReport};
+use crate::openings::OpeningCache;
+use crate::pipeline::{CommitmentPipeline, HeightProgress, Readiness};
+use crate::proposer::Proposer;
+use crate::quota::{BandwidthQuotas, QuotaPolicy};
+use crate::reconstruction::Reconstruction;
+use crate::rows::RowCache;
+use crate::store::{
+    setup_digest, BlockStore, HeldSquare, LineCommitments, RetentionPolicy, StoredBlock,
+};
+use crate::tiering::{ColdStore, Fetch};
+use crate::wal::{IngestLog, Recovery};
+use square_reed_solomon::audit::{audit_cells, verify_audit};
+use square_reed_solomon::bisection::{split_digests, BisectionStep, RowChallenge, Verdict};
+use square_reed_solomon::bitfield::CellBitfield;
+use square_reed_solomon::cancel::CancelToken;
+use square_reed_solomon::codec::{commitment_to_bytes, verifier_key_to_bytes};
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::inclusion::ShareProof;
+use square_reed_solomon::layout::{BlobPlacement, BlobProof, BlobReceipt, LayoutVersion};
+use square_reed_solomon::namespace::{NamespaceData, NamespaceId, NamespaceIndex};
+use square_reed_solomon::prover::RsSquareProver;
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::setup::check_setup;
+use square_reed_solomon::storage::storage_cells;
+use square_reed_solomon::verifier::{digests_equal, verifier_key};
 
-use rs_merkle::{MerkleTree, algorithms::Sha256, Hasher};
+use anyhow::{bail, Result};
 use ark_ec::pairing::Pairing;
-use ark_ff::PrimeField;
-use anyhow::Result;
+use ark_poly_commit::kzg10;
+use lion_roars::connection::Connection;
+use lion_roars::dht::{Axis, PeerId};
+use lion_roars::equivocation::{EquivocationDetector, EquivocationProof};
+use lion_roars::header::{Header, SignedHeader};
+use lion_roars::messages::LionMessages;
+use lion_roars::noise::{Capabilities, NodeKeys};
+use lion_roars::scoring::{Misbehaviour, PeerScores};
+use lion_roars::shutdown::{wait_for_request, ShutdownSignal};
+use log::{error, info, warn};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use rs_merkle::Hasher;
 
-use std::sync::Mutex;
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
 
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
 
-use tokio::net::{TcpSocket, TcpStream};
+/// Most cells a single audit challenge may ask for
+pub const MAX_AUDIT_CELLS: usize = 64;
 
-pub struct FullLionNode<E : Pairing, H : Hasher> {
-    square: RsSquare<E::ScalarField>,
+/// How long a relayed request may take to reach its target and be answered
+const RELAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a peer may take to finish sending a request it started
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Requests a single peer may have relayed per second
+const RELAYS_PER_SECOND: u64 = 16;
+
+/// Receipts of included blobs kept for `wait_for_inclusion`, oldest dropped first
+const MAX_RECEIPTS: usize = 4096;
+
+/// Cell openings kept for repeated samples by default, least recently used dropped
+/// first
+const OPENING_CACHE_CAPACITY: usize = 16384;
+
+/// Decoded rows kept for repeated samples by default, least recently used dropped
+/// first
+const ROW_CACHE_CAPACITY: usize = 1024;
+
+/// Heights below the tip whose signed headers are still checked for equivocation,
+/// at most
+const HEADER_WINDOW: u64 = 1024;
+
+pub struct FullLionNode<E: Pairing, H: Hasher> {
+    /// Address peers connect to
+    listen_addr: SocketAddr,
+    /// File the block store is flushed to on shutdown
+    snapshot_path: Option<PathBuf>,
+    /// Address the health and readiness probes are served on
+    health_addr: Option<SocketAddr>,
+    /// Static identity used to authenticate every peer connection
+    keys: Arc<NodeKeys>,
+    /// Setup digest and supported square sizes, exchanged in every handshake
+    capabilities: Arc<Capabilities>,
+    /// Full nodes periodically synced with while running
+    anti_entropy: Option<AntiEntropyPolicy>,
     inner: FullLionNodeInner<E, H>,
 }
 
 #[derive(Clone)]
 pub struct FullLionNodeInner<E: Pairing, H: Hasher> {
-    prover: Arc<Mutex<RsSquareProver<E, H>>>,
+    /// Blocks held, read concurrently by every request served and written only to
+    /// store, restore or drop blocks
+    store: Arc<RwLock<BlockStore<E, H>>>,
+    /// Number of open peer connections
+    peers: Arc<AtomicUsize>,
+    /// Behaviour of every peer that has connected
+    scores: Arc<Mutex<PeerScores<IpAddr>>>,
+    /// Access policy for privileged methods
+    auth: Arc<AuthLayer>,
+    /// Signed headers seen so far, checked for conflicting data roots
+    headers: Arc<Mutex<EquivocationDetector>>,
+    /// Producers whose signed headers are accepted; those of anyone else are dropped
+    producers: Arc<Mutex<HashSet<PeerId>>>,
+    /// Behaviour of every producer by signing key, banned for equivocating whichever
+    /// peer relayed the proof
+    producer_scores: Arc<Mutex<PeerScores<PeerId>>>,
+    /// Every equivocation detected
+    equivocations: Arc<Mutex<Vec<EquivocationProof>>>,
+    /// Bytes served to every peer, and their limits
+    quotas: Arc<Mutex<BandwidthQuotas<IpAddr>>>,
+    /// Squares accepted by `submit` and not yet stored
+    pipeline: Arc<CommitmentPipeline>,
+    /// Whether sampling requests are forwarded to other peers on request
+    relaying: Arc<AtomicBool>,
+    /// Peers relayed requests may go to, with the identity expected of each
+    relay_targets: Arc<Mutex<BTreeMap<SocketAddr, Option<PeerId>>>>,
+    /// Requests relayed for each peer, charged one unit each
+    relays: Arc<Mutex<BandwidthQuotas<IpAddr>>>,
+    /// Cells other peers announced they hold
+    availability: Arc<Mutex<AvailabilityMap<PeerId>>>,
+    /// Builds blocks from submitted blobs if this node produces blocks
+    proposer: Arc<Mutex<Option<Proposer>>>,
+    /// Receipts of the latest blobs included, by ticket
+    receipts: Arc<Mutex<BTreeMap<u64, BlobReceipt>>>,
+    /// Height of the latest block proposed, bumped once its receipts are recorded
+    included: Arc<watch::Sender<u64>>,
+    /// Openings of cells already served, reused for the next samples of the same cell
+    openings: Arc<OpeningCache<E>>,
+    /// Rows of recently sampled squares, with their polynomials
+    rows: Arc<RowCache<E::ScalarField>>,
+    /// Write-ahead log of every square ingested since the last flush, once opened
+    /// by `recover`
+    wal: Arc<Mutex<Option<IngestLog<E, H>>>>,
 }
 
-impl<E : Pairing, H : Hasher> FullLionNode<E, H> {
+impl<E: Pairing, H: Hasher> FullLionNode<E, H> {
+    pub fn new(params: kzg10::UniversalParams<E>, listen_addr: SocketAddr) -> Self {
+        let capabilities = Arc::new(Capabilities::new(
+            setup_digest::<E, H>(&params),
+            params.powers_of_g.len().saturating_sub(1) as u64,
+        ));
+        Self {
+            listen_addr,
+            snapshot_path: None,
+            health_addr: None,
+            keys: Arc::new(NodeKeys::generate()),
+            capabilities,
+            anti_entropy: None,
+            inner: FullLionNodeInner {
+                store: Arc::new(RwLock::new(BlockStore::new(params))),
+                peers: Arc::new(AtomicUsize::new(0)),
+                scores: Arc::new(Mutex::new(PeerScores::new(
+                    -20,
+                    -100,
+                    Duration::from_secs(3600),
+                ))),
+                auth: Arc::new(AuthLayer::new()),
+                headers: Arc::new(Mutex::new(EquivocationDetector::new())),
+                producers: Arc::new(Mutex::new(HashSet::new())),
+                producer_scores: Arc::new(Mutex::new(PeerScores::new(
+                    -20,
+                    -100,
+                    Duration::from_secs(3600),
+                ))),
+                equivocations: Arc::new(Mutex::new(vec![])),
+                quotas: Arc::new(Mutex::new(BandwidthQuotas::new(None))),
+                pipeline: Arc::new(CommitmentPipeline::new(default_pipeline_workers())),
+                relaying: Arc::new(AtomicBool::new(false)),
+                relay_targets: Arc::new(Mutex::new(BTreeMap::new())),
+                relays: Arc::new(Mutex::new(BandwidthQuotas::new(Some(QuotaPolicy {
+                    bytes_per_window: RELAYS_PER_SECOND,
+                    window: Duration::from_secs(1),
+                })))),
+                availability: Arc::new(Mutex::new(AvailabilityMap::new(64))),
+                proposer: Arc::new(Mutex::new(None)),
+                receipts: Arc::new(Mutex::new(BTreeMap::new())),
+                included: Arc::new(watch::channel(0).0),
+                openings: Arc::new(OpeningCache::new(OPENING_CACHE_CAPACITY)),
+                rows: Arc::new(RowCache::new(ROW_CACHE_CAPACITY)),
+                wal: Arc::new(Mutex::new(None)),
+            },
+        }
+    }
+
+    /// Keep serving and verifying squares committed under the earlier setup `params`
+    /// while peers migrate to the one this node was built with, and accept peers
+    /// still on it
+    pub fn add_previous_setup(&mut self, params: kzg10::UniversalParams<E>) {
+        let previous = {
+            let mut store = self.inner.store.write().expect("Store lock poisoned");
+            store.add_previous_setup(params);
+            store.previous_setups()
+        };
+        self.announce_setups(previous);
+    }
+
+    /// End the migration away from the setup with `digest`. Returns whether it was
+    /// still kept.
+    pub fn retire_setup(&mut self, digest: &[u8]) -> bool {
+        let (retired, previous) = {
+            let mut store = self.inner.store.write().expect("Store lock poisoned");
+            (store.retire_setup(digest), store.previous_setups())
+        };
+        self.announce_setups(previous);
+        retired
+    }
+
+    /// Refuse setups that are not well-formed, e.g. a corrupted or altered file,
+    /// before serving anything committed under them
+    fn check_setups(&self) -> Result<()> {
+        let store = self.inner.store.read().expect("Store lock poisoned");
+        if let Err(err) = check_setup(store.params(), &mut OsRng) {
+            bail!("Setup is malformed: {:?}", err);
+        }
+        for digest in store.previous_setups() {
+            let params = store.setup(&digest).expect("Previous setups are held");
+            if let Err(err) = check_setup(&params, &mut OsRng) {
+                bail!("Previous setup {:?} is malformed: {:?}", digest, err);
+            }
+        }
+        Ok(())
+    }
+
+    fn announce_setups(&mut self, previous: Vec<Vec<u8>>) {
+        self.capabilities = Arc::new((*self.capabilities).clone().with_previous_setups(previous));
+    }
+
+    pub fn set_snapshot_path(&mut self, path: impl Into<PathBuf>) {
+        self.snapshot_path = Some(path.into());
+    }
+
+    pub fn set_health_addr(&mut self, addr: SocketAddr) {
+        self.health_addr = Some(addr);
+    }
+
+    /// Periodically sync with other full nodes while running. The peers only serve
+    /// whole squares to nodes their `Method::Square` policy admits, e.g. by key.
+    pub fn set_anti_entropy(&mut self, policy: AntiEntropyPolicy) {
+        for (addr, expected) in &policy.peers {
+            self.add_relay_target(*addr, *expected);
+        }
+        self.anti_entropy = Some(policy);
+    }
+
+    /// Produce blocks from submitted blobs with `proposer`, one every
+    /// `ProposerPolicy::block_interval` while running. The interval is read when
+    /// `run` starts.
+    pub fn set_proposer(&self, proposer: Proposer) {
+        self.add_producer(PeerId::from_public_bytes(&proposer.producer()));
+        for (addr, expected) in &proposer.policy().peers {
+            self.add_relay_target(*addr, *expected);
+        }
+        *self.inner.proposer.lock().expect("Proposer lock poisoned") = Some(proposer);
+    }
+
+    /// Accept headers signed by `producer`, e.g. one of the chain's block producers.
+    /// Headers announced by any other signer are dropped.
+    pub fn add_producer(&self, producer: PeerId) {
+        self.inner
+            .producers
+            .lock()
+            .expect("Producers lock poisoned")
+            .insert(producer);
+    }
+
+    /// Bound how many cell openings are kept for cells sampled again, dropping the
+    /// least recently used beyond it
+    pub fn set_opening_cache(&self, capacity: usize) {
+        self.inner.openings.set_capacity(capacity);
+    }
+
+    /// Bound how many decoded rows are kept for cells sampled again, dropping the
+    /// least recently used beyond it
+    pub fn set_row_cache(&self, capacity: usize) {
+        self.inner.rows.set_capacity(capacity);
+    }
+
+    /// Replace the freshly generated identity, e.g. with one loaded from disk
+    pub fn set_keys(&mut self, keys: NodeKeys) {
+        self.keys = Arc::new(keys);
+    }
+
+    /// Bound how many squares keep their cell data; headers are always kept
+    pub fn set_retention(&self, retention: RetentionPolicy) {
+        self.inner
+            .store
+            .write()
+            .expect("Store lock poisoned")
+            .set_retention(retention);
+        self.inner.prune_headers();
+    }
 
-    pub fn new(data: &[u8], stream: TcpStream) -> Self {
-        todo!();
+    /// Move cell data of heights beyond `RetentionPolicy::hot_last` to `cold`,
+    /// reading it back whenever one of them is sampled
+    pub fn set_cold_store(&self, cold: Arc<dyn ColdStore>) {
+        self.inner
+            .store
+            .write()
+            .expect("Store lock poisoned")
+            .set_cold_store(cold);
     }
 
+    /// Replace the default access policy, under which only sampling is public
+    pub fn set_auth(&mut self, auth: AuthLayer) {
+        self.inner.auth = Arc::new(auth);
+    }
+
+    /// Bound how many submitted squares are proven at once, including those
+    /// already queued
+    pub fn set_pipeline_workers(&self, workers: usize) {
+        self.inner.pipeline.set_workers(workers);
+    }
+
+    /// Limit how many bytes each peer is served per window, `None` to lift limits
+    pub fn set_quota(&self, policy: Option<QuotaPolicy>) {
+        self.inner
+            .quotas
+            .lock()
+            .expect("Quotas lock poisoned")
+            .set_policy(policy);
+    }
+
+    /// Forward sampling requests of light nodes to the peers they name, hiding
+    /// which node asked for which cells. Off by default. Only peers of the
+    /// anti-entropy and proposer policies, or added with `add_relay_target`, are
+    /// relayed to, and each peer gets `RELAYS_PER_SECOND` requests relayed.
+    pub fn set_relaying(&self, enabled: bool) {
+        self.inner.relaying.store(enabled, Ordering::Relaxed);
+    }
 
-    pub async fn run(&mut self) -> Result<()> {
-        loop {}
+    /// Let requests be relayed to the full node at `addr`, which must prove to be
+    /// `expected` if given
+    pub fn add_relay_target(&self, addr: SocketAddr, expected: Option<PeerId>) {
+        self.inner
+            .relay_targets
+            .lock()
+            .expect("Relay targets lock poisoned")
+            .insert(addr, expected);
     }
 
+    pub fn peer_id(&self) -> PeerId {
+        self.keys.peer_id()
+    }
+
+    pub fn health(&self) -> HealthReport {
+        self.inner.health(self.snapshot_path.as_deref())
+    }
+
+    /// Peer scores in Prometheus text format
+    pub fn metrics(&self) -> String {
+        self.inner.metrics()
+    }
+
+    /// Encode, commit to and store the square built from `shares`
+    pub fn ingest(&self, height: u64, shares: &Vec<Vec<E::ScalarField>>, scale: usize) -> Header {
+        self.inner.log_begin(height, shares, scale, None);
+        let prover = RsSquareProver::with_params(shares, scale, self.inner.shared_params());
+        let block = StoredBlock::new(height, prover);
+        let header = block.header.clone();
+        self.inner.insert(block);
+        self.inner.log_commit(&header);
+        header
+    }
+
+    /// Like `ingest`, reusing the commitments of the rows the square repeats from
+    /// the block at `height - 1` if its cell data is held, e.g. for rollups posting
+    /// mostly unchanged state pages. The store takes no writes meanwhile.
+    pub fn ingest_following(
+        &self,
+        height: u64,
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+    ) -> Header {
+        self.inner.log_begin(height, shares, scale, None);
+        let prover = RsSquareProver::with_params(shares, scale, self.inner.shared_params());
+        let block = {
+            let store = self.inner.store.read().expect("Store lock poisoned");
+            match height
+                .checked_sub(1)
+                .and_then(|previous| store.get(previous))
+            {
+                Some(previous) => StoredBlock::following(height, prover, previous),
+                None => StoredBlock::new(height, prover),
+            }
+        };
+        let header = block.header.clone();
+        self.inner.insert(block);
+        self.inner.log_commit(&header);
+        header
+    }
+
+    /// Like `ingest`, logging line commitments to `checkpoint` as they are computed.
+    /// If the node was killed while ingesting the same square, the commitments
+    /// already logged are reused. The log is deleted once the block is stored.
+    pub fn ingest_resumable(
+        &self,
+        height: u64,
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+        checkpoint: impl AsRef<Path>,
+    ) -> Result<Header> {
+        self.inner.log_begin(height, shares, scale, None);
+        let prover = RsSquareProver::with_params(shares, scale, self.inner.shared_params());
+        let log = CommitmentCheckpoint::open(checkpoint.as_ref(), height, &prover)?;
+        let block = log.commit_all(prover)?;
+        let header = block.header.clone();
+        self.inner.insert(block);
+        self.inner.log_commit(&header);
+        std::fs::remove_file(checkpoint)?;
+        Ok(header)
+    }
+
+    /// Like `ingest`, additionally committing to how the original shares are
+    /// divided among namespaces
+    pub fn ingest_with_namespaces(
+        &self,
+        height: u64,
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+        namespaces: NamespaceIndex,
+    ) -> Header {
+        self.inner
+            .log_begin(height, shares, scale, Some(&namespaces));
+        let prover = RsSquareProver::with_params(shares, scale, self.inner.shared_params());
+        let block = StoredBlock::new(height, prover).with_namespaces(namespaces);
+        let header = block.header.clone();
+        self.inner.insert(block);
+        self.inner.log_commit(&header);
+        header
+    }
 
+    /// All shares of `namespace` at `height` with a proof that none were left out,
+    /// or a proof that the namespace is absent. `None` if the cell data or the
+    /// namespace layout of `height` is not held.
+    pub fn get_namespace(
+        &self,
+        height: u64,
+        namespace: NamespaceId,
+    ) -> Option<NamespaceData<E, H>> {
+        self.inner.fetch(height)?.namespace_data(namespace)
+    }
+
+    /// Proof that the blob `receipt` names is part of the square at its height
+    pub fn blob_proof(&self, receipt: &BlobReceipt) -> Option<BlobProof<E, H>> {
+        self.inner
+            .fetch(receipt.height)?
+            .blob_proof(receipt.version, &receipt.placement)
+    }
+
+    /// Compact proof that every share of the blob `receipt` names is committed under
+    /// the data root of its height, checked with `verify_blob_inclusion`
+    pub fn prove_blob_inclusion(&self, receipt: &BlobReceipt) -> Option<ShareProof<E, H>> {
+        self.inner
+            .fetch(receipt.height)?
+            .blob_inclusion_proof(receipt.version, &receipt.placement)
+    }
+
+    /// Compact proof of fixed shares `shares` of the square at `height`, checked
+    /// with `verify_fixed_shares`
+    pub fn prove_fixed_shares(
+        &self,
+        height: u64,
+        shares: Range<usize>,
+    ) -> Option<ShareProof<E, H>> {
+        self.inner.fetch(height)?.fixed_share_proof(shares)
+    }
+
+    /// Cells of the square at `height` this node can serve
+    pub fn availability(&self, height: u64) -> Option<CellBitfield> {
+        self.inner
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .availability(height)
+    }
+
+    /// Data root and every row and column commitment of the square at `height`,
+    /// for explorers and indexers mirroring commitment data
+    pub fn commitments(&self, height: u64) -> Option<LineCommitments<E>> {
+        self.inner
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .commitments(height)
+    }
+
+    /// Peers that announced holding cell (rid, cid) at `height`
+    pub fn holders(&self, height: u64, rid: usize, cid: usize) -> Vec<PeerId> {
+        self.inner
+            .availability
+            .lock()
+            .expect("Availability lock poisoned")
+            .holders(height, rid, cid)
+    }
+
+    /// Cells of `height` that at least one peer announced, for squares of side `length`
+    pub fn peer_availability(&self, height: u64, length: usize) -> Option<CellBitfield> {
+        self.inner
+            .availability
+            .lock()
+            .expect("Availability lock poisoned")
+            .combined(height, length)
+    }
+
+    /// Gossip which cells of `height` this node holds to every peer in `peers`,
+    /// returning how many were reached
+    pub async fn announce_availability(
+        &self,
+        peers: &[(SocketAddr, Option<PeerId>)],
+        height: u64,
+    ) -> usize {
+        let announcement = LionMessages::AvailabilityAnnouncement {
+            height,
+            bitfield: self.inner.own_availability(height).to_compressed(),
+        };
+        let mut reached = 0;
+        for (addr, expected) in peers {
+            let sent = async {
+                let mut conn =
+                    Connection::connect(addr, &self.keys, *expected, &self.capabilities).await?;
+                conn.write_message(&announcement).await
+            };
+            if sent.await.is_ok() {
+                reached += 1;
+            }
+        }
+        reached
+    }
+
+    /// Ask the peer at `addr` which cells of `height` it holds, remembering the answer
+    pub async fn request_availability(
+        &self,
+        addr: SocketAddr,
+        expected: Option<PeerId>,
+        height: u64,
+    ) -> Result<CellBitfield> {
+        let mut conn = Connection::connect(addr, &self.keys, expected, &self.capabilities).await?;
+        let bitfield = match conn
+            .request(&LionMessages::AvailabilityRequest { height })
+            .await?
+        {
+            LionMessages::AvailabilityAnnouncement { bitfield, .. } => {
+                match CellBitfield::from_compressed(&bitfield, max_length(&self.capabilities)) {
+                    Ok(bitfield) => bitfield,
+                    Err(_) => {
+                        self.inner.penalize(addr.ip(), Misbehaviour::Spam);
+                        bail!("Malformed availability bitfield from {}", addr);
+                    }
+                }
+            }
+            other => bail!("Unexpected reply to availability request: {:?}", other),
+        };
+        let tip = self.inner.tip();
+        if !self
+            .inner
+            .availability
+            .lock()
+            .expect("Availability lock poisoned")
+            .record(conn.remote(), height, bitfield.clone(), tip)
+        {
+            bail!("Height {} is too far from the tip {} to track", height, tip);
+        }
+        Ok(bitfield)
+    }
+
+    /// Send each of `peers` its share of the square at `height` under a
+    /// `DisseminationPlan`, so that this node uploads every cell once. The peers
+    /// rebuild the rest from each other with `reconstruct`. Returns how many
+    /// peers were reached.
+    pub async fn disseminate_cells(
+        &self,
+        height: u64,
+        peers: &[(SocketAddr, Option<PeerId>)],
+    ) -> Result<usize> {
+        self.inner
+            .disseminate_cells(&self.keys, &self.capabilities, height, peers)
+            .await
+    }
+
+    /// Run one anti-entropy round with the full node at `addr` over the heights from
+    /// `depth` below the local tip on, pulling the squares it holds that this node
+    /// lacks and an accepted producer signed
+    pub async fn sync_with(
+        &self,
+        addr: SocketAddr,
+        expected: Option<PeerId>,
+        depth: u64,
+    ) -> Result<SyncDiff> {
+        self.inner
+            .sync_with(&self.keys, &self.capabilities, addr, expected, depth)
+            .await
+    }
+
+    /// Rebuild the square of side `length`, extended by `scale`, that `header`
+    /// commits to if this node does not hold all of it. The peers are asked which
+    /// cells they hold, just enough verified cells are fetched to decode the rest,
+    /// and the result is stored once it reproduces the header's data root. Peers
+    /// serving cells that fail to verify are penalized and asked no further.
+    /// Rebuilding is abandoned once `cancel` is cancelled, e.g. when a newer height
+    /// makes it stale.
+    pub async fn reconstruct(
+        &self,
+        header: &Header,
+        length: usize,
+        scale: usize,
+        peers: &[(SocketAddr, Option<PeerId>)],
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let height = header.height;
+        if self.availability(height).is_some_and(|held| held.is_full()) {
+            return Ok(());
+        }
+        if !self.capabilities.supports(length as u64) {
+            bail!(
+                "Squares are at most {} wide",
+                self.capabilities.max_square_length
+            );
+        }
+        let params = self
+            .inner
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .setup(&header.vk_digest);
+        let params = match params {
+            Some(params) => params,
+            None => bail!("No setup held for the square at height {}", height),
+        };
+        let vk = verifier_key(&params);
+        let mut rebuild = Reconstruction::<E, H>::new(header.clone(), length, scale)?;
+        // start from the cells already held, e.g. this node's share of a cell batch
+        let held: Vec<Vec<u8>> = self
+            .inner
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .partial(height)
+            .map_or(vec![], |partial| {
+                partial.samples.values().cloned().collect()
+            });
+        for sample in &held {
+            if let Ok(sample) = CellSample::<E, H>::from_bytes(sample) {
+                rebuild.accept(&vk, &sample);
+            }
+        }
+
+        let mut offers = vec![];
+        for (idx, (addr, expected)) in peers.iter().enumerate() {
+            match self.request_availability(*addr, *expected, height).await {
+                Ok(bitfield) => offers.push((idx, bitfield)),
+                Err(e) => warn!("No availability from {}: {}", addr, e),
+            }
+        }
+
+        while !rebuild.is_recoverable() {
+            if cancel.is_cancelled() {
+                bail!("Rebuilding height {} was cancelled", height);
+            }
+            let plan = rebuild.plan(&offers);
+            if plan.is_empty() {
+                bail!(
+                    "Peers do not hold enough cells to rebuild height {}",
+                    height
+                );
+            }
+            for (idx, cells) in plan {
+                let (addr, expected) = peers[idx];
+                let asked = cells.len();
+                let request = LionMessages::CellsRequest { height, cells };
+                let served = async {
+                    let mut conn =
+                        Connection::connect(addr, &self.keys, expected, &self.capabilities).await?;
+                    conn.request(&request).await
+                };
+                // a peer that fails to serve what it announced is not asked again,
+                // and penalized if what it served does not verify
+                match served.await {
+                    Ok(LionMessages::CellsResponse { samples, .. }) => {
+                        let verified = samples.iter().all(|sample| {
+                            CellSample::<E, H>::from_bytes(sample)
+                                .is_ok_and(|sample| rebuild.accept(&vk, &sample))
+                        });
+                        if !verified {
+                            self.inner.penalize(addr.ip(), Misbehaviour::InvalidProof);
+                            offers.retain(|(offered, _)| *offered != idx);
+                        } else if samples.len() < asked {
+                            warn!("{} no longer holds every cell of height {}", addr, height);
+                            offers.retain(|(offered, _)| *offered != idx);
+                        }
+                    }
+                    Ok(other) => {
+                        warn!(
+                            "Unexpected reply to cells request from {}: {:?}",
+                            addr, other
+                        );
+                        self.inner.penalize(addr.ip(), Misbehaviour::Spam);
+                        offers.retain(|(offered, _)| *offered != idx);
+                    }
+                    Err(e) => {
+                        warn!("Reconstruction of height {} lost {}: {}", height, addr, e);
+                        offers.retain(|(offered, _)| *offered != idx);
+                    }
+                }
+            }
+        }
+
+        let block = rebuild.finish(params, cancel)?;
+        self.inner.insert(block);
+        Ok(())
+    }
+
+    /// Rebuild from `peers`, with `reconstruct`, every square whose cold cells were
+    /// found corrupt since the last call. The corrupt cells stop being served right
+    /// away. Returns the heights rebuilt; a height that cannot be rebuilt now is
+    /// only served again once it is ingested or synced anew.
+    pub async fn repair_corrupt(
+        &self,
+        peers: &[(SocketAddr, Option<PeerId>)],
+        cancel: &CancelToken,
+    ) -> Vec<u64> {
+        let damaged = self
+            .inner
+            .store
+            .write()
+            .expect("Store lock poisoned")
+            .take_corrupt();
+        let mut repaired = vec![];
+        for (header, length, scale) in damaged {
+            match self
+                .reconstruct(&header, length, scale, peers, cancel)
+                .await
+            {
+                Ok(()) => repaired.push(header.height),
+                Err(e) => warn!("Failed to repair height {}: {}", header.height, e),
+            }
+        }
+        repaired
+    }
+
+    /// How far the square at `height` is from being served: in the pipeline, stored,
+    /// or `None` if it was never submitted or ingested
+    pub fn readiness(&self, height: u64) -> Option<Readiness> {
+        self.inner.pipeline.readiness(height).or_else(|| {
+            self.inner
+                .store
+                .read()
+                .expect("Store lock poisoned")
+                .header(height)
+                .cloned()
+                .map(Readiness::Ready)
+        })
+    }
+
+    /// Check a signed header against those seen before, returning a proof if its
+    /// producer signed a different data root for the same height. Headers of
+    /// producers never added with `add_producer` are ignored.
+    pub fn observe_header(&self, signed: SignedHeader) -> Option<EquivocationProof> {
+        self.inner.observe_header(signed)
+    }
+
+    /// Equivocation proofs collected so far
+    pub fn equivocations(&self) -> Vec<EquivocationProof> {
+        self.inner
+            .equivocations
+            .lock()
+            .expect("Equivocations lock poisoned")
+            .clone()
+    }
+
+    /// Dispute row `rid` of the square the peer at `addr` committed to under
+    /// `data_root` at `height`, taking the locally stored row as correct. The game
+    /// ends in agreement only if the peer's row commitment is ours, and the peer is
+    /// penalized otherwise.
+    pub async fn challenge_row(
+        &self,
+        addr: SocketAddr,
+        expected: Option<PeerId>,
+        height: u64,
+        data_root: H::Hash,
+        rid: usize,
+    ) -> Result<Verdict<E::ScalarField>> {
+        let (vk, version, ours, row) = match self.inner.fetch(height) {
+            Some(block) if rid < block.length() && block.length() > 1 => (
+                verifier_key(block.prover.params()),
+                block.prover.root_version(),
+                block.row_commitments[rid],
+                block.prover.square().row_vals(rid),
+            ),
+            _ => bail!("No row {} to dispute at height {}", rid, height),
+        };
+        let mut conn = Connection::connect(addr, &self.keys, expected, &self.capabilities).await?;
+        let mut challenge = RowChallenge::<E::ScalarField, H>::new(rid, row);
+
+        let verdict = loop {
+            match challenge.step() {
+                BisectionStep::Split { lo, hi } => {
+                    let request = LionMessages::BisectionRequest {
+                        height,
+                        rid,
+                        lo,
+                        hi,
+                    };
+                    let halves = match conn.request(&request).await? {
+                        LionMessages::BisectionResponse { left, right, .. } => {
+                            H::Hash::try_from(left)
+                                .ok()
+                                .zip(H::Hash::try_from(right).ok())
+                        }
+                        LionMessages::BisectionUnavailable { .. } => None,
+                        other => bail!("Unexpected reply to bisection request: {:?}", other),
+                    };
+                    let verdict = match halves {
+                        Some((left, right)) => challenge.narrow(left, right),
+                        None => Some(Verdict::DefenderFaulty),
+                    };
+                    if let Some(verdict) = verdict {
+                        break verdict;
+                    }
+                }
+                BisectionStep::Bind { idx } | BisectionStep::Settle { idx } => {
+                    let request = LionMessages::SampleRequest {
+                        height,
+                        rid,
+                        cid: idx,
+                    };
+                    let sample = match conn.request(&request).await? {
+                        LionMessages::SampleResponse { sample, .. } => {
+                            CellSample::<E, H>::from_bytes(&sample).ok()
+                        }
+                        LionMessages::SampleUnavailable { .. } => None,
+                        other => bail!("Unexpected reply to sample request: {:?}", other),
+                    };
+                    let verdict = match (challenge.step(), sample) {
+                        (_, None) => Some(Verdict::DefenderFaulty),
+                        (BisectionStep::Bind { .. }, Some(sample)) => {
+                            challenge.bind(&vk, version, data_root, &ours, &sample)
+                        }
+                        (_, Some(sample)) => {
+                            Some(challenge.settle(&vk, version, data_root, &sample))
+                        }
+                    };
+                    if let Some(verdict) = verdict {
+                        break verdict;
+                    }
+                }
+            }
+        };
+        if verdict != Verdict::Agreed {
+            self.inner.penalize(addr.ip(), Misbehaviour::InvalidProof);
+        }
+        Ok(verdict)
+    }
+
+    /// Check that the peer at `addr` still stores the square of side `length` under
+    /// `header` by having it open `count` cells chosen by a fresh seed within
+    /// `deadline`. The peer is penalized unless it passes.
+    pub async fn audit(
+        &self,
+        addr: SocketAddr,
+        expected: Option<PeerId>,
+        header: &Header,
+        length: usize,
+        count: usize,
+        deadline: Duration,
+    ) -> Result<bool> {
+        if count > MAX_AUDIT_CELLS {
+            bail!("Audits open at most {} cells", MAX_AUDIT_CELLS);
+        }
+        let height = header.height;
+        let data_root = match H::Hash::try_from(header.data_root.clone()) {
+            Ok(root) => root,
+            Err(_) => bail!("Data root of height {} is not a hash", height),
+        };
+        let version = match SquareLayout::root_version_of(&header.layout) {
+            Some(version) => version,
+            None => bail!("Unknown root version at height {}", height),
+        };
+        let params = self
+            .inner
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .setup(&header.vk_digest);
+        let vk = match params {
+            Some(params) => verifier_key(&params),
+            None => bail!("No setup held for the square at height {}", height),
+        };
+        let mut seed = vec![0; 32];
+        OsRng.fill_bytes(&mut seed);
+        let mut conn = Connection::connect(addr, &self.keys, expected, &self.capabilities).await?;
+
+        let request = LionMessages::AuditChallenge {
+            height,
+            seed: seed.clone(),
+            count,
+        };
+        // the deadline starts once the seed is sent, so it only covers the answer
+        let passed = match tokio::time::timeout(deadline, conn.request(&request)).await {
+            Ok(reply) => match reply? {
+                LionMessages::AuditResponse { samples, .. } => samples
+                    .iter()
+                    .map(|sample| CellSample::<E, H>::from_bytes(sample))
+                    .collect::<Result<Vec<_>, _>>()
+                    .is_ok_and(|samples| {
+                        verify_audit(&vk, version, data_root, length, &seed, count, &samples)
+                    }),
+                LionMessages::AuditUnavailable { .. } => false,
+                other => bail!("Unexpected reply to audit challenge: {:?}", other),
+            },
+            Err(_) => false,
+        };
+        if !passed {
+            self.inner.penalize(addr.ip(), Misbehaviour::InvalidProof);
+        }
+        Ok(passed)
+    }
+
+    /// Write squares, commitments, line roots and headers for `heights` to a single archive
+    pub fn snapshot(&self, heights: RangeInclusive<u64>, path: impl AsRef<Path>) -> Result<()> {
+        let archive = self
+            .inner
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .snapshot(heights);
+        // write then rename, so a crash mid-write never clobbers the previous archive
+        let tmp = path.as_ref().with_extension("tmp");
+        std::fs::write(&tmp, archive)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Load an archive written by `snapshot`, returning the restored heights
+    pub fn restore(&self, path: impl AsRef<Path>) -> Result<Vec<u64>> {
+        let archive = std::fs::read(path)?;
+        self.inner
+            .store
+            .write()
+            .expect("Store lock poisoned")
+            .restore(&archive)
+    }
+
+    /// Persist every stored height to the snapshot path, if one is configured, then
+    /// drop the squares it holds from the ingestion log
+    pub fn flush(&self) -> Result<()> {
+        let path = match &self.snapshot_path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // no ingest is logged complete between the snapshot and the trim
+        let mut wal = self.inner.wal.lock().expect("Ingestion log lock poisoned");
+        self.snapshot(0..=u64::MAX, path)?;
+        match wal.as_mut() {
+            Some(wal) => wal.trim(),
+            None => Ok(()),
+        }
+    }
+
+    /// Open the ingestion log at `path` and recover from a crash mid-ingest. Every
+    /// square the log shows stored is ingested again unless already held, e.g.
+    /// restored from the snapshot; squares whose ingestion never finished are
+    /// dropped. Every later ingest is logged until the next `flush`. Call once at
+    /// startup, after `restore`.
+    pub fn recover(&self, path: impl AsRef<Path>) -> Result<Recovery> {
+        let (log, replay) = IngestLog::<E, H>::open(path)?;
+        let mut recovery = Recovery {
+            replayed: vec![],
+            discarded: replay.incomplete,
+        };
+        for (square, data_root) in replay.complete {
+            let height = square.height;
+            let stored = self
+                .inner
+                .store
+                .read()
+                .expect("Store lock poisoned")
+                .header(height)
+                .is_some_and(|header| digests_equal(&header.data_root, &data_root));
+            if stored {
+                continue;
+            }
+            let prover = RsSquareProver::with_params(
+                &square.shares,
+                square.scale,
+                self.inner.shared_params(),
+            );
+            let mut block = StoredBlock::new(height, prover);
+            if let Some(namespaces) = square.namespaces {
+                block = block.with_namespaces(namespaces);
+            }
+            if !digests_equal(&block.header.data_root, &data_root) {
+                warn!(
+                    "Replaying height {} did not reproduce its data root",
+                    height
+                );
+                recovery.discarded.push(height);
+                continue;
+            }
+            self.inner.insert(block);
+            recovery.replayed.push(height);
+        }
+        recovery.discarded.sort();
+        recovery.discarded.dedup();
+        *self.inner.wal.lock().expect("Ingestion log lock poisoned") = Some(log);
+        Ok(recovery)
+    }
+}
+
+impl<E: Pairing, H: Hasher + Send + Sync + 'static> FullLionNode<E, H> {
+    /// Accept the square built from `shares` without waiting for it to be extended
+    /// and committed to, which happens on a background worker. Track it with
+    /// `readiness`; the handle resolves to its header once it is stored.
+    pub fn submit(
+        &self,
+        height: u64,
+        shares: Vec<Vec<E::ScalarField>>,
+        scale: usize,
+    ) -> JoinHandle<Header> {
+        let inner = self.inner.clone();
+        inner.log_begin(height, &shares, scale, None);
+        inner.pipeline.enqueue(height);
+        tokio::spawn(async move {
+            let _worker = inner.pipeline.start(height).await;
+            let params = inner.shared_params();
+            let progress = HeightProgress::new(inner.pipeline.clone(), height);
+            let proven = tokio::task::spawn_blocking(move || {
+                let cancel = CancelToken::new();
+                let prover = RsSquareProver::with_params_observed(
+                    &shares, scale, params, &cancel, &progress,
+                )?;
+                StoredBlock::new_observed(height, prover, &cancel, &progress)
+            })
+            .await
+            .map(|block| block.expect("Fresh token is never cancelled"));
+            let block = match proven {
+                Ok(block) => block,
+                Err(e) => {
+                    inner.pipeline.finish(height);
+                    panic!("Commitment worker failed at height {}: {}", height, e);
+                }
+            };
+            let header = block.header.clone();
+            inner.insert(block);
+            inner.log_commit(&header);
+            inner.pipeline.finish(height);
+            header
+        })
+    }
+
+    /// Queue `data` under `namespace` for the next block the proposer builds,
+    /// returning a ticket for `wait_for_inclusion`
+    pub fn queue_blob(&self, namespace: NamespaceId, data: Vec<u8>) -> Result<u64> {
+        self.queue_blob_with_fee(namespace, data, 0)
+    }
+
+    /// Queue `data` like `queue_blob`, offering `fee` to the proposer's
+    /// `BlobSelection`
+    pub fn queue_blob_with_fee(
+        &self,
+        namespace: NamespaceId,
+        data: Vec<u8>,
+        fee: u64,
+    ) -> Result<u64> {
+        match self
+            .inner
+            .proposer
+            .lock()
+            .expect("Proposer lock poisoned")
+            .as_mut()
+        {
+            Some(proposer) => proposer.submit_with_fee(namespace, data, fee),
+            None => bail!("No proposer configured"),
+        }
+    }
+
+    /// Blob bytes each namespace took in the block this node proposed at `height`
+    pub fn block_usage(&self, height: u64) -> Option<BTreeMap<NamespaceId, usize>> {
+        self.inner
+            .proposer
+            .lock()
+            .expect("Proposer lock poisoned")
+            .as_ref()?
+            .usage(height)
+            .cloned()
+    }
+
+    /// Submit `data` under `namespace` and wait until a block includes it
+    pub async fn submit_blob(&self, namespace: NamespaceId, data: Vec<u8>) -> Result<BlobReceipt> {
+        let ticket = self.queue_blob(namespace, data)?;
+        self.wait_for_inclusion(ticket).await
+    }
+
+    /// Receipt of the blob queued under `ticket`, once a block includes it. Fails
+    /// for tickets never handed out, blobs the proposer rejected, or blobs
+    /// included so long ago the receipt was dropped.
+    pub async fn wait_for_inclusion(&self, ticket: u64) -> Result<BlobReceipt> {
+        let mut included = self.inner.included.subscribe();
+        loop {
+            if let Some(receipt) = self.inner.receipt(ticket) {
+                return Ok(receipt);
+            }
+            let (pending, rejection) = match self
+                .inner
+                .proposer
+                .lock()
+                .expect("Proposer lock poisoned")
+                .as_ref()
+            {
+                Some(proposer) => (
+                    proposer.is_pending(ticket),
+                    proposer.rejection(ticket).map(str::to_owned),
+                ),
+                None => (false, None),
+            };
+            if let Some(reason) = rejection {
+                bail!(
+                    "Blob queued under ticket {} was rejected: {}",
+                    ticket,
+                    reason
+                );
+            }
+            // receipts are recorded before the blob leaves the queue
+            if !pending {
+                return match self.inner.receipt(ticket) {
+                    Some(receipt) => Ok(receipt),
+                    None => bail!("No blob queued under ticket {}", ticket),
+                };
+            }
+            included.changed().await?;
+        }
+    }
+
+    /// Build the next block from the blobs submitted to the proposer, store it,
+    /// announce its signed header to the proposer's peers and disseminate its
+    /// cells to them. `None` if no blob is pending.
+    pub async fn propose(&self) -> Result<Option<SignedHeader>> {
+        self.inner.propose(&self.keys, &self.capabilities).await
+    }
+
+    /// Serve peers until shutdown is requested. On shutdown the listener is closed,
+    /// in-flight requests are answered, the store is flushed and the signal is completed.
+    pub async fn run(&self, mut shutdown: ShutdownSignal) -> Result<()> {
+        self.check_setups()?;
+        let listener = TcpListener::bind(self.listen_addr).await?;
+        let mut connections = JoinSet::new();
+
+        let health = self.health_addr.map(|addr| {
+            let inner = self.inner.clone();
+            let snapshot_path = self.snapshot_path.clone();
+            let metrics_inner = self.inner.clone();
+            let report = move || inner.health(snapshot_path.as_deref());
+            let metrics = move || metrics_inner.metrics();
+            let auth = self.inner.auth.clone();
+            tokio::spawn(serve_health(
+                addr,
+                report,
+                metrics,
+                auth,
+                shutdown.subscribe(),
+            ))
+        });
+
+        let mut sync = self.anti_entropy.clone().map(|policy| SyncSchedule {
+            ticker: tokio::time::interval(policy.interval),
+            policy,
+            next: 0,
+        });
+        let mut blocks = self
+            .inner
+            .proposer
+            .lock()
+            .expect("Proposer lock poisoned")
+            .as_ref()
+            .map(|proposer| tokio::time::interval(proposer.policy().block_interval));
+
+        loop {
+            tokio::select! {
+                _ = shutdown.requested() => break,
+                (addr, expected, depth) = next_sync_peer(&mut sync) => {
+                    let inner = self.inner.clone();
+                    let keys = self.keys.clone();
+                    let capabilities = self.capabilities.clone();
+                    connections.spawn(async move {
+                        inner
+                            .sync_with(&keys, &capabilities, addr, expected, depth)
+                            .await
+                            .map(|_| ())
+                    });
+                }
+                _ = next_block(&mut blocks) => {
+                    let inner = self.inner.clone();
+                    let keys = self.keys.clone();
+                    let capabilities = self.capabilities.clone();
+                    connections.spawn(async move {
+                        inner.propose(&keys, &capabilities).await.map(|_| ())
+                    });
+                }
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, addr)) => {
+                        if self.inner.scores.lock().expect("Scores lock poisoned").is_banned(&addr.ip()) {
+                            continue;
+                        }
+                        let inner = self.inner.clone();
+                        let keys = self.keys.clone();
+                        let capabilities = self.capabilities.clone();
+                        let stop = shutdown.subscribe();
+                        connections.spawn(async move { inner.serve(stream, &keys, &capabilities, addr.ip(), stop).await });
+                    }
+                    Err(e) => error!("Failed to accept connection: {}", e),
+                },
+                Some(finished) = connections.join_next() => log_connection_result(finished),
+            }
+        }
+
+        // stop accepting, then let every connection finish its current request
+        drop(listener);
+        while let Some(finished) = connections.join_next().await {
+            log_connection_result(finished);
+        }
+
+        if let Some(health) = health {
+            match health.await {
+                Ok(Err(e)) => error!("Health endpoint error: {}", e),
+                Err(e) => error!("Health endpoint failed: {}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+
+        let flushed = self.flush();
+        shutdown.complete();
+        flushed
+    }
+}
+
+impl<E: Pairing, H: Hasher + Send + Sync + 'static> FullLionNodeInner<E, H> {
+    async fn propose(
+        &self,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+    ) -> Result<Option<SignedHeader>> {
+        let inner = self.clone();
+        let built = tokio::task::spawn_blocking(move || {
+            let params = inner
+                .store
+                .read()
+                .expect("Store lock poisoned")
+                .shared_params();
+            let mut proposer = inner.proposer.lock().expect("Proposer lock poisoned");
+            let proposer = match proposer.as_mut() {
+                Some(proposer) => proposer,
+                None => bail!("No proposer configured"),
+            };
+            let proposal = match proposer.propose::<E, H>(params)? {
+                Some(proposal) => proposal,
+                None => return Ok(None),
+            };
+            // store the block and its receipts before its blobs stop being pending
+            let block = proposal.block;
+            let header = block.header.clone();
+            inner.log_begin(
+                header.height,
+                block.prover.shares(),
+                block.prover.scale(),
+                block.namespaces.as_ref(),
+            );
+            inner.insert(block);
+            inner.log_commit(&header);
+            let mut receipts = inner.receipts.lock().expect("Receipts lock poisoned");
+            for receipt in proposal.receipts {
+                receipts.insert(receipt.ticket, receipt);
+            }
+            while receipts.len() > MAX_RECEIPTS {
+                receipts.pop_first();
+            }
+            Ok(Some((proposal.signed, proposer.policy().peers.clone())))
+        })
+        .await?;
+        let (signed, peers) = match built? {
+            Some(built) => built,
+            None => {
+                // wake waiters on blobs the proposer may have rejected
+                self.included.send_modify(|_| {});
+                return Ok(None);
+            }
+        };
+
+        let height = signed.header.height;
+        self.included.send_replace(height);
+        self.observe_header(signed.clone());
+        // peers only keep cells of squares whose signed header they have seen
+        let announcement = LionMessages::HeaderAnnouncement {
+            header: signed.to_bytes(),
+        };
+        for (addr, expected) in &peers {
+            let sent = async {
+                let mut conn = Connection::connect(addr, keys, *expected, capabilities).await?;
+                conn.write_message(&announcement).await
+            };
+            if let Err(e) = sent.await {
+                warn!(
+                    "Failed to announce header of height {} to {}: {}",
+                    height, addr, e
+                );
+            }
+        }
+        self.disseminate_cells(keys, capabilities, height, &peers)
+            .await?;
+        Ok(Some(signed))
+    }
+}
+
+/// One worker per core, or a single one when arkworks already spreads every
+/// commitment over all cores
+fn default_pipeline_workers() -> usize {
+    if cfg!(feature = "parallel") {
+        return 1;
+    }
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Side of the widest square `capabilities` admit, for bounding what peers claim
+fn max_length(capabilities: &Capabilities) -> usize {
+    usize::try_from(capabilities.max_square_length).unwrap_or(usize::MAX)
+}
+
+/// Check the snapshot location accepts writes by creating and removing a probe file
+fn is_writable(snapshot_path: &Path) -> bool {
+    let probe = snapshot_path.with_extension("probe");
+    let writable = std::fs::write(&probe, b"probe").is_ok();
+    let _ = std::fs::remove_file(&probe);
+    writable
+}
+
+/// When and with whom the next anti-entropy round runs
+struct SyncSchedule {
+    policy: AntiEntropyPolicy,
+    ticker: tokio::time::Interval,
+    /// Index of the next peer to sync with
+    next: usize,
+}
+
+/// Wait for the next anti-entropy round, forever if none are scheduled
+async fn next_sync_peer(sync: &mut Option<SyncSchedule>) -> (SocketAddr, Option<PeerId>, u64) {
+    match sync {
+        Some(schedule) if !schedule.policy.peers.is_empty() => {
+            schedule.ticker.tick().await;
+            let (addr, expected) = schedule.policy.peers[schedule.next];
+            schedule.next = (schedule.next + 1) % schedule.policy.peers.len();
+            (addr, expected, schedule.policy.depth)
+        }
+        _ => std::future::pending().await,
+    }
+}
+
+/// Wait for the next block to be proposed, forever if this node proposes none
+async fn next_block(blocks: &mut Option<tokio::time::Interval>) {
+    match blocks {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Counts an open connection for as long as it is alive
+struct PeerGuard(Arc<AtomicUsize>);
+
+impl PeerGuard {
+    fn new(peers: Arc<AtomicUsize>) -> Self {
+        peers.fetch_add(1, Ordering::Relaxed);
+        Self(peers)
+    }
+}
+
+impl Drop for PeerGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn log_connection_result(finished: Result<Result<()>, tokio::task::JoinError>) {
+    match finished {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => error!("Connection error: {}", e),
+        Err(e) => error!("Connection task failed: {}", e),
+    }
+}
+
+impl<E: Pairing, H: Hasher> FullLionNodeInner<E, H> {
+    /// Current setup, for committing to a square without holding the store: peers
+    /// are served while the commitments, which arkworks may spread over every core,
+    /// are computed
+    fn shared_params(&self) -> Arc<kzg10::UniversalParams<E>> {
+        self.store
+            .read()
+            .expect("Store lock poisoned")
+            .shared_params()
+    }
+
+    fn insert(&self, block: StoredBlock<E, H>) {
+        self.store
+            .write()
+            .expect("Store lock poisoned")
+            .insert(block);
+        self.prune_headers();
+    }
+
+    /// Forget signed headers of heights whose cells retention no longer keeps, or
+    /// further than `HEADER_WINDOW` below the tip
+    fn prune_headers(&self) {
+        let below = {
+            let store = self.store.read().expect("Store lock poisoned");
+            match store.latest_height() {
+                Some(tip) => store.retained_from().max(tip.saturating_sub(HEADER_WINDOW)),
+                None => return,
+            }
+        };
+        self.headers
+            .lock()
+            .expect("Headers lock poisoned")
+            .prune_below(below);
+    }
+
+    /// Whether headers signed by `producer` are accepted: it was added and is not
+    /// banned for equivocating
+    /// Latest height this node stored or saw an accepted producer sign
+    fn tip(&self) -> u64 {
+        let stored = self
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .latest_height();
+        let signed = self
+            .headers
+            .lock()
+            .expect("Headers lock poisoned")
+            .latest_height();
+        stored.max(signed).unwrap_or(0)
+    }
+
+    /// Block at `height`, taken out of the store so that serving it does not hold
+    /// the store's lock
+    fn fetch(&self, height: u64) -> Option<Arc<StoredBlock<E, H>>> {
+        let cold = match self
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .begin_fetch(height)?
+        {
+            Fetch::Ready(block) => return Some(block),
+            Fetch::Cold(cold) => cold,
+        };
+        // the cold store is read and the block rebuilt without holding the lock
+        let loaded = cold.load();
+        self.store
+            .read()
+            .expect("Store lock poisoned")
+            .finish_fetch(&cold, loaded)
+    }
+
+    fn held(&self, height: u64) -> Option<HeldSquare<E, H>> {
+        self.store.read().expect("Store lock poisoned").held(height)
+    }
+
+    /// Samples of `cells` of `held`, reading its cells back from the cold store
+    /// without holding the store's lock if a row is not cached
+    fn serve_held(
+        &self,
+        held: &HeldSquare<E, H>,
+        cells: &[(usize, usize)],
+    ) -> Option<Vec<CellSample<E, H>>> {
+        held.serve_cells(cells, &self.rows, &self.openings, || {
+            self.fetch(held.height)
+        })
+    }
+
+    /// Those of `cells` held of the square at `height` if only some of its cells
+    /// are, as they arrived
+    fn partial_samples(&self, height: u64, cells: &[(usize, usize)]) -> Vec<Vec<u8>> {
+        let store = self.store.read().expect("Store lock poisoned");
+        match store.partial(height) {
+            Some(partial) => cells
+                .iter()
+                .filter_map(|cell| partial.samples.get(cell).cloned())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn touch(&self, height: u64) {
+        self.store
+            .read()
+            .expect("Store lock poisoned")
+            .touch(height);
+    }
+
+    /// Header an accepted producer signed at `height` committing to `data_root`
+    fn signed_header(&self, height: u64, data_root: &[u8]) -> Option<SignedHeader> {
+        self.headers
+            .lock()
+            .expect("Headers lock poisoned")
+            .at_height(height)
+            .find(|signed| {
+                self.is_producer(&signed.producer_id())
+                    && digests_equal(&signed.header.data_root, data_root)
+            })
+            .cloned()
+    }
+
+    fn is_producer(&self, producer: &PeerId) -> bool {
+        self.producers
+            .lock()
+            .expect("Producers lock poisoned")
+            .contains(producer)
+            && !self
+                .producer_scores
+                .lock()
+                .expect("Producer scores lock poisoned")
+                .is_banned(producer)
+    }
+
+    /// Log that ingestion of a square begins, if an ingestion log is open
+    fn log_begin(
+        &self,
+        height: u64,
+        shares: &[Vec<E::ScalarField>],
+        scale: usize,
+        namespaces: Option<&NamespaceIndex>,
+    ) {
+        let mut wal = self.wal.lock().expect("Ingestion log lock poisoned");
+        if let Some(wal) = wal.as_mut() {
+            if let Err(e) = wal.begin(height, shares, scale, namespaces) {
+                error!("Failed to log ingestion of height {}: {}", height, e);
+            }
+        }
+    }
+
+    /// Log that the block of `header` is stored, if an ingestion log is open
+    fn log_commit(&self, header: &Header) {
+        let mut wal = self.wal.lock().expect("Ingestion log lock poisoned");
+        if let Some(wal) = wal.as_mut() {
+            if let Err(e) = wal.commit(header) {
+                error!("Failed to log storing height {}: {}", header.height, e);
+            }
+        }
+    }
+
+    fn health(&self, snapshot_path: Option<&Path>) -> HealthReport {
+        let store = self.store.read().expect("Store lock poisoned");
+        let latest = store.latest_height().and_then(|height| store.get(height));
+        HealthReport {
+            srs_loaded: !store.params().powers_of_g.is_empty(),
+            latest_height: latest.map(|block| block.header.height),
+            latest_committed: latest.map_or(false, |block| {
+                block.col_commitments.len() == block.length()
+                    && block.length() == block.prover.shares().len() * block.prover.scale()
+            }),
+            storage_writable: snapshot_path.map_or(true, is_writable),
+            peers_connected: self.peers.load(Ordering::Relaxed),
+        }
+    }
+
+    fn metrics(&self) -> String {
+        let mut out = self
+            .scores
+            .lock()
+            .expect("Scores lock poisoned")
+            .metrics("full_lion");
+        out.push_str(
+            &self
+                .producer_scores
+                .lock()
+                .expect("Producer scores lock poisoned")
+                .metrics("full_lion_producer"),
+        );
+        out.push_str(
+            &self
+                .quotas
+                .lock()
+                .expect("Quotas lock poisoned")
+                .metrics("full_lion"),
+        );
+        out.push_str(&self.pipeline.metrics("full_lion"));
+        out.push_str(&self.rows.metrics("full_lion_row_cache"));
+        out.push_str(&self.openings.metrics("full_lion_opening_cache"));
+        out
+    }
+
+    /// Cells of `height` this node holds, empty if it holds none
+    fn own_availability(&self, height: u64) -> CellBitfield {
+        self.store
+            .read()
+            .expect("Store lock poisoned")
+            .availability(height)
+            .unwrap_or_else(|| CellBitfield::empty(0))
+    }
+
+    fn receipt(&self, ticket: u64) -> Option<BlobReceipt> {
+        self.receipts
+            .lock()
+            .expect("Receipts lock poisoned")
+            .get(&ticket)
+            .cloned()
+    }
+
+    fn penalize(&self, peer: IpAddr, misbehaviour: Misbehaviour) {
+        self.scores
+            .lock()
+            .expect("Scores lock poisoned")
+            .record(&peer, misbehaviour);
+    }
+
+    fn observe_header(&self, signed: SignedHeader) -> Option<EquivocationProof> {
+        if !self.is_producer(&signed.producer_id()) {
+            return None;
+        }
+        let proof = self
+            .headers
+            .lock()
+            .expect("Headers lock poisoned")
+            .observe(signed)?;
+        warn!(
+            "Producer {} equivocated at height {}",
+            proof.producer_id(),
+            proof.height()
+        );
+        // the signer equivocated, not whoever relayed the second header
+        self.producer_scores
+            .lock()
+            .expect("Producer scores lock poisoned")
+            .record(&proof.producer_id(), Misbehaviour::Equivocation);
+        self.equivocations
+            .lock()
+            .expect("Equivocations lock poisoned")
+            .push(proof.clone());
+        Some(proof)
+    }
+
+    async fn serve(
+        self,
+        stream: TcpStream,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+        peer: IpAddr,
+        mut stop: watch::Receiver<bool>,
+    ) -> Result<()> {
+        let _peer = PeerGuard::new(self.peers.clone());
+        let mut conn = tokio::select! {
+            conn = Connection::accept(stream, keys, capabilities) => conn?,
+            _ = wait_for_request(&mut stop) => return Ok(()),
+        };
+        let mut credentials = Credentials {
+            peer: Some(conn.remote()),
+            token: None,
+        };
+        loop {
+            // only wait for new requests while running; a request the peer started
+            // sending is always read and answered before the connection is closed
+            tokio::select! {
+                readable = conn.readable() => readable?,
+                _ = wait_for_request(&mut stop) => break,
+            };
+            let msg = match tokio::time::timeout(REQUEST_TIMEOUT, conn.read_message()).await {
+                Ok(msg) => msg,
+                Err(_) => bail!("Timed out reading a request from {}", peer),
+            };
+            let msg = match msg {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::InvalidData {
+                        self.penalize(peer, Misbehaviour::Spam);
+                    }
+                    return Err(e.into());
+                }
+            };
+            let retry_after = self
+                .quotas
+                .lock()
+                .expect("Quotas lock poisoned")
+                .check(&peer);
+            if let Some(retry_after) = retry_after {
+                let reply = LionMessages::RateLimited {
+                    retry_after_ms: retry_after.as_millis() as u64,
+                };
+                conn.write_message(&reply).await?;
+            } else {
+                // relaying awaits the target, so it cannot happen inside `handle`
+                let reply = match msg {
+                    LionMessages::RelayRequest { target, request } => Some(
+                        self.relay(keys, capabilities, peer, &credentials, target, &request)
+                            .await,
+                    ),
+                    // openings and proofs are computed off the async workers
+                    msg => {
+                        let (inner, local) = (self.clone(), keys.peer_id());
+                        let capabilities = capabilities.clone();
+                        let mut held = credentials.clone();
+                        let (reply, held) = tokio::task::spawn_blocking(move || {
+                            let reply = inner.handle(&local, &capabilities, peer, &mut held, msg);
+                            (reply, held)
+                        })
+                        .await?;
+                        credentials = held;
+                        reply?
+                    }
+                };
+                if let Some(reply) = reply {
+                    let written = conn.write_message(&reply).await?;
+                    self.quotas
+                        .lock()
+                        .expect("Quotas lock poisoned")
+                        .charge(&peer, written as u64);
+                }
+            }
+            if self
+                .scores
+                .lock()
+                .expect("Scores lock poisoned")
+                .is_banned(&peer)
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forward a sampling request of `peer` to `target` over a connection of our
+    /// own and return its reply
+    async fn relay(
+        &self,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+        peer: IpAddr,
+        credentials: &Credentials,
+        target: String,
+        request: &[u8],
+    ) -> LionMessages {
+        if !self.auth.authorize(Method::Sample, credentials) {
+            return LionMessages::Denied {
+                reason: format!("{:?} requires authorization", Method::Sample),
+            };
+        }
+        let request = match LionMessages::decode(request) {
+            Ok(request) if Method::of(&request) == Some(Method::Sample) => request,
+            _ => return LionMessages::RelayUnavailable { target },
+        };
+        if !self.relaying.load(Ordering::Relaxed) {
+            return LionMessages::RelayUnavailable { target };
+        }
+        let (addr, expected) = match target.parse::<SocketAddr>() {
+            Ok(addr) => match self
+                .relay_targets
+                .lock()
+                .expect("Relay targets lock poisoned")
+                .get(&addr)
+            {
+                Some(expected) => (addr, *expected),
+                None => return LionMessages::RelayUnavailable { target },
+            },
+            Err(_) => return LionMessages::RelayUnavailable { target },
+        };
+        let mut relays = self.relays.lock().expect("Relays lock poisoned");
+        if let Some(retry_after) = relays.check(&peer) {
+            return LionMessages::RateLimited {
+                retry_after_ms: retry_after.as_millis() as u64,
+            };
+        }
+        relays.charge(&peer, 1);
+        drop(relays);
+        let forwarded = tokio::time::timeout(RELAY_TIMEOUT, async {
+            let mut conn = Connection::connect(addr, keys, expected, capabilities).await?;
+            conn.request(&request).await
+        })
+        .await;
+        match forwarded {
+            Ok(Ok(reply)) => LionMessages::RelayResponse {
+                response: reply.encode(),
+            },
+            _ => LionMessages::RelayUnavailable { target },
+        }
+    }
+
+    /// Check a square gossiped or pulled from `peer` and store it, penalizing the
+    /// peer if it does not verify. Only squares an accepted producer signed a header
+    /// for are checked at all. A square conflicting with the one already held is
+    /// refused without penalty, as the peer may only relay what a producer signed.
+    fn store_disseminated(
+        &self,
+        capabilities: &Capabilities,
+        peer: IpAddr,
+        msg: LionMessages,
+    ) -> Result<Header> {
+        match dissemination_root(&msg) {
+            Some((height, root)) if self.signed_header(height, root).is_none() => {
+                bail!("No producer signed the square sent for height {}", height)
+            }
+            Some(_) => {}
+            None => bail!("Not a dissemination message"),
+        }
+        let params = self
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .shared_params();
+        let (height, prover) =
+            match accept_dissemination::<E, H>(msg, params, max_length(capabilities)) {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    self.penalize(peer, Misbehaviour::InvalidProof);
+                    return Err(e);
+                }
+            };
+        let block = StoredBlock::new(height, prover);
+        let header = block.header.clone();
+
+        let mut store = self.store.write().expect("Store lock poisoned");
+        if let Some(existing) = store.header(height) {
+            if !digests_equal(&existing.data_root, &block.header.data_root) {
+                bail!("Peer sent a second, different square for height {}", height);
+            }
+        }
+        store.insert(block);
+        drop(store);
+        self.prune_headers();
+
+        self.scores
+            .lock()
+            .expect("Scores lock poisoned")
+            .record_good(&peer);
+        Ok(header)
+    }
+
+    async fn disseminate_cells(
+        &self,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+        height: u64,
+        peers: &[(SocketAddr, Option<PeerId>)],
+    ) -> Result<usize> {
+        let block = match self.fetch(height) {
+            Some(block) => block,
+            None => bail!("No square to disseminate at height {}", height),
+        };
+        let count = peers.len();
+        let batches: Vec<LionMessages> = tokio::task::spawn_blocking(move || {
+            let plan = DisseminationPlan::new(block.length(), count);
+            (0..count)
+                .map(|peer| cell_batch(&block, &plan.cells(peer)))
+                .collect()
+        })
+        .await?;
+        let mut reached = 0;
+        for ((addr, expected), batch) in peers.iter().zip(&batches) {
+            let sent = async {
+                let mut conn = Connection::connect(addr, keys, *expected, capabilities).await?;
+                conn.write_message(batch).await
+            };
+            match sent.await {
+                Ok(_) => reached += 1,
+                Err(e) => warn!(
+                    "Failed to send cells of height {} to {}: {}",
+                    height, addr, e
+                ),
+            }
+        }
+        Ok(reached)
+    }
+
+    /// Compare holdings with the peer at `addr` from `depth` below the local tip
+    /// on, and pull every square the peer holds completely that this node lacks.
+    /// Squares this node holds and the peer lacks are left for the peer to pull
+    /// in its own rounds. `pull` in the returned diff lists only the heights
+    /// actually pulled.
+    async fn sync_with(
+        &self,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+        addr: SocketAddr,
+        expected: Option<PeerId>,
+        depth: u64,
+    ) -> Result<SyncDiff> {
+        let from = self
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .latest_height()
+            .map_or(0, |tip| tip.saturating_sub(depth));
+        let to = self.tip().saturating_add(MAX_SYNC_AHEAD);
+        let mut conn = Connection::connect(addr, keys, expected, capabilities).await?;
+        let request = LionMessages::SyncRequest { from, to };
+        let summary = summary_entries(conn.request(&request).await?, max_length(capabilities))?;
+        // a capped summary says nothing about the heights past its last one
+        let to = match summary.last() {
+            Some(last) if summary.len() == MAX_SYNC_HEIGHTS => last.height.min(to),
+            _ => to,
+        };
+        let remote: Vec<SyncEntry> = summary
+            .into_iter()
+            .filter(|entry| (from..=to).contains(&entry.height))
+            .collect();
+        let local = self
+            .store
+            .read()
+            .expect("Store lock poisoned")
+            .sync_entries(from, to);
+        let mut diff = SyncDiff::compare(&local, &remote);
+        for height in &diff.conflicts {
+            warn!("{} holds a different square at height {}", addr, height);
+        }
+
+        let mut pulled = vec![];
+        for entry in remote
+            .iter()
+            .filter(|entry| diff.pull.contains(&entry.height))
+        {
+            if self.signed_header(entry.height, &entry.data_root).is_none() {
+                self.pull_header(&mut conn, addr, entry.height).await?;
+            }
+            let request = LionMessages::SquareRequest {
+                height: entry.height,
+            };
+            let msg = match conn.request(&request).await? {
+                msg @ (LionMessages::ExtendedSquare { .. }
+                | LionMessages::OriginalShares { .. }) => msg,
+                LionMessages::SquareUnavailable { .. } => continue,
+                other => bail!("Unexpected reply to square request: {:?}", other),
+            };
+            match self.store_disseminated(capabilities, addr.ip(), msg) {
+                Ok(header) if header.height == entry.height => pulled.push(header.height),
+                Ok(header) => warn!(
+                    "{} answered a request for height {} with height {}",
+                    addr, entry.height, header.height
+                ),
+                Err(e) => warn!(
+                    "Failed to pull height {} from {}: {}",
+                    entry.height, addr, e
+                ),
+            }
+        }
+        diff.pull = pulled;
+        Ok(diff)
+    }
+
+    /// Ask the peer on `conn` for the signed header at `height`, keeping it if an
+    /// accepted producer signed it
+    async fn pull_header(
+        &self,
+        conn: &mut Connection,
+        addr: SocketAddr,
+        height: u64,
+    ) -> Result<()> {
+        match conn
+            .request(&LionMessages::HeaderRequest { height })
+            .await?
+        {
+            LionMessages::HeaderAnnouncement { header } => {
+                match SignedHeader::from_bytes(&header) {
+                    Ok(signed) if signed.verify() && signed.header.height == height => {
+                        self.observe_header(signed);
+                    }
+                    _ => self.penalize(addr.ip(), Misbehaviour::InvalidProof),
+                }
+            }
+            LionMessages::HeaderUnavailable { .. } => {}
+            other => bail!("Unexpected reply to header request: {:?}", other),
+        }
+        Ok(())
+    }
+
+    /// Answer `msg` from `peer` on behalf of the node `local`, which announced
+    /// `capabilities`
+    fn handle(
+        &self,
+        local: &PeerId,
+        capabilities: &Capabilities,
+        peer: IpAddr,
+        credentials: &mut Credentials,
+        msg: LionMessages,
+    ) -> Result<Option<LionMessages>> {
+        if let Some(method) = Method::of(&msg) {
+            if !self.auth.authorize(method, credentials) {
+                return Ok(Some(LionMessages::Denied {
+                    reason: format!("{:?} requires authorization", method),
+                }));
+            }
+        }
+
+        match msg {
+            LionMessages::Authenticate { token } => {
+                if self.auth.is_valid_token(&token) {
+                    credentials.token = Some(token);
+                    Ok(Some(LionMessages::Authenticated))
+                } else {
+                    self.penalize(peer, Misbehaviour::Spam);
+                    Ok(Some(LionMessages::Denied {
+                        reason: "Invalid token".to_string(),
+                    }))
+                }
+            }
+            LionMessages::VerifierKeyRequest => {
+                let store = self.store.read().expect("Store lock poisoned");
+                Ok(Some(LionMessages::VerifierKeyResponse {
+                    vk: verifier_key_to_bytes(&verifier_key(store.params())),
+                }))
+            }
+            LionMessages::SampleRequest { height, rid, cid } => {
+                // cells outside the square are refused before reading anything back
+                // from cold storage
+                let sample = match self.held(height) {
+                    Some(held) => self
+                        .serve_held(&held, &[(rid, cid)])
+                        .map(|samples| samples[0].to_bytes()),
+                    // cells of partly held squares are served as they arrived
+                    None => self.partial_samples(height, &[(rid, cid)]).pop(),
+                };
+                let reply = match sample {
+                    Some(sample) => LionMessages::SampleResponse { height, sample },
+                    None => LionMessages::SampleUnavailable { height, rid, cid },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::CellsRequest { height, cells } => {
+                let samples = match self.held(height) {
+                    Some(held) if cells.len() <= held.length() * held.length() => self
+                        .serve_held(&held, &cells)
+                        .map(|samples| samples.iter().map(CellSample::to_bytes).collect())
+                        .unwrap_or_default(),
+                    Some(_) => vec![],
+                    // cells of partly held squares are served as they arrived
+                    None => self.partial_samples(height, &cells),
+                };
+                self.touch(height);
+                Ok(Some(LionMessages::CellsResponse { height, samples }))
+            }
+            LionMessages::LineRequest { height, axis, idx } => {
+                let reply = match self.fetch(height) {
+                    Some(block) if idx < block.length() => {
+                        let sample = match axis {
+                            Axis::Row => block.prover.sample_row(idx),
+                            Axis::Col => block.prover.sample_col(idx),
+                        };
+                        LionMessages::LineResponse {
+                            height,
+                            sample: sample.to_bytes(),
+                        }
+                    }
+                    _ => LionMessages::LineUnavailable { height, axis, idx },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::NamespaceRequest { height, namespace } => {
+                let data = match <[u8; 8]>::try_from(namespace.as_slice()) {
+                    Ok(id) => self
+                        .fetch(height)
+                        .and_then(|block| block.namespace_data(NamespaceId(id))),
+                    Err(_) => None,
+                };
+                let reply = match data {
+                    Some(data) => LionMessages::NamespaceResponse {
+                        height,
+                        data: data.to_bytes(),
+                    },
+                    None => LionMessages::NamespaceUnavailable { height, namespace },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::BlobRequest {
+                height,
+                namespace,
+                version,
+                start,
+                end,
+                len,
+            } => {
+                let placement = <[u8; 8]>::try_from(namespace.as_slice()).map(|id| BlobPlacement {
+                    namespace: NamespaceId(id),
+                    start,
+                    end,
+                    len,
+                });
+                let version = u8::try_from(version).ok().and_then(LayoutVersion::from_id);
+                let proofs = match (placement, version) {
+                    (Ok(placement), Some(version)) => self.fetch(height).and_then(|block| {
+                        let proof = block.blob_inclusion_proof(version, &placement)?;
+                        let namespaces = block.namespaces.as_ref()?;
+                        Some((proof, namespaces.proof::<H>(placement.namespace)))
+                    }),
+                    _ => None,
+                };
+                let reply = match proofs {
+                    Some((proof, namespace_proof)) => LionMessages::BlobResponse {
+                        height,
+                        proof: proof.to_bytes(),
+                        namespace_proof: namespace_proof.to_bytes(),
+                    },
+                    None => LionMessages::BlobUnavailable { height },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::BisectionRequest {
+                height,
+                rid,
+                lo,
+                hi,
+            } => {
+                let halves = self
+                    .fetch(height)
+                    .filter(|block| rid < block.length())
+                    .and_then(|block| {
+                        split_digests::<E::ScalarField, H>(
+                            &block.prover.square().row_vals(rid),
+                            lo,
+                            hi,
+                        )
+                    });
+                let reply = match halves {
+                    Some((left, right)) => LionMessages::BisectionResponse {
+                        height,
+                        rid,
+                        left: left.into(),
+                        right: right.into(),
+                    },
+                    None => LionMessages::BisectionUnavailable { height, rid },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::AuditChallenge {
+                height,
+                seed,
+                count,
+            } => {
+                let served = match self.held(height) {
+                    Some(held) if count <= MAX_AUDIT_CELLS => {
+                        let cells = audit_cells::<H>(&seed, held.length(), count);
+                        self.serve_held(&held, &cells)
+                    }
+                    _ => None,
+                };
+                let reply = match served {
+                    Some(samples) => LionMessages::AuditResponse {
+                        height,
+                        samples: samples.iter().map(CellSample::to_bytes).collect(),
+                    },
+                    None => LionMessages::AuditUnavailable { height },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::StorageChallenge {
+                height,
+                anchor_height,
+            } => {
+                let anchor = self
+                    .store
+                    .read()
+                    .expect("Store lock poisoned")
+                    .header(anchor_height)
+                    .map(|anchor| anchor.data_root.clone());
+                let served = match (self.held(height), anchor) {
+                    (Some(held), Some(anchor_root)) if anchor_height > height => {
+                        let cells = storage_cells::<H>(
+                            anchor_height,
+                            &anchor_root,
+                            &local.0,
+                            height,
+                            held.length(),
+                        );
+                        self.serve_held(&held, &cells)
+                    }
+                    _ => None,
+                };
+                let reply = match served {
+                    Some(samples) => LionMessages::StorageProof {
+                        height,
+                        anchor_height,
+                        samples: samples.iter().map(CellSample::to_bytes).collect(),
+                    },
+                    None => LionMessages::StorageUnavailable { height },
+                };
+                self.touch(height);
+                Ok(Some(reply))
+            }
+            LionMessages::CommitmentsRequest { height } => {
+                let store = self.store.read().expect("Store lock poisoned");
+                let reply = match store.commitments(height) {
+                    Some(commitments) => LionMessages::Commitments {
+                        height,
+                        root: commitments.data_root,
+                        row_commitments: commitments
+                            .row_commitments
+                            .iter()
+                            .map(commitment_to_bytes::<E>)
+                            .collect(),
+                        col_commitments: commitments
+                            .col_commitments
+                            .iter()
+                            .map(commitment_to_bytes::<E>)
+                            .collect(),
+                    },
+                    None => LionMessages::CommitmentsUnavailable { height },
+                };
+                Ok(Some(reply))
+            }
+            LionMessages::AvailabilityRequest { height } => {
+                Ok(Some(LionMessages::AvailabilityAnnouncement {
+                    height,
+                    bitfield: self.own_availability(height).to_compressed(),
+                }))
+            }
+            LionMessages::AvailabilityAnnouncement { height, bitfield } => {
+                let bitfield = CellBitfield::from_compressed(&bitfield, max_length(capabilities));
+                match (credentials.peer, bitfield) {
+                    (Some(sender), Ok(bitfield)) => {
+                        let tip = self.tip();
+                        // announcements of heights nowhere near the tip are dropped
+                        self.availability
+                            .lock()
+                            .expect("Availability lock poisoned")
+                            .record(sender, height, bitfield, tip);
+                    }
+                    (None, Ok(_)) => {}
+                    (_, Err(_)) => self.penalize(peer, Misbehaviour::Spam),
+                }
+                Ok(None)
+            }
+            LionMessages::HeaderAnnouncement { header } => {
+                match SignedHeader::from_bytes(&header) {
+                    Ok(signed) if !signed.verify() => {
+                        self.penalize(peer, Misbehaviour::InvalidProof)
+                    }
+                    // headers of unknown signers are not worth relaying or keeping
+                    Ok(signed) if !self.is_producer(&signed.producer_id()) => {
+                        self.penalize(peer, Misbehaviour::Spam)
+                    }
+                    Ok(signed) => {
+                        // relaying proof of an equivocation is a service
+                        if self.observe_header(signed).is_some() {
+                            self.scores
+                                .lock()
+                                .expect("Scores lock poisoned")
+                                .record_good(&peer);
+                        }
+                    }
+                    Err(_) => self.penalize(peer, Misbehaviour::Spam),
+                }
+                Ok(None)
+            }
+            msg @ (LionMessages::ExtendedSquare { .. } | LionMessages::OriginalShares { .. }) => {
+                self.store_disseminated(capabilities, peer, msg)?;
+                Ok(None)
+            }
+            msg @ LionMessages::CellBatch { .. } => {
+                // only batches of squares a producer signed may claim a height
+                let signed = dissemination_root(&msg)
+                    .and_then(|(height, root)| self.signed_header(height, root));
+                let signed = match signed {
+                    Some(signed) => signed,
+                    None => {
+                        info!("Ignored cell batch no producer signed");
+                        return Ok(None);
+                    }
+                };
+                let params = self
+                    .store
+                    .read()
+                    .expect("Store lock poisoned")
+                    .shared_params();
+                let partial = match accept_cell_batch::<E, H>(
+                    msg,
+                    &signed.header,
+                    &params,
+                    max_length(capabilities),
+                ) {
+                    Ok(partial) => partial,
+                    Err(e) => {
+                        self.penalize(peer, Misbehaviour::InvalidProof);
+                        return Err(e);
+                    }
+                };
+                let height = partial.header.height;
+                if !self
+                    .store
+                    .write()
+                    .expect("Store lock poisoned")
+                    .insert_partial(partial)
+                {
+                    info!("Ignored cell batch for height {}", height);
+                }
+                Ok(None)
+            }
+            LionMessages::SyncRequest { from, to } => Ok(Some(summary_message(
+                &self
+                    .store
+                    .read()
+                    .expect("Store lock poisoned")
+                    .sync_entries(from, to),
+            ))),
+            LionMessages::HeaderRequest { height } => {
+                let data_root = self
+                    .store
+                    .read()
+                    .expect("Store lock poisoned")
+                    .header(height)
+                    .map(|header| header.data_root.clone());
+                let reply = match data_root.and_then(|root| self.signed_header(height, &root)) {
+                    Some(signed) => LionMessages::HeaderAnnouncement {
+                        header: signed.to_bytes(),
+                    },
+                    None => LionMessages::HeaderUnavailable { height },
+                };
+                Ok(Some(reply))
+            }
+            LionMessages::SquareRequest { height } => {
+                let reply = match self.fetch(height) {
+                    Some(block) => stored_message(&block, DisseminationMode::OriginalShares),
+                    None => LionMessages::SquareUnavailable { height },
+                };
+                Ok(Some(reply))
+            }
+            // relay requests are forwarded before reaching here
+            LionMessages::RelayRequest { target, .. } => {
+                Ok(Some(LionMessages::RelayUnavailable { target }))
+            }
+            // replies are only expected on connections we initiated
+            LionMessages::SampleResponse { .. }
+            | LionMessages::SampleUnavailable { .. }
+            | LionMessages::Authenticated
+            | LionMessages::Denied { .. }
+            | LionMessages::VerifierKeyResponse { .. }
+            | LionMessages::LineResponse { .. }
+            | LionMessages::LineUnavailable { .. }
+            | LionMessages::NamespaceResponse { .. }
+            | LionMessages::NamespaceUnavailable { .. }
+            | LionMessages::BisectionResponse { .. }
+            | LionMessages::BisectionUnavailable { .. }
+            | LionMessages::RateLimited { .. }
+            | LionMessages::AuditResponse { .. }
+            | LionMessages::AuditUnavailable { .. }
+            | LionMessages::RelayResponse { .. }
+            | LionMessages::RelayUnavailable { .. }
+            | LionMessages::SyncSummary { .. }
+            | LionMessages::SquareUnavailable { .. }
+            | LionMessages::BlobResponse { .. }
+            | LionMessages::BlobUnavailable { .. }
+            | LionMessages::StorageProof { .. }
+            | LionMessages::StorageUnavailable { .. }
+            | LionMessages::Commitments { .. }
+            | LionMessages::CommitmentsUnavailable { .. }
+            | LionMessages::HeaderUnavailable { .. }
+            | LionMessages::CellsResponse { .. } => {
+                self.penalize(peer, Misbehaviour::Spam);
+                Ok(None)
+            }
+        }
+    }
 }
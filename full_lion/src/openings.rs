@@ -0,0 +1,65 @@
+use crate::cache::LruCache;
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::Proof;
+
+use std::sync::{Mutex, MutexGuard};
+
+/// Height, data root and cell an opening was computed for
+type OpeningKey = (u64, Vec<u8>, usize, usize);
+
+/// KZG openings already computed, by height and cell. Cells sampled by many light
+/// nodes are opened once; past the capacity the least recently used are dropped.
+/// Shared by every request being served, it is only locked to look openings up
+/// and store them, never while one is computed.
+pub struct OpeningCache<E: Pairing> {
+    openings: Mutex<LruCache<OpeningKey, Proof<E>>>,
+}
+
+impl<E: Pairing> OpeningCache<E> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            openings: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Opening of cell (rid, cid) of the square with `data_root` at `height`,
+    /// computed with `open` unless it is cached. Openings cached for another square
+    /// at the same height are never returned.
+    pub fn get_or_open(
+        &self,
+        height: u64,
+        data_root: &[u8],
+        rid: usize,
+        cid: usize,
+        open: impl FnOnce() -> Proof<E>,
+    ) -> Proof<E> {
+        let key = (height, data_root.to_vec(), rid, cid);
+        if let Some(proof) = self.lock().get(&key) {
+            return *proof;
+        }
+        let proof = open();
+        self.lock().insert(key, proof);
+        proof
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.lock().set_capacity(capacity);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn metrics(&self, prefix: &str) -> String {
+        self.lock().metrics(prefix)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LruCache<OpeningKey, Proof<E>>> {
+        self.openings.lock().expect("Opening cache lock poisoned")
+    }
+}
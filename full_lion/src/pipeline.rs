@@ -0,0 +1,268 @@
+use square_reed_solomon::progress::{ProgressObserver, Stage};
+
+use lion_roars::header::Header;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Where a submitted square is on its way to being served
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Readiness {
+    /// Accepted and waiting for a free worker
+    Queued,
+    /// Being extended and committed to
+    Proving,
+    /// Stored and served under this header
+    Ready(Header),
+}
+
+/// Tracks squares accepted for ingestion whose extension and commitments are
+/// computed in the background, bounding how many are proven at once. Heights
+/// leave the pipeline once stored.
+pub struct CommitmentPipeline {
+    states: Mutex<BTreeMap<u64, Readiness>>,
+    /// Latest stage reached by each height being proven, with its steps done and total
+    progress: Mutex<BTreeMap<u64, (Stage, usize, usize)>>,
+    workers: Arc<Semaphore>,
+    /// Number of workers, and permits still to retire since the pool last shrank
+    /// while they were held
+    size: Mutex<(usize, usize)>,
+}
+
+impl CommitmentPipeline {
+    pub fn new(workers: usize) -> Self {
+        assert!(workers > 0, "Pipeline needs at least one worker");
+        Self {
+            states: Mutex::new(BTreeMap::new()),
+            progress: Mutex::new(BTreeMap::new()),
+            workers: Arc::new(Semaphore::new(workers)),
+            size: Mutex::new((workers, 0)),
+        }
+    }
+
+    /// Bound proving to `workers` heights at once from now on, including heights
+    /// already queued. Workers proving when the pool shrinks finish their height.
+    pub fn set_workers(&self, workers: usize) {
+        assert!(workers > 0, "Pipeline needs at least one worker");
+        let mut size = self.size.lock().expect("Pipeline lock poisoned");
+        let (current, retiring) = &mut *size;
+        if workers > *current {
+            let added = workers - *current;
+            let kept = added.min(*retiring);
+            *retiring -= kept;
+            self.workers.add_permits(added - kept);
+        } else {
+            let mut removed = *current - workers;
+            while removed > 0 {
+                match self.workers.try_acquire() {
+                    Ok(permit) => permit.forget(),
+                    Err(_) => break,
+                }
+                removed -= 1;
+            }
+            *retiring += removed;
+        }
+        *current = workers;
+    }
+
+    /// Number of heights proven at once
+    pub fn workers(&self) -> usize {
+        self.size.lock().expect("Pipeline lock poisoned").0
+    }
+
+    /// Accept `height`, which is queued until a worker is free
+    pub fn enqueue(&self, height: u64) {
+        self.states
+            .lock()
+            .expect("Pipeline lock poisoned")
+            .insert(height, Readiness::Queued);
+    }
+
+    /// Wait for a free worker and mark `height` as being proven. The worker is
+    /// released when the permit is dropped.
+    pub async fn start(&self, height: u64) -> OwnedSemaphorePermit {
+        let permit = loop {
+            let permit = self
+                .workers
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("Pipeline semaphore is never closed");
+            let mut size = self.size.lock().expect("Pipeline lock poisoned");
+            if size.1 == 0 {
+                break permit;
+            }
+            // the pool shrank while this permit was held: retire it
+            size.1 -= 1;
+            permit.forget();
+        };
+        self.states
+            .lock()
+            .expect("Pipeline lock poisoned")
+            .insert(height, Readiness::Proving);
+        permit
+    }
+
+    /// Forget `height`, once its block is stored or its proving failed
+    pub fn finish(&self, height: u64) {
+        self.states
+            .lock()
+            .expect("Pipeline lock poisoned")
+            .remove(&height);
+        self.progress
+            .lock()
+            .expect("Progress lock poisoned")
+            .remove(&height);
+    }
+
+    /// Note that `done` of the `total` steps of `stage` are complete for `height`.
+    /// Reports from workers racing each other never move the progress back.
+    pub fn record(&self, height: u64, stage: Stage, done: usize, total: usize) {
+        let mut progress = self.progress.lock().expect("Progress lock poisoned");
+        match progress.get(&height) {
+            Some((current, current_done, _)) if *current == stage && *current_done >= done => {}
+            _ => {
+                progress.insert(height, (stage, done, total));
+            }
+        }
+    }
+
+    /// Latest stage `height` reached while being proven, with its steps done and total
+    pub fn progress(&self, height: u64) -> Option<(Stage, usize, usize)> {
+        self.progress
+            .lock()
+            .expect("Progress lock poisoned")
+            .get(&height)
+            .copied()
+    }
+
+    /// State of `height`, if it is still in the pipeline
+    pub fn readiness(&self, height: u64) -> Option<Readiness> {
+        self.states
+            .lock()
+            .expect("Pipeline lock poisoned")
+            .get(&height)
+            .cloned()
+    }
+
+    /// Number of heights queued or being proven
+    pub fn pending(&self) -> usize {
+        self.states.lock().expect("Pipeline lock poisoned").len()
+    }
+
+    pub fn metrics(&self, prefix: &str) -> String {
+        let mut out = format!("{prefix}_pipeline_pending {}\n", self.pending());
+        let progress = self.progress.lock().expect("Progress lock poisoned");
+        for (height, (stage, done, total)) in progress.iter() {
+            let labels = format!("height=\"{height}\",stage=\"{}\"", stage.name());
+            out.push_str(&format!(
+                "{prefix}_pipeline_steps_done{{{labels}}} {done}\n"
+            ));
+            out.push_str(&format!(
+                "{prefix}_pipeline_steps_total{{{labels}}} {total}\n"
+            ));
+        }
+        out
+    }
+}
+
+/// Reports the progress of proving one height to its pipeline
+pub struct HeightProgress {
+    pipeline: Arc<CommitmentPipeline>,
+    height: u64,
+}
+
+impl HeightProgress {
+    pub fn new(pipeline: Arc<CommitmentPipeline>, height: u64) -> Self {
+        Self { pipeline, height }
+    }
+}
+
+impl ProgressObserver for HeightProgress {
+    fn report(&self, stage: Stage, done: usize, total: usize) {
+        self.pipeline.record(self.height, stage, done, total);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommitmentPipeline, HeightProgress, Readiness};
+    use square_reed_solomon::progress::{ProgressObserver, Stage};
+
+    use std::sync::Arc;
+
+    #[tokio::test]
+    pub async fn workers_bound_concurrent_proving() {
+        let pipeline = CommitmentPipeline::new(1);
+        pipeline.enqueue(1);
+        pipeline.enqueue(2);
+        assert_eq!(pipeline.pending(), 2);
+
+        let permit = pipeline.start(1).await;
+        assert_eq!(pipeline.readiness(1), Some(Readiness::Proving));
+        let waiting = tokio::time::timeout(std::time::Duration::from_millis(10), pipeline.start(2));
+        assert!(waiting.await.is_err());
+        assert_eq!(pipeline.readiness(2), Some(Readiness::Queued));
+
+        drop(permit);
+        pipeline.finish(1);
+        let _permit = pipeline.start(2).await;
+        assert_eq!(pipeline.readiness(1), None);
+        assert_eq!(pipeline.readiness(2), Some(Readiness::Proving));
+    }
+
+    #[tokio::test]
+    pub async fn resizing_keeps_queued_heights() {
+        let short = std::time::Duration::from_millis(10);
+        let pipeline = CommitmentPipeline::new(2);
+        for height in 1..=4 {
+            pipeline.enqueue(height);
+        }
+        let first = pipeline.start(1).await;
+        let second = pipeline.start(2).await;
+
+        // both workers are busy, so the first one released is retired
+        pipeline.set_workers(1);
+        assert_eq!(pipeline.workers(), 1);
+        drop(first);
+        pipeline.finish(1);
+        assert!(tokio::time::timeout(short, pipeline.start(3))
+            .await
+            .is_err());
+        assert_eq!(pipeline.readiness(3), Some(Readiness::Queued));
+        drop(second);
+        pipeline.finish(2);
+        let third = tokio::time::timeout(short, pipeline.start(3))
+            .await
+            .unwrap();
+        assert_eq!(pipeline.readiness(3), Some(Readiness::Proving));
+
+        // growing again lets the last height start next to the busy one
+        pipeline.set_workers(2);
+        let _fourth = tokio::time::timeout(short, pipeline.start(4))
+            .await
+            .unwrap();
+        assert_eq!(pipeline.readiness(4), Some(Readiness::Proving));
+        drop(third);
+    }
+
+    #[test]
+    pub fn progress_is_reported_per_height() {
+        let pipeline = Arc::new(CommitmentPipeline::new(1));
+        pipeline.enqueue(3);
+        let progress = HeightProgress::new(pipeline.clone(), 3);
+        progress.report(Stage::CommitRows, 2, 8);
+        // a worker reporting late does not move progress back
+        progress.report(Stage::CommitRows, 1, 8);
+        assert_eq!(pipeline.progress(3), Some((Stage::CommitRows, 2, 8)));
+        assert!(pipeline
+            .metrics("full")
+            .contains("full_pipeline_steps_done{height=\"3\",stage=\"commit_rows\"} 2\n"));
+
+        pipeline.finish(3);
+        assert_eq!(pipeline.progress(3), None);
+        assert_eq!(pipeline.metrics("full"), "full_pipeline_pending 0\n");
+    }
+}
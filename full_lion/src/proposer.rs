@@ -0,0 +1,567 @@
+use crate::store::StoredBlock;
+use square_reed_solomon::layout::{build_layout, BlobReceipt, BlockLayout};
+use square_reed_solomon::namespace::NamespaceId;
+use square_reed_solomon::prover::RsSquareProver;
+
+use anyhow::{bail, Result};
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10;
+use lion_roars::dht::PeerId;
+use lion_roars::header::{Header, ProducerKey, SignedHeader};
+use rs_merkle::Hasher;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Most recent blocks whose per-namespace usage a proposer remembers
+pub const MAX_USAGE_HEIGHTS: usize = 256;
+
+/// Most blobs a proposer queues; further submissions fail until a block takes some
+pub const MAX_PENDING_BLOBS: usize = 4096;
+
+/// Rejected tickets whose reason a proposer remembers, oldest dropped first
+const MAX_REJECTED: usize = 4096;
+
+/// How a proposer turns submitted blobs into blocks
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProposerPolicy {
+    /// Fraction of every line that may be lost with the square still recoverable
+    pub erasure_rate: f64,
+    /// Most blob bytes one block takes; the rest wait for the next block
+    pub max_block_bytes: usize,
+    /// Time between two blocks while the node runs
+    pub block_interval: Duration,
+    /// Full nodes each block is disseminated to
+    pub peers: Vec<(SocketAddr, Option<PeerId>)>,
+}
+
+/// A pending blob as a `BlobSelection` sees it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlobInfo {
+    pub ticket: u64,
+    pub namespace: NamespaceId,
+    pub len: usize,
+    /// Fee offered at submission, 0 if none
+    pub fee: u64,
+}
+
+/// Decides which pending blobs a block takes when they do not all fit. Blobs are
+/// packed by decreasing priority, skipping those that would exceed the block or
+/// their namespace's limit.
+pub trait BlobSelection: Send + Sync {
+    /// Rank of `blob`: higher ranks are packed first, equal ranks in submission
+    /// order. `None` leaves the blob pending for a later block.
+    fn priority(&self, blob: &BlobInfo) -> Option<u64>;
+
+    /// Most blob bytes `namespace` may take in one block, `None` for no limit
+    /// beyond `ProposerPolicy::max_block_bytes`
+    fn namespace_limit(&self, _namespace: NamespaceId) -> Option<usize> {
+        None
+    }
+}
+
+/// Packs blobs in the order they were submitted
+pub struct FirstCome;
+
+impl BlobSelection for FirstCome {
+    fn priority(&self, _blob: &BlobInfo) -> Option<u64> {
+        Some(0)
+    }
+}
+
+/// Packs the blobs offering the highest fees first, leaving those below
+/// `min_fee` pending
+pub struct HighestFee {
+    pub min_fee: u64,
+}
+
+impl BlobSelection for HighestFee {
+    fn priority(&self, blob: &BlobInfo) -> Option<u64> {
+        (blob.fee >= self.min_fee).then_some(blob.fee)
+    }
+}
+
+/// A block built by a `Proposer`, with its signed header and a receipt for every
+/// blob it includes
+pub struct Proposal<E: Pairing, H: Hasher> {
+    pub block: StoredBlock<E, H>,
+    pub signed: SignedHeader,
+    pub receipts: Vec<BlobReceipt>,
+    /// Blob bytes each namespace takes in the block
+    pub usage: BTreeMap<NamespaceId, usize>,
+}
+
+/// A submitted blob waiting for a block
+struct PendingBlob {
+    ticket: u64,
+    namespace: NamespaceId,
+    data: Vec<u8>,
+    fee: u64,
+}
+
+impl PendingBlob {
+    fn info(&self) -> BlobInfo {
+        BlobInfo {
+            ticket: self.ticket,
+            namespace: self.namespace,
+            len: self.data.len(),
+            fee: self.fee,
+        }
+    }
+}
+
+/// Producer side of the protocol: queues submitted blobs and packs them into
+/// signed blocks, one height after the other
+pub struct Proposer {
+    key: ProducerKey,
+    policy: ProposerPolicy,
+    next_height: u64,
+    /// Hash of the last header proposed, which the next one names as its parent
+    parent: Vec<u8>,
+    next_ticket: u64,
+    /// In submission order
+    pending: Vec<PendingBlob>,
+    selection: Arc<dyn BlobSelection>,
+    /// Per-namespace blob bytes of the latest blocks, by height
+    usage: BTreeMap<u64, BTreeMap<NamespaceId, usize>>,
+    /// Why the latest blobs dropped from the queue could not be included, by ticket
+    rejected: BTreeMap<u64, String>,
+}
+
+impl Proposer {
+    pub fn new(key: ProducerKey, policy: ProposerPolicy, next_height: u64) -> Self {
+        Self {
+            key,
+            policy,
+            next_height,
+            parent: vec![],
+            next_ticket: 0,
+            pending: vec![],
+            selection: Arc::new(FirstCome),
+            usage: BTreeMap::new(),
+            rejected: BTreeMap::new(),
+        }
+    }
+
+    /// Replace the default selection, which packs blobs in submission order.
+    /// Pending blobs exceeding its namespace limits are rejected.
+    pub fn set_selection(&mut self, selection: Arc<dyn BlobSelection>) {
+        self.selection = selection;
+        for blob in std::mem::take(&mut self.pending) {
+            match self.selection.namespace_limit(blob.namespace) {
+                Some(limit) if blob.data.len() > limit => self.reject(
+                    blob.ticket,
+                    format!("Blob exceeds the namespace limit of {} bytes", limit),
+                ),
+                _ => self.pending.push(blob),
+            }
+        }
+    }
+
+    pub fn policy(&self) -> &ProposerPolicy {
+        &self.policy
+    }
+
+    /// Public key the proposer signs headers with
+    pub fn producer(&self) -> [u8; 32] {
+        self.key.public()
+    }
+
+    /// Continue the chain ending at `tip`, e.g. after a restart: the next block
+    /// names it as its parent
+    pub fn set_parent(&mut self, tip: &Header) {
+        self.parent = tip.hash().to_vec();
+    }
+
+    /// Height of the next block proposed
+    pub fn next_height(&self) -> u64 {
+        self.next_height
+    }
+
+    /// Number of blobs waiting for a block
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Blob bytes each namespace took in the block at `height`, if it is among the
+    /// latest `MAX_USAGE_HEIGHTS` this proposer built
+    pub fn usage(&self, height: u64) -> Option<&BTreeMap<NamespaceId, usize>> {
+        self.usage.get(&height)
+    }
+
+    /// Whether the blob submitted under `ticket` still waits for a block
+    pub fn is_pending(&self, ticket: u64) -> bool {
+        self.pending.iter().any(|blob| blob.ticket == ticket)
+    }
+
+    /// Why the blob submitted under `ticket` was dropped from the queue without
+    /// being included, if it is among the latest rejected
+    pub fn rejection(&self, ticket: u64) -> Option<&str> {
+        self.rejected.get(&ticket).map(String::as_str)
+    }
+
+    fn reject(&mut self, ticket: u64, reason: String) {
+        self.rejected.insert(ticket, reason);
+        while self.rejected.len() > MAX_REJECTED {
+            self.rejected.pop_first();
+        }
+    }
+
+    /// Queue `data` for inclusion under `namespace`, returning the ticket its
+    /// receipt will carry
+    pub fn submit(&mut self, namespace: NamespaceId, data: Vec<u8>) -> Result<u64> {
+        self.submit_with_fee(namespace, data, 0)
+    }
+
+    /// Queue `data` like `submit`, offering `fee` to a `BlobSelection` ranking by
+    /// fees
+    pub fn submit_with_fee(
+        &mut self,
+        namespace: NamespaceId,
+        data: Vec<u8>,
+        fee: u64,
+    ) -> Result<u64> {
+        if namespace.is_padding() {
+            bail!("The padding namespace is reserved");
+        }
+        if data.len() > self.policy.max_block_bytes {
+            bail!(
+                "Blob of {} bytes exceeds the block limit of {} bytes",
+                data.len(),
+                self.policy.max_block_bytes
+            );
+        }
+        if let Some(limit) = self.selection.namespace_limit(namespace) {
+            if data.len() > limit {
+                bail!(
+                    "Blob of {} bytes exceeds the namespace limit of {} bytes",
+                    data.len(),
+                    limit
+                );
+            }
+        }
+        if self.pending.len() >= MAX_PENDING_BLOBS {
+            bail!("{} blobs already wait for a block", self.pending.len());
+        }
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.pending.push(PendingBlob {
+            ticket,
+            namespace,
+            data,
+            fee,
+        });
+        Ok(ticket)
+    }
+
+    /// Pending blobs the next block takes, as indices into `pending` in packing
+    /// order, and the bytes each namespace takes
+    fn select(&self) -> (Vec<usize>, BTreeMap<NamespaceId, usize>) {
+        let mut ranked: Vec<(u64, usize)> = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, blob)| Some((self.selection.priority(&blob.info())?, idx)))
+            .collect();
+        ranked.sort_by_key(|(priority, idx)| (Reverse(*priority), *idx));
+
+        let (mut total, mut usage) = (0, BTreeMap::new());
+        let mut selected = vec![];
+        for (_, idx) in ranked {
+            let blob = &self.pending[idx];
+            let used = usage.get(&blob.namespace).copied().unwrap_or(0);
+            let limit = self
+                .selection
+                .namespace_limit(blob.namespace)
+                .unwrap_or(usize::MAX);
+            let len = blob.data.len();
+            if total + len > self.policy.max_block_bytes || used + len > limit {
+                continue;
+            }
+            total += len;
+            usage.insert(blob.namespace, used + len);
+            selected.push(idx);
+        }
+        (selected, usage)
+    }
+
+    /// Square holding the pending blobs at `selected` that `params` can commit to
+    fn layout<E: Pairing>(
+        &self,
+        selected: &[usize],
+        params: &kzg10::UniversalParams<E>,
+    ) -> Result<BlockLayout<E::ScalarField>> {
+        let blobs: Vec<(NamespaceId, Vec<u8>)> = selected
+            .iter()
+            .map(|idx| {
+                (
+                    self.pending[*idx].namespace,
+                    self.pending[*idx].data.clone(),
+                )
+            })
+            .collect();
+        let layout = match build_layout::<E::ScalarField>(&blobs, self.policy.erasure_rate) {
+            Some(layout) => layout,
+            None => bail!("No square fits {} pending blobs", blobs.len()),
+        };
+        if params.powers_of_g.len() <= layout.dimensions.length {
+            bail!(
+                "KZG setup does not support squares of side {}",
+                layout.dimensions.length
+            );
+        }
+        Ok(layout)
+    }
+
+    /// Pack the pending blobs the selection picks into the next block, extend it
+    /// and commit to it, and sign its header. `None` if no blob is picked. Blobs
+    /// that fit no square on their own are dropped from the queue, with the reason
+    /// kept for `rejection`; if the picked blobs only fail together, the first is
+    /// packed alone.
+    pub fn propose<E: Pairing, H: Hasher>(
+        &mut self,
+        params: Arc<kzg10::UniversalParams<E>>,
+    ) -> Result<Option<Proposal<E, H>>> {
+        let (selected, usage, layout) = loop {
+            let (selected, usage) = self.select();
+            if selected.is_empty() {
+                return Ok(None);
+            }
+            if let Ok(layout) = self.layout(&selected, &params) {
+                break (selected, usage, layout);
+            }
+            let failing: Vec<(u64, String)> = selected
+                .iter()
+                .filter_map(|idx| {
+                    let error = self.layout(&[*idx], &params).err()?;
+                    Some((self.pending[*idx].ticket, error.to_string()))
+                })
+                .collect();
+            if failing.is_empty() {
+                let first = &self.pending[selected[0]];
+                let usage = BTreeMap::from([(first.namespace, first.data.len())]);
+                break (
+                    vec![selected[0]],
+                    usage,
+                    self.layout(&selected[..1], &params)?,
+                );
+            }
+            let tickets: HashSet<u64> = failing.iter().map(|(ticket, _)| *ticket).collect();
+            self.pending.retain(|blob| !tickets.contains(&blob.ticket));
+            for (ticket, reason) in failing {
+                self.reject(ticket, reason);
+            }
+        };
+
+        let height = self.next_height;
+        let prover = RsSquareProver::with_params(&layout.shares, layout.dimensions.scale, params);
+        let mut block = StoredBlock::new(height, prover).with_namespaces(layout.namespaces);
+        block.header.parent = self.parent.clone();
+        let signed = self.key.sign(block.header.clone());
+        let tickets: Vec<u64> = selected
+            .iter()
+            .map(|idx| self.pending[*idx].ticket)
+            .collect();
+        let included: HashSet<u64> = tickets.iter().copied().collect();
+        let receipts = tickets
+            .iter()
+            .zip(layout.placements)
+            .map(|(ticket, placement)| BlobReceipt {
+                ticket: *ticket,
+                height,
+                placement,
+                commitment: block.header.data_root.clone(),
+                version: layout.version,
+                n_rows: layout.dimensions.n_rows,
+                scale: layout.dimensions.scale,
+            })
+            .collect();
+        self.pending.retain(|blob| !included.contains(&blob.ticket));
+        self.usage.insert(height, usage.clone());
+        while self.usage.len() > MAX_USAGE_HEIGHTS {
+            self.usage.pop_first();
+        }
+        self.next_height += 1;
+        self.parent = block.header.hash().to_vec();
+        Ok(Some(Proposal {
+            block,
+            signed,
+            receipts,
+            usage,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlobInfo, BlobSelection, HighestFee, Proposer, ProposerPolicy};
+    use square_reed_solomon::inclusion::{verify_blob_inclusion, ShareProof};
+    use square_reed_solomon::layout::verify_blob_proof;
+    use square_reed_solomon::namespace::{NamespaceId, PADDING_NAMESPACE};
+    use square_reed_solomon::verifier::verifier_key;
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use lion_roars::header::ProducerKey;
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn policy(max_block_bytes: usize) -> ProposerPolicy {
+        ProposerPolicy {
+            erasure_rate: 0.5,
+            max_block_bytes,
+            block_interval: Duration::from_secs(1),
+            peers: vec![],
+        }
+    }
+
+    #[test]
+    pub fn pending_blobs_become_signed_blocks() {
+        let params = Arc::new(
+            KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(8, false, &mut OsRng)
+                .expect("KZG setup failed"),
+        );
+        let mut proposer = Proposer::new(ProducerKey::generate(), policy(100), 7);
+        let namespace = NamespaceId([3; 8]);
+        assert!(proposer.submit(PADDING_NAMESPACE, vec![1]).is_err());
+        assert!(proposer.submit(namespace, vec![1; 101]).is_err());
+        let first = proposer.submit(namespace, vec![1; 60]).unwrap();
+        let second = proposer.submit(namespace, vec![2; 60]).unwrap();
+
+        // the second blob does not fit next to the first
+        let proposal = proposer
+            .propose::<Bls12_381, Sha256>(params.clone())
+            .unwrap()
+            .unwrap();
+        assert!(proposal.signed.verify());
+        assert_eq!(proposal.signed.header, proposal.block.header);
+        assert_eq!(proposal.block.header.height, 7);
+        assert!(proposal.block.header.parent.is_empty());
+        let parent = proposal.block.header.hash().to_vec();
+        assert!(!proposal.block.header.namespace_root.is_empty());
+        assert_eq!((proposer.pending(), proposer.next_height()), (1, 8));
+        let receipt = &proposal.receipts[..];
+        assert_eq!((receipt.len(), receipt[0].ticket), (1, first));
+        assert_eq!(
+            (receipt[0].placement.start, receipt[0].placement.len),
+            (0, 60)
+        );
+        assert_eq!(receipt[0].commitment, proposal.block.header.data_root);
+        assert_eq!(proposer.usage(7).unwrap()[&namespace], 60);
+        assert!(!proposer.is_pending(first) && proposer.is_pending(second));
+
+        // the blob is proven from the cells its receipt names
+        let receipt = &receipt[0];
+        assert_eq!(receipt.cells(), vec![(0, 0), (0, 2)]);
+        let proof = proposal
+            .block
+            .blob_proof(receipt.version, &receipt.placement)
+            .unwrap();
+        let data_root = receipt.commitment.clone().try_into().unwrap();
+        let root_version = proposal.block.prover.root_version();
+        let blob = verify_blob_proof::<Bls12_381, Sha256>(
+            &verifier_key(&params),
+            receipt.height,
+            root_version,
+            data_root,
+            receipt.version,
+            receipt.n_rows,
+            receipt.scale,
+            &receipt.placement,
+            &proof,
+        );
+        assert_eq!(blob, Some(vec![1; 60]));
+
+        let inclusion = proposal
+            .block
+            .blob_inclusion_proof(receipt.version, &receipt.placement)
+            .unwrap();
+        let inclusion = ShareProof::<Bls12_381, Sha256>::from_bytes(&inclusion.to_bytes()).unwrap();
+        let blob = verify_blob_inclusion(
+            &verifier_key(&params),
+            receipt.height,
+            root_version,
+            data_root,
+            receipt.version,
+            receipt.n_rows,
+            receipt.scale,
+            &receipt.placement,
+            &inclusion,
+        );
+        assert_eq!(blob, Some(vec![1; 60]));
+
+        let proposal = proposer
+            .propose::<Bls12_381, Sha256>(params.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(proposal.block.header.height, 8);
+        assert_eq!(proposal.block.header.parent, parent);
+        assert!(proposer
+            .propose::<Bls12_381, Sha256>(params)
+            .unwrap()
+            .is_none());
+    }
+
+    struct CappedFees;
+
+    impl BlobSelection for CappedFees {
+        fn priority(&self, blob: &BlobInfo) -> Option<u64> {
+            HighestFee { min_fee: 1 }.priority(blob)
+        }
+
+        fn namespace_limit(&self, namespace: NamespaceId) -> Option<usize> {
+            (namespace == NamespaceId([1; 8])).then_some(50)
+        }
+    }
+
+    #[test]
+    pub fn selection_follows_fees_and_namespace_limits() {
+        let mut proposer = Proposer::new(ProducerKey::generate(), policy(100), 0);
+        proposer.set_selection(Arc::new(CappedFees));
+        let (a, b) = (NamespaceId([1; 8]), NamespaceId([2; 8]));
+        proposer.submit_with_fee(b, vec![1; 40], 1).unwrap();
+        proposer.submit_with_fee(a, vec![2; 30], 5).unwrap();
+        proposer.submit_with_fee(a, vec![3; 30], 4).unwrap();
+        proposer.submit(b, vec![4; 10]).unwrap();
+        proposer.submit_with_fee(b, vec![5; 30], 3).unwrap();
+        assert!(proposer.submit_with_fee(a, vec![6; 51], 9).is_err());
+
+        // the second blob of a exceeds its namespace limit, the fourth pays nothing
+        let (selected, usage) = proposer.select();
+        assert_eq!(selected, vec![1, 4, 0]);
+        assert_eq!((usage[&a], usage[&b]), (30, 70));
+    }
+
+    #[test]
+    pub fn blobs_fitting_no_square_are_rejected() {
+        let params = Arc::new(
+            KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(8, false, &mut OsRng)
+                .expect("KZG setup failed"),
+        );
+        let mut proposer = Proposer::new(ProducerKey::generate(), policy(10_000), 0);
+        let namespace = NamespaceId([3; 8]);
+        let large = proposer.submit(namespace, vec![1; 5000]).unwrap();
+        let small = proposer.submit(namespace, vec![2; 60]).unwrap();
+
+        // the setup cannot commit to a square holding the first blob
+        let proposal = proposer
+            .propose::<Bls12_381, Sha256>(params.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(proposal.receipts.len(), 1);
+        assert_eq!(proposal.receipts[0].ticket, small);
+        assert!(!proposer.is_pending(large) && proposer.rejection(large).is_some());
+        assert!(proposer.rejection(small).is_none());
+        assert!(proposer
+            .propose::<Bls12_381, Sha256>(params)
+            .unwrap()
+            .is_none());
+    }
+}
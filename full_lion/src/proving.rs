@@ -0,0 +1,118 @@
+use square_reed_solomon::prover::RsSquareProver;
+
+use anyhow::{anyhow, Result};
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Commitment, Proof};
+use rs_merkle::Hasher;
+
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+/// A prover whose commitments and openings are computed on tokio's blocking pool,
+/// so that reactor threads never wait on an MSM. Provers sharing `workers` run at
+/// most that many such jobs at once between them.
+pub struct AsyncProver<E: Pairing, H: Hasher> {
+    prover: Arc<RsSquareProver<E, H>>,
+    workers: Arc<Semaphore>,
+}
+
+impl<E: Pairing, H: Hasher> Clone for AsyncProver<E, H> {
+    fn clone(&self) -> Self {
+        Self {
+            prover: self.prover.clone(),
+            workers: self.workers.clone(),
+        }
+    }
+}
+
+impl<E: Pairing, H: Hasher + Send + Sync + 'static> AsyncProver<E, H> {
+    pub fn new(prover: Arc<RsSquareProver<E, H>>, workers: Arc<Semaphore>) -> Self {
+        Self { prover, workers }
+    }
+
+    pub fn prover(&self) -> &RsSquareProver<E, H> {
+        &self.prover
+    }
+
+    /// KZG opening of cell (rid, cid), see `RsSquareProver::open_cell`. Fails if the
+    /// cell lies outside the square.
+    pub async fn open_cell_async(&self, rid: usize, cid: usize) -> Result<Proof<E>> {
+        self.check_cell(rid, cid)?;
+        self.run(move |prover| prover.open_cell(rid, cid)).await
+    }
+
+    /// Commitment to row rid, see `RsSquareProver::commit_to_row`. Fails if the row
+    /// lies outside the square.
+    pub async fn commit_row_async(&self, rid: usize) -> Result<Commitment<E>> {
+        self.check_cell(rid, 0)?;
+        self.run(move |prover| prover.commit_to_row(rid)).await
+    }
+
+    fn check_cell(&self, rid: usize, cid: usize) -> Result<()> {
+        let length = self.prover.shares().len() * self.prover.scale();
+        if rid >= length || cid >= length {
+            return Err(anyhow!(
+                "Cell ({}, {}) lies outside a square of side {}",
+                rid,
+                cid,
+                length
+            ));
+        }
+        Ok(())
+    }
+
+    /// Run `work` on the blocking pool once a worker is free
+    async fn run<T: Send + 'static>(
+        &self,
+        work: impl FnOnce(&RsSquareProver<E, H>) -> T + Send + 'static,
+    ) -> Result<T> {
+        let _worker = self
+            .workers
+            .acquire()
+            .await
+            .expect("Proving semaphore is never closed");
+        let prover = self.prover.clone();
+        tokio::task::spawn_blocking(move || work(&prover))
+            .await
+            .map_err(|e| anyhow!("Proving worker failed: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncProver;
+    use square_reed_solomon::prover::RsSquareProver;
+
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rs_merkle::algorithms::Sha256;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::Semaphore;
+
+    #[tokio::test]
+    pub async fn proofs_match_the_blocking_prover() {
+        let shares = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+        ];
+        let prover = Arc::new(RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2));
+        let workers = Arc::new(Semaphore::new(1));
+        let proving = AsyncProver::new(prover.clone(), workers.clone());
+
+        let (opening, commitment) =
+            tokio::join!(proving.open_cell_async(1, 3), proving.commit_row_async(2));
+        assert_eq!(opening.unwrap(), prover.open_cell(1, 3));
+        assert_eq!(commitment.unwrap(), prover.commit_to_row(2));
+        assert!(proving.open_cell_async(4, 0).await.is_err());
+
+        // no work starts while every worker is busy
+        let busy = workers.acquire().await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(10), proving.commit_row_async(0));
+        assert!(waiting.await.is_err());
+        drop(busy);
+        assert!(proving.commit_row_async(0).await.is_ok());
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Most bytes a single peer may be served within each window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuotaPolicy {
+    pub bytes_per_window: u64,
+    pub window: Duration,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Usage {
+    window_start: Instant,
+    /// Bytes served since `window_start`
+    bytes: u64,
+}
+
+/// Per-peer accounting of bytes served. With a policy set, a peer that used up its
+/// quota is refused until its window resets, so a few peers downloading whole
+/// squares cannot crowd out many small sample requests.
+pub struct BandwidthQuotas<K: Eq + Hash + Clone> {
+    policy: Option<QuotaPolicy>,
+    usage: HashMap<K, Usage>,
+    /// Bytes served to each peer since startup
+    totals: HashMap<K, u64>,
+}
+
+impl<K: Eq + Hash + Clone> BandwidthQuotas<K> {
+    /// Accounting only, without limits, when `policy` is `None`
+    pub fn new(policy: Option<QuotaPolicy>) -> Self {
+        Self {
+            policy,
+            usage: HashMap::new(),
+            totals: HashMap::new(),
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: Option<QuotaPolicy>) {
+        self.policy = policy;
+    }
+
+    /// `None` if `peer` may be served now, otherwise how long until its quota resets
+    pub fn check(&mut self, peer: &K) -> Option<Duration> {
+        let policy = self.policy?;
+        let usage = *self.usage.get(peer)?;
+        let elapsed = usage.window_start.elapsed();
+        if elapsed >= policy.window {
+            self.usage.remove(peer);
+            return None;
+        }
+        (usage.bytes >= policy.bytes_per_window).then(|| policy.window - elapsed)
+    }
+
+    /// Account for `bytes` just served to `peer`
+    pub fn charge(&mut self, peer: &K, bytes: u64) {
+        *self.totals.entry(peer.clone()).or_default() += bytes;
+        let window = self.policy.map_or(Duration::MAX, |policy| policy.window);
+        let usage = self.usage.entry(peer.clone()).or_insert(Usage {
+            window_start: Instant::now(),
+            bytes: 0,
+        });
+        if usage.window_start.elapsed() >= window {
+            *usage = Usage {
+                window_start: Instant::now(),
+                bytes: 0,
+            };
+        }
+        usage.bytes += bytes;
+    }
+
+    /// Bytes served to `peer` since startup
+    pub fn served(&self, peer: &K) -> u64 {
+        self.totals.get(peer).copied().unwrap_or(0)
+    }
+}
+
+impl<K: Eq + Hash + Clone + Display> BandwidthQuotas<K> {
+    /// Bytes served per peer in Prometheus text exposition format
+    pub fn metrics(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        for (peer, bytes) in self.totals.iter() {
+            out.push_str(&format!(
+                "{prefix}_peer_bytes_served{{peer=\"{peer}\"}} {bytes}\n"
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BandwidthQuotas, QuotaPolicy};
+    use std::time::Duration;
+
+    #[test]
+    pub fn greedy_peers_are_refused_until_the_window_resets() {
+        let mut quotas = BandwidthQuotas::<u8>::new(Some(QuotaPolicy {
+            bytes_per_window: 100,
+            window: Duration::from_secs(3600),
+        }));
+        assert_eq!(quotas.check(&1), None);
+        quotas.charge(&1, 60);
+        assert_eq!(quotas.check(&1), None);
+        quotas.charge(&1, 60);
+        assert!(quotas.check(&1).unwrap() <= Duration::from_secs(3600));
+        assert_eq!(quotas.check(&2), None);
+        assert_eq!(quotas.served(&1), 120);
+
+        quotas.set_policy(Some(QuotaPolicy {
+            bytes_per_window: 100,
+            window: Duration::ZERO,
+        }));
+        assert_eq!(quotas.check(&1), None);
+        assert!(quotas
+            .metrics("full")
+            .contains("full_peer_bytes_served{peer=\"1\"} 120"));
+    }
+}
@@ -0,0 +1,234 @@
+use crate::store::StoredBlock;
+use square_reed_solomon::bitfield::CellBitfield;
+use square_reed_solomon::cancel::CancelToken;
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::prover::RsSquareProver;
+use square_reed_solomon::reconstruct::{PartialSquare, ReconstructionError};
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::verifier::{digests_equal, verify_cell_sample, RootVersion};
+
+use anyhow::{bail, Result};
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{self, VerifierKey};
+use lion_roars::header::Header;
+use rs_merkle::Hasher;
+
+use std::sync::Arc;
+
+/// Rebuilds a square the node holds only part of, or none of, from cells
+/// served by peers. Every cell is checked against the header's data root before
+/// it is used, and the finished square must commit to that same root.
+pub struct Reconstruction<E: Pairing, H: Hasher> {
+    header: Header,
+    data_root: H::Hash,
+    root_version: RootVersion,
+    square: PartialSquare<E::ScalarField>,
+}
+
+impl<E: Pairing, H: Hasher> Reconstruction<E, H> {
+    /// Start rebuilding the square of side `length`, extended by `scale`, that
+    /// `header` commits to
+    pub fn new(header: Header, length: usize, scale: usize) -> Result<Self> {
+        let data_root = match H::Hash::try_from(header.data_root.clone()) {
+            Ok(root) => root,
+            Err(_) => bail!("Data root of height {} is not a hash", header.height),
+        };
+        let root_version = match SquareLayout::root_version_of(&header.layout) {
+            Some(version) => version,
+            None => bail!("Unknown root version at height {}", header.height),
+        };
+        let square = match PartialSquare::new(length, scale) {
+            Some(square) => square,
+            None => bail!(
+                "No square of side {} extended by {} can be rebuilt",
+                length,
+                scale
+            ),
+        };
+        Ok(Self {
+            header,
+            data_root,
+            root_version,
+            square,
+        })
+    }
+
+    pub fn height(&self) -> u64 {
+        self.header.height
+    }
+
+    /// Cells gathered so far
+    pub fn held(&self) -> CellBitfield {
+        self.square.held()
+    }
+
+    /// Whether enough cells were gathered to rebuild the whole square
+    pub fn is_recoverable(&self) -> bool {
+        self.held().is_recoverable(self.square.threshold())
+    }
+
+    /// Keep the cell of `sample` if it checks out against the data root
+    pub fn accept(&mut self, vk: &VerifierKey<E>, sample: &CellSample<E, H>) -> bool {
+        let length = self.square.length();
+        if sample.rid >= length
+            || sample.cid >= length
+            || !verify_cell_sample(vk, self.root_version, self.data_root, length, sample)
+        {
+            return false;
+        }
+        self.square.set(sample.rid, sample.cid, sample.value);
+        true
+    }
+
+    /// Cells to request from each peer given the bitfields they offer. Each row is
+    /// topped up to the decoding threshold, spreading requests over the peers
+    /// holding a cell; if that does not make the square recoverable, every offered
+    /// cell still missing is requested. Peers with nothing to fetch are left out.
+    pub fn plan<K: Clone>(&self, offers: &[(K, CellBitfield)]) -> Vec<(K, Vec<(usize, usize)>)> {
+        let length = self.square.length();
+        let n = self.square.threshold();
+        let mut planned = self.held();
+        let mut assigned = vec![vec![]; offers.len()];
+        let mut next = 0;
+        let holds = |idx: usize, rid: usize, cid: usize| {
+            let offer = &offers[idx].1;
+            offer.length() == length && offer.contains(rid, cid)
+        };
+        let mut assign = |planned: &mut CellBitfield, rid: usize, cid: usize| {
+            let holder = (0..offers.len())
+                .map(|k| (next + k) % offers.len())
+                .find(|idx| holds(*idx, rid, cid));
+            if let Some(idx) = holder {
+                assigned[idx].push((rid, cid));
+                planned.set(rid, cid);
+                next = idx + 1;
+            }
+        };
+
+        for rid in 0..length {
+            for cid in 0..length {
+                if planned.row_count(rid) >= n {
+                    break;
+                }
+                if !planned.contains(rid, cid) {
+                    assign(&mut planned, rid, cid);
+                }
+            }
+        }
+        if !planned.is_recoverable(n) {
+            for (rid, cid) in planned.missing() {
+                assign(&mut planned, rid, cid);
+            }
+        }
+
+        offers
+            .iter()
+            .zip(assigned)
+            .filter(|(_, cells)| !cells.is_empty())
+            .map(|((peer, _), cells)| (peer.clone(), cells))
+            .collect()
+    }
+
+    /// Decode the missing cells and commit to the result, failing unless it
+    /// reproduces the header's data root or once `cancel` is cancelled
+    pub fn finish(
+        self,
+        params: Arc<kzg10::UniversalParams<E>>,
+        cancel: &CancelToken,
+    ) -> Result<StoredBlock<E, H>> {
+        let height = self.header.height;
+        let scale = self.square.length() / self.square.threshold();
+        let square = match self.square.reconstruct_cancellable(cancel) {
+            Ok(square) => square,
+            Err(ReconstructionError::Insufficient) => {
+                bail!("Too few cells to rebuild the square at height {}", height)
+            }
+            Err(ReconstructionError::BadEncoding { axis, idx }) => {
+                bail!("{:?} {} of height {} is badly encoded", axis, idx, height)
+            }
+            Err(ReconstructionError::Cancelled) => {
+                bail!("Rebuilding height {} was cancelled", height)
+            }
+        };
+        let prover =
+            RsSquareProver::from_square(square, scale, params).with_root_version(self.root_version);
+        // once cancelled, committing to the columns stops before the first one
+        let rows = prover.commit_all_rows_cancellable(cancel);
+        let cols = prover.commit_all_cols_cancellable(cancel);
+        let (row_commitments, col_commitments) = match (rows, cols) {
+            (Ok(rows), Ok(cols)) => (rows, cols),
+            _ => bail!("Rebuilding height {} was cancelled", height),
+        };
+        let block = StoredBlock::from_commitments(height, prover, row_commitments, col_commitments);
+        if !digests_equal(&block.header.data_root, &self.header.data_root) {
+            bail!(
+                "Rebuilt square does not match the data root of height {}",
+                height
+            );
+        }
+        Ok(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reconstruction;
+    use crate::store::StoredBlock;
+    use square_reed_solomon::bitfield::CellBitfield;
+    use square_reed_solomon::cancel::CancelToken;
+    use square_reed_solomon::prover::RsSquareProver;
+    use square_reed_solomon::verifier::verifier_key;
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+    use std::sync::Arc;
+
+    #[test]
+    pub fn squares_are_rebuilt_from_peer_cells() {
+        let params = Arc::new(
+            KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+                .expect("KZG setup failed"),
+        );
+        let vk = verifier_key(&params);
+        let shares = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+        ];
+        let prover = RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, 2, params.clone());
+        let block = StoredBlock::new(5, prover);
+
+        // one peer holds the first two rows, the other the last column
+        let (mut rows, mut col) = (CellBitfield::empty(4), CellBitfield::empty(4));
+        (0..8).for_each(|idx| rows.set(idx / 4, idx % 4));
+        (0..4).for_each(|idx| col.set(idx, 3));
+        let mut rebuild =
+            Reconstruction::<Bls12_381, Sha256>::new(block.header.clone(), 4, 2).unwrap();
+        let plan = rebuild.plan(&[("rows", rows), ("col", col)]);
+        assert!(plan.iter().any(|(peer, _)| *peer == "col"));
+
+        for (_, cells) in &plan {
+            for sample in block.sample_cells(cells) {
+                assert!(rebuild.accept(&vk, &sample));
+            }
+        }
+        assert!(rebuild.is_recoverable());
+        // a cell of another square is refused
+        let other = StoredBlock::new(
+            5,
+            RsSquareProver::<Bls12_381, Sha256>::with_params(
+                &vec![vec![Fr::from(9), Fr::from(2)], shares[1].clone()],
+                2,
+                params.clone(),
+            ),
+        );
+        assert!(!rebuild.accept(&vk, &other.sample_cells(&[(1, 1)])[0]));
+
+        let cancel = CancelToken::new();
+        let rebuilt = rebuild.finish(params, &cancel).unwrap();
+        assert_eq!(rebuilt.header, block.header);
+        assert!(Reconstruction::<Bls12_381, Sha256>::new(block.header, 4, 8).is_err());
+    }
+}
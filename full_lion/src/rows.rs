@@ -0,0 +1,70 @@
+use crate::cache::LruCache;
+
+use ark_ff::PrimeField;
+use ark_poly::univariate::DensePolynomial;
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Height, data root and index of a cached row
+type RowKey = (u64, Vec<u8>, usize);
+
+/// An extended row ready to be served: its values and the polynomial they are
+/// evaluations of
+pub struct CachedRow<F: PrimeField> {
+    pub values: Vec<F>,
+    pub poly: DensePolynomial<F>,
+}
+
+/// Rows of recent squares kept decoded, so that repeated samples neither read the
+/// cold store nor interpolate the row again. Past the capacity the least recently
+/// used rows are dropped. Rows are shared, so the cache is only locked to look them
+/// up and store them.
+pub struct RowCache<F: PrimeField> {
+    rows: Mutex<LruCache<RowKey, Arc<CachedRow<F>>>>,
+}
+
+impl<F: PrimeField> RowCache<F> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            rows: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    /// Row rid of the square with `data_root` at `height`, if cached
+    pub fn get(&self, height: u64, data_root: &[u8], rid: usize) -> Option<Arc<CachedRow<F>>> {
+        self.lock().get(&(height, data_root.to_vec(), rid)).cloned()
+    }
+
+    pub fn insert(
+        &self,
+        height: u64,
+        data_root: &[u8],
+        rid: usize,
+        row: CachedRow<F>,
+    ) -> Arc<CachedRow<F>> {
+        let row = Arc::new(row);
+        self.lock()
+            .insert((height, data_root.to_vec(), rid), row.clone());
+        row
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        self.lock().set_capacity(capacity);
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn metrics(&self, prefix: &str) -> String {
+        self.lock().metrics(prefix)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, LruCache<RowKey, Arc<CachedRow<F>>>> {
+        self.rows.lock().expect("Row cache lock poisoned")
+    }
+}
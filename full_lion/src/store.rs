@@ -0,0 +1,1448 @@
+use crate::anti_entropy::{SyncEntry, MAX_SYNC_HEIGHTS};
+use crate::openings::OpeningCache;
+use crate::rows::{CachedRow, RowCache};
+use crate::tiering::{encode_cells, ColdFetch, ColdStore, CorruptionError, Fetch, TieredBlock};
+use square_reed_solomon::bitfield::CellBitfield;
+use square_reed_solomon::cancel::{CancelToken, Cancelled};
+use square_reed_solomon::codec::{
+    commitment_from_bytes, commitment_to_bytes, field_from_bytes, field_to_bytes, put_bytes,
+    put_u64, take_bytes, take_hash, take_u64, verifier_key_digest,
+};
+use square_reed_solomon::fixed_share::fixed_share_range_cells;
+use square_reed_solomon::inclusion::ShareProof;
+use square_reed_solomon::layout::{BlobPlacement, BlobProof, LayoutVersion};
+use square_reed_solomon::merkle::StoredTree;
+use square_reed_solomon::namespace::{NamespaceData, NamespaceId, NamespaceIndex, NamespaceRange};
+use square_reed_solomon::progress::{ProgressObserver, Stage};
+use square_reed_solomon::prover::{open_poly, RsSquareProver};
+use square_reed_solomon::rs_square::{is_power_of_two, RsSquare};
+use square_reed_solomon::sample::{CellSample, LineAxis};
+use square_reed_solomon::verifier::{
+    combine_line_roots, digests_equal, line_leaf, line_leaves, verifier_key, verify_root,
+    RootVersion,
+};
+
+use anyhow::{anyhow, bail, Result};
+use ark_ec::pairing::Pairing;
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_poly_commit::kzg10::{self, Commitment};
+use lion_roars::header::Header;
+use log::error;
+use rand::rngs::OsRng;
+use rs_merkle::{Hasher, MerkleProof};
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::{Range, RangeInclusive};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"LLSNAP06";
+
+/// Blocks read back from the cold store kept for the next requests, the least
+/// recently fetched dropped first
+const REHYDRATED_CAPACITY: usize = 4;
+
+/// A committed square together with everything needed to serve it
+pub struct StoredBlock<E: Pairing, H: Hasher> {
+    pub header: Header,
+    pub prover: RsSquareProver<E, H>,
+    /// Commitments to every row of the extended square
+    pub row_commitments: Vec<Commitment<E>>,
+    /// Commitments to every column of the extended square
+    pub col_commitments: Vec<Commitment<E>>,
+    /// Trees over the row and column commitments, kept so that paths are read off
+    /// rather than rehashed for every proof
+    pub row_tree: StoredTree,
+    pub col_tree: StoredTree,
+    /// When the block was stored or last sampled. Samples are served under a shared
+    /// borrow of the store, so the time is updated in place.
+    pub last_sampled: Mutex<Instant>,
+    /// Namespace layout of the original shares, if the block has one
+    pub namespaces: Option<NamespaceIndex>,
+}
+
+impl<E: Pairing, H: Hasher> StoredBlock<E, H> {
+    pub fn new(height: u64, prover: RsSquareProver<E, H>) -> Self {
+        let row_commitments = prover.commit_all_rows();
+        let col_commitments = prover.commit_all_cols();
+        Self::from_commitments(height, prover, row_commitments, col_commitments)
+    }
+
+    /// `new`, reporting the commitments and the data root built over them to
+    /// `progress`, and abandoning them once `cancel` is cancelled
+    pub fn new_observed(
+        height: u64,
+        prover: RsSquareProver<E, H>,
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<Self, Cancelled> {
+        let row_commitments = prover.commit_all_rows_observed(cancel, progress)?;
+        let col_commitments = prover.commit_all_cols_observed(cancel, progress)?;
+        let block = Self::from_commitments(height, prover, row_commitments, col_commitments);
+        progress.report(Stage::Tree, 1, 1);
+        Ok(block)
+    }
+
+    /// `new` for a square following `previous`, reusing the commitments and row
+    /// leaves of the rows it repeats (see `RsSquareProver::unchanged_rows`)
+    pub fn following(height: u64, prover: RsSquareProver<E, H>, previous: &Self) -> Self {
+        let version = prover.root_version();
+        let mut unchanged = prover.reuse_rows(&previous.prover);
+        // leaves of a square rooted under another version hash differently
+        if previous.prover.root_version() != version {
+            unchanged.clear();
+        }
+        let row_commitments = prover.commit_all_rows();
+        let col_commitments = prover.commit_all_cols();
+        let row_leaves: Vec<H::Hash> = row_commitments
+            .iter()
+            .enumerate()
+            .map(|(rid, commitment)| {
+                let kept = unchanged
+                    .binary_search(&rid)
+                    .ok()
+                    .and_then(|_| previous.row_tree.leaves().get(rid))
+                    .and_then(|leaf| H::Hash::try_from(leaf.clone()).ok());
+                kept.unwrap_or_else(|| line_leaf::<E, H>(version, LineAxis::Row, rid, commitment))
+            })
+            .collect();
+        let col_leaves = line_leaves::<E, H>(version, LineAxis::Col, &col_commitments);
+        let trees = (
+            StoredTree::build::<H>(version.line_tag(LineAxis::Row), &row_leaves),
+            StoredTree::build::<H>(version.line_tag(LineAxis::Col), &col_leaves),
+        );
+        Self::from_trees(height, prover, row_commitments, col_commitments, trees)
+    }
+
+    /// Build a block from line commitments already computed for `prover`, e.g.
+    /// while reconstructing its square
+    pub fn from_commitments(
+        height: u64,
+        prover: RsSquareProver<E, H>,
+        row_commitments: Vec<Commitment<E>>,
+        col_commitments: Vec<Commitment<E>>,
+    ) -> Self {
+        let trees = line_trees::<E, H>(prover.root_version(), &row_commitments, &col_commitments);
+        Self::from_trees(height, prover, row_commitments, col_commitments, trees)
+    }
+
+    /// Build a block from line commitments and the trees over them, e.g. both
+    /// resumed from a `CommitmentCheckpoint`
+    pub(crate) fn from_trees(
+        height: u64,
+        prover: RsSquareProver<E, H>,
+        row_commitments: Vec<Commitment<E>>,
+        col_commitments: Vec<Commitment<E>>,
+        (row_tree, col_tree): (StoredTree, StoredTree),
+    ) -> Self {
+        let data_root: Vec<u8> = combine_line_roots::<H>(
+            prover.root_version(),
+            tree_root::<H>(&row_tree),
+            tree_root::<H>(&col_tree),
+        )
+        .into();
+        let vk_digest = setup_digest::<E, H>(prover.params());
+        // proofs are opened under the height of the block and its line commitments
+        let prover = prover
+            .at_height(height)
+            .with_commitments(&data_root, row_commitments.clone(), col_commitments.clone())
+            .expect("Line trees are built over the commitments of the block");
+        Self {
+            header: Header {
+                height,
+                data_root,
+                vk_digest,
+                namespace_root: vec![],
+                parent: vec![],
+                layout: prover.layout().to_bytes(),
+            },
+            prover,
+            row_commitments,
+            col_commitments,
+            row_tree,
+            col_tree,
+            last_sampled: Mutex::new(Instant::now()),
+            namespaces: None,
+        }
+    }
+
+    /// Attach the namespace layout of the block, committing to it in the header
+    pub fn with_namespaces(mut self, namespaces: NamespaceIndex) -> Self {
+        self.header.namespace_root = namespaces.root::<H>().into();
+        self.namespaces = Some(namespaces);
+        self
+    }
+
+    /// All shares of `namespace` with a proof that none were left out, or of its
+    /// absence. `None` if the block has no namespace layout.
+    pub fn namespace_data(&self, namespace: NamespaceId) -> Option<NamespaceData<E, H>> {
+        let namespaces = self.namespaces.as_ref()?;
+        let n = self.prover.shares().len();
+        let scale = self.prover.scale();
+        let cells: Vec<(usize, usize)> = match namespaces.range_of(namespace) {
+            Some(range) => (range.start..range.end)
+                .map(|share| ((share / n) * scale, (share % n) * scale))
+                .collect(),
+            None => vec![],
+        };
+        Some(NamespaceData {
+            proof: namespaces.proof::<H>(namespace),
+            shares: self.share_proof(&cells),
+        })
+    }
+
+    /// Proof that the blob at `placement`, laid out under `version`, is part of
+    /// this block. `None` if the placement lies outside the original square.
+    pub fn blob_proof(
+        &self,
+        version: LayoutVersion,
+        placement: &BlobPlacement,
+    ) -> Option<BlobProof<E, H>> {
+        let n = self.prover.shares().len();
+        let scale = self.prover.scale();
+        if placement.start > placement.end || placement.end > n * n {
+            return None;
+        }
+        let rows = version
+            .blob_rows(placement, n, scale)
+            .into_iter()
+            .map(|rid| self.prover.row_proof(rid))
+            .collect();
+        let (row_root, col_root) = self.line_roots();
+        Some(BlobProof {
+            row_root,
+            col_root,
+            rows,
+        })
+    }
+
+    /// Compact proof that every share of the blob at `placement`, laid out under
+    /// `version`, is part of this block: one range proof per row holding the
+    /// blob and a single Merkle multi-proof of those rows. `None` if the placement
+    /// lies outside the original square.
+    pub fn blob_inclusion_proof(
+        &self,
+        version: LayoutVersion,
+        placement: &BlobPlacement,
+    ) -> Option<ShareProof<E, H>> {
+        let n = self.prover.shares().len();
+        let scale = self.prover.scale();
+        if placement.start > placement.end || placement.end > n * n {
+            return None;
+        }
+        Some(self.share_proof(&version.blob_cells(placement, n, scale)))
+    }
+
+    /// Compact proof of fixed shares `shares` of the block, however many rows they
+    /// straddle, checked with `verify_fixed_shares`. `None` if a share lies outside
+    /// the original square.
+    pub fn fixed_share_proof(&self, shares: Range<usize>) -> Option<ShareProof<E, H>> {
+        let n = self.prover.shares().len();
+        let cells = fixed_share_range_cells::<E::ScalarField>(shares, n, self.prover.scale())?;
+        Some(self.share_proof(&cells))
+    }
+
+    /// Proof of the shares at `cells`, grouped by row in increasing row order,
+    /// reading the multi-proof off the kept row tree
+    fn share_proof(&self, cells: &[(usize, usize)]) -> ShareProof<E, H> {
+        let spans: Vec<&[(usize, usize)]> = cells.chunk_by(|a, b| a.0 == b.0).collect();
+        let rows: Vec<usize> = spans.iter().map(|span| span[0].0).collect();
+        let (row_root, col_root) = self.line_roots();
+        ShareProof {
+            height: self.header.height,
+            row_root,
+            col_root,
+            row_commitments: rows.iter().map(|rid| self.row_commitments[*rid]).collect(),
+            rows_path: tree_proof::<H>(&self.row_tree, &rows),
+            values: cells
+                .iter()
+                .map(|&(rid, cid)| self.prover.square().val_at(rid, cid))
+                .collect(),
+            ranges: spans
+                .iter()
+                .map(|span| {
+                    let cids: Vec<usize> = span.iter().map(|(_, cid)| *cid).collect();
+                    self.prover.range_proof(span[0].0, &cids)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        self.row_commitments.len()
+    }
+
+    /// Samples of `cells`, reusing the cached line commitments so that answering an
+    /// audit costs one opening per cell
+    pub fn sample_cells(&self, cells: &[(usize, usize)]) -> Vec<CellSample<E, H>> {
+        let (row_root, col_root) = self.line_roots();
+        cells
+            .iter()
+            .map(|&(rid, cid)| CellSample {
+                rid,
+                cid,
+                value: self.prover.square().val_at(rid, cid),
+                row_commitment: self.row_commitments[rid],
+                opening: self.prover.open_cell(rid, cid),
+                row_path: tree_proof::<H>(&self.row_tree, &[rid]),
+                row_root,
+                col_root,
+            })
+            .collect()
+    }
+
+    /// Roots of the kept row and column trees
+    pub fn line_roots(&self) -> (H::Hash, H::Hash) {
+        (
+            tree_root::<H>(&self.row_tree),
+            tree_root::<H>(&self.col_tree),
+        )
+    }
+}
+
+/// Some of the cells of a square, e.g. a receiver's share of an erasure-aware
+/// dissemination. Cells are kept as the verified samples they arrived in, so each
+/// can be served as is until the whole square is rebuilt.
+pub struct PartialBlock {
+    pub header: Header,
+    pub length: usize,
+    pub scale: usize,
+    /// Encoded sample of each held cell
+    pub samples: BTreeMap<(usize, usize), Vec<u8>>,
+}
+
+impl PartialBlock {
+    pub fn held(&self) -> CellBitfield {
+        let mut held = CellBitfield::empty(self.length);
+        for (rid, cid) in self.samples.keys() {
+            held.set(*rid, *cid);
+        }
+        held
+    }
+}
+
+/// What remains of a block once its cell data has been pruned
+pub struct PrunedBlock<H: Hasher> {
+    pub header: Header,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+}
+
+enum Tier<E: Pairing, H: Hasher> {
+    Hot(Arc<StoredBlock<E, H>>),
+    Cold(Arc<TieredBlock<E>>),
+}
+
+/// A square whose cells are held in memory or the cold store, with the setup it
+/// was committed under, as taken out of the store by `BlockStore::held`
+pub struct HeldSquare<E: Pairing, H: Hasher> {
+    pub height: u64,
+    params: Arc<kzg10::UniversalParams<E>>,
+    tier: Tier<E, H>,
+}
+
+impl<E: Pairing, H: Hasher> HeldSquare<E, H> {
+    pub fn length(&self) -> usize {
+        match &self.tier {
+            Tier::Hot(block) => block.length(),
+            Tier::Cold(tiered) => tiered.row_commitments.len(),
+        }
+    }
+
+    /// Samples of `cells` like `BlockStore::serve_cells`. The cell data of a cold
+    /// square is taken from `fetch` the first time a row is not cached.
+    pub fn serve_cells(
+        &self,
+        cells: &[(usize, usize)],
+        rows: &RowCache<E::ScalarField>,
+        openings: &OpeningCache<E>,
+        fetch: impl FnOnce() -> Option<Arc<StoredBlock<E, H>>>,
+    ) -> Option<Vec<CellSample<E, H>>> {
+        let (header, row_commitments, row_tree, col_tree) = match &self.tier {
+            Tier::Hot(block) => (
+                &block.header,
+                &block.row_commitments,
+                &block.row_tree,
+                &block.col_tree,
+            ),
+            Tier::Cold(tiered) => (
+                &tiered.header,
+                &tiered.row_commitments,
+                &tiered.row_tree,
+                &tiered.col_tree,
+            ),
+        };
+        let length = row_commitments.len();
+        if cells
+            .iter()
+            .any(|&(rid, cid)| rid >= length || cid >= length)
+        {
+            return None;
+        }
+        let domain = Radix2EvaluationDomain::<E::ScalarField>::new(length)?;
+        let row_root = tree_root::<H>(row_tree);
+        let col_root = tree_root::<H>(col_tree);
+
+        let (height, data_root) = (self.height, &header.data_root);
+        let mut fetch = Some(fetch);
+        let mut block = match &self.tier {
+            Tier::Hot(block) => Some(block.clone()),
+            Tier::Cold(_) => None,
+        };
+        let mut samples = Vec::with_capacity(cells.len());
+        for &(rid, cid) in cells {
+            let row = match rows.get(height, data_root, rid) {
+                Some(row) => row,
+                None => {
+                    if block.is_none() {
+                        block = Some(fetch.take().expect("Fetched at most once")()?);
+                    }
+                    let square = block.as_ref().expect("Block fetched above").prover.square();
+                    let row = CachedRow {
+                        values: square.row_vals(rid),
+                        poly: square.row_poly(rid),
+                    };
+                    rows.insert(height, data_root, rid, row)
+                }
+            };
+            let opening = openings.get_or_open(height, data_root, rid, cid, || {
+                open_poly(&self.params, &row.poly, domain.element(cid))
+            });
+            samples.push(CellSample {
+                rid,
+                cid,
+                value: row.values[cid],
+                row_commitment: row_commitments[rid],
+                opening,
+                row_path: tree_proof::<H>(row_tree, &[rid]),
+                row_root,
+                col_root,
+            });
+        }
+        Some(samples)
+    }
+}
+
+/// Every line commitment of a square with the root they commit to, e.g. for an
+/// indexer to mirror and serve paths from
+pub struct LineCommitments<E: Pairing> {
+    pub height: u64,
+    pub data_root: Vec<u8>,
+    pub row_commitments: Vec<Commitment<E>>,
+    pub col_commitments: Vec<Commitment<E>>,
+}
+
+/// Which blocks keep their cell data. The latest block is always kept.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RetentionPolicy {
+    /// Keep cell data only for the most recent `keep_last` heights
+    pub keep_last: Option<u64>,
+    /// Drop cell data of blocks nobody has sampled for this long
+    pub idle_after: Option<Duration>,
+    /// With a cold store set, keep cell data of only the most recent `hot_last`
+    /// heights in memory and move that of older ones to the cold store
+    pub hot_last: Option<u64>,
+}
+
+/// Digest of the verifier key derived from `params`, as carried in headers
+pub fn setup_digest<E: Pairing, H: Hasher>(params: &kzg10::UniversalParams<E>) -> Vec<u8> {
+    verifier_key_digest::<E, H>(&verifier_key(params)).into()
+}
+
+/// Trees over the row and column commitments of a square rooted under `version`,
+/// as a stored block keeps them
+pub(crate) fn line_trees<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    row_commitments: &[Commitment<E>],
+    col_commitments: &[Commitment<E>],
+) -> (StoredTree, StoredTree) {
+    let tree = |axis: LineAxis, commitments: &[Commitment<E>]| {
+        let leaves = line_leaves::<E, H>(version, axis, commitments);
+        StoredTree::build::<H>(version.line_tag(axis), &leaves)
+    };
+    (
+        tree(LineAxis::Row, row_commitments),
+        tree(LineAxis::Col, col_commitments),
+    )
+}
+
+pub(crate) fn tree_root<H: Hasher>(tree: &StoredTree) -> H::Hash {
+    tree.root::<H>()
+        .expect("Kept trees are built over the store's hasher and not empty")
+}
+
+fn tree_proof<H: Hasher>(tree: &StoredTree, idxs: &[usize]) -> MerkleProof<H> {
+    tree.proof::<H>(idxs)
+        .expect("Kept trees are built over the store's hasher")
+}
+
+/// Squares held by a full node, indexed by height. New squares are committed under
+/// one setup; squares committed under earlier ones stay servable while they are
+/// kept for a migration.
+pub struct BlockStore<E: Pairing, H: Hasher> {
+    params: Arc<kzg10::UniversalParams<E>>,
+    /// `setup_digest` of `params`
+    digest: Vec<u8>,
+    /// Earlier setups still served, by digest
+    previous: BTreeMap<Vec<u8>, Arc<kzg10::UniversalParams<E>>>,
+    blocks: BTreeMap<u64, Arc<StoredBlock<E, H>>>,
+    /// Headers and line roots of blocks whose cell data was discarded
+    pruned: BTreeMap<u64, PrunedBlock<H>>,
+    retention: RetentionPolicy,
+    /// Where the cell data of blocks beyond `retention.hot_last` is moved
+    cold: Option<Arc<dyn ColdStore>>,
+    /// Blocks whose cell data is in the cold store
+    tiered: BTreeMap<u64, Arc<TieredBlock<E>>>,
+    /// Squares of which only some cells are held
+    partial: BTreeMap<u64, PartialBlock>,
+    /// Heights whose cold cells were found corrupt on load, awaiting `take_corrupt`
+    corrupt: Mutex<BTreeSet<u64>>,
+    /// Blocks recently read back from the cold store, most recently fetched last
+    rehydrated: Mutex<VecDeque<Arc<StoredBlock<E, H>>>>,
+}
+
+impl<E: Pairing, H: Hasher> BlockStore<E, H> {
+    pub fn new(params: kzg10::UniversalParams<E>) -> Self {
+        Self {
+            digest: setup_digest::<E, H>(&params),
+            params: Arc::new(params),
+            previous: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+            pruned: BTreeMap::new(),
+            retention: RetentionPolicy::default(),
+            cold: None,
+            tiered: BTreeMap::new(),
+            partial: BTreeMap::new(),
+            corrupt: Mutex::new(BTreeSet::new()),
+            rehydrated: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn params(&self) -> &kzg10::UniversalParams<E> {
+        &self.params
+    }
+
+    /// Handle on the setup for building provers that share it
+    pub fn shared_params(&self) -> Arc<kzg10::UniversalParams<E>> {
+        self.params.clone()
+    }
+
+    /// Setup the squares whose headers carry `digest` were committed under, if it
+    /// is the current one or an earlier one still kept
+    pub fn setup(&self, digest: &[u8]) -> Option<Arc<kzg10::UniversalParams<E>>> {
+        if digests_equal(&self.digest, digest) {
+            return Some(self.params.clone());
+        }
+        self.previous.get(digest).cloned()
+    }
+
+    /// Keep serving squares committed under the earlier setup `params` while peers
+    /// migrate to the current one. Returns its digest.
+    pub fn add_previous_setup(&mut self, params: kzg10::UniversalParams<E>) -> Vec<u8> {
+        let digest = setup_digest::<E, H>(&params);
+        if !digests_equal(&digest, &self.digest) {
+            self.previous.insert(digest.clone(), Arc::new(params));
+        }
+        digest
+    }
+
+    /// End the migration away from the setup with `digest`: squares in the cold
+    /// store or a snapshot can no longer be loaded under it. Returns whether it was
+    /// kept.
+    pub fn retire_setup(&mut self, digest: &[u8]) -> bool {
+        self.previous.remove(digest).is_some()
+    }
+
+    /// Digests of the earlier setups still kept
+    pub fn previous_setups(&self) -> Vec<Vec<u8>> {
+        self.previous.keys().cloned().collect()
+    }
+
+    /// Retention applied on every insert; tightening it prunes immediately
+    pub fn set_retention(&mut self, retention: RetentionPolicy) {
+        self.retention = retention;
+        self.prune();
+    }
+
+    /// Move cell data of old blocks to `cold` as `RetentionPolicy::hot_last` dictates
+    pub fn set_cold_store(&mut self, cold: Arc<dyn ColdStore>) {
+        self.cold = Some(cold);
+        self.prune();
+    }
+
+    pub fn insert(&mut self, block: StoredBlock<E, H>) {
+        self.pruned.remove(&block.header.height);
+        self.tiered.remove(&block.header.height);
+        self.forget_rehydrated(block.header.height);
+        self.partial.remove(&block.header.height);
+        self.blocks.insert(block.header.height, Arc::new(block));
+        self.prune();
+    }
+
+    /// Block at `height`, if its cell data is still held
+    pub fn get(&self, height: u64) -> Option<&StoredBlock<E, H>> {
+        self.blocks.get(&height).map(Arc::as_ref)
+    }
+
+    /// Block at `height`, reading its cell data back from the cold store if it was
+    /// moved there. Cells failing their checksum are not served, and the height is
+    /// reported by `take_corrupt`. The block is shared, so it can be served once
+    /// the store is no longer borrowed.
+    pub fn fetch(&self, height: u64) -> Option<Arc<StoredBlock<E, H>>> {
+        match self.begin_fetch(height)? {
+            Fetch::Ready(block) => Some(block),
+            Fetch::Cold(cold) => {
+                let loaded = cold.load();
+                self.finish_fetch(&cold, loaded)
+            }
+        }
+    }
+
+    /// `fetch` up to reading the cold store, which the caller does with
+    /// `ColdFetch::load` before handing the result to `finish_fetch`, so that the
+    /// store need not be borrowed meanwhile. The latest blocks read back are kept
+    /// and returned ready.
+    pub fn begin_fetch(&self, height: u64) -> Option<Fetch<E, H>> {
+        if let Some(block) = self.blocks.get(&height) {
+            return Some(Fetch::Ready(block.clone()));
+        }
+        let tiered = self.tiered.get(&height)?;
+        {
+            let mut rehydrated = self.rehydrated.lock().expect("Rehydrated lock poisoned");
+            if let Some(idx) = rehydrated
+                .iter()
+                .position(|block| block.header.height == height)
+            {
+                let block = rehydrated.remove(idx).expect("Position is in range");
+                rehydrated.push_back(block.clone());
+                return Some(Fetch::Ready(block));
+            }
+        }
+        let params = match self.setup(&tiered.header.vk_digest) {
+            Some(params) => params,
+            None => {
+                error!("No setup held for the square at height {}", height);
+                return None;
+            }
+        };
+        Some(Fetch::Cold(ColdFetch {
+            tiered: tiered.clone(),
+            cold: self.cold.clone()?,
+            params,
+        }))
+    }
+
+    /// Keep the block `cold` read back for the next fetches, or note its height for
+    /// `take_corrupt` if its cells were refused
+    pub fn finish_fetch(
+        &self,
+        cold: &ColdFetch<E>,
+        loaded: Option<Result<StoredBlock<E, H>, CorruptionError>>,
+    ) -> Option<Arc<StoredBlock<E, H>>> {
+        let height = cold.height();
+        match loaded? {
+            // the height may have been re-inserted or pruned while it was read
+            Ok(_)
+                if !self
+                    .tiered
+                    .get(&height)
+                    .is_some_and(|tiered| Arc::ptr_eq(tiered, &cold.tiered)) =>
+            {
+                None
+            }
+            Ok(block) => {
+                let block = Arc::new(block);
+                let mut rehydrated = self.rehydrated.lock().expect("Rehydrated lock poisoned");
+                rehydrated.push_back(block.clone());
+                while rehydrated.len() > REHYDRATED_CAPACITY {
+                    rehydrated.pop_front();
+                }
+                Some(block)
+            }
+            Err(e) => {
+                error!("Corrupt cold cells at height {}: {:?}", height, e);
+                self.corrupt
+                    .lock()
+                    .expect("Corrupt heights lock poisoned")
+                    .insert(height);
+                None
+            }
+        }
+    }
+
+    /// Drop the block at `height` read back from the cold store, if kept
+    fn forget_rehydrated(&mut self, height: u64) {
+        self.rehydrated
+            .get_mut()
+            .expect("Rehydrated lock poisoned")
+            .retain(|block| block.header.height != height);
+    }
+
+    /// Drop the cell data of every height found corrupt since the last call, so
+    /// that none of it is offered to peers, keeping its header and line roots.
+    /// Returns the header, side and scale of each square, to rebuild it from peers.
+    pub fn take_corrupt(&mut self) -> Vec<(Header, usize, usize)> {
+        let heights = std::mem::take(
+            self.corrupt
+                .get_mut()
+                .expect("Corrupt heights lock poisoned"),
+        );
+        let mut damaged = vec![];
+        for height in heights {
+            // a height re-inserted since is no longer corrupt
+            let tiered = match self.tiered.remove(&height) {
+                Some(tiered) => tiered,
+                None => continue,
+            };
+            self.forget_rehydrated(height);
+            if let Some(cold) = &self.cold {
+                if let Err(e) = cold.delete(height) {
+                    error!("Failed to delete cold cells at height {}: {}", height, e);
+                }
+            }
+            damaged.push((
+                tiered.header.clone(),
+                tiered.row_commitments.len(),
+                tiered.scale,
+            ));
+            self.pruned.insert(height, tiered.pruned());
+        }
+        damaged
+    }
+
+    /// Line commitments of the square at `height`, whether its cell data is held
+    /// or was moved to the cold store, which is not read
+    pub fn commitments(&self, height: u64) -> Option<LineCommitments<E>> {
+        let (header, rows, cols) = match (self.blocks.get(&height), self.tiered.get(&height)) {
+            (Some(block), _) => (
+                &block.header,
+                &block.row_commitments,
+                &block.col_commitments,
+            ),
+            (None, Some(tiered)) => (
+                &tiered.header,
+                &tiered.row_commitments,
+                &tiered.col_commitments,
+            ),
+            (None, None) => return None,
+        };
+        Some(LineCommitments {
+            height,
+            data_root: header.data_root.clone(),
+            row_commitments: rows.clone(),
+            col_commitments: cols.clone(),
+        })
+    }
+
+    /// Header at `height`, whether the block is held, tiered or was pruned
+    pub fn header(&self, height: u64) -> Option<&Header> {
+        self.blocks
+            .get(&height)
+            .map(|block| &block.header)
+            .or_else(|| self.tiered.get(&height).map(|tiered| &tiered.header))
+            .or_else(|| self.partial.get(&height).map(|partial| &partial.header))
+            .or_else(|| self.pruned.get(&height).map(|pruned| &pruned.header))
+    }
+
+    /// Keep the cells of `partial`, adding them to those already held of the same
+    /// square. Ignored if the whole square is held or was pruned, or if cells of a
+    /// different square are held at that height.
+    pub fn insert_partial(&mut self, partial: PartialBlock) -> bool {
+        let height = partial.header.height;
+        if self.held_length(height).is_some() || self.pruned.contains_key(&height) {
+            return false;
+        }
+        match self.partial.get_mut(&height) {
+            Some(held) if !digests_equal(&held.header.data_root, &partial.header.data_root) => {
+                false
+            }
+            Some(held) => {
+                held.samples.extend(partial.samples);
+                true
+            }
+            None => {
+                self.partial.insert(height, partial);
+                true
+            }
+        }
+    }
+
+    /// Cells held of the square at `height`, if only some of them are
+    pub fn partial(&self, height: u64) -> Option<&PartialBlock> {
+        self.partial.get(&height)
+    }
+
+    /// Cells of the square at `height` this store can serve, whether from memory or
+    /// the cold store. `None` if it holds no cell of that square.
+    pub fn availability(&self, height: u64) -> Option<CellBitfield> {
+        self.held_length(height)
+            .map(CellBitfield::full)
+            .or_else(|| self.partial.get(&height).map(PartialBlock::held))
+    }
+
+    /// Whether cell (rid, cid) of the square at `height` can be served, without
+    /// touching the cold store
+    pub fn can_serve(&self, height: u64, rid: usize, cid: usize) -> bool {
+        match self.held_length(height) {
+            Some(length) => rid < length && cid < length,
+            None => self
+                .partial
+                .get(&height)
+                .is_some_and(|partial| partial.samples.contains_key(&(rid, cid))),
+        }
+    }
+
+    /// Samples of `cells` of the square at `height`, taking rows and openings from
+    /// `rows` and `openings` where cached. The cell data is only read back, from
+    /// memory or the cold store, for rows not cached. `None` if the cells of
+    /// `height` are not held or a cell lies outside the square.
+    pub fn serve_cells(
+        &self,
+        height: u64,
+        cells: &[(usize, usize)],
+        rows: &RowCache<E::ScalarField>,
+        openings: &OpeningCache<E>,
+    ) -> Option<Vec<CellSample<E, H>>> {
+        self.held(height)?
+            .serve_cells(cells, rows, openings, || self.fetch(height))
+    }
+
+    /// Handle on the square at `height` whose cells are held in either tier, to
+    /// serve them once the store is no longer borrowed
+    pub fn held(&self, height: u64) -> Option<HeldSquare<E, H>> {
+        let tier = match (self.blocks.get(&height), self.tiered.get(&height)) {
+            (Some(block), _) => Tier::Hot(block.clone()),
+            (None, Some(tiered)) => Tier::Cold(tiered.clone()),
+            (None, None) => return None,
+        };
+        let params = match &tier {
+            Tier::Hot(block) => self.setup(&block.header.vk_digest),
+            Tier::Cold(tiered) => self.setup(&tiered.header.vk_digest),
+        }?;
+        Some(HeldSquare {
+            height,
+            params,
+            tier,
+        })
+    }
+
+    /// Side of the square at `height`, if its cells are held in either tier
+    pub fn held_length(&self, height: u64) -> Option<usize> {
+        match (self.blocks.get(&height), self.tiered.get(&height)) {
+            (Some(block), _) => Some(block.length()),
+            (None, Some(tiered)) => Some(tiered.row_commitments.len()),
+            (None, None) => None,
+        }
+    }
+
+    /// Data root and held cells of every known height in `from..=to`, lowest
+    /// first and at most `MAX_SYNC_HEIGHTS` of them
+    pub fn sync_entries(&self, from: u64, to: u64) -> Vec<SyncEntry> {
+        if from > to {
+            return vec![];
+        }
+        let heights: BTreeSet<u64> = self
+            .blocks
+            .range(from..=to)
+            .map(|(height, _)| *height)
+            .chain(self.tiered.range(from..=to).map(|(height, _)| *height))
+            .chain(self.partial.range(from..=to).map(|(height, _)| *height))
+            .chain(self.pruned.range(from..=to).map(|(height, _)| *height))
+            .collect();
+        heights
+            .into_iter()
+            .take(MAX_SYNC_HEIGHTS)
+            .filter_map(|height| {
+                Some(SyncEntry {
+                    height,
+                    data_root: self.header(height)?.data_root.clone(),
+                    held: self
+                        .availability(height)
+                        .unwrap_or_else(|| CellBitfield::empty(0)),
+                })
+            })
+            .collect()
+    }
+
+    /// Block at `height`, if its cell data was moved to the cold store
+    pub fn tiered(&self, height: u64) -> Option<&TieredBlock<E>> {
+        self.tiered.get(&height)
+    }
+
+    pub fn pruned(&self, height: u64) -> Option<&PrunedBlock<H>> {
+        self.pruned.get(&height)
+    }
+
+    /// Note that a cell of the block at `height` was sampled, keeping it from going idle
+    pub fn touch(&self, height: u64) {
+        if let Some(block) = self.blocks.get(&height) {
+            *block
+                .last_sampled
+                .lock()
+                .expect("Sampling time lock poisoned") = Instant::now();
+        }
+    }
+
+    /// Discard cell data of every block the retention policy no longer covers,
+    /// keeping its header and line roots, and move that of blocks beyond
+    /// `hot_last` to the cold store. Returns the pruned heights.
+    pub fn prune(&mut self) -> Vec<u64> {
+        let latest = match self.latest_height() {
+            Some(latest) => latest,
+            None => return vec![],
+        };
+        let now = Instant::now();
+        let retention = self.retention;
+        let expired: Vec<u64> = self
+            .blocks
+            .iter()
+            .filter(|(height, block)| {
+                let too_old = retention
+                    .keep_last
+                    .map_or(false, |keep| latest - **height >= keep.max(1));
+                let idle = retention.idle_after.map_or(false, |idle| {
+                    let last_sampled = *block
+                        .last_sampled
+                        .lock()
+                        .expect("Sampling time lock poisoned");
+                    now.duration_since(last_sampled) >= idle
+                });
+                **height != latest && (too_old || idle)
+            })
+            .map(|(height, _)| *height)
+            .collect();
+
+        for height in &expired {
+            let block = self
+                .blocks
+                .remove(height)
+                .expect("Expired heights are taken from the store");
+            let (row_root, col_root) = block.line_roots();
+            self.pruned.insert(
+                *height,
+                PrunedBlock {
+                    header: block.header.clone(),
+                    row_root,
+                    col_root,
+                },
+            );
+        }
+
+        // tiered blocks only age out by height, as nobody samples them from memory
+        let expired_cold: Vec<u64> = self
+            .tiered
+            .keys()
+            .filter(|height| {
+                retention
+                    .keep_last
+                    .map_or(false, |keep| latest - **height >= keep.max(1))
+            })
+            .copied()
+            .collect();
+        for height in &expired_cold {
+            let tiered = self
+                .tiered
+                .remove(height)
+                .expect("Expired heights are taken from the store");
+            self.forget_rehydrated(*height);
+            if let Some(cold) = &self.cold {
+                if let Err(e) = cold.delete(*height) {
+                    error!("Failed to delete cold cells at height {}: {}", height, e);
+                }
+            }
+            self.pruned.insert(*height, tiered.pruned());
+        }
+        // partial squares are dropped outright, having no line roots to keep
+        if let Some(keep) = retention.keep_last {
+            self.partial
+                .retain(|height, _| *height >= latest || latest - *height < keep.max(1));
+        }
+
+        if let (Some(cold), Some(hot_last)) = (self.cold.clone(), retention.hot_last) {
+            let cooling: Vec<u64> = self
+                .blocks
+                .keys()
+                .filter(|height| latest - **height >= hot_last.max(1))
+                .copied()
+                .collect();
+            for height in cooling {
+                // a block stays hot if its cells cannot be written out
+                let cells = encode_cells(&self.blocks[&height]);
+                if let Err(e) = cold.put(height, &cells) {
+                    error!("Failed to move height {} to cold storage: {}", height, e);
+                    continue;
+                }
+                let block = self
+                    .blocks
+                    .remove(&height)
+                    .expect("Cooling heights are taken from the store");
+                let tiered = TieredBlock::from_block(&block, &cells);
+                self.tiered.insert(height, Arc::new(tiered));
+            }
+        }
+        expired.into_iter().chain(expired_cold).collect()
+    }
+
+    /// Lowest height whose cell data the retention policy keeps, 0 when it keeps
+    /// every height
+    pub fn retained_from(&self) -> u64 {
+        match (self.latest_height(), self.retention.keep_last) {
+            (Some(latest), Some(keep)) => (latest + 1).saturating_sub(keep.max(1)),
+            _ => 0,
+        }
+    }
+
+    pub fn heights(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocks.keys().copied()
+    }
+
+    pub fn latest_height(&self) -> Option<u64> {
+        self.blocks.keys().next_back().copied()
+    }
+
+    /// Archive the extended squares, commitments, line trees and headers of every
+    /// stored height in `heights`, each after a checksum of its record.
+    pub fn snapshot(&self, heights: RangeInclusive<u64>) -> Vec<u8> {
+        let blocks: Vec<&StoredBlock<E, H>> = self
+            .blocks
+            .range(heights)
+            .map(|(_, block)| &**block)
+            .collect();
+
+        let mut archive = SNAPSHOT_MAGIC.to_vec();
+        put_u64(&mut archive, blocks.len() as u64);
+        for block in blocks {
+            let mut record = vec![];
+            let length = block.length();
+
+            put_u64(&mut record, block.header.height);
+            put_bytes(&mut record, &block.header.data_root);
+            put_bytes(&mut record, &block.header.vk_digest);
+            put_bytes(&mut record, &block.header.parent);
+            put_u64(&mut record, block.prover.scale() as u64);
+            put_u64(&mut record, length as u64);
+            put_u64(&mut record, block.prover.root_version().id() as u64);
+            for rid in 0..length {
+                for val in block.prover.square().row_vals(rid) {
+                    put_bytes(&mut record, &field_to_bytes(&val));
+                }
+            }
+            for com in block.row_commitments.iter().chain(&block.col_commitments) {
+                put_bytes(&mut record, &commitment_to_bytes(com));
+            }
+            put_bytes(&mut record, &block.row_tree.to_bytes());
+            put_bytes(&mut record, &block.col_tree.to_bytes());
+
+            let ranges = block.namespaces.as_ref().map_or(&[][..], |ns| ns.ranges());
+            put_u64(&mut record, ranges.len() as u64);
+            for range in ranges {
+                put_bytes(&mut record, &range.namespace.0);
+                put_u64(&mut record, range.start as u64);
+                put_u64(&mut record, range.end as u64);
+            }
+
+            let checksum: Vec<u8> = H::hash(&record).into();
+            put_bytes(&mut archive, &checksum);
+            archive.extend(record);
+        }
+        archive
+    }
+
+    /// Load an archive produced by `snapshot`, returning the restored heights. Fails
+    /// on the first record not matching its checksum. Squares are taken as already
+    /// extended, commitments are not recomputed and the inner nodes of the line
+    /// trees are not rehashed, so restored heights serve proofs at once. Checked are
+    /// that the trees' leaves are the commitments' and their roots are under each
+    /// header's root, and that the square matches its commitments, at the cost of
+    /// one commitment per axis (see `RsSquareProver::commitments_match`).
+    pub fn restore(&mut self, archive: &[u8]) -> Result<Vec<u64>> {
+        if archive.len() < SNAPSHOT_MAGIC.len() || &archive[..8] != SNAPSHOT_MAGIC {
+            bail!("Not a lazy-lion snapshot");
+        }
+        let mut bytes = &archive[8..];
+
+        let count = take_u64(&mut bytes)?;
+        let mut restored = vec![];
+        for _ in 0..count {
+            let checksum = take_hash::<H>(&mut bytes)?;
+            let record = bytes;
+            let height = take_u64(&mut bytes)?;
+            let data_root = take_bytes(&mut bytes)?.to_vec();
+            let vk_digest = take_bytes(&mut bytes)?.to_vec();
+            let parent = take_bytes(&mut bytes)?.to_vec();
+            let scale = take_u64(&mut bytes)? as usize;
+            let length = take_u64(&mut bytes)? as usize;
+            if !is_power_of_two(length) || !is_power_of_two(scale) || scale > length {
+                bail!("Invalid square dimensions at height {}", height);
+            }
+            let version = match u8::try_from(take_u64(&mut bytes)?)
+                .ok()
+                .and_then(RootVersion::from_id)
+            {
+                Some(version) => version,
+                None => bail!("Unknown root version at height {}", height),
+            };
+            let params = match self.setup(&vk_digest) {
+                Some(params) => params,
+                None => bail!("No setup held for the square at height {}", height),
+            };
+            if params.powers_of_g.len() <= length {
+                bail!("Setup too small for square at height {}", height);
+            }
+
+            let mut rows = Vec::with_capacity(length);
+            for _ in 0..length {
+                let row = (0..length)
+                    .map(|_| field_from_bytes::<E::ScalarField>(take_bytes(&mut bytes)?))
+                    .collect::<Result<Vec<_>, _>>()?;
+                rows.push(row);
+            }
+            let mut take_commitments = || {
+                (0..length)
+                    .map(|_| commitment_from_bytes::<E>(take_bytes(&mut bytes)?))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            let row_commitments = take_commitments()?;
+            let col_commitments = take_commitments()?;
+            let row_tree = StoredTree::from_bytes(take_bytes(&mut bytes)?)?;
+            let col_tree = StoredTree::from_bytes(take_bytes(&mut bytes)?)?;
+
+            let ranges = (0..take_u64(&mut bytes)?)
+                .map(|_| {
+                    let namespace = take_bytes(&mut bytes)?
+                        .try_into()
+                        .map_err(|_| anyhow!("Malformed namespace at height {}", height))?;
+                    Ok(NamespaceRange {
+                        namespace: NamespaceId(namespace),
+                        start: take_u64(&mut bytes)? as usize,
+                        end: take_u64(&mut bytes)? as usize,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if H::hash(&record[..record.len() - bytes.len()]) != checksum {
+                bail!("Snapshot record at height {} fails its checksum", height);
+            }
+
+            let root = <H::Hash as TryFrom<Vec<u8>>>::try_from(data_root.clone())
+                .map_err(|_| anyhow!("Malformed data root at height {}", height))?;
+            let holds = |tree: &StoredTree, axis: LineAxis, commitments: &[Commitment<E>]| {
+                let leaves = line_leaves::<E, H>(version, axis, commitments);
+                tree.leaves()
+                    .iter()
+                    .cloned()
+                    .eq(leaves.into_iter().map(Into::into))
+            };
+            let under_root = match (row_tree.root::<H>(), col_tree.root::<H>()) {
+                (Some(row_root), Some(col_root)) => {
+                    verify_root::<H>(version, root, row_root, col_root)
+                }
+                _ => false,
+            };
+            if !holds(&row_tree, LineAxis::Row, &row_commitments)
+                || !holds(&col_tree, LineAxis::Col, &col_commitments)
+                || !under_root
+            {
+                bail!(
+                    "Snapshot commitments do not match the root at height {}",
+                    height
+                );
+            }
+
+            let square = RsSquare::from_extended_rows(rows, scale);
+            let prover = match RsSquareProver::from_square(square, scale, params)
+                .at_height(height)
+                .with_root_version(version)
+                .with_commitments(&data_root, row_commitments.clone(), col_commitments.clone())
+            {
+                Some(prover) if prover.commitments_match(&mut OsRng) => prover,
+                _ => bail!(
+                    "Snapshot square does not match its commitments at height {}",
+                    height
+                ),
+            };
+            let mut block = StoredBlock {
+                header: Header {
+                    height,
+                    data_root,
+                    vk_digest,
+                    namespace_root: vec![],
+                    parent,
+                    layout: prover.layout().to_bytes(),
+                },
+                prover,
+                row_commitments,
+                col_commitments,
+                row_tree,
+                col_tree,
+                last_sampled: Mutex::new(Instant::now()),
+                namespaces: None,
+            };
+            if !ranges.is_empty() {
+                let n = block.prover.shares().len();
+                if !NamespaceIndex::is_valid_layout(&ranges, n * n) {
+                    bail!("Invalid namespace layout at height {}", height);
+                }
+                block = block.with_namespaces(NamespaceIndex::new(ranges, n * n));
+            }
+
+            restored.push(height);
+            self.insert(block);
+        }
+
+        if !bytes.is_empty() {
+            bail!("Trailing bytes after snapshot");
+        }
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockStore, RetentionPolicy, StoredBlock, SNAPSHOT_MAGIC};
+    use square_reed_solomon::codec::{field_to_bytes, put_bytes};
+    use square_reed_solomon::dimensions::SquareLayout;
+    use square_reed_solomon::fixed_share::{
+        fixed_shares_square, split_fixed_shares, verify_fixed_shares,
+    };
+    use square_reed_solomon::fixtures::counting_square;
+    use square_reed_solomon::namespace::{
+        verify_namespace_data, NamespaceData, NamespaceId, NamespaceIndex, NamespaceRange,
+    };
+    use square_reed_solomon::prover::RsSquareProver;
+    use square_reed_solomon::verifier::{verifier_key, verify_cell_sample, RootVersion};
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+    use rs_merkle::Hasher;
+
+    #[test]
+    pub fn pruning_keeps_headers() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let mut store = BlockStore::<Bls12_381, Sha256>::new(params);
+        store.set_retention(RetentionPolicy {
+            keep_last: Some(2),
+            ..RetentionPolicy::default()
+        });
+
+        for height in 0..4 {
+            let shares = vec![
+                vec![Fr::from(height), Fr::from(1)],
+                vec![Fr::from(2), Fr::from(3)],
+            ];
+            let prover = RsSquareProver::with_params(&shares, 2, store.shared_params());
+            store.insert(StoredBlock::new(height, prover));
+        }
+
+        assert_eq!(store.heights().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(store.retained_from(), 2);
+        for height in 0..2 {
+            assert!(store.get(height).is_none());
+            assert_eq!(store.header(height).unwrap().height, height);
+            assert!(store.pruned(height).is_some());
+        }
+    }
+
+    #[test]
+    pub fn restored_heights_serve_from_their_saved_trees() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let vk = verifier_key(&params);
+        let mut before = BlockStore::<Bls12_381, Sha256>::new(params.clone());
+        let shares = counting_square(2);
+        let prover = RsSquareProver::with_params(&shares, 2, before.shared_params());
+        before.insert(StoredBlock::new(3, prover));
+
+        let mut after = BlockStore::<Bls12_381, Sha256>::new(params);
+        assert_eq!(after.restore(&before.snapshot(0..=3)).unwrap(), vec![3]);
+        let (saved, restored) = (before.get(3).unwrap(), after.get(3).unwrap());
+        assert_eq!(restored.row_tree, saved.row_tree);
+        assert_eq!(restored.col_tree, saved.col_tree);
+        let (version, root) = (restored.prover.root_version(), restored.prover.root());
+        for sample in restored.sample_cells(&[(0, 1), (3, 2)]) {
+            assert!(verify_cell_sample(&vk, version, root, 4, &sample));
+        }
+        let exported = after.commitments(3).unwrap();
+        assert_eq!(exported.data_root, saved.header.data_root);
+        assert_eq!(exported.row_commitments, saved.row_commitments);
+        assert_eq!(exported.col_commitments, saved.col_commitments);
+        assert!(after.commitments(4).is_none());
+    }
+
+    #[test]
+    pub fn snapshots_round_trip_only_with_squares_matching_their_commitments() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let mut before = BlockStore::<Bls12_381, Sha256>::new(params.clone());
+        let shares = counting_square(2);
+        let prover = RsSquareProver::with_params(&shares, 2, before.shared_params())
+            .with_root_version(RootVersion::V2);
+        before.insert(StoredBlock::new(3, prover));
+        let archive = before.snapshot(0..=3);
+
+        // a restored store archives its heights exactly as they were saved
+        let mut after = BlockStore::<Bls12_381, Sha256>::new(params.clone());
+        assert_eq!(after.restore(&archive).unwrap(), vec![3]);
+        assert_eq!(after.snapshot(0..=3), archive);
+        let restored = after.get(3).unwrap();
+        assert_eq!(restored.prover.root_version(), RootVersion::V2);
+        assert_eq!(restored.header, before.get(3).unwrap().header);
+
+        // a cell changed under its commitments, trees and checksum is caught
+        let mut encoded = vec![];
+        put_bytes(&mut encoded, &field_to_bytes(&Fr::from(3)));
+        let cell = archive
+            .windows(encoded.len())
+            .position(|window| window == encoded)
+            .unwrap();
+        let mut tampered = archive.clone();
+        tampered[cell + 4] ^= 4;
+        // the only record follows the magic, the count and its own checksum
+        let record = SNAPSHOT_MAGIC.len() + 12 + 4 + 32;
+        let checksum = Sha256::hash(&tampered[record..]);
+        tampered[record - 32..record].copy_from_slice(&checksum);
+        let mut fresh = BlockStore::<Bls12_381, Sha256>::new(params);
+        assert!(fresh.restore(&tampered).is_err());
+        assert!(fresh.get(3).is_none());
+    }
+
+    #[test]
+    pub fn fixed_shares_are_proven_across_rows() {
+        let data: Vec<u8> = (0..1200u32).map(|byte| (byte % 251) as u8).collect();
+        let shares = split_fixed_shares(&data);
+        let square = fixed_shares_square::<Fr>(&shares);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&square, 2);
+        let vk = verifier_key(prover.params());
+        let block = StoredBlock::new(5, prover);
+        let data_root = block.header.data_root.clone().try_into().unwrap();
+        let version = block.prover.root_version();
+
+        // shares 1 and 2 take cells 17..51 of the 8 x 8 square, rows 2 to 6
+        let proof = block.fixed_share_proof(1..3).unwrap();
+        assert_eq!(proof.ranges.len(), 5);
+        assert_eq!(
+            verify_fixed_shares(&vk, 5, version, data_root, 8, 2, 1..3, &proof),
+            Some(shares[1..].to_vec())
+        );
+        assert!(verify_fixed_shares(&vk, 5, version, data_root, 8, 2, 0..2, &proof).is_none());
+        assert!(block.fixed_share_proof(2..4).is_none());
+    }
+
+    #[test]
+    pub fn namespace_data_is_served_and_verified_end_to_end() {
+        let range = |ns: u8, start, end| NamespaceRange {
+            namespace: NamespaceId([ns; 8]),
+            start,
+            end,
+        };
+        let index =
+            NamespaceIndex::new(vec![range(1, 0, 3), range(4, 3, 10), range(7, 10, 16)], 16);
+        let shares = counting_square(4);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let vk = verifier_key(prover.params());
+        let block = StoredBlock::new(5, prover).with_namespaces(index);
+        let data_root = block.header.data_root.clone().try_into().unwrap();
+        let namespace_root = block.header.namespace_root.clone().try_into().unwrap();
+        let version = block.prover.root_version();
+        let verify = |namespace: u8, data: &NamespaceData<Bls12_381, Sha256>| {
+            let namespace = NamespaceId([namespace; 8]);
+            verify_namespace_data(
+                &vk,
+                5,
+                version,
+                data_root,
+                namespace_root,
+                4,
+                2,
+                namespace,
+                data,
+            )
+        };
+
+        // every share of a present namespace, as served over the wire
+        let served = block
+            .namespace_data(NamespaceId([4; 8]))
+            .unwrap()
+            .to_bytes();
+        let present = NamespaceData::from_bytes(&served).unwrap();
+        assert_eq!(verify(4, &present), Some((3..10).map(Fr::from).collect()));
+        assert_eq!(verify(7, &present), None);
+
+        // an absent namespace is proven absent with no shares
+        let served = block
+            .namespace_data(NamespaceId([5; 8]))
+            .unwrap()
+            .to_bytes();
+        let absent = NamespaceData::from_bytes(&served).unwrap();
+        assert_eq!(verify(5, &absent), Some(vec![]));
+
+        // absence cannot carry shares, nor presence drop them
+        let padded = NamespaceData {
+            proof: absent.proof,
+            shares: present.shares,
+        };
+        assert_eq!(verify(5, &padded), None);
+        let emptied = NamespaceData {
+            proof: present.proof,
+            shares: absent.shares,
+        };
+        assert_eq!(verify(4, &emptied), None);
+    }
+
+    #[test]
+    pub fn following_blocks_match_blocks_built_afresh() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let store = BlockStore::<Bls12_381, Sha256>::new(params);
+        let shares = counting_square(2);
+        let previous = StoredBlock::new(
+            3,
+            RsSquareProver::with_params(&shares, 2, store.shared_params()),
+        );
+        let mut next_shares = shares.clone();
+        next_shares[0][1] = Fr::from(5);
+        let prover = || RsSquareProver::with_params(&next_shares, 2, store.shared_params());
+
+        let following = StoredBlock::following(4, prover(), &previous);
+        let fresh = StoredBlock::new(4, prover());
+        assert_eq!(following.header, fresh.header);
+        // headers carry the geometry of their square
+        let layout = SquareLayout::from_bytes(&fresh.header.layout).unwrap();
+        assert_eq!((layout.n_rows, layout.scale, layout.length()), (2, 2, 4));
+        assert_eq!(following.row_commitments, fresh.row_commitments);
+        assert_eq!(following.row_tree, fresh.row_tree);
+        assert_eq!(following.col_tree, fresh.col_tree);
+        assert_eq!(
+            following.row_tree.leaves()[1],
+            previous.row_tree.leaves()[1]
+        );
+    }
+
+    #[test]
+    pub fn squares_outlive_their_setup_until_retired() {
+        let setup = || {
+            KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+                .expect("KZG setup failed")
+        };
+        let (old, new) = (setup(), setup());
+        let mut before = BlockStore::<Bls12_381, Sha256>::new(old.clone());
+        let shares = counting_square(2);
+        let prover = RsSquareProver::with_params(&shares, 2, before.shared_params());
+        before.insert(StoredBlock::new(5, prover));
+        let archive = before.snapshot(0..=5);
+
+        let mut after = BlockStore::<Bls12_381, Sha256>::new(new);
+        assert!(after.restore(&archive).is_err());
+        let digest = after.add_previous_setup(old);
+        assert_eq!(after.previous_setups(), vec![digest.clone()]);
+        assert_eq!(after.restore(&archive).unwrap(), vec![5]);
+        assert_eq!(after.header(5).unwrap().vk_digest, digest);
+
+        assert!(after.retire_setup(&digest) && after.setup(&digest).is_none());
+        assert!(after.restore(&archive).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+use crate::store::{tree_root, PrunedBlock, StoredBlock};
+use square_reed_solomon::codec::{
+    decode, field_to_bytes, put_bytes, put_u64, take_bytes, take_hash, take_u64, Validation,
+};
+use square_reed_solomon::merkle::StoredTree;
+use square_reed_solomon::namespace::NamespaceIndex;
+use square_reed_solomon::prover::RsSquareProver;
+use square_reed_solomon::rs_square::{is_power_of_two, RsSquare};
+use square_reed_solomon::verifier::digests_equal;
+
+use anyhow::Result;
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{self, Commitment};
+use lion_roars::header::Header;
+use log::error;
+use rs_merkle::Hasher;
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Slower, cheaper storage the cell data of old blocks is moved to, e.g. a
+/// directory on a large disk or an object store bucket
+pub trait ColdStore: Send + Sync {
+    fn put(&self, height: u64, data: &[u8]) -> Result<()>;
+
+    /// `None` if nothing is stored for `height`
+    fn get(&self, height: u64) -> Result<Option<Vec<u8>>>;
+
+    fn delete(&self, height: u64) -> Result<()>;
+}
+
+/// Cold store keeping the cells of each height in a file of its own
+pub struct DirectoryColdStore {
+    dir: PathBuf,
+}
+
+impl DirectoryColdStore {
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, height: u64) -> PathBuf {
+        self.dir.join(format!("{}.cells", height))
+    }
+}
+
+impl ColdStore for DirectoryColdStore {
+    fn put(&self, height: u64, data: &[u8]) -> Result<()> {
+        // readers never see a partially written file
+        let tmp = self.dir.join(format!("{}.cells.tmp", height));
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(tmp, self.path(height))?;
+        Ok(())
+    }
+
+    fn get(&self, height: u64) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.path(height)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delete(&self, height: u64) -> Result<()> {
+        match std::fs::remove_file(self.path(height)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why cell data read back from the cold store was refused. The block's cells are
+/// then rebuilt from peers rather than served.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorruptionError {
+    /// The cells do not hash to the checksum written with them
+    ChecksumMismatch,
+    /// The cells do not decode into the square at this height
+    Malformed,
+}
+
+/// A block whose cell data was moved to the cold store. Header, commitments and
+/// namespace layout stay in memory so that roots and proofs of inclusion of
+/// commitments never need the cold store, as does a checksum of the cells
+/// written out, which is all their reading back is checked against.
+pub struct TieredBlock<E: Pairing> {
+    pub header: Header,
+    pub scale: usize,
+    pub row_commitments: Vec<Commitment<E>>,
+    pub col_commitments: Vec<Commitment<E>>,
+    pub row_tree: StoredTree,
+    pub col_tree: StoredTree,
+    pub namespaces: Option<NamespaceIndex>,
+    /// Hash of the cells `encode_cells` wrote to the cold store
+    pub checksum: Vec<u8>,
+}
+
+impl<E: Pairing> TieredBlock<E> {
+    /// What stays in memory of `block` once `cells`, its cells encoded by
+    /// `encode_cells`, are in the cold store
+    pub fn from_block<H: Hasher>(block: &StoredBlock<E, H>, cells: &[u8]) -> Self {
+        Self {
+            checksum: H::hash(cells).into(),
+            header: block.header.clone(),
+            scale: block.prover.scale(),
+            row_commitments: block.row_commitments.clone(),
+            col_commitments: block.col_commitments.clone(),
+            row_tree: block.row_tree.clone(),
+            col_tree: block.col_tree.clone(),
+            namespaces: block.namespaces.clone(),
+        }
+    }
+
+    /// Rebuild the full block from cells read back from the cold store. The cells
+    /// must hash to the checksum kept in memory when they were written out, so
+    /// cells altered in the cold store are refused along with damaged ones; being
+    /// the cells the commitments were computed over, they are then taken as
+    /// already extended.
+    pub fn rehydrate<H: Hasher>(
+        &self,
+        cells: &[u8],
+        params: Arc<kzg10::UniversalParams<E>>,
+    ) -> Result<StoredBlock<E, H>, CorruptionError> {
+        let checksum: Vec<u8> = H::hash(cells).into();
+        if !digests_equal(&checksum, &self.checksum) {
+            return Err(CorruptionError::ChecksumMismatch);
+        }
+        let mut bytes = cells;
+        let checksum = take_hash::<H>(&mut bytes).map_err(|_| CorruptionError::Malformed)?;
+        if H::hash(bytes) != checksum {
+            return Err(CorruptionError::ChecksumMismatch);
+        }
+        let length = self.row_commitments.len();
+        let rows = Self::decode_rows(bytes, length).ok_or(CorruptionError::Malformed)?;
+        let square = RsSquare::from_extended_rows(rows, self.scale);
+        let prover = RsSquareProver::from_square(square, self.scale, params)
+            .at_height(self.header.height)
+            .with_commitments(
+                &self.header.data_root,
+                self.row_commitments.clone(),
+                self.col_commitments.clone(),
+            )
+            .ok_or(CorruptionError::Malformed)?;
+        Ok(StoredBlock {
+            header: self.header.clone(),
+            prover,
+            row_commitments: self.row_commitments.clone(),
+            col_commitments: self.col_commitments.clone(),
+            row_tree: self.row_tree.clone(),
+            col_tree: self.col_tree.clone(),
+            last_sampled: Mutex::new(Instant::now()),
+            namespaces: self.namespaces.clone(),
+        })
+    }
+
+    fn decode_rows(mut bytes: &[u8], length: usize) -> Option<Vec<Vec<E::ScalarField>>> {
+        if take_u64(&mut bytes).ok()? as usize != length || !is_power_of_two(length) {
+            return None;
+        }
+        let rows = (0..length)
+            .map(|_| {
+                (0..length)
+                    .map(|_| decode::<E::ScalarField>(take_bytes(&mut bytes)?, Validation::Trusted))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        bytes.is_empty().then_some(rows)
+    }
+
+    /// What remains once the block ages out of retention altogether
+    pub fn pruned<H: Hasher>(&self) -> PrunedBlock<H> {
+        PrunedBlock {
+            row_root: tree_root::<H>(&self.row_tree),
+            col_root: tree_root::<H>(&self.col_tree),
+            header: self.header.clone(),
+        }
+    }
+}
+
+/// A block to be read back from the cold store, taken out of the store by
+/// `BlockStore::begin_fetch` so that the cold store is read without holding it
+pub struct ColdFetch<E: Pairing> {
+    pub(crate) tiered: Arc<TieredBlock<E>>,
+    pub(crate) cold: Arc<dyn ColdStore>,
+    pub(crate) params: Arc<kzg10::UniversalParams<E>>,
+}
+
+impl<E: Pairing> ColdFetch<E> {
+    pub fn height(&self) -> u64 {
+        self.tiered.header.height
+    }
+
+    /// Read the cells back and rebuild the block. `None` if the cold store holds
+    /// nothing for the height or cannot be read.
+    pub fn load<H: Hasher>(&self) -> Option<Result<StoredBlock<E, H>, CorruptionError>> {
+        let height = self.height();
+        match self.cold.get(height) {
+            Ok(Some(cells)) => Some(self.tiered.rehydrate(&cells, self.params.clone())),
+            Ok(None) => None,
+            Err(e) => {
+                error!("Failed to read cold cells at height {}: {}", height, e);
+                None
+            }
+        }
+    }
+}
+
+/// Where `BlockStore::begin_fetch` found a block
+pub enum Fetch<E: Pairing, H: Hasher> {
+    /// In memory, or read back from the cold store recently
+    Ready(Arc<StoredBlock<E, H>>),
+    /// In the cold store, to be read back with `ColdFetch::load`
+    Cold(ColdFetch<E>),
+}
+
+/// Every cell of the extended square, row by row, after a checksum of them
+pub fn encode_cells<E: Pairing, H: Hasher>(block: &StoredBlock<E, H>) -> Vec<u8> {
+    let length = block.length();
+    let mut cells = vec![];
+    put_u64(&mut cells, length as u64);
+    for rid in 0..length {
+        for val in block.prover.square().row_vals(rid) {
+            put_bytes(&mut cells, &field_to_bytes(&val));
+        }
+    }
+    let checksum: Vec<u8> = H::hash(&cells).into();
+    let mut out = vec![];
+    put_bytes(&mut out, &checksum);
+    out.extend(cells);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_cells, CorruptionError, DirectoryColdStore};
+    use crate::openings::OpeningCache;
+    use crate::rows::RowCache;
+    use crate::store::{BlockStore, RetentionPolicy, StoredBlock};
+    use square_reed_solomon::prover::RsSquareProver;
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+    use std::sync::Arc;
+
+    #[test]
+    pub fn cold_blocks_are_fetched_through() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let dir = std::env::temp_dir().join(format!("lazy-lion-cold-{}", std::process::id()));
+        let mut store = BlockStore::<Bls12_381, Sha256>::new(params);
+        store.set_cold_store(Arc::new(DirectoryColdStore::open(&dir).unwrap()));
+        store.set_retention(RetentionPolicy {
+            keep_last: Some(3),
+            hot_last: Some(1),
+            ..RetentionPolicy::default()
+        });
+
+        let mut samples = vec![];
+        for height in 0..4 {
+            let shares = vec![
+                vec![Fr::from(height), Fr::from(1)],
+                vec![Fr::from(2), Fr::from(3)],
+            ];
+            let prover = RsSquareProver::with_params(&shares, 2, store.shared_params());
+            samples.push(prover.sample_cell(1, 3).to_bytes());
+            store.insert(StoredBlock::new(height, prover));
+        }
+
+        assert_eq!(store.heights().collect::<Vec<_>>(), vec![3]);
+        assert!(store.get(2).is_none() && store.tiered(2).is_some());
+        let fetched = store.fetch(2).unwrap();
+        assert_eq!(fetched.prover.sample_cell(1, 3).to_bytes(), samples[2]);
+        assert_eq!(fetched.header, *store.header(2).unwrap());
+        // the block read back is kept for the next fetch
+        std::fs::remove_file(dir.join("2.cells")).unwrap();
+        assert_eq!(store.fetch(2).unwrap().header, fetched.header);
+        // beyond keep_last the cold copy is dropped as well
+        assert!(store.fetch(0).is_none() && store.pruned(0).is_some());
+        assert!(!dir.join("0.cells").exists());
+
+        // well-formed cells of another square are refused like damaged ones
+        let other = encode_cells(&store.fetch(3).unwrap());
+        let rehydrated = store
+            .tiered(1)
+            .unwrap()
+            .rehydrate::<Sha256>(&other, store.shared_params());
+        assert_eq!(rehydrated.err(), Some(CorruptionError::ChecksumMismatch));
+
+        // cells damaged on disk are refused, then dropped to be rebuilt from peers
+        let path = dir.join("1.cells");
+        let mut cells = std::fs::read(&path).unwrap();
+        let last = cells.len() - 1;
+        cells[last] ^= 1;
+        std::fs::write(&path, &cells).unwrap();
+        let rehydrated = store
+            .tiered(1)
+            .unwrap()
+            .rehydrate::<Sha256>(&cells, store.shared_params());
+        assert_eq!(rehydrated.err(), Some(CorruptionError::ChecksumMismatch));
+        assert!(store.fetch(1).is_none());
+        let header = store.header(1).unwrap().clone();
+        assert_eq!(store.take_corrupt(), vec![(header, 4, 2)]);
+        assert!(store.tiered(1).is_none() && store.pruned(1).is_some());
+        assert!(!path.exists() && store.take_corrupt().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    pub fn cached_rows_spare_the_cold_store() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let dir = std::env::temp_dir().join(format!("lazy-lion-rows-{}", std::process::id()));
+        let mut store = BlockStore::<Bls12_381, Sha256>::new(params);
+        store.set_cold_store(Arc::new(DirectoryColdStore::open(&dir).unwrap()));
+        store.set_retention(RetentionPolicy {
+            hot_last: Some(1),
+            ..RetentionPolicy::default()
+        });
+        let mut samples = vec![];
+        for height in 0..2 {
+            let shares = vec![
+                vec![Fr::from(height), Fr::from(1)],
+                vec![Fr::from(2), Fr::from(3)],
+            ];
+            let prover = RsSquareProver::with_params(&shares, 2, store.shared_params());
+            samples.push(prover.sample_cell(1, 3).to_bytes());
+            store.insert(StoredBlock::new(height, prover));
+        }
+
+        let (rows, openings) = (RowCache::new(4), OpeningCache::new(4));
+        let served = store
+            .serve_cells(0, &[(1, 3), (1, 0)], &rows, &openings)
+            .unwrap();
+        assert_eq!(served[0].to_bytes(), samples[0]);
+        assert_eq!((rows.len(), openings.len()), (1, 2));
+        assert!(store.serve_cells(1, &[(4, 0)], &rows, &openings).is_none());
+
+        // the cold copy is no longer needed for the cached row
+        std::fs::remove_file(dir.join("0.cells")).unwrap();
+        let served = store.serve_cells(0, &[(1, 3)], &rows, &openings);
+        assert_eq!(served.unwrap()[0].to_bytes(), samples[0]);
+        assert!(store.serve_cells(0, &[(2, 3)], &rows, &openings).is_none());
+        assert!(rows.metrics("rows").contains("rows_hits 2\n"));
+
+        // samples are served under a shared borrow, side by side
+        std::thread::scope(|scope| {
+            for _ in 0..2 {
+                scope.spawn(|| {
+                    let served = store.serve_cells(1, &[(1, 3)], &rows, &openings);
+                    assert_eq!(served.unwrap()[0].to_bytes(), samples[1]);
+                });
+            }
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::checkpoint::put_record;
+use square_reed_solomon::codec::{
+    decode, field_to_bytes, put_bytes, put_u64, take_bytes, take_hash, take_u64, Validation,
+};
+use square_reed_solomon::namespace::{NamespaceId, NamespaceIndex, NamespaceRange};
+
+use anyhow::{bail, Result};
+use ark_ec::pairing::Pairing;
+use lion_roars::header::Header;
+use rs_merkle::Hasher;
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+const WAL_MAGIC: &[u8; 8] = b"LLWAL001";
+
+const BEGIN: u8 = 0;
+const COMMIT: u8 = 1;
+
+/// Square whose ingestion was logged, with everything needed to ingest it again
+#[derive(Clone, Debug)]
+pub struct LoggedSquare<E: Pairing> {
+    pub height: u64,
+    pub shares: Vec<Vec<E::ScalarField>>,
+    pub scale: usize,
+    pub namespaces: Option<NamespaceIndex>,
+}
+
+/// What an ingestion log held when it was reopened
+#[derive(Clone, Debug)]
+pub struct Replay<E: Pairing> {
+    /// Last square committed at each height, with the data root it was stored
+    /// under, lowest height first
+    pub complete: Vec<(LoggedSquare<E>, Vec<u8>)>,
+    /// Heights whose latest ingestion began but was never committed, lowest first
+    pub incomplete: Vec<u64>,
+}
+
+/// What recovering from an ingestion log did to the store
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recovery {
+    /// Heights ingested again from the log, lowest first
+    pub replayed: Vec<u64>,
+    /// Heights whose ingestion never finished, or whose replay did not reproduce
+    /// the logged data root, lowest first
+    pub discarded: Vec<u64>,
+}
+
+/// Write-ahead log of block ingestion. A square is logged before it is extended and
+/// committed to, and its data root once the block is stored, so that a node killed
+/// mid-ingest knows on restart which heights to ingest again and which to drop.
+///
+/// The log starts with a magic, followed by records of a payload and its checksum.
+/// A begin record holds the height, scale, original shares and namespace ranges of
+/// a square, a commit record the height and data root. Replay stops at the first
+/// record cut short by a crash or failing its checksum.
+pub struct IngestLog<E: Pairing, H: Hasher> {
+    file: File,
+    /// Begin records of squares not yet committed, kept across `trim`
+    pending: BTreeMap<u64, Vec<u8>>,
+    _pairing: PhantomData<E>,
+    _hasher: PhantomData<H>,
+}
+
+impl<E: Pairing, H: Hasher> IngestLog<E, H> {
+    /// Open the log at `path`, creating it if missing, and return what it holds.
+    /// The log is rewritten to hold only the complete squares, so that recovering
+    /// twice from it does the same.
+    pub fn open(path: impl AsRef<Path>) -> Result<(Self, Replay<E>)> {
+        let path = path.as_ref();
+        let existing = match std::fs::read(path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e.into()),
+        };
+        let replay = match existing.strip_prefix(WAL_MAGIC.as_slice()) {
+            Some(body) => Self::replay(body),
+            None if existing.is_empty() => Replay {
+                complete: vec![],
+                incomplete: vec![],
+            },
+            None => bail!("Not a lazy-lion ingestion log: {}", path.display()),
+        };
+
+        let mut log = WAL_MAGIC.to_vec();
+        for (square, data_root) in &replay.complete {
+            put_record::<H>(
+                &mut log,
+                &begin_payload::<E>(
+                    square.height,
+                    &square.shares,
+                    square.scale,
+                    square.namespaces.as_ref(),
+                ),
+            );
+            put_record::<H>(&mut log, &commit_payload(square.height, data_root));
+        }
+        // write then rename, so a crash mid-rewrite leaves the old log in place
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, log)?;
+        std::fs::rename(&tmp, path)?;
+        let log = Self {
+            file: OpenOptions::new().append(true).open(path)?,
+            pending: BTreeMap::new(),
+            _pairing: PhantomData,
+            _hasher: PhantomData,
+        };
+        Ok((log, replay))
+    }
+
+    fn replay(mut bytes: &[u8]) -> Replay<E> {
+        let mut begun: BTreeMap<u64, LoggedSquare<E>> = BTreeMap::new();
+        let mut complete = BTreeMap::new();
+        while let Some(payload) = take_record::<H>(&mut bytes) {
+            match decode_payload::<E>(payload) {
+                Some(Record::Begin(square)) => {
+                    begun.insert(square.height, square);
+                }
+                Some(Record::Commit(height, data_root)) => {
+                    if let Some(square) = begun.remove(&height) {
+                        complete.insert(height, (square, data_root));
+                    }
+                }
+                None => break,
+            }
+        }
+        Replay {
+            complete: complete.into_values().collect(),
+            incomplete: begun.into_keys().collect(),
+        }
+    }
+
+    /// Log that ingestion of the square of `shares` extended by `scale` begins at
+    /// `height`. Returns once the record is on disk.
+    pub fn begin(
+        &mut self,
+        height: u64,
+        shares: &[Vec<E::ScalarField>],
+        scale: usize,
+        namespaces: Option<&NamespaceIndex>,
+    ) -> Result<()> {
+        let mut record = vec![];
+        put_record::<H>(
+            &mut record,
+            &begin_payload::<E>(height, shares, scale, namespaces),
+        );
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.pending.insert(height, record);
+        Ok(())
+    }
+
+    /// Log that the block of `header` is stored. Returns once the record is on disk.
+    pub fn commit(&mut self, header: &Header) -> Result<()> {
+        let mut record = vec![];
+        put_record::<H>(
+            &mut record,
+            &commit_payload(header.height, &header.data_root),
+        );
+        self.file.write_all(&record)?;
+        self.file.sync_data()?;
+        self.pending.remove(&header.height);
+        Ok(())
+    }
+
+    /// Drop every square committed so far, once the store holding them is
+    /// persisted elsewhere, e.g. in a snapshot. Squares still being ingested stay.
+    pub fn trim(&mut self) -> Result<()> {
+        self.file.set_len(WAL_MAGIC.len() as u64)?;
+        for record in self.pending.values() {
+            self.file.write_all(record)?;
+        }
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+enum Record<E: Pairing> {
+    Begin(LoggedSquare<E>),
+    Commit(u64, Vec<u8>),
+}
+
+fn begin_payload<E: Pairing>(
+    height: u64,
+    shares: &[Vec<E::ScalarField>],
+    scale: usize,
+    namespaces: Option<&NamespaceIndex>,
+) -> Vec<u8> {
+    let mut payload = vec![BEGIN];
+    put_u64(&mut payload, height);
+    put_u64(&mut payload, scale as u64);
+    put_u64(&mut payload, shares.len() as u64);
+    for row in shares {
+        put_u64(&mut payload, row.len() as u64);
+        for val in row {
+            put_bytes(&mut payload, &field_to_bytes(val));
+        }
+    }
+    let ranges = namespaces.map_or(&[][..], |ns| ns.ranges());
+    put_u64(&mut payload, ranges.len() as u64);
+    for range in ranges {
+        put_bytes(&mut payload, &range.namespace.0);
+        put_u64(&mut payload, range.start as u64);
+        put_u64(&mut payload, range.end as u64);
+    }
+    payload
+}
+
+fn commit_payload(height: u64, data_root: &[u8]) -> Vec<u8> {
+    let mut payload = vec![COMMIT];
+    put_u64(&mut payload, height);
+    put_bytes(&mut payload, data_root);
+    payload
+}
+
+/// `None` if the payload does not hold a record of a known kind
+fn decode_payload<E: Pairing>(payload: &[u8]) -> Option<Record<E>> {
+    let (kind, mut bytes) = payload.split_first()?;
+    let height = take_u64(&mut bytes).ok()?;
+    let record = match *kind {
+        BEGIN => {
+            let scale = take_u64(&mut bytes).ok()? as usize;
+            let rows = take_u64(&mut bytes).ok()? as usize;
+            let mut shares = Vec::with_capacity(rows.min(bytes.len()));
+            for _ in 0..rows {
+                let len = take_u64(&mut bytes).ok()? as usize;
+                // the log is only ever written by this node
+                let row = (0..len)
+                    .map(|_| decode::<E::ScalarField>(take_bytes(&mut bytes)?, Validation::Trusted))
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()?;
+                shares.push(row);
+            }
+            let mut ranges = vec![];
+            for _ in 0..take_u64(&mut bytes).ok()? {
+                ranges.push(NamespaceRange {
+                    namespace: NamespaceId(take_bytes(&mut bytes).ok()?.try_into().ok()?),
+                    start: take_u64(&mut bytes).ok()? as usize,
+                    end: take_u64(&mut bytes).ok()? as usize,
+                });
+            }
+            let namespaces = if ranges.is_empty() {
+                None
+            } else if NamespaceIndex::is_valid_layout(&ranges, rows * rows) {
+                Some(NamespaceIndex::new(ranges, rows * rows))
+            } else {
+                return None;
+            };
+            Record::Begin(LoggedSquare {
+                height,
+                shares,
+                scale,
+                namespaces,
+            })
+        }
+        COMMIT => Record::Commit(height, take_bytes(&mut bytes).ok()?.to_vec()),
+        _ => return None,
+    };
+    bytes.is_empty().then_some(record)
+}
+
+/// Split a record written by `put_record` off the front of `bytes`, `None` if it is
+/// cut short or fails its checksum
+fn take_record<'a, H: Hasher>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let payload = take_bytes(bytes).ok()?;
+    let checksum = take_hash::<H>(bytes).ok()?;
+    (H::hash(payload) == checksum).then_some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IngestLog;
+    use crate::store::StoredBlock;
+    use square_reed_solomon::prover::RsSquareProver;
+
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn only_committed_squares_are_replayed() {
+        let path = std::env::temp_dir().join(format!("lazy-lion-{}.wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let squares: Vec<Vec<Vec<Fr>>> = (0..3)
+            .map(|height| {
+                vec![
+                    vec![Fr::from(height), Fr::from(1)],
+                    vec![Fr::from(2), Fr::from(3)],
+                ]
+            })
+            .collect();
+
+        let (mut log, replay) = IngestLog::<Bls12_381, Sha256>::open(&path).unwrap();
+        assert!(replay.complete.is_empty() && replay.incomplete.is_empty());
+        let mut headers = vec![];
+        for (height, shares) in squares.iter().enumerate() {
+            log.begin(height as u64, shares, 2, None).unwrap();
+            let prover = RsSquareProver::<Bls12_381, Sha256>::new(shares, 2);
+            headers.push(StoredBlock::new(height as u64, prover).header);
+        }
+        log.commit(&headers[0]).unwrap();
+        log.commit(&headers[2]).unwrap();
+        // a square committed before the last flush is no longer replayed
+        log.trim().unwrap();
+        log.commit(&headers[1]).unwrap();
+        drop(log);
+        // a record torn by the crash is discarded
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.extend([48, 0, 0, 0, 1, 2]);
+        std::fs::write(&path, bytes).unwrap();
+
+        let (_, replay) = IngestLog::<Bls12_381, Sha256>::open(&path).unwrap();
+        assert_eq!(replay.complete.len(), 1);
+        let (square, data_root) = &replay.complete[0];
+        assert_eq!((square.height, &square.shares), (1, &squares[1]));
+        assert_eq!(*data_root, headers[1].data_root);
+        assert!(replay.incomplete.is_empty());
+
+        // a square begun and never committed is reported, and gone once rewritten
+        let (mut log, _) = IngestLog::<Bls12_381, Sha256>::open(&path).unwrap();
+        log.begin(3, &squares[0], 2, None).unwrap();
+        drop(log);
+        let (_, replay) = IngestLog::<Bls12_381, Sha256>::open(&path).unwrap();
+        assert_eq!(replay.incomplete, vec![3]);
+        let (_, replay) = IngestLog::<Bls12_381, Sha256>::open(&path).unwrap();
+        assert!(replay.incomplete.is_empty() && replay.complete.len() == 1);
+        let _ = std::fs::remove_file(&path);
+    }
+}
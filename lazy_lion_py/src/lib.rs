@@ -0,0 +1,198 @@
+//! Python bindings over BLS12-381 with SHA-256 Merkle trees.
+
+use square_reed_solomon::codec::{
+    commitment_to_bytes, field_to_bytes, verifier_key_from_bytes, verifier_key_to_bytes,
+};
+use square_reed_solomon::prover::RsSquareProver;
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::verifier::{verifier_key, verify_cell_sample, RootVersion};
+
+use ark_poly_commit::kzg10::VerifierKey;
+use ark_test_curves::bls12_381::{Bls12_381, Fr};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use rand::Rng;
+use rs_merkle::algorithms::Sha256;
+
+/// Reed-Solomon encoded and committed square
+#[pyclass(name = "Square")]
+pub struct PySquare {
+    prover: RsSquareProver<Bls12_381, Sha256>,
+}
+
+#[pymethods]
+impl PySquare {
+    #[new]
+    fn new(shares: Vec<Vec<u64>>, scale: usize) -> PyResult<Self> {
+        let n_rows = shares.len();
+        if !n_rows.is_power_of_two() || !scale.is_power_of_two() {
+            return Err(PyValueError::new_err(
+                "Square side and scale must be powers of 2",
+            ));
+        }
+        if shares.iter().any(|share| share.len() != n_rows) {
+            return Err(PyValueError::new_err("Shares do not form a square"));
+        }
+        let shares: Vec<Vec<Fr>> = shares
+            .into_iter()
+            .map(|share| share.into_iter().map(Fr::from).collect())
+            .collect();
+        Ok(Self {
+            prover: RsSquareProver::new(&shares, scale),
+        })
+    }
+
+    #[getter]
+    fn length(&self) -> usize {
+        self.prover.shares().len() * self.prover.scale()
+    }
+
+    /// Compressed encoding of the value at (rid, cid)
+    fn value(&self, py: Python<'_>, rid: usize, cid: usize) -> PyResult<Py<PyBytes>> {
+        self.check_coords(rid, cid)?;
+        let val = self.prover.square().val_at(rid, cid);
+        Ok(PyBytes::new(py, &field_to_bytes(&val)).into())
+    }
+
+    fn row_commitment(&self, py: Python<'_>, rid: usize) -> PyResult<Py<PyBytes>> {
+        self.check_coords(rid, 0)?;
+        let com = self.prover.commit_to_row(rid);
+        Ok(PyBytes::new(py, &commitment_to_bytes(&com)).into())
+    }
+
+    fn col_commitment(&self, py: Python<'_>, cid: usize) -> PyResult<Py<PyBytes>> {
+        self.check_coords(0, cid)?;
+        let com = self.prover.commit_to_col(cid);
+        Ok(PyBytes::new(py, &commitment_to_bytes(&com)).into())
+    }
+
+    fn root(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, &self.prover.root()).into()
+    }
+
+    /// Id of the root version the square's roots are built under
+    #[getter]
+    fn root_version(&self) -> u8 {
+        self.prover.root_version().id()
+    }
+
+    /// Encoded `CellSample` answering a query for (rid, cid)
+    fn sample(&self, py: Python<'_>, rid: usize, cid: usize) -> PyResult<Py<PyBytes>> {
+        self.check_coords(rid, cid)?;
+        let sample = self.prover.sample_cell(rid, cid);
+        Ok(PyBytes::new(py, &sample.to_bytes()).into())
+    }
+
+    /// Versioned encoding of the `VerifierKey` matching this square's setup, as
+    /// `Verifier` takes it
+    fn verifier_key(&self, py: Python<'_>) -> Py<PyBytes> {
+        let bytes = verifier_key_to_bytes(&verifier_key(self.prover.params()));
+        PyBytes::new(py, &bytes).into()
+    }
+}
+
+impl PySquare {
+    fn check_coords(&self, rid: usize, cid: usize) -> PyResult<()> {
+        let length = self.length();
+        if rid >= length || cid >= length {
+            return Err(PyValueError::new_err(format!(
+                "Coordinates ({}, {}) outside of {}x{} square",
+                rid, cid, length, length
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[pyclass(name = "Verifier")]
+#[derive(Clone)]
+pub struct PyVerifier {
+    vk: VerifierKey<Bls12_381>,
+}
+
+#[pymethods]
+impl PyVerifier {
+    /// Verifier for a verifier key encoded by `verifier_key_to_bytes`: the
+    /// `VK_FORMAT_VERSION` byte followed by the compressed canonical key
+    #[new]
+    fn new(vk: &[u8]) -> PyResult<Self> {
+        let vk = verifier_key_from_bytes::<Bls12_381>(vk)
+            .map_err(|e| PyValueError::new_err(format!("Invalid verifier key: {}", e)))?;
+        Ok(Self { vk })
+    }
+
+    fn verify_sample(
+        &self,
+        root_version: u8,
+        root: &[u8],
+        length: usize,
+        sample: &[u8],
+    ) -> PyResult<bool> {
+        let version = RootVersion::from_id(root_version).ok_or_else(|| {
+            PyValueError::new_err(format!("Unknown root version {}", root_version))
+        })?;
+        let root: [u8; 32] = root
+            .try_into()
+            .map_err(|_| PyValueError::new_err("Data root must be 32 bytes"))?;
+        let sample = CellSample::<Bls12_381, Sha256>::from_bytes(sample)
+            .map_err(|e| PyValueError::new_err(format!("Invalid sample encoding: {}", e)))?;
+        Ok(verify_cell_sample(&self.vk, version, root, length, &sample))
+    }
+}
+
+/// Draws uniformly random coordinates and keeps a tally of verified samples
+#[pyclass(name = "SamplingClient")]
+pub struct PySamplingClient {
+    verifier: PyVerifier,
+    root_version: u8,
+    root: Vec<u8>,
+    length: usize,
+    #[pyo3(get)]
+    attempted: usize,
+    #[pyo3(get)]
+    verified: usize,
+}
+
+#[pymethods]
+impl PySamplingClient {
+    #[new]
+    fn new(verifier: PyVerifier, root_version: u8, root: Vec<u8>, length: usize) -> Self {
+        Self {
+            verifier,
+            root_version,
+            root,
+            length,
+            attempted: 0,
+            verified: 0,
+        }
+    }
+
+    fn draw(&self, count: usize) -> Vec<(usize, usize)> {
+        let mut rng = rand::thread_rng();
+        (0..count)
+            .map(|_| (rng.gen_range(0..self.length), rng.gen_range(0..self.length)))
+            .collect()
+    }
+
+    /// Verify a sample and record the outcome
+    fn submit(&mut self, sample: &[u8]) -> PyResult<bool> {
+        let ok = self
+            .verifier
+            .verify_sample(self.root_version, &self.root, self.length, sample)
+            .unwrap_or(false);
+        self.attempted += 1;
+        if ok {
+            self.verified += 1;
+        }
+        Ok(ok)
+    }
+}
+
+#[pymodule]
+fn lazy_lion_py(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PySquare>()?;
+    m.add_class::<PyVerifier>()?;
+    m.add_class::<PySamplingClient>()?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use std::time::{Duration, Instant};
+
+/// Which past heights a light node samples once its headers are synced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BackfillPolicy {
+    /// Number of heights below the tip to sample
+    pub depth: u64,
+    /// Least time between starting two backfill heights
+    pub interval: Duration,
+}
+
+/// Walks back from the tip over the heights a `BackfillPolicy` covers, most
+/// recent first, handing out at most one height per interval so that backfill
+/// does not compete with sampling new heights.
+#[derive(Clone, Debug)]
+pub struct Backfill {
+    policy: BackfillPolicy,
+    /// Next height to hand out, `None` once the walk is finished
+    next: Option<u64>,
+    /// Lowest height covered
+    floor: u64,
+    last_started: Option<Instant>,
+}
+
+impl Backfill {
+    /// Backfill the `policy.depth` heights just below `tip`
+    pub fn new(policy: BackfillPolicy, tip: u64) -> Self {
+        Self {
+            policy,
+            next: tip.checked_sub(1).filter(|_| policy.depth > 0),
+            floor: tip.saturating_sub(policy.depth),
+            last_started: None,
+        }
+    }
+
+    /// Next height to sample, skipping those `done` reports as already sampled.
+    /// `None` while rate limited or once every covered height was handed out.
+    pub fn next_height(&mut self, now: Instant, done: impl Fn(u64) -> bool) -> Option<u64> {
+        if let Some(last) = self.last_started {
+            if now.saturating_duration_since(last) < self.policy.interval {
+                return None;
+            }
+        }
+        loop {
+            let height = self.next?;
+            self.next = height.checked_sub(1).filter(|next| *next >= self.floor);
+            if !done(height) {
+                self.last_started = Some(now);
+                return Some(height);
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Number of covered heights not yet handed out, including skipped ones
+    pub fn remaining(&self) -> u64 {
+        self.next.map_or(0, |next| next - self.floor + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backfill, BackfillPolicy};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    pub fn recent_heights_come_first_and_are_rate_limited() {
+        let policy = BackfillPolicy {
+            depth: 4,
+            interval: Duration::from_secs(10),
+        };
+        let mut backfill = Backfill::new(policy, 10);
+        assert_eq!(backfill.remaining(), 4);
+        let start = Instant::now();
+        assert_eq!(backfill.next_height(start, |_| false), Some(9));
+        assert_eq!(backfill.next_height(start, |_| false), None);
+
+        // height 8 was already sampled and is skipped
+        let later = start + Duration::from_secs(10);
+        assert_eq!(backfill.next_height(later, |height| height == 8), Some(7));
+        let last = later + Duration::from_secs(10);
+        assert_eq!(backfill.next_height(last, |_| false), Some(6));
+        assert!(backfill.is_finished());
+        assert_eq!(
+            backfill.next_height(last + policy.interval, |_| false),
+            None
+        );
+
+        assert!(Backfill::new(policy, 0).is_finished());
+        assert_eq!(Backfill::new(policy, 2).remaining(), 2);
+    }
+}
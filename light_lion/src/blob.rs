@@ -0,0 +1,213 @@
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::inclusion::{verify_blob_inclusion, ShareProof};
+use square_reed_solomon::layout::BlobReceipt;
+use square_reed_solomon::namespace::{NamespaceLookup, NamespaceProof};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::VerifierKey;
+use lion_roars::header::Header;
+use lion_roars::messages::LionMessages;
+use rs_merkle::Hasher;
+use std::io;
+
+/// Why a blob could not be retrieved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlobError {
+    /// No verifier key is loaded, or the header was committed under another setup
+    UnknownSetup,
+    /// The receipt names another height, namespace or data root than the header
+    ReceiptMismatch,
+    /// No peer could serve the blob
+    Unavailable,
+    /// Every proof served failed to verify
+    InvalidProof,
+}
+
+/// Proofs a full node serves for a blob: the inclusion of its shares, and the
+/// range of its namespace
+pub struct BlobResponse<E: Pairing, H: Hasher> {
+    pub proof: ShareProof<E, H>,
+    pub namespace_proof: NamespaceProof<H>,
+}
+
+/// Request for the blob `receipt` names
+pub fn blob_request(receipt: &BlobReceipt) -> LionMessages {
+    LionMessages::BlobRequest {
+        height: receipt.height,
+        namespace: receipt.placement.namespace.0.to_vec(),
+        version: receipt.version.id() as u64,
+        start: receipt.placement.start,
+        end: receipt.placement.end,
+        len: receipt.placement.len,
+    }
+}
+
+/// Proofs carried by a full node's reply to `blob_request`, `None` if it cannot
+/// serve the blob
+pub fn blob_response<E: Pairing, H: Hasher>(
+    reply: LionMessages,
+) -> io::Result<Option<BlobResponse<E, H>>> {
+    match reply {
+        LionMessages::BlobResponse {
+            proof,
+            namespace_proof,
+            ..
+        } => {
+            let proof = ShareProof::from_bytes(&proof)
+                .map_err(|_| invalid_data("Malformed blob inclusion proof".to_string()))?;
+            let mut bytes = namespace_proof.as_slice();
+            let namespace_proof = NamespaceProof::from_bytes(&mut bytes)
+                .ok()
+                .filter(|_| bytes.is_empty())
+                .ok_or_else(|| invalid_data("Malformed namespace proof".to_string()))?;
+            Ok(Some(BlobResponse {
+                proof,
+                namespace_proof,
+            }))
+        }
+        LionMessages::BlobUnavailable { .. } => Ok(None),
+        other => Err(invalid_data(format!(
+            "Unexpected reply to blob request: {:?}",
+            other
+        ))),
+    }
+}
+
+/// Check `response` against `header` for the blob `receipt` names: the blob's
+/// shares lie within its namespace's range and are committed under the data root.
+/// Returns the blob's bytes without the padding of its last share.
+pub fn verify_blob_response<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    header: &Header,
+    receipt: &BlobReceipt,
+    response: &BlobResponse<E, H>,
+) -> Option<Vec<u8>> {
+    let data_root = H::Hash::try_from(header.data_root.clone()).ok()?;
+    let namespace_root = H::Hash::try_from(header.namespace_root.clone()).ok()?;
+    let root_version = SquareLayout::root_version_of(&header.layout)?;
+    let placement = &receipt.placement;
+    match response
+        .namespace_proof
+        .verify(namespace_root, placement.namespace)?
+    {
+        NamespaceLookup::Present(range)
+            if range.start <= placement.start && placement.end <= range.end => {}
+        _ => return None,
+    }
+    verify_blob_inclusion(
+        vk,
+        header.height,
+        root_version,
+        data_root,
+        receipt.version,
+        receipt.n_rows,
+        receipt.scale,
+        placement,
+        &response.proof,
+    )
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blob_request, verify_blob_response, BlobResponse};
+    use square_reed_solomon::inclusion::ShareProof;
+    use square_reed_solomon::layout::{build_layout, BlobReceipt};
+    use square_reed_solomon::namespace::NamespaceId;
+    use square_reed_solomon::prover::RsSquareProver;
+    use square_reed_solomon::verifier::verifier_key;
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use lion_roars::header::Header;
+    use lion_roars::messages::LionMessages;
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn blobs_are_checked_against_the_header() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(8, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let (ours, theirs) = (NamespaceId([1; 8]), NamespaceId([2; 8]));
+        let blobs = vec![(ours, vec![5; 40]), (theirs, vec![6; 50])];
+        let layout = build_layout::<Fr>(&blobs, 0.5).unwrap();
+        let (n, scale) = (layout.dimensions.n_rows, layout.dimensions.scale);
+        let prover =
+            RsSquareProver::<Bls12_381, Sha256>::with_params(&layout.shares, scale, params)
+                .at_height(4);
+        let header = Header {
+            height: 4,
+            data_root: prover.root().to_vec(),
+            vk_digest: vec![],
+            namespace_root: layout.namespaces.root::<Sha256>().to_vec(),
+            parent: vec![],
+            layout: vec![],
+        };
+        let receipt = BlobReceipt {
+            ticket: 0,
+            height: 4,
+            placement: layout.placements[0],
+            commitment: header.data_root.clone(),
+            version: layout.version,
+            n_rows: n,
+            scale,
+        };
+        assert!(matches!(
+            blob_request(&receipt),
+            LionMessages::BlobRequest {
+                start: 0,
+                end: 2,
+                len: 40,
+                ..
+            }
+        ));
+
+        // what a full node serves for the receipt
+        let commitments = prover.commit_all_rows();
+        let rows = receipt.version.blob_rows(&receipt.placement, n, scale);
+        let cells = receipt.cells();
+        let response = BlobResponse {
+            proof: ShareProof {
+                height: prover.height(),
+                row_root: prover.row_root(),
+                col_root: prover.col_root(),
+                row_commitments: rows.iter().map(|rid| commitments[*rid]).collect(),
+                rows_path: prover.row_commitments_proof(&rows),
+                values: cells
+                    .iter()
+                    .map(|&(rid, cid)| prover.square().val_at(rid, cid))
+                    .collect(),
+                ranges: rows
+                    .iter()
+                    .map(|rid| {
+                        let cids: Vec<usize> = cells
+                            .iter()
+                            .filter(|(row, _)| row == rid)
+                            .map(|(_, cid)| *cid)
+                            .collect();
+                        prover.range_proof(*rid, &cids)
+                    })
+                    .collect(),
+            },
+            namespace_proof: layout.namespaces.proof::<Sha256>(ours),
+        };
+        let vk = verifier_key(prover.params());
+        assert_eq!(
+            verify_blob_response(&vk, &header, &receipt, &response),
+            Some(vec![5; 40])
+        );
+
+        // the same shares cannot be passed off as part of another namespace
+        let mut forged = receipt.clone();
+        forged.placement.namespace = theirs;
+        let response = BlobResponse {
+            namespace_proof: layout.namespaces.proof::<Sha256>(theirs),
+            ..response
+        };
+        assert_eq!(verify_blob_response(&vk, &header, &forged, &response), None);
+    }
+}
@@ -0,0 +1,327 @@
+use square_reed_solomon::verifier::digests_equal;
+
+use lion_roars::dht::PeerId;
+use lion_roars::header::{Header, SignedHeader};
+
+use std::collections::{BTreeMap, HashSet};
+
+/// Most heights whose verified header is remembered
+const RETAINED_HEADERS: usize = 1024;
+
+/// Why a header does not extend the verified chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChainError {
+    /// The signature does not verify under the key it names
+    BadSignature,
+    /// Signed by another key than the block producer's
+    UnknownProducer,
+    /// Not above the verified tip
+    StaleHeight,
+    /// Above the height right after the tip, so its parent is not yet verified
+    MissingParent,
+    /// Names another parent than the verified tip: a fork
+    ParentMismatch,
+    /// The header or its parent was proven to commit to a badly encoded square, or
+    /// the header lost to a conflicting one whose data passed sampling
+    Quarantined,
+}
+
+/// What is known of the data of one of several conflicting headers at a height
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchStatus {
+    /// Not sampled yet
+    Pending,
+    /// The last sampling round passed
+    Available,
+    /// The last sampling round failed; a later one may still pass
+    Unavailable,
+    /// A fraud proof showed the square is badly encoded
+    FraudProven,
+}
+
+impl BranchStatus {
+    /// Order of preference between conflicting headers, `None` if quarantined
+    fn rank(self) -> Option<u8> {
+        match self {
+            BranchStatus::Available => Some(1),
+            BranchStatus::Pending => Some(0),
+            BranchStatus::Unavailable | BranchStatus::FraudProven => None,
+        }
+    }
+}
+
+/// Headers a light node has verified to form one chain signed by the producer.
+/// Each extends the previous by one height and names its hash as parent, so a
+/// fork is rejected before any sampling is spent on it. The exception is a header
+/// conflicting with the tip under the same parent: both are kept as branches that
+/// may be sampled, and the chain follows the one whose data passes.
+pub struct HeaderChain {
+    /// Preferred header at each of the latest heights; the last one is the tip
+    headers: BTreeMap<u64, Header>,
+    /// Conflicting headers at contested heights, in arrival order
+    branches: BTreeMap<u64, Vec<(Header, BranchStatus)>>,
+    /// Hashes of headers proven fraudulent, which are never accepted nor extended
+    quarantined: HashSet<[u8; 32]>,
+}
+
+impl HeaderChain {
+    /// Chain continuing from `tip`, e.g. the latest header a store holds, or empty
+    /// to trust the first header accepted
+    pub fn new(tip: Option<Header>) -> Self {
+        Self {
+            headers: tip.into_iter().map(|tip| (tip.height, tip)).collect(),
+            branches: BTreeMap::new(),
+            quarantined: HashSet::new(),
+        }
+    }
+
+    pub fn tip(&self) -> Option<&Header> {
+        self.headers.last_key_value().map(|(_, tip)| tip)
+    }
+
+    /// Check that `signed` is signed by `producer` and extends the tip, then make
+    /// it the new tip. A header conflicting with the tip under the same parent is
+    /// kept as a competing branch instead. Returns whether the tip moved.
+    pub fn extend(&mut self, producer: &PeerId, signed: &SignedHeader) -> Result<bool, ChainError> {
+        if !signed.verify() {
+            return Err(ChainError::BadSignature);
+        }
+        if signed.producer_id() != *producer {
+            return Err(ChainError::UnknownProducer);
+        }
+        let header = &signed.header;
+        if self.quarantined.contains(&header.hash())
+            || self
+                .quarantined
+                .iter()
+                .any(|hash| digests_equal(&header.parent, hash))
+        {
+            return Err(ChainError::Quarantined);
+        }
+        if let Some(tip) = self.tip() {
+            if header.height == tip.height
+                && header != tip
+                && digests_equal(&header.parent, &tip.parent)
+            {
+                let branches = self
+                    .branches
+                    .entry(header.height)
+                    .or_insert_with(|| vec![(tip.clone(), BranchStatus::Pending)]);
+                if !branches.iter().any(|(branch, _)| branch == header) {
+                    branches.push((header.clone(), BranchStatus::Pending));
+                }
+                return Ok(false);
+            }
+            if header.height <= tip.height {
+                return Err(ChainError::StaleHeight);
+            }
+            if header.height > tip.height + 1 {
+                return Err(ChainError::MissingParent);
+            }
+            if !digests_equal(&header.parent, &tip.hash()) {
+                return Err(ChainError::ParentMismatch);
+            }
+        }
+        let lost = self
+            .branches(header.height)
+            .iter()
+            .any(|(branch, status)| branch == header && status.rank().is_none());
+        if lost {
+            return Err(ChainError::Quarantined);
+        }
+        if let Some(branches) = self.branches.get_mut(&header.height) {
+            if !branches.iter().any(|(branch, _)| branch == header) {
+                branches.push((header.clone(), BranchStatus::Pending));
+            }
+        }
+        self.headers.insert(header.height, header.clone());
+        while self.headers.len() > RETAINED_HEADERS {
+            if let Some((height, _)) = self.headers.pop_first() {
+                self.branches.remove(&height);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether `header` is verified to extend the chain, either as the preferred
+    /// header at its height or as a competing branch not proven fraudulent
+    pub fn is_verified(&self, header: &Header) -> bool {
+        self.headers.get(&header.height) == Some(header)
+            || self
+                .branches(header.height)
+                .iter()
+                .any(|(branch, status)| branch == header && *status != BranchStatus::FraudProven)
+    }
+
+    /// Preferred header at `height`, `None` if every header there is quarantined
+    pub fn preferred(&self, height: u64) -> Option<&Header> {
+        self.headers.get(&height)
+    }
+
+    /// Conflicting headers at `height` and what is known of their data, empty if
+    /// the height is not contested
+    pub fn branches(&self, height: u64) -> &[(Header, BranchStatus)] {
+        self.branches.get(&height).map_or(&[], |branches| branches)
+    }
+
+    /// Record whether a sampling round of `header` passed. Returns the newly
+    /// preferred header at its height if the preference moved to another branch.
+    pub fn record_availability(&mut self, header: &Header, available: bool) -> Option<Header> {
+        let status = if available {
+            BranchStatus::Available
+        } else {
+            BranchStatus::Unavailable
+        };
+        self.set_status(header, status)
+    }
+
+    /// Quarantine `header` after a fraud proof against its square, dropping it and
+    /// its descendants from the chain. Returns the newly preferred header at its
+    /// height if the preference moved to another branch.
+    pub fn record_fraud(&mut self, header: &Header) -> Option<Header> {
+        self.quarantined.insert(header.hash());
+        if !self.branches.contains_key(&header.height) {
+            if self.headers.get(&header.height) == Some(header) {
+                self.headers.split_off(&header.height);
+            }
+            return None;
+        }
+        self.set_status(header, BranchStatus::FraudProven)
+    }
+
+    fn set_status(&mut self, header: &Header, status: BranchStatus) -> Option<Header> {
+        let height = header.height;
+        let branches = self.branches.get_mut(&height)?;
+        let entry = branches.iter_mut().find(|(branch, _)| branch == header)?;
+        if entry.1 == BranchStatus::FraudProven {
+            return None;
+        }
+        entry.1 = status;
+
+        let rank_of = |header: &Header| {
+            branches
+                .iter()
+                .find(|(branch, _)| branch == header)
+                .and_then(|(_, status)| status.rank())
+        };
+        let best_rank = branches
+            .iter()
+            .filter_map(|(_, status)| status.rank())
+            .max();
+        let current = self.headers.get(&height);
+        let unchanged = match best_rank {
+            Some(_) => current.and_then(rank_of) == best_rank,
+            None => current.is_none(),
+        };
+        if unchanged {
+            return None;
+        }
+        // the earliest branch seen wins among equally ranked ones
+        let best = branches
+            .iter()
+            .find(|(_, status)| best_rank.is_some() && status.rank() == best_rank)
+            .map(|(branch, _)| branch.clone());
+        // descendants only ever extend the preferred branch
+        self.headers.split_off(&height);
+        if let Some(best) = &best {
+            self.headers.insert(height, best.clone());
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BranchStatus, ChainError, HeaderChain};
+
+    use lion_roars::header::{Header, ProducerKey};
+
+    fn header(height: u64, root: u8, parent: Vec<u8>) -> Header {
+        Header {
+            height,
+            data_root: vec![root; 32],
+            vk_digest: vec![],
+            namespace_root: vec![],
+            parent,
+            layout: vec![],
+        }
+    }
+
+    #[test]
+    pub fn chains_only_grow_from_their_tip() {
+        let key = ProducerKey::generate();
+        let first = key.sign(header(0, 0, vec![]));
+        let (genesis, id) = (first.header.clone(), first.producer_id());
+        let mut chain = HeaderChain::new(None);
+        assert_eq!(chain.extend(&id, &first), Ok(true));
+
+        let next = key.sign(header(1, 1, genesis.hash().to_vec()));
+        let fork = key.sign(header(1, 2, header(0, 9, vec![]).hash().to_vec()));
+        let skip = key.sign(header(3, 1, genesis.hash().to_vec()));
+        let stranger = ProducerKey::generate().sign(next.header.clone());
+        let mut forged = next.clone();
+        forged.header.data_root = vec![3; 32];
+        assert_eq!(chain.extend(&id, &fork), Err(ChainError::ParentMismatch));
+        assert_eq!(chain.extend(&id, &skip), Err(ChainError::MissingParent));
+        assert_eq!(
+            chain.extend(&id, &stranger),
+            Err(ChainError::UnknownProducer)
+        );
+        assert_eq!(chain.extend(&id, &forged), Err(ChainError::BadSignature));
+        assert!(!chain.is_verified(&next.header));
+
+        chain.extend(&id, &next).unwrap();
+        assert!(chain.is_verified(&genesis) && chain.is_verified(&next.header));
+        assert!(!chain.is_verified(&fork.header));
+        assert_eq!(chain.extend(&id, &next), Err(ChainError::StaleHeight));
+
+        // a restarted node resumes from its stored tip
+        let mut resumed = HeaderChain::new(Some(next.header.clone()));
+        let after = key.sign(header(2, 1, next.header.hash().to_vec()));
+        assert_eq!(resumed.extend(&id, &after), Ok(true));
+    }
+
+    #[test]
+    pub fn conflicting_headers_follow_available_data() {
+        let key = ProducerKey::generate();
+        let genesis = key.sign(header(0, 0, vec![]));
+        let id = genesis.producer_id();
+        let parent = genesis.header.hash().to_vec();
+        let first = key.sign(header(1, 1, parent.clone()));
+        let second = key.sign(header(1, 2, parent));
+        let mut chain = HeaderChain::new(None);
+        chain.extend(&id, &genesis).unwrap();
+        chain.extend(&id, &first).unwrap();
+        assert_eq!(chain.extend(&id, &second), Ok(false));
+        assert!(chain.is_verified(&second.header));
+        assert_eq!(chain.branches(1).len(), 2);
+        let child = key.sign(header(2, 1, first.header.hash().to_vec()));
+        chain.extend(&id, &child).unwrap();
+
+        // data that passes sampling wins over data not sampled yet, dropping the
+        // other branch's descendants
+        let preferred = chain.record_availability(&second.header, true);
+        assert_eq!(preferred, Some(second.header.clone()));
+        assert!(chain.preferred(2).is_none());
+        assert_eq!(chain.extend(&id, &child), Err(ChainError::ParentMismatch));
+        assert_eq!(chain.record_availability(&first.header, true), None);
+
+        // a fraud proof quarantines the branch for good
+        assert_eq!(
+            chain.record_fraud(&second.header),
+            Some(first.header.clone())
+        );
+        assert!(!chain.is_verified(&second.header));
+        assert_eq!(chain.branches(1)[1].1, BranchStatus::FraudProven);
+        assert_eq!(chain.record_availability(&second.header, true), None);
+        let grandchild = key.sign(header(2, 2, second.header.hash().to_vec()));
+        assert_eq!(chain.extend(&id, &grandchild), Err(ChainError::Quarantined));
+
+        // with every branch quarantined no header is preferred
+        assert_eq!(chain.record_availability(&first.header, false), None);
+        assert!(chain.preferred(1).is_none() && chain.is_verified(&first.header));
+        assert_eq!(chain.extend(&id, &first), Err(ChainError::Quarantined));
+        let preferred = chain.record_availability(&first.header, true);
+        assert_eq!(preferred, Some(first.header.clone()));
+    }
+}
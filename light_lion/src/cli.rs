@@ -0,0 +1,261 @@
+//! Command line options of a light node's sampling run, and the verdict it reports
+//! when it exits
+
+use crate::sampling::{confidence, AdversaryModel, NamespaceWeightedSampler, SamplingGoal};
+use crate::store::SamplingRecord;
+
+use square_reed_solomon::dynamic::Curve;
+use square_reed_solomon::namespace::NamespaceId;
+
+use std::io;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+
+/// How a sampling run is configured from the command line
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SamplingOptions {
+    /// Most sample requests to make at each height
+    pub samples: usize,
+    /// Confidence of availability to reach at each height
+    pub confidence: f64,
+    /// Full nodes to request samples from
+    pub peers: Vec<SocketAddr>,
+    /// Heights to sample, inclusive
+    pub heights: RangeInclusive<u64>,
+    /// Namespace whose shares samples are biased toward, if any
+    pub namespace: Option<NamespaceId>,
+    /// Curve the sampled squares are committed over
+    pub curve: Curve,
+}
+
+impl Default for SamplingOptions {
+    fn default() -> Self {
+        Self {
+            samples: 16,
+            confidence: 0.99,
+            peers: vec![],
+            heights: 1..=1,
+            namespace: None,
+            curve: Curve::Bls12_381,
+        }
+    }
+}
+
+impl SamplingOptions {
+    /// Parse the arguments following the program name. Options left out keep their
+    /// defaults.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> io::Result<Self> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| invalid_input(format!("{} expects a value", flag)))?;
+            match flag.as_str() {
+                "--samples" => options.samples = parse_value(&flag, &value)?,
+                "--confidence" => {
+                    options.confidence = parse_value(&flag, &value)?;
+                    if !(0.0..1.0).contains(&options.confidence) {
+                        return Err(invalid_input(format!(
+                            "--confidence must lie in [0, 1), got {}",
+                            value
+                        )));
+                    }
+                }
+                "--peers" => {
+                    options.peers = value
+                        .split(',')
+                        .map(|peer| parse_value(&flag, peer))
+                        .collect::<io::Result<_>>()?
+                }
+                "--height-range" => options.heights = parse_heights(&value)?,
+                "--namespace" => options.namespace = Some(parse_namespace(&value)?),
+                "--curve" => options.curve = parse_value(&flag, &value)?,
+                _ => return Err(invalid_input(format!("Unknown option {}", flag))),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Goal of the round sampling a square with original side `n` extended by `scale`
+    pub fn goal(&self, n: usize, scale: usize) -> SamplingGoal {
+        SamplingGoal {
+            n,
+            scale,
+            model: AdversaryModel::Unrecoverable,
+            confidence: self.confidence,
+            budget: self.samples,
+        }
+    }
+
+    /// Sampler drawing each round's cells. With a namespace, half the samples are
+    /// drawn from its shares and the rest uniformly.
+    pub fn sampler(&self) -> NamespaceWeightedSampler {
+        match self.namespace {
+            Some(namespace) => {
+                let mut sampler = NamespaceWeightedSampler::new(self.samples.div_ceil(2));
+                sampler.set_weight(namespace, 1);
+                sampler
+            }
+            None => NamespaceWeightedSampler::new(self.samples),
+        }
+    }
+}
+
+/// Outcome of a sampling run, printed as one line of JSON when the node exits
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    pub heights: Vec<HeightVerdict>,
+}
+
+/// Outcome of sampling one height
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeightVerdict {
+    pub record: SamplingRecord,
+    /// Confidence of availability the verified samples give
+    pub confidence: f64,
+    /// Whether every sample verified and the goal's confidence was reached
+    pub available: bool,
+}
+
+impl HeightVerdict {
+    pub fn new(record: SamplingRecord, goal: SamplingGoal) -> Self {
+        let confidence = confidence(goal.n, goal.scale, record.verified, goal.model);
+        Self {
+            record,
+            confidence,
+            available: record.succeeded() && confidence >= goal.confidence,
+        }
+    }
+}
+
+impl Verdict {
+    /// Whether every sampled height was found available
+    pub fn available(&self) -> bool {
+        !self.heights.is_empty() && self.heights.iter().all(|height| height.available)
+    }
+
+    /// Process exit code: 0 when every height is available, 1 otherwise
+    pub fn exit_code(&self) -> i32 {
+        !self.available() as i32
+    }
+
+    pub fn to_json(&self) -> String {
+        let heights: Vec<String> = self
+            .heights
+            .iter()
+            .map(|height| {
+                format!(
+                    "{{\"height\":{},\"attempted\":{},\"verified\":{},\
+                     \"confidence\":{:.6},\"available\":{}}}",
+                    height.record.height,
+                    height.record.attempted,
+                    height.record.verified,
+                    height.confidence,
+                    height.available
+                )
+            })
+            .collect();
+        format!(
+            "{{\"available\":{},\"heights\":[{}]}}",
+            self.available(),
+            heights.join(",")
+        )
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> io::Result<T> {
+    value
+        .parse()
+        .map_err(|_| invalid_input(format!("Invalid value for {}: {}", flag, value)))
+}
+
+/// `FROM..TO`, both inclusive, or a single height
+fn parse_heights(value: &str) -> io::Result<RangeInclusive<u64>> {
+    let (from, to) = value.split_once("..").unwrap_or((value, value));
+    let heights = parse_value("--height-range", from)?..=parse_value("--height-range", to)?;
+    if heights.is_empty() {
+        return Err(invalid_input(format!("Empty height range {}", value)));
+    }
+    Ok(heights)
+}
+
+fn parse_namespace(value: &str) -> io::Result<NamespaceId> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(NamespaceId)
+        .ok_or_else(|| invalid_input(format!("Namespaces are 8 hex-encoded bytes, got {}", value)))
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeightVerdict, SamplingOptions, Verdict};
+    use crate::store::SamplingRecord;
+
+    use square_reed_solomon::dynamic::Curve;
+    use square_reed_solomon::namespace::NamespaceId;
+
+    fn parse(args: &str) -> std::io::Result<SamplingOptions> {
+        SamplingOptions::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    pub fn options_parse_into_goals_and_verdicts() {
+        let options = parse(
+            "--samples 12 --confidence 0.9 --peers 127.0.0.1:7000,127.0.0.1:7001 \
+             --height-range 3..5 --namespace 0001020304050607 --curve bn254",
+        )
+        .unwrap();
+        assert_eq!(options.samples, 12);
+        assert_eq!(options.peers.len(), 2);
+        assert_eq!(options.heights, 3..=5);
+        assert_eq!(options.curve, Curve::Bn254);
+        assert_eq!(
+            options.namespace,
+            Some(NamespaceId([0, 1, 2, 3, 4, 5, 6, 7]))
+        );
+        assert_eq!(parse("--height-range 4").unwrap().heights, 4..=4);
+        for bad in [
+            "--samples",
+            "--confidence 1",
+            "--height-range 5..3",
+            "--namespace 00",
+            "--curve secp256k1",
+            "-x 1",
+        ] {
+            assert!(parse(bad).is_err(), "{}", bad);
+        }
+
+        let goal = options.goal(2, 2);
+        let passed = HeightVerdict::new(
+            SamplingRecord {
+                height: 3,
+                attempted: 12,
+                verified: 12,
+            },
+            goal,
+        );
+        let failed = HeightVerdict::new(
+            SamplingRecord {
+                height: 4,
+                attempted: 2,
+                verified: 1,
+            },
+            goal,
+        );
+        assert!(passed.available && !failed.available);
+        let verdict = Verdict {
+            heights: vec![passed, failed],
+        };
+        assert_eq!(verdict.exit_code(), 1);
+        assert!(verdict
+            .to_json()
+            .starts_with("{\"available\":false,\"heights\":[{\"height\":3,"));
+    }
+}
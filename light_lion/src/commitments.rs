@@ -0,0 +1,138 @@
+use square_reed_solomon::codec::commitment_from_bytes;
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::verifier::{digests_equal, verify_root_from_commitments};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::Commitment;
+use lion_roars::header::Header;
+use lion_roars::messages::LionMessages;
+use rs_merkle::Hasher;
+use std::io;
+
+/// Every row and column commitment of a square, in order
+pub struct SquareCommitments<E: Pairing> {
+    pub row_commitments: Vec<Commitment<E>>,
+    pub col_commitments: Vec<Commitment<E>>,
+}
+
+/// Request for every line commitment of the square at `height`
+pub fn commitments_request(height: u64) -> LionMessages {
+    LionMessages::CommitmentsRequest { height }
+}
+
+/// Commitments carried by a full node's reply to `commitments_request`, checked to
+/// recompute the data root of `header`. `None` if the node holds none.
+pub fn commitments_response<E: Pairing, H: Hasher>(
+    header: &Header,
+    reply: LionMessages,
+) -> io::Result<Option<SquareCommitments<E>>> {
+    match reply {
+        LionMessages::Commitments {
+            height,
+            root,
+            row_commitments,
+            col_commitments,
+        } => {
+            if height != header.height || !digests_equal(&root, &header.data_root) {
+                return Err(invalid_data(format!(
+                    "Commitments served for another square than height {}",
+                    header.height
+                )));
+            }
+            let decode = |list: &[Vec<u8>]| {
+                list.iter()
+                    .map(|bytes| commitment_from_bytes::<E>(bytes))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| invalid_data("Malformed commitment".to_string()))
+            };
+            let commitments = SquareCommitments {
+                row_commitments: decode(&row_commitments)?,
+                col_commitments: decode(&col_commitments)?,
+            };
+            let data_root = H::Hash::try_from(root)
+                .map_err(|_| invalid_data("Malformed data root".to_string()))?;
+            let version = SquareLayout::root_version_of(&header.layout)
+                .ok_or_else(|| invalid_data("Unknown root version".to_string()))?;
+            if !verify_root_from_commitments::<E, H>(
+                version,
+                data_root,
+                &commitments.row_commitments,
+                &commitments.col_commitments,
+            ) {
+                return Err(invalid_data(
+                    "Commitments do not recompute the data root".to_string(),
+                ));
+            }
+            Ok(Some(commitments))
+        }
+        LionMessages::CommitmentsUnavailable { .. } => Ok(None),
+        other => Err(invalid_data(format!(
+            "Unexpected reply to commitments request: {:?}",
+            other
+        ))),
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::commitments_response;
+    use square_reed_solomon::codec::commitment_to_bytes;
+    use square_reed_solomon::fixtures::counting_square;
+    use square_reed_solomon::prover::RsSquareProver;
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use lion_roars::header::Header;
+    use lion_roars::messages::LionMessages;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn served_commitments_must_recompute_the_root() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let header = Header {
+            height: 6,
+            data_root: prover.root().to_vec(),
+            vk_digest: vec![],
+            namespace_root: vec![],
+            parent: vec![],
+            layout: vec![],
+        };
+        let encode = |commitments: Vec<_>| -> Vec<Vec<u8>> {
+            commitments
+                .iter()
+                .map(commitment_to_bytes::<Bls12_381>)
+                .collect()
+        };
+        let reply = |row_commitments, col_commitments| LionMessages::Commitments {
+            height: 6,
+            root: header.data_root.clone(),
+            row_commitments,
+            col_commitments,
+        };
+        let (rows, cols) = (
+            encode(prover.commit_all_rows()),
+            encode(prover.commit_all_cols()),
+        );
+
+        let served =
+            commitments_response::<Bls12_381, Sha256>(&header, reply(rows.clone(), cols.clone()))
+                .unwrap()
+                .unwrap();
+        assert_eq!(served.row_commitments, prover.commit_all_rows());
+        assert_eq!(served.col_commitments, prover.commit_all_cols());
+
+        // rows swapped for columns no longer hash to the root
+        let swapped = reply(cols, rows);
+        assert!(commitments_response::<Bls12_381, Sha256>(&header, swapped).is_err());
+        let unavailable = LionMessages::CommitmentsUnavailable { height: 6 };
+        assert!(
+            commitments_response::<Bls12_381, Sha256>(&header, unavailable)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
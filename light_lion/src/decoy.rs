@@ -0,0 +1,76 @@
+use rand::Rng;
+use std::collections::HashSet;
+
+/// How many dummy cell requests accompany the real ones. Decoys are requested
+/// like real samples and their answers discarded, so an observer of the queries
+/// cannot tell which cells the node actually checks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecoyPolicy {
+    /// Average number of decoys sent with each real request
+    pub per_request: f64,
+    /// Most decoys sent in one sampling round
+    pub max_per_round: usize,
+}
+
+impl DecoyPolicy {
+    /// Decoy cells to send along with one real request, decrementing `remaining` by
+    /// their number. Decoys are distinct cells of the square of side `length` and
+    /// never one in `avoid`, so a repeated cell does not give away the real one.
+    pub fn draw<R: Rng>(
+        &self,
+        rng: &mut R,
+        length: usize,
+        avoid: &HashSet<(usize, usize)>,
+        remaining: &mut usize,
+    ) -> Vec<(usize, usize)> {
+        let per_request = self.per_request.max(0.0);
+        let mut count = per_request.floor() as usize;
+        if rng.gen_bool(per_request.fract()) {
+            count += 1;
+        }
+        let count = count
+            .min(*remaining)
+            .min((length * length).saturating_sub(avoid.len()));
+
+        let mut decoys = Vec::with_capacity(count);
+        while decoys.len() < count {
+            let cell = (rng.gen_range(0..length), rng.gen_range(0..length));
+            if !avoid.contains(&cell) && !decoys.contains(&cell) {
+                decoys.push(cell);
+            }
+        }
+        *remaining -= decoys.len();
+        decoys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DecoyPolicy;
+    use std::collections::HashSet;
+
+    #[test]
+    pub fn decoys_avoid_real_cells_and_respect_the_round_budget() {
+        let policy = DecoyPolicy {
+            per_request: 2.5,
+            max_per_round: 5,
+        };
+        let mut rng = rand::thread_rng();
+        let avoid: HashSet<(usize, usize)> = [(0, 0), (1, 1)].into_iter().collect();
+        let mut remaining = policy.max_per_round;
+
+        let first = policy.draw(&mut rng, 4, &avoid, &mut remaining);
+        assert!(first.len() == 2 || first.len() == 3);
+        assert!(first.iter().all(|cell| !avoid.contains(cell)));
+        let second = policy.draw(&mut rng, 4, &avoid, &mut remaining);
+        let third = policy.draw(&mut rng, 4, &avoid, &mut remaining);
+        assert_eq!(first.len() + second.len() + third.len(), 5);
+        assert_eq!(remaining, 0);
+
+        // a square with no cell left to spare gets no decoys
+        let mut remaining = 10;
+        let full: HashSet<(usize, usize)> = [(0, 0)].into_iter().collect();
+        assert!(policy.draw(&mut rng, 1, &full, &mut remaining).is_empty());
+    }
+}
@@ -1,19 +1,26 @@
-use ark_ec::pairing::Pairing;
-use square_reed_solomon::rs_square;
+use square_reed_solomon::verifier::verifier_key;
 
-use ark_poly::univariate::DensePolynomial;
-use ark_poly_commit::kzg10::{self, Powers, VerifierKey, KZG10};
-use rand::rngs::OsRng;
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10;
 
+pub mod backfill;
+pub mod blob;
+pub mod chain;
+pub mod cli;
+pub mod commitments;
+pub mod decoy;
 pub mod node;
+pub mod report;
+pub mod routing;
+pub mod sampling;
+pub mod stats;
+pub mod storage;
+pub mod store;
+pub mod verifier;
+
+use verifier::LightVerifier;
 
-pub fn setup<E: Pairing>(params: kzg10::UniversalParams<E>) {
-    let vk: ark_poly_commit::kzg10::VerifierKey<E> = VerifierKey {
-        g: params.powers_of_g[0],
-        gamma_g: params.powers_of_gamma_g[&0],
-        h: params.h,
-        beta_h: params.beta_h,
-        prepared_h: params.prepared_h.clone(),
-        prepared_beta_h: params.prepared_beta_h.clone(),
-    };
+/// Build the verifier a light node uses from the setup parameters
+pub fn setup<E: Pairing>(params: kzg10::UniversalParams<E>) -> LightVerifier<E> {
+    LightVerifier::new(verifier_key(&params))
 }
@@ -1,7 +1,668 @@
-use std::marker::PhantomData;
+use crate::backfill::{Backfill, BackfillPolicy};
+use crate::blob::{verify_blob_response, BlobError, BlobResponse};
+use crate::chain::{BranchStatus, ChainError, HeaderChain};
+use crate::decoy::DecoyPolicy;
+use crate::report::RoundReport;
+use crate::routing::RelayRouter;
+use crate::sampling::{confidence, vrf_cells, SamplingGoal};
+use crate::stats::{HeightStats, SampleOutcome, SamplingStats};
+use crate::store::{LightStore, SamplingRecord, StorageRecord};
+use crate::verifier::LightVerifier;
+
+use square_reed_solomon::beacon::BeaconRng;
+use square_reed_solomon::codec::{verifier_key_digest, verifier_key_from_bytes};
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::layout::BlobReceipt;
+use square_reed_solomon::namespace::NamespaceId;
+use square_reed_solomon::sample::{CellSample, LineSample};
+use square_reed_solomon::storage::{verify_storage, StorageProof};
+use square_reed_solomon::verifier::digests_equal;
+
 use ark_ec::pairing::Pairing;
-use ark_ff::PrimeField;
+use lion_roars::dht::{Axis, CustodyTable, PeerId};
+use lion_roars::equivocation::{EquivocationDetector, EquivocationProof};
+use lion_roars::header::{Header, SignedHeader};
+use lion_roars::noise::Capabilities;
+use lion_roars::scoring::{Misbehaviour, PeerScores};
+use lion_roars::vrf::{VrfKey, VrfProof};
+use rand::Rng;
+use rs_merkle::Hasher;
+use std::collections::HashSet;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplingMode {
+    /// Request individual cells, each with its own opening
+    Cells,
+    /// Request whole rows and columns, each with a single opening. On small
+    /// squares a few lines give both availability confidence and usable data.
+    Lines,
+}
+
+pub struct LightLionNode<E: Pairing> {
+    /// Peer that produced the blocks being sampled
+    producer: PeerId,
+    /// Mapping of line ranges to the peers storing them
+    custody: CustodyTable,
+    /// On-disk record of verified headers and sampling outcomes
+    store: Option<LightStore>,
+    /// Behaviour of the peers samples were requested from
+    scores: PeerScores<PeerId>,
+    /// Verifier for the setup in use, once a key has been loaded
+    verifier: Option<LightVerifier<E>>,
+    /// Digest of the loaded verifier key
+    setup_digest: Option<Vec<u8>>,
+    /// Keys of earlier setups replaced by `verifier`, still used for the squares
+    /// committed under them until retired, by digest
+    previous_verifiers: Vec<(Vec<u8>, LightVerifier<E>)>,
+    sampling_mode: SamplingMode,
+    /// Signed headers seen so far, checked for conflicting data roots
+    headers: EquivocationDetector,
+    /// Headers verified to extend the producer's chain; only these are sampled
+    chain: HeaderChain,
+    /// Outcomes of recent sampling rounds
+    stats: SamplingStats,
+    /// Mixed into beacon values so that other parties cannot predict this node's samples
+    sampling_secret: Vec<u8>,
+    /// Key proving which cells this node chose to sample, if it attests to its samples
+    vrf_key: Option<VrfKey>,
+    /// Relays sample requests are routed through, if routing is enabled
+    relays: Option<RelayRouter>,
+    /// Dummy requests mixed in with real samples, if any
+    decoys: Option<DecoyPolicy>,
+    /// Past heights still to be sampled after syncing headers
+    backfill: Option<Backfill>,
+}
+
+impl<E: Pairing> LightLionNode<E> {
+    pub fn new(producer: PeerId, custody: CustodyTable) -> Self {
+        Self {
+            producer,
+            custody,
+            store: None,
+            scores: PeerScores::new(-20, -100, Duration::from_secs(3600)),
+            verifier: None,
+            setup_digest: None,
+            previous_verifiers: vec![],
+            sampling_mode: SamplingMode::Cells,
+            headers: EquivocationDetector::new(),
+            chain: HeaderChain::new(None),
+            stats: SamplingStats::new(64),
+            sampling_secret: rand::thread_rng().gen::<[u8; 32]>().to_vec(),
+            vrf_key: None,
+            relays: None,
+            decoys: None,
+            backfill: None,
+        }
+    }
+
+    /// Persist to `store`, continuing the header chain from the latest header it holds
+    pub fn set_store(&mut self, store: LightStore) {
+        self.chain = HeaderChain::new(store.latest_header().cloned());
+        self.store = Some(store);
+    }
+
+    pub fn store_mut(&mut self) -> Option<&mut LightStore> {
+        self.store.as_mut()
+    }
+
+    /// Flush and release the persistent store. Every record is already synced as it is
+    /// written, so this only guards against a store left half-open by the caller.
+    pub fn shutdown(&mut self) -> std::io::Result<()> {
+        match self.store.take() {
+            Some(store) => store.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// First height that still needs to be verified and sampled. A node with a
+    /// persisted checkpoint resumes right after it rather than starting over.
+    pub fn resume_height(&self) -> u64 {
+        self.store
+            .as_ref()
+            .and_then(|store| store.checkpoint())
+            .map_or(0, |height| height + 1)
+    }
+
+    /// Adopt an encoded verifier key, caching it in the store so it is not fetched
+    /// again. The key it replaces is kept for the squares committed under it until
+    /// `retire_verifier_key`; only the latest key is cached.
+    pub fn set_verifier_key<H: Hasher>(&mut self, vk_bytes: &[u8]) -> io::Result<()> {
+        let vk = verifier_key_from_bytes::<E>(vk_bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if let Some(store) = &self.store {
+            store.record_verifier_key(vk_bytes)?;
+        }
+        let digest: Vec<u8> = verifier_key_digest::<E, H>(&vk).into();
+        self.previous_verifiers
+            .retain(|(previous, _)| *previous != digest);
+        if let (Some(previous), Some(verifier)) = (self.setup_digest.take(), self.verifier.take()) {
+            if previous != digest {
+                self.previous_verifiers.push((previous, verifier));
+            }
+        }
+        self.setup_digest = Some(digest);
+        self.verifier = Some(LightVerifier::new(vk));
+        Ok(())
+    }
+
+    /// Stop verifying squares committed under the earlier setup with `digest`, once
+    /// the migration away from it is over. Returns whether its key was still kept.
+    pub fn retire_verifier_key(&mut self, digest: &[u8]) -> bool {
+        let kept = self.previous_verifiers.len();
+        self.previous_verifiers
+            .retain(|(previous, _)| previous != digest);
+        self.previous_verifiers.len() != kept
+    }
+
+    /// Load the verifier key cached by an earlier run, returning whether one was found
+    pub fn load_verifier_key<H: Hasher>(&mut self) -> io::Result<bool> {
+        let cached = match &self.store {
+            Some(store) => store.verifier_key()?,
+            None => None,
+        };
+        match cached {
+            Some(vk_bytes) => self.set_verifier_key::<H>(&vk_bytes).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Replace the random secret drawn at startup, e.g. with one kept across restarts
+    pub fn set_sampling_secret(&mut self, secret: Vec<u8>) {
+        self.sampling_secret = secret;
+    }
+
+    /// Randomness for sampling `height`, derived from the beacon value published for
+    /// it and this node's secret. Pass it to `sample_until` so that cells are only
+    /// predictable once the beacon is out, and only by this node.
+    pub fn beacon_rng<H: Hasher>(&self, beacon: &[u8], height: u64) -> BeaconRng<H> {
+        BeaconRng::new(&self.sampling_secret, beacon, height)
+    }
+
+    pub fn set_vrf_key(&mut self, key: VrfKey) {
+        self.vrf_key = Some(key);
+    }
+
+    /// Public VRF key others check this node's sample selections against
+    pub fn vrf_public(&self) -> Option<[u8; 32]> {
+        self.vrf_key.as_ref().map(VrfKey::public)
+    }
+
+    /// Cells to sample under `header`, derived from the VRF key and the header, with
+    /// a proof that lets full nodes and aggregators confirm the selection. `None`
+    /// without a VRF key.
+    pub fn vrf_cells<H: Hasher>(
+        &self,
+        header: &Header,
+        length: usize,
+        count: usize,
+    ) -> Option<(Vec<(usize, usize)>, VrfProof)> {
+        let key = self.vrf_key.as_ref()?;
+        Some(vrf_cells::<H>(key, header, length, count))
+    }
+
+    /// Route every sample request through a relay, `None` to request directly
+    pub fn set_relays(&mut self, relays: Option<RelayRouter>) {
+        self.relays = relays;
+    }
+
+    /// Relay the next request to `target` should go through; `None` when routing is
+    /// off or no relay other than the target is known
+    pub fn relay_for(&mut self, target: SocketAddr) -> Option<SocketAddr> {
+        self.relays.as_mut()?.route(target)
+    }
+
+    /// Mix dummy cell requests into every sampling round, `None` for real ones only
+    pub fn set_decoys(&mut self, decoys: Option<DecoyPolicy>) {
+        self.decoys = decoys;
+    }
+
+    pub fn set_sampling_mode(&mut self, mode: SamplingMode) {
+        self.sampling_mode = mode;
+    }
+
+    pub fn sampling_mode(&self) -> SamplingMode {
+        self.sampling_mode
+    }
+
+    pub fn verifier(&self) -> Option<&LightVerifier<E>> {
+        self.verifier.as_ref()
+    }
+
+    pub fn setup_digest(&self) -> Option<&[u8]> {
+        self.setup_digest.as_deref()
+    }
+
+    /// What to announce in handshakes once a key is loaded. The verifier key alone
+    /// bounds no square size, so the caller picks the largest squares it will sample.
+    pub fn capabilities(&self, max_square_length: u64) -> Option<Capabilities> {
+        let digest = self.setup_digest.clone()?;
+        let previous = self
+            .previous_verifiers
+            .iter()
+            .map(|(digest, _)| digest.clone())
+            .collect();
+        Some(Capabilities::new(digest, max_square_length).with_previous_setups(previous))
+    }
+
+    /// Whether `header` was committed under a loaded setup. Headers for another
+    /// setup can never verify, so they are rejected before any sampling.
+    pub fn matches_setup(&self, header: &Header) -> bool {
+        self.verifier_for(header).is_some()
+    }
+
+    /// Verifier for the setup `header` was committed under, if its key is loaded
+    pub fn verifier_for(&self, header: &Header) -> Option<&LightVerifier<E>> {
+        let current = match (&self.setup_digest, &self.verifier) {
+            (Some(digest), Some(verifier)) => Some((digest, verifier)),
+            _ => None,
+        };
+        current
+            .into_iter()
+            .chain(
+                self.previous_verifiers
+                    .iter()
+                    .map(|(digest, verifier)| (digest, verifier)),
+            )
+            .find(|(digest, _)| digests_equal(digest, &header.vk_digest))
+            .map(|(_, verifier)| verifier)
+    }
+
+    /// Check a sample of the square announced by `header`. Fails without a loaded
+    /// key or when the header was committed under another setup.
+    pub fn verify_sample<H: Hasher>(
+        &self,
+        header: &Header,
+        length: usize,
+        sample: &CellSample<E, H>,
+    ) -> bool {
+        let (verifier, version) = match (
+            self.verifier_for(header),
+            SquareLayout::root_version_of(&header.layout),
+        ) {
+            (Some(verifier), Some(version)) => (verifier, version),
+            _ => return false,
+        };
+        match <H::Hash as TryFrom<Vec<u8>>>::try_from(header.data_root.clone()) {
+            Ok(root) => verifier.verify_sample::<H>(version, root, length, sample),
+            Err(_) => false,
+        }
+    }
+
+    /// Line counterpart of `verify_sample`
+    pub fn verify_line_sample<H: Hasher>(
+        &self,
+        header: &Header,
+        length: usize,
+        sample: &LineSample<E, H>,
+    ) -> bool {
+        let (verifier, version) = match (
+            self.verifier_for(header),
+            SquareLayout::root_version_of(&header.layout),
+        ) {
+            (Some(verifier), Some(version)) => (verifier, version),
+            _ => return false,
+        };
+        match <H::Hash as TryFrom<Vec<u8>>>::try_from(header.data_root.clone()) {
+            Ok(root) => verifier.verify_line::<H>(header.height, version, root, length, sample),
+            Err(_) => false,
+        }
+    }
+
+    /// Check a signed header against those seen before. When its producer signed a
+    /// different data root for the same height the proof is returned and kept as
+    /// evidence in the store.
+    pub fn observe_header(
+        &mut self,
+        signed: SignedHeader,
+    ) -> io::Result<Option<EquivocationProof>> {
+        let proof = match self.headers.observe(signed) {
+            Some(proof) => proof,
+            None => return Ok(None),
+        };
+        if let Some(store) = &mut self.store {
+            store.record_evidence(proof.height(), &proof.to_bytes())?;
+        }
+        Ok(Some(proof))
+    }
+
+    /// Verify that `signed` is signed by the producer and extends the verified chain
+    /// by one height, keeping it in the store if it becomes the tip. A header
+    /// conflicting with the tip is kept as a competing branch to be sampled. Headers
+    /// that fail are never sampled.
+    pub fn accept_header(&mut self, signed: &SignedHeader) -> io::Result<Result<(), ChainError>> {
+        match self.chain.extend(&self.producer, signed) {
+            Ok(true) => self.record_preferred(&signed.header)?,
+            Ok(false) => {}
+            Err(e) => return Ok(Err(e)),
+        }
+        Ok(Ok(()))
+    }
+
+    /// Latest header verified to extend the producer's chain
+    pub fn chain_tip(&self) -> Option<&Header> {
+        self.chain.tip()
+    }
+
+    /// Header the chain follows at `height`. When conflicting headers exist there,
+    /// the one whose data passed sampling is preferred and those with unavailable
+    /// or fraud-proven data are quarantined.
+    pub fn preferred_header(&self, height: u64) -> Option<&Header> {
+        self.chain.preferred(height)
+    }
+
+    /// Conflicting headers at `height` and what is known of their data, empty if
+    /// the height is not contested
+    pub fn branches(&self, height: u64) -> &[(Header, BranchStatus)] {
+        self.chain.branches(height)
+    }
+
+    /// Quarantine `header` once a fraud proof against its square has been verified,
+    /// moving the chain to a competing header if one is left
+    pub fn report_fraud(&mut self, header: &Header) -> io::Result<()> {
+        match self.chain.record_fraud(header) {
+            Some(preferred) => self.record_preferred(&preferred),
+            None => Ok(()),
+        }
+    }
+
+    fn record_preferred(&mut self, header: &Header) -> io::Result<()> {
+        match &mut self.store {
+            Some(store) => store.record_header(header),
+            None => Ok(()),
+        }
+    }
+
+    /// Record how a peer answered a sample request
+    pub fn record_response(&mut self, peer: &PeerId, misbehaviour: Option<Misbehaviour>) {
+        match misbehaviour {
+            Some(misbehaviour) => self.scores.record(peer, misbehaviour),
+            None => self.scores.record_good(peer),
+        }
+    }
+
+    /// Record the outcome of one sample request at `height`, both in the per-height
+    /// statistics and in the answering peer's score
+    pub fn record_sample(
+        &mut self,
+        height: u64,
+        peer: &PeerId,
+        latency: Duration,
+        outcome: SampleOutcome,
+    ) {
+        self.stats.record(height, *peer, latency, outcome);
+        let misbehaviour = match outcome {
+            SampleOutcome::Verified => None,
+            SampleOutcome::Failed => Some(Misbehaviour::InvalidProof),
+            SampleOutcome::Unavailable => Some(Misbehaviour::Unavailable),
+        };
+        self.record_response(peer, misbehaviour);
+    }
+
+    /// Sample fresh random cells of the square announced by `header` until passing
+    /// them all gives the goal's confidence of availability against its adversary
+    /// model, or its budget of requests is spent. `fetch` asks the ranked peers for a cell
+    /// and returns who answered and with what. Stops at the first cell that cannot be
+    /// fetched or verified; the round is then recorded as failed. With a decoy policy
+    /// set, decoy cells are fetched around each real one and their answers ignored.
+    /// Fails without requesting anything unless `accept_header` verified `header`.
+    pub fn sample_until<H, R, F>(
+        &mut self,
+        rng: &mut R,
+        header: &Header,
+        goal: SamplingGoal,
+        mut fetch: F,
+    ) -> io::Result<SamplingRecord>
+    where
+        H: Hasher,
+        R: Rng,
+        F: FnMut(&[PeerId], usize, usize) -> Option<(PeerId, Option<CellSample<E, H>>)>,
+    {
+        if !self.chain.is_verified(header) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Header at height {} does not extend the verified chain",
+                    header.height
+                ),
+            ));
+        }
+        let length = goal.n * goal.scale;
+        let mut drawn = HashSet::new();
+        let mut decoys_left = self.decoys.map_or(0, |policy| policy.max_per_round);
+        let mut record = SamplingRecord {
+            height: header.height,
+            attempted: 0,
+            verified: 0,
+        };
+        while record.attempted < goal.budget.min(length * length)
+            && confidence(goal.n, goal.scale, record.verified, goal.model) < goal.confidence
+        {
+            let cell = (rng.gen_range(0..length), rng.gen_range(0..length));
+            if !drawn.insert(cell) {
+                continue;
+            }
+            let decoys = match self.decoys {
+                Some(policy) => policy.draw(rng, length, &drawn, &mut decoys_left),
+                None => vec![],
+            };
+            let (before, after) = decoys.split_at(rng.gen_range(0..=decoys.len()));
+            for &(rid, cid) in before {
+                fetch(&self.sample_targets(header.height, rid, cid), rid, cid);
+            }
+            let targets = self.sample_targets(header.height, cell.0, cell.1);
+            let start = Instant::now();
+            let reply = fetch(&targets, cell.0, cell.1);
+            let latency = start.elapsed();
+            record.attempted += 1;
+            for &(rid, cid) in after {
+                fetch(&self.sample_targets(header.height, rid, cid), rid, cid);
+            }
+
+            let (peer, outcome) = match reply {
+                Some((peer, Some(sample)))
+                    if (sample.rid, sample.cid) == cell
+                        && self.verify_sample::<H>(header, length, &sample) =>
+                {
+                    (peer, SampleOutcome::Verified)
+                }
+                Some((peer, Some(_))) => (peer, SampleOutcome::Failed),
+                Some((peer, None)) => (peer, SampleOutcome::Unavailable),
+                None => (targets[0], SampleOutcome::Unavailable),
+            };
+            self.record_sample(header.height, &peer, latency, outcome);
+            if outcome != SampleOutcome::Verified {
+                break;
+            }
+            record.verified += 1;
+        }
+        if let Some(store) = &mut self.store {
+            store.record_sampling(record)?;
+        }
+        if let Some(preferred) = self.chain.record_availability(header, record.succeeded()) {
+            self.record_preferred(&preferred)?;
+        }
+        Ok(record)
+    }
+
+    /// Fetch the blob `receipt` names under `namespace` in the square announced by
+    /// `header`, checking every share against the data root and the namespace's
+    /// range. `fetch` asks the ranked peers for the blob and returns who answered
+    /// and with what. Peers serving proofs that fail to verify are penalized and
+    /// the remaining ones asked in turn.
+    pub fn get_blob<H, F>(
+        &mut self,
+        header: &Header,
+        namespace: NamespaceId,
+        receipt: &BlobReceipt,
+        mut fetch: F,
+    ) -> Result<Vec<u8>, BlobError>
+    where
+        H: Hasher,
+        F: FnMut(&[PeerId]) -> Option<(PeerId, Option<BlobResponse<E, H>>)>,
+    {
+        if self.verifier_for(header).is_none() {
+            return Err(BlobError::UnknownSetup);
+        }
+        if receipt.height != header.height
+            || receipt.placement.namespace != namespace
+            || !digests_equal(&receipt.commitment, &header.data_root)
+        {
+            return Err(BlobError::ReceiptMismatch);
+        }
+        let first_row = receipt.cells().first().map_or(0, |(rid, _)| *rid);
+        let mut targets = self.line_targets(header.height, Axis::Row, first_row);
+        let mut error = BlobError::Unavailable;
+        for _ in 0..targets.len() {
+            let (peer, response) = match fetch(&targets) {
+                Some(reply) => reply,
+                None => break,
+            };
+            targets.retain(|target| *target != peer);
+            let verified = response.map(|response| {
+                let verifier = self.verifier_for(header).expect("Verifier checked above");
+                verify_blob_response(verifier.vk(), header, receipt, &response)
+            });
+            match verified {
+                Some(Some(blob)) => {
+                    self.record_response(&peer, None);
+                    return Ok(blob);
+                }
+                Some(None) => {
+                    self.record_response(&peer, Some(Misbehaviour::InvalidProof));
+                    error = BlobError::InvalidProof;
+                }
+                None => self.record_response(&peer, Some(Misbehaviour::Unavailable)),
+            }
+            if targets.is_empty() {
+                break;
+            }
+        }
+        Err(error)
+    }
+
+    /// Check `peer`'s answer to the storage challenge for the square announced by
+    /// `header`, of side `length`, in the epoch anchored at `anchor`. A missing or
+    /// invalid proof counts against the peer, and every outcome is kept in the
+    /// store. Returns whether the proof verified.
+    pub fn check_storage_proof<H: Hasher>(
+        &mut self,
+        peer: &PeerId,
+        header: &Header,
+        anchor: &Header,
+        length: usize,
+        proof: Option<&StorageProof<E, H>>,
+    ) -> io::Result<bool> {
+        let verifier = match self.verifier_for(header) {
+            Some(verifier) => verifier,
+            None => return Ok(false),
+        };
+        let version = SquareLayout::root_version_of(&header.layout);
+        let verified = proof.map(|proof| {
+            proof.height == header.height
+                && proof.anchor_height == anchor.height
+                && match (
+                    version,
+                    <H::Hash as TryFrom<Vec<u8>>>::try_from(header.data_root.clone()),
+                ) {
+                    (Some(version), Ok(root)) => verify_storage(
+                        verifier.vk(),
+                        version,
+                        root,
+                        length,
+                        &anchor.data_root,
+                        &peer.0,
+                        proof,
+                    ),
+                    _ => false,
+                }
+        });
+        let misbehaviour = match verified {
+            Some(true) => None,
+            Some(false) => Some(Misbehaviour::InvalidProof),
+            None => Some(Misbehaviour::Unavailable),
+        };
+        self.record_response(peer, misbehaviour);
+        if let Some(store) = &mut self.store {
+            store.record_storage(StorageRecord {
+                peer: *peer,
+                height: header.height,
+                anchor_height: anchor.height,
+                verified: verified == Some(true),
+            })?;
+        }
+        Ok(verified == Some(true))
+    }
+
+    /// Start sampling the heights below `tip`, once headers up to it are synced.
+    /// Replaces any backfill still in progress.
+    pub fn start_backfill(&mut self, policy: BackfillPolicy, tip: u64) {
+        self.backfill = Some(Backfill::new(policy, tip));
+    }
+
+    /// Next past height to sample, most recent first, skipping heights the store
+    /// already records a successful round for. `None` while the backfill is rate
+    /// limited, finished or was never started.
+    pub fn next_backfill_height(&mut self, now: Instant) -> Option<u64> {
+        let store = self.store.as_ref();
+        let height = self.backfill.as_mut()?.next_height(now, |height| {
+            store
+                .and_then(|store| store.sampling(height))
+                .map_or(false, |record| record.succeeded())
+        });
+        if self.backfill.as_ref().map_or(false, Backfill::is_finished) {
+            self.backfill = None;
+        }
+        height
+    }
+
+    /// Sampling statistics of `height`, if it is among the recent heights sampled
+    pub fn sampling_stats(&self, height: u64) -> Option<&HeightStats> {
+        self.stats.height(height)
+    }
+
+    /// Report of the round that sampled the square announced by `header` toward
+    /// `goal`, if its height is among the recent heights sampled
+    pub fn round_report(&self, header: &Header, goal: SamplingGoal) -> Option<RoundReport> {
+        self.stats
+            .height(header.height)
+            .map(|stats| RoundReport::new(header, goal, stats))
+    }
+
+    /// Peer scores and sampling statistics in Prometheus text format
+    pub fn metrics(&self) -> String {
+        let mut out = self.scores.metrics("light_lion");
+        out.push_str(&self.stats.metrics("light_lion"));
+        out
+    }
+
+    pub fn custody_mut(&mut self) -> &mut CustodyTable {
+        &mut self.custody
+    }
+
+    /// Peers to which a sample request for cell (rid, cid) at `height` should be sent,
+    /// in order of preference. Banned peers are skipped and poorly scored ones tried last.
+    /// Falls back to the block producer when no custodians are usable.
+    pub fn sample_targets(&self, height: u64, rid: usize, cid: usize) -> Vec<PeerId> {
+        let mut targets = self
+            .scores
+            .rank(self.custody.custodians_of_cell(height, rid, cid));
+        if targets.is_empty() {
+            targets.push(self.producer);
+        }
+        targets
+    }
 
-pub struct LightLionNode<E:  Pairing> {
-    _pairing_phantom : PhantomData<E>,
-}
\ No newline at end of file
+    /// Peers to which a request for line `idx` along `axis` at `height` should be sent,
+    /// ranked like `sample_targets`
+    pub fn line_targets(&self, height: u64, axis: Axis, idx: usize) -> Vec<PeerId> {
+        let range = self.custody.range_of(height, axis, idx);
+        let mut targets = self.scores.rank(self.custody.custodians(&range));
+        if targets.is_empty() {
+            targets.push(self.producer);
+        }
+        targets
+    }
+}
@@ -0,0 +1,119 @@
+use crate::sampling::{confidence, SamplingGoal};
+use crate::stats::{HeightStats, SampleOutcome};
+
+use lion_roars::dht::PeerId;
+use lion_roars::header::Header;
+
+/// Version of the report's JSON schema, bumped whenever a field changes meaning
+/// or is removed
+pub const REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Result of one sampling round, for monitoring pipelines and bridges to consume
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundReport {
+    pub height: u64,
+    pub data_root: Vec<u8>,
+    /// Number of cells requested
+    pub samples: usize,
+    /// Number of cells whose proofs verified
+    pub verified: usize,
+    /// Confidence of availability the verified samples give
+    pub confidence: f64,
+    pub peers: Vec<PeerId>,
+    /// Peers whose sample failed or was unavailable, in request order
+    pub failures: Vec<(PeerId, SampleOutcome)>,
+}
+
+impl RoundReport {
+    /// Report of the round sampling the square announced by `header` toward `goal`
+    pub fn new(header: &Header, goal: SamplingGoal, stats: &HeightStats) -> Self {
+        Self {
+            height: header.height,
+            data_root: header.data_root.clone(),
+            samples: stats.attempted,
+            verified: stats.verified,
+            confidence: confidence(goal.n, goal.scale, stats.verified, goal.model),
+            peers: stats.peers.iter().copied().collect(),
+            failures: stats.failures.clone(),
+        }
+    }
+
+    /// The report as one line of JSON. Roots and peers are hex encoded.
+    pub fn to_json(&self) -> String {
+        let peers: Vec<String> = self
+            .peers
+            .iter()
+            .map(|peer| format!("\"{}\"", hex::encode(peer.0)))
+            .collect();
+        let failures: Vec<String> = self
+            .failures
+            .iter()
+            .map(|(peer, outcome)| {
+                let reason = match outcome {
+                    SampleOutcome::Verified => "verified",
+                    SampleOutcome::Failed => "invalid_proof",
+                    SampleOutcome::Unavailable => "unavailable",
+                };
+                format!(
+                    "{{\"peer\":\"{}\",\"reason\":\"{}\"}}",
+                    hex::encode(peer.0),
+                    reason
+                )
+            })
+            .collect();
+        format!(
+            "{{\"schema_version\":{},\"height\":{},\"data_root\":\"{}\",\"samples\":{},\
+             \"verified\":{},\"confidence\":{:.6},\"peers\":[{}],\"failures\":[{}]}}",
+            REPORT_SCHEMA_VERSION,
+            self.height,
+            hex::encode(&self.data_root),
+            self.samples,
+            self.verified,
+            self.confidence,
+            peers.join(","),
+            failures.join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoundReport;
+    use crate::sampling::{AdversaryModel, SamplingGoal};
+    use crate::stats::{SampleOutcome, SamplingStats};
+
+    use lion_roars::dht::PeerId;
+    use lion_roars::header::Header;
+    use std::time::Duration;
+
+    #[test]
+    pub fn reports_list_peers_and_failures() {
+        let mut stats = SamplingStats::new(1);
+        stats.record(3, PeerId([1; 32]), Duration::ZERO, SampleOutcome::Verified);
+        stats.record(3, PeerId([2; 32]), Duration::ZERO, SampleOutcome::Failed);
+        let header = Header {
+            height: 3,
+            data_root: vec![0xab; 2],
+            vk_digest: vec![],
+            namespace_root: vec![],
+            parent: vec![],
+            layout: vec![],
+        };
+        let goal = SamplingGoal {
+            n: 2,
+            scale: 2,
+            model: AdversaryModel::Unrecoverable,
+            confidence: 0.99,
+            budget: 8,
+        };
+
+        let report = RoundReport::new(&header, goal, stats.height(3).unwrap());
+        assert_eq!((report.samples, report.verified), (2, 1));
+        let json = report.to_json();
+        assert!(json.starts_with("{\"schema_version\":1,\"height\":3,\"data_root\":\"abab\""));
+        assert!(json.ends_with(&format!(
+            "\"failures\":[{{\"peer\":\"{}\",\"reason\":\"invalid_proof\"}}]}}",
+            "02".repeat(32)
+        )));
+    }
+}
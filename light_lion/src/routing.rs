@@ -0,0 +1,103 @@
+use lion_roars::messages::LionMessages;
+
+use rand::Rng;
+use std::io;
+use std::net::SocketAddr;
+
+/// Spreads sample requests over relay peers, each request going through the next
+/// relay in turn, so that no single full node sees every cell a light node checks
+/// nor can tell which of its targets' requests came from this node.
+#[derive(Clone, Debug)]
+pub struct RelayRouter {
+    relays: Vec<SocketAddr>,
+    /// Relay the next request is tried through first
+    next: usize,
+}
+
+impl RelayRouter {
+    /// Start at a random relay so that restarts do not replay the same order
+    pub fn new<R: Rng>(relays: Vec<SocketAddr>, rng: &mut R) -> Self {
+        let next = if relays.is_empty() {
+            0
+        } else {
+            rng.gen_range(0..relays.len())
+        };
+        Self { relays, next }
+    }
+
+    pub fn relays(&self) -> &[SocketAddr] {
+        &self.relays
+    }
+
+    /// Relay for the next request to `target`, never the target itself. `None`
+    /// if there is no relay other than the target.
+    pub fn route(&mut self, target: SocketAddr) -> Option<SocketAddr> {
+        for _ in 0..self.relays.len() {
+            let relay = self.relays[self.next];
+            self.next = (self.next + 1) % self.relays.len();
+            if relay != target {
+                return Some(relay);
+            }
+        }
+        None
+    }
+}
+
+/// Wrap `request` for a relay to forward to `target`
+pub fn relayed(target: SocketAddr, request: &LionMessages) -> LionMessages {
+    LionMessages::RelayRequest {
+        target: target.to_string(),
+        request: request.encode(),
+    }
+}
+
+/// The target's reply carried by a relay's answer, `None` if the relay could not
+/// forward the request
+pub fn unwrap_relayed(reply: LionMessages) -> io::Result<Option<LionMessages>> {
+    match reply {
+        LionMessages::RelayResponse { response } => LionMessages::decode(&response).map(Some),
+        LionMessages::RelayUnavailable { .. } => Ok(None),
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected reply to relay request: {:?}", other),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relayed, unwrap_relayed, RelayRouter};
+    use lion_roars::messages::LionMessages;
+
+    use std::net::SocketAddr;
+
+    #[test]
+    pub fn consecutive_requests_use_different_relays() {
+        let addrs: Vec<SocketAddr> = (0..3)
+            .map(|port| SocketAddr::from(([127, 0, 0, 1], 4000 + port)))
+            .collect();
+        let mut router = RelayRouter::new(addrs.clone(), &mut rand::thread_rng());
+        let target = addrs[0];
+        let first = router.route(target).unwrap();
+        let second = router.route(target).unwrap();
+        assert!(first != target && second != target && first != second);
+        assert_eq!(
+            RelayRouter::new(vec![target], &mut rand::thread_rng()).route(target),
+            None
+        );
+
+        let request = LionMessages::SampleRequest {
+            height: 1,
+            rid: 2,
+            cid: 3,
+        };
+        let forwarded = match relayed(target, &request) {
+            LionMessages::RelayRequest { request, .. } => request,
+            other => panic!("Unexpected message {:?}", other),
+        };
+        let reply = LionMessages::RelayResponse {
+            response: forwarded,
+        };
+        assert_eq!(unwrap_relayed(reply).unwrap(), Some(request));
+    }
+}
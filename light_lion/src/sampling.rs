@@ -0,0 +1,266 @@
+use square_reed_solomon::beacon::beacon_cells;
+use square_reed_solomon::namespace::{NamespaceId, NamespaceRange};
+
+use lion_roars::header::Header;
+use lion_roars::vrf::{VrfKey, VrfProof};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use rs_merkle::Hasher;
+use std::collections::{HashMap, HashSet};
+
+/// What an adversary withholding cells of an extended square is assumed to do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdversaryModel {
+    /// Withholds the fewest cells that still prevent reconstruction: a
+    /// (length - n + 1) x (length - n + 1) block
+    Unrecoverable,
+    /// Withholds the given fraction of all cells
+    Fraction(f64),
+}
+
+/// When a `sample_until` round may stop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SamplingGoal {
+    /// Side of the original square
+    pub n: usize,
+    pub scale: usize,
+    pub model: AdversaryModel,
+    /// Confidence of availability to reach before stopping
+    pub confidence: f64,
+    /// Most sample requests to make
+    pub budget: usize,
+}
+
+/// Number of cells of the extended square the adversary withholds
+pub fn withheld_cells(n: usize, scale: usize, model: AdversaryModel) -> usize {
+    let length = n * scale;
+    match model {
+        AdversaryModel::Unrecoverable => (length - n + 1).pow(2),
+        AdversaryModel::Fraction(fraction) => {
+            ((length * length) as f64 * fraction.clamp(0.0, 1.0)).ceil() as usize
+        }
+    }
+}
+
+/// Probability that `samples` distinct uniformly drawn cells would have hit at
+/// least one withheld cell, i.e. that a square passing them all is available
+pub fn confidence(n: usize, scale: usize, samples: usize, model: AdversaryModel) -> f64 {
+    let total = (n * scale).pow(2);
+    let withheld = withheld_cells(n, scale, model).min(total);
+    if withheld == 0 {
+        return 0.0;
+    }
+    // drawing without replacement, every miss makes the next one less likely
+    let mut all_miss = 1.0;
+    for idx in 0..samples.min(total) {
+        if total - idx <= withheld {
+            return 1.0;
+        }
+        all_miss *= (total - withheld - idx) as f64 / (total - idx) as f64;
+    }
+    1.0 - all_miss
+}
+
+/// The `count` cells the holder of `key` samples in the square of side `length`
+/// announced by `header`, with a proof that they were not chosen freely
+pub fn vrf_cells<H: Hasher>(
+    key: &VrfKey,
+    header: &Header,
+    length: usize,
+    count: usize,
+) -> (Vec<(usize, usize)>, VrfProof) {
+    let (output, proof) = key.prove(&header.to_bytes());
+    (
+        beacon_cells::<H>(&output, &header.data_root, header.height, length, count),
+        proof,
+    )
+}
+
+/// The cells `proof` commits the node with VRF key `public` to sampling under
+/// `header`, or `None` if the proof is not valid for them
+pub fn verify_vrf_cells<H: Hasher>(
+    public: &[u8; 32],
+    header: &Header,
+    length: usize,
+    count: usize,
+    proof: &VrfProof,
+) -> Option<Vec<(usize, usize)>> {
+    let output = proof.verify(public, &header.to_bytes())?;
+    Some(beacon_cells::<H>(
+        &output,
+        &header.data_root,
+        header.height,
+        length,
+        count,
+    ))
+}
+
+/// Draws sample coordinates biased toward the namespaces a node cares about,
+/// while always keeping some uniform samples so availability of the whole
+/// square is still checked.
+#[derive(Clone, Debug)]
+pub struct NamespaceWeightedSampler {
+    /// Relative weight of each namespace of interest
+    weights: HashMap<NamespaceId, u32>,
+    /// Number of samples drawn uniformly from the whole extended square
+    uniform: usize,
+}
+
+impl NamespaceWeightedSampler {
+    pub fn new(uniform: usize) -> Self {
+        Self {
+            weights: HashMap::new(),
+            uniform,
+        }
+    }
+
+    /// Weight of `namespace` relative to the other namespaces of interest; zero removes it
+    pub fn set_weight(&mut self, namespace: NamespaceId, weight: u32) {
+        if weight == 0 {
+            self.weights.remove(&namespace);
+        } else {
+            self.weights.insert(namespace, weight);
+        }
+    }
+
+    /// Draw `count` distinct cells of the square with original side `n` extended by
+    /// `scale`. `ranges` is the namespace layout of the block; namespaces of interest
+    /// absent from it get no samples and their share goes to uniform sampling.
+    pub fn draw<R: Rng>(
+        &self,
+        rng: &mut R,
+        n: usize,
+        scale: usize,
+        ranges: &[NamespaceRange],
+        count: usize,
+    ) -> Vec<(usize, usize)> {
+        let length = n * scale;
+        let count = count.min(length * length);
+        let mut cells = HashSet::with_capacity(count);
+        let mut drawn = Vec::with_capacity(count);
+        let mut push = |cell: (usize, usize), drawn: &mut Vec<(usize, usize)>| {
+            if cells.insert(cell) {
+                drawn.push(cell);
+            }
+        };
+
+        while drawn.len() < self.uniform.min(count) {
+            push(
+                (rng.gen_range(0..length), rng.gen_range(0..length)),
+                &mut drawn,
+            );
+        }
+
+        let targets: Vec<(&NamespaceRange, u32)> = ranges
+            .iter()
+            .filter(|range| !range.is_empty())
+            .filter_map(|range| {
+                self.weights
+                    .get(&range.namespace)
+                    .map(|weight| (range, *weight))
+            })
+            .collect();
+        if let Ok(choose) = WeightedIndex::new(targets.iter().map(|(_, weight)| *weight)) {
+            let capacity: usize = targets.iter().map(|(range, _)| range.len()).sum();
+            let wanted = (drawn.len() + capacity).min(count);
+            // every draw may repeat a cell, so bound the attempts rather than loop forever
+            let mut attempts = 0;
+            while drawn.len() < wanted && attempts < 8 * count {
+                let range = targets[choose.sample(rng)].0;
+                push(
+                    range.cell(rng.gen_range(0..range.len()), n, scale),
+                    &mut drawn,
+                );
+                attempts += 1;
+            }
+        }
+
+        while drawn.len() < count {
+            push(
+                (rng.gen_range(0..length), rng.gen_range(0..length)),
+                &mut drawn,
+            );
+        }
+        drawn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        confidence, verify_vrf_cells, vrf_cells, withheld_cells, AdversaryModel,
+        NamespaceWeightedSampler,
+    };
+    use square_reed_solomon::namespace::{NamespaceId, NamespaceRange};
+
+    use lion_roars::header::Header;
+    use lion_roars::vrf::VrfKey;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn samples_favour_weighted_namespaces() {
+        let ours = NamespaceId([1; 8]);
+        let theirs = NamespaceId([2; 8]);
+        let ranges = [
+            NamespaceRange {
+                namespace: ours,
+                start: 0,
+                end: 16,
+            },
+            NamespaceRange {
+                namespace: theirs,
+                start: 16,
+                end: 64,
+            },
+        ];
+        let mut sampler = NamespaceWeightedSampler::new(4);
+        sampler.set_weight(ours, 1);
+
+        let cells = sampler.draw(&mut rand::thread_rng(), 8, 2, &ranges, 12);
+        assert_eq!(cells.len(), 12);
+        // shares 0..16 are the first two original rows, at extended rows 0 and 2
+        let in_ours = cells
+            .iter()
+            .filter(|(rid, cid)| *rid <= 2 && rid % 2 == 0 && cid % 2 == 0)
+            .count();
+        assert!(in_ours >= 8);
+    }
+
+    #[test]
+    pub fn confidence_grows_with_samples() {
+        // with scale 2 at least a quarter of the square must be withheld
+        assert_eq!(withheld_cells(8, 2, AdversaryModel::Unrecoverable), 81);
+        let few = confidence(8, 2, 4, AdversaryModel::Unrecoverable);
+        let many = confidence(8, 2, 16, AdversaryModel::Unrecoverable);
+        assert!(few > 1.0 - 0.75f64.powi(4) && few < many && many < 1.0);
+        assert_eq!(confidence(8, 2, 256, AdversaryModel::Fraction(0.5)), 1.0);
+        assert_eq!(confidence(8, 2, 16, AdversaryModel::Fraction(0.0)), 0.0);
+    }
+
+    #[test]
+    pub fn vrf_cells_are_checkable_by_others() {
+        let key = VrfKey::from_bytes(&[3; 32]);
+        let header = Header {
+            height: 9,
+            data_root: vec![1; 32],
+            vk_digest: vec![2; 32],
+            namespace_root: vec![],
+            parent: vec![],
+            layout: vec![],
+        };
+        let (cells, proof) = vrf_cells::<Sha256>(&key, &header, 8, 6);
+        assert_eq!(cells.len(), 6);
+        assert_eq!(
+            verify_vrf_cells::<Sha256>(&key.public(), &header, 8, 6, &proof),
+            Some(cells)
+        );
+        let other = Header {
+            height: 10,
+            ..header.clone()
+        };
+        assert_eq!(
+            verify_vrf_cells::<Sha256>(&key.public(), &other, 8, 6, &proof),
+            None
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use lion_roars::dht::PeerId;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+/// How a single sample request ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleOutcome {
+    /// The peer answered and the proof verified
+    Verified,
+    /// The peer answered with a proof that did not verify
+    Failed,
+    /// The peer could not serve the sample or never answered
+    Unavailable,
+}
+
+/// Sampling outcomes observed at one height
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeightStats {
+    pub attempted: usize,
+    pub verified: usize,
+    pub failed: usize,
+    pub unavailable: usize,
+    /// Time to answer of every request that got an answer, in arrival order
+    latencies: Vec<Duration>,
+    /// Peers asked for at least one sample
+    pub peers: BTreeSet<PeerId>,
+    /// Peers whose sample failed or was unavailable, in request order
+    pub failures: Vec<(PeerId, SampleOutcome)>,
+}
+
+impl HeightStats {
+    /// Nearest-rank latency percentile of answered requests, `pct` in `0.0..=100.0`
+    pub fn latency_percentile(&self, pct: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+}
+
+/// Per-height sampling statistics for the most recent `retain` heights, so a
+/// degrading network shows up before availability actually fails
+pub struct SamplingStats {
+    heights: BTreeMap<u64, HeightStats>,
+    retain: usize,
+}
+
+impl SamplingStats {
+    pub fn new(retain: usize) -> Self {
+        assert!(retain > 0, "Must retain statistics for at least one height");
+        Self {
+            heights: BTreeMap::new(),
+            retain,
+        }
+    }
+
+    /// Record one sample request at `height` sent to `peer`. `latency` is ignored
+    /// for unavailable samples.
+    pub fn record(&mut self, height: u64, peer: PeerId, latency: Duration, outcome: SampleOutcome) {
+        let stats = self.heights.entry(height).or_default();
+        stats.attempted += 1;
+        stats.peers.insert(peer);
+        match outcome {
+            SampleOutcome::Verified => stats.verified += 1,
+            SampleOutcome::Failed => stats.failed += 1,
+            SampleOutcome::Unavailable => stats.unavailable += 1,
+        }
+        if outcome != SampleOutcome::Verified {
+            stats.failures.push((peer, outcome));
+        }
+        if outcome != SampleOutcome::Unavailable {
+            stats.latencies.push(latency);
+        }
+        while self.heights.len() > self.retain {
+            self.heights.pop_first();
+        }
+    }
+
+    pub fn height(&self, height: u64) -> Option<&HeightStats> {
+        self.heights.get(&height)
+    }
+
+    pub fn heights(&self) -> impl Iterator<Item = (&u64, &HeightStats)> {
+        self.heights.iter()
+    }
+
+    /// Statistics in Prometheus text exposition format, one series per retained height
+    pub fn metrics(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        for (height, stats) in self.heights.iter() {
+            out.push_str(&format!(
+                "{prefix}_samples_attempted{{height=\"{height}\"}} {}\n\
+                 {prefix}_samples_verified{{height=\"{height}\"}} {}\n\
+                 {prefix}_samples_failed{{height=\"{height}\"}} {}\n\
+                 {prefix}_samples_unavailable{{height=\"{height}\"}} {}\n\
+                 {prefix}_sampling_peers{{height=\"{height}\"}} {}\n",
+                stats.attempted,
+                stats.verified,
+                stats.failed,
+                stats.unavailable,
+                stats.peers.len(),
+            ));
+            for (quantile, pct) in [("0.5", 50.0), ("0.9", 90.0), ("0.99", 99.0)] {
+                if let Some(latency) = stats.latency_percentile(pct) {
+                    out.push_str(&format!(
+                        "{prefix}_sample_latency_seconds{{height=\"{height}\",quantile=\"{quantile}\"}} {}\n",
+                        latency.as_secs_f64()
+                    ));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SampleOutcome, SamplingStats};
+    use lion_roars::dht::PeerId;
+    use std::time::Duration;
+
+    #[test]
+    pub fn stats_track_outcomes_and_latency() {
+        let mut stats = SamplingStats::new(2);
+        for ms in 1..=10 {
+            let outcome = if ms == 10 {
+                SampleOutcome::Failed
+            } else {
+                SampleOutcome::Verified
+            };
+            stats.record(
+                7,
+                PeerId([ms as u8 % 3; 32]),
+                Duration::from_millis(ms),
+                outcome,
+            );
+        }
+        stats.record(
+            7,
+            PeerId([0; 32]),
+            Duration::from_secs(60),
+            SampleOutcome::Unavailable,
+        );
+
+        let height = stats.height(7).unwrap();
+        assert_eq!(
+            (
+                height.attempted,
+                height.verified,
+                height.failed,
+                height.unavailable
+            ),
+            (11, 9, 1, 1)
+        );
+        assert_eq!(height.peers.len(), 3);
+        assert_eq!(
+            height.failures,
+            vec![
+                (PeerId([1; 32]), SampleOutcome::Failed),
+                (PeerId([0; 32]), SampleOutcome::Unavailable)
+            ]
+        );
+        assert_eq!(
+            height.latency_percentile(50.0),
+            Some(Duration::from_millis(5))
+        );
+        assert_eq!(
+            height.latency_percentile(99.0),
+            Some(Duration::from_millis(10))
+        );
+        assert!(stats
+            .metrics("light")
+            .contains("light_samples_failed{height=\"7\"} 1"));
+
+        stats.record(8, PeerId([0; 32]), Duration::ZERO, SampleOutcome::Verified);
+        stats.record(9, PeerId([0; 32]), Duration::ZERO, SampleOutcome::Verified);
+        assert!(stats.height(7).is_none());
+        assert_eq!(stats.heights().count(), 2);
+    }
+}
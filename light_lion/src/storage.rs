@@ -0,0 +1,50 @@
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::storage::StorageProof;
+
+use ark_ec::pairing::Pairing;
+use lion_roars::messages::LionMessages;
+use rs_merkle::Hasher;
+use std::io;
+
+/// Challenge to prove the square at `height` is still held, in the epoch anchored
+/// at `anchor_height`
+pub fn storage_challenge(height: u64, anchor_height: u64) -> LionMessages {
+    LionMessages::StorageChallenge {
+        height,
+        anchor_height,
+    }
+}
+
+/// Proof carried by a full node's reply to `storage_challenge`, `None` if it
+/// cannot answer
+pub fn storage_response<E: Pairing, H: Hasher>(
+    reply: LionMessages,
+) -> io::Result<Option<StorageProof<E, H>>> {
+    match reply {
+        LionMessages::StorageProof {
+            height,
+            anchor_height,
+            samples,
+        } => {
+            let samples = samples
+                .iter()
+                .map(|sample| CellSample::from_bytes(sample))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid_data("Malformed storage proof".to_string()))?;
+            Ok(Some(StorageProof {
+                height,
+                anchor_height,
+                samples,
+            }))
+        }
+        LionMessages::StorageUnavailable { .. } => Ok(None),
+        other => Err(invalid_data(format!(
+            "Unexpected reply to storage challenge: {:?}",
+            other
+        ))),
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,276 @@
+use lion_roars::dht::PeerId;
+use lion_roars::header::Header;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const STATE_FILE: &str = "light_state.log";
+const VERIFIER_KEY_FILE: &str = "verifier_key.bin";
+
+/// Outcome of sampling the square at one height
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SamplingRecord {
+    pub height: u64,
+    /// Number of cells requested
+    pub attempted: usize,
+    /// Number of cells whose proofs verified
+    pub verified: usize,
+}
+
+impl SamplingRecord {
+    pub fn succeeded(&self) -> bool {
+        self.attempted > 0 && self.verified == self.attempted
+    }
+}
+
+/// Outcome of checking a storage proof a peer gave for the square at `height`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StorageRecord {
+    pub peer: PeerId,
+    pub height: u64,
+    /// Height of the header the proof was anchored at
+    pub anchor_height: u64,
+    pub verified: bool,
+}
+
+/// Append-only on-disk record of what a light node has verified, replayed on
+/// startup so a restarted node resumes from its last checkpoint.
+pub struct LightStore {
+    /// File the state log is appended to
+    path: PathBuf,
+    /// File the fetched verifier key is cached in
+    vk_path: PathBuf,
+    /// Headers accepted by the node
+    headers: BTreeMap<u64, Header>,
+    /// Latest sampling outcome per height
+    sampling: BTreeMap<u64, SamplingRecord>,
+    /// Serialized evidence of misbehaviour per height
+    evidence: BTreeMap<u64, Vec<Vec<u8>>>,
+    /// Latest storage proof checked per peer and height
+    storage: BTreeMap<(PeerId, u64), StorageRecord>,
+}
+
+impl LightStore {
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        let path = dir.as_ref().join(STATE_FILE);
+        let mut store = Self {
+            path,
+            vk_path: dir.as_ref().join(VERIFIER_KEY_FILE),
+            headers: BTreeMap::new(),
+            sampling: BTreeMap::new(),
+            evidence: BTreeMap::new(),
+            storage: BTreeMap::new(),
+        };
+
+        if store.path.exists() {
+            let reader = BufReader::new(File::open(&store.path)?);
+            for line in reader.lines() {
+                store.replay(&line?)?;
+            }
+        }
+        Ok(store)
+    }
+
+    fn replay(&mut self, line: &str) -> io::Result<()> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts.as_slice() {
+            // entries written by older versions lack the trailing fields
+            ["header", height, root, extra @ ..] if extra.len() <= 4 => {
+                let height = parse_num(height)?;
+                let optional = |idx: usize| extra.get(idx).map_or(Ok(vec![]), |f| parse_field(f));
+                let header = Header {
+                    height,
+                    data_root: parse_hex(root)?,
+                    vk_digest: optional(0)?,
+                    namespace_root: optional(1)?,
+                    parent: optional(2)?,
+                    layout: optional(3)?,
+                };
+                self.headers.insert(height, header);
+            }
+            ["sample", height, attempted, verified] => {
+                let height = parse_num(height)?;
+                self.sampling.insert(
+                    height,
+                    SamplingRecord {
+                        height,
+                        attempted: parse_num(attempted)? as usize,
+                        verified: parse_num(verified)? as usize,
+                    },
+                );
+            }
+            ["evidence", height, bytes] => {
+                self.evidence
+                    .entry(parse_num(height)?)
+                    .or_default()
+                    .push(parse_hex(bytes)?);
+            }
+            ["storage", peer, height, anchor_height, verified] => {
+                let peer = PeerId(
+                    parse_hex(peer)?
+                        .try_into()
+                        .map_err(|_| invalid_data(format!("Bad peer id {}", peer)))?,
+                );
+                let record = StorageRecord {
+                    peer,
+                    height: parse_num(height)?,
+                    anchor_height: parse_num(anchor_height)?,
+                    verified: parse_num(verified)? == 1,
+                };
+                self.storage.insert((peer, record.height), record);
+            }
+            [] => {}
+            _ => return Err(invalid_data(format!("Malformed state entry: {}", line))),
+        }
+        Ok(())
+    }
+
+    fn append(&self, line: String) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", line)?;
+        file.sync_data()
+    }
+
+    pub fn record_header(&mut self, header: &Header) -> io::Result<()> {
+        self.append(format!(
+            "header {} {} {} {} {} {}",
+            header.height,
+            hex::encode(&header.data_root),
+            field(&header.vk_digest),
+            field(&header.namespace_root),
+            field(&header.parent),
+            field(&header.layout)
+        ))?;
+        self.headers.insert(header.height, header.clone());
+        Ok(())
+    }
+
+    pub fn record_sampling(&mut self, record: SamplingRecord) -> io::Result<()> {
+        self.append(format!(
+            "sample {} {} {}",
+            record.height, record.attempted, record.verified
+        ))?;
+        self.sampling.insert(record.height, record);
+        Ok(())
+    }
+
+    pub fn record_evidence(&mut self, height: u64, evidence: &[u8]) -> io::Result<()> {
+        self.append(format!("evidence {} {}", height, hex::encode(evidence)))?;
+        self.evidence
+            .entry(height)
+            .or_default()
+            .push(evidence.to_vec());
+        Ok(())
+    }
+
+    pub fn record_storage(&mut self, record: StorageRecord) -> io::Result<()> {
+        self.append(format!(
+            "storage {} {} {} {}",
+            hex::encode(record.peer.0),
+            record.height,
+            record.anchor_height,
+            record.verified as u8
+        ))?;
+        self.storage.insert((record.peer, record.height), record);
+        Ok(())
+    }
+
+    /// Cache an encoded verifier key so it is only fetched once
+    pub fn record_verifier_key(&self, vk_bytes: &[u8]) -> io::Result<()> {
+        let tmp = self.vk_path.with_extension("tmp");
+        fs::write(&tmp, vk_bytes)?;
+        File::open(&tmp)?.sync_all()?;
+        fs::rename(&tmp, &self.vk_path)
+    }
+
+    /// Encoded verifier key cached by `record_verifier_key`, if any
+    pub fn verifier_key(&self) -> io::Result<Option<Vec<u8>>> {
+        match fs::read(&self.vk_path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Make sure everything recorded so far has reached the disk
+    pub fn flush(&self) -> io::Result<()> {
+        if self.path.exists() {
+            File::open(&self.path)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    pub fn header(&self, height: u64) -> Option<&Header> {
+        self.headers.get(&height)
+    }
+
+    /// Verified header at the greatest height
+    pub fn latest_header(&self) -> Option<&Header> {
+        self.headers.last_key_value().map(|(_, header)| header)
+    }
+
+    pub fn sampling(&self, height: u64) -> Option<&SamplingRecord> {
+        self.sampling.get(&height)
+    }
+
+    pub fn evidence(&self, height: u64) -> &[Vec<u8>] {
+        self.evidence
+            .get(&height)
+            .map(|evidence| evidence.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Latest storage proof `peer` gave for the square at `height`
+    pub fn storage(&self, peer: &PeerId, height: u64) -> Option<&StorageRecord> {
+        self.storage.get(&(*peer, height))
+    }
+
+    /// Highest height such that it and every height below it known to the store
+    /// has a verified header and a successful sampling round.
+    pub fn checkpoint(&self) -> Option<u64> {
+        let mut checkpoint = None;
+        for height in self.headers.keys() {
+            match self.sampling.get(height) {
+                Some(record) if record.succeeded() => checkpoint = Some(*height),
+                _ => break,
+            }
+        }
+        checkpoint
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_num(s: &str) -> io::Result<u64> {
+    s.parse::<u64>()
+        .map_err(|e| invalid_data(format!("Bad number {}: {}", s, e)))
+}
+
+fn parse_hex(s: &str) -> io::Result<Vec<u8>> {
+    hex::decode(s).map_err(|e| invalid_data(format!("Bad hex {}: {}", s, e)))
+}
+
+/// Hex encoding of an optional field, with `-` standing in for an empty one
+fn field(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        "-".to_string()
+    } else {
+        hex::encode(bytes)
+    }
+}
+
+fn parse_field(s: &str) -> io::Result<Vec<u8>> {
+    if s == "-" {
+        Ok(vec![])
+    } else {
+        parse_hex(s)
+    }
+}
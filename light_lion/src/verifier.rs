@@ -0,0 +1,133 @@
+use square_reed_solomon::sample::{CellSample, LineAxis, LineSample};
+use square_reed_solomon::verifier::{self, PreparedVerifierKey, RootVersion};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Commitment, Proof, VerifierKey};
+use rs_merkle::{Hasher, MerkleProof};
+
+/// Everything a light node needs to check samples: the verifier key, prepared once
+/// so each of the many openings checked per block skips the fixed-input work.
+pub struct LightVerifier<E: Pairing> {
+    prepared: PreparedVerifierKey<E>,
+}
+
+impl<E: Pairing> LightVerifier<E> {
+    pub fn new(vk: VerifierKey<E>) -> Self {
+        Self {
+            prepared: PreparedVerifierKey::new(vk),
+        }
+    }
+
+    pub fn vk(&self) -> &VerifierKey<E> {
+        &self.prepared.vk
+    }
+
+    /// Check the opening of cell `cid` of a row with commitment `row_commitment`
+    /// in a square of side `length`
+    pub fn verify_cell(
+        &self,
+        row_commitment: &Commitment<E>,
+        length: usize,
+        cid: usize,
+        value: E::ScalarField,
+        proof: &Proof<E>,
+    ) -> bool {
+        self.prepared
+            .verify_cell_proof(row_commitment, length, cid, value, proof)
+    }
+
+    /// Check that `row_commitment` is the commitment of row `rid` under `row_root`,
+    /// built as `version` builds it
+    pub fn verify_row_commitment<H: Hasher>(
+        &self,
+        version: RootVersion,
+        row_root: H::Hash,
+        length: usize,
+        rid: usize,
+        row_commitment: &Commitment<E>,
+        path: &MerkleProof<H>,
+    ) -> bool {
+        verifier::verify_line_commitment::<E, H>(
+            version,
+            row_root,
+            LineAxis::Row,
+            length,
+            rid,
+            row_commitment,
+            path,
+        )
+    }
+
+    /// Check that `row_commitments` are the commitments of rows `rids`, in
+    /// increasing order, under `row_root` with one multi-proof
+    pub fn verify_row_commitments<H: Hasher>(
+        &self,
+        version: RootVersion,
+        row_root: H::Hash,
+        length: usize,
+        rids: &[usize],
+        row_commitments: &[Commitment<E>],
+        path: &MerkleProof<H>,
+    ) -> bool {
+        verifier::verify_line_commitments::<E, H>(
+            version,
+            row_root,
+            LineAxis::Row,
+            length,
+            rids,
+            row_commitments,
+            path,
+        )
+    }
+
+    /// Check that the data root commits to the given row and column roots under
+    /// `version`
+    pub fn verify_root<H: Hasher>(
+        &self,
+        version: RootVersion,
+        root: H::Hash,
+        row_root: H::Hash,
+        col_root: H::Hash,
+    ) -> bool {
+        verifier::verify_root::<H>(version, root, row_root, col_root)
+    }
+
+    /// All three checks for a sampled cell
+    pub fn verify_sample<H: Hasher>(
+        &self,
+        version: RootVersion,
+        root: H::Hash,
+        length: usize,
+        sample: &CellSample<E, H>,
+    ) -> bool {
+        self.verify_root::<H>(version, root, sample.row_root, sample.col_root)
+            && self.verify_row_commitment::<H>(
+                version,
+                sample.row_root,
+                length,
+                sample.rid,
+                &sample.row_commitment,
+                &sample.row_path,
+            )
+            && self.verify_cell(
+                &sample.row_commitment,
+                length,
+                sample.cid,
+                sample.value,
+                &sample.opening,
+            )
+    }
+
+    /// Check every value of a sampled row or column with its single opening, for
+    /// the block at `height` with `root`
+    pub fn verify_line<H: Hasher>(
+        &self,
+        height: u64,
+        version: RootVersion,
+        root: H::Hash,
+        length: usize,
+        sample: &LineSample<E, H>,
+    ) -> bool {
+        verifier::verify_line_sample::<E, H>(self.vk(), height, version, root, length, sample)
+    }
+}
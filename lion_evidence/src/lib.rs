@@ -0,0 +1,294 @@
+mod protobuf;
+mod ssz;
+
+use square_reed_solomon::codec::{
+    commitment_from_bytes, commitment_to_bytes, field_from_bytes, field_to_bytes, line_from_bytes,
+    line_to_bytes, proof_from_bytes,
+};
+use square_reed_solomon::dimensions::SquareLayout;
+use square_reed_solomon::sample::{CellSample, LineAxis, LineSample};
+use square_reed_solomon::verifier::verify_bad_encoding;
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Proof, VerifierKey};
+use ark_serialize::CanonicalSerialize;
+use lion_roars::dht::PeerId;
+use lion_roars::equivocation::EquivocationProof;
+use lion_roars::header::SignedHeader;
+use rs_merkle::{Hasher, MerkleProof};
+use std::io;
+
+/// Signed header of a square whose rows and columns disagree, with a cell sample
+/// and a sample of the cell's column proving it
+pub struct BadEncodingEvidence<E: Pairing, H: Hasher> {
+    pub header: SignedHeader,
+    pub cell: CellSample<E, H>,
+    pub col: LineSample<E, H>,
+}
+
+impl<E: Pairing, H: Hasher> BadEncodingEvidence<E, H> {
+    pub fn verify(&self, vk: &VerifierKey<E>) -> bool {
+        let root = match H::Hash::try_from(self.header.header.data_root.clone()) {
+            Ok(root) => root,
+            Err(_) => return false,
+        };
+        let version = match SquareLayout::root_version_of(&self.header.header.layout) {
+            Some(version) => version,
+            None => return false,
+        };
+        let height = self.header.header.height;
+        self.header.verify()
+            && verify_bad_encoding(
+                vk,
+                height,
+                version,
+                root,
+                self.col.values.len(),
+                &self.cell,
+                &self.col,
+            )
+    }
+}
+
+/// Misbehaviour of a block producer, provable to anyone holding the verifier key.
+/// Encodes to SSZ and to protobuf (schema in `proto/evidence.proto`) for consumption
+/// by an external consensus layer or contract.
+pub enum Evidence<E: Pairing, H: Hasher> {
+    Equivocation(EquivocationProof),
+    BadEncoding(BadEncodingEvidence<E, H>),
+}
+
+impl<E: Pairing, H: Hasher> Evidence<E, H> {
+    pub fn height(&self) -> u64 {
+        match self {
+            Evidence::Equivocation(proof) => proof.height(),
+            Evidence::BadEncoding(evidence) => evidence.header.header.height,
+        }
+    }
+
+    /// Producer the evidence incriminates
+    pub fn producer_id(&self) -> PeerId {
+        match self {
+            Evidence::Equivocation(proof) => proof.producer_id(),
+            Evidence::BadEncoding(evidence) => evidence.header.producer_id(),
+        }
+    }
+
+    pub fn verify(&self, vk: &VerifierKey<E>) -> bool {
+        match self {
+            Evidence::Equivocation(proof) => proof.verify(),
+            Evidence::BadEncoding(evidence) => evidence.verify(vk),
+        }
+    }
+
+    pub fn to_ssz(&self) -> Vec<u8> {
+        ssz::encode(&self.to_raw())
+    }
+
+    pub fn from_ssz(bytes: &[u8]) -> io::Result<Self> {
+        Self::from_raw(ssz::decode(bytes)?)
+    }
+
+    pub fn to_protobuf(&self) -> Vec<u8> {
+        protobuf::encode(&self.to_raw())
+    }
+
+    pub fn from_protobuf(bytes: &[u8]) -> io::Result<Self> {
+        Self::from_raw(protobuf::decode(bytes)?)
+    }
+
+    fn to_raw(&self) -> RawEvidence {
+        match self {
+            Evidence::Equivocation(proof) => RawEvidence::Equivocation {
+                first: proof.first.clone(),
+                second: proof.second.clone(),
+            },
+            Evidence::BadEncoding(evidence) => {
+                let cell = &evidence.cell;
+                let col = &evidence.col;
+                RawEvidence::BadEncoding(RawBadEncoding {
+                    header: evidence.header.clone(),
+                    row_root: cell.row_root.into(),
+                    col_root: cell.col_root.into(),
+                    rid: cell.rid as u64,
+                    cid: cell.cid as u64,
+                    value: field_to_bytes(&cell.value),
+                    row_commitment: commitment_to_bytes(&cell.row_commitment),
+                    row_path: cell.row_path.to_bytes(),
+                    row_opening: proof_to_bytes(&cell.opening),
+                    col_values: line_to_bytes(&col.values),
+                    col_commitment: commitment_to_bytes(&col.commitment),
+                    col_path: col.path.to_bytes(),
+                    col_opening: proof_to_bytes(&col.opening),
+                })
+            }
+        }
+    }
+
+    fn from_raw(raw: RawEvidence) -> io::Result<Self> {
+        match raw {
+            RawEvidence::Equivocation { first, second } => {
+                Ok(Evidence::Equivocation(EquivocationProof { first, second }))
+            }
+            RawEvidence::BadEncoding(raw) => {
+                let row_root = hash_from_bytes::<H>(raw.row_root)?;
+                let col_root = hash_from_bytes::<H>(raw.col_root)?;
+                let cell = CellSample {
+                    rid: index(raw.rid)?,
+                    cid: index(raw.cid)?,
+                    value: field_from_bytes(&raw.value).map_err(invalid_data)?,
+                    row_commitment: commitment_from_bytes(&raw.row_commitment)
+                        .map_err(invalid_data)?,
+                    opening: proof_from_bytes::<E>(&raw.row_opening).map_err(invalid_data)?,
+                    row_path: MerkleProof::<H>::from_bytes(&raw.row_path).map_err(invalid_data)?,
+                    row_root,
+                    col_root,
+                };
+                let col = LineSample {
+                    axis: LineAxis::Col,
+                    idx: cell.cid,
+                    // the column sample is bound to the height of the header it accuses
+                    height: raw.header.header.height,
+                    values: line_from_bytes(&raw.col_values).map_err(invalid_data)?,
+                    commitment: commitment_from_bytes(&raw.col_commitment).map_err(invalid_data)?,
+                    opening: proof_from_bytes::<E>(&raw.col_opening).map_err(invalid_data)?,
+                    path: MerkleProof::<H>::from_bytes(&raw.col_path).map_err(invalid_data)?,
+                    row_root,
+                    col_root,
+                };
+                Ok(Evidence::BadEncoding(BadEncodingEvidence {
+                    header: raw.header,
+                    cell,
+                    col,
+                }))
+            }
+        }
+    }
+}
+
+/// Decode SSZ-encoded evidence and check it, for consumers holding only the bytes
+pub fn verify_ssz_evidence<E: Pairing, H: Hasher>(vk: &VerifierKey<E>, bytes: &[u8]) -> bool {
+    Evidence::<E, H>::from_ssz(bytes).map_or(false, |evidence| evidence.verify(vk))
+}
+
+/// Protobuf counterpart of `verify_ssz_evidence`
+pub fn verify_protobuf_evidence<E: Pairing, H: Hasher>(vk: &VerifierKey<E>, bytes: &[u8]) -> bool {
+    Evidence::<E, H>::from_protobuf(bytes).map_or(false, |evidence| evidence.verify(vk))
+}
+
+/// Evidence reduced to plain bytes, shared by both wire encodings
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RawEvidence {
+    Equivocation {
+        first: SignedHeader,
+        second: SignedHeader,
+    },
+    BadEncoding(RawBadEncoding),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct RawBadEncoding {
+    header: SignedHeader,
+    row_root: Vec<u8>,
+    col_root: Vec<u8>,
+    rid: u64,
+    cid: u64,
+    value: Vec<u8>,
+    row_commitment: Vec<u8>,
+    row_path: Vec<u8>,
+    row_opening: Vec<u8>,
+    col_values: Vec<Vec<u8>>,
+    col_commitment: Vec<u8>,
+    col_path: Vec<u8>,
+    col_opening: Vec<u8>,
+}
+
+fn proof_to_bytes<E: Pairing>(proof: &Proof<E>) -> Vec<u8> {
+    let mut bytes = vec![];
+    proof
+        .serialize_compressed(&mut bytes)
+        .expect("Serializing opening proof should not fail");
+    bytes
+}
+
+fn hash_from_bytes<H: Hasher>(bytes: Vec<u8>) -> io::Result<H::Hash> {
+    H::Hash::try_from(bytes).map_err(|_| invalid_data("Bad hash length"))
+}
+
+fn index(val: u64) -> io::Result<usize> {
+    usize::try_from(val).map_err(invalid_data)
+}
+
+fn invalid_data(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_protobuf_evidence, verify_ssz_evidence, BadEncodingEvidence, Evidence};
+    use square_reed_solomon::fixtures::counting_square;
+    use square_reed_solomon::prover::RsSquareProver;
+    use square_reed_solomon::verifier::verifier_key;
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use lion_roars::equivocation::EquivocationProof;
+    use lion_roars::header::{Header, ProducerKey};
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn evidence_survives_both_encodings() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2).at_height(9);
+        let vk = verifier_key(prover.params());
+        let producer = ProducerKey::generate();
+        let header = |root: Vec<u8>| Header {
+            height: 9,
+            data_root: root,
+            vk_digest: vec![2; 32],
+            namespace_root: vec![],
+            parent: vec![],
+            layout: vec![],
+        };
+
+        let theirs = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let equivocation = Evidence::<Bls12_381, Sha256>::Equivocation(
+            EquivocationProof::new(
+                producer.sign(header(prover.root().to_vec())),
+                producer.sign(header(theirs.root().to_vec())),
+            )
+            .unwrap(),
+        );
+        let ssz = equivocation.to_ssz();
+        let protobuf = equivocation.to_protobuf();
+        assert!(verify_ssz_evidence::<Bls12_381, Sha256>(&vk, &ssz));
+        assert!(verify_protobuf_evidence::<Bls12_381, Sha256>(
+            &vk, &protobuf
+        ));
+        assert_eq!(
+            Evidence::<Bls12_381, Sha256>::from_ssz(&ssz)
+                .unwrap()
+                .height(),
+            9
+        );
+
+        // an honest square's rows and columns agree, so this is no evidence at all
+        let honest = Evidence::BadEncoding(BadEncodingEvidence {
+            header: producer.sign(header(prover.root().to_vec())),
+            cell: prover.sample_cell(1, 3),
+            col: prover.sample_col(3),
+        });
+        let ssz = honest.to_ssz();
+        let protobuf = honest.to_protobuf();
+        assert_eq!(
+            Evidence::<Bls12_381, Sha256>::from_ssz(&ssz)
+                .unwrap()
+                .to_protobuf(),
+            protobuf
+        );
+        assert!(!verify_ssz_evidence::<Bls12_381, Sha256>(&vk, &ssz));
+        assert!(!verify_protobuf_evidence::<Bls12_381, Sha256>(
+            &vk, &protobuf
+        ));
+        assert!(!verify_ssz_evidence::<Bls12_381, Sha256>(&vk, &ssz[1..]));
+    }
+}
@@ -0,0 +1,344 @@
+//! Protobuf encoding of evidence following `proto/evidence.proto`. Fields are
+//! written in field-number order and proto3 defaults are omitted, so equal
+//! evidence always encodes to equal bytes. Decoding accepts that encoding and no
+//! other, so evidence bytes differing from the canonical ones are never taken for
+//! the same headers and signatures.
+
+use crate::{invalid_data, RawBadEncoding, RawEvidence};
+
+use lion_roars::header::{Header, SignedHeader};
+use std::io;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+pub(crate) fn encode(evidence: &RawEvidence) -> Vec<u8> {
+    let mut out = vec![];
+    match evidence {
+        RawEvidence::Equivocation { first, second } => {
+            let mut body = vec![];
+            put_message(&mut body, 1, &signed_header(first));
+            put_message(&mut body, 2, &signed_header(second));
+            put_message(&mut out, 1, &body);
+        }
+        RawEvidence::BadEncoding(raw) => {
+            let mut body = vec![];
+            put_message(&mut body, 1, &signed_header(&raw.header));
+            put_bytes(&mut body, 2, &raw.row_root);
+            put_bytes(&mut body, 3, &raw.col_root);
+            put_uint64(&mut body, 4, raw.rid);
+            put_uint64(&mut body, 5, raw.cid);
+            put_bytes(&mut body, 6, &raw.value);
+            put_bytes(&mut body, 7, &raw.row_commitment);
+            put_bytes(&mut body, 8, &raw.row_path);
+            put_bytes(&mut body, 9, &raw.row_opening);
+            for value in &raw.col_values {
+                put_message(&mut body, 10, value);
+            }
+            put_bytes(&mut body, 11, &raw.col_commitment);
+            put_bytes(&mut body, 12, &raw.col_path);
+            put_bytes(&mut body, 13, &raw.col_opening);
+            put_message(&mut out, 2, &body);
+        }
+    }
+    out
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> io::Result<RawEvidence> {
+    let mut evidence = None;
+    for (number, value) in fields(bytes)? {
+        evidence = match (number, value) {
+            (1, Value::Len(body)) => Some(read_equivocation(body)?),
+            (2, Value::Len(body)) => Some(read_bad_encoding(body)?),
+            _ => evidence,
+        };
+    }
+    let evidence = evidence.ok_or_else(|| invalid_data("Evidence has no kind set"))?;
+    if encode(&evidence) != bytes {
+        return Err(invalid_data("Evidence is not canonically encoded"));
+    }
+    Ok(evidence)
+}
+
+fn read_equivocation(bytes: &[u8]) -> io::Result<RawEvidence> {
+    let (mut first, mut second) = (None, None);
+    for (number, value) in fields(bytes)? {
+        match (number, value) {
+            (1, Value::Len(body)) => first = Some(read_signed_header(body)?),
+            (2, Value::Len(body)) => second = Some(read_signed_header(body)?),
+            _ => {}
+        }
+    }
+    match (first, second) {
+        (Some(first), Some(second)) => Ok(RawEvidence::Equivocation { first, second }),
+        _ => Err(invalid_data("Equivocation evidence lacks a header")),
+    }
+}
+
+fn read_bad_encoding(bytes: &[u8]) -> io::Result<RawEvidence> {
+    let mut header = None;
+    let mut raw = RawBadEncoding {
+        header: empty_signed_header(),
+        row_root: vec![],
+        col_root: vec![],
+        rid: 0,
+        cid: 0,
+        value: vec![],
+        row_commitment: vec![],
+        row_path: vec![],
+        row_opening: vec![],
+        col_values: vec![],
+        col_commitment: vec![],
+        col_path: vec![],
+        col_opening: vec![],
+    };
+    for (number, value) in fields(bytes)? {
+        match (number, value) {
+            (1, Value::Len(body)) => header = Some(read_signed_header(body)?),
+            (2, Value::Len(body)) => raw.row_root = body.to_vec(),
+            (3, Value::Len(body)) => raw.col_root = body.to_vec(),
+            (4, Value::Varint(val)) => raw.rid = val,
+            (5, Value::Varint(val)) => raw.cid = val,
+            (6, Value::Len(body)) => raw.value = body.to_vec(),
+            (7, Value::Len(body)) => raw.row_commitment = body.to_vec(),
+            (8, Value::Len(body)) => raw.row_path = body.to_vec(),
+            (9, Value::Len(body)) => raw.row_opening = body.to_vec(),
+            (10, Value::Len(body)) => raw.col_values.push(body.to_vec()),
+            (11, Value::Len(body)) => raw.col_commitment = body.to_vec(),
+            (12, Value::Len(body)) => raw.col_path = body.to_vec(),
+            (13, Value::Len(body)) => raw.col_opening = body.to_vec(),
+            _ => {}
+        }
+    }
+    raw.header = header.ok_or_else(|| invalid_data("Bad encoding evidence lacks a header"))?;
+    Ok(RawEvidence::BadEncoding(raw))
+}
+
+fn signed_header(signed: &SignedHeader) -> Vec<u8> {
+    let mut out = vec![];
+    put_uint64(&mut out, 1, signed.header.height);
+    put_bytes(&mut out, 2, &signed.header.data_root);
+    put_bytes(&mut out, 3, &signed.header.vk_digest);
+    put_bytes(&mut out, 4, &signed.header.namespace_root);
+    put_bytes(&mut out, 5, &signed.producer);
+    put_bytes(&mut out, 6, &signed.signature);
+    put_bytes(&mut out, 7, &signed.header.parent);
+    put_bytes(&mut out, 8, &signed.header.layout);
+    out
+}
+
+fn read_signed_header(bytes: &[u8]) -> io::Result<SignedHeader> {
+    let mut signed = empty_signed_header();
+    let (mut producer, mut signature) = (None, None);
+    for (number, value) in fields(bytes)? {
+        match (number, value) {
+            (1, Value::Varint(val)) => signed.header.height = val,
+            (2, Value::Len(body)) => signed.header.data_root = body.to_vec(),
+            (3, Value::Len(body)) => signed.header.vk_digest = body.to_vec(),
+            (4, Value::Len(body)) => signed.header.namespace_root = body.to_vec(),
+            (5, Value::Len(body)) => producer = Some(body),
+            (6, Value::Len(body)) => signature = Some(body),
+            (7, Value::Len(body)) => signed.header.parent = body.to_vec(),
+            (8, Value::Len(body)) => signed.header.layout = body.to_vec(),
+            _ => {}
+        }
+    }
+    signed.producer = producer
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid_data("Producer key must be 32 bytes"))?;
+    signed.signature = signature
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| invalid_data("Signature must be 64 bytes"))?;
+    Ok(signed)
+}
+
+fn empty_signed_header() -> SignedHeader {
+    SignedHeader {
+        header: Header {
+            height: 0,
+            data_root: vec![],
+            vk_digest: vec![],
+            namespace_root: vec![],
+            parent: vec![],
+            layout: vec![],
+        },
+        producer: [0; 32],
+        signature: [0; 64],
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        out.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn put_key(out: &mut Vec<u8>, number: u32, wire_type: u8) {
+    put_varint(out, ((number as u64) << 3) | wire_type as u64);
+}
+
+fn put_uint64(out: &mut Vec<u8>, number: u32, val: u64) {
+    if val != 0 {
+        put_key(out, number, WIRE_VARINT);
+        put_varint(out, val);
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, number: u32, data: &[u8]) {
+    if !data.is_empty() {
+        put_message(out, number, data);
+    }
+}
+
+/// Length-delimited field written even when empty, for embedded messages and
+/// elements of repeated fields
+fn put_message(out: &mut Vec<u8>, number: u32, data: &[u8]) {
+    put_key(out, number, WIRE_LEN);
+    put_varint(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Len(&'a [u8]),
+    /// Fixed-width fields, which no evidence message uses
+    Fixed,
+}
+
+fn fields(mut bytes: &[u8]) -> io::Result<Vec<(u32, Value<'_>)>> {
+    let mut out = vec![];
+    while !bytes.is_empty() {
+        let key = take_varint(&mut bytes)?;
+        let number = u32::try_from(key >> 3).map_err(|_| invalid_data("Field number overflow"))?;
+        let value = match (key & 7) as u8 {
+            WIRE_VARINT => Value::Varint(take_varint(&mut bytes)?),
+            WIRE_LEN => {
+                let len = usize::try_from(take_varint(&mut bytes)?)
+                    .map_err(|_| invalid_data("Length overflow"))?;
+                Value::Len(take(&mut bytes, len)?)
+            }
+            WIRE_FIXED64 => {
+                take(&mut bytes, 8)?;
+                Value::Fixed
+            }
+            WIRE_FIXED32 => {
+                take(&mut bytes, 4)?;
+                Value::Fixed
+            }
+            wire_type => return Err(invalid_data(format!("Unsupported wire type {}", wire_type))),
+        };
+        out.push((number, value));
+    }
+    Ok(out)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if bytes.len() < len {
+        return Err(invalid_data("Protobuf message truncated"));
+    }
+    let (data, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(data)
+}
+
+fn take_varint(bytes: &mut &[u8]) -> io::Result<u64> {
+    let mut val = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = take(bytes, 1)?[0];
+        val |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err(invalid_data("Varint too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode, encode, fields, put_bytes, put_message, put_uint64, put_varint, signed_header,
+        Value,
+    };
+    use crate::RawEvidence;
+
+    use lion_roars::header::{Header, SignedHeader};
+
+    #[test]
+    pub fn fields_round_trip_and_defaults_are_omitted() {
+        let mut out = vec![];
+        put_varint(&mut out, 300);
+        assert_eq!(out, vec![0xac, 0x02]);
+
+        let mut out = vec![];
+        put_uint64(&mut out, 1, 0);
+        put_bytes(&mut out, 2, &[]);
+        assert!(out.is_empty());
+
+        put_uint64(&mut out, 4, 150);
+        put_bytes(&mut out, 2, b"lion");
+        // an unknown fixed32 field is skipped over
+        out.extend([(7 << 3) | 5, 1, 2, 3, 4]);
+        let parsed = fields(&out).unwrap();
+        assert!(matches!(parsed[0], (4, Value::Varint(150))));
+        assert!(matches!(parsed[1], (2, Value::Len(b"lion"))));
+        assert!(matches!(parsed[2], (7, Value::Fixed)));
+        assert!(fields(&out[..out.len() - 1]).is_err());
+    }
+
+    #[test]
+    pub fn only_canonical_encodings_decode() {
+        let signed = |height| SignedHeader {
+            header: Header {
+                height,
+                data_root: vec![1; 32],
+                vk_digest: vec![2; 32],
+                namespace_root: vec![],
+                parent: vec![],
+                layout: vec![],
+            },
+            producer: [3; 32],
+            signature: [4; 64],
+        };
+        let evidence = RawEvidence::Equivocation {
+            first: signed(7),
+            second: signed(0),
+        };
+        let bytes = encode(&evidence);
+        assert_eq!(decode(&bytes).unwrap(), evidence);
+
+        let wrap = |first: &[u8], second: &[u8]| {
+            let (mut body, mut out) = (vec![], vec![]);
+            put_message(&mut body, 1, first);
+            put_message(&mut body, 2, second);
+            put_message(&mut out, 1, &body);
+            out
+        };
+        let (first, second) = (signed_header(&signed(7)), signed_header(&signed(0)));
+        assert_eq!(wrap(&first, &second), bytes);
+
+        // a default written out, an overlong varint, a repeated field and an unknown
+        // field all decode to the same evidence but are rejected
+        let mut zero_height = vec![];
+        put_uint64(&mut zero_height, 1, 1);
+        zero_height[1] = 0;
+        zero_height.extend(&second);
+        let mut overlong = vec![0x08, 0x87, 0x00];
+        overlong.extend(&first[2..]);
+        let mut repeated = first.clone();
+        repeated.extend(&first[..2]);
+        let mut unknown = bytes.clone();
+        unknown.extend([15 << 3, 1]);
+        for bytes in [
+            wrap(&first, &zero_height),
+            wrap(&overlong, &second),
+            wrap(&repeated, &second),
+            unknown,
+        ] {
+            assert!(decode(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! SSZ encoding of evidence. Byte strings are `List[byte]`, integers `uint64`,
+//! the producer key `Vector[byte, 32]` and signatures `Vector[byte, 64]`:
+//!
+//! ```text
+//! SignedHeader         { height: uint64, data_root, vk_digest, namespace_root: List[byte],
+//!                        producer: Vector[byte, 32], signature: Vector[byte, 64],
+//!                        parent, layout: List[byte] }
+//! EquivocationEvidence { first, second: SignedHeader }
+//! BadEncodingEvidence  { header: SignedHeader, row_root, col_root: List[byte],
+//!                        rid, cid: uint64, value, row_commitment, row_path,
+//!                        row_opening: List[byte], col_values: List[List[byte]],
+//!                        col_commitment, col_path, col_opening: List[byte] }
+//! Evidence             Union[EquivocationEvidence, BadEncodingEvidence]
+//! ```
+
+use crate::{invalid_data, RawBadEncoding, RawEvidence};
+
+use lion_roars::header::{Header, SignedHeader};
+use std::io;
+
+const SELECTOR_EQUIVOCATION: u8 = 0;
+const SELECTOR_BAD_ENCODING: u8 = 1;
+
+const OFFSET_LEN: usize = 4;
+
+pub(crate) fn encode(evidence: &RawEvidence) -> Vec<u8> {
+    match evidence {
+        RawEvidence::Equivocation { first, second } => {
+            let mut out = vec![SELECTOR_EQUIVOCATION];
+            out.extend(container(vec![
+                Field::Variable(signed_header(first)),
+                Field::Variable(signed_header(second)),
+            ]));
+            out
+        }
+        RawEvidence::BadEncoding(raw) => {
+            let mut out = vec![SELECTOR_BAD_ENCODING];
+            out.extend(container(vec![
+                Field::Variable(signed_header(&raw.header)),
+                Field::Variable(raw.row_root.clone()),
+                Field::Variable(raw.col_root.clone()),
+                Field::Fixed(raw.rid.to_le_bytes().to_vec()),
+                Field::Fixed(raw.cid.to_le_bytes().to_vec()),
+                Field::Variable(raw.value.clone()),
+                Field::Variable(raw.row_commitment.clone()),
+                Field::Variable(raw.row_path.clone()),
+                Field::Variable(raw.row_opening.clone()),
+                Field::Variable(list(&raw.col_values)),
+                Field::Variable(raw.col_commitment.clone()),
+                Field::Variable(raw.col_path.clone()),
+                Field::Variable(raw.col_opening.clone()),
+            ]));
+            out
+        }
+    }
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> io::Result<RawEvidence> {
+    let (selector, body) = bytes
+        .split_first()
+        .ok_or_else(|| invalid_data("Empty evidence"))?;
+    match *selector {
+        SELECTOR_EQUIVOCATION => {
+            let parts = split_container(body, &[Kind::Variable, Kind::Variable])?;
+            Ok(RawEvidence::Equivocation {
+                first: read_signed_header(parts[0])?,
+                second: read_signed_header(parts[1])?,
+            })
+        }
+        SELECTOR_BAD_ENCODING => {
+            let parts = split_container(
+                body,
+                &[
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Fixed(8),
+                    Kind::Fixed(8),
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                    Kind::Variable,
+                ],
+            )?;
+            Ok(RawEvidence::BadEncoding(RawBadEncoding {
+                header: read_signed_header(parts[0])?,
+                row_root: parts[1].to_vec(),
+                col_root: parts[2].to_vec(),
+                rid: uint64(parts[3]),
+                cid: uint64(parts[4]),
+                value: parts[5].to_vec(),
+                row_commitment: parts[6].to_vec(),
+                row_path: parts[7].to_vec(),
+                row_opening: parts[8].to_vec(),
+                col_values: split_list(parts[9])?
+                    .into_iter()
+                    .map(<[u8]>::to_vec)
+                    .collect(),
+                col_commitment: parts[10].to_vec(),
+                col_path: parts[11].to_vec(),
+                col_opening: parts[12].to_vec(),
+            }))
+        }
+        selector => Err(invalid_data(format!(
+            "Unknown evidence selector {}",
+            selector
+        ))),
+    }
+}
+
+fn signed_header(signed: &SignedHeader) -> Vec<u8> {
+    container(vec![
+        Field::Fixed(signed.header.height.to_le_bytes().to_vec()),
+        Field::Variable(signed.header.data_root.clone()),
+        Field::Variable(signed.header.vk_digest.clone()),
+        Field::Variable(signed.header.namespace_root.clone()),
+        Field::Fixed(signed.producer.to_vec()),
+        Field::Fixed(signed.signature.to_vec()),
+        Field::Variable(signed.header.parent.clone()),
+        Field::Variable(signed.header.layout.clone()),
+    ])
+}
+
+fn read_signed_header(bytes: &[u8]) -> io::Result<SignedHeader> {
+    let parts = split_container(
+        bytes,
+        &[
+            Kind::Fixed(8),
+            Kind::Variable,
+            Kind::Variable,
+            Kind::Variable,
+            Kind::Fixed(32),
+            Kind::Fixed(64),
+            Kind::Variable,
+            Kind::Variable,
+        ],
+    )?;
+    Ok(SignedHeader {
+        header: Header {
+            height: uint64(parts[0]),
+            data_root: parts[1].to_vec(),
+            vk_digest: parts[2].to_vec(),
+            namespace_root: parts[3].to_vec(),
+            parent: parts[6].to_vec(),
+            layout: parts[7].to_vec(),
+        },
+        producer: parts[4].try_into().unwrap(),
+        signature: parts[5].try_into().unwrap(),
+    })
+}
+
+/// Field of an SSZ container, already serialized
+enum Field {
+    Fixed(Vec<u8>),
+    Variable(Vec<u8>),
+}
+
+/// Shape of a container field expected when decoding
+#[derive(Clone, Copy)]
+enum Kind {
+    Fixed(usize),
+    Variable,
+}
+
+/// Fixed-size fields inline, variable-size fields as offsets into the data that follows
+fn container(fields: Vec<Field>) -> Vec<u8> {
+    let fixed_len: usize = fields
+        .iter()
+        .map(|field| match field {
+            Field::Fixed(bytes) => bytes.len(),
+            Field::Variable(_) => OFFSET_LEN,
+        })
+        .sum();
+    let mut out = vec![];
+    let mut tail = vec![];
+    for field in fields {
+        match field {
+            Field::Fixed(bytes) => out.extend(bytes),
+            Field::Variable(bytes) => {
+                out.extend(((fixed_len + tail.len()) as u32).to_le_bytes());
+                tail.extend(bytes);
+            }
+        }
+    }
+    out.extend(tail);
+    out
+}
+
+/// A list of variable-size items is laid out like a container of that many items
+fn list(items: &[Vec<u8>]) -> Vec<u8> {
+    container(items.iter().cloned().map(Field::Variable).collect())
+}
+
+fn split_container<'a>(bytes: &'a [u8], kinds: &[Kind]) -> io::Result<Vec<&'a [u8]>> {
+    let fixed_len: usize = kinds
+        .iter()
+        .map(|kind| match kind {
+            Kind::Fixed(len) => *len,
+            Kind::Variable => OFFSET_LEN,
+        })
+        .sum();
+    if bytes.len() < fixed_len {
+        return Err(invalid_data("SSZ container truncated"));
+    }
+    let mut parts = vec![&bytes[..0]; kinds.len()];
+    let mut offsets = vec![];
+    let mut cursor = 0;
+    for (idx, kind) in kinds.iter().enumerate() {
+        match kind {
+            Kind::Fixed(len) => {
+                parts[idx] = &bytes[cursor..cursor + len];
+                cursor += len;
+            }
+            Kind::Variable => {
+                offsets.push((idx, offset(&bytes[cursor..])));
+                cursor += OFFSET_LEN;
+            }
+        }
+    }
+    if offsets.is_empty() && bytes.len() != fixed_len {
+        return Err(invalid_data("Trailing bytes after SSZ container"));
+    }
+    fill_variable(bytes, fixed_len, &offsets, &mut parts)?;
+    Ok(parts)
+}
+
+fn split_list(bytes: &[u8]) -> io::Result<Vec<&[u8]>> {
+    if bytes.is_empty() {
+        return Ok(vec![]);
+    }
+    if bytes.len() < OFFSET_LEN {
+        return Err(invalid_data("SSZ list truncated"));
+    }
+    let first = offset(bytes);
+    if first == 0 || first % OFFSET_LEN != 0 || first > bytes.len() {
+        return Err(invalid_data("Bad SSZ list offset"));
+    }
+    let offsets: Vec<(usize, usize)> = (0..first / OFFSET_LEN)
+        .map(|idx| (idx, offset(&bytes[idx * OFFSET_LEN..])))
+        .collect();
+    let mut parts = vec![&bytes[..0]; offsets.len()];
+    fill_variable(bytes, first, &offsets, &mut parts)?;
+    Ok(parts)
+}
+
+/// Slice out variable-size parts, which must start right after the fixed part and
+/// follow each other in order up to the end of `bytes`
+fn fill_variable<'a>(
+    bytes: &'a [u8],
+    fixed_len: usize,
+    offsets: &[(usize, usize)],
+    parts: &mut [&'a [u8]],
+) -> io::Result<()> {
+    for (pos, (idx, start)) in offsets.iter().enumerate() {
+        let end = offsets.get(pos + 1).map_or(bytes.len(), |(_, end)| *end);
+        if (pos == 0 && *start != fixed_len) || start > &end || end > bytes.len() {
+            return Err(invalid_data("Bad SSZ offset"));
+        }
+        parts[*idx] = &bytes[*start..end];
+    }
+    Ok(())
+}
+
+fn offset(bytes: &[u8]) -> usize {
+    u32::from_le_bytes(bytes[..OFFSET_LEN].try_into().unwrap()) as usize
+}
+
+fn uint64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{container, list, split_container, split_list, Field, Kind};
+
+    #[test]
+    pub fn offsets_point_past_the_fixed_part() {
+        let bytes = container(vec![
+            Field::Fixed(vec![7; 8]),
+            Field::Variable(vec![1, 2]),
+            Field::Variable(vec![3]),
+        ]);
+        assert_eq!(&bytes[8..16], &[16, 0, 0, 0, 18, 0, 0, 0]);
+        let parts = split_container(&bytes, &[Kind::Fixed(8), Kind::Variable, Kind::Variable]);
+        assert_eq!(parts.unwrap(), vec![&[7u8; 8][..], &[1, 2][..], &[3][..]]);
+        assert!(split_container(&bytes[..10], &[Kind::Fixed(8), Kind::Variable]).is_err());
+
+        let items = vec![vec![], vec![4, 5], vec![6]];
+        let encoded = list(&items);
+        assert_eq!(split_list(&encoded).unwrap(), items);
+        assert_eq!(split_list(&[]).unwrap(), Vec::<&[u8]>::new());
+    }
+}
@@ -0,0 +1,43 @@
+/* C interface to lazy-lion verification (BLS12-381, SHA-256 Merkle trees). */
+#ifndef LION_FFI_H
+#define LION_FFI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define LION_VALID 1
+#define LION_INVALID 0
+#define LION_ERR_NULL (-1)
+#define LION_ERR_DECODE (-2)
+
+#define LION_FIELD_BYTES 32
+#define LION_POINT_BYTES 48
+#define LION_HASH_BYTES 32
+
+typedef struct LionVerifier LionVerifier;
+
+typedef struct LionCellProof {
+    uint64_t rid;
+    uint64_t cid;
+    uint8_t value[LION_FIELD_BYTES];
+    uint8_t row_commitment[LION_POINT_BYTES];
+    uint8_t witness[LION_POINT_BYTES];
+} LionCellProof;
+
+/* `vk` is the versioned verifier key encoding: the VK_FORMAT_VERSION byte (1)
+ * followed by the compressed canonical key, as nodes serve it. Returns NULL if
+ * the version or the key is invalid. */
+LionVerifier *lion_verifier_new(const uint8_t *vk, size_t vk_len);
+void lion_verifier_free(LionVerifier *verifier);
+
+int32_t lion_verify_cell_proof(const LionVerifier *verifier, uint64_t length,
+                               const LionCellProof *proof);
+int32_t lion_verify_root(uint8_t root_version, const uint8_t *root,
+                         const uint8_t *row_root, const uint8_t *col_root);
+int32_t lion_verify_cell_sample(const LionVerifier *verifier, uint8_t root_version,
+                                const uint8_t *root, uint64_t length,
+                                const uint8_t *sample, size_t sample_len);
+int32_t lion_decode_cell_proof(const uint8_t *sample, size_t sample_len,
+                               LionCellProof *out);
+
+#endif /* LION_FFI_H */
@@ -0,0 +1,240 @@
+//! C ABI for lazy-lion proof verification over BLS12-381 with SHA-256 Merkle trees.
+//! See `include/lion_ffi.h` for the matching declarations.
+
+use square_reed_solomon::codec::{
+    commitment_to_bytes, decode, field_to_bytes, verifier_key_from_bytes, Validation,
+};
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::verifier::{
+    verify_cell_proof, verify_cell_sample, verify_root, RootVersion,
+};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Commitment, Proof, VerifierKey};
+use ark_serialize::CanonicalSerialize;
+use ark_test_curves::bls12_381::{Bls12_381, Fr};
+use rs_merkle::algorithms::Sha256;
+
+use std::slice;
+
+type G1Affine = <Bls12_381 as Pairing>::G1Affine;
+
+pub const LION_VALID: i32 = 1;
+pub const LION_INVALID: i32 = 0;
+pub const LION_ERR_NULL: i32 = -1;
+pub const LION_ERR_DECODE: i32 = -2;
+
+/// Size of a compressed BLS12-381 scalar
+pub const LION_FIELD_BYTES: usize = 32;
+/// Size of a compressed BLS12-381 G1 point
+pub const LION_POINT_BYTES: usize = 48;
+/// Size of a SHA-256 digest
+pub const LION_HASH_BYTES: usize = 32;
+
+/// Opening of a single cell against its row commitment. The layout is fixed and
+/// mirrored by `LionCellProof` in the C header.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LionCellProof {
+    pub rid: u64,
+    pub cid: u64,
+    /// Compressed value of the cell
+    pub value: [u8; LION_FIELD_BYTES],
+    /// Compressed commitment to the row polynomial
+    pub row_commitment: [u8; LION_POINT_BYTES],
+    /// Compressed KZG witness of the opening
+    pub witness: [u8; LION_POINT_BYTES],
+}
+
+/// Opaque verifier handle owned by the caller
+pub struct LionVerifier {
+    vk: VerifierKey<Bls12_381>,
+}
+
+/// Create a verifier from a verifier key encoded by `verifier_key_to_bytes`: the
+/// `VK_FORMAT_VERSION` byte followed by the compressed canonical key, as nodes serve it.
+/// Returns null on failure. The handle must be released with `lion_verifier_free`.
+///
+/// # Safety
+/// `vk` must point to `vk_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lion_verifier_new(vk: *const u8, vk_len: usize) -> *mut LionVerifier {
+    if vk.is_null() {
+        return std::ptr::null_mut();
+    }
+    let bytes = slice::from_raw_parts(vk, vk_len);
+    match verifier_key_from_bytes::<Bls12_381>(bytes) {
+        Ok(vk) => Box::into_raw(Box::new(LionVerifier { vk })),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// # Safety
+/// `verifier` must be null or a handle returned by `lion_verifier_new` that has not been freed.
+#[no_mangle]
+pub unsafe extern "C" fn lion_verifier_free(verifier: *mut LionVerifier) {
+    if !verifier.is_null() {
+        drop(Box::from_raw(verifier));
+    }
+}
+
+/// Check the KZG opening of cell (rid, cid) in a square of side `length`.
+///
+/// # Safety
+/// `verifier` must be a live handle and `proof` must point to a `LionCellProof`.
+#[no_mangle]
+pub unsafe extern "C" fn lion_verify_cell_proof(
+    verifier: *const LionVerifier,
+    length: u64,
+    proof: *const LionCellProof,
+) -> i32 {
+    if verifier.is_null() || proof.is_null() {
+        return LION_ERR_NULL;
+    }
+    let proof = &*proof;
+
+    let value = match decode::<Fr>(&proof.value, Validation::Strict) {
+        Ok(value) => value,
+        Err(_) => return LION_ERR_DECODE,
+    };
+    let commitment = match decode::<G1Affine>(&proof.row_commitment, Validation::Strict) {
+        Ok(point) => Commitment::<Bls12_381>(point),
+        Err(_) => return LION_ERR_DECODE,
+    };
+    let witness = match decode::<G1Affine>(&proof.witness, Validation::Strict) {
+        Ok(w) => Proof::<Bls12_381> { w, random_v: None },
+        Err(_) => return LION_ERR_DECODE,
+    };
+
+    let valid = verify_cell_proof(
+        &(*verifier).vk,
+        &commitment,
+        length as usize,
+        proof.cid as usize,
+        value,
+        &witness,
+    );
+    if valid {
+        LION_VALID
+    } else {
+        LION_INVALID
+    }
+}
+
+/// Check that `root` commits to `row_root` and `col_root` under the root version
+/// with id `root_version`, as carried in the square's layout. Each other argument
+/// points to a `LION_HASH_BYTES` digest.
+///
+/// # Safety
+/// All pointers must point to `LION_HASH_BYTES` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lion_verify_root(
+    root_version: u8,
+    root: *const u8,
+    row_root: *const u8,
+    col_root: *const u8,
+) -> i32 {
+    let version = match RootVersion::from_id(root_version) {
+        Some(version) => version,
+        None => return LION_ERR_DECODE,
+    };
+    match (read_hash(root), read_hash(row_root), read_hash(col_root)) {
+        (Some(root), Some(row_root), Some(col_root)) => {
+            if verify_root::<Sha256>(version, root, row_root, col_root) {
+                LION_VALID
+            } else {
+                LION_INVALID
+            }
+        }
+        _ => LION_ERR_NULL,
+    }
+}
+
+/// Fully verify an encoded `CellSample` (wire format) against a data root built
+/// under the root version with id `root_version`.
+///
+/// # Safety
+/// `verifier` must be a live handle, `root` must point to `LION_HASH_BYTES` bytes
+/// and `sample` to `sample_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lion_verify_cell_sample(
+    verifier: *const LionVerifier,
+    root_version: u8,
+    root: *const u8,
+    length: u64,
+    sample: *const u8,
+    sample_len: usize,
+) -> i32 {
+    let version = match RootVersion::from_id(root_version) {
+        Some(version) => version,
+        None => return LION_ERR_DECODE,
+    };
+    let root = match read_hash(root) {
+        Some(root) => root,
+        None => return LION_ERR_NULL,
+    };
+    if verifier.is_null() || sample.is_null() {
+        return LION_ERR_NULL;
+    }
+    let sample = match CellSample::<Bls12_381, Sha256>::from_bytes(slice::from_raw_parts(
+        sample, sample_len,
+    )) {
+        Ok(sample) => sample,
+        Err(_) => return LION_ERR_DECODE,
+    };
+    if verify_cell_sample(&(*verifier).vk, version, root, length as usize, &sample) {
+        LION_VALID
+    } else {
+        LION_INVALID
+    }
+}
+
+/// Decode the opening part of an encoded `CellSample` into the fixed C layout.
+///
+/// # Safety
+/// `sample` must point to `sample_len` bytes and `out` to a writable `LionCellProof`.
+#[no_mangle]
+pub unsafe extern "C" fn lion_decode_cell_proof(
+    sample: *const u8,
+    sample_len: usize,
+    out: *mut LionCellProof,
+) -> i32 {
+    if sample.is_null() || out.is_null() {
+        return LION_ERR_NULL;
+    }
+    let sample = match CellSample::<Bls12_381, Sha256>::from_bytes(slice::from_raw_parts(
+        sample, sample_len,
+    )) {
+        Ok(sample) => sample,
+        Err(_) => return LION_ERR_DECODE,
+    };
+
+    let mut witness = vec![];
+    if sample.opening.w.serialize_compressed(&mut witness).is_err() {
+        return LION_ERR_DECODE;
+    }
+    let mut proof = LionCellProof {
+        rid: sample.rid as u64,
+        cid: sample.cid as u64,
+        value: [0u8; LION_FIELD_BYTES],
+        row_commitment: [0u8; LION_POINT_BYTES],
+        witness: [0u8; LION_POINT_BYTES],
+    };
+    proof.value.copy_from_slice(&field_to_bytes(&sample.value));
+    proof
+        .row_commitment
+        .copy_from_slice(&commitment_to_bytes(&sample.row_commitment));
+    proof.witness.copy_from_slice(&witness);
+
+    *out = proof;
+    LION_VALID
+}
+
+unsafe fn read_hash(ptr: *const u8) -> Option<[u8; LION_HASH_BYTES]> {
+    if ptr.is_null() {
+        return None;
+    }
+    let mut hash = [0u8; LION_HASH_BYTES];
+    hash.copy_from_slice(slice::from_raw_parts(ptr, LION_HASH_BYTES));
+    Some(hash)
+}
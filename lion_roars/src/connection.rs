@@ -1,2 +1,137 @@
+use crate::dht::PeerId;
+use crate::messages::LionMessages;
+use crate::noise::{Capabilities, NodeKeys, NoiseSession, MAX_CHUNK_LEN};
 
-pub struct Connection {}
\ No newline at end of file
+use std::io;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+/// Largest frame a peer may send (a 256x256 extended square with room to spare)
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// Length-prefixed framing of `LionMessages` over a Noise-encrypted TCP stream.
+/// Each frame is an encrypted length chunk followed by the encrypted message,
+/// split into chunks no larger than a Noise message.
+pub struct Connection {
+    stream: TcpStream,
+    session: NoiseSession,
+}
+
+impl Connection {
+    /// Handshake as the initiator over an open stream. `capabilities` identify
+    /// the KZG setup in use; peers on a different setup are refused.
+    pub async fn initiate(
+        mut stream: TcpStream,
+        keys: &NodeKeys,
+        expected: Option<PeerId>,
+        capabilities: &Capabilities,
+    ) -> io::Result<Self> {
+        let session = NoiseSession::initiate(&mut stream, keys, expected, capabilities).await?;
+        Ok(Self { stream, session })
+    }
+
+    /// Handshake as the responder over an accepted stream
+    pub async fn accept(
+        mut stream: TcpStream,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+    ) -> io::Result<Self> {
+        let session = NoiseSession::respond(&mut stream, keys, capabilities).await?;
+        Ok(Self { stream, session })
+    }
+
+    /// Connect to `addr` and authenticate it as `expected`, if given
+    pub async fn connect(
+        addr: impl tokio::net::ToSocketAddrs,
+        keys: &NodeKeys,
+        expected: Option<PeerId>,
+        capabilities: &Capabilities,
+    ) -> io::Result<Self> {
+        Self::initiate(
+            TcpStream::connect(addr).await?,
+            keys,
+            expected,
+            capabilities,
+        )
+        .await
+    }
+
+    /// Authenticated id of the remote peer
+    pub fn remote(&self) -> PeerId {
+        self.session.remote()
+    }
+
+    /// Setup and square sizes the remote announced in the handshake
+    pub fn remote_capabilities(&self) -> &Capabilities {
+        self.session.remote_capabilities()
+    }
+
+    /// Wait until the peer has sent something. Unlike `read_message` this may be
+    /// cancelled without losing part of a message.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.stream.readable().await
+    }
+
+    /// Next message from the peer, or `None` if the peer closed the connection
+    /// between messages.
+    pub async fn read_message(&mut self) -> io::Result<Option<LionMessages>> {
+        let len = match self.session.recv(&mut self.stream).await {
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let len: [u8; 4] = len
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Malformed frame length"))?;
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Frame of {} bytes exceeds limit", len),
+            ));
+        }
+
+        let mut frame = Vec::with_capacity(len);
+        while frame.len() < len {
+            let chunk = self.session.recv(&mut self.stream).await?;
+            if chunk.is_empty() || frame.len() + chunk.len() > len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Chunk does not fit the announced frame",
+                ));
+            }
+            frame.extend_from_slice(&chunk);
+        }
+        LionMessages::decode(&frame).map(Some)
+    }
+
+    /// Send `msg`, returning the size of its encoding
+    pub async fn write_message(&mut self, msg: &LionMessages) -> io::Result<usize> {
+        let frame = msg.encode();
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Frame of {} bytes exceeds limit", frame.len()),
+            ));
+        }
+        self.session
+            .send(&mut self.stream, &(frame.len() as u32).to_le_bytes())
+            .await?;
+        for chunk in frame.chunks(MAX_CHUNK_LEN) {
+            self.session.send(&mut self.stream, chunk).await?;
+        }
+        self.stream.flush().await?;
+        Ok(frame.len())
+    }
+
+    /// Send a request and wait for the peer's reply
+    pub async fn request(&mut self, msg: &LionMessages) -> io::Result<LionMessages> {
+        self.write_message(msg).await?;
+        self.read_message().await?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Peer closed connection before replying",
+            )
+        })
+    }
+}
@@ -0,0 +1,154 @@
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// 256-bit identifier of a peer in the Kademlia keyspace
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PeerId(pub [u8; 32]);
+
+impl PeerId {
+    /// Derive a peer id from the peer's public identity bytes (e.g. its public key)
+    pub fn from_public_bytes(bytes: &[u8]) -> Self {
+        PeerId(Sha256::digest(bytes).into())
+    }
+
+    /// XOR distance between this peer and a key in the keyspace
+    pub fn distance(&self, key: &[u8; 32]) -> [u8; 32] {
+        let mut dist = [0u8; 32];
+        for idx in 0..32 {
+            dist[idx] = self.0[idx] ^ key[idx];
+        }
+        dist
+    }
+}
+
+impl fmt::Display for PeerId {
+    /// Short hex prefix, enough to tell peers apart in logs and metrics
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.0[..8] {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Axis {
+    Row,
+    Col,
+}
+
+/// Contiguous range of rows or columns of the square at a given height
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LineRange {
+    pub height: u64,
+    pub axis: Axis,
+    /// Index of the range (line index / lines per range)
+    pub range_id: usize,
+}
+
+impl LineRange {
+    /// Location of this range in the keyspace
+    pub fn key(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.height.to_le_bytes());
+        hasher.update(match self.axis {
+            Axis::Row => [0u8],
+            Axis::Col => [1u8],
+        });
+        hasher.update((self.range_id as u64).to_le_bytes());
+        hasher.finalize().into()
+    }
+}
+
+/// Deterministic assignment of line ranges to the peers responsible for storing them.
+/// Every node holding the same peer set computes the same custodians for a range.
+#[derive(Clone, Debug)]
+pub struct CustodyTable {
+    /// Peers currently known to the local node
+    peers: Vec<PeerId>,
+    /// Number of peers responsible for each line range
+    replication: usize,
+    /// Number of consecutive lines grouped into one range
+    lines_per_range: usize,
+}
+
+impl CustodyTable {
+    pub fn new(replication: usize, lines_per_range: usize) -> Self {
+        assert!(replication > 0, "Replication factor must be positive");
+        assert!(lines_per_range > 0, "Lines per range must be positive");
+        Self {
+            peers: vec![],
+            replication,
+            lines_per_range,
+        }
+    }
+
+    pub fn add_peer(&mut self, peer: PeerId) {
+        if !self.peers.contains(&peer) {
+            self.peers.push(peer);
+        }
+    }
+
+    pub fn remove_peer(&mut self, peer: &PeerId) {
+        self.peers.retain(|p| p != peer);
+    }
+
+    pub fn peers(&self) -> &[PeerId] {
+        &self.peers
+    }
+
+    pub fn range_of(&self, height: u64, axis: Axis, line: usize) -> LineRange {
+        LineRange {
+            height,
+            axis,
+            range_id: line / self.lines_per_range,
+        }
+    }
+
+    /// The `replication` peers closest (by XOR distance) to the range's key
+    pub fn custodians(&self, range: &LineRange) -> Vec<PeerId> {
+        let key = range.key();
+        let mut peers = self.peers.clone();
+        peers.sort_by_key(|peer| peer.distance(&key));
+        peers.truncate(self.replication);
+        peers
+    }
+
+    /// Custodians able to serve cell (rid, cid): holders of its row range first,
+    /// then holders of its column range.
+    pub fn custodians_of_cell(&self, height: u64, rid: usize, cid: usize) -> Vec<PeerId> {
+        let mut peers = self.custodians(&self.range_of(height, Axis::Row, rid));
+        for peer in self.custodians(&self.range_of(height, Axis::Col, cid)) {
+            if !peers.contains(&peer) {
+                peers.push(peer);
+            }
+        }
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Axis, CustodyTable, PeerId};
+
+    #[test]
+    pub fn custody_is_deterministic_and_replicated() {
+        let mut table_a = CustodyTable::new(3, 4);
+        let mut table_b = CustodyTable::new(3, 4);
+        let peers: Vec<PeerId> = (0..10u8).map(|i| PeerId::from_public_bytes(&[i])).collect();
+        for peer in peers.iter() {
+            table_a.add_peer(*peer);
+        }
+        // insertion order must not affect the assignment
+        for peer in peers.iter().rev() {
+            table_b.add_peer(*peer);
+        }
+
+        let range = table_a.range_of(7, Axis::Row, 9);
+        assert_eq!(range.range_id, 2);
+        assert_eq!(table_a.custodians(&range).len(), 3);
+        assert_eq!(table_a.custodians(&range), table_b.custodians(&range));
+    }
+}
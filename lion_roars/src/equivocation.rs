@@ -0,0 +1,156 @@
+use crate::dht::PeerId;
+use crate::header::SignedHeader;
+use crate::messages::Reader;
+
+use std::collections::HashMap;
+use std::io;
+
+/// Two headers validly signed by the same producer for the same height but with
+/// different data roots. Anyone holding the proof can check it without further context.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EquivocationProof {
+    pub first: SignedHeader,
+    pub second: SignedHeader,
+}
+
+impl EquivocationProof {
+    /// `None` unless the two headers actually prove an equivocation
+    pub fn new(first: SignedHeader, second: SignedHeader) -> Option<Self> {
+        let proof = Self { first, second };
+        proof.verify().then_some(proof)
+    }
+
+    pub fn height(&self) -> u64 {
+        self.first.header.height
+    }
+
+    pub fn producer_id(&self) -> PeerId {
+        self.first.producer_id()
+    }
+
+    pub fn verify(&self) -> bool {
+        self.first.producer == self.second.producer
+            && self.first.header.height == self.second.header.height
+            && self.first.header.data_root != self.second.header.data_root
+            && self.first.verify()
+            && self.second.verify()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.first.write(&mut out);
+        self.second.write(&mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let proof = Self {
+            first: SignedHeader::read(&mut reader)?,
+            second: SignedHeader::read(&mut reader)?,
+        };
+        reader.finish()?;
+        Ok(proof)
+    }
+}
+
+/// Remembers the first signed header seen from each producer at each height and
+/// reports a proof as soon as a conflicting one turns up
+#[derive(Default)]
+pub struct EquivocationDetector {
+    /// First header per (producer, height), and whether an equivocation was already reported
+    seen: HashMap<([u8; 32], u64), (SignedHeader, bool)>,
+}
+
+impl EquivocationDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check `signed` against earlier headers. Headers with an invalid signature are
+    /// ignored, and each producer and height is reported at most once.
+    pub fn observe(&mut self, signed: SignedHeader) -> Option<EquivocationProof> {
+        if !signed.verify() {
+            return None;
+        }
+        let key = (signed.producer, signed.header.height);
+        match self.seen.get_mut(&key) {
+            None => {
+                self.seen.insert(key, (signed, false));
+                None
+            }
+            Some((_, true)) => None,
+            Some((first, reported)) => {
+                let proof = EquivocationProof::new(first.clone(), signed)?;
+                *reported = true;
+                Some(proof)
+            }
+        }
+    }
+
+    /// First header seen from each producer at `height`
+    pub fn at_height(&self, height: u64) -> impl Iterator<Item = &SignedHeader> + '_ {
+        self.seen
+            .iter()
+            .filter(move |((_, seen_height), _)| *seen_height == height)
+            .map(|(_, (signed, _))| signed)
+    }
+
+    /// Highest height any header was seen at
+    pub fn latest_height(&self) -> Option<u64> {
+        self.seen.keys().map(|(_, height)| *height).max()
+    }
+
+    /// Forget headers below `height`
+    pub fn prune_below(&mut self, height: u64) {
+        self.seen
+            .retain(|(_, seen_height), _| *seen_height >= height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EquivocationDetector, EquivocationProof};
+    use crate::header::{Header, ProducerKey};
+
+    fn header(height: u64, root: u8) -> Header {
+        Header {
+            height,
+            data_root: vec![root; 32],
+            vk_digest: vec![1; 32],
+            namespace_root: vec![],
+            parent: vec![],
+            layout: vec![],
+        }
+    }
+
+    #[test]
+    pub fn conflicting_roots_are_proven() {
+        let producer = ProducerKey::generate();
+        let mut detector = EquivocationDetector::new();
+        assert_eq!(detector.observe(producer.sign(header(5, 1))), None);
+        assert_eq!(detector.observe(producer.sign(header(5, 1))), None);
+        assert_eq!(detector.observe(producer.sign(header(6, 2))), None);
+        assert_eq!(detector.latest_height(), Some(6));
+        assert_eq!(detector.at_height(5).count(), 1);
+
+        let proof = detector.observe(producer.sign(header(5, 2))).unwrap();
+        assert!(proof.verify());
+        assert_eq!(proof.height(), 5);
+        assert_eq!(
+            EquivocationProof::from_bytes(&proof.to_bytes()).unwrap(),
+            proof
+        );
+        assert_eq!(detector.observe(producer.sign(header(5, 3))), None);
+
+        // a header re-signed by someone else proves nothing
+        let mut forged = producer.sign(header(6, 3));
+        forged.signature = ProducerKey::generate().sign(header(6, 3)).signature;
+        assert_eq!(detector.observe(forged), None);
+
+        // headers below a pruned height are forgotten, so nothing conflicts
+        detector.prune_below(7);
+        assert_eq!(detector.observe(producer.sign(header(6, 3))), None);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::dht::PeerId;
+use crate::messages::{put_bytes, put_u64, Reader};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// Prefix of every signed header encoding, so header signatures cannot be
+/// replayed as signatures over anything else
+const HEADER_SIGNING_DOMAIN: &[u8] = b"lazy-lion/header/v1";
+
+/// Header announcing the square produced at a given height
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
+    pub height: u64,
+    /// Merkle root over the row and column commitments of the square
+    pub data_root: Vec<u8>,
+    /// Digest of the verifier key the square's commitments check against
+    pub vk_digest: Vec<u8>,
+    /// Merkle root of the block's namespace index, empty if the block has none
+    pub namespace_root: Vec<u8>,
+    /// `hash` of the header at the previous height, empty for the first header of
+    /// a chain and for headers a full node derives from a square on its own
+    pub parent: Vec<u8>,
+    /// Encoded `SquareLayout` of the square, so peers learn its side from the
+    /// header alone. Empty for headers that do not carry one.
+    pub layout: Vec<u8>,
+}
+
+impl Header {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.write(&mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let header = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(header)
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        put_u64(out, self.height);
+        put_bytes(out, &self.data_root);
+        put_bytes(out, &self.vk_digest);
+        put_bytes(out, &self.namespace_root);
+        put_bytes(out, &self.parent);
+        put_bytes(out, &self.layout);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> io::Result<Self> {
+        Ok(Self {
+            height: reader.u64()?,
+            data_root: reader.bytes()?,
+            vk_digest: reader.bytes()?,
+            namespace_root: reader.bytes()?,
+            parent: reader.bytes()?,
+            layout: reader.bytes()?,
+        })
+    }
+
+    /// Digest the next header names as its parent
+    pub fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.to_bytes()).into()
+    }
+
+    fn signing_bytes(&self) -> Vec<u8> {
+        let mut out = HEADER_SIGNING_DOMAIN.to_vec();
+        self.write(&mut out);
+        out
+    }
+}
+
+/// Key a block producer signs its headers with
+pub struct ProducerKey {
+    signing: SigningKey,
+}
+
+impl ProducerKey {
+    pub fn generate() -> Self {
+        Self {
+            signing: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    pub fn from_bytes(secret: &[u8; 32]) -> Self {
+        Self {
+            signing: SigningKey::from_bytes(secret),
+        }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.signing.verifying_key().to_bytes()
+    }
+
+    pub fn sign(&self, header: Header) -> SignedHeader {
+        let signature = self.signing.sign(&header.signing_bytes()).to_bytes();
+        SignedHeader {
+            header,
+            producer: self.public(),
+            signature,
+        }
+    }
+}
+
+/// Header together with its producer's signature over it
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignedHeader {
+    pub header: Header,
+    /// Public key of the producer that signed the header
+    pub producer: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(with = "signature_bytes"))]
+    pub signature: [u8; 64],
+}
+
+/// `serde` only derives arrays of up to 32 elements
+#[cfg(feature = "serde")]
+mod signature_bytes {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        signature: &[u8; 64],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(signature)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 64], D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        bytes
+            .try_into()
+            .map_err(|_| D::Error::custom("Signatures are 64 bytes"))
+    }
+}
+
+impl SignedHeader {
+    /// Peer id derived from the producer's signing key
+    pub fn producer_id(&self) -> PeerId {
+        PeerId::from_public_bytes(&self.producer)
+    }
+
+    /// Whether the signature is valid for the header under the producer's key
+    pub fn verify(&self) -> bool {
+        match VerifyingKey::from_bytes(&self.producer) {
+            Ok(key) => key
+                .verify_strict(
+                    &self.header.signing_bytes(),
+                    &Signature::from_bytes(&self.signature),
+                )
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.write(&mut out);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let signed = Self::read(&mut reader)?;
+        reader.finish()?;
+        Ok(signed)
+    }
+
+    pub(crate) fn write(&self, out: &mut Vec<u8>) {
+        self.header.write(out);
+        out.extend_from_slice(&self.producer);
+        out.extend_from_slice(&self.signature);
+    }
+
+    pub(crate) fn read(reader: &mut Reader) -> io::Result<Self> {
+        Ok(Self {
+            header: Header::read(reader)?,
+            producer: reader.array()?,
+            signature: reader.array()?,
+        })
+    }
+}
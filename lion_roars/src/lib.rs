@@ -1,2 +1,9 @@
 pub mod connection;
+pub mod dht;
+pub mod equivocation;
+pub mod header;
 pub mod messages;
+pub mod noise;
+pub mod scoring;
+pub mod shutdown;
+pub mod vrf;
@@ -1,2 +1,974 @@
+use crate::dht::Axis;
 
-pub enum LionMessages {}
\ No newline at end of file
+use std::io;
+
+/// Messages exchanged between lion nodes. Field elements, commitments and
+/// hashes travel as canonical byte encodings so this crate stays agnostic
+/// of the curve and hasher in use.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LionMessages {
+    /// Every row of the extended (length x length) square
+    ExtendedSquare {
+        height: u64,
+        scale: usize,
+        rows: Vec<Vec<Vec<u8>>>,
+        row_commitments: Vec<Vec<u8>>,
+        col_commitments: Vec<Vec<u8>>,
+        root: Vec<u8>,
+    },
+    /// Only the original (n x n) shares; receivers re-extend them locally
+    OriginalShares {
+        height: u64,
+        scale: usize,
+        shares: Vec<Vec<Vec<u8>>>,
+        row_commitments: Vec<Vec<u8>>,
+        col_commitments: Vec<Vec<u8>>,
+        root: Vec<u8>,
+    },
+    /// Ask for cell (rid, cid) of the square at `height`
+    SampleRequest { height: u64, rid: usize, cid: usize },
+    /// Encoded cell sample answering a `SampleRequest`
+    SampleResponse { height: u64, sample: Vec<u8> },
+    /// The peer cannot serve the requested cell
+    SampleUnavailable { height: u64, rid: usize, cid: usize },
+    /// Present a token unlocking privileged methods for the rest of the connection
+    Authenticate { token: Vec<u8> },
+    /// The token was accepted
+    Authenticated,
+    /// The request was refused
+    Denied { reason: String },
+    /// Ask for the verifier key of the peer's setup
+    VerifierKeyRequest,
+    /// Versioned encoding of the verifier key answering a `VerifierKeyRequest`
+    VerifierKeyResponse { vk: Vec<u8> },
+    /// Ask for every cell of one row or column of the square at `height`
+    LineRequest { height: u64, axis: Axis, idx: usize },
+    /// Encoded line sample answering a `LineRequest`
+    LineResponse { height: u64, sample: Vec<u8> },
+    /// The peer cannot serve the requested line
+    LineUnavailable { height: u64, axis: Axis, idx: usize },
+    /// Ask for every share of `namespace` in the square at `height`
+    NamespaceRequest { height: u64, namespace: Vec<u8> },
+    /// Encoded namespace shares and completeness proof answering a `NamespaceRequest`
+    NamespaceResponse { height: u64, data: Vec<u8> },
+    /// The peer holds no namespace layout for the square at `height`
+    NamespaceUnavailable { height: u64, namespace: Vec<u8> },
+    /// Bisection round over row `rid`: ask for the digests of both halves of `lo..hi`
+    BisectionRequest {
+        height: u64,
+        rid: usize,
+        lo: usize,
+        hi: usize,
+    },
+    /// Digests of the halves of `lo..hi` answering a `BisectionRequest`
+    BisectionResponse {
+        height: u64,
+        rid: usize,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    },
+    /// The peer cannot take part in a bisection over the requested row
+    BisectionUnavailable { height: u64, rid: usize },
+    /// Encoded signed header, relayed so that equivocating producers are noticed
+    HeaderAnnouncement { header: Vec<u8> },
+    /// The peer used up its bandwidth quota; retry after the given time
+    RateLimited { retry_after_ms: u64 },
+    /// Prove custody of the square at `height` by opening `count` cells derived from `seed`
+    AuditChallenge {
+        height: u64,
+        seed: Vec<u8>,
+        count: usize,
+    },
+    /// Encoded cell samples of the challenged cells, in order, answering an `AuditChallenge`
+    AuditResponse { height: u64, samples: Vec<Vec<u8>> },
+    /// The peer holds no cell data for the square at `height`
+    AuditUnavailable { height: u64 },
+    /// Forward the encoded sampling `request` to the peer at `target` and relay
+    /// its answer, so the target does not learn who asked
+    RelayRequest { target: String, request: Vec<u8> },
+    /// Encoded reply of the target answering a `RelayRequest`
+    RelayResponse { response: Vec<u8> },
+    /// The peer does not relay, or could not reach `target`
+    RelayUnavailable { target: String },
+    /// Ask which cells of the square at `height` the peer holds
+    AvailabilityRequest { height: u64 },
+    /// Compressed bitfield of the cells the sender holds at `height`, gossiped
+    /// unprompted or answering an `AvailabilityRequest`
+    AvailabilityAnnouncement { height: u64, bitfield: Vec<u8> },
+    /// Ask for the data root and held cells of every height in `from..=to`
+    SyncRequest { from: u64, to: u64 },
+    /// Data root and compressed bitfield of held cells of each listed height,
+    /// answering a `SyncRequest`
+    SyncSummary {
+        heights: Vec<u64>,
+        roots: Vec<Vec<u8>>,
+        bitfields: Vec<Vec<u8>>,
+    },
+    /// Ask for the whole square at `height`, answered by a dissemination message
+    SquareRequest { height: u64 },
+    /// The peer holds no cell data for the square at `height`
+    SquareUnavailable { height: u64 },
+    /// The producer's share of the (length x length) square under `root` assigned
+    /// to the receiver, as encoded cell samples. Receivers rebuild the rest from
+    /// each other.
+    CellBatch {
+        height: u64,
+        length: usize,
+        scale: usize,
+        root: Vec<u8>,
+        samples: Vec<Vec<u8>>,
+    },
+    /// Ask for a proof that the blob of `len` bytes at shares `start..end` of
+    /// `namespace`, laid out under layout `version`, is part of the square at `height`
+    BlobRequest {
+        height: u64,
+        namespace: Vec<u8>,
+        version: u64,
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    /// Encoded blob inclusion proof, and proof of the namespace's range, answering
+    /// a `BlobRequest`
+    BlobResponse {
+        height: u64,
+        proof: Vec<u8>,
+        namespace_proof: Vec<u8>,
+    },
+    /// The peer cannot prove the requested blob
+    BlobUnavailable { height: u64 },
+    /// Prove the square at `height` is still held by opening the cells the header
+    /// at `anchor_height` challenges
+    StorageChallenge { height: u64, anchor_height: u64 },
+    /// Encoded cell samples of the challenged cells, in order, answering a
+    /// `StorageChallenge`
+    StorageProof {
+        height: u64,
+        anchor_height: u64,
+        samples: Vec<Vec<u8>>,
+    },
+    /// The peer holds no cell data for the square at `height`, or does not know
+    /// the anchor
+    StorageUnavailable { height: u64 },
+    /// Ask for every row and column commitment of the square at `height`
+    CommitmentsRequest { height: u64 },
+    /// The data root of the square at `height` and its row and column commitments
+    /// in order, from which the root is recomputed
+    Commitments {
+        height: u64,
+        root: Vec<u8>,
+        row_commitments: Vec<Vec<u8>>,
+        col_commitments: Vec<Vec<u8>>,
+    },
+    /// The peer holds no commitments for the square at `height`
+    CommitmentsUnavailable { height: u64 },
+    /// Ask for the producer-signed header of the square at `height`, answered by a
+    /// `HeaderAnnouncement`
+    HeaderRequest { height: u64 },
+    /// The peer knows no signed header for the square at `height`
+    HeaderUnavailable { height: u64 },
+    /// Ask for every cell (rid, cid) in `cells` of the square at `height` at once
+    CellsRequest {
+        height: u64,
+        cells: Vec<(usize, usize)>,
+    },
+    /// Encoded cell samples of the requested cells the peer holds, in request
+    /// order, answering a `CellsRequest`
+    CellsResponse { height: u64, samples: Vec<Vec<u8>> },
+}
+
+const TAG_EXTENDED_SQUARE: u8 = 0;
+const TAG_ORIGINAL_SHARES: u8 = 1;
+const TAG_SAMPLE_REQUEST: u8 = 2;
+const TAG_SAMPLE_RESPONSE: u8 = 3;
+const TAG_SAMPLE_UNAVAILABLE: u8 = 4;
+const TAG_AUTHENTICATE: u8 = 5;
+const TAG_AUTHENTICATED: u8 = 6;
+const TAG_DENIED: u8 = 7;
+const TAG_VERIFIER_KEY_REQUEST: u8 = 8;
+const TAG_VERIFIER_KEY_RESPONSE: u8 = 9;
+const TAG_LINE_REQUEST: u8 = 10;
+const TAG_LINE_RESPONSE: u8 = 11;
+const TAG_LINE_UNAVAILABLE: u8 = 12;
+const TAG_NAMESPACE_REQUEST: u8 = 13;
+const TAG_NAMESPACE_RESPONSE: u8 = 14;
+const TAG_NAMESPACE_UNAVAILABLE: u8 = 15;
+const TAG_BISECTION_REQUEST: u8 = 16;
+const TAG_BISECTION_RESPONSE: u8 = 17;
+const TAG_BISECTION_UNAVAILABLE: u8 = 18;
+const TAG_HEADER_ANNOUNCEMENT: u8 = 19;
+const TAG_RATE_LIMITED: u8 = 20;
+const TAG_AUDIT_CHALLENGE: u8 = 21;
+const TAG_AUDIT_RESPONSE: u8 = 22;
+const TAG_AUDIT_UNAVAILABLE: u8 = 23;
+const TAG_RELAY_REQUEST: u8 = 24;
+const TAG_RELAY_RESPONSE: u8 = 25;
+const TAG_RELAY_UNAVAILABLE: u8 = 26;
+const TAG_AVAILABILITY_REQUEST: u8 = 27;
+const TAG_AVAILABILITY_ANNOUNCEMENT: u8 = 28;
+const TAG_SYNC_REQUEST: u8 = 29;
+const TAG_SYNC_SUMMARY: u8 = 30;
+const TAG_SQUARE_REQUEST: u8 = 31;
+const TAG_SQUARE_UNAVAILABLE: u8 = 32;
+const TAG_CELL_BATCH: u8 = 33;
+const TAG_BLOB_REQUEST: u8 = 34;
+const TAG_BLOB_RESPONSE: u8 = 35;
+const TAG_BLOB_UNAVAILABLE: u8 = 36;
+const TAG_STORAGE_CHALLENGE: u8 = 37;
+const TAG_STORAGE_PROOF: u8 = 38;
+const TAG_STORAGE_UNAVAILABLE: u8 = 39;
+const TAG_COMMITMENTS_REQUEST: u8 = 40;
+const TAG_COMMITMENTS: u8 = 41;
+const TAG_COMMITMENTS_UNAVAILABLE: u8 = 42;
+const TAG_HEADER_REQUEST: u8 = 43;
+const TAG_HEADER_UNAVAILABLE: u8 = 44;
+const TAG_CELLS_REQUEST: u8 = 45;
+const TAG_CELLS_RESPONSE: u8 = 46;
+
+impl LionMessages {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        match self {
+            LionMessages::ExtendedSquare {
+                height,
+                scale,
+                rows,
+                row_commitments,
+                col_commitments,
+                root,
+            } => {
+                out.push(TAG_EXTENDED_SQUARE);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *scale as u64);
+                put_grid(&mut out, rows);
+                put_list(&mut out, row_commitments);
+                put_list(&mut out, col_commitments);
+                put_bytes(&mut out, root);
+            }
+            LionMessages::OriginalShares {
+                height,
+                scale,
+                shares,
+                row_commitments,
+                col_commitments,
+                root,
+            } => {
+                out.push(TAG_ORIGINAL_SHARES);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *scale as u64);
+                put_grid(&mut out, shares);
+                put_list(&mut out, row_commitments);
+                put_list(&mut out, col_commitments);
+                put_bytes(&mut out, root);
+            }
+            LionMessages::SampleRequest { height, rid, cid } => {
+                out.push(TAG_SAMPLE_REQUEST);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *rid as u64);
+                put_u64(&mut out, *cid as u64);
+            }
+            LionMessages::SampleResponse { height, sample } => {
+                out.push(TAG_SAMPLE_RESPONSE);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, sample);
+            }
+            LionMessages::SampleUnavailable { height, rid, cid } => {
+                out.push(TAG_SAMPLE_UNAVAILABLE);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *rid as u64);
+                put_u64(&mut out, *cid as u64);
+            }
+            LionMessages::Authenticate { token } => {
+                out.push(TAG_AUTHENTICATE);
+                put_bytes(&mut out, token);
+            }
+            LionMessages::Authenticated => out.push(TAG_AUTHENTICATED),
+            LionMessages::Denied { reason } => {
+                out.push(TAG_DENIED);
+                put_bytes(&mut out, reason.as_bytes());
+            }
+            LionMessages::VerifierKeyRequest => out.push(TAG_VERIFIER_KEY_REQUEST),
+            LionMessages::VerifierKeyResponse { vk } => {
+                out.push(TAG_VERIFIER_KEY_RESPONSE);
+                put_bytes(&mut out, vk);
+            }
+            LionMessages::LineRequest { height, axis, idx } => {
+                out.push(TAG_LINE_REQUEST);
+                put_line(&mut out, *height, *axis, *idx);
+            }
+            LionMessages::LineResponse { height, sample } => {
+                out.push(TAG_LINE_RESPONSE);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, sample);
+            }
+            LionMessages::LineUnavailable { height, axis, idx } => {
+                out.push(TAG_LINE_UNAVAILABLE);
+                put_line(&mut out, *height, *axis, *idx);
+            }
+            LionMessages::NamespaceRequest { height, namespace } => {
+                out.push(TAG_NAMESPACE_REQUEST);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, namespace);
+            }
+            LionMessages::NamespaceResponse { height, data } => {
+                out.push(TAG_NAMESPACE_RESPONSE);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, data);
+            }
+            LionMessages::NamespaceUnavailable { height, namespace } => {
+                out.push(TAG_NAMESPACE_UNAVAILABLE);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, namespace);
+            }
+            LionMessages::BisectionRequest {
+                height,
+                rid,
+                lo,
+                hi,
+            } => {
+                out.push(TAG_BISECTION_REQUEST);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *rid as u64);
+                put_u64(&mut out, *lo as u64);
+                put_u64(&mut out, *hi as u64);
+            }
+            LionMessages::BisectionResponse {
+                height,
+                rid,
+                left,
+                right,
+            } => {
+                out.push(TAG_BISECTION_RESPONSE);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *rid as u64);
+                put_bytes(&mut out, left);
+                put_bytes(&mut out, right);
+            }
+            LionMessages::BisectionUnavailable { height, rid } => {
+                out.push(TAG_BISECTION_UNAVAILABLE);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *rid as u64);
+            }
+            LionMessages::HeaderAnnouncement { header } => {
+                out.push(TAG_HEADER_ANNOUNCEMENT);
+                put_bytes(&mut out, header);
+            }
+            LionMessages::RateLimited { retry_after_ms } => {
+                out.push(TAG_RATE_LIMITED);
+                put_u64(&mut out, *retry_after_ms);
+            }
+            LionMessages::AuditChallenge {
+                height,
+                seed,
+                count,
+            } => {
+                out.push(TAG_AUDIT_CHALLENGE);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, seed);
+                put_u64(&mut out, *count as u64);
+            }
+            LionMessages::AuditResponse { height, samples } => {
+                out.push(TAG_AUDIT_RESPONSE);
+                put_u64(&mut out, *height);
+                put_list(&mut out, samples);
+            }
+            LionMessages::AuditUnavailable { height } => {
+                out.push(TAG_AUDIT_UNAVAILABLE);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::RelayRequest { target, request } => {
+                out.push(TAG_RELAY_REQUEST);
+                put_bytes(&mut out, target.as_bytes());
+                put_bytes(&mut out, request);
+            }
+            LionMessages::RelayResponse { response } => {
+                out.push(TAG_RELAY_RESPONSE);
+                put_bytes(&mut out, response);
+            }
+            LionMessages::RelayUnavailable { target } => {
+                out.push(TAG_RELAY_UNAVAILABLE);
+                put_bytes(&mut out, target.as_bytes());
+            }
+            LionMessages::AvailabilityRequest { height } => {
+                out.push(TAG_AVAILABILITY_REQUEST);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::AvailabilityAnnouncement { height, bitfield } => {
+                out.push(TAG_AVAILABILITY_ANNOUNCEMENT);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, bitfield);
+            }
+            LionMessages::SyncRequest { from, to } => {
+                out.push(TAG_SYNC_REQUEST);
+                put_u64(&mut out, *from);
+                put_u64(&mut out, *to);
+            }
+            LionMessages::SyncSummary {
+                heights,
+                roots,
+                bitfields,
+            } => {
+                out.push(TAG_SYNC_SUMMARY);
+                put_u64(&mut out, heights.len() as u64);
+                for height in heights {
+                    put_u64(&mut out, *height);
+                }
+                put_list(&mut out, roots);
+                put_list(&mut out, bitfields);
+            }
+            LionMessages::SquareRequest { height } => {
+                out.push(TAG_SQUARE_REQUEST);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::SquareUnavailable { height } => {
+                out.push(TAG_SQUARE_UNAVAILABLE);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::CellBatch {
+                height,
+                length,
+                scale,
+                root,
+                samples,
+            } => {
+                out.push(TAG_CELL_BATCH);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *length as u64);
+                put_u64(&mut out, *scale as u64);
+                put_bytes(&mut out, root);
+                put_list(&mut out, samples);
+            }
+            LionMessages::BlobRequest {
+                height,
+                namespace,
+                version,
+                start,
+                end,
+                len,
+            } => {
+                out.push(TAG_BLOB_REQUEST);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, namespace);
+                put_u64(&mut out, *version);
+                put_u64(&mut out, *start as u64);
+                put_u64(&mut out, *end as u64);
+                put_u64(&mut out, *len as u64);
+            }
+            LionMessages::BlobResponse {
+                height,
+                proof,
+                namespace_proof,
+            } => {
+                out.push(TAG_BLOB_RESPONSE);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, proof);
+                put_bytes(&mut out, namespace_proof);
+            }
+            LionMessages::BlobUnavailable { height } => {
+                out.push(TAG_BLOB_UNAVAILABLE);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::StorageChallenge {
+                height,
+                anchor_height,
+            } => {
+                out.push(TAG_STORAGE_CHALLENGE);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *anchor_height);
+            }
+            LionMessages::StorageProof {
+                height,
+                anchor_height,
+                samples,
+            } => {
+                out.push(TAG_STORAGE_PROOF);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, *anchor_height);
+                put_list(&mut out, samples);
+            }
+            LionMessages::StorageUnavailable { height } => {
+                out.push(TAG_STORAGE_UNAVAILABLE);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::CommitmentsRequest { height } => {
+                out.push(TAG_COMMITMENTS_REQUEST);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::Commitments {
+                height,
+                root,
+                row_commitments,
+                col_commitments,
+            } => {
+                out.push(TAG_COMMITMENTS);
+                put_u64(&mut out, *height);
+                put_bytes(&mut out, root);
+                put_list(&mut out, row_commitments);
+                put_list(&mut out, col_commitments);
+            }
+            LionMessages::CommitmentsUnavailable { height } => {
+                out.push(TAG_COMMITMENTS_UNAVAILABLE);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::HeaderRequest { height } => {
+                out.push(TAG_HEADER_REQUEST);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::HeaderUnavailable { height } => {
+                out.push(TAG_HEADER_UNAVAILABLE);
+                put_u64(&mut out, *height);
+            }
+            LionMessages::CellsRequest { height, cells } => {
+                out.push(TAG_CELLS_REQUEST);
+                put_u64(&mut out, *height);
+                put_u64(&mut out, cells.len() as u64);
+                for (rid, cid) in cells {
+                    put_u64(&mut out, *rid as u64);
+                    put_u64(&mut out, *cid as u64);
+                }
+            }
+            LionMessages::CellsResponse { height, samples } => {
+                out.push(TAG_CELLS_RESPONSE);
+                put_u64(&mut out, *height);
+                put_list(&mut out, samples);
+            }
+        }
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let msg = match reader.u8()? {
+            TAG_EXTENDED_SQUARE => LionMessages::ExtendedSquare {
+                height: reader.u64()?,
+                scale: reader.usize()?,
+                rows: reader.grid()?,
+                row_commitments: reader.list()?,
+                col_commitments: reader.list()?,
+                root: reader.bytes()?,
+            },
+            TAG_ORIGINAL_SHARES => LionMessages::OriginalShares {
+                height: reader.u64()?,
+                scale: reader.usize()?,
+                shares: reader.grid()?,
+                row_commitments: reader.list()?,
+                col_commitments: reader.list()?,
+                root: reader.bytes()?,
+            },
+            TAG_SAMPLE_REQUEST => LionMessages::SampleRequest {
+                height: reader.u64()?,
+                rid: reader.usize()?,
+                cid: reader.usize()?,
+            },
+            TAG_SAMPLE_RESPONSE => LionMessages::SampleResponse {
+                height: reader.u64()?,
+                sample: reader.bytes()?,
+            },
+            TAG_SAMPLE_UNAVAILABLE => LionMessages::SampleUnavailable {
+                height: reader.u64()?,
+                rid: reader.usize()?,
+                cid: reader.usize()?,
+            },
+            TAG_AUTHENTICATE => LionMessages::Authenticate {
+                token: reader.bytes()?,
+            },
+            TAG_AUTHENTICATED => LionMessages::Authenticated,
+            TAG_DENIED => LionMessages::Denied {
+                reason: reader.string()?,
+            },
+            TAG_VERIFIER_KEY_REQUEST => LionMessages::VerifierKeyRequest,
+            TAG_VERIFIER_KEY_RESPONSE => LionMessages::VerifierKeyResponse {
+                vk: reader.bytes()?,
+            },
+            TAG_LINE_REQUEST => LionMessages::LineRequest {
+                height: reader.u64()?,
+                axis: reader.axis()?,
+                idx: reader.usize()?,
+            },
+            TAG_LINE_RESPONSE => LionMessages::LineResponse {
+                height: reader.u64()?,
+                sample: reader.bytes()?,
+            },
+            TAG_LINE_UNAVAILABLE => LionMessages::LineUnavailable {
+                height: reader.u64()?,
+                axis: reader.axis()?,
+                idx: reader.usize()?,
+            },
+            TAG_NAMESPACE_REQUEST => LionMessages::NamespaceRequest {
+                height: reader.u64()?,
+                namespace: reader.bytes()?,
+            },
+            TAG_NAMESPACE_RESPONSE => LionMessages::NamespaceResponse {
+                height: reader.u64()?,
+                data: reader.bytes()?,
+            },
+            TAG_NAMESPACE_UNAVAILABLE => LionMessages::NamespaceUnavailable {
+                height: reader.u64()?,
+                namespace: reader.bytes()?,
+            },
+            TAG_BISECTION_REQUEST => LionMessages::BisectionRequest {
+                height: reader.u64()?,
+                rid: reader.usize()?,
+                lo: reader.usize()?,
+                hi: reader.usize()?,
+            },
+            TAG_BISECTION_RESPONSE => LionMessages::BisectionResponse {
+                height: reader.u64()?,
+                rid: reader.usize()?,
+                left: reader.bytes()?,
+                right: reader.bytes()?,
+            },
+            TAG_BISECTION_UNAVAILABLE => LionMessages::BisectionUnavailable {
+                height: reader.u64()?,
+                rid: reader.usize()?,
+            },
+            TAG_HEADER_ANNOUNCEMENT => LionMessages::HeaderAnnouncement {
+                header: reader.bytes()?,
+            },
+            TAG_RATE_LIMITED => LionMessages::RateLimited {
+                retry_after_ms: reader.u64()?,
+            },
+            TAG_AUDIT_CHALLENGE => LionMessages::AuditChallenge {
+                height: reader.u64()?,
+                seed: reader.bytes()?,
+                count: reader.usize()?,
+            },
+            TAG_AUDIT_RESPONSE => LionMessages::AuditResponse {
+                height: reader.u64()?,
+                samples: reader.list()?,
+            },
+            TAG_AUDIT_UNAVAILABLE => LionMessages::AuditUnavailable {
+                height: reader.u64()?,
+            },
+            TAG_RELAY_REQUEST => LionMessages::RelayRequest {
+                target: reader.string()?,
+                request: reader.bytes()?,
+            },
+            TAG_RELAY_RESPONSE => LionMessages::RelayResponse {
+                response: reader.bytes()?,
+            },
+            TAG_RELAY_UNAVAILABLE => LionMessages::RelayUnavailable {
+                target: reader.string()?,
+            },
+            TAG_AVAILABILITY_REQUEST => LionMessages::AvailabilityRequest {
+                height: reader.u64()?,
+            },
+            TAG_AVAILABILITY_ANNOUNCEMENT => LionMessages::AvailabilityAnnouncement {
+                height: reader.u64()?,
+                bitfield: reader.bytes()?,
+            },
+            TAG_SYNC_REQUEST => LionMessages::SyncRequest {
+                from: reader.u64()?,
+                to: reader.u64()?,
+            },
+            TAG_SYNC_SUMMARY => LionMessages::SyncSummary {
+                heights: reader.u64_list()?,
+                roots: reader.list()?,
+                bitfields: reader.list()?,
+            },
+            TAG_SQUARE_REQUEST => LionMessages::SquareRequest {
+                height: reader.u64()?,
+            },
+            TAG_SQUARE_UNAVAILABLE => LionMessages::SquareUnavailable {
+                height: reader.u64()?,
+            },
+            TAG_CELL_BATCH => LionMessages::CellBatch {
+                height: reader.u64()?,
+                length: reader.usize()?,
+                scale: reader.usize()?,
+                root: reader.bytes()?,
+                samples: reader.list()?,
+            },
+            TAG_BLOB_REQUEST => LionMessages::BlobRequest {
+                height: reader.u64()?,
+                namespace: reader.bytes()?,
+                version: reader.u64()?,
+                start: reader.usize()?,
+                end: reader.usize()?,
+                len: reader.usize()?,
+            },
+            TAG_BLOB_RESPONSE => LionMessages::BlobResponse {
+                height: reader.u64()?,
+                proof: reader.bytes()?,
+                namespace_proof: reader.bytes()?,
+            },
+            TAG_BLOB_UNAVAILABLE => LionMessages::BlobUnavailable {
+                height: reader.u64()?,
+            },
+            TAG_STORAGE_CHALLENGE => LionMessages::StorageChallenge {
+                height: reader.u64()?,
+                anchor_height: reader.u64()?,
+            },
+            TAG_STORAGE_PROOF => LionMessages::StorageProof {
+                height: reader.u64()?,
+                anchor_height: reader.u64()?,
+                samples: reader.list()?,
+            },
+            TAG_STORAGE_UNAVAILABLE => LionMessages::StorageUnavailable {
+                height: reader.u64()?,
+            },
+            TAG_COMMITMENTS_REQUEST => LionMessages::CommitmentsRequest {
+                height: reader.u64()?,
+            },
+            TAG_COMMITMENTS => LionMessages::Commitments {
+                height: reader.u64()?,
+                root: reader.bytes()?,
+                row_commitments: reader.list()?,
+                col_commitments: reader.list()?,
+            },
+            TAG_COMMITMENTS_UNAVAILABLE => LionMessages::CommitmentsUnavailable {
+                height: reader.u64()?,
+            },
+            TAG_HEADER_REQUEST => LionMessages::HeaderRequest {
+                height: reader.u64()?,
+            },
+            TAG_HEADER_UNAVAILABLE => LionMessages::HeaderUnavailable {
+                height: reader.u64()?,
+            },
+            TAG_CELLS_REQUEST => LionMessages::CellsRequest {
+                height: reader.u64()?,
+                cells: reader.cells()?,
+            },
+            TAG_CELLS_RESPONSE => LionMessages::CellsResponse {
+                height: reader.u64()?,
+                samples: reader.list()?,
+            },
+            tag => return Err(invalid_data(format!("Unknown message tag {}", tag))),
+        };
+        reader.finish()?;
+        Ok(msg)
+    }
+}
+
+pub(crate) fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, val: u64) {
+    out.extend_from_slice(&val.to_le_bytes());
+}
+
+pub(crate) fn put_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    put_u64(out, data.len() as u64);
+    out.extend_from_slice(data);
+}
+
+fn put_line(out: &mut Vec<u8>, height: u64, axis: Axis, idx: usize) {
+    put_u64(out, height);
+    out.push(match axis {
+        Axis::Row => 0,
+        Axis::Col => 1,
+    });
+    put_u64(out, idx as u64);
+}
+
+pub(crate) fn put_list(out: &mut Vec<u8>, list: &[Vec<u8>]) {
+    put_u64(out, list.len() as u64);
+    for data in list {
+        put_bytes(out, data);
+    }
+}
+
+fn put_grid(out: &mut Vec<u8>, grid: &[Vec<Vec<u8>>]) {
+    put_u64(out, grid.len() as u64);
+    for list in grid {
+        put_list(out, list);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    /// Fail unless everything has been read
+    pub(crate) fn finish(&self) -> io::Result<()> {
+        if !self.bytes.is_empty() {
+            return Err(invalid_data("Trailing bytes after message".to_string()));
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid_data("Message truncated".to_string()));
+        }
+        let (data, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(data)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u64(&mut self) -> io::Result<u64> {
+        let data: [u8; 8] = self.take(8)?.try_into().unwrap();
+        Ok(u64::from_le_bytes(data))
+    }
+
+    pub(crate) fn usize(&mut self) -> io::Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid_data("Index overflow".to_string()))
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn axis(&mut self) -> io::Result<Axis> {
+        match self.u8()? {
+            0 => Ok(Axis::Row),
+            1 => Ok(Axis::Col),
+            axis => Err(invalid_data(format!("Unknown axis {}", axis))),
+        }
+    }
+
+    pub(crate) fn bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.usize()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?)
+            .map_err(|_| invalid_data("String is not UTF-8".to_string()))
+    }
+
+    pub(crate) fn list(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let len = self.usize()?;
+        // every entry takes at least its 8-byte length prefix
+        if len > self.bytes.len() / 8 {
+            return Err(invalid_data("Message truncated".to_string()));
+        }
+        (0..len).map(|_| self.bytes()).collect()
+    }
+
+    fn u64_list(&mut self) -> io::Result<Vec<u64>> {
+        let len = self.usize()?;
+        if len > self.bytes.len() / 8 {
+            return Err(invalid_data("Message truncated".to_string()));
+        }
+        (0..len).map(|_| self.u64()).collect()
+    }
+
+    fn cells(&mut self) -> io::Result<Vec<(usize, usize)>> {
+        let len = self.usize()?;
+        if len > self.bytes.len() / 16 {
+            return Err(invalid_data("Message truncated".to_string()));
+        }
+        let mut cells = Vec::with_capacity(len);
+        for _ in 0..len {
+            cells.push((self.usize()?, self.usize()?));
+        }
+        Ok(cells)
+    }
+
+    fn grid(&mut self) -> io::Result<Vec<Vec<Vec<u8>>>> {
+        let len = self.usize()?;
+        if len > self.bytes.len() / 8 {
+            return Err(invalid_data("Message truncated".to_string()));
+        }
+        (0..len).map(|_| self.list()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LionMessages;
+
+    #[test]
+    pub fn messages_round_trip() {
+        let msgs = vec![
+            LionMessages::OriginalShares {
+                height: 3,
+                scale: 2,
+                shares: vec![vec![vec![1, 2], vec![3]], vec![vec![], vec![4]]],
+                row_commitments: vec![vec![5; 48]],
+                col_commitments: vec![vec![6; 48]],
+                root: vec![7; 32],
+            },
+            LionMessages::SampleRequest {
+                height: 3,
+                rid: 1,
+                cid: 7,
+            },
+            LionMessages::SampleResponse {
+                height: 3,
+                sample: vec![8; 100],
+            },
+            LionMessages::Authenticate { token: vec![9; 16] },
+            LionMessages::BisectionResponse {
+                height: 3,
+                rid: 2,
+                left: vec![10; 32],
+                right: vec![11; 32],
+            },
+            LionMessages::Denied {
+                reason: "Submit requires authorization".to_string(),
+            },
+            LionMessages::AuditResponse {
+                height: 3,
+                samples: vec![vec![12; 100], vec![13; 100]],
+            },
+            LionMessages::RelayRequest {
+                target: "127.0.0.1:4000".to_string(),
+                request: vec![2, 3, 0, 0, 0, 0, 0, 0, 0],
+            },
+            LionMessages::AvailabilityAnnouncement {
+                height: 3,
+                bitfield: vec![4, 0, 16],
+            },
+            LionMessages::SyncSummary {
+                heights: vec![3, 4],
+                roots: vec![vec![14; 32], vec![15; 32]],
+                bitfields: vec![vec![4, 0, 16], vec![0]],
+            },
+            LionMessages::CellBatch {
+                height: 3,
+                length: 4,
+                scale: 2,
+                root: vec![16; 32],
+                samples: vec![vec![17; 100]],
+            },
+            LionMessages::BlobRequest {
+                height: 3,
+                namespace: vec![18; 8],
+                version: 1,
+                start: 2,
+                end: 5,
+                len: 70,
+            },
+            LionMessages::StorageChallenge {
+                height: 3,
+                anchor_height: 9,
+            },
+            LionMessages::StorageProof {
+                height: 3,
+                anchor_height: 9,
+                samples: vec![vec![19; 100]],
+            },
+            LionMessages::Commitments {
+                height: 3,
+                root: vec![20; 32],
+                row_commitments: vec![vec![21; 48], vec![22; 48]],
+                col_commitments: vec![vec![23; 48], vec![24; 48]],
+            },
+            LionMessages::HeaderRequest { height: 3 },
+            LionMessages::CellsRequest {
+                height: 3,
+                cells: vec![(0, 1), (2, 3)],
+            },
+            LionMessages::CellsResponse {
+                height: 3,
+                samples: vec![vec![25; 100]],
+            },
+        ];
+        for msg in msgs {
+            assert_eq!(LionMessages::decode(&msg.encode()).unwrap(), msg);
+        }
+        assert!(LionMessages::decode(&[42]).is_err());
+    }
+}
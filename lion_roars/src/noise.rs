@@ -0,0 +1,360 @@
+use crate::dht::PeerId;
+use crate::messages::{put_bytes, put_list, put_u64, Reader};
+
+use std::io;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// XX gives mutual authentication without either side knowing the other's key up front
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+/// Binds handshakes to this protocol so transcripts can't be replayed against another one
+const PROLOGUE: &[u8] = b"lazy-lion/1";
+/// Largest Noise message, ciphertext included
+pub const MAX_NOISE_MSG_LEN: usize = 65535;
+/// Authentication tag appended to every encrypted message
+const TAG_LEN: usize = 16;
+/// Largest plaintext carried by a single Noise message
+pub const MAX_CHUNK_LEN: usize = MAX_NOISE_MSG_LEN - TAG_LEN;
+
+/// Static X25519 identity of a node. The peer id is derived from the public key,
+/// so a completed handshake proves the remote controls the id it is known by.
+#[derive(Clone)]
+pub struct NodeKeys {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+impl NodeKeys {
+    pub fn generate() -> Self {
+        let keypair = builder()
+            .generate_keypair()
+            .expect("Generating a keypair should not fail");
+        Self {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+
+    pub fn from_keypair(private: Vec<u8>, public: Vec<u8>) -> Self {
+        Self { private, public }
+    }
+
+    pub fn public(&self) -> &[u8] {
+        &self.public
+    }
+
+    pub fn peer_id(&self) -> PeerId {
+        PeerId::from_public_bytes(&self.public)
+    }
+}
+
+/// What a node announces about its setup during the handshake, so a peer learns
+/// up front whether it can verify the node's blocks
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Digest of the KZG setup new squares are committed under; peers sharing no
+    /// setup are refused
+    pub setup_digest: Vec<u8>,
+    /// Digests of earlier setups still served and verified while peers migrate
+    pub previous_setups: Vec<Vec<u8>>,
+    /// Largest polynomial degree the setup supports
+    pub max_degree: u64,
+    /// Side of the largest extended square the node handles
+    pub max_square_length: u64,
+}
+
+impl Capabilities {
+    /// Capabilities of a node whose setup supports polynomials up to `max_degree`,
+    /// which bounds the side of the squares it can commit to or verify
+    pub fn new(setup_digest: Vec<u8>, max_degree: u64) -> Self {
+        let max_square_length = match max_degree {
+            0 => 0,
+            degree => 1 << (63 - degree.leading_zeros()),
+        };
+        Self {
+            setup_digest,
+            previous_setups: vec![],
+            max_degree,
+            max_square_length,
+        }
+    }
+
+    /// Also accept squares committed under the setups with `digests`
+    pub fn with_previous_setups(mut self, digests: Vec<Vec<u8>>) -> Self {
+        self.previous_setups = digests;
+        self
+    }
+
+    /// Whether squares committed under the setup with `digest` are accepted
+    pub fn accepts_setup(&self, digest: &[u8]) -> bool {
+        self.setups().any(|setup| setup == digest)
+    }
+
+    /// Digests of every setup accepted, the current one first
+    pub fn setups(&self) -> impl Iterator<Item = &[u8]> {
+        std::iter::once(self.setup_digest.as_slice())
+            .chain(self.previous_setups.iter().map(|digest| digest.as_slice()))
+    }
+
+    /// Whether extended squares of side `length` are within these capabilities
+    pub fn supports(&self, length: u64) -> bool {
+        length <= self.max_square_length
+    }
+
+    /// Whether every square `remote` may serve is one this node can verify
+    pub fn can_verify(&self, remote: &Capabilities) -> bool {
+        remote.setups().all(|digest| self.accepts_setup(digest))
+            && self.supports(remote.max_square_length)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        put_bytes(&mut out, &self.setup_digest);
+        put_u64(&mut out, self.max_degree);
+        put_u64(&mut out, self.max_square_length);
+        put_list(&mut out, &self.previous_setups);
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        let capabilities = Self {
+            setup_digest: reader.bytes()?,
+            max_degree: reader.u64()?,
+            max_square_length: reader.u64()?,
+            previous_setups: reader.list()?,
+        };
+        reader.finish()?;
+        Ok(capabilities)
+    }
+}
+
+/// Encrypted session established by a completed handshake
+pub struct NoiseSession {
+    transport: snow::TransportState,
+    /// Id of the authenticated remote
+    remote: PeerId,
+    /// What the remote announced about its setup
+    remote_capabilities: Capabilities,
+}
+
+impl NoiseSession {
+    /// Run the initiator side of the handshake. If `expected` is given the
+    /// handshake fails unless the remote proves it owns that id. Both sides
+    /// exchange their capabilities and refuse peers running a different setup.
+    pub async fn initiate(
+        stream: &mut TcpStream,
+        keys: &NodeKeys,
+        expected: Option<PeerId>,
+        capabilities: &Capabilities,
+    ) -> io::Result<Self> {
+        let mut handshake = builder()
+            .local_private_key(&keys.private)
+            .prologue(PROLOGUE)
+            .build_initiator()
+            .map_err(noise_error)?;
+        let mut buf = vec![0u8; MAX_NOISE_MSG_LEN];
+
+        // -> e
+        let len = handshake
+            .write_message(&[], &mut buf)
+            .map_err(noise_error)?;
+        write_chunk(stream, &buf[..len]).await?;
+        stream.flush().await?;
+        // <- e, ee, s, es
+        let msg = read_chunk(stream).await?;
+        let len = handshake
+            .read_message(&msg, &mut buf)
+            .map_err(noise_error)?;
+        let remote_capabilities = check_capabilities(&buf[..len], capabilities)?;
+        // -> s, se
+        let len = handshake
+            .write_message(&capabilities.to_bytes(), &mut buf)
+            .map_err(noise_error)?;
+        write_chunk(stream, &buf[..len]).await?;
+        stream.flush().await?;
+
+        let session = Self::finish(handshake, remote_capabilities)?;
+        match expected {
+            Some(expected) if expected != session.remote => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("Expected peer {} but {} answered", expected, session.remote),
+            )),
+            _ => Ok(session),
+        }
+    }
+
+    /// Run the responder side of the handshake
+    pub async fn respond(
+        stream: &mut TcpStream,
+        keys: &NodeKeys,
+        capabilities: &Capabilities,
+    ) -> io::Result<Self> {
+        let mut handshake = builder()
+            .local_private_key(&keys.private)
+            .prologue(PROLOGUE)
+            .build_responder()
+            .map_err(noise_error)?;
+        let mut buf = vec![0u8; MAX_NOISE_MSG_LEN];
+
+        // -> e
+        let msg = read_chunk(stream).await?;
+        handshake
+            .read_message(&msg, &mut buf)
+            .map_err(noise_error)?;
+        // <- e, ee, s, es
+        let len = handshake
+            .write_message(&capabilities.to_bytes(), &mut buf)
+            .map_err(noise_error)?;
+        write_chunk(stream, &buf[..len]).await?;
+        stream.flush().await?;
+        // -> s, se
+        let msg = read_chunk(stream).await?;
+        let len = handshake
+            .read_message(&msg, &mut buf)
+            .map_err(noise_error)?;
+        let remote_capabilities = check_capabilities(&buf[..len], capabilities)?;
+
+        Self::finish(handshake, remote_capabilities)
+    }
+
+    fn finish(
+        handshake: snow::HandshakeState,
+        remote_capabilities: Capabilities,
+    ) -> io::Result<Self> {
+        let remote = handshake
+            .get_remote_static()
+            .map(PeerId::from_public_bytes)
+            .ok_or_else(|| noise_error("Remote sent no static key"))?;
+        let transport = handshake.into_transport_mode().map_err(noise_error)?;
+        Ok(Self {
+            transport,
+            remote,
+            remote_capabilities,
+        })
+    }
+
+    pub fn remote(&self) -> PeerId {
+        self.remote
+    }
+
+    pub fn remote_capabilities(&self) -> &Capabilities {
+        &self.remote_capabilities
+    }
+
+    /// Encrypt `plaintext` (at most `MAX_CHUNK_LEN` bytes) and send it as a single chunk
+    pub async fn send(&mut self, stream: &mut TcpStream, plaintext: &[u8]) -> io::Result<()> {
+        let mut buf = vec![0u8; plaintext.len() + TAG_LEN];
+        let len = self
+            .transport
+            .write_message(plaintext, &mut buf)
+            .map_err(noise_error)?;
+        write_chunk(stream, &buf[..len]).await
+    }
+
+    /// Receive and decrypt one chunk. Tampered, reordered or dropped chunks fail here.
+    pub async fn recv(&mut self, stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+        let msg = read_chunk(stream).await?;
+        let mut buf = vec![0u8; msg.len()];
+        let len = self
+            .transport
+            .read_message(&msg, &mut buf)
+            .map_err(noise_error)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+fn builder() -> snow::Builder<'static> {
+    snow::Builder::new(
+        NOISE_PARAMS
+            .parse()
+            .expect("Parsing the Noise parameters should not fail"),
+    )
+}
+
+fn check_capabilities(remote: &[u8], local: &Capabilities) -> io::Result<Capabilities> {
+    let remote = Capabilities::from_bytes(remote)?;
+    if !remote.setups().any(|digest| local.accepts_setup(digest)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Peer shares no setup",
+        ));
+    }
+    Ok(remote)
+}
+
+fn noise_error(e: impl ToString) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+async fn write_chunk(stream: &mut TcpStream, chunk: &[u8]) -> io::Result<()> {
+    stream
+        .write_all(&(chunk.len() as u16).to_le_bytes())
+        .await?;
+    stream.write_all(chunk).await
+}
+
+async fn read_chunk(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await?;
+    let mut chunk = vec![0u8; u16::from_le_bytes(len) as usize];
+    stream.read_exact(&mut chunk).await?;
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, NodeKeys, NoiseSession};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[tokio::test]
+    pub async fn handshake_authenticates_both_sides() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server_keys = NodeKeys::generate();
+        let client_keys = NodeKeys::generate();
+        let server_capabilities = Capabilities::new(b"setup".to_vec(), 300);
+        let client_capabilities = Capabilities::new(b"setup".to_vec(), 64);
+        assert_eq!(server_capabilities.max_square_length, 256);
+
+        let server = {
+            let keys = server_keys.clone();
+            let capabilities = server_capabilities.clone();
+            tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut session = NoiseSession::respond(&mut stream, &keys, &capabilities)
+                    .await
+                    .unwrap();
+                let msg = session.recv(&mut stream).await.unwrap();
+                (session.remote(), msg)
+            })
+        };
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut session = NoiseSession::initiate(
+            &mut stream,
+            &client_keys,
+            Some(server_keys.peer_id()),
+            &client_capabilities,
+        )
+        .await
+        .unwrap();
+        assert_eq!(session.remote(), server_keys.peer_id());
+        assert_eq!(session.remote_capabilities(), &server_capabilities);
+        // the server may serve squares too large for the client's setup
+        assert!(!client_capabilities.can_verify(session.remote_capabilities()));
+        assert!(server_capabilities.can_verify(&client_capabilities));
+        // a node migrating setups verifies peers on either one, but not the reverse
+        let migrating =
+            Capabilities::new(b"next".to_vec(), 300).with_previous_setups(vec![b"setup".to_vec()]);
+        assert!(migrating.can_verify(&server_capabilities));
+        assert!(!server_capabilities.can_verify(&migrating));
+        assert!(!Capabilities::new(b"next".to_vec(), 300).accepts_setup(b"setup"));
+        session.send(&mut stream, b"sample").await.unwrap();
+
+        let (remote, msg) = server.await.unwrap();
+        assert_eq!(remote, client_keys.peer_id());
+        assert_eq!(msg, b"sample");
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// Ways a peer can misbehave, each carrying its own penalty
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Misbehaviour {
+    /// Served a sample or square that failed verification
+    InvalidProof,
+    /// Served two conflicting answers for the same height
+    Equivocation,
+    /// Sent malformed or unsolicited messages
+    Spam,
+    /// Could not serve data it was expected to hold
+    Unavailable,
+}
+
+impl Misbehaviour {
+    pub fn penalty(&self) -> i64 {
+        match self {
+            Misbehaviour::InvalidProof => 50,
+            Misbehaviour::Equivocation => 100,
+            Misbehaviour::Spam => 10,
+            Misbehaviour::Unavailable => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PeerScore {
+    /// Reward for good responses minus penalties for misbehaviour
+    pub score: i64,
+    pub invalid_proofs: u64,
+    pub equivocations: u64,
+    pub spam: u64,
+    pub unavailable: u64,
+    pub banned_until: Option<Instant>,
+}
+
+/// Per-peer behaviour tracking. Peers below `deprioritize_below` are tried last,
+/// peers at or below `ban_below` are refused for `ban_duration`.
+pub struct PeerScores<K: Eq + Hash + Clone> {
+    scores: HashMap<K, PeerScore>,
+    deprioritize_below: i64,
+    ban_below: i64,
+    ban_duration: Duration,
+}
+
+impl<K: Eq + Hash + Clone> PeerScores<K> {
+    pub fn new(deprioritize_below: i64, ban_below: i64, ban_duration: Duration) -> Self {
+        assert!(
+            ban_below < deprioritize_below,
+            "Ban threshold must be below the deprioritization threshold"
+        );
+        Self {
+            scores: HashMap::new(),
+            deprioritize_below,
+            ban_below,
+            ban_duration,
+        }
+    }
+
+    pub fn score(&self, peer: &K) -> PeerScore {
+        self.scores.get(peer).copied().unwrap_or_default()
+    }
+
+    /// Reward a correct response, slowly restoring a peer's standing
+    pub fn record_good(&mut self, peer: &K) {
+        let entry = self.scores.entry(peer.clone()).or_default();
+        entry.score = (entry.score + 1).min(100);
+    }
+
+    pub fn record(&mut self, peer: &K, misbehaviour: Misbehaviour) {
+        let entry = self.scores.entry(peer.clone()).or_default();
+        entry.score -= misbehaviour.penalty();
+        match misbehaviour {
+            Misbehaviour::InvalidProof => entry.invalid_proofs += 1,
+            Misbehaviour::Equivocation => entry.equivocations += 1,
+            Misbehaviour::Spam => entry.spam += 1,
+            Misbehaviour::Unavailable => entry.unavailable += 1,
+        }
+        if entry.score <= self.ban_below {
+            entry.banned_until = Some(Instant::now() + self.ban_duration);
+        }
+    }
+
+    pub fn is_banned(&self, peer: &K) -> bool {
+        self.scores
+            .get(peer)
+            .and_then(|score| score.banned_until)
+            .map_or(false, |until| Instant::now() < until)
+    }
+
+    pub fn is_deprioritized(&self, peer: &K) -> bool {
+        self.score(peer).score < self.deprioritize_below
+    }
+
+    /// Drop banned peers and move deprioritized ones to the back, otherwise
+    /// keeping the caller's order.
+    pub fn rank(&self, peers: Vec<K>) -> Vec<K> {
+        let (preferred, deprioritized): (Vec<K>, Vec<K>) = peers
+            .into_iter()
+            .filter(|peer| !self.is_banned(peer))
+            .partition(|peer| !self.is_deprioritized(peer));
+        preferred.into_iter().chain(deprioritized).collect()
+    }
+}
+
+impl<K: Eq + Hash + Clone + Display> PeerScores<K> {
+    /// Scores in Prometheus text exposition format
+    pub fn metrics(&self, prefix: &str) -> String {
+        let mut out = String::new();
+        for (peer, score) in self.scores.iter() {
+            let banned = self.is_banned(peer) as u8;
+            out.push_str(&format!(
+                "{prefix}_peer_score{{peer=\"{peer}\"}} {}\n\
+                 {prefix}_peer_invalid_proofs{{peer=\"{peer}\"}} {}\n\
+                 {prefix}_peer_equivocations{{peer=\"{peer}\"}} {}\n\
+                 {prefix}_peer_spam{{peer=\"{peer}\"}} {}\n\
+                 {prefix}_peer_unavailable{{peer=\"{peer}\"}} {}\n\
+                 {prefix}_peer_banned{{peer=\"{peer}\"}} {banned}\n",
+                score.score,
+                score.invalid_proofs,
+                score.equivocations,
+                score.spam,
+                score.unavailable,
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Misbehaviour, PeerScores};
+    use std::time::Duration;
+
+    #[test]
+    pub fn misbehaving_peers_are_ranked_down_then_banned() {
+        let mut scores = PeerScores::<u8>::new(-5, -100, Duration::from_secs(60));
+        scores.record(&1, Misbehaviour::Spam);
+        assert_eq!(scores.rank(vec![1, 2, 3]), vec![2, 3, 1]);
+
+        scores.record(&2, Misbehaviour::Equivocation);
+        assert!(scores.is_banned(&2));
+        assert_eq!(scores.rank(vec![1, 2, 3]), vec![3, 1]);
+    }
+}
@@ -0,0 +1,71 @@
+use tokio::sync::watch;
+
+/// Create a linked pair: the trigger is kept by whoever decides when the node stops,
+/// the signal is handed to the node's `run` loop.
+pub fn shutdown_channel() -> (ShutdownTrigger, ShutdownSignal) {
+    let (request_tx, request_rx) = watch::channel(false);
+    let (complete_tx, complete_rx) = watch::channel(false);
+    (
+        ShutdownTrigger {
+            request: request_tx,
+            complete: complete_rx,
+        },
+        ShutdownSignal {
+            request: request_rx,
+            complete: complete_tx,
+        },
+    )
+}
+
+pub struct ShutdownTrigger {
+    request: watch::Sender<bool>,
+    complete: watch::Receiver<bool>,
+}
+
+impl ShutdownTrigger {
+    /// Ask the node to stop, resolving once it has drained in-flight work and
+    /// flushed its state (or dropped its signal).
+    pub async fn shutdown(mut self) {
+        let _ = self.request.send(true);
+        while !*self.complete.borrow() {
+            if self.complete.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+pub struct ShutdownSignal {
+    request: watch::Receiver<bool>,
+    complete: watch::Sender<bool>,
+}
+
+impl ShutdownSignal {
+    pub fn is_requested(&self) -> bool {
+        *self.request.borrow()
+    }
+
+    /// Resolve once shutdown has been requested (or the trigger was dropped)
+    pub async fn requested(&mut self) {
+        wait_for_request(&mut self.request).await
+    }
+
+    /// Independent receiver for tasks spawned by the node
+    pub fn subscribe(&self) -> watch::Receiver<bool> {
+        self.request.clone()
+    }
+
+    /// Report that shutdown has finished
+    pub fn complete(self) {
+        let _ = self.complete.send(true);
+    }
+}
+
+/// Resolve once a receiver obtained from `ShutdownSignal::subscribe` sees a shutdown request
+pub async fn wait_for_request(request: &mut watch::Receiver<bool>) {
+    while !*request.borrow() {
+        if request.changed().await.is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Verifiable random function ECVRF-EDWARDS25519-SHA512-TAI of RFC 9381: the
+//! output for an input is unique to the key, unpredictable without the secret,
+//! and comes with a proof anyone holding the public key can check. Keys, proofs
+//! and outputs are those of the RFC, so any implementation of the suite checks
+//! them.
+
+use crate::messages::invalid_data;
+
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha512};
+use std::io;
+
+/// Suite string of ECVRF-EDWARDS25519-SHA512-TAI
+const SUITE: u8 = 0x03;
+
+const ENCODE_TO_CURVE: u8 = 0x01;
+const CHALLENGE: u8 = 0x02;
+const PROOF_TO_HASH: u8 = 0x03;
+/// Closes every hash input of the suite
+const BACK: u8 = 0x00;
+
+/// Length of an encoded `VrfProof`
+pub const VRF_PROOF_LEN: usize = 80;
+
+/// Key a node evaluates the VRF with
+pub struct VrfKey {
+    secret: Scalar,
+    /// Second half of the hashed secret key, seeding the proof nonces
+    nonce_key: [u8; 32],
+    public: EdwardsPoint,
+}
+
+impl VrfKey {
+    pub fn generate() -> Self {
+        let mut seed = [0; 32];
+        OsRng.fill_bytes(&mut seed);
+        Self::from_bytes(&seed)
+    }
+
+    /// Key with secret key `seed`, expanded as an Ed25519 secret key is
+    pub fn from_bytes(seed: &[u8; 32]) -> Self {
+        let expanded = finish(Sha512::new().chain_update(seed));
+        let mut secret: [u8; 32] = expanded[..32]
+            .try_into()
+            .expect("Half a SHA-512 digest is 32 bytes");
+        secret[0] &= 248;
+        secret[31] &= 127;
+        secret[31] |= 64;
+        let secret = Scalar::from_bytes_mod_order(secret);
+        Self {
+            secret,
+            nonce_key: expanded[32..]
+                .try_into()
+                .expect("Half a SHA-512 digest is 32 bytes"),
+            public: ED25519_BASEPOINT_POINT * secret,
+        }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.public.compress().to_bytes()
+    }
+
+    /// Evaluate the VRF at `input`, returning the output and a proof of it
+    pub fn prove(&self, input: &[u8]) -> ([u8; 64], VrfProof) {
+        let point = encode_to_curve(&self.public(), input);
+        let gamma = point * self.secret;
+        let nonce = Scalar::from_bytes_mod_order_wide(&finish(
+            Sha512::new()
+                .chain_update(self.nonce_key)
+                .chain_update(point.compress().as_bytes()),
+        ));
+        let challenge = challenge(&[
+            &self.public,
+            &point,
+            &gamma,
+            &(ED25519_BASEPOINT_POINT * nonce),
+            &(point * nonce),
+        ]);
+        let proof = VrfProof {
+            gamma: gamma.compress().to_bytes(),
+            challenge,
+            response: (nonce + challenge_scalar(&challenge) * self.secret).to_bytes(),
+        };
+        (output(&gamma), proof)
+    }
+}
+
+/// Proof that a VRF output was computed with the secret behind a public key
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VrfProof {
+    /// The input point multiplied by the secret
+    pub gamma: [u8; 32],
+    pub challenge: [u8; 16],
+    pub response: [u8; 32],
+}
+
+impl VrfProof {
+    /// The VRF output at `input` for the key `public`, if the proof is valid
+    pub fn verify(&self, public: &[u8; 32], input: &[u8]) -> Option<[u8; 64]> {
+        let key = string_to_point(public)?;
+        if key.is_small_order() {
+            return None;
+        }
+        let gamma = string_to_point(&self.gamma)?;
+        let response = Option::<Scalar>::from(Scalar::from_canonical_bytes(self.response))?;
+        let c = challenge_scalar(&self.challenge);
+
+        let point = encode_to_curve(public, input);
+        let u = ED25519_BASEPOINT_POINT * response - key * c;
+        let v = point * response - gamma * c;
+        (challenge(&[&key, &point, &gamma, &u, &v]) == self.challenge).then(|| output(&gamma))
+    }
+
+    pub fn to_bytes(&self) -> [u8; VRF_PROOF_LEN] {
+        let mut out = [0; VRF_PROOF_LEN];
+        out[..32].copy_from_slice(&self.gamma);
+        out[32..48].copy_from_slice(&self.challenge);
+        out[48..].copy_from_slice(&self.response);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() != VRF_PROOF_LEN {
+            return Err(invalid_data(format!(
+                "VRF proof is {} bytes, expected {}",
+                bytes.len(),
+                VRF_PROOF_LEN
+            )));
+        }
+        Ok(Self {
+            gamma: bytes[..32].try_into().expect("Length checked above"),
+            challenge: bytes[32..48].try_into().expect("Length checked above"),
+            response: bytes[48..].try_into().expect("Length checked above"),
+        })
+    }
+}
+
+/// Point encoded by `bytes`, `None` unless they are its canonical encoding as
+/// RFC 8032 decodes points
+fn string_to_point(bytes: &[u8; 32]) -> Option<EdwardsPoint> {
+    let point = CompressedEdwardsY(*bytes).decompress()?;
+    (point.compress().as_bytes() == bytes).then_some(point)
+}
+
+/// Try-and-increment, salted with the public key: the first counter whose hash
+/// decodes to a point gives the input point, cleared of its cofactor
+fn encode_to_curve(public: &[u8; 32], input: &[u8]) -> EdwardsPoint {
+    (0..=u8::MAX)
+        .find_map(|counter| {
+            let digest = Sha512::new()
+                .chain_update([SUITE, ENCODE_TO_CURVE])
+                .chain_update(public)
+                .chain_update(input)
+                .chain_update([counter, BACK])
+                .finalize();
+            let candidate: [u8; 32] = digest[..32].try_into().expect("Digest is 64 bytes");
+            string_to_point(&candidate).map(|point| point.mul_by_cofactor())
+        })
+        .expect("Some counter should hash to a curve point")
+}
+
+fn challenge(points: &[&EdwardsPoint; 5]) -> [u8; 16] {
+    let mut hasher = Sha512::new().chain_update([SUITE, CHALLENGE]);
+    for point in points {
+        hasher.update(point.compress().as_bytes());
+    }
+    let digest = hasher.chain_update([BACK]).finalize();
+    digest[..16].try_into().expect("Digest is 64 bytes")
+}
+
+fn challenge_scalar(challenge: &[u8; 16]) -> Scalar {
+    let mut bytes = [0; 32];
+    bytes[..16].copy_from_slice(challenge);
+    Scalar::from_bytes_mod_order(bytes)
+}
+
+fn output(gamma: &EdwardsPoint) -> [u8; 64] {
+    finish(
+        Sha512::new()
+            .chain_update([SUITE, PROOF_TO_HASH])
+            .chain_update(gamma.mul_by_cofactor().compress().as_bytes())
+            .chain_update([BACK]),
+    )
+}
+
+fn finish(hasher: Sha512) -> [u8; 64] {
+    let mut out = [0; 64];
+    out.copy_from_slice(&hasher.finalize());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{VrfKey, VrfProof};
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    pub fn outputs_verify_only_for_their_key_and_input() {
+        let key = VrfKey::from_bytes(&[7; 32]);
+        let (output, proof) = key.prove(b"header");
+        assert_eq!(key.prove(b"header").0, output);
+        assert_ne!(key.prove(b"other header").0, output);
+        assert_eq!(proof.verify(&key.public(), b"header"), Some(output));
+        assert_eq!(proof.verify(&key.public(), b"other header"), None);
+        assert_eq!(proof.verify(&VrfKey::generate().public(), b"header"), None);
+
+        let decoded = VrfProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(decoded, proof);
+        let mut tampered = proof;
+        tampered.response[0] ^= 1;
+        assert_eq!(tampered.verify(&key.public(), b"header"), None);
+        assert!(VrfProof::from_bytes(&[0; 79]).is_err());
+    }
+
+    #[test]
+    pub fn proofs_match_the_rfc_9381_test_vectors() {
+        // examples 16 to 18 of RFC 9381, ECVRF-EDWARDS25519-SHA512-TAI
+        let vectors = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "8657106690b5526245a92b003bb079ccd1a92130477671f6fc01ad16f26f723f\
+                 26f8a57ccaed74ee1b190bed1f479d9727d2d0f9b005a6e456a35d4fb0daab12\
+                 68a1b0db10836d9826a528ca76567805",
+                "90cf1df3b703cce59e2a35b925d411164068269d7b2d29f3301c03dd757876ff\
+                 66b71dda49d2de59d03450451af026798e8f81cd2e333de5cdf4f3e140fdd8ae",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "f3141cd382dc42909d19ec5110469e4feae18300e94f304590abdced48aed593\
+                 3bf0864a62558b3ed7f2fea45c92a465301b3bbf5e3e54ddf2d935be3b67926d\
+                 a3ef39226bbc355bdc9850112c8f4b02",
+                "eb4440665d3891d668e7e0fcaf587f1b4bd7fbfe99d0eb2211ccec90496310eb\
+                 5e33821bc613efb94db5e5b54c70a848a0bef4553a41befc57663b56373a5031",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "9bc0f79119cc5604bf02d23b4caede71393cedfbb191434dd016d30177ccbf80\
+                 96bb474e53895c362d8628ee9f9ea3c0e52c7a5c691b6c18c9979866568add7a\
+                 2d41b00b05081ed0f58ee5e31b3a970e",
+                "645427e5d00c62a23fb703732fa5d892940935942101e456ecca7bb217c61c45\
+                 2118fec1219202a0edcf038bb6373241578be7217ba85a2687f7a0310b2df19f",
+            ),
+        ];
+        for (secret, public, input, proof, output) in vectors {
+            let key = VrfKey::from_bytes(&unhex(secret).try_into().unwrap());
+            let public: [u8; 32] = unhex(public).try_into().unwrap();
+            assert_eq!(key.public(), public);
+            let input = unhex(input);
+            let (beta, pi) = key.prove(&input);
+            assert_eq!(pi.to_bytes().to_vec(), unhex(proof));
+            assert_eq!(beta.to_vec(), unhex(output));
+            assert_eq!(pi.verify(&public, &input), Some(beta));
+        }
+    }
+}
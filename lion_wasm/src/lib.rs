@@ -0,0 +1,71 @@
+use square_reed_solomon::codec::verifier_key_from_bytes;
+use square_reed_solomon::sample::CellSample;
+use square_reed_solomon::verifier::{verify_cell_sample, verify_root, RootVersion};
+
+use ark_poly_commit::kzg10::VerifierKey;
+use ark_test_curves::bls12_381::Bls12_381;
+use rs_merkle::algorithms::Sha256;
+use wasm_bindgen::prelude::*;
+
+/// Verifier for squares committed over BLS12-381 with SHA-256 Merkle trees
+#[wasm_bindgen]
+pub struct WasmVerifier {
+    vk: VerifierKey<Bls12_381>,
+}
+
+#[wasm_bindgen]
+impl WasmVerifier {
+    /// Build a verifier from a verifier key encoded by `verifier_key_to_bytes`: the
+    /// `VK_FORMAT_VERSION` byte followed by the compressed canonical key, as nodes
+    /// serve it
+    #[wasm_bindgen(constructor)]
+    pub fn new(vk_bytes: &[u8]) -> Result<WasmVerifier, JsError> {
+        let vk = verifier_key_from_bytes::<Bls12_381>(vk_bytes)
+            .map_err(|e| JsError::new(&format!("Invalid verifier key: {}", e)))?;
+        Ok(Self { vk })
+    }
+
+    /// Verify a sample produced by `CellSample::to_bytes` against a data root built
+    /// under the root version with id `root_version`
+    #[wasm_bindgen(js_name = verifySample)]
+    pub fn verify_sample(
+        &self,
+        root_version: u8,
+        root: &[u8],
+        length: usize,
+        sample: &[u8],
+    ) -> Result<bool, JsError> {
+        let version = root_version_from_id(root_version)?;
+        let root = hash_from_slice(root)?;
+        let sample = CellSample::<Bls12_381, Sha256>::from_bytes(sample)
+            .map_err(|e| JsError::new(&format!("Invalid sample encoding: {}", e)))?;
+        Ok(verify_cell_sample(&self.vk, version, root, length, &sample))
+    }
+}
+
+/// Check that a data root commits to the given row and column roots under the root
+/// version with id `root_version`
+#[wasm_bindgen(js_name = verifyRoot)]
+pub fn verify_root_js(
+    root_version: u8,
+    root: &[u8],
+    row_root: &[u8],
+    col_root: &[u8],
+) -> Result<bool, JsError> {
+    Ok(verify_root::<Sha256>(
+        root_version_from_id(root_version)?,
+        hash_from_slice(root)?,
+        hash_from_slice(row_root)?,
+        hash_from_slice(col_root)?,
+    ))
+}
+
+fn root_version_from_id(id: u8) -> Result<RootVersion, JsError> {
+    RootVersion::from_id(id).ok_or_else(|| JsError::new(&format!("Unknown root version {}", id)))
+}
+
+fn hash_from_slice(bytes: &[u8]) -> Result<[u8; 32], JsError> {
+    bytes
+        .try_into()
+        .map_err(|_| JsError::new("Hashes must be 32 bytes"))
+}
@@ -0,0 +1,109 @@
+//! Proof-of-retrievability audits. An auditor sends a fresh random seed and the
+//! storing node answers with cell samples at coordinates derived from it. The
+//! coordinates cannot be predicted before the seed is known, so a node that kept
+//! only its commitments cannot answer before the deadline.
+
+use crate::sample::CellSample;
+use crate::verifier::{verify_cell_sample, RootVersion};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::VerifierKey;
+use rs_merkle::Hasher;
+
+/// The `count` cells of a square of side `length` that `seed` challenges
+pub fn audit_cells<H: Hasher>(seed: &[u8], length: usize, count: usize) -> Vec<(usize, usize)> {
+    (0..count as u64)
+        .map(|i| {
+            (
+                audit_index::<H>(seed, i, 0, length),
+                audit_index::<H>(seed, i, 1, length),
+            )
+        })
+        .collect()
+}
+
+/// Check that `samples` open exactly the cells `seed` challenges, in order,
+/// against `root` under `version`
+pub fn verify_audit<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    seed: &[u8],
+    count: usize,
+    samples: &[CellSample<E, H>],
+) -> bool {
+    let cells = audit_cells::<H>(seed, length, count);
+    samples.len() == cells.len()
+        && samples.iter().zip(cells).all(|(sample, cell)| {
+            (sample.rid, sample.cid) == cell
+                && verify_cell_sample(vk, version, root, length, sample)
+        })
+}
+
+fn audit_index<H: Hasher>(seed: &[u8], i: u64, axis: u8, length: usize) -> usize {
+    let mut input = seed.to_vec();
+    input.extend_from_slice(&i.to_le_bytes());
+    input.push(axis);
+    let digest: Vec<u8> = H::hash(&input).into();
+    let val = digest
+        .iter()
+        .take(8)
+        .fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+    (val % length as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{audit_cells, verify_audit};
+    use crate::fixtures::counting_square;
+    use crate::prover::RsSquareProver;
+    use crate::verifier::verifier_key;
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn audit_answers_must_open_the_seeded_cells() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let vk = verifier_key(prover.params());
+
+        let cells = audit_cells::<Sha256>(b"seed", 4, 3);
+        assert_eq!(cells, audit_cells::<Sha256>(b"seed", 4, 3));
+        assert_ne!(cells, audit_cells::<Sha256>(b"other seed", 4, 3));
+        assert!(cells.iter().all(|&(rid, cid)| rid < 4 && cid < 4));
+
+        let samples: Vec<_> = cells
+            .iter()
+            .map(|&(rid, cid)| prover.sample_cell(rid, cid))
+            .collect();
+        assert!(verify_audit(
+            &vk,
+            prover.root_version(),
+            prover.root(),
+            4,
+            b"seed",
+            3,
+            &samples
+        ));
+        assert!(!verify_audit(
+            &vk,
+            prover.root_version(),
+            prover.root(),
+            4,
+            b"seed",
+            3,
+            &samples[..2]
+        ));
+        assert!(!verify_audit(
+            &vk,
+            prover.root_version(),
+            prover.root(),
+            4,
+            b"other seed",
+            3,
+            &samples
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+//! Sample coordinates derived from a public randomness beacon mixed with a node's
+//! secret. The beacon value is unknown until the height is due, so a producer
+//! cannot decide ahead of time which cells to keep; the secret keeps every node's
+//! draws different, so it cannot serve only the cells nodes will ask for either.
+
+use crate::codec::{put_bytes, put_u64};
+
+use rand::RngCore;
+use rs_merkle::Hasher;
+use std::marker::PhantomData;
+
+/// Deterministic random stream seeded by `H(secret, beacon, height)`, usable
+/// wherever sampling takes an `Rng`
+pub struct BeaconRng<H: Hasher> {
+    seed: Vec<u8>,
+    /// Number of hash blocks drawn so far
+    counter: u64,
+    /// Unused bytes of the current hash block
+    buffer: Vec<u8>,
+    _hasher: PhantomData<H>,
+}
+
+impl<H: Hasher> BeaconRng<H> {
+    pub fn new(secret: &[u8], beacon: &[u8], height: u64) -> Self {
+        let mut input = vec![];
+        put_bytes(&mut input, secret);
+        put_bytes(&mut input, beacon);
+        put_u64(&mut input, height);
+        Self {
+            seed: H::hash(&input).into(),
+            counter: 0,
+            buffer: vec![],
+            _hasher: PhantomData,
+        }
+    }
+
+    fn refill(&mut self) {
+        let mut input = self.seed.clone();
+        put_u64(&mut input, self.counter);
+        self.buffer = H::hash(&input).into();
+        self.counter += 1;
+    }
+}
+
+impl<H: Hasher> RngCore for BeaconRng<H> {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest {
+            if self.buffer.is_empty() {
+                self.refill();
+            }
+            *byte = self.buffer.pop().expect("Hash output should not be empty");
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// The first `count` distinct cells of a square of side `length` drawn from the
+/// beacon stream, capped at the number of cells
+pub fn beacon_cells<H: Hasher>(
+    secret: &[u8],
+    beacon: &[u8],
+    height: u64,
+    length: usize,
+    count: usize,
+) -> Vec<(usize, usize)> {
+    let mut rng = BeaconRng::<H>::new(secret, beacon, height);
+    let count = count.min(length * length);
+    let mut cells = Vec::with_capacity(count);
+    while cells.len() < count {
+        let cell = (
+            (rng.next_u64() % length as u64) as usize,
+            (rng.next_u64() % length as u64) as usize,
+        );
+        if !cells.contains(&cell) {
+            cells.push(cell);
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{beacon_cells, BeaconRng};
+
+    use rand::Rng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn draws_depend_on_secret_beacon_and_height() {
+        let cells = beacon_cells::<Sha256>(b"secret", b"beacon", 7, 8, 10);
+        assert_eq!(cells.len(), 10);
+        assert!(cells.iter().all(|&(rid, cid)| rid < 8 && cid < 8));
+        assert_eq!(
+            cells,
+            beacon_cells::<Sha256>(b"secret", b"beacon", 7, 8, 10)
+        );
+        assert_ne!(cells, beacon_cells::<Sha256>(b"other", b"beacon", 7, 8, 10));
+        assert_ne!(cells, beacon_cells::<Sha256>(b"secret", b"next", 7, 8, 10));
+        assert_ne!(
+            cells,
+            beacon_cells::<Sha256>(b"secret", b"beacon", 8, 8, 10)
+        );
+        assert_eq!(
+            beacon_cells::<Sha256>(b"secret", b"beacon", 7, 2, 10).len(),
+            4
+        );
+
+        let mut rng = BeaconRng::<Sha256>::new(b"secret", b"beacon", 7);
+        let draws: Vec<usize> = (0..100).map(|_| rng.gen_range(0..8)).collect();
+        assert!(draws.iter().all(|draw| *draw < 8));
+    }
+}
@@ -0,0 +1,47 @@
+//! Take part in a trusted setup ceremony over BLS12-381 setups stored compressed:
+//! `setup contribute SRS UPDATED PROOF` writes the updated setup and the proof of
+//! the contribution, and `setup verify SRS UPDATED PROOF` checks one
+use square_reed_solomon::codec::{decode, Validation};
+use square_reed_solomon::setup::{contribute, verify_contribution, ContributionProof};
+
+use ark_poly_commit::kzg10::UniversalParams;
+use ark_serialize::CanonicalSerialize;
+use ark_test_curves::bls12_381::Bls12_381;
+use rand::rngs::OsRng;
+
+fn read_setup(path: &str) -> UniversalParams<Bls12_381> {
+    let bytes = std::fs::read(path).unwrap_or_else(|err| panic!("Reading {}: {}", path, err));
+    decode(&bytes, Validation::Strict).unwrap_or_else(|err| panic!("Decoding {}: {}", path, err))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (command, srs, updated, proof) = match args.as_slice() {
+        [command, srs, updated, proof] => (command, srs, updated, proof),
+        _ => panic!("Usage: setup contribute|verify SRS UPDATED PROOF"),
+    };
+    let before = read_setup(srs);
+    match command.as_str() {
+        "contribute" => {
+            let (after, contribution) = contribute(&before, &mut OsRng::default());
+            let mut bytes = vec![];
+            after
+                .serialize_compressed(&mut bytes)
+                .expect("Serializing into a vector cannot fail");
+            std::fs::write(updated, bytes).expect("Writing the updated setup failed");
+            std::fs::write(proof, contribution.to_bytes()).expect("Writing the proof failed");
+        }
+        "verify" => {
+            let after = read_setup(updated);
+            let bytes = std::fs::read(proof).expect("Reading the proof failed");
+            let contribution = ContributionProof::<Bls12_381>::from_bytes(&bytes)
+                .unwrap_or_else(|err| panic!("Decoding {}: {}", proof, err));
+            if !verify_contribution(&before, &after, &contribution, &mut OsRng::default()) {
+                eprintln!("Contribution does not verify");
+                std::process::exit(1);
+            }
+            println!("Contribution verifies");
+        }
+        _ => panic!("Unknown command {}, expected contribute or verify", command),
+    }
+}
@@ -0,0 +1,15 @@
+//! Print the test vectors for a seed and square dimensions, by default
+//! `test_vectors 0 2 2`
+use square_reed_solomon::vectors::test_vectors;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let arg = |idx: usize, default: u64| match args.get(idx) {
+        Some(arg) => arg
+            .parse::<u64>()
+            .unwrap_or_else(|_| panic!("Expected a number, got {}", arg)),
+        None => default,
+    };
+    let (seed, n, scale) = (arg(0, 0), arg(1, 2) as usize, arg(2, 2) as usize);
+    print!("{}", test_vectors(seed, n, scale));
+}
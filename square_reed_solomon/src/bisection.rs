@@ -0,0 +1,322 @@
+use crate::codec::field_to_bytes;
+use crate::sample::CellSample;
+use crate::verifier::{hashes_equal, verify_cell_sample, RootVersion};
+
+use ark_ec::pairing::Pairing;
+use ark_ff::PrimeField;
+use ark_poly_commit::kzg10::{Commitment, VerifierKey};
+use rs_merkle::Hasher;
+
+/// Digest binding every value of `values`: a single value is hashed directly,
+/// longer ranges hash the digests of their two halves, split at `len / 2`.
+pub fn range_digest<F: PrimeField, H: Hasher>(values: &[F]) -> H::Hash {
+    assert!(!values.is_empty(), "Cannot digest an empty range");
+    if values.len() == 1 {
+        return H::hash(&field_to_bytes(&values[0]));
+    }
+    let mid = values.len() / 2;
+    H::concat_and_hash(
+        &range_digest::<F, H>(&values[..mid]),
+        Some(&range_digest::<F, H>(&values[mid..])),
+    )
+}
+
+/// Defender's answer to a split request: digests of the two halves of `lo..hi`.
+/// `None` if the range is out of bounds or holds fewer than two values.
+pub fn split_digests<F: PrimeField, H: Hasher>(
+    values: &[F],
+    lo: usize,
+    hi: usize,
+) -> Option<(H::Hash, H::Hash)> {
+    if hi > values.len() || hi < lo + 2 {
+        return None;
+    }
+    let mid = lo + (hi - lo) / 2;
+    Some((
+        range_digest::<F, H>(&values[lo..mid]),
+        range_digest::<F, H>(&values[mid..hi]),
+    ))
+}
+
+/// What the challenger asks the defender for next
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BisectionStep {
+    /// A sample of cell `idx`, binding the game to the row commitment under the
+    /// defender's data root
+    Bind { idx: usize },
+    /// Digests of both halves of `lo..hi`
+    Split { lo: usize, hi: usize },
+    /// A sample of the single disputed cell, opened against the row commitment
+    Settle { idx: usize },
+}
+
+/// Outcome of a bisection game
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict<F: PrimeField> {
+    /// The defender's row commitment is the one of the challenger's row
+    Agreed,
+    /// The defender contradicted an earlier digest or could not back its claim
+    /// with a valid opening
+    DefenderFaulty,
+    /// The committed row holds `committed` at `idx` where `expected` was correct
+    Mismatch {
+        idx: usize,
+        committed: F,
+        expected: F,
+    },
+}
+
+/// Challenger side of an interactive bisection over row `rid`. The game opens
+/// one cell to learn the defender's row commitment and goes on only if it is not
+/// the commitment to the challenger's row. Each round then halves the range in
+/// dispute until a single cell is left, which is settled with one KZG opening.
+/// Rounds grow logarithmically with the row length, unlike a one-shot proof
+/// carrying the whole row.
+pub struct RowChallenge<F: PrimeField, H: Hasher> {
+    pub rid: usize,
+    /// Values the challenger holds to be correct for the row
+    expected: Vec<F>,
+    /// Whether the defender's row commitment is known to differ from ours
+    bound: bool,
+    lo: usize,
+    hi: usize,
+    /// Digest the defender is bound to for `lo..hi`, none before the first round
+    claimed: Option<H::Hash>,
+}
+
+impl<F: PrimeField, H: Hasher> RowChallenge<F, H> {
+    pub fn new(rid: usize, expected: Vec<F>) -> Self {
+        assert!(
+            expected.len() >= 2,
+            "Rows of a single cell need no bisection"
+        );
+        Self {
+            rid,
+            hi: expected.len(),
+            lo: 0,
+            expected,
+            bound: false,
+            claimed: None,
+        }
+    }
+
+    /// The request to send next
+    pub fn step(&self) -> BisectionStep {
+        if !self.bound {
+            BisectionStep::Bind { idx: 0 }
+        } else if self.hi - self.lo == 1 {
+            BisectionStep::Settle { idx: self.lo }
+        } else {
+            BisectionStep::Split {
+                lo: self.lo,
+                hi: self.hi,
+            }
+        }
+    }
+
+    /// Take the defender's sample of the first cell, opened against its data
+    /// `root` under `version`, and compare the row commitment it carries with `ours`, the
+    /// commitment to the challenger's row. Equal commitments hold equal rows, so
+    /// the game ends in agreement; otherwise the rows differ somewhere and no
+    /// later digest may claim they do not. Returns a verdict once the game is over.
+    pub fn bind<E: Pairing<ScalarField = F>>(
+        &mut self,
+        vk: &VerifierKey<E>,
+        version: RootVersion,
+        root: H::Hash,
+        ours: &Commitment<E>,
+        sample: &CellSample<E, H>,
+    ) -> Option<Verdict<F>> {
+        if self.bound
+            || sample.rid != self.rid
+            || sample.cid != 0
+            || !verify_cell_sample(vk, version, root, self.expected.len(), sample)
+        {
+            return Some(Verdict::DefenderFaulty);
+        }
+        if sample.row_commitment == *ours {
+            return Some(Verdict::Agreed);
+        }
+        if sample.value != self.expected[0] {
+            return Some(Verdict::Mismatch {
+                idx: 0,
+                committed: sample.value,
+                expected: self.expected[0],
+            });
+        }
+        self.bound = true;
+        None
+    }
+
+    /// Take the defender's digests of both halves of the current range and
+    /// narrow to the half that differs. Returns a verdict once the game is over.
+    pub fn narrow(&mut self, left: H::Hash, right: H::Hash) -> Option<Verdict<F>> {
+        if !self.bound {
+            return Some(Verdict::DefenderFaulty);
+        }
+        if let Some(claimed) = self.claimed {
+            if !hashes_equal::<H>(H::concat_and_hash(&left, Some(&right)), claimed) {
+                return Some(Verdict::DefenderFaulty);
+            }
+        }
+        let mid = self.lo + (self.hi - self.lo) / 2;
+        if !hashes_equal::<H>(left, range_digest::<F, H>(&self.expected[self.lo..mid])) {
+            self.hi = mid;
+            self.claimed = Some(left);
+        } else if !hashes_equal::<H>(right, range_digest::<F, H>(&self.expected[mid..self.hi])) {
+            self.lo = mid;
+            self.claimed = Some(right);
+        } else {
+            // the bound commitments differ, so halves agreeing with our row are a lie
+            return Some(Verdict::DefenderFaulty);
+        }
+        None
+    }
+
+    /// Settle the single remaining cell with the defender's sample, checked
+    /// against the defender's data `root` under `version`
+    pub fn settle<E: Pairing<ScalarField = F>>(
+        &self,
+        vk: &VerifierKey<E>,
+        version: RootVersion,
+        root: H::Hash,
+        sample: &CellSample<E, H>,
+    ) -> Verdict<F> {
+        let idx = self.lo;
+        let bound = self.claimed.map_or(false, |claimed| {
+            hashes_equal::<H>(H::hash(&field_to_bytes(&sample.value)), claimed)
+        });
+        if self.hi - self.lo != 1
+            || sample.rid != self.rid
+            || sample.cid != idx
+            || !bound
+            || !verify_cell_sample(vk, version, root, self.expected.len(), sample)
+        {
+            return Verdict::DefenderFaulty;
+        }
+        if sample.value == self.expected[idx] {
+            Verdict::Agreed
+        } else {
+            Verdict::Mismatch {
+                idx,
+                committed: sample.value,
+                expected: self.expected[idx],
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{range_digest, split_digests, BisectionStep, RowChallenge, Verdict};
+    use crate::fixtures::counting_square;
+    use crate::prover::RsSquareProver;
+    use crate::verifier::verifier_key;
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use ark_test_curves::bls12_381::Fr;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn bisection_finds_the_disputed_cell() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 4);
+        let vk = verifier_key(prover.params());
+        let committed = prover.square().row_vals(1);
+        let ours = prover.commit_to_row(1);
+
+        // the challenger disagrees with the committed row at a single cell
+        let mut expected = committed.clone();
+        expected[5] += Fr::from(1);
+        // standing in for the commitment to the challenger's row
+        let other = prover.commit_to_row(0);
+        let mut challenge = RowChallenge::<Fr, Sha256>::new(1, expected.clone());
+        assert_eq!(challenge.step(), BisectionStep::Bind { idx: 0 });
+        let first = prover.sample_cell(1, 0);
+        assert_eq!(
+            challenge.bind(&vk, prover.root_version(), prover.root(), &other, &first),
+            None
+        );
+        let mut rounds = 0;
+        while let BisectionStep::Split { lo, hi } = challenge.step() {
+            let (left, right) = split_digests::<Fr, Sha256>(&committed, lo, hi).unwrap();
+            assert_eq!(challenge.narrow(left, right), None);
+            rounds += 1;
+        }
+        assert_eq!(rounds, 3);
+        assert_eq!(challenge.step(), BisectionStep::Settle { idx: 5 });
+        assert_eq!(
+            challenge.settle(
+                &vk,
+                prover.root_version(),
+                prover.root(),
+                &prover.sample_cell(1, 5)
+            ),
+            Verdict::Mismatch {
+                idx: 5,
+                committed: committed[5],
+                expected: committed[5] + Fr::from(1),
+            }
+        );
+        // an opening of any other cell does not settle the dispute
+        assert_eq!(
+            challenge.settle(
+                &vk,
+                prover.root_version(),
+                prover.root(),
+                &prover.sample_cell(1, 4)
+            ),
+            Verdict::DefenderFaulty
+        );
+
+        // the row commitment settles agreement before any digest is exchanged
+        let mut agreeing = RowChallenge::<Fr, Sha256>::new(1, committed.clone());
+        assert_eq!(
+            agreeing.bind(&vk, prover.root_version(), prover.root(), &ours, &first),
+            Some(Verdict::Agreed)
+        );
+    }
+
+    #[test]
+    pub fn defenders_echoing_the_challenger_are_faulty() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 4);
+        let vk = verifier_key(prover.params());
+        let mut expected = prover.square().row_vals(1);
+        expected[5] += Fr::from(1);
+        // standing in for the commitment to the challenger's row
+        let ours = prover.commit_to_row(0);
+
+        // digests are refused until a sample binds the defender's commitment
+        let mut challenge = RowChallenge::<Fr, Sha256>::new(1, expected.clone());
+        let (left, right) = split_digests::<Fr, Sha256>(&expected, 0, 8).unwrap();
+        assert_eq!(challenge.narrow(left, right), Some(Verdict::DefenderFaulty));
+
+        // once bound, a first round claiming our row in both halves is a lie
+        let mut challenge = RowChallenge::<Fr, Sha256>::new(1, expected.clone());
+        let first = prover.sample_cell(1, 0);
+        assert_eq!(
+            challenge.bind(&vk, prover.root_version(), prover.root(), &ours, &first),
+            None
+        );
+        assert_eq!(
+            challenge.narrow(
+                range_digest::<Fr, Sha256>(&expected[..4]),
+                range_digest::<Fr, Sha256>(&expected[4..])
+            ),
+            Some(Verdict::DefenderFaulty)
+        );
+        // and an opening of another cell does not bind it
+        let mut challenge = RowChallenge::<Fr, Sha256>::new(1, expected);
+        assert_eq!(
+            challenge.bind(
+                &vk,
+                prover.root_version(),
+                prover.root(),
+                &ours,
+                &prover.sample_cell(1, 2)
+            ),
+            Some(Verdict::DefenderFaulty)
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use crate::codec::{put_bytes, put_u64, take_bytes, take_u64};
+
+use ark_serialize::SerializationError;
+
+/// Which cells of a (length x length) square are held, one bit per cell in
+/// row-major order
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellBitfield {
+    length: usize,
+    bits: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(CellBitfield);
+
+impl CellBitfield {
+    /// No cell held
+    pub fn empty(length: usize) -> Self {
+        Self {
+            length,
+            bits: vec![0; (length * length).div_ceil(8)],
+        }
+    }
+
+    /// Every cell held
+    pub fn full(length: usize) -> Self {
+        let mut bitfield = Self::empty(length);
+        for rid in 0..length {
+            for cid in 0..length {
+                bitfield.set(rid, cid);
+            }
+        }
+        bitfield
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn contains(&self, rid: usize, cid: usize) -> bool {
+        if rid >= self.length || cid >= self.length {
+            return false;
+        }
+        let idx = rid * self.length + cid;
+        self.bits[idx / 8] & (1 << (idx % 8)) != 0
+    }
+
+    /// Panics if (rid, cid) is outside the square
+    pub fn set(&mut self, rid: usize, cid: usize) {
+        assert!(rid < self.length && cid < self.length, "Cell out of bounds");
+        let idx = rid * self.length + cid;
+        self.bits[idx / 8] |= 1 << (idx % 8);
+    }
+
+    /// Number of cells held
+    pub fn count(&self) -> usize {
+        self.bits
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.count() == self.length * self.length
+    }
+
+    pub fn row_count(&self, rid: usize) -> usize {
+        (0..self.length)
+            .filter(|cid| self.contains(rid, *cid))
+            .count()
+    }
+
+    pub fn col_count(&self, cid: usize) -> usize {
+        (0..self.length)
+            .filter(|rid| self.contains(*rid, cid))
+            .count()
+    }
+
+    /// Cells not held, in row-major order
+    pub fn missing(&self) -> Vec<(usize, usize)> {
+        (0..self.length)
+            .flat_map(|rid| (0..self.length).map(move |cid| (rid, cid)))
+            .filter(|(rid, cid)| !self.contains(*rid, *cid))
+            .collect()
+    }
+
+    /// Cells held by either bitfield. Panics if the squares differ in size.
+    pub fn union(&self, other: &CellBitfield) -> CellBitfield {
+        assert_eq!(self.length, other.length, "Bitfields of different squares");
+        CellBitfield {
+            length: self.length,
+            bits: self
+                .bits
+                .iter()
+                .zip(&other.bits)
+                .map(|(a, b)| a | b)
+                .collect(),
+        }
+    }
+
+    /// Cells recoverable from the held ones by repeatedly decoding every row and
+    /// column holding at least `n` cells, `n` being the side of the original square
+    pub fn closure(&self, n: usize) -> CellBitfield {
+        let mut closure = self.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..self.length {
+                let row_count = closure.row_count(idx);
+                if row_count >= n && row_count < self.length {
+                    (0..self.length).for_each(|cid| closure.set(idx, cid));
+                    changed = true;
+                }
+                let col_count = closure.col_count(idx);
+                if col_count >= n && col_count < self.length {
+                    (0..self.length).for_each(|rid| closure.set(rid, idx));
+                    changed = true;
+                }
+            }
+        }
+        closure
+    }
+
+    /// Whether the whole square can be reconstructed from the held cells
+    pub fn is_recoverable(&self, n: usize) -> bool {
+        self.closure(n).is_full()
+    }
+
+    /// Run-length encoding for gossip: the side length, then the lengths of the
+    /// alternating runs of missing and held cells as LEB128 varints, starting with
+    /// a missing run. Full and empty squares compress to a few bytes.
+    pub fn to_compressed(&self) -> Vec<u8> {
+        let mut out = vec![];
+        put_varint(&mut out, self.length as u64);
+        let cells = self.length * self.length;
+        let (mut held, mut run) = (false, 0u64);
+        for idx in 0..cells {
+            if self.contains(idx / self.length, idx % self.length) != held {
+                put_varint(&mut out, run);
+                held = !held;
+                run = 0;
+            }
+            run += 1;
+        }
+        put_varint(&mut out, run);
+        out
+    }
+
+    /// Undo `to_compressed`, refusing squares wider than `max_length` before
+    /// anything is allocated for them
+    pub fn from_compressed(
+        mut bytes: &[u8],
+        max_length: usize,
+    ) -> Result<Self, SerializationError> {
+        let length = take_varint(&mut bytes)?;
+        if length > max_length as u64 {
+            return Err(SerializationError::InvalidData);
+        }
+        let length = length as usize;
+        let cells = length
+            .checked_mul(length)
+            .ok_or(SerializationError::InvalidData)?;
+        let mut bitfield = Self::empty(length);
+        let (mut idx, mut held) = (0, false);
+        while !bytes.is_empty() {
+            let run = take_varint(&mut bytes)? as usize;
+            let end = idx + run;
+            if end > cells {
+                return Err(SerializationError::InvalidData);
+            }
+            if held {
+                (idx..end).for_each(|idx| bitfield.set(idx / length, idx % length));
+            }
+            idx = end;
+            held = !held;
+        }
+        if idx != cells {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(bitfield)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        put_u64(&mut out, self.length as u64);
+        put_bytes(&mut out, &self.bits);
+        out
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let length = take_u64(&mut bytes)? as usize;
+        let bits = take_bytes(&mut bytes)?.to_vec();
+        let cells = length
+            .checked_mul(length)
+            .ok_or(SerializationError::InvalidData)?;
+        if !bytes.is_empty() || bits.len() != cells.div_ceil(8) {
+            return Err(SerializationError::InvalidData);
+        }
+        // bits past the last cell must be clear so that equal bitfields encode equally
+        if cells % 8 != 0 && bits[bits.len() - 1] >> (cells % 8) != 0 {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self { length, bits })
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn take_varint(bytes: &mut &[u8]) -> Result<u64, SerializationError> {
+    let mut val = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first().ok_or(SerializationError::InvalidData)?;
+        *bytes = rest;
+        val |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err(SerializationError::InvalidData)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CellBitfield;
+
+    #[test]
+    pub fn closure_follows_row_and_column_decoding() {
+        // original side 2 extended to 4: any 2 cells of a line recover it
+        let mut held = CellBitfield::empty(4);
+        held.set(0, 0);
+        held.set(0, 1);
+        held.set(1, 2);
+        held.set(2, 3);
+        assert_eq!(held.count(), 4);
+        assert!(held.contains(0, 1) && !held.contains(1, 1) && !held.contains(4, 0));
+
+        // row 0 is recovered, which gives columns 2 and 3 two cells each, after
+        // which every row holds two
+        assert!(held.is_recoverable(2));
+        let mut stuck = CellBitfield::empty(4);
+        stuck.set(0, 0);
+        stuck.set(1, 1);
+        stuck.set(2, 2);
+        assert!(!stuck.is_recoverable(2));
+        assert_eq!(stuck.closure(2), stuck);
+        assert_eq!(stuck.missing().len(), 13);
+        assert!(stuck.union(&held).is_recoverable(2));
+
+        assert_eq!(CellBitfield::from_bytes(&held.to_bytes()).unwrap(), held);
+        assert_eq!(
+            CellBitfield::from_compressed(&held.to_compressed(), 4).unwrap(),
+            held
+        );
+        let full = CellBitfield::full(128);
+        assert!(full.to_compressed().len() <= 6);
+        assert_eq!(
+            CellBitfield::from_compressed(&full.to_compressed(), 128).unwrap(),
+            full
+        );
+        assert!(CellBitfield::from_compressed(&full.to_compressed(), 64).is_err());
+        // a claimed side near 2^32 would take 2^61 bytes
+        assert!(
+            CellBitfield::from_compressed(&[0x80, 0x80, 0x80, 0x80, 0x10, 0], 1 << 20).is_err()
+        );
+        assert!(CellBitfield::from_compressed(&[4, 3], 4).is_err());
+        assert!(CellBitfield::full(3).is_full());
+        assert_eq!(
+            CellBitfield::from_bytes(&CellBitfield::full(3).to_bytes()).unwrap(),
+            CellBitfield::full(3)
+        );
+    }
+}
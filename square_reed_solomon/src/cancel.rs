@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Work abandoned because its token was cancelled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+/// Asks long-running work, such as extending, committing to or rebuilding a large
+/// square, to stop at its next line, e.g. because the node is shutting down or
+/// moved on to a newer height. Clones share one flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// `Err(Cancelled)` once the token is cancelled, letting other threads run
+    /// before the work goes on otherwise
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            return Err(Cancelled);
+        }
+        std::thread::yield_now();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CancelToken, Cancelled};
+    use crate::prover::RsSquareProver;
+    use crate::reconstruct::{PartialSquare, ReconstructionError};
+    use crate::rs_line::RsLine;
+    use crate::rs_square::RsSquare;
+
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn cancelled_work_stops() {
+        let shares = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+        ];
+        let lines: Vec<RsLine<Fr>> = shares.iter().map(|row| RsLine::new(row, 2)).collect();
+        let cancel = CancelToken::new();
+        let mut square = RsSquare::new(&lines, 2);
+        square.extend_cancellable(&cancel).unwrap();
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        assert_eq!(
+            prover.commit_all_rows_cancellable(&cancel),
+            Ok(prover.commit_all_rows())
+        );
+
+        let mut partial = PartialSquare::new(4, 2).unwrap();
+        for (rid, cid) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            partial.set(rid, cid, square.val_at(rid, cid));
+        }
+        cancel.clone().cancel();
+        assert_eq!(
+            partial.reconstruct_cancellable(&cancel).err(),
+            Some(ReconstructionError::Cancelled)
+        );
+        assert_eq!(prover.commit_all_cols_cancellable(&cancel), Err(Cancelled));
+        let mut square = RsSquare::new(&lines, 2);
+        assert_eq!(square.extend_cancellable(&cancel), Err(Cancelled));
+    }
+}
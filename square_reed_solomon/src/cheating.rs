@@ -0,0 +1,173 @@
+use crate::prover::{open_poly, RsSquareProver};
+use crate::sample::{CellSample, LineAxis, LineSample};
+
+use ark_ec::pairing::Pairing;
+use ark_ff::One;
+use ark_poly_commit::kzg10;
+use rs_merkle::Hasher;
+
+use std::sync::Arc;
+
+/// Way a `CheatingProver` corrupts what it serves
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cheat {
+    /// Another value than the square's, with the honest proof of the true one
+    WrongEvaluation,
+    /// The true value, with the opening of the next line or cell
+    WrongWitness,
+    /// The column through the cell, or the column of the same index, passed off as
+    /// the row
+    SwappedAxes,
+    /// A consistent sample of a square committed to earlier, under its own roots
+    StaleRoot,
+}
+
+impl Cheat {
+    pub const ALL: [Cheat; 4] = [
+        Cheat::WrongEvaluation,
+        Cheat::WrongWitness,
+        Cheat::SwappedAxes,
+        Cheat::StaleRoot,
+    ];
+}
+
+/// Test double answering samples like `RsSquareProver` does, but corrupting every
+/// answer in the way `cheat` says, so tests can check each failure is caught.
+/// It claims the root of the honest square it was built from.
+pub struct CheatingProver<E: Pairing, H: Hasher> {
+    honest: RsSquareProver<E, H>,
+    /// Prover of the same square with its first share changed, for stale answers
+    stale: RsSquareProver<E, H>,
+    cheat: Cheat,
+}
+
+impl<E: Pairing, H: Hasher> CheatingProver<E, H> {
+    pub fn new(
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+        params: Arc<kzg10::UniversalParams<E>>,
+        cheat: Cheat,
+    ) -> Self {
+        let mut stale_shares = shares.clone();
+        stale_shares[0][0] += E::ScalarField::one();
+        Self {
+            honest: RsSquareProver::with_params(shares, scale, params.clone()),
+            stale: RsSquareProver::with_params(&stale_shares, scale, params),
+            cheat,
+        }
+    }
+
+    /// Prover of the square this one claims to serve
+    pub fn honest(&self) -> &RsSquareProver<E, H> {
+        &self.honest
+    }
+
+    pub fn root(&self) -> H::Hash {
+        self.honest.root()
+    }
+
+    pub fn sample_cell(&self, rid: usize, cid: usize) -> CellSample<E, H> {
+        let mut sample = self.honest.sample_cell(rid, cid);
+        match self.cheat {
+            Cheat::WrongEvaluation => sample.value += E::ScalarField::one(),
+            Cheat::WrongWitness => {
+                sample.opening = self.honest.open_cell(rid, (cid + 1) % self.length())
+            }
+            Cheat::SwappedAxes => {
+                let square = self.honest.square();
+                sample.value = square.val_at(cid, rid);
+                sample.row_commitment = self.honest.commit_to_col(rid);
+                sample.row_path = self.honest.col_commitment_proof(rid);
+                sample.opening = open_poly(
+                    self.honest.params(),
+                    &square.col_poly(rid),
+                    square.domain_element(cid),
+                );
+            }
+            Cheat::StaleRoot => sample = self.stale.sample_cell(rid, cid),
+        }
+        sample
+    }
+
+    pub fn sample_row(&self, rid: usize) -> LineSample<E, H> {
+        let mut sample = self.honest.sample_row(rid);
+        match self.cheat {
+            Cheat::WrongEvaluation => sample.values[0] += E::ScalarField::one(),
+            Cheat::WrongWitness => {
+                sample.opening = self.honest.sample_row((rid + 1) % self.length()).opening
+            }
+            Cheat::SwappedAxes => {
+                sample = LineSample {
+                    axis: LineAxis::Row,
+                    ..self.honest.sample_col(rid)
+                }
+            }
+            Cheat::StaleRoot => sample = self.stale.sample_row(rid),
+        }
+        sample
+    }
+
+    fn length(&self) -> usize {
+        self.honest.shares().len() * self.honest.scale()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cheat, CheatingProver};
+    use crate::verifier::{verifier_key, verify_cell_sample, verify_line_sample};
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    use std::sync::Arc;
+
+    #[test]
+    pub fn every_cheat_is_caught() {
+        let params = Arc::new(
+            KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+                .expect("KZG setup failed"),
+        );
+        let vk = verifier_key(&params);
+        // not symmetric, so its rows and columns differ
+        let shares = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+        ];
+        for cheat in Cheat::ALL {
+            let cheater =
+                CheatingProver::<Bls12_381, Sha256>::new(&shares, 2, params.clone(), cheat);
+            let root = cheater.root();
+            let honest = cheater.honest();
+            let version = honest.root_version();
+            assert!(verify_cell_sample(
+                &vk,
+                version,
+                root,
+                4,
+                &honest.sample_cell(1, 2)
+            ));
+            assert!(verify_line_sample(
+                &vk,
+                0,
+                version,
+                root,
+                4,
+                &honest.sample_row(1)
+            ));
+            assert!(
+                !verify_cell_sample(&vk, version, root, 4, &cheater.sample_cell(1, 2)),
+                "{:?} cell passed",
+                cheat
+            );
+            assert!(
+                !verify_line_sample(&vk, 0, version, root, 4, &cheater.sample_row(1)),
+                "{:?} row passed",
+                cheat
+            );
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use ark_ec::pairing::Pairing;
+use ark_ff::{Field, PrimeField};
+use ark_poly_commit::kzg10::{Commitment, Proof, VerifierKey};
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, SerializationError, Validate,
+};
+use rs_merkle::Hasher;
+
+/// How far bytes are trusted when decoding field elements and curve points
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Validation {
+    /// Bytes from peers: every point must lie on the curve and in the prime order
+    /// subgroup, and every value must be canonically encoded with nothing after it,
+    /// all before any pairing is computed
+    Strict,
+    /// Bytes this node wrote itself, e.g. its snapshots and cold store, decoded
+    /// without the subgroup checks
+    Trusted,
+}
+
+/// Decode the compressed encoding of a value, checked as `validation` says
+pub fn decode<T: CanonicalSerialize + CanonicalDeserialize>(
+    bytes: &[u8],
+    validation: Validation,
+) -> Result<T, SerializationError> {
+    match validation {
+        Validation::Strict => {
+            let val = T::deserialize_with_mode(bytes, Compress::Yes, Validate::Yes)?;
+            // the only encoding accepted is the one this value serializes to
+            let mut canonical = Vec::with_capacity(bytes.len());
+            val.serialize_compressed(&mut canonical)?;
+            if canonical != bytes {
+                return Err(SerializationError::InvalidData);
+            }
+            Ok(val)
+        }
+        Validation::Trusted => T::deserialize_with_mode(bytes, Compress::Yes, Validate::No),
+    }
+}
+
+pub fn field_to_bytes<F: Field>(val: &F) -> Vec<u8> {
+    let mut bytes = vec![];
+    val.serialize_compressed(&mut bytes)
+        .expect("Serializing field element should not fail");
+    bytes
+}
+
+pub fn field_from_bytes<F: Field>(bytes: &[u8]) -> Result<F, SerializationError> {
+    decode(bytes, Validation::Strict)
+}
+
+pub fn line_to_bytes<F: PrimeField>(line: &[F]) -> Vec<Vec<u8>> {
+    line.iter().map(field_to_bytes).collect()
+}
+
+pub fn line_from_bytes<F: PrimeField>(line: &[Vec<u8>]) -> Result<Vec<F>, SerializationError> {
+    line.iter().map(|bytes| field_from_bytes(bytes)).collect()
+}
+
+pub fn commitment_to_bytes<E: Pairing>(com: &Commitment<E>) -> Vec<u8> {
+    let mut bytes = vec![];
+    com.serialize_compressed(&mut bytes)
+        .expect("Serializing commitment should not fail");
+    bytes
+}
+
+pub fn commitment_from_bytes<E: Pairing>(
+    bytes: &[u8],
+) -> Result<Commitment<E>, SerializationError> {
+    decode(bytes, Validation::Strict)
+}
+
+pub fn proof_from_bytes<E: Pairing>(bytes: &[u8]) -> Result<Proof<E>, SerializationError> {
+    decode(bytes, Validation::Strict)
+}
+
+/// Version of the verifier key encoding, bumped whenever the layout changes
+pub const VK_FORMAT_VERSION: u8 = 1;
+
+/// Versioned compressed encoding of a verifier key, suitable for distribution and caching
+pub fn verifier_key_to_bytes<E: Pairing>(vk: &VerifierKey<E>) -> Vec<u8> {
+    let mut bytes = vec![VK_FORMAT_VERSION];
+    vk.serialize_compressed(&mut bytes)
+        .expect("Serializing verifier key should not fail");
+    bytes
+}
+
+pub fn verifier_key_from_bytes<E: Pairing>(
+    bytes: &[u8],
+) -> Result<VerifierKey<E>, SerializationError> {
+    match bytes.split_first() {
+        Some((&VK_FORMAT_VERSION, key)) => decode(key, Validation::Strict),
+        _ => Err(SerializationError::InvalidData),
+    }
+}
+
+/// Digest identifying a setup, carried in headers and handshakes so mismatched
+/// setups are caught before any verification work
+pub fn verifier_key_digest<E: Pairing, H: Hasher>(vk: &VerifierKey<E>) -> H::Hash {
+    H::hash(&verifier_key_to_bytes(vk))
+}
+
+/// Append `data` prefixed by its length as a little-endian u32
+pub fn put_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Split a length-prefixed chunk written by `put_bytes` off the front of `bytes`
+pub fn take_bytes<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], SerializationError> {
+    if bytes.len() < 4 {
+        return Err(SerializationError::InvalidData);
+    }
+    let (len, rest) = bytes.split_at(4);
+    let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+    if rest.len() < len {
+        return Err(SerializationError::InvalidData);
+    }
+    let (data, rest) = rest.split_at(len);
+    *bytes = rest;
+    Ok(data)
+}
+
+pub fn take_u64(bytes: &mut &[u8]) -> Result<u64, SerializationError> {
+    let data: [u8; 8] = take_bytes(bytes)?
+        .try_into()
+        .map_err(|_| SerializationError::InvalidData)?;
+    Ok(u64::from_le_bytes(data))
+}
+
+pub fn take_hash<H: Hasher>(bytes: &mut &[u8]) -> Result<H::Hash, SerializationError> {
+    <H::Hash as TryFrom<Vec<u8>>>::try_from(take_bytes(bytes)?.to_vec())
+        .map_err(|_| SerializationError::InvalidData)
+}
+
+pub fn put_u64(out: &mut Vec<u8>, val: u64) {
+    put_bytes(out, &val.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        commitment_from_bytes, decode, field_from_bytes, field_to_bytes, line_from_bytes,
+        line_to_bytes, Validation,
+    };
+
+    use ark_ec::pairing::Pairing;
+    use ark_ec::short_weierstrass::Affine;
+    use ark_ec::AffineRepr;
+    use ark_ff::{BigInteger, PrimeField};
+    use ark_poly_commit::kzg10::Commitment;
+    use ark_serialize::CanonicalSerialize;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+
+    type G1Affine = <Bls12_381 as Pairing>::G1Affine;
+
+    #[test]
+    pub fn field_round_trip() {
+        let line = vec![Fr::from(3), Fr::from(1), Fr::from(4)];
+        assert_eq!(
+            field_from_bytes::<Fr>(&field_to_bytes(&line[0])).unwrap(),
+            line[0]
+        );
+        assert_eq!(line_from_bytes::<Fr>(&line_to_bytes(&line)).unwrap(), line);
+    }
+
+    #[test]
+    pub fn strict_decoding_rejects_points_off_the_subgroup() {
+        // a point on the curve outside the prime order subgroup
+        let point: G1Affine = (1u64..)
+            .filter_map(|x| {
+                let x = <G1Affine as AffineRepr>::BaseField::from(x);
+                Affine::get_point_from_x_unchecked(x, false)
+            })
+            .find(|point| !point.is_in_correct_subgroup_assuming_on_curve())
+            .unwrap();
+        let mut bytes = vec![];
+        point.serialize_compressed(&mut bytes).unwrap();
+        assert!(commitment_from_bytes::<Bls12_381>(&bytes).is_err());
+        assert!(decode::<Commitment<Bls12_381>>(&bytes, Validation::Trusted).is_ok());
+
+        // nothing may follow a value, and field elements must be reduced
+        let mut padded = field_to_bytes(&Fr::from(5));
+        padded.push(0);
+        assert!(field_from_bytes::<Fr>(&padded).is_err());
+        let modulus = Fr::MODULUS.to_bytes_le();
+        assert!(field_from_bytes::<Fr>(&modulus).is_err());
+    }
+}
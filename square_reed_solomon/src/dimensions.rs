@@ -0,0 +1,218 @@
+use crate::codec::{put_u64, take_u64};
+use crate::rs_square::is_power_of_two;
+use crate::verifier::RootVersion;
+
+use ark_ff::PrimeField;
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::SerializationError;
+
+/// Square configuration chosen for a payload by `choose_dimensions`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SquareDimensions {
+    /// Side of the original square
+    pub n_rows: usize,
+    pub scale: usize,
+    /// Side of the extended square
+    pub length: usize,
+    /// Shares the payload occupies
+    pub payload_shares: usize,
+    /// Zero shares filling the original square after the payload
+    pub padding_shares: usize,
+    /// Fraction of every row or column that may be lost with the line still
+    /// recoverable
+    pub erasure_tolerance: f64,
+}
+
+impl SquareDimensions {
+    /// Cells of the extended square per share of payload
+    pub fn overhead(&self) -> f64 {
+        (self.length * self.length) as f64 / self.payload_shares.max(1) as f64
+    }
+
+    pub fn layout(&self) -> SquareLayout {
+        SquareLayout {
+            n_rows: self.n_rows,
+            scale: self.scale,
+            root_version: RootVersion::LATEST,
+        }
+    }
+}
+
+/// Geometry of a square, as carried in headers so that peers learn the side of
+/// the square behind a data root, and the rules it was computed under, without
+/// fetching any of it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SquareLayout {
+    /// Side of the original square
+    pub n_rows: usize,
+    pub scale: usize,
+    /// Version the data root was computed under, which every path and sample of
+    /// the square is checked against
+    pub root_version: RootVersion,
+}
+
+impl SquareLayout {
+    /// Layout under `RootVersion::LATEST`. `None` unless both are powers of two,
+    /// as every square's are.
+    pub fn new(n_rows: usize, scale: usize) -> Option<Self> {
+        if !is_power_of_two(n_rows) || !is_power_of_two(scale) {
+            return None;
+        }
+        n_rows.checked_mul(scale)?;
+        Some(Self {
+            n_rows,
+            scale,
+            root_version: RootVersion::LATEST,
+        })
+    }
+
+    pub fn with_root_version(mut self, root_version: RootVersion) -> Self {
+        self.root_version = root_version;
+        self
+    }
+
+    /// Root version of the square whose header carries `layout`. Headers carrying
+    /// no layout at all are taken as `RootVersion::LATEST`; `None` if the layout
+    /// does not decode, so that no root is checked under a guessed version.
+    pub fn root_version_of(layout: &[u8]) -> Option<RootVersion> {
+        if layout.is_empty() {
+            return Some(RootVersion::LATEST);
+        }
+        Self::from_bytes(layout)
+            .ok()
+            .map(|layout| layout.root_version)
+    }
+
+    /// Side of the extended square
+    pub fn length(&self) -> usize {
+        self.n_rows * self.scale
+    }
+
+    pub fn log2_length(&self) -> u32 {
+        self.length().trailing_zeros()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        put_u64(&mut out, self.n_rows as u64);
+        put_u64(&mut out, self.scale as u64);
+        put_u64(&mut out, self.root_version.id() as u64);
+        out
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let n_rows = take_u64(&mut bytes)?;
+        let scale = take_u64(&mut bytes)?;
+        let root_version = u8::try_from(take_u64(&mut bytes)?)
+            .ok()
+            .and_then(RootVersion::from_id)
+            .ok_or(SerializationError::InvalidData)?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Self::new(n_rows as usize, scale as usize)
+            .map(|layout| layout.with_root_version(root_version))
+            .ok_or(SerializationError::InvalidData)
+    }
+}
+
+/// Payload bytes that fit in one share without reduction modulo the field
+pub fn bytes_per_share<F: PrimeField>() -> usize {
+    (F::MODULUS_BIT_SIZE as usize - 1) / 8
+}
+
+/// Smallest square holding `payload_len` bytes whose lines survive losing an
+/// `erasure_rate` fraction of their cells. `None` if the rate is not in `0.0..1.0`
+/// or the field has no domain large enough.
+pub fn choose_dimensions<F: PrimeField>(
+    payload_len: usize,
+    erasure_rate: f64,
+) -> Option<SquareDimensions> {
+    if !(0.0..1.0).contains(&erasure_rate) {
+        return None;
+    }
+    let payload_shares = payload_len.div_ceil(bytes_per_share::<F>());
+    let mut n_rows = 1;
+    while n_rows * n_rows < payload_shares {
+        n_rows *= 2;
+    }
+    // a line of length n * scale is recovered from any n of its cells
+    let mut scale = 1;
+    while 1.0 - 1.0 / (scale as f64) < erasure_rate {
+        scale *= 2;
+    }
+    let length = n_rows * scale;
+    Radix2EvaluationDomain::<F>::new(length)?;
+    Some(SquareDimensions {
+        n_rows,
+        scale,
+        length,
+        payload_shares,
+        padding_shares: n_rows * n_rows - payload_shares,
+        erasure_tolerance: 1.0 - 1.0 / (scale as f64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bytes_per_share, choose_dimensions, SquareLayout};
+    use crate::verifier::RootVersion;
+    use ark_test_curves::bls12_381::Fr;
+
+    #[test]
+    pub fn smallest_square_meeting_the_erasure_rate() {
+        assert_eq!(bytes_per_share::<Fr>(), 31);
+
+        let dims = choose_dimensions::<Fr>(31 * 17, 0.5).unwrap();
+        assert_eq!((dims.n_rows, dims.scale, dims.length), (8, 2, 16));
+        assert_eq!((dims.payload_shares, dims.padding_shares), (17, 47));
+        assert_eq!(dims.erasure_tolerance, 0.5);
+
+        let dims = choose_dimensions::<Fr>(31 * 16, 0.6).unwrap();
+        assert_eq!((dims.n_rows, dims.scale), (4, 4));
+        assert_eq!(dims.overhead(), 16.0);
+
+        assert_eq!(choose_dimensions::<Fr>(0, 0.0).unwrap().length, 1);
+        assert!(choose_dimensions::<Fr>(10, 1.0).is_none());
+    }
+
+    #[test]
+    pub fn layouts_round_trip_and_must_be_powers_of_two() {
+        let layout = choose_dimensions::<Fr>(31 * 17, 0.5).unwrap().layout();
+        assert_eq!(layout, SquareLayout::new(8, 2).unwrap());
+        assert_eq!((layout.length(), layout.log2_length()), (16, 4));
+        assert_eq!(
+            SquareLayout::from_bytes(&layout.to_bytes()).unwrap(),
+            layout
+        );
+
+        assert!(SquareLayout::new(6, 2).is_none());
+        assert!(SquareLayout::new(8, 0).is_none());
+        let mut bytes = layout.to_bytes();
+        bytes.push(0);
+        assert!(SquareLayout::from_bytes(&bytes).is_err());
+        assert!(
+            SquareLayout::from_bytes(&SquareLayout::new(4, 1).unwrap().to_bytes()[1..]).is_err()
+        );
+
+        // the root version travels with the geometry, and unknown ones are refused
+        let legacy = layout.with_root_version(RootVersion::V1);
+        let decoded = SquareLayout::from_bytes(&legacy.to_bytes()).unwrap();
+        assert_eq!(decoded.root_version, RootVersion::V1);
+        assert_eq!(
+            SquareLayout::root_version_of(&legacy.to_bytes()),
+            Some(RootVersion::V1)
+        );
+        assert_eq!(
+            SquareLayout::root_version_of(&[]),
+            Some(RootVersion::LATEST)
+        );
+        let mut unknown = layout.to_bytes();
+        let last = unknown.len() - 8;
+        unknown[last] = 9;
+        assert!(SquareLayout::from_bytes(&unknown).is_err());
+        assert_eq!(SquareLayout::root_version_of(&unknown), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! Provers and verifiers over a curve chosen at runtime, e.g. from a node's
+//! config, so that one binary serves squares over either curve. Merkle trees use
+//! SHA-256, and shares, commitments, keys and samples cross this layer as their
+//! canonical encodings, as they do on the wire.
+
+use crate::codec::{
+    commitment_to_bytes, decode, field_from_bytes, field_to_bytes, verifier_key_from_bytes,
+    verifier_key_to_bytes, Validation,
+};
+use crate::dimensions::SquareLayout;
+use crate::prover::RsSquareProver;
+use crate::sample::CellSample;
+use crate::verifier::{verifier_key, verify_cell_sample, RootVersion};
+
+use ark_bn254::Bn254;
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Commitment, UniversalParams, VerifierKey};
+use ark_serialize::SerializationError;
+use ark_test_curves::bls12_381::Bls12_381;
+use rs_merkle::algorithms::Sha256;
+
+use std::str::FromStr;
+
+/// Pairing-friendly curves a square can be committed over
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Curve {
+    Bls12_381,
+    Bn254,
+}
+
+impl Curve {
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::Bls12_381 => "bls12-381",
+            Curve::Bn254 => "bn254",
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = SerializationError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "bls12-381" => Ok(Curve::Bls12_381),
+            "bn254" => Ok(Curve::Bn254),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+/// `RsSquareProver` over the curve it was built for
+pub enum DynProver {
+    Bls12_381(RsSquareProver<Bls12_381, Sha256>),
+    Bn254(RsSquareProver<Bn254, Sha256>),
+}
+
+impl DynProver {
+    /// Prover over a fresh setup, for tests and local runs. `shares` holds the
+    /// encoding of every original share, row by row.
+    pub fn new(
+        curve: Curve,
+        shares: &[Vec<Vec<u8>>],
+        scale: usize,
+    ) -> Result<Self, SerializationError> {
+        Ok(match curve {
+            Curve::Bls12_381 => DynProver::Bls12_381(RsSquareProver::new(
+                &decode_shares::<Bls12_381>(shares)?,
+                scale,
+            )),
+            Curve::Bn254 => {
+                DynProver::Bn254(RsSquareProver::new(&decode_shares::<Bn254>(shares)?, scale))
+            }
+        })
+    }
+
+    /// Prover under `setup`, the compressed setup of the ceremony for `curve`
+    pub fn with_setup(
+        curve: Curve,
+        setup: &[u8],
+        shares: &[Vec<Vec<u8>>],
+        scale: usize,
+    ) -> Result<Self, SerializationError> {
+        Ok(match curve {
+            Curve::Bls12_381 => DynProver::Bls12_381(RsSquareProver::with_params(
+                &decode_shares::<Bls12_381>(shares)?,
+                scale,
+                decode::<UniversalParams<Bls12_381>>(setup, Validation::Strict)?,
+            )),
+            Curve::Bn254 => DynProver::Bn254(RsSquareProver::with_params(
+                &decode_shares::<Bn254>(shares)?,
+                scale,
+                decode::<UniversalParams<Bn254>>(setup, Validation::Strict)?,
+            )),
+        })
+    }
+
+    pub fn curve(&self) -> Curve {
+        match self {
+            DynProver::Bls12_381(_) => Curve::Bls12_381,
+            DynProver::Bn254(_) => Curve::Bn254,
+        }
+    }
+
+    /// Side of the extended square
+    pub fn length(&self) -> usize {
+        match self {
+            DynProver::Bls12_381(prover) => prover.shares().len() * prover.scale(),
+            DynProver::Bn254(prover) => prover.shares().len() * prover.scale(),
+        }
+    }
+
+    pub fn layout(&self) -> SquareLayout {
+        match self {
+            DynProver::Bls12_381(prover) => prover.layout(),
+            DynProver::Bn254(prover) => prover.layout(),
+        }
+    }
+
+    pub fn root(&self) -> Vec<u8> {
+        match self {
+            DynProver::Bls12_381(prover) => prover.root().to_vec(),
+            DynProver::Bn254(prover) => prover.root().to_vec(),
+        }
+    }
+
+    /// Encoded value at (rid, cid) of the extended square
+    pub fn value(&self, rid: usize, cid: usize) -> Vec<u8> {
+        match self {
+            DynProver::Bls12_381(prover) => field_to_bytes(&prover.square().val_at(rid, cid)),
+            DynProver::Bn254(prover) => field_to_bytes(&prover.square().val_at(rid, cid)),
+        }
+    }
+
+    /// Encoded commitments to every row of the extended square
+    pub fn row_commitments(&self) -> Vec<Vec<u8>> {
+        match self {
+            DynProver::Bls12_381(prover) => encode_commitments(&prover.commit_all_rows()),
+            DynProver::Bn254(prover) => encode_commitments(&prover.commit_all_rows()),
+        }
+    }
+
+    /// Encoded commitments to every column of the extended square
+    pub fn col_commitments(&self) -> Vec<Vec<u8>> {
+        match self {
+            DynProver::Bls12_381(prover) => encode_commitments(&prover.commit_all_cols()),
+            DynProver::Bn254(prover) => encode_commitments(&prover.commit_all_cols()),
+        }
+    }
+
+    /// Encoded `CellSample` answering a query for (rid, cid)
+    pub fn sample_cell(&self, rid: usize, cid: usize) -> Vec<u8> {
+        match self {
+            DynProver::Bls12_381(prover) => prover.sample_cell(rid, cid).to_bytes(),
+            DynProver::Bn254(prover) => prover.sample_cell(rid, cid).to_bytes(),
+        }
+    }
+
+    /// Verifier matching the prover's setup
+    pub fn verifier(&self) -> DynVerifier {
+        match self {
+            DynProver::Bls12_381(prover) => DynVerifier::Bls12_381(verifier_key(prover.params())),
+            DynProver::Bn254(prover) => DynVerifier::Bn254(verifier_key(prover.params())),
+        }
+    }
+}
+
+/// Verifier key of a setup over the curve it was built for
+#[derive(Clone)]
+pub enum DynVerifier {
+    Bls12_381(VerifierKey<Bls12_381>),
+    Bn254(VerifierKey<Bn254>),
+}
+
+impl DynVerifier {
+    /// Verifier from a key encoded by `to_bytes`
+    pub fn from_bytes(curve: Curve, bytes: &[u8]) -> Result<Self, SerializationError> {
+        Ok(match curve {
+            Curve::Bls12_381 => DynVerifier::Bls12_381(verifier_key_from_bytes(bytes)?),
+            Curve::Bn254 => DynVerifier::Bn254(verifier_key_from_bytes(bytes)?),
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            DynVerifier::Bls12_381(vk) => verifier_key_to_bytes(vk),
+            DynVerifier::Bn254(vk) => verifier_key_to_bytes(vk),
+        }
+    }
+
+    pub fn curve(&self) -> Curve {
+        match self {
+            DynVerifier::Bls12_381(_) => Curve::Bls12_381,
+            DynVerifier::Bn254(_) => Curve::Bn254,
+        }
+    }
+
+    /// Check an encoded `CellSample` against the data root of a square of side
+    /// `length` rooted under `version`. Malformed roots and samples do not verify.
+    pub fn verify_cell_sample(
+        &self,
+        version: RootVersion,
+        root: &[u8],
+        length: usize,
+        sample: &[u8],
+    ) -> bool {
+        let root: [u8; 32] = match root.try_into() {
+            Ok(root) => root,
+            Err(_) => return false,
+        };
+        match self {
+            DynVerifier::Bls12_381(vk) => CellSample::<Bls12_381, Sha256>::from_bytes(sample)
+                .is_ok_and(|sample| verify_cell_sample(vk, version, root, length, &sample)),
+            DynVerifier::Bn254(vk) => CellSample::<Bn254, Sha256>::from_bytes(sample)
+                .is_ok_and(|sample| verify_cell_sample(vk, version, root, length, &sample)),
+        }
+    }
+}
+
+fn decode_shares<E: Pairing>(
+    shares: &[Vec<Vec<u8>>],
+) -> Result<Vec<Vec<E::ScalarField>>, SerializationError> {
+    shares
+        .iter()
+        .map(|row| row.iter().map(|share| field_from_bytes(share)).collect())
+        .collect()
+}
+
+fn encode_commitments<E: Pairing>(commitments: &[Commitment<E>]) -> Vec<Vec<u8>> {
+    commitments.iter().map(commitment_to_bytes::<E>).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Curve, DynProver, DynVerifier};
+
+    #[test]
+    pub fn either_curve_is_picked_at_runtime() {
+        // 0..4, encoded little-endian in the 32 bytes scalars of both curves take
+        let share = |val: u8| {
+            let mut bytes = vec![0; 32];
+            bytes[0] = val;
+            bytes
+        };
+        let shares: Vec<Vec<Vec<u8>>> = (0..2u8)
+            .map(|rid| (0..2u8).map(|cid| share(2 * rid + cid)).collect())
+            .collect();
+        for name in ["bls12-381", "bn254"] {
+            let curve: Curve = name.parse().unwrap();
+            assert_eq!(curve.name(), name);
+            let prover = DynProver::new(curve, &shares, 2).unwrap();
+            assert_eq!(prover.curve(), curve);
+            assert_eq!(prover.row_commitments().len(), prover.length());
+            assert_eq!(prover.layout().length(), prover.length());
+
+            let verifier = DynVerifier::from_bytes(curve, &prover.verifier().to_bytes()).unwrap();
+            let (root, length) = (prover.root(), prover.length());
+            let version = prover.layout().root_version;
+            let sample = prover.sample_cell(3, 1);
+            assert!(verifier.verify_cell_sample(version, &root, length, &sample));
+            assert!(!verifier.verify_cell_sample(version, &root[1..], length, &sample));
+        }
+        assert!("secp256k1".parse::<Curve>().is_err());
+
+        // keys and samples do not cross curves
+        let bls = DynProver::new(Curve::Bls12_381, &shares, 2).unwrap();
+        let key = bls.verifier().to_bytes();
+        assert!(DynVerifier::from_bytes(Curve::Bn254, &key).is_err());
+    }
+}
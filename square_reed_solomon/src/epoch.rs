@@ -0,0 +1,137 @@
+use crate::prover::RsSquareProver;
+use crate::rs_square::RsSquare;
+use crate::sample::CellSample;
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{self, Proof};
+use rs_merkle::Hasher;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Provers for many squares, keyed by height, all sharing a single setup
+/// rather than each holding a copy of it.
+pub struct EpochProver<E: Pairing, H: Hasher> {
+    params: Arc<kzg10::UniversalParams<E>>,
+    provers: BTreeMap<u64, RsSquareProver<E, H>>,
+}
+
+impl<E: Pairing, H: Hasher> EpochProver<E, H> {
+    pub fn new(params: impl Into<Arc<kzg10::UniversalParams<E>>>) -> Self {
+        Self {
+            params: params.into(),
+            provers: BTreeMap::new(),
+        }
+    }
+
+    pub fn params(&self) -> &kzg10::UniversalParams<E> {
+        &self.params
+    }
+
+    /// Encode and add the square built from `shares` at `height`, replacing any
+    /// square already held there
+    pub fn insert(
+        &mut self,
+        height: u64,
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+    ) -> &RsSquareProver<E, H> {
+        let prover = RsSquareProver::with_params(shares, scale, self.params.clone());
+        self.provers.insert(height, prover);
+        &self.provers[&height]
+    }
+
+    /// Add an already extended square at `height`
+    pub fn insert_square(
+        &mut self,
+        height: u64,
+        square: RsSquare<E::ScalarField>,
+        scale: usize,
+    ) -> &RsSquareProver<E, H> {
+        let prover = RsSquareProver::from_square(square, scale, self.params.clone());
+        self.provers.insert(height, prover);
+        &self.provers[&height]
+    }
+
+    pub fn get(&self, height: u64) -> Option<&RsSquareProver<E, H>> {
+        self.provers.get(&height)
+    }
+
+    pub fn remove(&mut self, height: u64) -> Option<RsSquareProver<E, H>> {
+        self.provers.remove(&height)
+    }
+
+    pub fn heights(&self) -> impl Iterator<Item = u64> + '_ {
+        self.provers.keys().copied()
+    }
+
+    /// Data root of the square at `height`
+    pub fn root(&self, height: u64) -> Option<H::Hash> {
+        self.get(height).map(|prover| prover.root())
+    }
+
+    /// KZG opening of cell (rid, cid) of the square at `height`
+    pub fn open_cell(&self, height: u64, rid: usize, cid: usize) -> Option<Proof<E>> {
+        self.in_bounds(height, rid, cid)
+            .map(|prover| prover.open_cell(rid, cid))
+    }
+
+    /// Answer a sample query for cell (rid, cid) of the square at `height`
+    pub fn sample_cell(&self, height: u64, rid: usize, cid: usize) -> Option<CellSample<E, H>> {
+        self.in_bounds(height, rid, cid)
+            .map(|prover| prover.sample_cell(rid, cid))
+    }
+
+    fn in_bounds(&self, height: u64, rid: usize, cid: usize) -> Option<&RsSquareProver<E, H>> {
+        self.get(height).filter(|prover| {
+            let length = prover.shares().len() * prover.scale();
+            rid < length && cid < length
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EpochProver;
+    use crate::verifier::{verifier_key, verify_cell_sample, RootVersion};
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::Bls12_381;
+    use ark_test_curves::bls12_381::Fr;
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn heights_share_one_setup() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(8, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let vk = verifier_key(&params);
+        let mut epoch = EpochProver::<Bls12_381, Sha256>::new(params);
+
+        let first = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+        ];
+        let second = vec![
+            vec![Fr::from(5), Fr::from(6)],
+            vec![Fr::from(7), Fr::from(8)],
+        ];
+        epoch.insert(1, &first, 2);
+        epoch.insert(2, &second, 2);
+        assert_ne!(epoch.root(1), epoch.root(2));
+
+        for height in [1, 2] {
+            let sample = epoch.sample_cell(height, 3, 1).unwrap();
+            assert!(verify_cell_sample(
+                &vk,
+                RootVersion::LATEST,
+                epoch.root(height).unwrap(),
+                4,
+                &sample
+            ));
+        }
+        assert!(epoch.sample_cell(2, 4, 0).is_none());
+        assert!(epoch.sample_cell(3, 0, 0).is_none());
+    }
+}
@@ -0,0 +1,214 @@
+//! Fixed-size shares, the unit deployed DA layers cut block data into. Every
+//! share is `FIXED_SHARE_SIZE` bytes packed into `cells_per_share` consecutive
+//! cells of the original square, counted row-major like the field-element
+//! shares of `LayoutVersion::V1`, so a share may straddle rows. Proving a
+//! share proves all of its cells against their rows.
+
+use crate::dimensions::bytes_per_share;
+use crate::inclusion::{verify_shares, ShareProof};
+use crate::layout::{blob_to_shares, shares_to_blob, LayoutVersion};
+use crate::namespace::pad_to_square;
+use crate::verifier::RootVersion;
+
+use ark_ec::pairing::Pairing;
+use ark_ff::PrimeField;
+use ark_poly_commit::kzg10::VerifierKey;
+use rs_merkle::Hasher;
+use std::ops::Range;
+
+/// Bytes in every fixed share
+pub const FIXED_SHARE_SIZE: usize = 512;
+
+/// Cells one fixed share takes over field `F`, 17 for 31 bytes per cell
+pub fn cells_per_share<F: PrimeField>() -> usize {
+    FIXED_SHARE_SIZE.div_ceil(bytes_per_share::<F>())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FixedShare([u8; FIXED_SHARE_SIZE]);
+
+impl FixedShare {
+    pub fn new(bytes: [u8; FIXED_SHARE_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// `None` unless `bytes` is exactly one share long
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        bytes.try_into().ok().map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; FIXED_SHARE_SIZE] {
+        &self.0
+    }
+
+    /// Cells holding the share, `bytes_per_share` bytes each in little-endian
+    /// order, the last one zero-padded
+    pub fn to_cells<F: PrimeField>(&self) -> Vec<F> {
+        blob_to_shares(&self.0)
+    }
+
+    /// Share held by `cells`, undoing `to_cells`. `None` unless there are
+    /// `cells_per_share` of them and each is one `to_cells` writes, so that no
+    /// two runs of cells decode to the same share.
+    pub fn from_cells<F: PrimeField>(cells: &[F]) -> Option<Self> {
+        if cells.len() != cells_per_share::<F>() {
+            return None;
+        }
+        let share = Self::from_slice(&shares_to_blob(cells, FIXED_SHARE_SIZE))?;
+        if share.to_cells::<F>() != cells {
+            return None;
+        }
+        Some(share)
+    }
+}
+
+/// Fixed shares holding `data`, the last one zero-padded
+pub fn split_fixed_shares(data: &[u8]) -> Vec<FixedShare> {
+    data.chunks(FIXED_SHARE_SIZE)
+        .map(|chunk| {
+            let mut bytes = [0; FIXED_SHARE_SIZE];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            FixedShare(bytes)
+        })
+        .collect()
+}
+
+/// Cells of `shares` back to back, in share order
+pub fn pack_fixed_shares<F: PrimeField>(shares: &[FixedShare]) -> Vec<F> {
+    shares.iter().flat_map(FixedShare::to_cells::<F>).collect()
+}
+
+/// Shares held by `cells`, undoing `pack_fixed_shares`. `None` if the cells do
+/// not split into whole shares.
+pub fn unpack_fixed_shares<F: PrimeField>(cells: &[F]) -> Option<Vec<FixedShare>> {
+    if cells.len() % cells_per_share::<F>() != 0 {
+        return None;
+    }
+    cells
+        .chunks(cells_per_share::<F>())
+        .map(FixedShare::from_cells)
+        .collect()
+}
+
+/// Original square holding `shares` row-major from its first cell, of the
+/// smallest power of two side fitting them, the remaining cells zero
+pub fn fixed_shares_square<F: PrimeField>(shares: &[FixedShare]) -> Vec<Vec<F>> {
+    let cells = pack_fixed_shares::<F>(shares);
+    let mut n = 1;
+    while n * n < cells.len() {
+        n *= 2;
+    }
+    pad_to_square(&cells, n)
+}
+
+/// Cells of the extended square holding fixed share `share` of an original
+/// square of side `n` extended by `scale`, in cell order. `None` if the share
+/// does not fit in the original square.
+pub fn fixed_share_cells<F: PrimeField>(
+    share: usize,
+    n: usize,
+    scale: usize,
+) -> Option<Vec<(usize, usize)>> {
+    let per_share = cells_per_share::<F>();
+    let end = share.checked_add(1)?.checked_mul(per_share)?;
+    if end > n * n {
+        return None;
+    }
+    Some(
+        (end - per_share..end)
+            .map(|cell| LayoutVersion::V1.share_cell(cell, n, scale))
+            .collect(),
+    )
+}
+
+/// Cells holding fixed shares `shares`, in cell order and so grouped by row in
+/// increasing row order as `verify_shares` takes them
+pub fn fixed_share_range_cells<F: PrimeField>(
+    shares: Range<usize>,
+    n: usize,
+    scale: usize,
+) -> Option<Vec<(usize, usize)>> {
+    let mut cells = vec![];
+    for share in shares {
+        cells.extend(fixed_share_cells::<F>(share, n, scale)?);
+    }
+    Some(cells)
+}
+
+/// Check that `proof` shows fixed shares `shares` of an original square of side
+/// `n` extended by `scale` are part of the block at `height` with `data_root`
+/// under `root_version`, however many rows they straddle. Returns the shares,
+/// or `None` if anything fails to verify.
+pub fn verify_fixed_shares<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    root_version: RootVersion,
+    data_root: H::Hash,
+    n: usize,
+    scale: usize,
+    shares: Range<usize>,
+    proof: &ShareProof<E, H>,
+) -> Option<Vec<FixedShare>> {
+    let cells = fixed_share_range_cells::<E::ScalarField>(shares, n, scale)?;
+    let values = verify_shares(
+        vk,
+        height,
+        root_version,
+        data_root,
+        n * scale,
+        &cells,
+        proof,
+    )?;
+    unpack_fixed_shares(&values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cells_per_share, fixed_share_cells, fixed_shares_square, pack_fixed_shares,
+        split_fixed_shares, unpack_fixed_shares, FixedShare, FIXED_SHARE_SIZE,
+    };
+
+    use ark_test_curves::bls12_381::Fr;
+
+    #[test]
+    pub fn fixed_shares_pack_into_cells_and_back() {
+        assert_eq!(cells_per_share::<Fr>(), 17);
+        let data: Vec<u8> = (0..1200u32).map(|byte| (byte % 251) as u8).collect();
+        let shares = split_fixed_shares(&data);
+        assert_eq!(shares.len(), 3);
+        assert_eq!(&shares[1].as_bytes()[..], &data[512..1024]);
+        assert!(shares[2].as_bytes()[1200 - 1024..]
+            .iter()
+            .all(|byte| *byte == 0));
+
+        let cells = pack_fixed_shares::<Fr>(&shares);
+        assert_eq!(cells.len(), 3 * 17);
+        assert_eq!(unpack_fixed_shares(&cells).unwrap(), shares);
+        assert!(unpack_fixed_shares(&cells[1..]).is_none());
+        // a last cell holding more than the share's remaining 16 bytes is not one
+        // `to_cells` writes
+        let mut overfull = cells[..17].to_vec();
+        overfull[16] = Fr::from(u128::MAX) * Fr::from(2u64);
+        assert!(FixedShare::from_cells(&overfull).is_none());
+        assert!(FixedShare::from_slice(&data[..FIXED_SHARE_SIZE - 1]).is_none());
+
+        let square = fixed_shares_square::<Fr>(&shares);
+        assert_eq!(square.len(), 8);
+        assert_eq!(square[2][1], cells[17]);
+    }
+
+    #[test]
+    pub fn fixed_shares_straddle_rows() {
+        // share 1 takes cells 17..34 of an 8 x 8 square: the last 7 of row 2, all
+        // of row 3 and the first 2 of row 4
+        let cells = fixed_share_cells::<Fr>(1, 8, 2).unwrap();
+        assert_eq!(cells.len(), 17);
+        assert_eq!(cells[0], (4, 2));
+        assert_eq!(cells[7], (6, 0));
+        assert_eq!(cells[16], (8, 2));
+        // only three whole shares fit in 64 cells
+        assert!(fixed_share_cells::<Fr>(2, 8, 2).is_some());
+        assert!(fixed_share_cells::<Fr>(3, 8, 2).is_none());
+    }
+}
@@ -0,0 +1,11 @@
+//! Squares shared by the tests of this crate and, through the `test-fixtures`
+//! feature, by those of the crates built on it
+
+use ark_test_curves::bls12_381::Fr;
+
+/// Original square of side `n` holding 0, 1, 2, ... row by row
+pub fn counting_square(n: usize) -> Vec<Vec<Fr>> {
+    (0..n)
+        .map(|row| (0..n).map(|col| Fr::from((n * row + col) as u64)).collect())
+        .collect()
+}
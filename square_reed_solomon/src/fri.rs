@@ -0,0 +1,370 @@
+//! FRI proofs that a line committed by a Merkle tree over its values is a
+//! Reed-Solomon codeword, needing nothing but a hash. Each round folds the line in
+//! half with a challenge drawn from the commitments so far, halving its degree,
+//! until a codeword of degree one is a constant. The verifier checks the folds at
+//! positions drawn the same way. Challenges, and so every folded layer, lie in the
+//! extension `FriField::Challenge` of the line's field.
+
+use crate::codec::{field_from_bytes, field_to_bytes, put_bytes, put_u64, take_bytes, take_u64};
+use crate::merkle::{MerkleProof, MerkleRoot};
+
+use ark_ff::{Field, One, PrimeField};
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_serialize::SerializationError;
+use rs_merkle::{Hasher, MerkleTree};
+
+/// Bits of security FRI proofs are sized for
+pub const FRI_SECURITY_BITS: usize = 100;
+
+/// Field of the lines FRI proves. A fold of a line far from every codeword lands
+/// close to one for at most about as many challenges as the line has values, so
+/// challenges are drawn from `Challenge`, an extension large enough for that to
+/// happen with probability below `2^-FRI_SECURITY_BITS`.
+pub trait FriField: PrimeField {
+    type Challenge: Field<BasePrimeField = Self>;
+}
+
+/// Positions checked per proof that a line of `length` values has degree below
+/// `degree`. Each catches a line far from every codeword with probability at least
+/// one minus the rate `degree / length`, so is worth `log2(length / degree)` bits,
+/// counted as one for lines that are not extended.
+pub fn fri_queries(length: usize, degree: usize) -> usize {
+    let bits = (length / degree).trailing_zeros().max(1) as usize;
+    FRI_SECURITY_BITS.div_ceil(bits)
+}
+
+/// Both values of a layer one fold combines, with their paths in the layer's tree
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FriOpening<K: Field> {
+    pub values: [K; 2],
+    pub paths: [MerkleProof; 2],
+}
+
+/// Openings of one query position, in the line and in every folded layer but the
+/// last
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FriQuery<F: FriField> {
+    pub line: FriOpening<F>,
+    pub layers: Vec<FriOpening<F::Challenge>>,
+}
+
+/// Proof that a committed line of evaluations has degree below a bound
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FriProof<F: FriField> {
+    /// Roots of the folded layers, except the last which is a constant
+    pub layer_roots: Vec<MerkleRoot>,
+    /// The constant the last fold reaches
+    pub last: F::Challenge,
+    /// Openings of every query
+    pub queries: Vec<FriQuery<F>>,
+}
+
+/// Leaf of a value in a line's or a layer's tree
+pub fn value_leaf<K: Field, H: Hasher>(value: &K) -> H::Hash {
+    H::hash(&field_to_bytes(value))
+}
+
+/// Tree over the values of a line, whose root commits to the line
+pub fn commit_values<K: Field, H: Hasher>(values: &[K]) -> MerkleTree<H> {
+    let leaves: Vec<H::Hash> = values.iter().map(value_leaf::<K, H>).collect();
+    MerkleTree::<H>::from_leaves(&leaves)
+}
+
+/// Prove that `values`, evaluations over the domain of their length, have degree
+/// below `degree`. Both must be powers of two with `degree` at most the length.
+pub fn prove_low_degree<F: FriField, H: Hasher>(values: &[F], degree: usize) -> FriProof<F> {
+    let line_tree = commit_values::<F, H>(values);
+    let mut state = tree_root::<H>(&line_tree).0;
+    let line: Vec<F::Challenge> = values
+        .iter()
+        .map(|value| F::Challenge::from_base_prime_field(*value))
+        .collect();
+    let mut layers = vec![line];
+    let mut trees = vec![];
+    let mut layer_roots = vec![];
+    let mut bound = degree;
+    while bound > 1 {
+        let beta = challenge::<F::Challenge, H>(&state);
+        let layer = layers.last().expect("Layers start with the line");
+        let next = fold::<F>(layer, beta);
+        bound /= 2;
+        if bound > 1 {
+            let tree = commit_values::<F::Challenge, H>(&next);
+            let root = tree_root::<H>(&tree);
+            state = absorb::<H>(&state, &root.0);
+            layer_roots.push(root);
+            trees.push(tree);
+        }
+        layers.push(next);
+    }
+    let last = layers.last().expect("Layers start with the line")[0];
+    state = absorb::<H>(&state, &field_to_bytes(&last));
+    // the line is opened from its own values and the last layer sent as its constant
+    let folded = &layers[1..(layers.len() - 1).max(1)];
+
+    let queries = query_positions::<H>(&state, values.len(), fri_queries(values.len(), degree))
+        .into_iter()
+        .map(|position| FriQuery {
+            line: open(values, &line_tree, position),
+            layers: folded
+                .iter()
+                .zip(&trees)
+                .map(|(layer, tree)| open(layer, tree, position))
+                .collect(),
+        })
+        .collect();
+    FriProof {
+        layer_roots,
+        last,
+        queries,
+    }
+}
+
+/// Check that `proof` shows the line of `length` values committed by `root` has
+/// degree below `degree`
+pub fn verify_low_degree<F: FriField, H: Hasher>(
+    root: &MerkleRoot,
+    length: usize,
+    degree: usize,
+    proof: &FriProof<F>,
+) -> bool {
+    if !length.is_power_of_two() || !degree.is_power_of_two() || degree > length {
+        return false;
+    }
+    let rounds = degree.trailing_zeros() as usize;
+    if proof.layer_roots.len() != rounds.saturating_sub(1)
+        || proof.queries.len() != fri_queries(length, degree)
+    {
+        return false;
+    }
+    let domain = match Radix2EvaluationDomain::<F>::new(length) {
+        Some(domain) => domain,
+        None => return false,
+    };
+    let mut state = root.0.clone();
+    let mut betas = vec![];
+    for round in 0..rounds {
+        betas.push(challenge::<F::Challenge, H>(&state));
+        if let Some(root) = proof.layer_roots.get(round) {
+            state = absorb::<H>(&state, &root.0);
+        }
+    }
+    state = absorb::<H>(&state, &field_to_bytes(&proof.last));
+
+    query_positions::<H>(&state, length, proof.queries.len())
+        .into_iter()
+        .zip(&proof.queries)
+        .all(|(position, query)| {
+            if query.layers.len() != rounds.saturating_sub(1)
+                || !opens::<F, H>(&query.line, root, position, length)
+            {
+                return false;
+            }
+            let [a, b] = query.line.values.map(F::Challenge::from_base_prime_field);
+            let beta = match betas.first() {
+                Some(beta) => *beta,
+                // a line of degree below one is left unfolded and must be constant
+                None => return a == proof.last && b == proof.last,
+            };
+            let mut generator_inv = domain.group_gen_inv;
+            let mut layer_len = length;
+            let low = pair(position, layer_len).0 as u64;
+            // the value the previous fold produced, checked against the next layer
+            let mut expected = fold_pair::<F>(a, b, beta, generator_inv.pow([low]));
+            for ((opening, root), beta) in
+                query.layers.iter().zip(&proof.layer_roots).zip(&betas[1..])
+            {
+                generator_inv.square_in_place();
+                layer_len /= 2;
+                let at_position = opening.values[(position % layer_len >= layer_len / 2) as usize];
+                if !opens::<F::Challenge, H>(opening, root, position, layer_len)
+                    || at_position != expected
+                {
+                    return false;
+                }
+                let [a, b] = opening.values;
+                let low = pair(position, layer_len).0 as u64;
+                expected = fold_pair::<F>(a, b, *beta, generator_inv.pow([low]));
+            }
+            expected == proof.last
+        })
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(FriProof<F: FriField>);
+
+impl<F: FriField> FriProof<F> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.layer_roots.len() as u64);
+        for root in &self.layer_roots {
+            put_bytes(&mut bytes, &root.0);
+        }
+        put_bytes(&mut bytes, &field_to_bytes(&self.last));
+        put_u64(&mut bytes, self.queries.len() as u64);
+        for query in &self.queries {
+            put_opening(&mut bytes, &query.line);
+            put_u64(&mut bytes, query.layers.len() as u64);
+            for opening in &query.layers {
+                put_opening(&mut bytes, opening);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        // every entry takes at least its 4-byte length prefix
+        let count = |bytes: &mut &[u8]| -> Result<usize, SerializationError> {
+            let count = take_u64(bytes)? as usize;
+            if count > bytes.len() / 4 {
+                return Err(SerializationError::InvalidData);
+            }
+            Ok(count)
+        };
+        let layer_roots = (0..count(&mut bytes)?)
+            .map(|_| take_bytes(&mut bytes).map(|root| MerkleRoot(root.to_vec())))
+            .collect::<Result<Vec<_>, _>>()?;
+        let last = field_from_bytes(take_bytes(&mut bytes)?)?;
+        let mut queries = vec![];
+        for _ in 0..count(&mut bytes)? {
+            let line = take_opening(&mut bytes)?;
+            let layers = (0..count(&mut bytes)?)
+                .map(|_| take_opening(&mut bytes))
+                .collect::<Result<Vec<_>, _>>()?;
+            queries.push(FriQuery { line, layers });
+        }
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self {
+            layer_roots,
+            last,
+            queries,
+        })
+    }
+}
+
+fn put_opening<K: Field>(bytes: &mut Vec<u8>, opening: &FriOpening<K>) {
+    for side in 0..2 {
+        put_bytes(bytes, &field_to_bytes(&opening.values[side]));
+        put_bytes(bytes, &opening.paths[side].to_bytes());
+    }
+}
+
+fn take_opening<K: Field>(bytes: &mut &[u8]) -> Result<FriOpening<K>, SerializationError> {
+    let mut side = || -> Result<(K, MerkleProof), SerializationError> {
+        let value = field_from_bytes(take_bytes(bytes)?)?;
+        let path = MerkleProof::from_bytes(take_bytes(bytes)?)?;
+        Ok((value, path))
+    };
+    let (low, low_path) = side()?;
+    let (high, high_path) = side()?;
+    Ok(FriOpening {
+        values: [low, high],
+        paths: [low_path, high_path],
+    })
+}
+
+/// Fold `layer`, evaluations over the domain of its length, into evaluations over
+/// the domain of half its length of `even + beta * odd`, where the layer's
+/// polynomial is `even(x^2) + x * odd(x^2)`
+fn fold<F: FriField>(layer: &[F::Challenge], beta: F::Challenge) -> Vec<F::Challenge> {
+    let half = layer.len() / 2;
+    let domain =
+        Radix2EvaluationDomain::<F>::new(layer.len()).expect("Layers have power of two lengths");
+    let mut x_inv = F::one();
+    (0..half)
+        .map(|idx| {
+            let folded = fold_pair::<F>(layer[idx], layer[idx + half], beta, x_inv);
+            x_inv *= domain.group_gen_inv;
+            folded
+        })
+        .collect()
+}
+
+/// `even + beta * odd` at `x^2` from the values `a` at `x` and `b` at `-x`
+fn fold_pair<F: FriField>(
+    a: F::Challenge,
+    b: F::Challenge,
+    beta: F::Challenge,
+    x_inv: F,
+) -> F::Challenge {
+    let two_inv = F::from(2u64).inverse().expect("2 is invertible");
+    let half_sum = (a + b) * F::Challenge::from_base_prime_field(two_inv);
+    let half_diff = (a - b) * F::Challenge::from_base_prime_field(two_inv * x_inv);
+    half_sum + beta * half_diff
+}
+
+/// Both values of `layer` one fold combines for `position` of the line, opened in
+/// its `tree`
+fn open<K: Field, H: Hasher>(layer: &[K], tree: &MerkleTree<H>, position: usize) -> FriOpening<K> {
+    let (low, high) = pair(position, layer.len());
+    FriOpening {
+        values: [layer[low], layer[high]],
+        paths: [prove_leaf(tree, low), prove_leaf(tree, high)],
+    }
+}
+
+/// Whether `opening` holds the values of a layer of `len` values under `root` that
+/// one fold combines for `position` of the line
+fn opens<K: Field, H: Hasher>(
+    opening: &FriOpening<K>,
+    root: &MerkleRoot,
+    position: usize,
+    len: usize,
+) -> bool {
+    let (low, high) = pair(position, len);
+    [low, high]
+        .into_iter()
+        .zip(&opening.values)
+        .zip(&opening.paths)
+        .all(|((idx, value), path)| {
+            let leaf: Vec<u8> = value_leaf::<K, H>(value).into();
+            path.index == idx && path.leaf_count == len && path.verify::<H>(root, &leaf)
+        })
+}
+
+/// Positions in a layer of `len` values that one fold combines for `position` of
+/// the line
+fn pair(position: usize, len: usize) -> (usize, usize) {
+    let low = position % (len / 2);
+    (low, low + len / 2)
+}
+
+fn tree_root<H: Hasher>(tree: &MerkleTree<H>) -> MerkleRoot {
+    MerkleRoot::from_hash::<H>(tree.root().expect("Lines are not empty"))
+}
+
+fn prove_leaf<H: Hasher>(tree: &MerkleTree<H>, idx: usize) -> MerkleProof {
+    MerkleProof::from_rs_merkle(&tree.proof(&[idx]), idx, tree.leaves_len())
+}
+
+fn absorb<H: Hasher>(state: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut input = state.to_vec();
+    put_bytes(&mut input, data);
+    H::hash(&input).into()
+}
+
+/// Challenge in `K` from the transcript `state`, one hash per coefficient over the
+/// prime field
+fn challenge<K: Field, H: Hasher>(state: &[u8]) -> K {
+    let coeffs: Vec<K::BasePrimeField> = (0..K::extension_degree())
+        .map(|coeff| {
+            let mut label = b"fold".to_vec();
+            label.extend_from_slice(&coeff.to_le_bytes());
+            K::BasePrimeField::from_le_bytes_mod_order(&absorb::<H>(state, &label))
+        })
+        .collect();
+    K::from_base_prime_field_elems(&coeffs).expect("One coefficient per degree of the extension")
+}
+
+fn query_positions<H: Hasher>(state: &[u8], length: usize, count: usize) -> Vec<usize> {
+    (0..count as u64)
+        .map(|query| {
+            let hash = absorb::<H>(state, &query.to_le_bytes());
+            let mut word = [0; 8];
+            word.copy_from_slice(&hash[..8]);
+            (u64::from_le_bytes(word) % length as u64) as usize
+        })
+        .collect()
+}
@@ -0,0 +1,403 @@
+//! Squares extended in Goldilocks and committed with KZG over a pairing field.
+//! Extension is done with word-sized arithmetic; only the commitments pay for the
+//! pairing field. Two Goldilocks values are packed into each scalar, the low one in
+//! the low 64 bits, so a line of `length` values is committed as the polynomial
+//! through `length / 2` scalars. A cell is opened at the scalar holding it, and the
+//! verifier unpacks the half the cell falls in.
+//!
+//! KZG binds the packed values of a row but not their extension, since the packed
+//! scalars are not a codeword over the pairing field, so it only buys cell
+//! openings. Extension is checked with hashes alone: each column is committed by
+//! the root of the tree over its values, as on the hash-only path of `small_field`,
+//! and no column is committed with KZG. A column fetched whole is checked under the
+//! column root and with `is_codeword` by `verify_hybrid_col`, as for a
+//! bad-encoding fraud proof.
+
+use crate::fri::commit_values;
+use crate::merkle::TaggedTree;
+use crate::prover::{commit_poly, open_poly};
+use crate::rs_line::RsLine;
+use crate::rs_square::RsSquare;
+use crate::sample::LineAxis;
+use crate::small_field::Goldilocks;
+use crate::verifier::{
+    combine_line_roots, line_leaves, verify_cell_proof, verify_line_commitment, verify_line_path,
+    verify_root, RootVersion,
+};
+
+use ark_ec::pairing::Pairing;
+use ark_ff::{BigInteger, PrimeField};
+use ark_poly::univariate::DensePolynomial;
+use ark_poly::{EvaluationDomain, Evaluations, Polynomial, Radix2EvaluationDomain};
+use ark_poly_commit::kzg10::{Commitment, Proof, UniversalParams, VerifierKey};
+use rs_merkle::{Hasher, MerkleProof};
+
+use std::sync::Arc;
+
+/// Goldilocks values packed into each pairing field scalar
+pub const PACKED_VALUES: usize = 2;
+
+/// Pack `values` two by two into scalars, padding an odd tail with zero
+pub fn pack<F: PrimeField>(values: &[Goldilocks]) -> Vec<F> {
+    assert!(
+        F::MODULUS_BIT_SIZE as usize > 64 * PACKED_VALUES,
+        "Scalars must hold two Goldilocks values"
+    );
+    let shift = F::from(1u128 << 64);
+    values
+        .chunks(PACKED_VALUES)
+        .map(|pair| {
+            let low = F::from(pair[0].into_bigint().0[0]);
+            let high = pair.get(1).map_or(0, |high| high.into_bigint().0[0]);
+            low + F::from(high) * shift
+        })
+        .collect()
+}
+
+/// The two values packed into `scalar`, `None` unless it is the packing of two
+/// canonical Goldilocks values
+pub fn unpack<F: PrimeField>(scalar: &F) -> Option<[Goldilocks; 2]> {
+    let bytes = scalar.into_bigint().to_bytes_le();
+    if bytes[16..].iter().any(|byte| *byte != 0) {
+        return None;
+    }
+    let half = |range: std::ops::Range<usize>| {
+        let word = u64::from_le_bytes(bytes[range].try_into().expect("8 bytes"));
+        (word < Goldilocks::MODULUS.0[0]).then(|| Goldilocks::from(word))
+    };
+    Some([half(0..8)?, half(8..16)?])
+}
+
+/// Unpack a line of `length` values from its packed scalars
+pub fn unpack_line<F: PrimeField>(packed: &[F], length: usize) -> Option<Vec<Goldilocks>> {
+    if packed.len() != packed_len(length) {
+        return None;
+    }
+    let mut values = vec![];
+    for scalar in packed {
+        values.extend(unpack(scalar)?);
+    }
+    values.truncate(length);
+    Some(values)
+}
+
+/// Whether `values`, evaluations over the domain of their length, have degree
+/// below `n`, i.e. are a line correctly extended from `n` shares
+pub fn is_codeword(values: &[Goldilocks], n: usize) -> bool {
+    match Radix2EvaluationDomain::<Goldilocks>::new(values.len()) {
+        Some(domain) if domain.size() == values.len() => {
+            let poly = Evaluations::from_vec_and_domain(values.to_vec(), domain).interpolate();
+            poly.degree() < n
+        }
+        _ => false,
+    }
+}
+
+/// Number of scalars a line of `length` values packs into
+pub fn packed_len(length: usize) -> usize {
+    length.div_ceil(PACKED_VALUES)
+}
+
+/// Square extended over Goldilocks whose lines are packed and committed over the
+/// scalar field of `E`
+pub struct HybridSquareProver<E: Pairing, H: Hasher> {
+    square: RsSquare<Goldilocks>,
+    params: Arc<UniversalParams<E>>,
+    /// Polynomials through the packed scalars of every row
+    row_polys: Vec<DensePolynomial<E::ScalarField>>,
+    row_commitments: Vec<Commitment<E>>,
+    row_tree: TaggedTree<H>,
+    /// Tree over the roots of the trees over every column's values
+    col_tree: TaggedTree<H>,
+    /// Rules the line trees and the data root are hashed under
+    root_version: RootVersion,
+}
+
+/// A cell with the opening of the packed scalar holding it
+pub struct HybridCellSample<E: Pairing, H: Hasher> {
+    pub rid: usize,
+    pub cid: usize,
+    /// Claimed value of the square at (rid, cid)
+    pub value: Goldilocks,
+    /// Scalar packing the cell with its neighbour
+    pub packed: E::ScalarField,
+    /// KZG commitment to the polynomial through the packed scalars of row rid
+    pub row_commitment: Commitment<E>,
+    /// KZG opening of the row polynomial at the point of the packed scalar
+    pub opening: Proof<E>,
+    /// Merkle path of the row commitment under the row root
+    pub row_path: MerkleProof<H>,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+}
+
+/// A column served whole, with the path of the root of the tree over its values
+/// under the column root
+pub struct HybridColumn<H: Hasher> {
+    pub cid: usize,
+    pub values: Vec<Goldilocks>,
+    pub col_path: MerkleProof<H>,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+}
+
+impl<E: Pairing, H: Hasher> HybridSquareProver<E, H> {
+    /// Extend the square of `shares` by `scale`, commit to every packed row and
+    /// hash every column.
+    /// `params` must support polynomials of degree below half the extended side.
+    pub fn new(
+        shares: &[Vec<Goldilocks>],
+        scale: usize,
+        params: impl Into<Arc<UniversalParams<E>>>,
+    ) -> Self {
+        Self::with_root_version(shares, scale, params, RootVersion::LATEST)
+    }
+
+    /// `new`, hashing the line trees and the data root under `root_version`
+    pub fn with_root_version(
+        shares: &[Vec<Goldilocks>],
+        scale: usize,
+        params: impl Into<Arc<UniversalParams<E>>>,
+        root_version: RootVersion,
+    ) -> Self {
+        let params = params.into();
+        let lines: Vec<RsLine<Goldilocks>> = shares
+            .iter()
+            .map(|share| RsLine::new(share, scale))
+            .collect();
+        let mut square = RsSquare::new(&lines, scale);
+        square.extend();
+        let length = shares.len() * scale;
+        let domain = Radix2EvaluationDomain::<E::ScalarField>::new(packed_len(length))
+            .expect("Scalar field has roots of unity of the packed line length");
+        let packed_poly = |values: Vec<Goldilocks>| {
+            Evaluations::from_vec_and_domain(pack::<E::ScalarField>(&values), domain).interpolate()
+        };
+
+        let row_polys: Vec<DensePolynomial<E::ScalarField>> = (0..length)
+            .map(|rid| packed_poly(square.row_vals(rid)))
+            .collect();
+        let row_commitments: Vec<Commitment<E>> = row_polys
+            .iter()
+            .map(|poly| commit_poly(&params, poly))
+            .collect();
+        let row_leaves = line_leaves::<E, H>(root_version, LineAxis::Row, &row_commitments);
+        let col_roots: Vec<H::Hash> = (0..length)
+            .map(|cid| column_root::<H>(&square.col_vals(cid)))
+            .collect();
+        Self {
+            row_tree: TaggedTree::new(root_version.line_tag(LineAxis::Row), &row_leaves),
+            col_tree: TaggedTree::new(root_version.line_tag(LineAxis::Col), &col_roots),
+            square,
+            params,
+            row_polys,
+            row_commitments,
+            root_version,
+        }
+    }
+
+    pub fn square(&self) -> &RsSquare<Goldilocks> {
+        &self.square
+    }
+
+    pub fn root_version(&self) -> RootVersion {
+        self.root_version
+    }
+
+    pub fn row_root(&self) -> H::Hash {
+        self.row_tree.root().expect("Squares are not empty")
+    }
+
+    pub fn col_root(&self) -> H::Hash {
+        self.col_tree.root().expect("Squares are not empty")
+    }
+
+    /// Data root over the row and column roots, as for squares extended over the
+    /// pairing field
+    pub fn root(&self) -> H::Hash {
+        combine_line_roots::<H>(self.root_version, self.row_root(), self.col_root())
+    }
+
+    /// Packed scalars of row rid, e.g. to serve the whole row for `is_codeword`
+    pub fn packed_row(&self, rid: usize) -> Vec<E::ScalarField> {
+        pack(&self.square.row_vals(rid))
+    }
+
+    pub fn sample_cell(&self, rid: usize, cid: usize) -> HybridCellSample<E, H> {
+        let packed = self.packed_row(rid);
+        let domain = Radix2EvaluationDomain::<E::ScalarField>::new(packed.len())
+            .expect("Domain was built for the packed line length");
+        let idx = cid / PACKED_VALUES;
+        HybridCellSample {
+            rid,
+            cid,
+            value: self.square.val_at(rid, cid),
+            packed: packed[idx],
+            row_commitment: self.row_commitments[rid],
+            opening: open_poly(&self.params, &self.row_polys[rid], domain.element(idx)),
+            row_path: self.row_tree.proof(&[rid]),
+            row_root: self.row_root(),
+            col_root: self.col_root(),
+        }
+    }
+
+    /// Column cid whole, e.g. to check its extension with `verify_hybrid_col`
+    pub fn sample_col(&self, cid: usize) -> HybridColumn<H> {
+        HybridColumn {
+            cid,
+            values: self.square.col_vals(cid),
+            col_path: self.col_tree.proof(&[cid]),
+            row_root: self.row_root(),
+            col_root: self.col_root(),
+        }
+    }
+}
+
+/// Full check of a sampled cell against a data root: the root covers the row root,
+/// the row commitment sits under it, the packed scalar opens the row and the cell
+/// is the half of it the column points at. `version` is the root version of the
+/// square.
+pub fn verify_hybrid_cell<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    sample: &HybridCellSample<E, H>,
+) -> bool {
+    sample.cid < length
+        && verify_root::<H>(version, root, sample.row_root, sample.col_root)
+        && verify_line_commitment::<E, H>(
+            version,
+            sample.row_root,
+            LineAxis::Row,
+            length,
+            sample.rid,
+            &sample.row_commitment,
+            &sample.row_path,
+        )
+        && verify_cell_proof(
+            vk,
+            &sample.row_commitment,
+            packed_len(length),
+            sample.cid / PACKED_VALUES,
+            sample.packed,
+            &sample.opening,
+        )
+        && unpack(&sample.packed)
+            .is_some_and(|pair| pair[sample.cid % PACKED_VALUES] == sample.value)
+}
+
+/// Check a column served whole against a data root: the root covers the column
+/// root, the tree over the column's values sits under it and the values are a
+/// codeword extended from `n` shares. `version` is the root version of the square.
+pub fn verify_hybrid_col<H: Hasher>(
+    version: RootVersion,
+    root: H::Hash,
+    n: usize,
+    column: &HybridColumn<H>,
+) -> bool {
+    let length = column.values.len();
+    column.cid < length
+        && verify_root::<H>(version, root, column.row_root, column.col_root)
+        && verify_line_path::<H>(
+            version,
+            LineAxis::Col,
+            &column.col_path,
+            column.col_root,
+            &[column.cid],
+            &[column_root::<H>(&column.values)],
+            length,
+        )
+        && is_codeword(&column.values, n)
+}
+
+/// Root of the tree over the values of a column, its leaf in the column tree
+fn column_root<H: Hasher>(values: &[Goldilocks]) -> H::Hash {
+    commit_values::<Goldilocks, H>(values)
+        .root()
+        .expect("Lines are not empty")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_codeword, pack, unpack, unpack_line, verify_hybrid_cell, verify_hybrid_col,
+        HybridSquareProver,
+    };
+    use crate::small_field::{Goldilocks, HashSquareProver};
+    use crate::verifier::verifier_key;
+
+    use ark_ff::PrimeField;
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn packing_round_trips_only_canonical_values() {
+        let values = [Goldilocks::from(7u64), -Goldilocks::from(1u64)];
+        let packed = pack::<Fr>(&values);
+        assert_eq!(unpack(&packed[0]), Some(values));
+        assert_eq!(
+            unpack_line(&pack::<Fr>(&values[..1]), 1),
+            Some(values[..1].to_vec())
+        );
+        // a half at or above the Goldilocks modulus, or bits above both halves
+        let modulus = Goldilocks::MODULUS.0[0];
+        assert_eq!(unpack(&Fr::from(modulus)), None);
+        assert_eq!(unpack(&(Fr::from(1u128 << 127) * Fr::from(2u64))), None);
+    }
+
+    #[test]
+    pub fn hybrid_squares_open_packed_cells_end_to_end() {
+        let shares: Vec<Vec<Goldilocks>> = (0..4u64)
+            .map(|rid| {
+                (0..4u64)
+                    .map(|cid| Goldilocks::from(rid * 4 + cid))
+                    .collect()
+            })
+            .collect();
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng).unwrap();
+        let vk = verifier_key(&params);
+        let prover = HybridSquareProver::<Bls12_381, Sha256>::new(&shares, 2, params);
+        let (version, root) = (prover.root_version(), prover.root());
+
+        for (rid, cid) in [(0, 0), (3, 5), (7, 7)] {
+            assert!(verify_hybrid_cell(
+                &vk,
+                version,
+                root,
+                8,
+                &prover.sample_cell(rid, cid)
+            ));
+        }
+        let mut forged = prover.sample_cell(3, 5);
+        forged.value += Goldilocks::from(1u64);
+        assert!(!verify_hybrid_cell(&vk, version, root, 8, &forged));
+        // the neighbour packed into the same scalar is not the cell
+        let mut moved = prover.sample_cell(3, 5);
+        moved.cid = 4;
+        assert!(!verify_hybrid_cell(&vk, version, root, 8, &moved));
+
+        // columns are checked whole under the column root, values and extension
+        let column = prover.sample_col(5);
+        assert!(verify_hybrid_col(version, root, 4, &column));
+        let mut altered = prover.sample_col(5);
+        altered.values[1] += Goldilocks::from(1u64);
+        assert!(!verify_hybrid_col(version, root, 4, &altered));
+        let mut shifted = prover.sample_col(5);
+        shifted.cid = 4;
+        assert!(!verify_hybrid_col(version, root, 4, &shifted));
+
+        // extension matches the hash-committed path over the same field, and full
+        // rows unpack to codewords
+        let hashed = HashSquareProver::<Goldilocks, Sha256>::new(&shares, 2);
+        let row = unpack_line(&prover.packed_row(6), 8).unwrap();
+        assert_eq!(row, hashed.square().row_vals(6));
+        assert!(is_codeword(&row, 4));
+        let mut broken = row;
+        broken[2] += Goldilocks::from(1u64);
+        assert!(!is_codeword(&broken, 4));
+    }
+}
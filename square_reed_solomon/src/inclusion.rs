@@ -0,0 +1,187 @@
+use crate::codec::{
+    commitment_from_bytes, commitment_to_bytes, field_from_bytes, field_to_bytes, put_bytes,
+    put_u64, take_bytes, take_hash, take_u64,
+};
+use crate::dimensions::bytes_per_share;
+use crate::layout::{shares_to_blob, BlobPlacement, LayoutVersion};
+use crate::sample::{ChallengeContext, LineAxis, RangeProof};
+use crate::verifier::{verify_line_commitments, verify_range_proof, verify_root, RootVersion};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Commitment, VerifierKey};
+use ark_serialize::SerializationError;
+use rs_merkle::{Hasher, MerkleProof};
+
+/// Compact proof that a run of shares is committed under a data root: the
+/// commitments of the rows holding them under one Merkle multi-proof, and a range
+/// proof of each row's part of the run. It grows with the rows touched, not with
+/// the shares.
+pub struct ShareProof<E: Pairing, H: Hasher> {
+    /// Height of the block the proof was made for, bound into every range
+    /// challenge with the data root and the row
+    pub height: u64,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+    /// Commitments of the extended rows holding the shares, in increasing row order
+    pub row_commitments: Vec<Commitment<E>>,
+    /// Merkle multi-proof of the row commitments under the row root
+    pub rows_path: MerkleProof<H>,
+    /// Share values, in share order
+    pub values: Vec<E::ScalarField>,
+    /// Range proof of the shares in each row, in increasing row order
+    pub ranges: Vec<RangeProof<E>>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(ShareProof<E: Pairing, H: Hasher>);
+
+impl<E: Pairing, H: Hasher> ShareProof<E, H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let row_root: Vec<u8> = self.row_root.into();
+        let col_root: Vec<u8> = self.col_root.into();
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.height);
+        put_bytes(&mut bytes, &row_root);
+        put_bytes(&mut bytes, &col_root);
+        put_bytes(&mut bytes, &self.rows_path.to_bytes());
+        put_u64(&mut bytes, self.row_commitments.len() as u64);
+        for (commitment, range) in self.row_commitments.iter().zip(&self.ranges) {
+            put_bytes(&mut bytes, &commitment_to_bytes(commitment));
+            put_bytes(&mut bytes, &range.to_bytes());
+        }
+        put_u64(&mut bytes, self.values.len() as u64);
+        for value in &self.values {
+            put_bytes(&mut bytes, &field_to_bytes(value));
+        }
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let height = take_u64(&mut bytes)?;
+        let row_root = take_hash::<H>(&mut bytes)?;
+        let col_root = take_hash::<H>(&mut bytes)?;
+        let rows_path = MerkleProof::<H>::from_bytes(take_bytes(&mut bytes)?)
+            .map_err(|_| SerializationError::InvalidData)?;
+        let rows = take_u64(&mut bytes)? as usize;
+        if rows > bytes.len() / 8 {
+            return Err(SerializationError::InvalidData);
+        }
+        let mut row_commitments = Vec::with_capacity(rows);
+        let mut ranges = Vec::with_capacity(rows);
+        for _ in 0..rows {
+            row_commitments.push(commitment_from_bytes(take_bytes(&mut bytes)?)?);
+            ranges.push(RangeProof::<E>::from_bytes(take_bytes(&mut bytes)?)?);
+        }
+        let shares = take_u64(&mut bytes)? as usize;
+        if shares > bytes.len() / 4 {
+            return Err(SerializationError::InvalidData);
+        }
+        let values = (0..shares)
+            .map(|_| field_from_bytes(take_bytes(&mut bytes)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self {
+            height,
+            row_root,
+            col_root,
+            row_commitments,
+            rows_path,
+            values,
+            ranges,
+        })
+    }
+}
+
+/// Check that `proof` shows the shares at `cells` of an extended square of side
+/// `length` are committed under `data_root`, that of the trusted header at
+/// `height` rooted under `root_version`. The cells must be grouped by row in
+/// increasing row order, as shares laid out row-major are. Returns the shares'
+/// values, or `None` if anything fails to verify.
+pub fn verify_shares<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    root_version: RootVersion,
+    data_root: H::Hash,
+    length: usize,
+    cells: &[(usize, usize)],
+    proof: &ShareProof<E, H>,
+) -> Option<Vec<E::ScalarField>> {
+    let spans: Vec<&[(usize, usize)]> = cells.chunk_by(|a, b| a.0 == b.0).collect();
+    if proof.height != height
+        || proof.values.len() != cells.len()
+        || proof.row_commitments.len() != spans.len()
+        || proof.ranges.len() != spans.len()
+        || spans.windows(2).any(|pair| pair[0][0].0 >= pair[1][0].0)
+        || !verify_root::<H>(root_version, data_root, proof.row_root, proof.col_root)
+    {
+        return None;
+    }
+    if spans.is_empty() {
+        return Some(vec![]);
+    }
+    let rows: Vec<usize> = spans.iter().map(|span| span[0].0).collect();
+    if !verify_line_commitments::<E, H>(
+        root_version,
+        proof.row_root,
+        LineAxis::Row,
+        length,
+        &rows,
+        &proof.row_commitments,
+        &proof.rows_path,
+    ) {
+        return None;
+    }
+
+    let data_root: Vec<u8> = data_root.into();
+    let mut values = proof.values.as_slice();
+    for ((span, commitment), range) in spans.iter().zip(&proof.row_commitments).zip(&proof.ranges) {
+        let (span_values, rest) = values.split_at(span.len());
+        let cids: Vec<usize> = span.iter().map(|(_, cid)| *cid).collect();
+        let context = ChallengeContext {
+            height,
+            data_root: &data_root,
+            axis: LineAxis::Row,
+            idx: span[0].0,
+        };
+        if !verify_range_proof::<E, H>(vk, &context, commitment, length, &cids, span_values, range)
+        {
+            return None;
+        }
+        values = rest;
+    }
+    Some(proof.values.clone())
+}
+
+/// Check that `proof` shows the blob at `placement` is part of the block at
+/// `height` with `data_root` under `root_version`, laid out under `version` in an
+/// original square of side `n` extended by `scale`. Returns the blob's bytes, or
+/// `None` if anything fails to verify.
+pub fn verify_blob_inclusion<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    root_version: RootVersion,
+    data_root: H::Hash,
+    version: LayoutVersion,
+    n: usize,
+    scale: usize,
+    placement: &BlobPlacement,
+    proof: &ShareProof<E, H>,
+) -> Option<Vec<u8>> {
+    let shares = placement.len.div_ceil(bytes_per_share::<E::ScalarField>());
+    if placement.end != placement.start + shares || placement.end > n * n {
+        return None;
+    }
+    let cells = version.blob_cells(placement, n, scale);
+    let values = verify_shares(
+        vk,
+        height,
+        root_version,
+        data_root,
+        n * scale,
+        &cells,
+        proof,
+    )?;
+    Some(shares_to_blob(&values, placement.len))
+}
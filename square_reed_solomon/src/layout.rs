@@ -0,0 +1,311 @@
+use crate::dimensions::{bytes_per_share, choose_dimensions, SquareDimensions};
+use crate::namespace::{pad_to_square, NamespaceId, NamespaceIndex, NamespaceRange};
+use crate::sample::RowProof;
+use crate::verifier::{verify_root, verify_row_proof, RootVersion};
+
+use ark_ec::pairing::Pairing;
+use ark_ff::{BigInteger, PrimeField};
+use ark_poly_commit::kzg10::VerifierKey;
+use rs_merkle::Hasher;
+
+/// Rules mapping blobs to shares and cells. Implementations agreeing on a
+/// version lay out the same blobs identically and derive the same cells for
+/// every blob.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LayoutVersion {
+    /// 1. A blob of `len` bytes becomes `len.div_ceil(bytes_per_share)` shares,
+    ///    each holding the next `bytes_per_share` bytes in little-endian order,
+    ///    the last one zero-padded.
+    /// 2. Blobs are sorted stably by namespace and their shares concatenated, so
+    ///    each blob starts on a share of its own.
+    /// 3. The original square has the smallest power of two side `n` with `n * n`
+    ///    at least the number of shares, and is extended by the smallest power of
+    ///    two `scale` surviving the erasure rate (see `choose_dimensions`).
+    /// 4. Shares fill the original square row-major and the rest are zero shares
+    ///    of `PADDING_NAMESPACE`.
+    /// 5. Share `i` lies at row `i / n`, column `i % n` of the original square,
+    ///    which is cell `((i / n) * scale, (i % n) * scale)` of the extended one.
+    V1,
+}
+
+impl LayoutVersion {
+    /// Version blocks are built with
+    pub const LATEST: LayoutVersion = LayoutVersion::V1;
+
+    pub fn id(&self) -> u8 {
+        match self {
+            LayoutVersion::V1 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(LayoutVersion::V1),
+            _ => None,
+        }
+    }
+
+    /// Cell of the extended square holding share `share` of an original square of
+    /// side `n` extended by `scale`
+    pub fn share_cell(&self, share: usize, n: usize, scale: usize) -> (usize, usize) {
+        match self {
+            LayoutVersion::V1 => ((share / n) * scale, (share % n) * scale),
+        }
+    }
+
+    /// Cells holding the blob at `placement`, in share order
+    pub fn blob_cells(
+        &self,
+        placement: &BlobPlacement,
+        n: usize,
+        scale: usize,
+    ) -> Vec<(usize, usize)> {
+        (placement.start..placement.end)
+            .map(|share| self.share_cell(share, n, scale))
+            .collect()
+    }
+
+    /// Extended rows holding the blob at `placement`, in increasing order
+    pub fn blob_rows(&self, placement: &BlobPlacement, n: usize, scale: usize) -> Vec<usize> {
+        let mut rows: Vec<usize> = self
+            .blob_cells(placement, n, scale)
+            .into_iter()
+            .map(|(rid, _)| rid)
+            .collect();
+        rows.dedup();
+        rows
+    }
+}
+
+/// Where a blob sits in the original square: shares `start..end`, counted in
+/// row-major order, of which the first `len` bytes are the blob's
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlobPlacement {
+    pub namespace: NamespaceId,
+    pub start: usize,
+    pub end: usize,
+    pub len: usize,
+}
+
+/// Proof of a blob's inclusion: the square it went into and where it sits there
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlobReceipt {
+    /// Ticket the proposer handed out when the blob was submitted
+    pub ticket: u64,
+    pub height: u64,
+    pub placement: BlobPlacement,
+    /// Data root of the square at `height`
+    pub commitment: Vec<u8>,
+    /// Layout rules the square was built under
+    pub version: LayoutVersion,
+    /// Side of the original square
+    pub n_rows: usize,
+    pub scale: usize,
+}
+
+impl BlobReceipt {
+    /// Cells of the extended square holding the blob, in share order
+    pub fn cells(&self) -> Vec<(usize, usize)> {
+        self.version
+            .blob_cells(&self.placement, self.n_rows, self.scale)
+    }
+}
+
+/// Original square built from a batch of blobs, ready to be extended
+#[derive(Clone, Debug)]
+pub struct BlockLayout<F: PrimeField> {
+    pub version: LayoutVersion,
+    pub shares: Vec<Vec<F>>,
+    pub dimensions: SquareDimensions,
+    pub namespaces: NamespaceIndex,
+    /// Placement of every blob, in the order the blobs were given
+    pub placements: Vec<BlobPlacement>,
+}
+
+/// Shares holding `data`, `bytes_per_share` bytes each in little-endian order,
+/// the last one zero-padded
+pub fn blob_to_shares<F: PrimeField>(data: &[u8]) -> Vec<F> {
+    data.chunks(bytes_per_share::<F>())
+        .map(F::from_le_bytes_mod_order)
+        .collect()
+}
+
+/// The first `len` bytes held by `shares`, undoing `blob_to_shares`
+pub fn shares_to_blob<F: PrimeField>(shares: &[F], len: usize) -> Vec<u8> {
+    let mut data: Vec<u8> = shares
+        .iter()
+        .flat_map(|share| {
+            let mut bytes = share.into_bigint().to_bytes_le();
+            bytes.truncate(bytes_per_share::<F>());
+            bytes
+        })
+        .collect();
+    data.truncate(len);
+    data
+}
+
+/// Lay `blobs` out in the smallest square surviving `erasure_rate`: grouped by
+/// namespace in increasing order, each blob starting on a share of its own and
+/// blobs of one namespace kept in the order given, then padding. `None` if a blob
+/// uses the padding namespace or no square fits (see `choose_dimensions`).
+pub fn build_layout<F: PrimeField>(
+    blobs: &[(NamespaceId, Vec<u8>)],
+    erasure_rate: f64,
+) -> Option<BlockLayout<F>> {
+    if blobs.iter().any(|(namespace, _)| namespace.is_padding()) {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..blobs.len()).collect();
+    order.sort_by_key(|idx| blobs[*idx].0);
+
+    let mut payload: Vec<F> = vec![];
+    let mut ranges: Vec<NamespaceRange> = vec![];
+    let mut placements = vec![None; blobs.len()];
+    for idx in order {
+        let (namespace, data) = &blobs[idx];
+        let start = payload.len();
+        payload.extend(blob_to_shares::<F>(data));
+        let end = payload.len();
+        placements[idx] = Some(BlobPlacement {
+            namespace: *namespace,
+            start,
+            end,
+            len: data.len(),
+        });
+        match ranges.last_mut() {
+            Some(range) if range.namespace == *namespace => range.end = end,
+            _ => ranges.push(NamespaceRange {
+                namespace: *namespace,
+                start,
+                end,
+            }),
+        }
+    }
+
+    let dimensions = choose_dimensions::<F>(payload.len() * bytes_per_share::<F>(), erasure_rate)?;
+    let total_shares = dimensions.n_rows * dimensions.n_rows;
+    Some(BlockLayout {
+        version: LayoutVersion::V1,
+        shares: pad_to_square(&payload, dimensions.n_rows),
+        dimensions,
+        namespaces: NamespaceIndex::padded(ranges, total_shares),
+        placements: placements.into_iter().flatten().collect(),
+    })
+}
+
+/// Proof that a blob is part of a block: the line roots under its data root and
+/// a custody proof of every extended row holding the blob's shares
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound = "")
+)]
+pub struct BlobProof<E: Pairing, H: Hasher> {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_bridge::hash"))]
+    pub row_root: H::Hash,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_bridge::hash"))]
+    pub col_root: H::Hash,
+    pub rows: Vec<RowProof<E, H>>,
+}
+
+/// Check that `proof` shows the blob at `placement` is part of the block at
+/// `height` with `data_root` under `root_version`, laid out under `version` in an
+/// original square of side `n` extended by `scale`. Returns the blob's bytes, or
+/// `None` if anything fails to verify.
+pub fn verify_blob_proof<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    root_version: RootVersion,
+    data_root: H::Hash,
+    version: LayoutVersion,
+    n: usize,
+    scale: usize,
+    placement: &BlobPlacement,
+    proof: &BlobProof<E, H>,
+) -> Option<Vec<u8>> {
+    let shares = placement.len.div_ceil(bytes_per_share::<E::ScalarField>());
+    if placement.end != placement.start + shares || placement.end > n * n {
+        return None;
+    }
+    let rows = version.blob_rows(placement, n, scale);
+    if !verify_root::<H>(root_version, data_root, proof.row_root, proof.col_root)
+        || proof.rows.len() != rows.len()
+    {
+        return None;
+    }
+    for (rid, row) in rows.iter().zip(&proof.rows) {
+        if row.rid != *rid
+            || !verify_row_proof::<E, H>(
+                vk,
+                height,
+                root_version,
+                data_root,
+                proof.row_root,
+                n * scale,
+                row,
+            )
+        {
+            return None;
+        }
+    }
+
+    let values: Vec<E::ScalarField> = version
+        .blob_cells(placement, n, scale)
+        .into_iter()
+        .map(|(rid, cid)| {
+            let row = &proof.rows[rows.binary_search(&rid).ok()?];
+            row.values.get(cid).copied()
+        })
+        .collect::<Option<_>>()?;
+    Some(shares_to_blob(&values, placement.len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_layout, shares_to_blob, BlobPlacement, LayoutVersion};
+    use crate::namespace::{NamespaceId, PADDING_NAMESPACE};
+
+    use ark_test_curves::bls12_381::Fr;
+
+    #[test]
+    pub fn blobs_are_grouped_by_namespace() {
+        let (a, b) = (NamespaceId([1; 8]), NamespaceId([2; 8]));
+        let blobs = vec![(b, vec![7; 40]), (a, vec![8; 10]), (b, vec![9; 31])];
+        let layout = build_layout::<Fr>(&blobs, 0.5).unwrap();
+        assert_eq!((layout.dimensions.n_rows, layout.dimensions.scale), (2, 2));
+
+        // namespace a comes first, then both blobs of b in the order given
+        let starts: Vec<usize> = layout.placements.iter().map(|p| p.start).collect();
+        assert_eq!(starts, vec![1, 0, 3]);
+        assert_eq!(layout.namespaces.range_of(b).unwrap().end, 4);
+        assert!(layout.namespaces.range_of(PADDING_NAMESPACE).is_none());
+
+        let flat: Vec<Fr> = layout.shares.concat();
+        for (placement, (_, data)) in layout.placements.iter().zip(&blobs) {
+            let shares = &flat[placement.start..placement.end];
+            assert_eq!(shares_to_blob(shares, placement.len), *data);
+        }
+        assert!(build_layout::<Fr>(&[(PADDING_NAMESPACE, vec![1])], 0.5).is_none());
+    }
+
+    #[test]
+    pub fn blob_cells_follow_the_layout_version() {
+        let version = LayoutVersion::from_id(LayoutVersion::LATEST.id()).unwrap();
+        let placement = BlobPlacement {
+            namespace: NamespaceId([1; 8]),
+            start: 3,
+            end: 6,
+            len: 70,
+        };
+        // shares 3, 4 and 5 of a side 4 square doubled in size
+        assert_eq!(
+            version.blob_cells(&placement, 4, 2),
+            vec![(0, 6), (2, 0), (2, 2)]
+        );
+        assert_eq!(version.blob_rows(&placement, 4, 2), vec![0, 2]);
+        assert!(LayoutVersion::from_id(0).is_none());
+    }
+}
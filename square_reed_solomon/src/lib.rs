@@ -1,4 +1,34 @@
+pub mod audit;
+pub mod beacon;
+pub mod bisection;
+pub mod bitfield;
+pub mod cancel;
+pub mod cheating;
+pub mod codec;
+pub mod dimensions;
+pub mod dynamic;
+pub mod epoch;
+pub mod fixed_share;
+#[cfg(any(test, feature = "test-fixtures"))]
+pub mod fixtures;
+pub mod fri;
+pub mod hybrid;
+pub mod inclusion;
+pub mod layout;
+pub mod merkle;
+pub mod namespace;
+pub mod progress;
 pub mod prover;
+pub mod reconstruct;
+pub mod reference;
 pub mod rs_square;
+pub mod sample;
+#[cfg(feature = "serde")]
+pub mod serde_bridge;
+pub mod setup;
+pub mod small_field;
+pub mod storage;
+pub mod vectors;
+pub mod verifier;
 
 mod rs_line;
@@ -0,0 +1,745 @@
+//! Merkle roots and single-leaf inclusion proofs in a format owned by this crate.
+//! Trees have the shape `rs_merkle` builds: a node without a sibling is promoted
+//! to the next level unchanged. Verification needs nothing but a way to hash two
+//! siblings together, so it works the same over FFI, in wasm or without `rs_merkle`.
+
+use crate::codec::{put_bytes, put_u64, take_bytes, take_u64};
+use crate::sample::LineAxis;
+use crate::verifier::digests_equal;
+
+use ark_serialize::SerializationError;
+use digest::Digest;
+use rs_merkle::{Hasher, MerkleTree};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// Hash two sibling nodes into their parent
+pub trait MerkleHasher {
+    fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8>;
+}
+
+/// Nodes of the wrong length for `H` hash to nothing, which matches no root
+impl<H: Hasher> MerkleHasher for H {
+    fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+        match (
+            H::Hash::try_from(left.to_vec()),
+            H::Hash::try_from(right.to_vec()),
+        ) {
+            (Ok(left), Ok(right)) => H::concat_and_hash(&left, Some(&right)).into(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Byte the leaf hashes of a square's trees start with, so that no leaf hashes
+/// like an internal node
+pub const LEAF_PREFIX: u8 = 0x00;
+
+/// Byte the internal node hashes of a square's trees start with, followed by the
+/// tag of the tree and both children
+pub const NODE_PREFIX: u8 = 0x01;
+
+const ROW_TREE: u8 = 0;
+const COL_TREE: u8 = 1;
+const TOP_TREE: u8 = 2;
+const NAMESPACE_TREE: u8 = 3;
+
+/// Trees of a square, each hashing its internal nodes under its own tag so that
+/// no node of one passes for a node of another
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TreeTag {
+    /// Tree hashing its internal nodes as `H` does, both children alone, as every
+    /// tree of a square did before `RootVersion::V3`
+    Plain,
+    /// Tree over the row commitments
+    Row,
+    /// Tree over the column commitments
+    Col,
+    /// Tree over the row and column roots, whose root is the data root
+    Top,
+    /// Tree over the namespace ranges of a square
+    Namespace,
+}
+
+impl TreeTag {
+    /// Byte hashed after `NODE_PREFIX` in the internal nodes of the tree, `None`
+    /// for `Plain`
+    pub fn id(&self) -> Option<u8> {
+        match self {
+            TreeTag::Plain => None,
+            TreeTag::Row => Some(ROW_TREE),
+            TreeTag::Col => Some(COL_TREE),
+            TreeTag::Top => Some(TOP_TREE),
+            TreeTag::Namespace => Some(NAMESPACE_TREE),
+        }
+    }
+}
+
+impl From<LineAxis> for TreeTag {
+    fn from(axis: LineAxis) -> Self {
+        match axis {
+            LineAxis::Row => TreeTag::Row,
+            LineAxis::Col => TreeTag::Col,
+        }
+    }
+}
+
+/// `H` hashing the internal nodes of the tree tagged `TAG` as `NODE_PREFIX`, `TAG`
+/// and both children. A node without a sibling is promoted unchanged, as by
+/// `H`; leaves are hashed by whoever builds the tree.
+pub struct NodeHasher<H, const TAG: u8>(PhantomData<H>);
+
+// derived Clone would needlessly require `H: Clone`
+impl<H, const TAG: u8> Clone for NodeHasher<H, TAG> {
+    fn clone(&self) -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<H: Hasher, const TAG: u8> Hasher for NodeHasher<H, TAG> {
+    type Hash = H::Hash;
+
+    fn hash(data: &[u8]) -> H::Hash {
+        H::hash(data)
+    }
+
+    fn concat_and_hash(left: &H::Hash, right: Option<&H::Hash>) -> H::Hash {
+        match right {
+            Some(right) => {
+                let mut bytes = vec![NODE_PREFIX, TAG];
+                bytes.extend(Into::<Vec<u8>>::into(*left));
+                bytes.extend(Into::<Vec<u8>>::into(*right));
+                H::hash(&bytes)
+            }
+            None => *left,
+        }
+    }
+}
+
+/// `rs_merkle` tree whose internal nodes are hashed under a `TreeTag`. Its proofs
+/// are handed out over `H`, the hasher samples carry, and checked with
+/// `tagged_path_root`.
+pub enum TaggedTree<H: Hasher> {
+    Plain(MerkleTree<H>),
+    Row(MerkleTree<NodeHasher<H, ROW_TREE>>),
+    Col(MerkleTree<NodeHasher<H, COL_TREE>>),
+    Top(MerkleTree<NodeHasher<H, TOP_TREE>>),
+    Namespace(MerkleTree<NodeHasher<H, NAMESPACE_TREE>>),
+}
+
+impl<H: Hasher> TaggedTree<H> {
+    pub fn new(tag: TreeTag, leaves: &[H::Hash]) -> Self {
+        match tag {
+            TreeTag::Plain => TaggedTree::Plain(MerkleTree::from_leaves(leaves)),
+            TreeTag::Row => TaggedTree::Row(MerkleTree::from_leaves(leaves)),
+            TreeTag::Col => TaggedTree::Col(MerkleTree::from_leaves(leaves)),
+            TreeTag::Top => TaggedTree::Top(MerkleTree::from_leaves(leaves)),
+            TreeTag::Namespace => TaggedTree::Namespace(MerkleTree::from_leaves(leaves)),
+        }
+    }
+
+    pub fn root(&self) -> Option<H::Hash> {
+        match self {
+            TaggedTree::Plain(tree) => tree.root(),
+            TaggedTree::Row(tree) => tree.root(),
+            TaggedTree::Col(tree) => tree.root(),
+            TaggedTree::Top(tree) => tree.root(),
+            TaggedTree::Namespace(tree) => tree.root(),
+        }
+    }
+
+    /// Multi-proof of the leaves at `idxs`
+    pub fn proof(&self, idxs: &[usize]) -> rs_merkle::MerkleProof<H> {
+        let hashes = match self {
+            TaggedTree::Plain(tree) => tree.proof(idxs).proof_hashes().to_vec(),
+            TaggedTree::Row(tree) => tree.proof(idxs).proof_hashes().to_vec(),
+            TaggedTree::Col(tree) => tree.proof(idxs).proof_hashes().to_vec(),
+            TaggedTree::Top(tree) => tree.proof(idxs).proof_hashes().to_vec(),
+            TaggedTree::Namespace(tree) => tree.proof(idxs).proof_hashes().to_vec(),
+        };
+        rs_merkle::MerkleProof::new(hashes)
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        match self {
+            TaggedTree::Plain(tree) => tree.leaves_len(),
+            TaggedTree::Row(tree) => tree.leaves_len(),
+            TaggedTree::Col(tree) => tree.leaves_len(),
+            TaggedTree::Top(tree) => tree.leaves_len(),
+            TaggedTree::Namespace(tree) => tree.leaves_len(),
+        }
+    }
+}
+
+/// Root that `path` recomputes for `leaves` at `idxs` of a tree tagged `tag` with
+/// `total` leaves, `None` if the path does not fit them
+pub fn tagged_path_root<H: Hasher>(
+    tag: TreeTag,
+    path: &rs_merkle::MerkleProof<H>,
+    idxs: &[usize],
+    leaves: &[H::Hash],
+    total: usize,
+) -> Option<H::Hash> {
+    let hashes = path.proof_hashes().to_vec();
+    match tag {
+        TreeTag::Plain => path.root(idxs, leaves, total).ok(),
+        TreeTag::Row => rs_merkle::MerkleProof::<NodeHasher<H, ROW_TREE>>::new(hashes)
+            .root(idxs, leaves, total)
+            .ok(),
+        TreeTag::Col => rs_merkle::MerkleProof::<NodeHasher<H, COL_TREE>>::new(hashes)
+            .root(idxs, leaves, total)
+            .ok(),
+        TreeTag::Top => rs_merkle::MerkleProof::<NodeHasher<H, TOP_TREE>>::new(hashes)
+            .root(idxs, leaves, total)
+            .ok(),
+        TreeTag::Namespace => rs_merkle::MerkleProof::<NodeHasher<H, NAMESPACE_TREE>>::new(hashes)
+            .root(idxs, leaves, total)
+            .ok(),
+    }
+}
+
+/// A `TaggedTree` kept as the raw hashes of every level, leaves first. Unlike an
+/// `rs_merkle` tree it is `Send` and `Sync` whatever the hasher, so it can be held
+/// next to a stored square and persisted with it, serving paths without rehashing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredTree {
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(StoredTree);
+
+impl StoredTree {
+    pub fn build<H: Hasher>(tag: TreeTag, leaves: &[H::Hash]) -> Self {
+        let mut levels: Vec<Vec<H::Hash>> = vec![leaves.to_vec()];
+        loop {
+            let level = levels.last().expect("Levels start with the leaves");
+            if level.len() <= 1 {
+                break;
+            }
+            let next = level
+                .chunks(2)
+                .map(|pair| hash_node::<H>(tag, &pair[0], pair.get(1)))
+                .collect();
+            levels.push(next);
+        }
+        Self {
+            levels: levels
+                .into_iter()
+                .map(|level| level.into_iter().map(Into::into).collect())
+                .collect(),
+        }
+    }
+
+    pub fn leaves(&self) -> &[Vec<u8>] {
+        &self.levels[0]
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// `None` for a tree without leaves or built with a hasher other than `H`
+    pub fn root<H: Hasher>(&self) -> Option<H::Hash> {
+        match self.levels.last().map(Vec::as_slice) {
+            Some([root]) => H::Hash::try_from(root.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Multi-proof of the leaves at `idxs`, in increasing order, hash for hash the
+    /// one `TaggedTree::proof` gives. `None` if the tree was built with a hasher
+    /// other than `H`.
+    pub fn proof<H: Hasher>(&self, idxs: &[usize]) -> Option<rs_merkle::MerkleProof<H>> {
+        let mut current = idxs.to_vec();
+        let mut hashes = vec![];
+        for level in &self.levels {
+            for idx in &current {
+                let sibling = idx ^ 1;
+                if current.contains(&sibling) {
+                    continue;
+                }
+                if let Some(hash) = level.get(sibling) {
+                    hashes.push(H::Hash::try_from(hash.clone()).ok()?);
+                }
+            }
+            current = current.iter().map(|idx| idx / 2).collect();
+            current.dedup();
+        }
+        Some(rs_merkle::MerkleProof::new(hashes))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.levels.len() as u64);
+        for level in &self.levels {
+            put_u64(&mut bytes, level.len() as u64);
+            for hash in level {
+                put_bytes(&mut bytes, hash);
+            }
+        }
+        bytes
+    }
+
+    /// Decode a tree, checking that its levels have the sizes of a tree over its
+    /// leaves. The hashes themselves are taken as they are.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        // every entry takes at least its 4-byte length prefix
+        let count = |bytes: &mut &[u8]| -> Result<usize, SerializationError> {
+            let count = take_u64(bytes)? as usize;
+            if count > bytes.len() / 4 {
+                return Err(SerializationError::InvalidData);
+            }
+            Ok(count)
+        };
+        let mut levels: Vec<Vec<Vec<u8>>> = vec![];
+        for _ in 0..count(&mut bytes)? {
+            let level = (0..count(&mut bytes)?)
+                .map(|_| take_bytes(&mut bytes).map(<[u8]>::to_vec))
+                .collect::<Result<Vec<_>, _>>()?;
+            levels.push(level);
+        }
+        let well_formed = levels.first().is_some_and(|leaves| {
+            let mut expected = leaves.len();
+            levels[1..].iter().all(|level| {
+                let fits = expected > 1 && level.len() == expected.div_ceil(2);
+                expected = level.len();
+                fits
+            }) && expected <= 1
+        });
+        if !well_formed || !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self { levels })
+    }
+}
+
+/// Check single-leaf paths of a tree tagged `tag` with `total` leaves against one
+/// root, each given as the leaf's index, the leaf and its path. Nodes a checked
+/// path tied to the root are remembered, and a later path stops at the first of
+/// them it reaches instead of hashing on up, so nodes several paths pass through
+/// are hashed once. Every leaf is accepted only if it sits at its index under the
+/// root; siblings above the point where a path meets a checked one are not read.
+pub fn verify_tagged_paths<H: Hasher>(
+    tag: TreeTag,
+    root: H::Hash,
+    total: usize,
+    paths: &[(usize, H::Hash, &rs_merkle::MerkleProof<H>)],
+) -> bool {
+    let equal = |a: H::Hash, b: H::Hash| digests_equal(&a.into(), &b.into());
+    // checked nodes by level, counted from the leaves, and index in the level
+    let mut known: HashMap<(usize, usize), H::Hash> = HashMap::new();
+    paths.iter().all(|&(leaf_idx, leaf, path)| {
+        if leaf_idx >= total || path.proof_hashes().len() != path_len(leaf_idx, total) {
+            return false;
+        }
+        let mut siblings = path.proof_hashes().iter();
+        let mut nodes = vec![];
+        let (mut node, mut idx, mut count, mut level) = (leaf, leaf_idx, total, 0);
+        loop {
+            if let Some(checked) = known.get(&(level, idx)) {
+                if !equal(node, *checked) {
+                    return false;
+                }
+                break;
+            }
+            if count == 1 {
+                if !equal(node, root) {
+                    return false;
+                }
+                break;
+            }
+            nodes.push(((level, idx), node));
+            if idx ^ 1 < count {
+                let sibling = *siblings.next().expect("Path length was checked");
+                nodes.push(((level, idx ^ 1), sibling));
+                node = if idx % 2 == 0 {
+                    hash_node::<H>(tag, &node, Some(&sibling))
+                } else {
+                    hash_node::<H>(tag, &sibling, Some(&node))
+                };
+            }
+            idx /= 2;
+            count = count.div_ceil(2);
+            level += 1;
+        }
+        known.extend(nodes);
+        true
+    })
+}
+
+/// Siblings on the path of leaf `idx` of a tree of `total` leaves, one per level
+/// where the path node is not promoted
+fn path_len(mut idx: usize, mut total: usize) -> usize {
+    let mut len = 0;
+    while total > 1 {
+        len += (idx ^ 1 < total) as usize;
+        idx /= 2;
+        total = total.div_ceil(2);
+    }
+    len
+}
+
+fn hash_node<H: Hasher>(tag: TreeTag, left: &H::Hash, right: Option<&H::Hash>) -> H::Hash {
+    match tag {
+        TreeTag::Plain => H::concat_and_hash(left, right),
+        TreeTag::Row => NodeHasher::<H, ROW_TREE>::concat_and_hash(left, right),
+        TreeTag::Col => NodeHasher::<H, COL_TREE>::concat_and_hash(left, right),
+        TreeTag::Top => NodeHasher::<H, TOP_TREE>::concat_and_hash(left, right),
+        TreeTag::Namespace => NodeHasher::<H, NAMESPACE_TREE>::concat_and_hash(left, right),
+    }
+}
+
+/// Merkle `Hasher` over any RustCrypto digest producing `N` bytes, e.g.
+/// `DigestHasher<sha3::Sha3_256, 32>`. Hashing panics if `D` produces a digest of
+/// another size.
+pub struct DigestHasher<D, const N: usize>(PhantomData<D>);
+
+// derived Clone would needlessly require `D: Clone`
+impl<D, const N: usize> Clone for DigestHasher<D, N> {
+    fn clone(&self) -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<D: Digest, const N: usize> Hasher for DigestHasher<D, N> {
+    type Hash = [u8; N];
+
+    fn hash(data: &[u8]) -> [u8; N] {
+        D::digest(data)
+            .as_slice()
+            .try_into()
+            .expect("Digest size must match the hash length of DigestHasher")
+    }
+}
+
+/// Tree construction behind the line roots of a square, so that trees other than
+/// `rs_merkle`'s can be plugged into a prover. Leaves are leaf hashes; `tag` names
+/// the tree of the square being built or checked. A prover's roots and the paths
+/// in its samples all come from its backend.
+pub trait MerkleBackend {
+    type Tree;
+
+    fn build(tag: TreeTag, leaves: &[Vec<u8>]) -> Self::Tree;
+
+    fn root(tree: &Self::Tree) -> MerkleRoot;
+
+    fn prove(tree: &Self::Tree, index: usize) -> MerkleProof;
+
+    /// Sibling hashes of one proof of the leaves at `indices`, in increasing
+    /// order, laid out as in an `rs_merkle` multi-proof
+    fn prove_many(tree: &Self::Tree, indices: &[usize]) -> Vec<Vec<u8>>;
+
+    fn verify(tag: TreeTag, root: &MerkleRoot, proof: &MerkleProof, leaf: &[u8]) -> bool;
+}
+
+/// Binary trees built by `rs_merkle` with hasher `H`, hashing internal nodes under
+/// the tag of the tree, the default backend
+pub struct RsMerkle<H: Hasher>(PhantomData<H>);
+
+impl<H: Hasher> MerkleBackend for RsMerkle<H> {
+    type Tree = TaggedTree<H>;
+
+    /// Panics if a leaf is not a hash produced by `H`
+    fn build(tag: TreeTag, leaves: &[Vec<u8>]) -> TaggedTree<H> {
+        let leaves: Vec<H::Hash> = leaves
+            .iter()
+            .map(|leaf| H::Hash::try_from(leaf.clone()).ok())
+            .collect::<Option<_>>()
+            .expect("Leaves must be hashes of the tree's hasher");
+        TaggedTree::new(tag, &leaves)
+    }
+
+    fn root(tree: &TaggedTree<H>) -> MerkleRoot {
+        MerkleRoot::from_hash::<H>(tree.root().expect("Merkle tree should not be empty"))
+    }
+
+    fn prove(tree: &TaggedTree<H>, index: usize) -> MerkleProof {
+        MerkleProof::from_rs_merkle(&tree.proof(&[index]), index, tree.leaves_len())
+    }
+
+    fn prove_many(tree: &TaggedTree<H>, indices: &[usize]) -> Vec<Vec<u8>> {
+        let proof = tree.proof(indices);
+        proof
+            .proof_hashes()
+            .iter()
+            .map(|hash| (*hash).into())
+            .collect()
+    }
+
+    fn verify(tag: TreeTag, root: &MerkleRoot, proof: &MerkleProof, leaf: &[u8]) -> bool {
+        proof.verify_tagged::<H>(tag, root, leaf)
+    }
+}
+
+/// Root of a Merkle tree, as raw bytes
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleRoot(pub Vec<u8>);
+
+impl MerkleRoot {
+    pub fn from_hash<H: Hasher>(hash: H::Hash) -> Self {
+        Self(hash.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Path from one leaf to the root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MerkleProof {
+    /// Position of the leaf
+    pub index: usize,
+    /// Number of leaves in the tree
+    pub leaf_count: usize,
+    /// Sibling of the path node at every level from the leaves up, skipping levels
+    /// where the path node has none
+    pub siblings: Vec<Vec<u8>>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(MerkleProof);
+
+impl MerkleProof {
+    /// Proof that `leaves[index]` is in the tree built from `leaves`
+    pub fn build<H: Hasher>(leaves: &[H::Hash], index: usize) -> Self {
+        let proof = MerkleTree::<H>::from_leaves(leaves).proof(&[index]);
+        Self::from_rs_merkle(&proof, index, leaves.len())
+    }
+
+    /// Re-express an `rs_merkle` proof of the single leaf `index`
+    pub fn from_rs_merkle<H: Hasher>(
+        proof: &rs_merkle::MerkleProof<H>,
+        index: usize,
+        leaf_count: usize,
+    ) -> Self {
+        Self {
+            index,
+            leaf_count,
+            siblings: proof
+                .proof_hashes()
+                .iter()
+                .map(|hash| (*hash).into())
+                .collect(),
+        }
+    }
+
+    /// Check that `leaf` sits at `index` of a tree of `leaf_count` leaves with root `root`
+    pub fn verify<H: MerkleHasher>(&self, root: &MerkleRoot, leaf: &[u8]) -> bool {
+        if self.index >= self.leaf_count {
+            return false;
+        }
+        let mut siblings = self.siblings.iter();
+        let mut node = leaf.to_vec();
+        let (mut idx, mut count) = (self.index, self.leaf_count);
+        while count > 1 {
+            if idx ^ 1 < count {
+                let sibling = match siblings.next() {
+                    Some(sibling) => sibling,
+                    None => return false,
+                };
+                node = if idx % 2 == 0 {
+                    H::hash_pair(&node, sibling)
+                } else {
+                    H::hash_pair(sibling, &node)
+                };
+            }
+            idx /= 2;
+            count = count.div_ceil(2);
+        }
+        siblings.next().is_none() && digests_equal(&node, &root.0)
+    }
+
+    /// `verify` against a tree whose internal nodes are hashed under `tag`
+    pub fn verify_tagged<H: Hasher>(&self, tag: TreeTag, root: &MerkleRoot, leaf: &[u8]) -> bool {
+        match tag {
+            TreeTag::Plain => self.verify::<H>(root, leaf),
+            TreeTag::Row => self.verify::<NodeHasher<H, ROW_TREE>>(root, leaf),
+            TreeTag::Col => self.verify::<NodeHasher<H, COL_TREE>>(root, leaf),
+            TreeTag::Top => self.verify::<NodeHasher<H, TOP_TREE>>(root, leaf),
+            TreeTag::Namespace => self.verify::<NodeHasher<H, NAMESPACE_TREE>>(root, leaf),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.index as u64);
+        put_u64(&mut bytes, self.leaf_count as u64);
+        put_u64(&mut bytes, self.siblings.len() as u64);
+        for sibling in &self.siblings {
+            put_bytes(&mut bytes, sibling);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let index = take_u64(&mut bytes)? as usize;
+        let leaf_count = take_u64(&mut bytes)? as usize;
+        let siblings = (0..take_u64(&mut bytes)?)
+            .map(|_| take_bytes(&mut bytes).map(<[u8]>::to_vec))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self {
+            index,
+            leaf_count,
+            siblings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tagged_path_root, DigestHasher, MerkleProof, MerkleRoot, StoredTree};
+    use super::{verify_tagged_paths, TaggedTree, TreeTag};
+
+    use rs_merkle::{algorithms::Sha256, Hasher, MerkleTree};
+
+    #[test]
+    pub fn proofs_agree_with_rs_merkle() {
+        for leaf_count in 1..10u8 {
+            let leaves: Vec<[u8; 32]> = (0..leaf_count).map(|i| Sha256::hash(&[i])).collect();
+            let root = MerkleRoot::from_hash::<Sha256>(
+                MerkleTree::<Sha256>::from_leaves(&leaves).root().unwrap(),
+            );
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = MerkleProof::build::<Sha256>(&leaves, index);
+                assert!(proof.verify::<Sha256>(&root, leaf));
+                assert!(!proof.verify::<Sha256>(&root, &Sha256::hash(b"other")));
+                assert_eq!(MerkleProof::from_bytes(&proof.to_bytes()).unwrap(), proof);
+                if let Some(sibling) = proof.siblings.first() {
+                    let mut tampered = proof.clone();
+                    tampered.siblings[0] = Sha256::hash(sibling).to_vec();
+                    assert!(!tampered.verify::<Sha256>(&root, leaf));
+                }
+            }
+        }
+    }
+
+    #[test]
+    pub fn tagged_trees_keep_their_nodes_apart() {
+        let leaves: Vec<[u8; 32]> = (0..5u8).map(|i| Sha256::hash(&[i])).collect();
+        let rows = TaggedTree::<Sha256>::new(TreeTag::Row, &leaves);
+        let cols = TaggedTree::<Sha256>::new(TreeTag::Col, &leaves);
+        let root = rows.root().unwrap();
+        assert_ne!(Some(root), cols.root());
+        assert_ne!(
+            Some(root),
+            MerkleTree::<Sha256>::from_leaves(&leaves).root()
+        );
+        assert_ne!(
+            Some(root),
+            TaggedTree::<Sha256>::new(TreeTag::Namespace, &leaves).root()
+        );
+        let plain = TaggedTree::<Sha256>::new(TreeTag::Plain, &leaves);
+        assert_eq!(
+            plain.root(),
+            MerkleTree::<Sha256>::from_leaves(&leaves).root()
+        );
+        assert_eq!(
+            StoredTree::build::<Sha256>(TreeTag::Plain, &leaves).root::<Sha256>(),
+            plain.root()
+        );
+
+        let path = rows.proof(&[1, 4]);
+        let picked = [leaves[1], leaves[4]];
+        let recompute = |tag| tagged_path_root::<Sha256>(tag, &path, &[1, 4], &picked, 5);
+        assert_eq!(recompute(TreeTag::Row), Some(root));
+        assert_ne!(recompute(TreeTag::Col), Some(root));
+
+        let single = MerkleProof::from_rs_merkle(&rows.proof(&[2]), 2, rows.leaves_len());
+        let root = MerkleRoot::from_hash::<Sha256>(root);
+        assert!(single.verify_tagged::<Sha256>(TreeTag::Row, &root, &leaves[2]));
+        assert!(!single.verify_tagged::<Sha256>(TreeTag::Top, &root, &leaves[2]));
+        assert!(!single.verify::<Sha256>(&root, &leaves[2]));
+    }
+
+    #[test]
+    pub fn stored_trees_serve_the_paths_of_rs_merkle() {
+        for leaf_count in 1..10u8 {
+            let leaves: Vec<[u8; 32]> = (0..leaf_count).map(|i| Sha256::hash(&[i])).collect();
+            let tree = TaggedTree::<Sha256>::new(TreeTag::Col, &leaves);
+            let stored = StoredTree::build::<Sha256>(TreeTag::Col, &leaves);
+            assert_eq!(stored.root::<Sha256>(), tree.root());
+            let picks: Vec<Vec<usize>> = vec![
+                vec![0],
+                vec![leaf_count as usize - 1],
+                (0..leaf_count as usize).step_by(3).collect(),
+            ];
+            for idxs in picks {
+                let proof = stored.proof::<Sha256>(&idxs).unwrap();
+                assert_eq!(proof.proof_hashes(), tree.proof(&idxs).proof_hashes());
+            }
+            let decoded = StoredTree::from_bytes(&stored.to_bytes()).unwrap();
+            assert_eq!(decoded, stored);
+        }
+
+        // levels must shrink the way the tree's do
+        let skewed = StoredTree {
+            levels: vec![vec![vec![1; 32]; 3], vec![vec![2; 32]]],
+        };
+        assert!(StoredTree::from_bytes(&skewed.to_bytes()).is_err());
+        assert_eq!(
+            StoredTree::build::<Sha256>(TreeTag::Row, &[]).root::<Sha256>(),
+            None
+        );
+    }
+
+    #[test]
+    pub fn batched_paths_accept_what_single_paths_do() {
+        let leaves: Vec<[u8; 32]> = (0..7u8).map(|i| Sha256::hash(&[i])).collect();
+        let tree = TaggedTree::<Sha256>::new(TreeTag::Row, &leaves);
+        let root = tree.root().unwrap();
+        let proofs: Vec<_> = (0..7).map(|idx| tree.proof(&[idx])).collect();
+        let paths: Vec<_> = [6, 0, 1, 4, 6]
+            .into_iter()
+            .map(|idx| (idx, leaves[idx], &proofs[idx]))
+            .collect();
+        assert!(verify_tagged_paths::<Sha256>(TreeTag::Row, root, 7, &paths));
+        assert!(!verify_tagged_paths::<Sha256>(
+            TreeTag::Col,
+            root,
+            7,
+            &paths
+        ));
+
+        // a wrong leaf is caught where its path meets the checked ones
+        let mut forged = paths.clone();
+        forged[2].1 = leaves[0];
+        assert!(!verify_tagged_paths::<Sha256>(
+            TreeTag::Row,
+            root,
+            7,
+            &forged
+        ));
+        let mut moved = paths.clone();
+        moved[3] = (5, leaves[4], &proofs[4]);
+        assert!(!verify_tagged_paths::<Sha256>(
+            TreeTag::Row,
+            root,
+            7,
+            &moved
+        ));
+        let outside = [(7, leaves[6], &proofs[6])];
+        assert!(!verify_tagged_paths::<Sha256>(
+            TreeTag::Row,
+            root,
+            7,
+            &outside
+        ));
+    }
+
+    #[test]
+    pub fn digest_adapter_matches_builtin_hasher() {
+        type Sha2 = DigestHasher<sha2::Sha256, 32>;
+        let leaves: Vec<[u8; 32]> = (0..5u8).map(|i| Sha2::hash(&[i])).collect();
+        assert_eq!(leaves[3], Sha256::hash(&[3]));
+        assert_eq!(
+            MerkleTree::<Sha2>::from_leaves(&leaves).root(),
+            MerkleTree::<Sha256>::from_leaves(&leaves).root()
+        );
+    }
+}
@@ -0,0 +1,467 @@
+use crate::codec::{put_bytes, put_u64, take_bytes, take_u64};
+use crate::inclusion::{verify_shares, ShareProof};
+use crate::merkle::{tagged_path_root, TaggedTree, TreeTag, LEAF_PREFIX};
+use crate::verifier::{hashes_equal, RootVersion};
+
+use ark_ec::pairing::Pairing;
+use ark_ff::PrimeField;
+use ark_poly_commit::kzg10::VerifierKey;
+use ark_serialize::SerializationError;
+use rs_merkle::{Hasher, MerkleProof};
+
+/// Identifier of an application's data within a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamespaceId(pub [u8; 8]);
+
+/// Reserved for the shares padding a payload up to the square size. It sorts after
+/// every other namespace, so padding is always the tail of a layout.
+pub const PADDING_NAMESPACE: NamespaceId = NamespaceId([0xff; 8]);
+
+impl NamespaceId {
+    pub fn is_padding(&self) -> bool {
+        *self == PADDING_NAMESPACE
+    }
+}
+
+/// Lay `payload` out row-major in a square of side `n`, filling the tail with zero
+/// shares that `NamespaceIndex::padded` marks as padding
+pub fn pad_to_square<F: PrimeField>(payload: &[F], n: usize) -> Vec<Vec<F>> {
+    assert!(payload.len() <= n * n, "Payload does not fit in the square");
+    let mut shares = payload.to_vec();
+    shares.resize(n * n, F::zero());
+    shares.chunks(n).map(<[F]>::to_vec).collect()
+}
+
+/// Shares `start..end` of the original square, counted in row-major order,
+/// belong to `namespace`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NamespaceRange {
+    pub namespace: NamespaceId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl NamespaceRange {
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start >= self.end
+    }
+
+    /// Coordinates in the extended square of share `idx` of this range, for an
+    /// original square of side `n` extended by `scale`
+    pub fn cell(&self, idx: usize, n: usize, scale: usize) -> (usize, usize) {
+        let share = self.start + idx;
+        ((share / n) * scale, (share % n) * scale)
+    }
+
+    fn leaf<H: Hasher>(&self) -> H::Hash {
+        let mut bytes = vec![LEAF_PREFIX];
+        bytes.extend_from_slice(&self.namespace.0);
+        bytes.extend_from_slice(&(self.start as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.end as u64).to_le_bytes());
+        H::hash(&bytes)
+    }
+}
+
+/// Namespace layout of a block: ranges sorted by namespace that tile every share
+/// of the original square. Its Merkle root is carried in the block's header. The
+/// tree is domain separated as the line trees are under `RootVersion::V3`: a leaf
+/// hashes `LEAF_PREFIX` ahead of the namespace and both ends of its range, and a
+/// node hashes `NODE_PREFIX` and the tag of `TreeTag::Namespace` ahead of its
+/// children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamespaceIndex {
+    ranges: Vec<NamespaceRange>,
+}
+
+impl NamespaceIndex {
+    /// `ranges` must be sorted by strictly increasing namespace and cover shares
+    /// `0..total_shares` without gaps
+    pub fn new(ranges: Vec<NamespaceRange>, total_shares: usize) -> Self {
+        assert!(
+            Self::is_valid_layout(&ranges, total_shares),
+            "Namespace ranges must be sorted, contiguous and cover every share"
+        );
+        Self { ranges }
+    }
+
+    /// Layout of a payload whose `ranges` cover shares `0..payload_end`, followed by
+    /// padding up to `total_shares`. Consumers can then tell padding from real
+    /// zero-valued data and prove where the payload ends.
+    pub fn padded(mut ranges: Vec<NamespaceRange>, total_shares: usize) -> Self {
+        assert!(
+            ranges.iter().all(|range| !range.namespace.is_padding()),
+            "The padding namespace is reserved"
+        );
+        let payload_end = ranges.last().map_or(0, |range| range.end);
+        if payload_end < total_shares {
+            ranges.push(NamespaceRange {
+                namespace: PADDING_NAMESPACE,
+                start: payload_end,
+                end: total_shares,
+            });
+        }
+        Self::new(ranges, total_shares)
+    }
+
+    /// Number of shares before the padding
+    pub fn payload_end(&self) -> usize {
+        let last = self.ranges[self.ranges.len() - 1];
+        if last.namespace.is_padding() {
+            last.start
+        } else {
+            last.end
+        }
+    }
+
+    /// Whether `ranges` meet the requirements of `new`
+    pub fn is_valid_layout(ranges: &[NamespaceRange], total_shares: usize) -> bool {
+        !ranges.is_empty()
+            && ranges[0].start == 0
+            && ranges[ranges.len() - 1].end == total_shares
+            && ranges
+                .windows(2)
+                .all(|pair| pair[0].namespace < pair[1].namespace && pair[0].end == pair[1].start)
+    }
+
+    pub fn ranges(&self) -> &[NamespaceRange] {
+        &self.ranges
+    }
+
+    pub fn range_of(&self, namespace: NamespaceId) -> Option<NamespaceRange> {
+        self.ranges
+            .binary_search_by_key(&namespace, |range| range.namespace)
+            .ok()
+            .map(|pos| self.ranges[pos])
+    }
+
+    fn tree<H: Hasher>(&self) -> TaggedTree<H> {
+        let leaves: Vec<H::Hash> = self.ranges.iter().map(|range| range.leaf::<H>()).collect();
+        TaggedTree::new(TreeTag::Namespace, &leaves)
+    }
+
+    pub fn root<H: Hasher>(&self) -> H::Hash {
+        self.tree::<H>()
+            .root()
+            .expect("Merkle root construction of namespaces should succeed")
+    }
+
+    /// Proof locating `namespace` in the index: its range and both neighbours when
+    /// present, otherwise the two adjacent ranges it would sit between.
+    pub fn proof<H: Hasher>(&self, namespace: NamespaceId) -> NamespaceProof<H> {
+        let last = self.ranges.len() - 1;
+        let (first, end) = match self
+            .ranges
+            .binary_search_by_key(&namespace, |range| range.namespace)
+        {
+            Ok(pos) => (pos.saturating_sub(1), (pos + 1).min(last)),
+            Err(pos) => (pos.saturating_sub(1), pos.min(last)),
+        };
+        let indices: Vec<usize> = (first..=end).collect();
+        NamespaceProof {
+            total: self.ranges.len(),
+            leaves: indices
+                .iter()
+                .map(|idx| (*idx, self.ranges[*idx]))
+                .collect(),
+            path: self.tree::<H>().proof(&indices),
+        }
+    }
+}
+
+/// Outcome of checking a `NamespaceProof`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NamespaceLookup {
+    /// The namespace holds no shares in the block
+    Absent,
+    /// The namespace holds exactly the shares of this range
+    Present(NamespaceRange),
+}
+
+pub struct NamespaceProof<H: Hasher> {
+    /// Number of ranges in the index
+    pub total: usize,
+    /// Consecutive ranges of the index with their positions
+    pub leaves: Vec<(usize, NamespaceRange)>,
+    /// Merkle multiproof of the leaves under the namespace root
+    pub path: MerkleProof<H>,
+}
+
+#[cfg(feature = "serde")]
+impl<H: Hasher> serde::Serialize for NamespaceProof<H> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::serde_bridge::serialize_bytes(&self.to_bytes(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H: Hasher> serde::Deserialize<'de> for NamespaceProof<H> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        crate::serde_bridge::deserialize_bytes(deserializer, |mut bytes| {
+            let proof = Self::from_bytes(&mut bytes)?;
+            if !bytes.is_empty() {
+                return Err(SerializationError::InvalidData);
+            }
+            Ok(proof)
+        })
+    }
+}
+
+impl<H: Hasher> NamespaceProof<H> {
+    /// Check the proof against a namespace root. Revealed ranges must be
+    /// consecutive, sorted and contiguous, and must bracket `namespace` unless they
+    /// reach an end of the index, so no other range can hold its shares.
+    pub fn verify(&self, root: H::Hash, namespace: NamespaceId) -> Option<NamespaceLookup> {
+        let (first_idx, first) = *self.leaves.first()?;
+        let (last_idx, last) = *self.leaves.last()?;
+        let consecutive = self
+            .leaves
+            .windows(2)
+            .all(|pair| pair[0].0 + 1 == pair[1].0);
+        let sorted = self.leaves.windows(2).all(|pair| {
+            pair[0].1.namespace < pair[1].1.namespace && pair[0].1.end == pair[1].1.start
+        });
+        let bracketed = (first_idx == 0 && first.start == 0 || first.namespace < namespace)
+            && (last_idx + 1 == self.total || last.namespace > namespace);
+        if !consecutive || !sorted || !bracketed || last_idx >= self.total {
+            return None;
+        }
+
+        let indices: Vec<usize> = self.leaves.iter().map(|(idx, _)| *idx).collect();
+        let hashes: Vec<H::Hash> = self
+            .leaves
+            .iter()
+            .map(|(_, range)| range.leaf::<H>())
+            .collect();
+        let recomputed = tagged_path_root::<H>(
+            TreeTag::Namespace,
+            &self.path,
+            &indices,
+            &hashes,
+            self.total,
+        )?;
+        if !hashes_equal::<H>(recomputed, root) {
+            return None;
+        }
+
+        Some(
+            match self
+                .leaves
+                .iter()
+                .find(|(_, range)| range.namespace == namespace)
+            {
+                Some((_, range)) => NamespaceLookup::Present(*range),
+                None => NamespaceLookup::Absent,
+            },
+        )
+    }
+
+    /// Check a proof generated for `PADDING_NAMESPACE` against a namespace root,
+    /// returning the number of shares before the padding
+    pub fn verify_payload_end(&self, root: H::Hash) -> Option<usize> {
+        match self.verify(root, PADDING_NAMESPACE)? {
+            NamespaceLookup::Present(range) => Some(range.start),
+            // padding sorts last, so absence reveals the final range of the index
+            NamespaceLookup::Absent => self.leaves.last().map(|(_, range)| range.end),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.total as u64);
+        put_u64(&mut bytes, self.leaves.len() as u64);
+        for (idx, range) in &self.leaves {
+            put_u64(&mut bytes, *idx as u64);
+            put_bytes(&mut bytes, &range.namespace.0);
+            put_u64(&mut bytes, range.start as u64);
+            put_u64(&mut bytes, range.end as u64);
+        }
+        put_bytes(&mut bytes, &self.path.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &mut &[u8]) -> Result<Self, SerializationError> {
+        let total = take_u64(bytes)? as usize;
+        let len = take_u64(bytes)? as usize;
+        if len > bytes.len() / 4 {
+            return Err(SerializationError::InvalidData);
+        }
+        let mut leaves = Vec::with_capacity(len);
+        for _ in 0..len {
+            let idx = take_u64(bytes)? as usize;
+            let namespace = NamespaceId(
+                take_bytes(bytes)?
+                    .try_into()
+                    .map_err(|_| SerializationError::InvalidData)?,
+            );
+            let start = take_u64(bytes)? as usize;
+            let end = take_u64(bytes)? as usize;
+            leaves.push((
+                idx,
+                NamespaceRange {
+                    namespace,
+                    start,
+                    end,
+                },
+            ));
+        }
+        let path = MerkleProof::<H>::from_bytes(take_bytes(bytes)?)
+            .map_err(|_| SerializationError::InvalidData)?;
+        Ok(Self {
+            total,
+            leaves,
+            path,
+        })
+    }
+}
+
+/// Every share of a namespace in one block: where the namespace sits in the index,
+/// and a proof of the shares its range covers
+pub struct NamespaceData<E: Pairing, H: Hasher> {
+    pub proof: NamespaceProof<H>,
+    /// Proof of shares `range.start..range.end`, without any if the namespace is absent
+    pub shares: ShareProof<E, H>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(NamespaceData<E: Pairing, H: Hasher>);
+
+impl<E: Pairing, H: Hasher> NamespaceData<E, H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, &self.proof.to_bytes());
+        put_bytes(&mut bytes, &self.shares.to_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let mut proof_bytes = take_bytes(&mut bytes)?;
+        let proof = NamespaceProof::<H>::from_bytes(&mut proof_bytes)?;
+        let shares = ShareProof::<E, H>::from_bytes(take_bytes(&mut bytes)?)?;
+        if !proof_bytes.is_empty() || !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self { proof, shares })
+    }
+}
+
+/// Check namespace data against the data and namespace roots of the block at
+/// `height`, for an original square of side `n` extended by `scale` and rooted
+/// under `root_version`. Returns the
+/// namespace's shares in order, empty if the namespace is proven absent, or `None`
+/// if anything fails to verify.
+pub fn verify_namespace_data<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    root_version: RootVersion,
+    data_root: H::Hash,
+    namespace_root: H::Hash,
+    n: usize,
+    scale: usize,
+    namespace: NamespaceId,
+    data: &NamespaceData<E, H>,
+) -> Option<Vec<E::ScalarField>> {
+    let range = match data.proof.verify(namespace_root, namespace)? {
+        NamespaceLookup::Absent if data.shares.values.is_empty() => return Some(vec![]),
+        NamespaceLookup::Absent => return None,
+        NamespaceLookup::Present(range) if !range.is_empty() && range.end <= n * n => range,
+        NamespaceLookup::Present(_) => return None,
+    };
+    let cells: Vec<(usize, usize)> = (range.start..range.end)
+        .map(|share| ((share / n) * scale, (share % n) * scale))
+        .collect();
+    verify_shares(
+        vk,
+        height,
+        root_version,
+        data_root,
+        n * scale,
+        &cells,
+        &data.shares,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        pad_to_square, NamespaceId, NamespaceIndex, NamespaceLookup, NamespaceRange,
+        PADDING_NAMESPACE,
+    };
+    use crate::merkle::{TaggedTree, TreeTag, LEAF_PREFIX};
+
+    use ark_test_curves::bls12_381::Fr;
+    use rs_merkle::{algorithms::Sha256, Hasher};
+
+    #[test]
+    pub fn namespace_proofs_show_presence_and_absence() {
+        let range = |ns: u8, start, end| NamespaceRange {
+            namespace: NamespaceId([ns; 8]),
+            start,
+            end,
+        };
+        let index =
+            NamespaceIndex::new(vec![range(1, 0, 3), range(4, 3, 10), range(7, 10, 16)], 16);
+        let root = index.root::<Sha256>();
+
+        let present = index.proof::<Sha256>(NamespaceId([4; 8]));
+        assert_eq!(
+            present.verify(root, NamespaceId([4; 8])),
+            Some(NamespaceLookup::Present(range(4, 3, 10)))
+        );
+
+        for ns in [0, 5, 9] {
+            let absent = index.proof::<Sha256>(NamespaceId([ns; 8]));
+            assert_eq!(
+                absent.verify(root, NamespaceId([ns; 8])),
+                Some(NamespaceLookup::Absent)
+            );
+        }
+
+        // a proof for one namespace cannot be passed off as absence of its neighbour
+        let mut shrunk = index.proof::<Sha256>(NamespaceId([5; 8]));
+        shrunk.leaves.truncate(1);
+        assert_eq!(shrunk.verify(root, NamespaceId([7; 8])), None);
+
+        // leaves and nodes are domain separated like those of the line trees
+        let mut leaf = vec![LEAF_PREFIX];
+        leaf.extend([4; 8]);
+        leaf.extend(3u64.to_le_bytes());
+        leaf.extend(10u64.to_le_bytes());
+        assert_eq!(range(4, 3, 10).leaf::<Sha256>(), Sha256::hash(&leaf));
+        let leaves: Vec<_> = index.ranges().iter().map(|r| r.leaf::<Sha256>()).collect();
+        assert_ne!(
+            Some(root),
+            TaggedTree::<Sha256>::new(TreeTag::Plain, &leaves).root()
+        );
+    }
+
+    #[test]
+    pub fn padding_marks_where_the_payload_ends() {
+        let shares = pad_to_square(&[Fr::from(0), Fr::from(5), Fr::from(0)], 2);
+        assert_eq!(
+            shares,
+            vec![vec![Fr::from(0), Fr::from(5)], vec![Fr::from(0); 2]]
+        );
+
+        let payload = vec![NamespaceRange {
+            namespace: NamespaceId([1; 8]),
+            start: 0,
+            end: 3,
+        }];
+        let index = NamespaceIndex::padded(payload.clone(), 4);
+        assert_eq!(index.payload_end(), 3);
+        assert!(index.ranges()[1].namespace.is_padding());
+        let proof = index.proof::<Sha256>(PADDING_NAMESPACE);
+        assert_eq!(proof.verify_payload_end(index.root::<Sha256>()), Some(3));
+
+        let full = NamespaceIndex::padded(payload, 3);
+        assert_eq!(full.ranges().len(), 1);
+        let proof = full.proof::<Sha256>(PADDING_NAMESPACE);
+        assert_eq!(proof.verify_payload_end(full.root::<Sha256>()), Some(3));
+    }
+}
@@ -0,0 +1,87 @@
+/// Step of a long operation on a square
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Lines extended from the original shares
+    Extend,
+    /// Rows committed to
+    CommitRows,
+    /// Columns committed to
+    CommitCols,
+    /// Line roots and data root built from the commitments
+    Tree,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Extend => "extend",
+            Stage::CommitRows => "commit_rows",
+            Stage::CommitCols => "commit_cols",
+            Stage::Tree => "tree",
+        }
+    }
+}
+
+/// Told how far a long operation on a square has got, e.g. to drive a progress
+/// bar or a gauge. Reports may come from several worker threads at once, and
+/// should be cheap to handle.
+pub trait ProgressObserver: Sync {
+    /// `done` of the `total` steps of `stage` are complete
+    fn report(&self, stage: Stage, done: usize, total: usize);
+}
+
+/// Observer ignoring every report
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {
+    fn report(&self, _stage: Stage, _done: usize, _total: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProgressObserver, Stage};
+    use crate::cancel::CancelToken;
+    use crate::prover::RsSquareProver;
+
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    /// Furthest each stage got, and its total
+    #[derive(Default)]
+    struct Furthest(Mutex<BTreeMap<&'static str, (usize, usize)>>);
+
+    impl ProgressObserver for Furthest {
+        fn report(&self, stage: Stage, done: usize, total: usize) {
+            let mut furthest = self.0.lock().unwrap();
+            let entry = furthest.entry(stage.name()).or_insert((0, total));
+            entry.0 = entry.0.max(done);
+        }
+    }
+
+    #[test]
+    pub fn every_line_is_reported() {
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut OsRng)
+            .expect("KZG setup failed");
+        let shares = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::from(4)],
+        ];
+        let (cancel, progress) = (CancelToken::new(), Furthest::default());
+        let prover = RsSquareProver::<Bls12_381, Sha256>::with_params_observed(
+            &shares, 2, params, &cancel, &progress,
+        )
+        .unwrap();
+        prover.commit_all_rows_observed(&cancel, &progress).unwrap();
+        let furthest = progress.0.into_inner().unwrap();
+        // the 2 data rows, the 4 columns, then the 2 rows left
+        assert_eq!(furthest["extend"], (8, 8));
+        assert_eq!(furthest["commit_rows"], (4, 4));
+        assert!(!furthest.contains_key("commit_cols"));
+    }
+}
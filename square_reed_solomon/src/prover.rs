@@ -1,17 +1,34 @@
+use crate::cancel::{CancelToken, Cancelled};
+use crate::dimensions::SquareLayout;
+use crate::merkle::{MerkleBackend, MerkleProof as LineProof, MerkleRoot, RsMerkle};
+use crate::progress::{NoProgress, ProgressObserver, Stage};
 use crate::rs_line::RsLine;
 use crate::rs_square::RsSquare;
+use crate::sample::{CellSample, ChallengeContext, LineAxis, LineSample, RangeProof, RowProof};
+use crate::verifier::{
+    digests_equal, line_challenge, line_leaves, range_challenge, range_weight, RootVersion,
+};
 
 use rand::rngs::OsRng;
-use rs_merkle::{algorithms::Sha256, Hasher, MerkleTree};
+use rand::RngCore;
+use rs_merkle::{algorithms::Sha256, Hasher, MerkleProof};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
 use ark_ec::pairing::Pairing;
-use ark_poly::univariate::DensePolynomial;
-use ark_poly_commit::kzg10::{self, Powers, VerifierKey, KZG10};
-use ark_serialize::CanonicalSerialize;
-use kzg10::Commitment;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{One, UniformRand, Zero};
+use ark_poly::univariate::{DenseOrSparsePolynomial, DensePolynomial};
+use ark_poly::{DenseUVPolynomial, Polynomial};
+use ark_poly_commit::kzg10::{self, Powers, Randomness, VerifierKey, KZG10};
+use ark_poly_commit::PCRandomness;
+use kzg10::{Commitment, Proof};
 
-pub struct RsSquareProver<E: Pairing, H: Hasher> {
+/// The backend `B` builds the line trees behind the prover's roots and the paths
+/// in its samples. Samples carry `rs_merkle` proofs under hasher `H`, so `B` must
+/// build trees whose roots and siblings are `H` hashes.
+pub struct RsSquareProver<E: Pairing, H: Hasher, B: MerkleBackend = RsMerkle<H>> {
     /// Original square of shares of data
     shares: Vec<Vec<E::ScalarField>>,
     /// Scale used to extend shares to create square
@@ -19,13 +36,118 @@ pub struct RsSquareProver<E: Pairing, H: Hasher> {
     /// Reed-Solomon Encoded square of data
     square: RsSquare<E::ScalarField>,
     max_degree: usize,
-    params: kzg10::UniversalParams<E>,
+    /// KZG setup, shared with every other prover built from the same parameters
+    params: Arc<kzg10::UniversalParams<E>>,
+    /// Height of the block the square belongs to, bound into every challenge
+    height: u64,
+    /// Rules the line roots and the data root are computed under
+    root_version: RootVersion,
+    /// Line commitments, computed on first use and shared by the roots, trees and
+    /// samples built from them
+    row_commitments: OnceLock<Vec<Commitment<E>>>,
+    col_commitments: OnceLock<Vec<Commitment<E>>>,
     _pairing_phantom: PhantomData<E>,
     _hasher_phantom: PhantomData<H>,
+    _backend_phantom: PhantomData<B>,
+}
+
+/// Builds a prover with a chosen setup and Merkle backend
+pub struct RsSquareProverBuilder<'a, E: Pairing, H: Hasher, B: MerkleBackend = RsMerkle<H>> {
+    shares: &'a Vec<Vec<E::ScalarField>>,
+    scale: usize,
+    params: Option<Arc<kzg10::UniversalParams<E>>>,
+    _hasher_phantom: PhantomData<H>,
+    _backend_phantom: PhantomData<B>,
+}
+
+impl<'a, E: Pairing, H: Hasher, B: MerkleBackend> RsSquareProverBuilder<'a, E, H, B> {
+    /// Reuse an existing setup instead of generating a fresh one
+    pub fn params(mut self, params: impl Into<Arc<kzg10::UniversalParams<E>>>) -> Self {
+        self.params = Some(params.into());
+        self
+    }
+
+    pub fn backend<B2: MerkleBackend>(self) -> RsSquareProverBuilder<'a, E, H, B2> {
+        RsSquareProverBuilder {
+            shares: self.shares,
+            scale: self.scale,
+            params: self.params,
+            _hasher_phantom: PhantomData,
+            _backend_phantom: PhantomData,
+        }
+    }
+
+    pub fn build(self) -> RsSquareProver<E, H, B> {
+        let prover = match self.params {
+            Some(params) => RsSquareProver::<E, H>::with_params(self.shares, self.scale, params),
+            None => RsSquareProver::<E, H>::new(self.shares, self.scale),
+        };
+        RsSquareProver {
+            shares: prover.shares,
+            scale: prover.scale,
+            square: prover.square,
+            max_degree: prover.max_degree,
+            params: prover.params,
+            height: 0,
+            root_version: RootVersion::LATEST,
+            row_commitments: OnceLock::new(),
+            col_commitments: OnceLock::new(),
+            _pairing_phantom: PhantomData,
+            _hasher_phantom: PhantomData,
+            _backend_phantom: PhantomData,
+        }
+    }
 }
 
 impl<E: Pairing, H: Hasher> RsSquareProver<E, H> {
+    pub fn builder(
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+    ) -> RsSquareProverBuilder<'_, E, H> {
+        RsSquareProverBuilder {
+            shares,
+            scale,
+            params: None,
+            _hasher_phantom: PhantomData,
+            _backend_phantom: PhantomData,
+        }
+    }
+
     pub fn new(shares: &Vec<Vec<E::ScalarField>>, scale: usize) -> Self {
+        let max_degree = shares.len() * scale;
+
+        let params: kzg10::UniversalParams<E> = KZG10::<E, DensePolynomial<E::ScalarField>>::setup(
+            max_degree, /* Max degree = side length of square */
+            false,
+            &mut OsRng::default(),
+        )
+        .expect("KZG setup failed");
+
+        Self::with_params(shares, scale, params)
+    }
+
+    /// Build a prover over an existing setup, so that independently constructed
+    /// provers for the same shares agree on every commitment and root. Passing an
+    /// `Arc` shares the setup instead of copying it.
+    pub fn with_params(
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+        params: impl Into<Arc<kzg10::UniversalParams<E>>>,
+    ) -> Self {
+        Self::with_params_observed(shares, scale, params, &CancelToken::new(), &NoProgress)
+            .expect("Fresh token is never cancelled")
+    }
+
+    /// `with_params`, reporting the extension of the square to `progress` and
+    /// abandoning it once `cancel` is cancelled
+    pub fn with_params_observed(
+        shares: &Vec<Vec<E::ScalarField>>,
+        scale: usize,
+        params: impl Into<Arc<kzg10::UniversalParams<E>>>,
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<Self, Cancelled> {
+        let params = params.into();
         let lines = shares
             .iter()
             .map(|share| RsLine::new(share, scale))
@@ -33,28 +155,169 @@ impl<E: Pairing, H: Hasher> RsSquareProver<E, H> {
         let mut square = RsSquare::new(&lines, scale);
 
         // prover encodes shares to respond to queries
-        square.extend();
+        square.extend_observed(cancel, progress)?;
 
         let max_degree = shares.len() * scale;
+        assert!(
+            params.powers_of_g.len() > max_degree,
+            "KZG setup does not support degree {}",
+            max_degree
+        );
 
-        let params: kzg10::UniversalParams<E> = KZG10::<E, DensePolynomial<E::ScalarField>>::setup(
-            max_degree, /* Max degree = side length of square */
-            false,
-            &mut OsRng::default(),
-        )
-        .expect("KZG setup failed");
+        Ok(Self {
+            shares: shares.to_owned(),
+            scale,
+            square,
+            params,
+            max_degree,
+            height: 0,
+            root_version: RootVersion::LATEST,
+            row_commitments: OnceLock::new(),
+            col_commitments: OnceLock::new(),
+            _pairing_phantom: PhantomData,
+            _hasher_phantom: PhantomData,
+            _backend_phantom: PhantomData,
+        })
+    }
+
+    /// Build a prover around an already extended square, skipping re-encoding
+    pub fn from_square(
+        square: RsSquare<E::ScalarField>,
+        scale: usize,
+        params: impl Into<Arc<kzg10::UniversalParams<E>>>,
+    ) -> Self {
+        let params = params.into();
+        let shares = square.original_shares();
+        let max_degree = shares.len() * scale;
+        assert!(
+            params.powers_of_g.len() > max_degree,
+            "KZG setup does not support degree {}",
+            max_degree
+        );
 
         Self {
-            shares: shares.to_owned(),
+            shares,
             scale,
             square,
             params,
             max_degree,
+            height: 0,
+            root_version: RootVersion::LATEST,
+            row_commitments: OnceLock::new(),
+            col_commitments: OnceLock::new(),
             _pairing_phantom: PhantomData,
             _hasher_phantom: PhantomData,
+            _backend_phantom: PhantomData,
         }
     }
 
+    /// Prover for a square of `n_rows` data rows of which only the leading
+    /// `data_rows` hold shares, without materializing the zero padding
+    pub fn sparse(
+        data_rows: &[Vec<E::ScalarField>],
+        n_rows: usize,
+        scale: usize,
+        params: impl Into<Arc<kzg10::UniversalParams<E>>>,
+    ) -> Self {
+        Self::from_square(RsSquare::sparse(data_rows, n_rows, scale), scale, params)
+    }
+}
+
+impl<E: Pairing, H: Hasher, B: MerkleBackend> RsSquareProver<E, H, B> {
+    /// Prove for the block at `height`, which line, row and range proofs are
+    /// bound to along with the data root. Provers start at height 0.
+    pub fn at_height(mut self, height: u64) -> Self {
+        self.height = height;
+        self
+    }
+
+    /// Compute the roots of the square under `root_version` instead of
+    /// `RootVersion::LATEST`, e.g. to keep serving a block rooted under an older
+    /// one. Set it before seeding commitments, which are checked under it.
+    pub fn with_root_version(mut self, root_version: RootVersion) -> Self {
+        self.root_version = root_version;
+        self
+    }
+
+    /// Seed the line commitments with ones computed for this square before, e.g.
+    /// restored from a checkpoint, instead of committing again. Commitments
+    /// already computed are kept. `None` unless there is one per line and they
+    /// build `data_root`, so a prover never opens lines against commitments the
+    /// header does not bind.
+    pub fn with_commitments(
+        self,
+        data_root: &[u8],
+        row_commitments: Vec<Commitment<E>>,
+        col_commitments: Vec<Commitment<E>>,
+    ) -> Option<Self> {
+        let length = self.square.length();
+        if row_commitments.len() != length || col_commitments.len() != length {
+            return None;
+        }
+        let rows = Self::backend_tree(self.root_version, LineAxis::Row, &row_commitments);
+        let cols = Self::backend_tree(self.root_version, LineAxis::Col, &col_commitments);
+        let root = Self::backend_data_root(self.root_version, B::root(&rows), B::root(&cols));
+        if !digests_equal(&Into::<Vec<u8>>::into(root), data_root) {
+            return None;
+        }
+        let _ = self.row_commitments.set(row_commitments);
+        let _ = self.col_commitments.set(col_commitments);
+        Some(self)
+    }
+
+    /// Whether the line commitments, seeded or not, are those of the square. Lines
+    /// along each axis are combined under random weights and the combination is
+    /// committed to once, so this costs one commitment per axis; a square not
+    /// matching its commitments passes with negligible probability.
+    pub fn commitments_match<R: RngCore>(&self, rng: &mut R) -> bool {
+        [LineAxis::Row, LineAxis::Col].into_iter().all(|axis| {
+            let mut combined = DensePolynomial::zero();
+            let mut expected = E::G1::zero();
+            for (idx, com) in self.committed(axis).iter().enumerate() {
+                let weight = E::ScalarField::rand(rng);
+                let line = match axis {
+                    LineAxis::Row => self.square.row_poly(idx),
+                    LineAxis::Col => self.square.col_poly(idx),
+                };
+                combined += (weight, &line);
+                expected += com.0 * weight;
+            }
+            self.commit_to_poly(&combined).0 == expected.into_affine()
+        })
+    }
+
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    pub fn root_version(&self) -> RootVersion {
+        self.root_version
+    }
+
+    pub fn shares(&self) -> &Vec<Vec<E::ScalarField>> {
+        &self.shares
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn square(&self) -> &RsSquare<E::ScalarField> {
+        &self.square
+    }
+
+    pub fn layout(&self) -> SquareLayout {
+        self.square.layout().with_root_version(self.root_version)
+    }
+
+    pub fn params(&self) -> &kzg10::UniversalParams<E> {
+        &self.params
+    }
+
+    pub fn shared_params(&self) -> Arc<kzg10::UniversalParams<E>> {
+        self.params.clone()
+    }
+
     pub fn commit_to_row(&self, rid: usize) -> Commitment<E> {
         self.commit_to_poly(&self.square.row_poly(rid))
     }
@@ -63,8 +326,184 @@ impl<E: Pairing, H: Hasher> RsSquareProver<E, H> {
         self.commit_to_poly(&self.square.col_poly(cid))
     }
 
-    fn commit_to_poly(&self, poly: &DensePolynomial<E::ScalarField>) -> Commitment<E> {
-        let powers = Powers {
+    /// Commitments to every row, computed in parallel over a single copy of the setup
+    pub fn commit_all_rows(&self) -> Vec<Commitment<E>> {
+        self.commit_all_rows_cancellable(&CancelToken::new())
+            .expect("Fresh token is never cancelled")
+    }
+
+    /// Commitments to every column, computed in parallel over a single copy of the setup
+    pub fn commit_all_cols(&self) -> Vec<Commitment<E>> {
+        self.commit_all_cols_cancellable(&CancelToken::new())
+            .expect("Fresh token is never cancelled")
+    }
+
+    /// `commit_all_rows`, checking `cancel` before every row
+    pub fn commit_all_rows_cancellable(
+        &self,
+        cancel: &CancelToken,
+    ) -> Result<Vec<Commitment<E>>, Cancelled> {
+        self.commit_all_rows_observed(cancel, &NoProgress)
+    }
+
+    /// `commit_all_cols`, checking `cancel` before every column
+    pub fn commit_all_cols_cancellable(
+        &self,
+        cancel: &CancelToken,
+    ) -> Result<Vec<Commitment<E>>, Cancelled> {
+        self.commit_all_cols_observed(cancel, &NoProgress)
+    }
+
+    /// `commit_all_rows_cancellable`, reporting every row committed to `progress`
+    pub fn commit_all_rows_observed(
+        &self,
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<Vec<Commitment<E>>, Cancelled> {
+        self.line_commitments(LineAxis::Row, cancel, progress)
+            .map(<[_]>::to_vec)
+    }
+
+    /// `commit_all_cols_cancellable`, reporting every column committed to `progress`
+    pub fn commit_all_cols_observed(
+        &self,
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<Vec<Commitment<E>>, Cancelled> {
+        self.line_commitments(LineAxis::Col, cancel, progress)
+            .map(<[_]>::to_vec)
+    }
+
+    /// Commitments to every line along `axis`, computed on the first call only.
+    /// Later calls return them at once, reporting no progress.
+    fn line_commitments(
+        &self,
+        axis: LineAxis,
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<&[Commitment<E>], Cancelled> {
+        let (cache, stage) = match axis {
+            LineAxis::Row => (&self.row_commitments, Stage::CommitRows),
+            LineAxis::Col => (&self.col_commitments, Stage::CommitCols),
+        };
+        if let Some(commitments) = cache.get() {
+            return Ok(commitments.as_slice());
+        }
+        let square = &self.square;
+        let idxs: Vec<usize> = (0..self.max_degree).collect();
+        let commitments = self.commit_all(&idxs, cancel, progress, stage, |idx| match axis {
+            LineAxis::Row => square.row_poly(idx),
+            LineAxis::Col => square.col_poly(idx),
+        })?;
+        Ok(cache.get_or_init(|| commitments).as_slice())
+    }
+
+    /// Rows of the extended square holding the same values as in `previous`, the
+    /// prover of an earlier square, e.g. the block before when consecutive blocks
+    /// repeat most of their rows. Their commitments, and so their leaves, are the
+    /// same. Empty unless both squares have the same side and setup.
+    pub fn unchanged_rows<B2: MerkleBackend>(
+        &self,
+        previous: &RsSquareProver<E, H, B2>,
+    ) -> Vec<usize> {
+        let same_setup = self.max_degree == previous.max_degree
+            && (Arc::ptr_eq(&self.params, &previous.params)
+                || self.params.powers_of_g[..=self.max_degree]
+                    == previous.params.powers_of_g[..=self.max_degree]);
+        if !same_setup {
+            return vec![];
+        }
+        (0..self.max_degree)
+            .filter(|&rid| self.square.row_vals(rid) == previous.square.row_vals(rid))
+            .collect()
+    }
+
+    /// Commit to the rows, taking the commitment of every row in `unchanged_rows`
+    /// from `previous` and computing only the others. Returns the rows reused,
+    /// none if the rows were already committed.
+    pub fn reuse_rows<B2: MerkleBackend>(&self, previous: &RsSquareProver<E, H, B2>) -> Vec<usize> {
+        if self.row_commitments.get().is_some() {
+            return vec![];
+        }
+        let unchanged = self.unchanged_rows(previous);
+        let reused: &[Commitment<E>] = if unchanged.is_empty() {
+            &[]
+        } else {
+            previous.committed(LineAxis::Row)
+        };
+        let changed: Vec<usize> = (0..self.max_degree)
+            .filter(|rid| unchanged.binary_search(rid).is_err())
+            .collect();
+        let square = &self.square;
+        let fresh = self
+            .commit_all(
+                &changed,
+                &CancelToken::new(),
+                &NoProgress,
+                Stage::CommitRows,
+                |rid| square.row_poly(rid),
+            )
+            .expect("Fresh token is never cancelled");
+        let mut fresh = fresh.into_iter();
+        let commitments = (0..self.max_degree)
+            .map(|rid| match unchanged.binary_search(&rid) {
+                Ok(_) => reused[rid],
+                Err(_) => fresh.next().expect("Every changed row is committed"),
+            })
+            .collect();
+        let _ = self.row_commitments.set(commitments);
+        unchanged
+    }
+
+    fn committed(&self, axis: LineAxis) -> &[Commitment<E>] {
+        self.line_commitments(axis, &CancelToken::new(), &NoProgress)
+            .expect("Fresh token is never cancelled")
+    }
+
+    /// Commitments to the lines `idxs`, in order, split into one contiguous batch
+    /// per available core
+    fn commit_all(
+        &self,
+        idxs: &[usize],
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+        stage: Stage,
+        poly: impl Fn(usize) -> DensePolynomial<E::ScalarField> + Sync,
+    ) -> Result<Vec<Commitment<E>>, Cancelled> {
+        let powers = self.powers();
+        let length = idxs.len();
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let batch = length.div_ceil(workers).max(1);
+        let done = AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            let batches: Vec<_> = idxs
+                .chunks(batch)
+                .map(|chunk| {
+                    let (powers, poly, done) = (&powers, &poly, &done);
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|&idx| {
+                                cancel.check()?;
+                                let commitment = commit_with(powers, &poly(idx));
+                                let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                                progress.report(stage, done, length);
+                                Ok(commitment)
+                            })
+                            .collect::<Result<Vec<_>, Cancelled>>()
+                    })
+                })
+                .collect();
+            batches
+                .into_iter()
+                .map(|handle| handle.join().expect("Commitment worker panicked"))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|batches| batches.concat())
+        })
+    }
+
+    fn powers(&self) -> Powers<E> {
+        Powers {
             powers_of_g: std::borrow::Cow::Owned(
                 self.params.powers_of_g[..=self.max_degree].to_owned(),
             ),
@@ -73,56 +512,334 @@ impl<E: Pairing, H: Hasher> RsSquareProver<E, H> {
                     .map(|i| self.params.powers_of_gamma_g[&i])
                     .collect(),
             ),
-        };
+        }
+    }
 
-        // not a hiding commitment, so hiding_bound = None and no Randomness Engine.
-        let (com, _) =
-            KZG10::<E, DensePolynomial<E::ScalarField>>::commit(&powers, poly, None, None)
-                .expect("KZG commitment failed");
-        com
+    fn commit_to_poly(&self, poly: &DensePolynomial<E::ScalarField>) -> Commitment<E> {
+        commit_with(&self.powers(), poly)
     }
 
-    fn hash_commitment(&self, com: Commitment<E>) -> H::Hash {
-        let com_point = com.0;
-        let mut bytes: Vec<u8> = vec![];
-        let _ = com_point
-            .serialize_uncompressed(&mut bytes)
-            .expect("Serializing commitment point should not fail");
-        H::hash(bytes.as_slice())
+    /// KZG opening of row rid at the evaluation point of column cid
+    pub fn open_cell(&self, rid: usize, cid: usize) -> Proof<E> {
+        self.open_at(&self.square.row_poly(rid), self.square.domain_element(cid))
     }
 
-    pub fn row_root(&self) -> H::Hash {
-        let leaves: Vec<H::Hash> = (0..self.max_degree)
-            .map(|rid| self.hash_commitment(self.commit_to_row(rid)))
+    fn open_at(&self, poly: &DensePolynomial<E::ScalarField>, point: E::ScalarField) -> Proof<E> {
+        KZG10::<E, DensePolynomial<E::ScalarField>>::open(
+            &self.powers(),
+            poly,
+            point,
+            &Randomness::<E::ScalarField, DensePolynomial<E::ScalarField>>::empty(),
+        )
+        .expect("KZG opening failed")
+    }
+
+    /// Backend tree over `commitments` along `axis` under `version`
+    fn backend_tree(
+        version: RootVersion,
+        axis: LineAxis,
+        commitments: &[Commitment<E>],
+    ) -> B::Tree {
+        let leaves: Vec<Vec<u8>> = line_leaves::<E, H>(version, axis, commitments)
+            .into_iter()
+            .map(Into::into)
             .collect();
-        let row_tree = MerkleTree::<H>::from_leaves(leaves.as_slice());
-        row_tree
-            .root()
-            .expect("Merkle root construction of rows should succeed")
+        B::build(version.line_tag(axis), &leaves)
+    }
+
+    /// Data root over the backend's row and column roots under `version`
+    fn backend_data_root(
+        version: RootVersion,
+        row_root: MerkleRoot,
+        col_root: MerkleRoot,
+    ) -> H::Hash {
+        let tree = B::build(version.top_tag(), &[row_root.0, col_root.0]);
+        backend_hash::<H>(B::root(&tree).0)
+    }
+
+    /// Tree over the cached commitments along `axis`. Rebuilding it costs a hash
+    /// per node and no commitment; `rs_merkle` trees are only `Sync` for some
+    /// hashers, so they are not kept in the prover itself.
+    fn line_tree(&self, axis: LineAxis) -> B::Tree {
+        Self::backend_tree(self.root_version, axis, self.committed(axis))
+    }
+
+    /// Path of the commitments to lines `idxs` along `axis`, in increasing order,
+    /// under the backend's line root
+    fn line_path(&self, axis: LineAxis, idxs: &[usize]) -> MerkleProof<H> {
+        let siblings = B::prove_many(&self.line_tree(axis), idxs);
+        MerkleProof::new(siblings.into_iter().map(backend_hash::<H>).collect())
+    }
+
+    pub fn row_root(&self) -> H::Hash {
+        backend_hash::<H>(self.merkle_row_root().0)
     }
 
     pub fn col_root(&self) -> H::Hash {
-        let leaves: Vec<H::Hash> = (0..self.max_degree)
-            .map(|cid| self.hash_commitment(self.commit_to_col(cid)))
+        backend_hash::<H>(self.merkle_col_root().0)
+    }
+
+    /// Merkle path of the commitment to row rid under the row root
+    pub fn row_commitment_proof(&self, rid: usize) -> MerkleProof<H> {
+        self.line_path(LineAxis::Row, &[rid])
+    }
+
+    /// Merkle path of the commitment to column cid under the column root
+    pub fn col_commitment_proof(&self, cid: usize) -> MerkleProof<H> {
+        self.line_path(LineAxis::Col, &[cid])
+    }
+
+    /// One Merkle multi-proof of the commitments to rows `rids`, in increasing order,
+    /// under the row root
+    pub fn row_commitments_proof(&self, rids: &[usize]) -> MerkleProof<H> {
+        self.line_path(LineAxis::Row, rids)
+    }
+
+    /// One Merkle multi-proof of the commitments to columns `cids`, in increasing
+    /// order, under the column root
+    pub fn col_commitments_proof(&self, cids: &[usize]) -> MerkleProof<H> {
+        self.line_path(LineAxis::Col, cids)
+    }
+
+    /// Answer a light node's query for cell (rid, cid)
+    pub fn sample_cell(&self, rid: usize, cid: usize) -> CellSample<E, H> {
+        CellSample {
+            rid,
+            cid,
+            value: self.square.val_at(rid, cid),
+            row_commitment: self.committed(LineAxis::Row)[rid],
+            opening: self.open_cell(rid, cid),
+            row_path: self.row_commitment_proof(rid),
+            row_root: self.row_root(),
+            col_root: self.col_root(),
+        }
+    }
+
+    /// Answer a query for every cell of row rid with a single opening
+    pub fn sample_row(&self, rid: usize) -> LineSample<E, H> {
+        self.sample_line(LineAxis::Row, rid)
+    }
+
+    /// Answer a query for every cell of column cid with a single opening
+    pub fn sample_col(&self, cid: usize) -> LineSample<E, H> {
+        self.sample_line(LineAxis::Col, cid)
+    }
+
+    /// Prove custody of row rid against the row root
+    pub fn row_proof(&self, rid: usize) -> RowProof<E, H> {
+        let values = self.square.row_vals(rid);
+        let commitment = self.committed(LineAxis::Row)[rid];
+        let data_root: Vec<u8> = self.root().into();
+        let context = self.challenge_context(&data_root, LineAxis::Row, rid);
+        let challenge = line_challenge::<E, H>(&context, &commitment, &values);
+        RowProof {
+            rid,
+            height: self.height,
+            opening: self.open_at(&self.square.row_poly(rid), challenge),
+            values,
+            commitment,
+            path: self.row_commitment_proof(rid),
+        }
+    }
+
+    /// Prove the values of row rid at columns `cids`, given in increasing order,
+    /// with a proof of the same size however many cells the span covers
+    pub fn range_proof(&self, rid: usize, cids: &[usize]) -> RangeProof<E> {
+        let poly = self.square.row_poly(rid);
+        let values: Vec<E::ScalarField> = cids
+            .iter()
+            .map(|cid| self.square.val_at(rid, *cid))
             .collect();
-        let col_tree = MerkleTree::<H>::from_leaves(leaves.as_slice());
-        col_tree
-            .root()
-            .expect("Merkle root construction of rows should succeed")
+        let vanishing = cids.iter().fold(
+            DensePolynomial::from_coefficients_vec(vec![E::ScalarField::one()]),
+            |acc, cid| {
+                let root = self.square.domain_element(*cid);
+                &acc * &DensePolynomial::from_coefficients_vec(vec![-root, E::ScalarField::one()])
+            },
+        );
+        // the remainder of the division is the values' interpolant, so the quotient
+        // is that of the row polynomial minus the interpolant
+        let (quotient, _) = DenseOrSparsePolynomial::from(&poly)
+            .divide_with_q_and_r(&DenseOrSparsePolynomial::from(&vanishing))
+            .expect("Vanishing polynomial should not be zero");
+
+        let commitment = self.committed(LineAxis::Row)[rid];
+        let quotient_commitment = self.commit_to_poly(&quotient);
+        let data_root: Vec<u8> = self.root().into();
+        let context = self.challenge_context(&data_root, LineAxis::Row, rid);
+        let point =
+            range_challenge::<E, H>(&context, &commitment, &quotient_commitment, cids, &values);
+        let row_value = poly.evaluate(&point);
+        let quotient_value = quotient.evaluate(&point);
+        let weight = range_weight::<E, H>(point, row_value, quotient_value);
+        let mut combined = poly.coeffs;
+        combined.resize(
+            combined.len().max(quotient.coeffs.len()),
+            E::ScalarField::zero(),
+        );
+        for (coeff, q) in combined.iter_mut().zip(&quotient.coeffs) {
+            *coeff += weight * q;
+        }
+        RangeProof {
+            quotient: quotient_commitment,
+            row_value,
+            quotient_value,
+            opening: self.open_at(&DensePolynomial::from_coefficients_vec(combined), point),
+        }
+    }
+
+    fn sample_line(&self, axis: LineAxis, idx: usize) -> LineSample<E, H> {
+        let (values, poly, commitment, path) = match axis {
+            LineAxis::Row => (
+                self.square.row_vals(idx),
+                self.square.row_poly(idx),
+                self.committed(LineAxis::Row)[idx],
+                self.row_commitment_proof(idx),
+            ),
+            LineAxis::Col => (
+                self.square.col_vals(idx),
+                self.square.col_poly(idx),
+                self.committed(LineAxis::Col)[idx],
+                self.col_commitment_proof(idx),
+            ),
+        };
+        let data_root: Vec<u8> = self.root().into();
+        let context = self.challenge_context(&data_root, axis, idx);
+        let challenge = line_challenge::<E, H>(&context, &commitment, &values);
+        LineSample {
+            axis,
+            idx,
+            height: self.height,
+            opening: self.open_at(&poly, challenge),
+            values,
+            commitment,
+            path,
+            row_root: self.row_root(),
+            col_root: self.col_root(),
+        }
+    }
+
+    fn challenge_context<'a>(
+        &self,
+        data_root: &'a [u8],
+        axis: LineAxis,
+        idx: usize,
+    ) -> ChallengeContext<'a> {
+        ChallengeContext {
+            height: self.height,
+            data_root,
+            axis,
+            idx,
+        }
     }
 
+    /// Data root under the prover's root version, over the backend's line roots
     pub fn root(&self) -> H::Hash {
-        let row_col_tree = MerkleTree::<H>::from_leaves(&[self.row_root(), self.col_root()]);
-        row_col_tree
-            .root()
-            .expect("Merkle root construction from row and col roots should succeed")
+        Self::backend_data_root(
+            self.root_version,
+            self.merkle_row_root(),
+            self.merkle_col_root(),
+        )
+    }
+
+    /// Root of the backend's tree over the row commitments
+    pub fn merkle_row_root(&self) -> MerkleRoot {
+        B::root(&self.line_tree(LineAxis::Row))
     }
+
+    /// Root of the backend's tree over the column commitments
+    pub fn merkle_col_root(&self) -> MerkleRoot {
+        B::root(&self.line_tree(LineAxis::Col))
+    }
+
+    /// Path of the commitment to row rid in the backend's row tree
+    pub fn merkle_row_proof(&self, rid: usize) -> LineProof {
+        B::prove(&self.line_tree(LineAxis::Row), rid)
+    }
+
+    /// Path of the commitment to column cid in the backend's column tree
+    pub fn merkle_col_proof(&self, cid: usize) -> LineProof {
+        B::prove(&self.line_tree(LineAxis::Col), cid)
+    }
+
+    /// Data root over the backend's row and column roots
+    pub fn merkle_root(&self) -> MerkleRoot {
+        MerkleRoot::from_hash::<H>(self.root())
+    }
+}
+
+/// `bytes` as a hash of `H`; panics if a backend produced a node of another size
+fn backend_hash<H: Hasher>(bytes: Vec<u8>) -> H::Hash {
+    H::Hash::try_from(bytes)
+        .ok()
+        .expect("Merkle backend nodes must be hashes of the prover's hasher")
+}
+
+/// KZG opening of `poly` at `point`, for polynomials kept apart from their prover,
+/// e.g. rows cached for serving
+pub fn open_poly<E: Pairing>(
+    params: &kzg10::UniversalParams<E>,
+    poly: &DensePolynomial<E::ScalarField>,
+    point: E::ScalarField,
+) -> Proof<E> {
+    let degree = poly.degree();
+    let powers = Powers::<E> {
+        powers_of_g: std::borrow::Cow::Owned(params.powers_of_g[..=degree].to_owned()),
+        powers_of_gamma_g: std::borrow::Cow::Owned(
+            (0..=degree).map(|i| params.powers_of_gamma_g[&i]).collect(),
+        ),
+    };
+    KZG10::<E, DensePolynomial<E::ScalarField>>::open(
+        &powers,
+        poly,
+        point,
+        &Randomness::<E::ScalarField, DensePolynomial<E::ScalarField>>::empty(),
+    )
+    .expect("KZG opening failed")
+}
+
+/// KZG commitment to `poly`, for polynomials kept apart from their prover
+pub fn commit_poly<E: Pairing>(
+    params: &kzg10::UniversalParams<E>,
+    poly: &DensePolynomial<E::ScalarField>,
+) -> Commitment<E> {
+    let degree = poly.degree();
+    let powers = Powers::<E> {
+        powers_of_g: std::borrow::Cow::Borrowed(&params.powers_of_g[..=degree]),
+        powers_of_gamma_g: std::borrow::Cow::Owned(
+            (0..=degree).map(|i| params.powers_of_gamma_g[&i]).collect(),
+        ),
+    };
+    commit_with(&powers, poly)
+}
+
+fn commit_with<E: Pairing>(
+    powers: &Powers<E>,
+    poly: &DensePolynomial<E::ScalarField>,
+) -> Commitment<E> {
+    // padding rows of sparse squares have trivial polynomials
+    if poly.is_zero() {
+        return Commitment(E::G1Affine::zero());
+    }
+    // not a hiding commitment, so hiding_bound = None and no Randomness Engine.
+    let (com, _) = KZG10::<E, DensePolynomial<E::ScalarField>>::commit(powers, poly, None, None)
+        .expect("KZG commitment failed");
+    com
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::cancel::{CancelToken, Cancelled};
+    use crate::fixtures::counting_square;
+    use crate::merkle::{
+        MerkleBackend, MerkleProof as LineProof, MerkleRoot, RsMerkle, TaggedTree, TreeTag,
+    };
     use crate::prover::RsSquareProver;
     use crate::rs_line::RsLine;
+    use crate::sample::LineAxis;
+    use crate::verifier::{
+        compute_data_root, line_leaves, verifier_key, verify_cell_sample, verify_root, RootVersion,
+    };
 
     // Use BLS12_381 (pairing-friendly EC) for KZG
     use crate::rs_square::RsSquare;
@@ -130,21 +847,225 @@ mod tests {
     use ark_test_curves::bls12_381::Fr;
 
     // Use Sha256 for Merkle Hashing
+    use rand::rngs::OsRng;
     use rs_merkle::algorithms::Sha256;
 
     #[test]
     pub fn basic_rs_prover() {
         // arrange data shares into n by n grid (n must be power of 2)
-        let shares = vec![
-            vec![Fr::from(0), Fr::from(1), Fr::from(2), Fr::from(3)],
-            vec![Fr::from(4), Fr::from(5), Fr::from(6), Fr::from(7)],
-            vec![Fr::from(8), Fr::from(9), Fr::from(10), Fr::from(11)],
-            vec![Fr::from(12), Fr::from(13), Fr::from(14), Fr::from(15)],
-        ];
+        let shares = counting_square(4);
 
         // scale factor to dilate original shares (must be a power of 2)
         let scale: usize = 2;
 
-        let mut prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+
+        let rows = prover.commit_all_rows();
+        assert_eq!(rows.len(), shares.len() * scale);
+        assert_eq!(rows[5], prover.commit_to_row(5));
+        assert_eq!(prover.commit_all_cols()[2], prover.commit_to_col(2));
+
+        let built = RsSquareProver::<Bls12_381, Sha256>::builder(&shares, scale)
+            .params(prover.shared_params())
+            .backend::<RsMerkle<Sha256>>()
+            .build();
+        let root = compute_data_root::<Bls12_381, Sha256>(
+            RootVersion::LATEST,
+            &prover.commit_all_rows(),
+            &prover.commit_all_cols(),
+        )
+        .unwrap();
+        assert_eq!(built.root(), root);
+        assert_eq!(prover.merkle_root(), MerkleRoot::from_hash::<Sha256>(root));
+    }
+
+    /// Default trees, but hashing internal nodes untagged whatever the tree
+    struct Untagged;
+
+    impl MerkleBackend for Untagged {
+        type Tree = TaggedTree<Sha256>;
+
+        fn build(_: TreeTag, leaves: &[Vec<u8>]) -> TaggedTree<Sha256> {
+            RsMerkle::<Sha256>::build(TreeTag::Plain, leaves)
+        }
+
+        fn root(tree: &TaggedTree<Sha256>) -> MerkleRoot {
+            RsMerkle::<Sha256>::root(tree)
+        }
+
+        fn prove(tree: &TaggedTree<Sha256>, index: usize) -> LineProof {
+            RsMerkle::<Sha256>::prove(tree, index)
+        }
+
+        fn prove_many(tree: &TaggedTree<Sha256>, indices: &[usize]) -> Vec<Vec<u8>> {
+            RsMerkle::<Sha256>::prove_many(tree, indices)
+        }
+
+        fn verify(_: TreeTag, root: &MerkleRoot, proof: &LineProof, leaf: &[u8]) -> bool {
+            RsMerkle::<Sha256>::verify(TreeTag::Plain, root, proof, leaf)
+        }
+    }
+
+    #[test]
+    pub fn roots_and_samples_come_from_the_backend() {
+        let shares = counting_square(2);
+        let tagged = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let untagged = RsSquareProver::<Bls12_381, Sha256>::builder(&shares, 2)
+            .params(tagged.shared_params())
+            .backend::<Untagged>()
+            .build();
+        assert_ne!(untagged.root(), tagged.root());
+        assert_eq!(
+            untagged.merkle_row_root(),
+            MerkleRoot::from_hash::<Sha256>(untagged.row_root())
+        );
+
+        // the sample's path is one through the backend's untagged row tree
+        let sample = untagged.sample_cell(3, 1);
+        assert_eq!(sample.row_root, untagged.row_root());
+        let rows = untagged.commit_all_rows();
+        let leaves = line_leaves::<Bls12_381, Sha256>(RootVersion::LATEST, LineAxis::Row, &rows);
+        assert!(sample
+            .row_path
+            .verify(sample.row_root, &[3], &[leaves[3]], leaves.len()));
+        let vk = verifier_key(untagged.params());
+        assert!(!verify_cell_sample(
+            &vk,
+            RootVersion::LATEST,
+            untagged.root(),
+            4,
+            &sample
+        ));
+    }
+
+    #[test]
+    pub fn line_commitments_are_computed_once() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let cancel = CancelToken::new();
+        cancel.clone().cancel();
+        assert_eq!(prover.commit_all_rows_cancellable(&cancel), Err(Cancelled));
+
+        // once the root is computed, the commitments behind it are not redone
+        let root = prover.root();
+        let rows = prover.commit_all_rows_cancellable(&cancel).unwrap();
+        assert_eq!(rows[3], prover.commit_to_row(3));
+        assert!(prover.commit_all_cols_cancellable(&cancel).is_ok());
+        let sample = prover.sample_cell(3, 1);
+        assert_eq!(sample.row_commitment, rows[3]);
+        assert!(verify_root::<Sha256>(
+            prover.root_version(),
+            root,
+            sample.row_root,
+            sample.col_root
+        ));
+    }
+
+    #[test]
+    pub fn roots_follow_the_root_version() {
+        let shares = counting_square(2);
+        let latest = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let legacy =
+            RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, 2, latest.shared_params())
+                .with_root_version(RootVersion::V2);
+        assert_ne!(legacy.root(), latest.root());
+        assert_eq!(legacy.layout().root_version, RootVersion::V2);
+        assert_eq!(
+            legacy.merkle_root(),
+            MerkleRoot::from_hash::<Sha256>(legacy.root())
+        );
+
+        // samples verify under the version of their square and no other
+        let vk = verifier_key(legacy.params());
+        let sample = legacy.sample_cell(1, 2);
+        assert!(verify_cell_sample(
+            &vk,
+            RootVersion::V2,
+            legacy.root(),
+            4,
+            &sample
+        ));
+        assert!(!verify_cell_sample(
+            &vk,
+            RootVersion::V3,
+            legacy.root(),
+            4,
+            &sample
+        ));
+        let root: Vec<u8> = legacy.root().into();
+        let (rows, cols) = (latest.commit_all_rows(), latest.commit_all_cols());
+        assert!(RsSquareProver::<Bls12_381, Sha256>::with_params(
+            &shares,
+            2,
+            latest.shared_params()
+        )
+        .with_root_version(RootVersion::V2)
+        .with_commitments(&root, rows, cols)
+        .is_some());
+    }
+
+    #[test]
+    pub fn unchanged_rows_keep_their_commitments() {
+        let shares = counting_square(2);
+        let previous = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let mut next_shares = shares.clone();
+        next_shares[1][0] = Fr::from(4);
+        let next = RsSquareProver::<Bls12_381, Sha256>::with_params(
+            &next_shares,
+            2,
+            previous.shared_params(),
+        );
+
+        // parity rows mix every original row, so only row 0 repeats
+        assert_eq!(next.unchanged_rows(&previous), vec![0]);
+        assert_eq!(next.reuse_rows(&previous), vec![0]);
+        let fresh = RsSquareProver::<Bls12_381, Sha256>::with_params(
+            &next_shares,
+            2,
+            previous.shared_params(),
+        );
+        assert_eq!(next.commit_all_rows(), fresh.commit_all_rows());
+        assert_eq!(next.root(), fresh.root());
+
+        // nothing carries over from a square under another setup
+        let other = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        assert!(other.unchanged_rows(&previous).is_empty());
+    }
+
+    #[test]
+    pub fn seeded_commitments_must_build_the_root() {
+        let shares = counting_square(2);
+        let honest = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let root: Vec<u8> = honest.root().into();
+        let (rows, cols) = (honest.commit_all_rows(), honest.commit_all_cols());
+        let seed =
+            || RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, 2, honest.shared_params());
+        let seeded = seed()
+            .with_commitments(&root, rows.clone(), cols.clone())
+            .unwrap();
+        assert_eq!(seeded.root(), honest.root());
+        assert!(seeded.commitments_match(&mut OsRng));
+
+        // commitments building the root are still refused by the check once the
+        // square is not theirs
+        let other = vec![
+            vec![Fr::from(0), Fr::from(1)],
+            vec![Fr::from(2), Fr::from(4)],
+        ];
+        let forged =
+            RsSquareProver::<Bls12_381, Sha256>::with_params(&other, 2, honest.shared_params())
+                .with_commitments(&root, rows.clone(), cols.clone())
+                .unwrap();
+        assert!(!forged.commitments_match(&mut OsRng));
+
+        // commitments of another square, or too few of them, are refused
+        let mut swapped = rows.clone();
+        swapped.swap(0, 1);
+        assert!(seed()
+            .with_commitments(&root, swapped, cols.clone())
+            .is_none());
+        assert!(seed()
+            .with_commitments(&root, rows[1..].to_vec(), cols[1..].to_vec())
+            .is_none());
     }
 }
@@ -0,0 +1,244 @@
+use crate::bitfield::CellBitfield;
+use crate::cancel::CancelToken;
+use crate::rs_square::{is_power_of_two, RsSquare};
+use crate::sample::LineAxis;
+
+use ark_ff::PrimeField;
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconstructionError {
+    /// The held cells do not determine the whole square
+    Insufficient,
+    /// The held cells of a line do not lie on one polynomial of the expected degree
+    BadEncoding { axis: LineAxis, idx: usize },
+    /// The token passed in was cancelled before every cell was recovered
+    Cancelled,
+}
+
+/// Values of every position of a line over `domain`, decoded from the `known`
+/// (position, value) pairs. The first `n` pairs fix the line polynomial, the
+/// others must agree with it. `None` if fewer than `n` pairs are known or they
+/// disagree.
+pub fn decode_line<F: PrimeField>(
+    domain: &Radix2EvaluationDomain<F>,
+    known: &[(usize, F)],
+    n: usize,
+) -> Option<Vec<F>> {
+    if known.len() < n {
+        return None;
+    }
+    let (base, rest) = known.split_at(n);
+    let xs: Vec<F> = base.iter().map(|(idx, _)| domain.element(*idx)).collect();
+    // barycentric weights of the interpolation points
+    let weights: Vec<F> = (0..n)
+        .map(|j| {
+            (0..n)
+                .filter(|k| *k != j)
+                .map(|k| xs[j] - xs[k])
+                .product::<F>()
+                .inverse()
+                .expect("Interpolation points should be distinct")
+        })
+        .collect();
+
+    let vals: Vec<F> = (0..domain.size())
+        .map(|idx| {
+            let x = domain.element(idx);
+            if let Some(j) = xs.iter().position(|xj| *xj == x) {
+                return base[j].1;
+            }
+            let (mut vanishing, mut sum) = (F::one(), F::zero());
+            for (j, xj) in xs.iter().enumerate() {
+                let diff = x - xj;
+                vanishing *= diff;
+                sum += weights[j] * base[j].1 / diff;
+            }
+            vanishing * sum
+        })
+        .collect();
+    if rest.iter().any(|(idx, val)| vals[*idx] != *val) {
+        return None;
+    }
+    Some(vals)
+}
+
+/// An extended square of which only some cells are known, e.g. gathered from
+/// peers' samples. Missing cells are recovered by decoding rows and columns.
+pub struct PartialSquare<F: PrimeField> {
+    length: usize,
+    scale: usize,
+    /// Known cells in row-major order
+    cells: Vec<Option<F>>,
+    domain: Radix2EvaluationDomain<F>,
+}
+
+impl<F: PrimeField> PartialSquare<F> {
+    /// Empty square of side `length`, extended from data by a factor of `scale`.
+    /// `None` unless both are powers of 2, `scale` is at most `length` and the
+    /// field has roots of unity of order `length`.
+    pub fn new(length: usize, scale: usize) -> Option<Self> {
+        if !is_power_of_two(length) || !is_power_of_two(scale) || scale > length {
+            return None;
+        }
+        let domain = Radix2EvaluationDomain::<F>::new(length)?;
+        Some(Self {
+            length,
+            scale,
+            cells: vec![None; length.checked_mul(length)?],
+            domain,
+        })
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Side of the original data square, the number of cells decoding a line takes
+    pub fn threshold(&self) -> usize {
+        self.length / self.scale
+    }
+
+    pub fn get(&self, rid: usize, cid: usize) -> Option<F> {
+        self.cells[rid * self.length + cid]
+    }
+
+    /// Panics if (rid, cid) is outside the square
+    pub fn set(&mut self, rid: usize, cid: usize, val: F) {
+        assert!(rid < self.length && cid < self.length, "Cell out of bounds");
+        self.cells[rid * self.length + cid] = Some(val);
+    }
+
+    pub fn held(&self) -> CellBitfield {
+        let mut held = CellBitfield::empty(self.length);
+        for rid in 0..self.length {
+            for cid in 0..self.length {
+                if self.get(rid, cid).is_some() {
+                    held.set(rid, cid);
+                }
+            }
+        }
+        held
+    }
+
+    /// Recover every missing cell by repeatedly decoding the rows and columns that
+    /// hold enough cells, in the order `CellBitfield::closure` assumes
+    pub fn reconstruct(self) -> Result<RsSquare<F>, ReconstructionError> {
+        self.reconstruct_cancellable(&CancelToken::new())
+    }
+
+    /// `reconstruct`, checking `cancel` before every pair of lines decoded
+    pub fn reconstruct_cancellable(
+        mut self,
+        cancel: &CancelToken,
+    ) -> Result<RsSquare<F>, ReconstructionError> {
+        let n = self.threshold();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..self.length {
+                cancel.check().map_err(|_| ReconstructionError::Cancelled)?;
+                changed |= self.decode(LineAxis::Row, idx, n)?;
+                changed |= self.decode(LineAxis::Col, idx, n)?;
+            }
+        }
+        let rows = (0..self.length)
+            .map(|rid| {
+                (0..self.length)
+                    .map(|cid| self.get(rid, cid))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ReconstructionError::Insufficient)?;
+        Ok(RsSquare::from_extended_rows(rows, self.scale))
+    }
+
+    /// Decode line `idx` along `axis` if it holds enough cells but not all of
+    /// them, reporting whether cells were recovered
+    fn decode(
+        &mut self,
+        axis: LineAxis,
+        idx: usize,
+        n: usize,
+    ) -> Result<bool, ReconstructionError> {
+        let cell = |pos: usize| match axis {
+            LineAxis::Row => (idx, pos),
+            LineAxis::Col => (pos, idx),
+        };
+        let known: Vec<(usize, F)> = (0..self.length)
+            .filter_map(|pos| {
+                let (rid, cid) = cell(pos);
+                self.get(rid, cid).map(|val| (pos, val))
+            })
+            .collect();
+        if known.len() < n || known.len() == self.length {
+            return Ok(false);
+        }
+        let vals = decode_line(&self.domain, &known, n)
+            .ok_or(ReconstructionError::BadEncoding { axis, idx })?;
+        for (pos, val) in vals.into_iter().enumerate() {
+            let (rid, cid) = cell(pos);
+            self.set(rid, cid, val);
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PartialSquare, ReconstructionError};
+    use crate::rs_line::RsLine;
+    use crate::rs_square::RsSquare;
+    use crate::sample::LineAxis;
+
+    use ark_test_curves::bls12_381::Fr;
+
+    fn extended_square() -> RsSquare<Fr> {
+        let lines = vec![
+            RsLine::new(&[Fr::from(1), Fr::from(2)], 2),
+            RsLine::new(&[Fr::from(3), Fr::from(4)], 2),
+        ];
+        let mut square = RsSquare::new(&lines, 2);
+        square.extend();
+        square
+    }
+
+    #[test]
+    pub fn squares_are_recovered_from_enough_cells() {
+        let square = extended_square();
+        assert!(PartialSquare::<Fr>::new(4, 8).is_none());
+        assert!(PartialSquare::<Fr>::new(6, 2).is_none());
+        let mut partial = PartialSquare::<Fr>::new(4, 2).unwrap();
+        for (rid, cid) in [(0, 0), (0, 1), (1, 2), (2, 3)] {
+            partial.set(rid, cid, square.val_at(rid, cid));
+        }
+        assert!(partial.held().is_recoverable(partial.threshold()));
+        let recovered = partial.reconstruct().unwrap();
+        for rid in 0..4 {
+            assert_eq!(recovered.row_vals(rid), square.row_vals(rid));
+        }
+        assert_eq!(recovered, square);
+
+        let mut stuck = PartialSquare::<Fr>::new(4, 2).unwrap();
+        for idx in 0..3 {
+            stuck.set(idx, idx, square.val_at(idx, idx));
+        }
+        assert_eq!(
+            stuck.reconstruct().unwrap_err(),
+            ReconstructionError::Insufficient
+        );
+
+        // a third cell off the row polynomial is caught while decoding
+        let mut tampered = PartialSquare::<Fr>::new(4, 2).unwrap();
+        tampered.set(0, 0, square.val_at(0, 0));
+        tampered.set(0, 1, square.val_at(0, 1));
+        tampered.set(0, 2, square.val_at(0, 2) + Fr::from(1));
+        assert_eq!(
+            tampered.reconstruct().unwrap_err(),
+            ReconstructionError::BadEncoding {
+                axis: LineAxis::Row,
+                idx: 0
+            }
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Slow reference encoder the optimized square is checked against. Every value
+//! is the Lagrange interpolant of a line evaluated directly at a power of the
+//! root of unity, with no FFTs, sparse shortcuts or threads.
+use ark_ff::{FftField, Field, PrimeField};
+
+/// Powers of the primitive `length`-th root of unity, the evaluation point of
+/// every position of a line
+fn points<F: FftField>(length: usize) -> Vec<F> {
+    let root = F::get_root_of_unity(length as u64).expect("No root of unity of that order");
+    (0..length).map(|idx| root.pow([idx as u64])).collect()
+}
+
+/// Value at `x` of the polynomial of degree below `known.len()` through `known`
+pub fn lagrange<F: Field>(known: &[(F, F)], x: F) -> F {
+    known
+        .iter()
+        .enumerate()
+        .map(|(j, (xj, yj))| {
+            let (num, den) = known
+                .iter()
+                .enumerate()
+                .filter(|(k, _)| *k != j)
+                .fold((F::one(), F::one()), |(num, den), (_, (xk, _))| {
+                    (num * (x - xk), den * (*xj - xk))
+                });
+            *yj * num
+                * den
+                    .inverse()
+                    .expect("Interpolation points should be distinct")
+        })
+        .sum()
+}
+
+/// Extended square of side `n * scale` for the `n` by `n` `shares`, share (i, j)
+/// at cell (i * scale, j * scale): each data row is interpolated and evaluated at
+/// every column, then each column at every row
+pub fn reference_extend<F: PrimeField>(shares: &[Vec<F>], scale: usize) -> Vec<Vec<F>> {
+    let n = shares.len();
+    let length = n * scale;
+    let xs = points::<F>(length);
+    let data_rows: Vec<Vec<F>> = shares
+        .iter()
+        .map(|row| {
+            let known: Vec<(F, F)> = (0..n).map(|j| (xs[j * scale], row[j])).collect();
+            xs.iter().map(|x| lagrange(&known, *x)).collect()
+        })
+        .collect();
+    (0..length)
+        .map(|rid| {
+            (0..length)
+                .map(|cid| {
+                    let known: Vec<(F, F)> =
+                        (0..n).map(|i| (xs[i * scale], data_rows[i][cid])).collect();
+                    lagrange(&known, xs[rid])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Every cell of an extended square of side `cells.len()`, extended by `scale`,
+/// recovered from the `Some` cells by decoding any line holding at least `length
+/// / scale` of them until no line changes. `None` if cells stay missing or a line's
+/// cells do not lie on one polynomial.
+pub fn reference_reconstruct<F: PrimeField>(
+    cells: &[Vec<Option<F>>],
+    scale: usize,
+) -> Option<Vec<Vec<F>>> {
+    let length = cells.len();
+    let n = length / scale;
+    let xs = points::<F>(length);
+    let mut cells = cells.to_vec();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 0..length {
+            for transpose in [false, true] {
+                let at = |pos: usize| if transpose { (pos, idx) } else { (idx, pos) };
+                let known: Vec<(F, F)> = (0..length)
+                    .filter_map(|pos| {
+                        let (rid, cid) = at(pos);
+                        cells[rid][cid].map(|val| (xs[pos], val))
+                    })
+                    .collect();
+                if known.len() < n || known.len() == length {
+                    continue;
+                }
+                if known[n..]
+                    .iter()
+                    .any(|(x, y)| lagrange(&known[..n], *x) != *y)
+                {
+                    return None;
+                }
+                for pos in 0..length {
+                    let (rid, cid) = at(pos);
+                    cells[rid][cid] = Some(lagrange(&known[..n], xs[pos]));
+                }
+                changed = true;
+            }
+        }
+    }
+    cells
+        .into_iter()
+        .map(|row| row.into_iter().collect::<Option<Vec<F>>>())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reference_extend, reference_reconstruct};
+    use crate::reconstruct::{PartialSquare, ReconstructionError};
+    use crate::rs_line::RsLine;
+    use crate::rs_square::RsSquare;
+
+    use ark_ff::UniformRand;
+    use ark_test_curves::bls12_381::Fr;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_shares(rng: &mut StdRng, n: usize) -> Vec<Vec<Fr>> {
+        (0..n)
+            .map(|_| (0..n).map(|_| Fr::rand(rng)).collect())
+            .collect()
+    }
+
+    #[test]
+    pub fn extension_matches_the_reference() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for (n, scale) in [(2, 2), (4, 2), (2, 4), (4, 4), (8, 2)] {
+            let shares = random_shares(&mut rng, n);
+            let expected = reference_extend(&shares, scale);
+            let lines: Vec<RsLine<Fr>> = shares.iter().map(|row| RsLine::new(row, scale)).collect();
+            let mut square = RsSquare::new(&lines, scale);
+            square.extend();
+            let sparse = RsSquare::sparse(&shares[..n / 2 + 1], n, scale);
+            let mut padded = shares.clone();
+            for row in padded.iter_mut().skip(n / 2 + 1) {
+                row.iter_mut().for_each(|val| *val = Fr::from(0));
+            }
+            let padded = reference_extend(&padded, scale);
+            for rid in 0..n * scale {
+                assert_eq!(
+                    square.row_vals(rid),
+                    expected[rid],
+                    "n {} scale {}",
+                    n,
+                    scale
+                );
+                assert_eq!(sparse.row_vals(rid), padded[rid], "n {} scale {}", n, scale);
+            }
+        }
+    }
+
+    #[test]
+    pub fn reconstruction_matches_the_reference() {
+        let mut rng = StdRng::seed_from_u64(2);
+        for round in 0..40 {
+            let (n, scale) = [(2, 2), (4, 2), (2, 4)][round % 3];
+            let length = n * scale;
+            let full = reference_extend(&random_shares(&mut rng, n), scale);
+            let keep = rng.gen_range(0.2..0.8);
+            let cells: Vec<Vec<Option<Fr>>> = full
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|val| rng.gen_bool(keep).then_some(*val))
+                        .collect()
+                })
+                .collect();
+
+            let mut partial = PartialSquare::new(length, scale).unwrap();
+            for (rid, row) in cells.iter().enumerate() {
+                for (cid, val) in row.iter().enumerate() {
+                    if let Some(val) = val {
+                        partial.set(rid, cid, *val);
+                    }
+                }
+            }
+            match (reference_reconstruct(&cells, scale), partial.reconstruct()) {
+                (Some(expected), Ok(square)) => {
+                    assert_eq!(expected, full);
+                    for rid in 0..length {
+                        assert_eq!(square.row_vals(rid), expected[rid]);
+                    }
+                }
+                (None, Err(ReconstructionError::Insufficient)) => {}
+                (expected, rebuilt) => panic!(
+                    "Round {} disagrees: reference {:?}, square {:?}",
+                    round,
+                    expected.is_some(),
+                    rebuilt.map(|_| ())
+                ),
+            }
+        }
+    }
+}
@@ -32,6 +32,19 @@ impl<F: PrimeField> RsLine<F> {
         Self { vals, scale }
     }
 
+    /// Line made of already extended values
+    pub fn from_vals(vals: Vec<F>, scale: usize) -> Self {
+        assert!(
+            is_power_of_two(vals.len()),
+            "Length of Reed Solomon Line must be power of 2"
+        );
+        assert!(
+            is_power_of_two(scale) && scale <= vals.len(),
+            "Scale factor of Reed Solomon Line must be power of 2"
+        );
+        Self { vals, scale }
+    }
+
     pub fn length(&self) -> usize {
         self.vals.len()
     }
@@ -1,9 +1,17 @@
+use crate::cancel::{CancelToken, Cancelled};
+use crate::codec::{field_to_bytes, put_u64};
+use crate::dimensions::SquareLayout;
+use crate::layout::shares_to_blob;
+use crate::progress::{NoProgress, ProgressObserver, Stage};
 use crate::rs_line::RsLine;
-use std::fmt::{Debug, Formatter};
+use crate::verifier::RootVersion;
+use std::fmt::{Debug, Display, Formatter};
 
-use ark_ff::PrimeField;
+use ark_ff::{PrimeField, Zero};
 use ark_poly::univariate::DensePolynomial;
-use ark_poly::{EvaluationDomain, Evaluations, Radix2EvaluationDomain};
+use ark_poly::{DenseUVPolynomial, EvaluationDomain, Evaluations, Radix2EvaluationDomain};
+use rs_merkle::algorithms::Sha256;
+use rs_merkle::Hasher;
 
 pub struct RsSquare<F: PrimeField> {
     /// Original shares are presented as n_row by n_row field elements
@@ -12,8 +20,12 @@ pub struct RsSquare<F: PrimeField> {
     scale: usize,
     /// Encoded square side-length (= n_rows*scale)
     length: usize,
-    /// Rows of the Encoded Square
+    /// Rows of the Encoded Square, empty if the square is sparse
     rows: Vec<RsLine<F>>,
+    /// Set instead of `rows` for a square whose trailing data rows are all zero
+    sparse: Option<SparseRows<F>>,
+    /// Data rows appended so far to a square built with `appendable`
+    appended: Option<usize>,
     /// 2-adic domain used to interpolate original data shares over
     small_domain: Radix2EvaluationDomain<F>,
     /// 2-adic domain used to evaluate interpolated polynomials
@@ -21,15 +33,119 @@ pub struct RsSquare<F: PrimeField> {
     large_domain: Radix2EvaluationDomain<F>,
 }
 
+/// The data rows of a sparse square that hold shares, extended over the large
+/// domain. Data rows past them are zero, so every other value follows from these.
+struct SparseRows<F: PrimeField> {
+    /// Polynomial of each occupied data row
+    polys: Vec<DensePolynomial<F>>,
+    /// Evaluations of each occupied data row at every column
+    evals: Vec<Vec<F>>,
+}
+
+/// A summary that stays short however large the square: its dimensions, the
+/// values at the four corners of the encoded square and the SHA-256
+/// `content_digest` of its original shares. `dump` writes every value.
 impl<F: PrimeField> Debug for RsSquare<F> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        for rid in 0..self.length {
-            let _ = writeln!(f, "{:?}", &self.rows[rid]);
+        let last = self.length - 1;
+        let corners = [
+            self.val_at(0, 0),
+            self.val_at(0, last),
+            self.val_at(last, 0),
+            self.val_at(last, last),
+        ];
+        let digest: String = self
+            .content_digest::<Sha256>()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        f.debug_struct("RsSquare")
+            .field("n_rows", &self.n_rows)
+            .field("scale", &self.scale)
+            .field("length", &self.length)
+            .field("sparse", &self.is_sparse())
+            .field("corners", &corners)
+            .field("data_digest", &format_args!("{}", digest))
+            .finish()
+    }
+}
+
+/// Every value of a square, one row of the encoded square per line
+pub struct SquareDump<'a, F: PrimeField>(&'a RsSquare<F>);
+
+impl<F: PrimeField> Display for SquareDump<'_, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for rid in 0..self.0.length {
+            writeln!(f, "{:?}", self.0.row_vals(rid))?;
         }
         Ok(())
     }
 }
 
+/// Squares are equal when their dimensions and original shares are, which fix
+/// every other value of a correctly extended square
+impl<F: PrimeField> PartialEq for RsSquare<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.n_rows == other.n_rows
+            && self.scale == other.scale
+            && self.original_shares() == other.original_shares()
+    }
+}
+
+impl<F: PrimeField> Eq for RsSquare<F> {}
+
+/// What a square serializes to: its scale and encoded original shares, which are
+/// extended again when deserialized
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EncodedSquare {
+    scale: usize,
+    shares: Vec<Vec<Vec<u8>>>,
+}
+
+#[cfg(feature = "serde")]
+impl<F: PrimeField> serde::Serialize for RsSquare<F> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let shares = self.original_shares();
+        let encoded = EncodedSquare {
+            scale: self.scale,
+            shares: shares
+                .iter()
+                .map(|row| crate::codec::line_to_bytes(row))
+                .collect(),
+        };
+        serde::Serialize::serialize(&encoded, serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, F: PrimeField> serde::Deserialize<'de> for RsSquare<F> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let EncodedSquare { scale, shares } = serde::Deserialize::deserialize(deserializer)?;
+        let n_rows = shares.len();
+        let extendable = is_power_of_two(n_rows)
+            && is_power_of_two(scale)
+            && shares.iter().all(|row| row.len() == n_rows)
+            && n_rows
+                .checked_mul(scale)
+                .and_then(Radix2EvaluationDomain::<F>::new)
+                .is_some();
+        if !extendable {
+            return Err(D::Error::custom("Shares do not form an extendable square"));
+        }
+        let lines = shares
+            .iter()
+            .map(|row| crate::codec::line_from_bytes::<F>(row).map(|row| RsLine::new(&row, scale)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(D::Error::custom)?;
+        let mut square = Self::new(&lines, scale);
+        square.extend();
+        Ok(square)
+    }
+}
+
 impl<F: PrimeField> RsSquare<F> {
     pub fn new(data_rows: &[RsLine<F>], scale: usize) -> Self {
         let n_rows = data_rows.len();
@@ -67,11 +183,198 @@ impl<F: PrimeField> RsSquare<F> {
             scale,
             length,
             rows,
+            sparse: None,
+            appended: None,
+            small_domain,
+            large_domain,
+        }
+    }
+
+    /// Square of `n_rows` zero data rows, filled in one data row at a time with
+    /// `append_row` as shares arrive. The square is extended after every append, so
+    /// it needs no `extend`, and data rows not yet appended are zero padding. Hand
+    /// it to `RsSquareProver::from_square` once the block is complete.
+    pub fn appendable(n_rows: usize, scale: usize) -> Self {
+        let zero_rows: Vec<RsLine<F>> = (0..n_rows)
+            .map(|_| RsLine::new(&vec![F::zero(); n_rows], scale))
+            .collect();
+        let mut square = Self::new(&zero_rows, scale);
+        square.appended = Some(0);
+        square
+    }
+
+    /// Append `shares` as the next data row of an appendable square, returning its
+    /// index among the data rows. Only the new row is extended: extension is
+    /// linear, so every row between data rows gains its Lagrange weight at the new
+    /// row times it, and no column is re-extended. Other data rows are untouched.
+    pub fn append_row(&mut self, shares: &[F]) -> usize {
+        let idx = match self.appended {
+            Some(idx) => idx,
+            None => panic!("Square is not appendable"),
+        };
+        assert!(idx < self.n_rows, "Every data row was already appended");
+        assert_eq!(shares.len(), self.n_rows, "Data rows do not form a square");
+
+        let mut line = RsLine::new(shares, self.scale);
+        line.extend(self.small_domain, self.large_domain);
+        for rid in 0..self.length {
+            let weight = self
+                .small_domain
+                .evaluate_all_lagrange_coefficients(self.large_domain.element(rid))[idx];
+            if weight.is_zero() {
+                continue;
+            }
+            for cid in 0..self.length {
+                let val = self.rows[rid].get_element_at(cid) + weight * line.get_element_at(cid);
+                self.rows[rid].set_element_at(cid, val);
+            }
+        }
+        self.appended = Some(idx + 1);
+        idx
+    }
+
+    /// Data rows appended so far, `None` unless the square is appendable
+    pub fn appended_rows(&self) -> Option<usize> {
+        self.appended
+    }
+
+    /// Square of `n_rows` data rows of which only the leading `data_rows` hold
+    /// shares and the rest are zero padding. Only the occupied rows are stored and
+    /// extended; every other value is derived from them when asked for, so the
+    /// square is never materialized and needs no `extend`.
+    pub fn sparse(data_rows: &[Vec<F>], n_rows: usize, scale: usize) -> Self {
+        assert!(is_power_of_two(n_rows), "Number of rows must be power of 2");
+        assert!(is_power_of_two(scale), "Scale factor must be power of 2");
+        assert!(
+            data_rows.len() <= n_rows,
+            "More data rows than rows in the square"
+        );
+        let length = n_rows * scale;
+
+        let large_domain = Radix2EvaluationDomain::<F>::new(length).unwrap_or_else(|| {
+            panic!(
+                "Domain does not have roots of unity of order {} = {}*{}",
+                length, n_rows, scale
+            );
+        });
+        let small_domain = Radix2EvaluationDomain::<F>::new(n_rows).unwrap();
+
+        let polys: Vec<DensePolynomial<F>> = data_rows
+            .iter()
+            .map(|row| {
+                assert_eq!(row.len(), n_rows, "Data rows do not form a square");
+                Evaluations::from_vec_and_domain(row.clone(), small_domain).interpolate()
+            })
+            .collect();
+        let evals = polys
+            .iter()
+            .map(|poly| large_domain.fft(&poly.coeffs))
+            .collect();
+
+        Self {
+            n_rows,
+            scale,
+            length,
+            rows: vec![],
+            sparse: Some(SparseRows { polys, evals }),
+            appended: None,
+            small_domain,
+            large_domain,
+        }
+    }
+
+    /// Weights combining the occupied data rows of a sparse square into row `rid`
+    fn sparse_weights(&self, sparse: &SparseRows<F>, rid: usize) -> Vec<F> {
+        let mut weights = self
+            .small_domain
+            .evaluate_all_lagrange_coefficients(self.large_domain.element(rid));
+        weights.truncate(sparse.polys.len());
+        weights
+    }
+
+    /// Values of a sparse square's column `cid` at the data rows
+    fn sparse_data_col(&self, sparse: &SparseRows<F>, cid: usize) -> Vec<F> {
+        let mut col: Vec<F> = sparse.evals.iter().map(|evals| evals[cid]).collect();
+        col.resize(self.n_rows, F::zero());
+        col
+    }
+
+    /// Square made of already extended rows, e.g. one restored from storage.
+    /// No extension is performed.
+    pub fn from_extended_rows(extended_rows: Vec<Vec<F>>, scale: usize) -> Self {
+        let length = extended_rows.len();
+        assert!(is_power_of_two(length), "Number of rows must be power of 2");
+        assert!(
+            is_power_of_two(scale) && scale <= length,
+            "Scale factor must be power of 2"
+        );
+        let n_rows = length / scale;
+
+        let large_domain = Radix2EvaluationDomain::<F>::new(length).unwrap_or_else(|| {
+            panic!(
+                "Domain does not have roots of unity of order {} = {}*{}",
+                length, n_rows, scale
+            );
+        });
+        let small_domain = Radix2EvaluationDomain::<F>::new(n_rows).unwrap();
+
+        let rows = extended_rows
+            .into_iter()
+            .map(|row| {
+                assert_eq!(row.len(), length, "Extended rows do not form a square");
+                RsLine::from_vals(row, scale)
+            })
+            .collect();
+
+        Self {
+            n_rows,
+            scale,
+            length,
+            rows,
+            sparse: None,
+            appended: None,
             small_domain,
             large_domain,
         }
     }
 
+    /// Side of the original square
+    pub fn n_rows(&self) -> usize {
+        self.n_rows
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// Side of the encoded square
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn log2_length(&self) -> u32 {
+        self.length.trailing_zeros()
+    }
+
+    pub fn layout(&self) -> SquareLayout {
+        SquareLayout {
+            n_rows: self.n_rows,
+            scale: self.scale,
+            root_version: RootVersion::LATEST,
+        }
+    }
+
+    /// Every value of the encoded square for printing, which `Debug` only
+    /// summarizes
+    pub fn dump(&self) -> SquareDump<'_, F> {
+        SquareDump(self)
+    }
+
+    /// Whether the square derives its values from a few occupied rows
+    pub fn is_sparse(&self) -> bool {
+        self.sparse.is_some()
+    }
+
     fn set_row(&mut self, rid: usize, line: &RsLine<F>) {
         for cid in 0..self.length {
             self.rows[rid].set_element_at(cid, line.get_element_at(cid));
@@ -85,25 +388,149 @@ impl<F: PrimeField> RsSquare<F> {
     }
 
     pub fn val_at(&self, rid: usize, cid: usize) -> F {
-        self.rows[rid].get_element_at(cid)
+        match &self.sparse {
+            Some(sparse) => self
+                .sparse_weights(sparse, rid)
+                .iter()
+                .zip(&sparse.evals)
+                .map(|(weight, evals)| *weight * evals[cid])
+                .sum(),
+            None => self.rows[rid].get_element_at(cid),
+        }
+    }
+
+    /// Original data shares, found at coordinates divisible by the scale
+    pub fn original_shares(&self) -> Vec<Vec<F>> {
+        if let Some(sparse) = &self.sparse {
+            let mut shares: Vec<Vec<F>> = sparse
+                .evals
+                .iter()
+                .map(|evals| evals.iter().step_by(self.scale).copied().collect())
+                .collect();
+            shares.resize(self.n_rows, vec![F::zero(); self.n_rows]);
+            return shares;
+        }
+        (0..self.n_rows)
+            .map(|rid| {
+                (0..self.n_rows)
+                    .map(|cid| self.val_at(rid * self.scale, cid * self.scale))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// `original_shares`, consuming the square so the data rows are moved out
+    /// rather than copied and parity is dropped
+    pub fn into_original_shares(self) -> Vec<Vec<F>> {
+        if self.sparse.is_some() {
+            return self.original_shares();
+        }
+        self.rows
+            .into_iter()
+            .step_by(self.scale)
+            .map(|row| row.compressed_vals())
+            .collect()
+    }
+
+    /// The first `len` bytes held by the original shares in row-major order, e.g.
+    /// the payload of a block laid out under `LayoutVersion::V1` without the
+    /// padding shares that follow it
+    pub fn to_original_bytes(&self, len: usize) -> Vec<u8> {
+        shares_to_blob(&self.original_shares().concat(), len)
+    }
+
+    /// Digest of the dimensions and original shares, equal for squares that are
+    /// equal. Cheaper to exchange than the square, and cheaper to compute than a
+    /// data root since nothing is committed.
+    pub fn content_digest<H: Hasher>(&self) -> H::Hash {
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.n_rows as u64);
+        put_u64(&mut bytes, self.scale as u64);
+        for share in self.original_shares().iter().flatten() {
+            bytes.extend(field_to_bytes(share));
+        }
+        H::hash(&bytes)
+    }
+
+    /// All values of row `rid` of the encoded square
+    pub fn row_vals(&self, rid: usize) -> Vec<F> {
+        if let Some(sparse) = &self.sparse {
+            let mut vals = vec![F::zero(); self.length];
+            for (weight, evals) in self.sparse_weights(sparse, rid).iter().zip(&sparse.evals) {
+                for (val, eval) in vals.iter_mut().zip(evals) {
+                    *val += *weight * eval;
+                }
+            }
+            return vals;
+        }
+        (0..self.length)
+            .map(|cid| self.rows[rid].get_element_at(cid))
+            .collect()
+    }
+
+    /// All values of column `cid` of the encoded square
+    pub fn col_vals(&self, cid: usize) -> Vec<F> {
+        if self.sparse.is_some() {
+            return self.large_domain.fft(&self.col_poly(cid).coeffs);
+        }
+        (0..self.length)
+            .map(|rid| self.rows[rid].get_element_at(cid))
+            .collect()
+    }
+
+    /// Evaluation point corresponding to row/column index `idx` of the encoded square
+    pub fn domain_element(&self, idx: usize) -> F {
+        self.large_domain.element(idx)
     }
 
     pub fn extend(&mut self) {
+        self.extend_cancellable(&CancelToken::new())
+            .expect("Fresh token is never cancelled");
+    }
+
+    /// `extend`, checking `cancel` before every line. A cancelled square is left
+    /// partly extended and should be discarded.
+    pub fn extend_cancellable(&mut self, cancel: &CancelToken) -> Result<(), Cancelled> {
+        self.extend_observed(cancel, &NoProgress)
+    }
+
+    /// `extend_cancellable`, reporting every line extended to `progress`
+    pub fn extend_observed(
+        &mut self,
+        cancel: &CancelToken,
+        progress: &dyn ProgressObserver,
+    ) -> Result<(), Cancelled> {
+        if self.sparse.is_some() {
+            return Ok(());
+        }
+        let total = 2 * self.length;
+        let mut done = 0;
+        let mut step = || {
+            done += 1;
+            progress.report(Stage::Extend, done, total);
+        };
         // extend rows for which we originally have data shares in
         for rid in 0..self.n_rows {
+            cancel.check()?;
             self.extend_row(rid * self.scale);
+            step();
         }
         // each column now has enough shares to extend
         for cid in 0..self.length {
+            cancel.check()?;
             self.extend_col(cid);
+            step();
         }
         // extend rows we originally did not have enough shares to extend
         for rid in 0..self.length {
             if rid % self.scale == 0 {
                 continue;
             }
+            cancel.check()?;
             self.extend_row(rid);
+            step();
         }
+        Ok(())
     }
 
     fn extend_row(&mut self, rid: usize) {
@@ -111,11 +538,24 @@ impl<F: PrimeField> RsSquare<F> {
     }
 
     pub fn row_poly(&self, rid: usize) -> DensePolynomial<F> {
+        if let Some(sparse) = &self.sparse {
+            let mut coeffs = vec![F::zero(); self.n_rows];
+            for (weight, poly) in self.sparse_weights(sparse, rid).iter().zip(&sparse.polys) {
+                for (coeff, term) in coeffs.iter_mut().zip(&poly.coeffs) {
+                    *coeff += *weight * term;
+                }
+            }
+            return DensePolynomial::from_coefficients_vec(coeffs);
+        }
         Evaluations::from_vec_and_domain(self.rows[rid].compressed_vals(), self.small_domain)
             .interpolate()
     }
 
     pub fn col_poly(&self, cid: usize) -> DensePolynomial<F> {
+        if let Some(sparse) = &self.sparse {
+            let col = self.sparse_data_col(sparse, cid);
+            return Evaluations::from_vec_and_domain(col, self.small_domain).interpolate();
+        }
         let mut col = vec![];
         for rid in 0..self.n_rows {
             col.push(self.rows[rid * self.scale].get_element_at(cid));
@@ -147,23 +587,23 @@ pub fn is_power_of_two(x: usize) -> bool {
     false
 }
 
+#[cfg(test)]
 mod tests {
+    use crate::fixtures::counting_square;
     use rs_line::RsLine;
 
     // Use BLS12_381 (pairing-friendly EC) for KZG
+    use crate::layout::blob_to_shares;
+    use crate::namespace::pad_to_square;
     use crate::rs_line;
     use crate::rs_square::RsSquare;
     use ark_test_curves::bls12_381::Bls12_381;
     use ark_test_curves::bls12_381::Fr;
+    use rs_merkle::algorithms::Sha256;
 
     #[test]
     pub fn basic_square() {
-        let shares = vec![
-            vec![Fr::from(0), Fr::from(1), Fr::from(2), Fr::from(3)],
-            vec![Fr::from(4), Fr::from(5), Fr::from(6), Fr::from(7)],
-            vec![Fr::from(8), Fr::from(9), Fr::from(10), Fr::from(11)],
-            vec![Fr::from(12), Fr::from(13), Fr::from(14), Fr::from(15)],
-        ];
+        let shares = counting_square(4);
 
         let scale = 4;
         let lines: Vec<RsLine<_>> = shares
@@ -174,6 +614,12 @@ mod tests {
 
         let mut square = RsSquare::new(lines.as_slice(), scale);
         square.extend();
+        assert_eq!(
+            (square.n_rows(), square.scale(), square.length()),
+            (4, 4, 16)
+        );
+        assert_eq!(square.log2_length(), 4);
+        assert_eq!(square.layout().length(), square.length());
 
         // square should now be 4*4 x 4*4 and should contain original entries at (x,y) coords
         // with x and y divisible by 4.
@@ -183,4 +629,131 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    pub fn sparse_square_matches_dense() {
+        let data_rows = vec![vec![Fr::from(3), Fr::from(1), Fr::from(4), Fr::from(1)]];
+        let mut shares = data_rows.clone();
+        shares.resize(4, vec![Fr::from(0); 4]);
+
+        let scale = 2;
+        let lines: Vec<RsLine<_>> = shares
+            .iter()
+            .map(|share| RsLine::new(share, scale))
+            .collect();
+        let mut dense = RsSquare::new(lines.as_slice(), scale);
+        dense.extend();
+        let sparse = RsSquare::sparse(&data_rows, 4, scale);
+
+        assert!(sparse.is_sparse());
+        assert_eq!(sparse.original_shares(), shares);
+        assert_eq!(sparse, dense);
+        assert_eq!(
+            sparse.content_digest::<Sha256>(),
+            dense.content_digest::<Sha256>()
+        );
+        for idx in 0..8 {
+            assert_eq!(sparse.row_vals(idx), dense.row_vals(idx));
+            assert_eq!(sparse.col_vals(idx), dense.col_vals(idx));
+            assert_eq!(sparse.row_poly(idx), dense.row_poly(idx));
+            assert_eq!(sparse.col_poly(idx), dense.col_poly(idx));
+            assert_eq!(sparse.val_at(idx, 7 - idx), dense.val_at(idx, 7 - idx));
+        }
+    }
+
+    #[test]
+    pub fn original_data_comes_back_without_parity_or_padding() {
+        let data: Vec<u8> = (0..100u8).collect();
+        let shares = pad_to_square(&blob_to_shares::<Fr>(&data), 4);
+        let lines: Vec<RsLine<_>> = shares.iter().map(|share| RsLine::new(share, 2)).collect();
+        let mut square = RsSquare::new(lines.as_slice(), 2);
+        square.extend();
+
+        assert_eq!(square.to_original_bytes(data.len()), data);
+        assert_eq!(square.into_original_shares(), shares);
+        let sparse = RsSquare::sparse(&shares[..2], 4, 2);
+        assert_eq!(sparse.to_original_bytes(data.len()), data);
+        assert_eq!(sparse.into_original_shares(), shares);
+    }
+
+    #[test]
+    pub fn appended_rows_match_a_square_extended_at_once() {
+        let shares: Vec<Vec<Fr>> = (0..4u64)
+            .map(|rid| (0..4u64).map(|cid| Fr::from(rid * 7 + cid * cid)).collect())
+            .collect();
+        let scale = 2;
+        let extended = |shares: &[Vec<Fr>]| {
+            let lines: Vec<RsLine<_>> = shares
+                .iter()
+                .map(|share| RsLine::new(share, scale))
+                .collect();
+            let mut square = RsSquare::new(lines.as_slice(), scale);
+            square.extend();
+            square
+        };
+
+        let mut appendable = RsSquare::appendable(4, scale);
+        assert_eq!(appendable.append_row(&shares[0]), 0);
+        assert_eq!(appendable.append_row(&shares[1]), 1);
+        assert_eq!(appendable.appended_rows(), Some(2));
+        // a partly filled square is the square padded with zero rows
+        let mut padded = shares[..2].to_vec();
+        padded.resize(4, vec![Fr::from(0); 4]);
+        let partial = extended(&padded);
+        for idx in 0..8 {
+            assert_eq!(appendable.row_vals(idx), partial.row_vals(idx));
+            assert_eq!(appendable.col_vals(idx), partial.col_vals(idx));
+        }
+
+        appendable.append_row(&shares[2]);
+        appendable.append_row(&shares[3]);
+        let full = extended(&shares);
+        assert_eq!(appendable.original_shares(), shares);
+        assert_eq!(appendable, full);
+        assert_ne!(appendable, partial);
+        assert_ne!(
+            appendable.content_digest::<Sha256>(),
+            partial.content_digest::<Sha256>()
+        );
+        for idx in 0..8 {
+            assert_eq!(appendable.row_vals(idx), full.row_vals(idx));
+            assert_eq!(appendable.col_poly(idx), full.col_poly(idx));
+        }
+        assert_eq!(full.appended_rows(), None);
+    }
+
+    #[test]
+    pub fn debug_summarizes_and_dump_prints_every_row() {
+        let square = |n_rows: u64| {
+            let shares: Vec<Vec<Fr>> = (0..n_rows)
+                .map(|rid| {
+                    (0..n_rows)
+                        .map(|cid| Fr::from(rid * n_rows + cid))
+                        .collect()
+                })
+                .collect();
+            let lines: Vec<RsLine<_>> = shares.iter().map(|share| RsLine::new(share, 2)).collect();
+            let mut square = RsSquare::new(lines.as_slice(), 2);
+            square.extend();
+            square
+        };
+        let (small, large) = (square(2), square(32));
+
+        let summary = format!("{:?}", large);
+        assert!(summary.contains("length: 64"));
+        assert!(summary.contains(&format!("corners: [{:?}, ", Fr::from(0))));
+        let digest = large.content_digest::<Sha256>();
+        assert!(summary.contains(&format!("data_digest: {:02x}{:02x}", digest[0], digest[1])));
+        // the summary does not grow with the square
+        assert_eq!(summary.lines().count(), 1);
+        assert!(summary.len() < 4 * format!("{:?}", small).len());
+        assert_ne!(format!("{:?}", small), format!("{:?}", square(4)));
+
+        let dump = large.dump().to_string();
+        assert_eq!(dump.lines().count(), 64);
+        assert_eq!(
+            dump.lines().next().unwrap(),
+            format!("{:?}", large.row_vals(0))
+        );
+    }
 }
@@ -0,0 +1,331 @@
+use crate::codec::{
+    commitment_from_bytes, commitment_to_bytes, field_from_bytes, field_to_bytes, proof_from_bytes,
+    put_bytes, put_u64, take_bytes, take_hash, take_u64,
+};
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::{Commitment, Proof};
+use ark_serialize::{CanonicalSerialize, SerializationError};
+use rs_merkle::{Hasher, MerkleProof};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LineAxis {
+    Row,
+    Col,
+}
+
+/// Domain separating the Fiat-Shamir transcripts of proofs from other hashes
+pub const CHALLENGE_DOMAIN: &[u8] = b"lazy-lion/challenge/v1";
+
+/// What the Fiat-Shamir challenges of line, row and range proofs absorb before
+/// the proof's own commitments and values: the block the proof was made for and
+/// the line it opens. A proof replayed at another height, under another data root
+/// or for another line derives other challenges and fails to verify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChallengeContext<'a> {
+    pub height: u64,
+    pub data_root: &'a [u8],
+    pub axis: LineAxis,
+    pub idx: usize,
+}
+
+impl ChallengeContext<'_> {
+    /// `CHALLENGE_DOMAIN`, the height, the data root, the axis as 0 for rows and 1
+    /// for columns, and the index, each prefixed by its length (see `put_bytes`)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let axis = match self.axis {
+            LineAxis::Row => 0u8,
+            LineAxis::Col => 1u8,
+        };
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, CHALLENGE_DOMAIN);
+        put_u64(&mut bytes, self.height);
+        put_bytes(&mut bytes, self.data_root);
+        put_bytes(&mut bytes, &[axis]);
+        put_u64(&mut bytes, self.idx as u64);
+        bytes
+    }
+}
+
+/// Everything a light node needs to check a single cell against a data root
+pub struct CellSample<E: Pairing, H: Hasher> {
+    pub rid: usize,
+    pub cid: usize,
+    /// Claimed value of the square at (rid, cid)
+    pub value: E::ScalarField,
+    /// KZG commitment to the polynomial of row rid
+    pub row_commitment: Commitment<E>,
+    /// KZG opening of the row polynomial at the point of column cid
+    pub opening: Proof<E>,
+    /// Merkle path of the row commitment under the row root
+    pub row_path: MerkleProof<H>,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(CellSample<E: Pairing, H: Hasher>);
+
+impl<E: Pairing, H: Hasher> CellSample<E, H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut opening = vec![];
+        self.opening
+            .serialize_compressed(&mut opening)
+            .expect("Serializing opening proof should not fail");
+
+        let row_root: Vec<u8> = self.row_root.into();
+        let col_root: Vec<u8> = self.col_root.into();
+
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, &(self.rid as u64).to_le_bytes());
+        put_bytes(&mut bytes, &(self.cid as u64).to_le_bytes());
+        put_bytes(&mut bytes, &field_to_bytes(&self.value));
+        put_bytes(&mut bytes, &commitment_to_bytes(&self.row_commitment));
+        put_bytes(&mut bytes, &opening);
+        put_bytes(&mut bytes, &self.row_path.to_bytes());
+        put_bytes(&mut bytes, &row_root);
+        put_bytes(&mut bytes, &col_root);
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let rid = take_u64(&mut bytes)? as usize;
+        let cid = take_u64(&mut bytes)? as usize;
+        let value = field_from_bytes(take_bytes(&mut bytes)?)?;
+        let row_commitment = commitment_from_bytes(take_bytes(&mut bytes)?)?;
+        let opening = proof_from_bytes(take_bytes(&mut bytes)?)?;
+        let row_path = MerkleProof::<H>::from_bytes(take_bytes(&mut bytes)?)
+            .map_err(|_| SerializationError::InvalidData)?;
+        let row_root = take_hash::<H>(&mut bytes)?;
+        let col_root = take_hash::<H>(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(Self {
+            rid,
+            cid,
+            value,
+            row_commitment,
+            opening,
+            row_path,
+            row_root,
+            col_root,
+        })
+    }
+}
+
+/// A whole row or column with one proof covering every value: the line commitment
+/// opened at a point derived from the commitment and the values themselves.
+pub struct LineSample<E: Pairing, H: Hasher> {
+    pub axis: LineAxis,
+    pub idx: usize,
+    /// Height of the block the sample was made for, bound into its challenge
+    pub height: u64,
+    /// Claimed values of every cell of the line
+    pub values: Vec<E::ScalarField>,
+    /// KZG commitment to the line polynomial
+    pub commitment: Commitment<E>,
+    /// KZG opening of the line polynomial at the line challenge
+    pub opening: Proof<E>,
+    /// Merkle path of the commitment under the row or column root
+    pub path: MerkleProof<H>,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(LineSample<E: Pairing, H: Hasher>);
+
+impl<E: Pairing, H: Hasher> LineSample<E, H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut opening = vec![];
+        self.opening
+            .serialize_compressed(&mut opening)
+            .expect("Serializing opening proof should not fail");
+
+        let row_root: Vec<u8> = self.row_root.into();
+        let col_root: Vec<u8> = self.col_root.into();
+        let axis = match self.axis {
+            LineAxis::Row => 0u8,
+            LineAxis::Col => 1u8,
+        };
+
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, &[axis]);
+        put_bytes(&mut bytes, &(self.idx as u64).to_le_bytes());
+        put_u64(&mut bytes, self.height);
+        put_bytes(&mut bytes, &(self.values.len() as u64).to_le_bytes());
+        for value in &self.values {
+            put_bytes(&mut bytes, &field_to_bytes(value));
+        }
+        put_bytes(&mut bytes, &commitment_to_bytes(&self.commitment));
+        put_bytes(&mut bytes, &opening);
+        put_bytes(&mut bytes, &self.path.to_bytes());
+        put_bytes(&mut bytes, &row_root);
+        put_bytes(&mut bytes, &col_root);
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let axis = match take_bytes(&mut bytes)? {
+            [0] => LineAxis::Row,
+            [1] => LineAxis::Col,
+            _ => return Err(SerializationError::InvalidData),
+        };
+        let idx = take_u64(&mut bytes)? as usize;
+        let height = take_u64(&mut bytes)?;
+        let len = take_u64(&mut bytes)? as usize;
+        // every value takes at least its 4-byte length prefix
+        if len > bytes.len() / 4 {
+            return Err(SerializationError::InvalidData);
+        }
+        let values = (0..len)
+            .map(|_| field_from_bytes(take_bytes(&mut bytes)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let commitment = commitment_from_bytes(take_bytes(&mut bytes)?)?;
+        let opening = proof_from_bytes(take_bytes(&mut bytes)?)?;
+        let path = MerkleProof::<H>::from_bytes(take_bytes(&mut bytes)?)
+            .map_err(|_| SerializationError::InvalidData)?;
+        let row_root = take_hash::<H>(&mut bytes)?;
+        let col_root = take_hash::<H>(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(Self {
+            axis,
+            idx,
+            height,
+            values,
+            commitment,
+            opening,
+            path,
+            row_root,
+            col_root,
+        })
+    }
+}
+
+/// Proof that the holder has row rid: the row commitment, its Merkle path under the
+/// row root, and one opening vouching for every value. The unit a partial node
+/// answers with to prove custody of its assigned rows.
+pub struct RowProof<E: Pairing, H: Hasher> {
+    pub rid: usize,
+    /// Height of the block the proof was made for, bound into its challenge with
+    /// the data root the row root is checked under
+    pub height: u64,
+    /// Every value of the row
+    pub values: Vec<E::ScalarField>,
+    /// KZG commitment to the row polynomial
+    pub commitment: Commitment<E>,
+    /// Merkle path of the commitment under the row root
+    pub path: MerkleProof<H>,
+    /// KZG opening of the row polynomial at the line challenge
+    pub opening: Proof<E>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(RowProof<E: Pairing, H: Hasher>);
+
+impl<E: Pairing, H: Hasher> RowProof<E, H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut opening = vec![];
+        self.opening
+            .serialize_compressed(&mut opening)
+            .expect("Serializing opening proof should not fail");
+
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, &(self.rid as u64).to_le_bytes());
+        put_u64(&mut bytes, self.height);
+        put_bytes(&mut bytes, &(self.values.len() as u64).to_le_bytes());
+        for value in &self.values {
+            put_bytes(&mut bytes, &field_to_bytes(value));
+        }
+        put_bytes(&mut bytes, &commitment_to_bytes(&self.commitment));
+        put_bytes(&mut bytes, &self.path.to_bytes());
+        put_bytes(&mut bytes, &opening);
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let rid = take_u64(&mut bytes)? as usize;
+        let height = take_u64(&mut bytes)?;
+        let len = take_u64(&mut bytes)? as usize;
+        if len > bytes.len() / 4 {
+            return Err(SerializationError::InvalidData);
+        }
+        let values = (0..len)
+            .map(|_| field_from_bytes(take_bytes(&mut bytes)?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let commitment = commitment_from_bytes(take_bytes(&mut bytes)?)?;
+        let path = MerkleProof::<H>::from_bytes(take_bytes(&mut bytes)?)
+            .map_err(|_| SerializationError::InvalidData)?;
+        let opening = proof_from_bytes(take_bytes(&mut bytes)?)?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(Self {
+            rid,
+            height,
+            values,
+            commitment,
+            path,
+            opening,
+        })
+    }
+}
+
+/// Proof that a row polynomial takes claimed values at a span of cells, of constant
+/// size however long the span: a commitment to the quotient of the row polynomial
+/// by the span's vanishing polynomial, the values of both at the range challenge,
+/// and one opening of their combination there. It carries no context of its own:
+/// the range challenge is bound to the height and data root of the proof holding
+/// it, e.g. a `ShareProof`, and to the row.
+pub struct RangeProof<E: Pairing> {
+    pub quotient: Commitment<E>,
+    /// Value of the row polynomial at the range challenge
+    pub row_value: E::ScalarField,
+    /// Value of the quotient polynomial at the range challenge
+    pub quotient_value: E::ScalarField,
+    /// KZG opening of the weighted sum of both polynomials at the range challenge
+    pub opening: Proof<E>,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(RangeProof<E: Pairing>);
+
+impl<E: Pairing> RangeProof<E> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut opening = vec![];
+        self.opening
+            .serialize_compressed(&mut opening)
+            .expect("Serializing opening proof should not fail");
+
+        let mut bytes = vec![];
+        put_bytes(&mut bytes, &commitment_to_bytes(&self.quotient));
+        put_bytes(&mut bytes, &field_to_bytes(&self.row_value));
+        put_bytes(&mut bytes, &field_to_bytes(&self.quotient_value));
+        put_bytes(&mut bytes, &opening);
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let quotient = commitment_from_bytes(take_bytes(&mut bytes)?)?;
+        let row_value = field_from_bytes(take_bytes(&mut bytes)?)?;
+        let quotient_value = field_from_bytes(take_bytes(&mut bytes)?)?;
+        let opening = proof_from_bytes(take_bytes(&mut bytes)?)?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+
+        Ok(Self {
+            quotient,
+            row_value,
+            quotient_value,
+            opening,
+        })
+    }
+}
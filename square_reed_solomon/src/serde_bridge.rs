@@ -0,0 +1,164 @@
+//! `serde` support, behind the `serde` feature. Types with a canonical byte
+//! encoding (samples, proofs, trees) serialize as a byte string of it, so JSON or
+//! TOML carry exactly what the wire does and decoding checks the same things.
+//! Plain data types derive their fields.
+
+use crate::codec::{decode, Validation};
+
+use ark_serialize::SerializationError;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt;
+
+/// Serialize `bytes` as a byte string
+pub fn serialize_bytes<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(bytes)
+}
+
+/// Deserialize a byte string, or the sequence of bytes formats without byte
+/// strings write instead, and decode it with `decode`
+pub fn deserialize_bytes<'de, D: Deserializer<'de>, T>(
+    deserializer: D,
+    decode: impl FnOnce(&[u8]) -> Result<T, SerializationError>,
+) -> Result<T, D::Error> {
+    let bytes = deserializer.deserialize_byte_buf(BytesVisitor)?;
+    decode(&bytes).map_err(de::Error::custom)
+}
+
+/// For `#[serde(with = "...")]` on fields of arkworks types, serialized as their
+/// compressed encoding and decoded strictly
+pub mod canonical {
+    use super::{decode, deserialize_bytes, serialize_bytes, Validation};
+
+    use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<T: CanonicalSerialize, S: Serializer>(
+        val: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut bytes = vec![];
+        val.serialize_compressed(&mut bytes)
+            .expect("Serializing into a vector cannot fail");
+        serialize_bytes(&bytes, serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: CanonicalSerialize + CanonicalDeserialize,
+        D: Deserializer<'de>,
+    {
+        deserialize_bytes(deserializer, |bytes| decode(bytes, Validation::Strict))
+    }
+}
+
+/// For `#[serde(with = "...")]` on fields of a hasher's digests, serialized as
+/// their bytes
+pub mod hash {
+    use super::{deserialize_bytes, serialize_bytes};
+
+    use ark_serialize::SerializationError;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<T: Clone + Into<Vec<u8>>, S: Serializer>(
+        hash: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serialize_bytes(&hash.clone().into(), serializer)
+    }
+
+    pub fn deserialize<'de, T: TryFrom<Vec<u8>>, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        deserialize_bytes(deserializer, |bytes| {
+            T::try_from(bytes.to_vec()).map_err(|_| SerializationError::InvalidData)
+        })
+    }
+}
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte string")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+/// Implement `Serialize` and `Deserialize` for a type through its `to_bytes` and
+/// `from_bytes`
+macro_rules! bytes_serde {
+    ($ty:ident $(<$($param:ident: $bound:path),*>)?) => {
+        impl$(<$($param: $bound),*>)? serde::Serialize for $ty$(<$($param),*>)? {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                $crate::serde_bridge::serialize_bytes(&self.to_bytes(), serializer)
+            }
+        }
+
+        impl<'de $($(, $param: $bound)*)?> serde::Deserialize<'de> for $ty$(<$($param),*>)? {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                $crate::serde_bridge::deserialize_bytes(deserializer, |bytes| {
+                    Self::from_bytes(bytes)
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use bytes_serde;
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::counting_square;
+    use crate::prover::RsSquareProver;
+    use crate::rs_square::RsSquare;
+    use crate::sample::CellSample;
+    use crate::verifier::{verifier_key, verify_cell_sample};
+
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn squares_and_samples_survive_json() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+
+        let json = serde_json::to_string(prover.square()).unwrap();
+        let square: RsSquare<Fr> = serde_json::from_str(&json).unwrap();
+        assert_eq!(&square, prover.square());
+        assert_eq!(square.val_at(3, 3), prover.square().val_at(3, 3));
+        // shares a row short do not form a square
+        let mut short: serde_json::Value = serde_json::from_str(&json).unwrap();
+        short["shares"].as_array_mut().unwrap().pop();
+        assert!(serde_json::from_value::<RsSquare<Fr>>(short).is_err());
+
+        let json = serde_json::to_string(&prover.sample_cell(3, 1)).unwrap();
+        let sample: CellSample<Bls12_381, Sha256> = serde_json::from_str(&json).unwrap();
+        let vk = verifier_key(prover.params());
+        assert!(verify_cell_sample(
+            &vk,
+            prover.root_version(),
+            prover.root(),
+            4,
+            &sample
+        ));
+        assert!(serde_json::from_str::<CellSample<Bls12_381, Sha256>>("[1,2,3]").is_err());
+    }
+}
@@ -0,0 +1,433 @@
+//! Trusted setup ceremony. Each participant draws a secret `s`, multiplies every
+//! power of the setup's trapdoor by the matching power of `s`, and forgets it. The
+//! updated setup is as sound as its trapdoor is secret, which holds as long as a
+//! single participant forgot theirs. Alongside the new setup the participant emits
+//! `s` in G2, from which anyone checks that the update built on the previous setup
+//! and left a well-formed one, with a Schnorr proof that they know `s`. Without it
+//! a participant could publish `s` in G2 as a multiple of a negative power of h,
+//! replacing the trapdoor with one of their own choosing.
+
+use crate::codec::{decode, Validation};
+
+use ark_ec::pairing::Pairing;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{Field, One, PrimeField, UniformRand, Zero};
+use ark_poly_commit::kzg10::UniversalParams;
+use ark_serialize::{CanonicalSerialize, SerializationError};
+use rand::RngCore;
+use rs_merkle::{algorithms::Sha256, Hasher};
+
+/// Prefix of the hash the knowledge proof's challenge is drawn from
+const CONTRIBUTION_DOMAIN: &[u8] = b"lazy-lion/setup/contribution/v1";
+
+/// How a setup fails to be well-formed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetupError {
+    /// Fewer than two powers of g, so nothing can be committed
+    TooShort,
+    /// A generator is the identity, or the trapdoor is 0 or 1
+    Degenerate,
+    /// Some power of g is not the previous one times the trapdoor in beta_h
+    InconsistentPowers,
+    /// Same for the powers of gamma_g
+    InconsistentGammaPowers,
+    /// Some negative power of h does not invert the matching power of g
+    InconsistentNegativePowers,
+}
+
+/// What a participant publishes next to their updated setup
+pub struct ContributionProof<E: Pairing> {
+    /// The participant's secret times the G2 generator
+    pub secret_h: E::G2Affine,
+    /// Nonce times the G2 generator, committed to before the challenge
+    pub commitment: E::G2Affine,
+    /// Nonce plus the challenge times the secret, the challenge binding the setups
+    /// before and after the update
+    pub response: E::ScalarField,
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(ContributionProof<E: Pairing>);
+
+impl<E: Pairing> ContributionProof<E> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        (self.secret_h, self.commitment, self.response)
+            .serialize_compressed(&mut bytes)
+            .expect("Serializing into a vector cannot fail");
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        let (secret_h, commitment, response) = decode(bytes, Validation::Strict)?;
+        Ok(Self {
+            secret_h,
+            commitment,
+            response,
+        })
+    }
+}
+
+/// Re-randomize `params` with a fresh secret drawn from `rng`, which is dropped
+/// before returning
+pub fn contribute<E: Pairing, R: RngCore>(
+    params: &UniversalParams<E>,
+    rng: &mut R,
+) -> (UniversalParams<E>, ContributionProof<E>) {
+    let mut secret = E::ScalarField::rand(rng);
+    while secret.is_zero() {
+        secret = E::ScalarField::rand(rng);
+    }
+    let inverse = secret.inverse().expect("Secret is nonzero");
+
+    let powers_of_g = scale_powers::<E::G1Affine>(&params.powers_of_g, secret);
+    let gamma_g: Vec<E::G1Affine> = params.powers_of_gamma_g.values().copied().collect();
+    let powers_of_gamma_g = params
+        .powers_of_gamma_g
+        .keys()
+        .copied()
+        .zip(scale_powers::<E::G1Affine>(&gamma_g, secret))
+        .collect();
+    let neg_h: Vec<E::G2Affine> = params.neg_powers_of_h.values().copied().collect();
+    let neg_powers_of_h = params
+        .neg_powers_of_h
+        .keys()
+        .copied()
+        .zip(scale_powers::<E::G2Affine>(&neg_h, inverse))
+        .collect();
+    let beta_h = (params.beta_h * secret).into_affine();
+
+    let updated = UniversalParams {
+        powers_of_g,
+        powers_of_gamma_g,
+        h: params.h,
+        beta_h,
+        neg_powers_of_h,
+        prepared_h: params.h.into(),
+        prepared_beta_h: beta_h.into(),
+    };
+    let secret_h = (params.h * secret).into_affine();
+    let nonce = E::ScalarField::rand(rng);
+    let commitment = (params.h * nonce).into_affine();
+    let challenge = knowledge_challenge(params, &updated, &secret_h, &commitment);
+    let proof = ContributionProof {
+        secret_h,
+        commitment,
+        response: nonce + challenge * secret,
+    };
+    (updated, proof)
+}
+
+/// Check that `params` is a well-formed setup, e.g. before serving squares under
+/// one read from disk. `rng` draws the weights folding the per-power pairing checks
+/// into a few pairings.
+pub fn check_setup<E: Pairing, R: RngCore>(
+    params: &UniversalParams<E>,
+    rng: &mut R,
+) -> Result<(), SetupError> {
+    if params.powers_of_g.len() < 2 {
+        return Err(SetupError::TooShort);
+    }
+    let (g, h) = (params.powers_of_g[0], params.h);
+    let gamma_g: Vec<E::G1Affine> = params.powers_of_gamma_g.values().copied().collect();
+    let degenerate = g.is_zero()
+        || h.is_zero()
+        || params.beta_h.is_zero()
+        || params.beta_h == h
+        || gamma_g.first().map_or(true, |gamma_g| gamma_g.is_zero());
+    if degenerate {
+        return Err(SetupError::Degenerate);
+    }
+
+    // every G1 power is the previous one times the trapdoor in beta_h
+    let mut successive = |powers: &[E::G1Affine]| {
+        if powers.len() < 2 {
+            return true;
+        }
+        let weights: Vec<E::ScalarField> = (1..powers.len())
+            .map(|_| E::ScalarField::rand(rng))
+            .collect();
+        let lower = combine::<E::G1Affine>(&powers[..powers.len() - 1], &weights);
+        let upper = combine::<E::G1Affine>(&powers[1..], &weights);
+        pairings_cancel::<E>(&[(upper, h), (-lower, params.beta_h)])
+    };
+    if !successive(params.powers_of_g.as_slice()) {
+        return Err(SetupError::InconsistentPowers);
+    }
+    let contiguous = params
+        .powers_of_gamma_g
+        .keys()
+        .copied()
+        .eq(0..gamma_g.len());
+    if !contiguous || !successive(gamma_g.as_slice()) {
+        return Err(SetupError::InconsistentGammaPowers);
+    }
+
+    // every negative G2 power inverts the matching G1 power
+    let mut pairs: Vec<(E::G1, E::G2Affine)> = vec![];
+    let mut total = E::ScalarField::zero();
+    for (&i, &power) in &params.neg_powers_of_h {
+        let g_i = match params.powers_of_g.get(i) {
+            Some(g_i) => *g_i,
+            None => return Err(SetupError::InconsistentNegativePowers),
+        };
+        let weight = E::ScalarField::rand(rng);
+        total += weight;
+        pairs.push((g_i * weight, power));
+    }
+    pairs.push((g * -total, h));
+    if !pairings_cancel::<E>(&pairs) {
+        return Err(SetupError::InconsistentNegativePowers);
+    }
+    Ok(())
+}
+
+/// Check that `after` is `before` updated by the secret `proof` commits to, and is
+/// itself a well-formed setup
+pub fn verify_contribution<E: Pairing, R: RngCore>(
+    before: &UniversalParams<E>,
+    after: &UniversalParams<E>,
+    proof: &ContributionProof<E>,
+    rng: &mut R,
+) -> bool {
+    // generators are fixed and every list keeps its length
+    let shape_kept = after.powers_of_g.len() == before.powers_of_g.len()
+        && after.powers_of_g.len() >= 2
+        && after.powers_of_g[0] == before.powers_of_g[0]
+        && after.h == before.h
+        && after
+            .powers_of_gamma_g
+            .keys()
+            .eq(before.powers_of_gamma_g.keys())
+        && after.powers_of_gamma_g.get(&0) == before.powers_of_gamma_g.get(&0)
+        && after
+            .neg_powers_of_h
+            .keys()
+            .eq(before.neg_powers_of_h.keys())
+        && after.neg_powers_of_h.get(&0) == before.neg_powers_of_h.get(&0);
+    if !shape_kept || proof.secret_h.is_zero() {
+        return false;
+    }
+    // the participant knows the secret, so it cannot be derived from earlier ones
+    let challenge = knowledge_challenge(before, after, &proof.secret_h, &proof.commitment);
+    if before.h * proof.response != proof.commitment.into_group() + proof.secret_h * challenge {
+        return false;
+    }
+    // the update multiplied the trapdoor by the committed secret
+    let builds_on_before = pairings_cancel::<E>(&[
+        (after.powers_of_g[1].into_group(), after.h),
+        (-before.powers_of_g[1].into_group(), proof.secret_h),
+    ]);
+    builds_on_before && check_setup(after, rng).is_ok()
+}
+
+/// Challenge of the proof of knowledge of the secret behind `secret_h`, bound to
+/// both setups so that a proof vouches for one update only
+fn knowledge_challenge<E: Pairing>(
+    before: &UniversalParams<E>,
+    after: &UniversalParams<E>,
+    secret_h: &E::G2Affine,
+    commitment: &E::G2Affine,
+) -> E::ScalarField {
+    let mut bytes = CONTRIBUTION_DOMAIN.to_vec();
+    for params in [before, after] {
+        let mut encoded = vec![];
+        params
+            .serialize_compressed(&mut encoded)
+            .expect("Serializing into a vector cannot fail");
+        bytes.extend(Sha256::hash(&encoded));
+    }
+    for point in [secret_h, commitment] {
+        point
+            .serialize_compressed(&mut bytes)
+            .expect("Serializing into a vector cannot fail");
+    }
+    E::ScalarField::from_le_bytes_mod_order(&Sha256::hash(&bytes))
+}
+
+/// `powers[i]` times `scalar^i`
+fn scale_powers<A: AffineRepr>(powers: &[A], scalar: A::ScalarField) -> Vec<A> {
+    let mut factor = A::ScalarField::one();
+    let scaled: Vec<A::Group> = powers
+        .iter()
+        .map(|power| {
+            let scaled = *power * factor;
+            factor *= scalar;
+            scaled
+        })
+        .collect();
+    A::Group::normalize_batch(&scaled)
+}
+
+fn combine<A: AffineRepr>(points: &[A], weights: &[A::ScalarField]) -> A::Group {
+    points
+        .iter()
+        .zip(weights)
+        .map(|(point, weight)| *point * weight)
+        .sum()
+}
+
+/// Whether the product of the pairings of `pairs` is the identity
+fn pairings_cancel<E: Pairing>(pairs: &[(E::G1, E::G2Affine)]) -> bool {
+    let miller = E::multi_miller_loop(
+        pairs.iter().map(|(a, _)| E::G1Prepared::from(*a)),
+        pairs.iter().map(|(_, b)| E::G2Prepared::from(*b)),
+    );
+    E::final_exponentiation(miller).map_or(false, |output| output.is_zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_setup, contribute, verify_contribution, ContributionProof, SetupError};
+    use crate::fixtures::counting_square;
+    use crate::prover::RsSquareProver;
+    use crate::storage::{prove_storage, verify_storage};
+    use crate::verifier::verifier_key;
+
+    use ark_ec::pairing::Pairing;
+    use ark_ec::CurveGroup;
+    use ark_ff::{Field, UniformRand};
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::{UniversalParams, KZG10};
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::OsRng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn contributions_chain_and_keep_the_setup_usable() {
+        let mut rng = OsRng::default();
+        let initial = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, true, &mut rng).unwrap();
+        let (first, first_proof) = contribute(&initial, &mut rng);
+        let (second, second_proof) = contribute(&first, &mut rng);
+        assert!(verify_contribution(
+            &initial,
+            &first,
+            &first_proof,
+            &mut rng
+        ));
+        assert!(verify_contribution(
+            &first,
+            &second,
+            &second_proof,
+            &mut rng
+        ));
+        // a proof only vouches for the update it was made with
+        assert!(!verify_contribution(
+            &initial,
+            &second,
+            &first_proof,
+            &mut rng
+        ));
+        let decoded = ContributionProof::<Bls12_381>::from_bytes(&second_proof.to_bytes()).unwrap();
+        assert_eq!(decoded.secret_h, second_proof.secret_h);
+        assert_eq!(decoded.commitment, second_proof.commitment);
+        assert_eq!(decoded.response, second_proof.response);
+        assert!(verify_contribution(&first, &second, &decoded, &mut rng));
+
+        // squares commit and open under the updated setup
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, 2, second);
+        let proof = prove_storage(&prover, 0, 1, &[0; 32], b"node").unwrap();
+        let vk = verifier_key(prover.params());
+        assert!(verify_storage(
+            &vk,
+            prover.root_version(),
+            prover.root(),
+            4,
+            &[0; 32],
+            b"node",
+            &proof
+        ));
+    }
+
+    #[test]
+    pub fn malformed_setups_are_rejected() {
+        let mut rng = OsRng::default();
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, true, &mut rng).unwrap();
+        assert_eq!(check_setup(&params, &mut rng), Ok(()));
+
+        let mut altered = params.clone();
+        altered.powers_of_g[3] = (altered.powers_of_g[3] + altered.powers_of_g[0]).into_affine();
+        assert_eq!(
+            check_setup(&altered, &mut rng),
+            Err(SetupError::InconsistentPowers)
+        );
+        let mut broken = params.clone();
+        let gamma_g = broken.powers_of_gamma_g[&0];
+        broken.powers_of_gamma_g.insert(2, gamma_g);
+        assert_eq!(
+            check_setup(&broken, &mut rng),
+            Err(SetupError::InconsistentGammaPowers)
+        );
+        let mut broken = params.clone();
+        broken.neg_powers_of_h.insert(2, broken.h);
+        assert_eq!(
+            check_setup(&broken, &mut rng),
+            Err(SetupError::InconsistentNegativePowers)
+        );
+        let mut broken = params.clone();
+        broken.beta_h = broken.h;
+        assert_eq!(check_setup(&broken, &mut rng), Err(SetupError::Degenerate));
+        broken.powers_of_g.truncate(1);
+        assert_eq!(check_setup(&broken, &mut rng), Err(SetupError::TooShort));
+
+        // a contribution cannot hide a malformed setup
+        let (after, proof) = contribute(&altered, &mut rng);
+        assert!(!verify_contribution(&altered, &after, &proof, &mut rng));
+    }
+
+    #[test]
+    pub fn updates_replacing_the_trapdoor_are_rejected() {
+        let mut rng = OsRng::default();
+        let before = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, true, &mut rng).unwrap();
+
+        // a fresh setup under a trapdoor the forger knows, passed off as an update
+        // by scaling the first negative power of h
+        let trapdoor = Fr::rand(&mut rng);
+        let inverse = trapdoor.inverse().unwrap();
+        let (g, h) = (before.powers_of_g[0], before.h);
+        let gamma_g = before.powers_of_gamma_g[&0];
+        let beta_h = (h * trapdoor).into_affine();
+        let forged = UniversalParams::<Bls12_381> {
+            powers_of_g: (0..before.powers_of_g.len())
+                .map(|i| (g * trapdoor.pow([i as u64])).into_affine())
+                .collect(),
+            powers_of_gamma_g: before
+                .powers_of_gamma_g
+                .keys()
+                .map(|&i| (i, (gamma_g * trapdoor.pow([i as u64])).into_affine()))
+                .collect(),
+            h,
+            beta_h,
+            neg_powers_of_h: before
+                .neg_powers_of_h
+                .keys()
+                .map(|&i| (i, (h * inverse.pow([i as u64])).into_affine()))
+                .collect(),
+            prepared_h: h.into(),
+            prepared_beta_h: beta_h.into(),
+        };
+        let secret_h = (before.neg_powers_of_h[&1] * trapdoor).into_affine();
+        assert_eq!(check_setup(&forged, &mut rng), Ok(()));
+        assert_eq!(
+            Bls12_381::pairing(forged.powers_of_g[1], h),
+            Bls12_381::pairing(before.powers_of_g[1], secret_h)
+        );
+
+        // without the secret behind secret_h no knowledge proof can be made for it
+        let nonce = Fr::rand(&mut rng);
+        let proof = ContributionProof {
+            secret_h,
+            commitment: (h * nonce).into_affine(),
+            response: nonce,
+        };
+        assert!(!verify_contribution(&before, &forged, &proof, &mut rng));
+        let (_, honest) = contribute(&before, &mut rng);
+        let replayed = ContributionProof {
+            secret_h,
+            commitment: honest.commitment,
+            response: honest.response,
+        };
+        assert!(!verify_contribution(&before, &forged, &replayed, &mut rng));
+    }
+}
@@ -0,0 +1,539 @@
+//! Squares over small fields committed with hashes alone, for deployments that need
+//! no pairings. Each line is committed by a Merkle tree over its values instead of a
+//! KZG commitment, and a FRI proof per line shows it is correctly extended. Field
+//! arithmetic fits in a machine word and commitments only hash, with no group
+//! operations; FRI draws its challenges from an extension of each field.
+
+use crate::codec::{
+    field_from_bytes, field_to_bytes, put_bytes, put_u64, take_bytes, take_hash, take_u64,
+};
+use crate::fri::{
+    commit_values, prove_low_degree, value_leaf, verify_low_degree, FriField, FriProof,
+};
+use crate::merkle::{MerkleProof, MerkleRoot, TaggedTree};
+use crate::rs_line::RsLine;
+use crate::rs_square::RsSquare;
+use crate::sample::LineAxis;
+use crate::verifier::{combine_line_roots, verify_root, RootVersion};
+
+use ark_ff::fields::{Fp2, Fp2Config, Fp4, Fp4Config, Fp64, MontBackend, MontConfig};
+use ark_ff::{MontFp, PrimeField};
+use ark_serialize::SerializationError;
+use rs_merkle::{Hasher, MerkleTree};
+
+#[derive(MontConfig)]
+#[modulus = "18446744069414584321"]
+#[generator = "7"]
+pub struct GoldilocksConfig;
+
+/// The field of order 2^64 - 2^32 + 1, with roots of unity of order up to 2^32
+pub type Goldilocks = Fp64<MontBackend<GoldilocksConfig, 1>>;
+
+pub struct GoldilocksExtConfig;
+
+impl Fp2Config for GoldilocksExtConfig {
+    type Fp = Goldilocks;
+
+    /// 7 generates the multiplicative group, so is not a square
+    const NONRESIDUE: Goldilocks = MontFp!("7");
+
+    const FROBENIUS_COEFF_FP2_C1: &'static [Goldilocks] =
+        &[MontFp!("1"), MontFp!("18446744069414584320")];
+}
+
+/// Goldilocks extended by a square root of 7, of order about 2^128
+pub type GoldilocksExt = Fp2<GoldilocksExtConfig>;
+
+impl FriField for Goldilocks {
+    type Challenge = GoldilocksExt;
+}
+
+#[derive(MontConfig)]
+#[modulus = "2013265921"]
+#[generator = "31"]
+pub struct BabyBearConfig;
+
+/// The field of order 15 * 2^27 + 1, with roots of unity of order up to 2^27
+pub type BabyBear = Fp64<MontBackend<BabyBearConfig, 1>>;
+
+pub struct BabyBearExt2Config;
+
+impl Fp2Config for BabyBearExt2Config {
+    type Fp = BabyBear;
+
+    /// 11 is not a square, and as the order is 1 mod 4 neither is `u`, the root
+    /// of 11 the quadratic extension adds
+    const NONRESIDUE: BabyBear = MontFp!("11");
+
+    const FROBENIUS_COEFF_FP2_C1: &'static [BabyBear] = &[MontFp!("1"), MontFp!("2013265920")];
+}
+
+pub struct BabyBearExtConfig;
+
+impl Fp4Config for BabyBearExtConfig {
+    type Fp2Config = BabyBearExt2Config;
+
+    const NONRESIDUE: Fp2<BabyBearExt2Config> =
+        Fp2::<BabyBearExt2Config>::new(MontFp!("0"), MontFp!("1"));
+
+    /// 11^((p^k - 1) / 4) for k in 0..4
+    const FROBENIUS_COEFF_FP4_C1: &'static [BabyBear] = &[
+        MontFp!("1"),
+        MontFp!("1728404513"),
+        MontFp!("2013265920"),
+        MontFp!("284861408"),
+    ];
+}
+
+/// BabyBear extended by a fourth root of 11, of order about 2^124
+pub type BabyBearExt = Fp4<BabyBearExtConfig>;
+
+impl FriField for BabyBear {
+    type Challenge = BabyBearExt;
+}
+
+/// Extended square over `F` committed with hashes of `H`
+pub struct HashSquareProver<F: FriField, H: Hasher> {
+    square: RsSquare<F>,
+    n: usize,
+    row_trees: Vec<MerkleTree<H>>,
+    col_trees: Vec<MerkleTree<H>>,
+    /// Tree over the roots of the row trees
+    row_roots: TaggedTree<H>,
+    /// Tree over the roots of the column trees
+    col_roots: TaggedTree<H>,
+    /// Rules the trees over the line roots and the data root are hashed under
+    root_version: RootVersion,
+}
+
+/// A cell with its paths up to the data root, through its row and its column
+pub struct HashCellSample<F: PrimeField, H: Hasher> {
+    pub rid: usize,
+    pub cid: usize,
+    /// Claimed value of the square at (rid, cid)
+    pub value: F,
+    /// Root of the tree over the values of row rid
+    pub line_root: H::Hash,
+    /// Path of the value under the line root
+    pub cell_path: MerkleProof,
+    /// Path of the line root under the row root
+    pub line_path: MerkleProof,
+    /// Root of the tree over the values of column cid
+    pub col_line_root: H::Hash,
+    /// Path of the value under the column's line root
+    pub col_cell_path: MerkleProof,
+    /// Path of the column's line root under the column root
+    pub col_line_path: MerkleProof,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+}
+
+/// Proof that a row or column of a hash-committed square is correctly extended
+pub struct LineDegreeProof<F: FriField, H: Hasher> {
+    pub axis: LineAxis,
+    pub idx: usize,
+    /// Root of the tree over the values of the line
+    pub line_root: H::Hash,
+    /// Path of the line root under the row or column root
+    pub line_path: MerkleProof,
+    pub row_root: H::Hash,
+    pub col_root: H::Hash,
+    pub fri: FriProof<F>,
+}
+
+impl<F: FriField, H: Hasher> HashSquareProver<F, H> {
+    /// Extend the `n` by `n` square of `shares` by `scale` and commit to every line
+    pub fn new(shares: &[Vec<F>], scale: usize) -> Self {
+        Self::with_root_version(shares, scale, RootVersion::LATEST)
+    }
+
+    /// `new`, hashing the trees over the line roots and the data root under
+    /// `root_version`
+    pub fn with_root_version(shares: &[Vec<F>], scale: usize, root_version: RootVersion) -> Self {
+        let lines: Vec<RsLine<F>> = shares
+            .iter()
+            .map(|share| RsLine::new(share, scale))
+            .collect();
+        let mut square = RsSquare::new(&lines, scale);
+        square.extend();
+        let length = shares.len() * scale;
+        let row_trees: Vec<MerkleTree<H>> = (0..length)
+            .map(|rid| commit_values::<F, H>(&square.row_vals(rid)))
+            .collect();
+        let col_trees: Vec<MerkleTree<H>> = (0..length)
+            .map(|cid| commit_values::<F, H>(&square.col_vals(cid)))
+            .collect();
+        let roots_tree = |axis, trees: &[MerkleTree<H>]| {
+            let roots: Vec<H::Hash> = trees.iter().map(line_root).collect();
+            TaggedTree::<H>::new(root_version.line_tag(axis), &roots)
+        };
+        Self {
+            n: shares.len(),
+            row_roots: roots_tree(LineAxis::Row, &row_trees),
+            col_roots: roots_tree(LineAxis::Col, &col_trees),
+            square,
+            row_trees,
+            col_trees,
+            root_version,
+        }
+    }
+
+    pub fn square(&self) -> &RsSquare<F> {
+        &self.square
+    }
+
+    /// Side of the extended square
+    pub fn length(&self) -> usize {
+        self.row_trees.len()
+    }
+
+    pub fn root_version(&self) -> RootVersion {
+        self.root_version
+    }
+
+    pub fn row_root(&self) -> H::Hash {
+        self.row_roots.root().expect("Squares are not empty")
+    }
+
+    pub fn col_root(&self) -> H::Hash {
+        self.col_roots.root().expect("Squares are not empty")
+    }
+
+    /// Data root over the row and column roots, as for KZG-committed squares
+    pub fn root(&self) -> H::Hash {
+        combine_line_roots::<H>(self.root_version, self.row_root(), self.col_root())
+    }
+
+    pub fn sample_cell(&self, rid: usize, cid: usize) -> HashCellSample<F, H> {
+        HashCellSample {
+            rid,
+            cid,
+            value: self.square.val_at(rid, cid),
+            line_root: line_root(&self.row_trees[rid]),
+            cell_path: prove_leaf(&self.row_trees[rid], cid),
+            line_path: prove_line_root(&self.row_roots, rid),
+            col_line_root: line_root(&self.col_trees[cid]),
+            col_cell_path: prove_leaf(&self.col_trees[cid], rid),
+            col_line_path: prove_line_root(&self.col_roots, cid),
+            row_root: self.row_root(),
+            col_root: self.col_root(),
+        }
+    }
+
+    /// FRI proof that line `idx` along `axis` is a codeword of the square's degree
+    pub fn prove_line(&self, axis: LineAxis, idx: usize) -> LineDegreeProof<F, H> {
+        let (values, tree, roots) = match axis {
+            LineAxis::Row => (
+                self.square.row_vals(idx),
+                &self.row_trees[idx],
+                &self.row_roots,
+            ),
+            LineAxis::Col => (
+                self.square.col_vals(idx),
+                &self.col_trees[idx],
+                &self.col_roots,
+            ),
+        };
+        LineDegreeProof {
+            axis,
+            idx,
+            line_root: line_root(tree),
+            line_path: prove_line_root(roots, idx),
+            row_root: self.row_root(),
+            col_root: self.col_root(),
+            fri: prove_low_degree::<F, H>(&values, self.n),
+        }
+    }
+}
+
+/// Check a sampled cell against a data root: the root covers the row and column
+/// roots, the cell's row and column sit under them and the value sits in both, so
+/// the column trees hold the values the row trees do. On its own this does not
+/// show the lines are correctly extended; `verify_line_degree` does, once per line
+/// sampled. `version` is the root version of the square.
+pub fn verify_hash_cell<F: PrimeField, H: Hasher>(
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    sample: &HashCellSample<F, H>,
+) -> bool {
+    let opens = |path: &MerkleProof, idx: usize, root: H::Hash, leaf: Vec<u8>| {
+        path.index == idx
+            && path.leaf_count == length
+            && path.verify::<H>(&MerkleRoot::from_hash::<H>(root), &leaf)
+    };
+    let opens_line = |axis, path: &MerkleProof, idx: usize, root: H::Hash, leaf: H::Hash| {
+        path.index == idx
+            && path.leaf_count == length
+            && path.verify_tagged::<H>(
+                version.line_tag(axis),
+                &MerkleRoot::from_hash::<H>(root),
+                &Into::<Vec<u8>>::into(leaf),
+            )
+    };
+    let value_leaf: Vec<u8> = value_leaf::<F, H>(&sample.value).into();
+    verify_root::<H>(version, root, sample.row_root, sample.col_root)
+        && opens(
+            &sample.cell_path,
+            sample.cid,
+            sample.line_root,
+            value_leaf.clone(),
+        )
+        && opens_line(
+            LineAxis::Row,
+            &sample.line_path,
+            sample.rid,
+            sample.row_root,
+            sample.line_root,
+        )
+        && opens(
+            &sample.col_cell_path,
+            sample.rid,
+            sample.col_line_root,
+            value_leaf,
+        )
+        && opens_line(
+            LineAxis::Col,
+            &sample.col_line_path,
+            sample.cid,
+            sample.col_root,
+            sample.col_line_root,
+        )
+}
+
+/// Check that a line of the square of side `length` under `root` and `version`,
+/// extended from `n` shares, is a codeword
+pub fn verify_line_degree<F: FriField, H: Hasher>(
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    n: usize,
+    proof: &LineDegreeProof<F, H>,
+) -> bool {
+    let axis_root = match proof.axis {
+        LineAxis::Row => proof.row_root,
+        LineAxis::Col => proof.col_root,
+    };
+    let line_leaf: Vec<u8> = proof.line_root.into();
+    verify_root::<H>(version, root, proof.row_root, proof.col_root)
+        && proof.line_path.index == proof.idx
+        && proof.line_path.leaf_count == length
+        && proof.line_path.verify_tagged::<H>(
+            version.line_tag(proof.axis),
+            &MerkleRoot::from_hash::<H>(axis_root),
+            &line_leaf,
+        )
+        && verify_low_degree::<F, H>(
+            &MerkleRoot::from_hash::<H>(proof.line_root),
+            length,
+            n,
+            &proof.fri,
+        )
+}
+
+#[cfg(feature = "serde")]
+crate::serde_bridge::bytes_serde!(HashCellSample<F: PrimeField, H: Hasher>);
+
+impl<F: PrimeField, H: Hasher> HashCellSample<F, H> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let line_root: Vec<u8> = self.line_root.into();
+        let col_line_root: Vec<u8> = self.col_line_root.into();
+        let row_root: Vec<u8> = self.row_root.into();
+        let col_root: Vec<u8> = self.col_root.into();
+
+        let mut bytes = vec![];
+        put_u64(&mut bytes, self.rid as u64);
+        put_u64(&mut bytes, self.cid as u64);
+        put_bytes(&mut bytes, &field_to_bytes(&self.value));
+        put_bytes(&mut bytes, &line_root);
+        put_bytes(&mut bytes, &self.cell_path.to_bytes());
+        put_bytes(&mut bytes, &self.line_path.to_bytes());
+        put_bytes(&mut bytes, &col_line_root);
+        put_bytes(&mut bytes, &self.col_cell_path.to_bytes());
+        put_bytes(&mut bytes, &self.col_line_path.to_bytes());
+        put_bytes(&mut bytes, &row_root);
+        put_bytes(&mut bytes, &col_root);
+        bytes
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, SerializationError> {
+        let rid = take_u64(&mut bytes)? as usize;
+        let cid = take_u64(&mut bytes)? as usize;
+        let value = field_from_bytes(take_bytes(&mut bytes)?)?;
+        let line_root = take_hash::<H>(&mut bytes)?;
+        let cell_path = MerkleProof::from_bytes(take_bytes(&mut bytes)?)?;
+        let line_path = MerkleProof::from_bytes(take_bytes(&mut bytes)?)?;
+        let col_line_root = take_hash::<H>(&mut bytes)?;
+        let col_cell_path = MerkleProof::from_bytes(take_bytes(&mut bytes)?)?;
+        let col_line_path = MerkleProof::from_bytes(take_bytes(&mut bytes)?)?;
+        let row_root = take_hash::<H>(&mut bytes)?;
+        let col_root = take_hash::<H>(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(SerializationError::InvalidData);
+        }
+        Ok(Self {
+            rid,
+            cid,
+            value,
+            line_root,
+            cell_path,
+            line_path,
+            col_line_root,
+            col_cell_path,
+            col_line_path,
+            row_root,
+            col_root,
+        })
+    }
+}
+
+fn line_root<H: Hasher>(tree: &MerkleTree<H>) -> H::Hash {
+    tree.root().expect("Lines are not empty")
+}
+
+fn prove_leaf<H: Hasher>(tree: &MerkleTree<H>, idx: usize) -> MerkleProof {
+    MerkleProof::from_rs_merkle(&tree.proof(&[idx]), idx, tree.leaves_len())
+}
+
+fn prove_line_root<H: Hasher>(tree: &TaggedTree<H>, idx: usize) -> MerkleProof {
+    MerkleProof::from_rs_merkle(&tree.proof(&[idx]), idx, tree.leaves_len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        verify_hash_cell, verify_line_degree, BabyBear, BabyBearExt, Goldilocks, GoldilocksExt,
+        HashCellSample, HashSquareProver,
+    };
+    use crate::fri::{
+        commit_values, fri_queries, prove_low_degree, verify_low_degree, FriProof,
+        FRI_SECURITY_BITS,
+    };
+    use crate::merkle::MerkleRoot;
+    use crate::sample::LineAxis;
+    use crate::verifier::RootVersion;
+
+    use ark_ff::fields::Fp2;
+    use ark_ff::{Field, PrimeField};
+    use rs_merkle::algorithms::Sha256;
+
+    fn shares<F: PrimeField>(n: usize) -> Vec<Vec<F>> {
+        (0..n)
+            .map(|rid| (0..n).map(|cid| F::from((rid * n + cid) as u64)).collect())
+            .collect()
+    }
+
+    #[test]
+    pub fn hash_committed_squares_sample_and_prove_their_lines() {
+        let prover = HashSquareProver::<Goldilocks, Sha256>::new(&shares(4), 2);
+        let (version, root) = (prover.root_version(), prover.root());
+        let sample = prover.sample_cell(5, 3);
+        assert!(verify_hash_cell(version, root, 8, &sample));
+        let decoded = HashCellSample::<Goldilocks, Sha256>::from_bytes(&sample.to_bytes());
+        assert!(verify_hash_cell(version, root, 8, &decoded.unwrap()));
+        let mut forged = prover.sample_cell(5, 3);
+        forged.value += Goldilocks::from(1u64);
+        assert!(!verify_hash_cell(version, root, 8, &forged));
+        // the value must also sit in the column the FRI proofs check
+        let mut forged = prover.sample_cell(5, 3);
+        let other = prover.sample_cell(5, 2);
+        forged.col_line_root = other.col_line_root;
+        forged.col_line_path = other.col_line_path;
+        assert!(!verify_hash_cell(version, root, 8, &forged));
+
+        for axis in [LineAxis::Row, LineAxis::Col] {
+            let proof = prover.prove_line(axis, 6);
+            assert!(verify_line_degree(version, root, 8, 4, &proof));
+            assert!(!verify_line_degree(version, [0; 32], 8, 4, &proof));
+            let decoded = FriProof::<Goldilocks>::from_bytes(&proof.fri.to_bytes()).unwrap();
+            assert_eq!(decoded, proof.fri);
+        }
+
+        let other = HashSquareProver::<BabyBear, Sha256>::new(&shares(2), 4);
+        let proof = other.prove_line(LineAxis::Row, 1);
+        assert!(verify_line_degree(version, other.root(), 8, 2, &proof));
+        assert_ne!(other.root(), root);
+
+        // the trees over the line roots follow the square's root version
+        let legacy = HashSquareProver::<Goldilocks, Sha256>::with_root_version(
+            &shares(4),
+            2,
+            RootVersion::V2,
+        );
+        let sample = legacy.sample_cell(5, 3);
+        assert!(verify_hash_cell(RootVersion::V2, legacy.root(), 8, &sample));
+        assert!(!verify_hash_cell(
+            RootVersion::V3,
+            legacy.root(),
+            8,
+            &sample
+        ));
+        let proof = legacy.prove_line(LineAxis::Col, 2);
+        assert!(verify_line_degree(
+            RootVersion::V2,
+            legacy.root(),
+            8,
+            4,
+            &proof
+        ));
+        assert!(!verify_line_degree(
+            RootVersion::V3,
+            legacy.root(),
+            8,
+            4,
+            &proof
+        ));
+    }
+
+    #[test]
+    pub fn badly_extended_lines_fail_fri() {
+        // values of a polynomial of degree 7
+        let values: Vec<Goldilocks> = (0..8u64)
+            .map(|idx| Goldilocks::from(idx * idx * idx))
+            .collect();
+        let root = MerkleRoot::from_hash::<Sha256>(
+            commit_values::<Goldilocks, Sha256>(&values).root().unwrap(),
+        );
+        let proof = prove_low_degree::<Goldilocks, Sha256>(&values, 4);
+        assert!(!verify_low_degree::<Goldilocks, Sha256>(
+            &root, 8, 4, &proof
+        ));
+        assert!(verify_low_degree::<Goldilocks, Sha256>(
+            &root,
+            8,
+            8,
+            &prove_low_degree::<Goldilocks, Sha256>(&values, 8)
+        ));
+
+        let constant = vec![Goldilocks::from(3u64); 4];
+        let root = MerkleRoot::from_hash::<Sha256>(
+            commit_values::<Goldilocks, Sha256>(&constant)
+                .root()
+                .unwrap(),
+        );
+        let proof = prove_low_degree::<Goldilocks, Sha256>(&constant, 1);
+        assert!(verify_low_degree::<Goldilocks, Sha256>(&root, 4, 1, &proof));
+    }
+
+    #[test]
+    pub fn fri_is_sized_for_its_security_target() {
+        // a query at rate one half is worth a bit, at rate one quarter two
+        assert_eq!(fri_queries(8, 4), FRI_SECURITY_BITS);
+        assert_eq!(fri_queries(8, 2), FRI_SECURITY_BITS / 2);
+        let values: Vec<BabyBear> = (0..8u64).map(BabyBear::from).collect();
+        let proof = prove_low_degree::<BabyBear, Sha256>(&values, 8);
+        assert_eq!(proof.queries.len(), FRI_SECURITY_BITS);
+
+        // challenges lie in extensions of about 128 bits, by roots of 7 and 11
+        assert_eq!(GoldilocksExt::extension_degree(), 2);
+        let root = GoldilocksExt::new(Goldilocks::from(0u64), Goldilocks::from(1u64));
+        assert_eq!(root.square(), GoldilocksExt::from(7u64));
+        assert_eq!(BabyBearExt::extension_degree(), 4);
+        let root = BabyBearExt::new(
+            Fp2::from(0u64),
+            Fp2::new(BabyBear::from(1u64), BabyBear::from(0u64)),
+        );
+        assert_eq!(root.pow([4]), BabyBearExt::from(11u64));
+        assert_eq!(root.inverse().unwrap() * root, BabyBearExt::from(1u64));
+    }
+}
@@ -0,0 +1,163 @@
+//! Time-stamped storage proofs. An epoch is anchored at a header newer than the
+//! square being proven, whose data root nobody knew before it was produced. The
+//! anchor seeds which cells of the square the storing node must open, so answering
+//! an epoch shows the node still held the square when the anchor appeared, and not
+//! only when it first committed to it. The node's identity is mixed in so that
+//! nodes cannot pass each other's proofs off as their own.
+
+use crate::audit::{audit_cells, verify_audit};
+use crate::codec::{put_bytes, put_u64};
+use crate::prover::RsSquareProver;
+use crate::sample::CellSample;
+use crate::verifier::RootVersion;
+
+use ark_ec::pairing::Pairing;
+use ark_poly_commit::kzg10::VerifierKey;
+use rs_merkle::Hasher;
+
+/// Cells opened for each square in every epoch
+pub const STORAGE_PROOF_CELLS: usize = 8;
+
+/// Height of the header anchoring the current epoch of `epoch` heights once the
+/// chain reached `tip`: the latest multiple of `epoch` not above it
+pub fn epoch_anchor(tip: u64, epoch: u64) -> u64 {
+    tip - tip % epoch.max(1)
+}
+
+/// Proof that `prover` held the square at `height` when the header at anchor
+/// height was produced
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(bound = "")
+)]
+pub struct StorageProof<E: Pairing, H: Hasher> {
+    pub height: u64,
+    pub anchor_height: u64,
+    /// Samples of the cells the anchor challenges, in order
+    pub samples: Vec<CellSample<E, H>>,
+}
+
+/// The cells of the square at `height`, of side `length`, that the anchor at
+/// `anchor_height` with data root `anchor_root` challenges the node `prover` to open
+pub fn storage_cells<H: Hasher>(
+    anchor_height: u64,
+    anchor_root: &[u8],
+    prover: &[u8],
+    height: u64,
+    length: usize,
+) -> Vec<(usize, usize)> {
+    let seed = storage_seed::<H>(anchor_height, anchor_root, prover, height);
+    audit_cells::<H>(&seed, length, STORAGE_PROOF_CELLS)
+}
+
+/// Answer the epoch anchored at `anchor_height` for the square of `square` at
+/// `height`, as node `prover`. `None` if the anchor is not newer than the square.
+pub fn prove_storage<E: Pairing, H: Hasher>(
+    square: &RsSquareProver<E, H>,
+    height: u64,
+    anchor_height: u64,
+    anchor_root: &[u8],
+    prover: &[u8],
+) -> Option<StorageProof<E, H>> {
+    if anchor_height <= height {
+        return None;
+    }
+    let length = square.shares().len() * square.scale();
+    let samples = storage_cells::<H>(anchor_height, anchor_root, prover, height, length)
+        .into_iter()
+        .map(|(rid, cid)| square.sample_cell(rid, cid))
+        .collect();
+    Some(StorageProof {
+        height,
+        anchor_height,
+        samples,
+    })
+}
+
+/// Check that `proof` answers the epoch anchored at a header with data root
+/// `anchor_root` for node `prover`, against the square of side `length` under
+/// `root` and `version`
+pub fn verify_storage<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    anchor_root: &[u8],
+    prover: &[u8],
+    proof: &StorageProof<E, H>,
+) -> bool {
+    let seed = storage_seed::<H>(proof.anchor_height, anchor_root, prover, proof.height);
+    proof.anchor_height > proof.height
+        && verify_audit(
+            vk,
+            version,
+            root,
+            length,
+            &seed,
+            STORAGE_PROOF_CELLS,
+            &proof.samples,
+        )
+}
+
+/// Audit seed of an epoch, binding the anchor, the node and the square
+fn storage_seed<H: Hasher>(
+    anchor_height: u64,
+    anchor_root: &[u8],
+    prover: &[u8],
+    height: u64,
+) -> Vec<u8> {
+    let mut input = vec![];
+    put_u64(&mut input, anchor_height);
+    put_bytes(&mut input, anchor_root);
+    put_bytes(&mut input, prover);
+    put_u64(&mut input, height);
+    H::hash(&input).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{epoch_anchor, prove_storage, verify_storage, STORAGE_PROOF_CELLS};
+    use crate::fixtures::counting_square;
+    use crate::prover::RsSquareProver;
+    use crate::verifier::verifier_key;
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn proofs_answer_one_epoch_for_one_node() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let vk = verifier_key(prover.params());
+        let (version, root, anchor) = (prover.root_version(), prover.root(), [7; 32]);
+
+        let proof = prove_storage(&prover, 3, 9, &anchor, b"node").unwrap();
+        assert_eq!(proof.samples.len(), STORAGE_PROOF_CELLS);
+        assert!(verify_storage(
+            &vk, version, root, 4, &anchor, b"node", &proof
+        ));
+        // neither another node nor another epoch can reuse the answer
+        assert!(!verify_storage(
+            &vk,
+            version,
+            root,
+            4,
+            &anchor,
+            b"other node",
+            &proof
+        ));
+        assert!(!verify_storage(
+            &vk, version, root, 4, &[8; 32], b"node", &proof
+        ));
+
+        // an anchor no newer than the square was known when it was committed
+        assert!(prove_storage(&prover, 3, 3, &anchor, b"node").is_none());
+        let mut stale = proof;
+        stale.anchor_height = 2;
+        assert!(!verify_storage(
+            &vk, version, root, 4, &anchor, b"node", &stale
+        ));
+        assert_eq!((epoch_anchor(9, 4), epoch_anchor(8, 4)), (8, 8));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::codec::{commitment_to_bytes, field_to_bytes, verifier_key_to_bytes};
+use crate::prover::RsSquareProver;
+use crate::verifier::verifier_key;
+
+use ark_ff::UniformRand;
+use ark_poly::univariate::DensePolynomial;
+use ark_poly_commit::kzg10::KZG10;
+use ark_test_curves::bls12_381::{Bls12_381, Fr};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rs_merkle::algorithms::Sha256;
+
+/// Cells whose samples are included in every set of vectors
+pub const SAMPLED_CELLS: usize = 4;
+
+/// Test vectors over BLS12-381 and SHA-256 for a random original square of side
+/// `n` extended by `scale`: the setup and shares drawn from `seed`, the extended
+/// square, the line commitments, the roots and samples of `SAMPLED_CELLS` cells,
+/// also drawn from `seed`. Other implementations check they reproduce every
+/// value byte for byte.
+///
+/// The output is JSON with keys in a fixed order and no whitespace but a final
+/// newline. Field elements, commitments, roots and proofs are lowercase hex of
+/// their encodings in `codec` and `CellSample::to_bytes`.
+pub fn test_vectors(seed: u64, n: usize, scale: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let length = n * scale;
+    let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(length, false, &mut rng)
+        .expect("KZG setup failed");
+    let shares: Vec<Vec<Fr>> = (0..n)
+        .map(|_| (0..n).map(|_| Fr::rand(&mut rng)).collect())
+        .collect();
+    let prover = RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, scale, params);
+    let square = prover.square();
+    let cells: Vec<(usize, usize)> = (0..SAMPLED_CELLS)
+        .map(|_| (rng.gen_range(0..length), rng.gen_range(0..length)))
+        .collect();
+
+    let fields = |vals: &[Fr]| list(vals.iter().map(|val| hex(&field_to_bytes(val))));
+    let samples = cells.iter().map(|&(rid, cid)| {
+        let sample = prover.sample_cell(rid, cid).to_bytes();
+        format!(
+            "{{\"rid\":{},\"cid\":{},\"sample\":{}}}",
+            rid,
+            cid,
+            hex(&sample)
+        )
+    });
+    let entries = [
+        ("seed", seed.to_string()),
+        ("n", n.to_string()),
+        ("scale", scale.to_string()),
+        (
+            "verifier_key",
+            hex(&verifier_key_to_bytes(&verifier_key(prover.params()))),
+        ),
+        ("shares", list(shares.iter().map(|row| fields(row)))),
+        (
+            "extended",
+            list((0..length).map(|rid| fields(&square.row_vals(rid)))),
+        ),
+        (
+            "row_commitments",
+            list(
+                prover
+                    .commit_all_rows()
+                    .iter()
+                    .map(|com| hex(&commitment_to_bytes(com))),
+            ),
+        ),
+        (
+            "col_commitments",
+            list(
+                prover
+                    .commit_all_cols()
+                    .iter()
+                    .map(|com| hex(&commitment_to_bytes(com))),
+            ),
+        ),
+        ("row_root", hex(&prover.row_root())),
+        ("col_root", hex(&prover.col_root())),
+        ("data_root", hex(&prover.root())),
+        ("samples", list(samples)),
+    ];
+    let body: Vec<String> = entries
+        .iter()
+        .map(|(key, value)| format!("\"{}\":{}", key, value))
+        .collect();
+    format!("{{{}}}\n", body.join(","))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("\"{}\"", digits)
+}
+
+fn list(items: impl Iterator<Item = String>) -> String {
+    format!("[{}]", items.collect::<Vec<_>>().join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hex, test_vectors};
+    use crate::prover::RsSquareProver;
+
+    use ark_ff::UniformRand;
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly_commit::kzg10::KZG10;
+    use ark_test_curves::bls12_381::{Bls12_381, Fr};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use rs_merkle::algorithms::Sha256;
+
+    #[test]
+    pub fn vectors_are_reproducible() {
+        let vectors = test_vectors(7, 2, 2);
+        assert_eq!(vectors, test_vectors(7, 2, 2));
+        assert_ne!(vectors, test_vectors(8, 2, 2));
+        assert!(vectors.starts_with("{\"seed\":7,\"n\":2,\"scale\":2,"));
+        assert!(!vectors.trim_end().contains(char::is_whitespace));
+
+        // the setup is drawn first, then the shares row by row
+        let mut rng = StdRng::seed_from_u64(7);
+        let params = KZG10::<Bls12_381, DensePolynomial<Fr>>::setup(4, false, &mut rng)
+            .expect("KZG setup failed");
+        let shares: Vec<Vec<Fr>> = (0..2)
+            .map(|_| (0..2).map(|_| Fr::rand(&mut rng)).collect())
+            .collect();
+        let prover = RsSquareProver::<Bls12_381, Sha256>::with_params(&shares, 2, params);
+        assert!(vectors.contains(&format!("\"data_root\":{}", hex(&prover.root()))));
+    }
+}
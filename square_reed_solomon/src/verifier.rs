@@ -0,0 +1,1265 @@
+use crate::codec::{commitment_to_bytes, field_to_bytes, put_bytes, put_u64};
+use crate::merkle::{
+    self, tagged_path_root, verify_tagged_paths, MerkleRoot, TaggedTree, TreeTag, LEAF_PREFIX,
+};
+use crate::sample::{CellSample, ChallengeContext, LineAxis, LineSample, RangeProof, RowProof};
+
+use ark_ec::pairing::Pairing;
+use ark_ec::scalar_mul::fixed_base::FixedBase;
+use ark_ec::{AffineRepr, CurveGroup};
+use ark_ff::{PrimeField, Zero};
+use ark_poly::univariate::DensePolynomial;
+use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
+use ark_poly_commit::kzg10::{self, Commitment, Proof, VerifierKey, KZG10};
+use ark_serialize::CanonicalSerialize;
+use rand::RngCore;
+use rs_merkle::{Hasher, MerkleProof};
+
+/// Extract the verifier's part of a KZG setup
+pub fn verifier_key<E: Pairing>(params: &kzg10::UniversalParams<E>) -> VerifierKey<E> {
+    VerifierKey {
+        g: params.powers_of_g[0],
+        gamma_g: params.powers_of_gamma_g[&0],
+        h: params.h,
+        beta_h: params.beta_h,
+        prepared_h: params.prepared_h.clone(),
+        prepared_beta_h: params.prepared_beta_h.clone(),
+    }
+}
+
+/// Window size of the fixed-base tables, trading table memory for multiplication speed
+pub const PREPARED_WINDOW: usize = 6;
+
+/// Verifier key with everything that does not depend on the opening precomputed:
+/// windowed tables for multiplying `g` and `gamma_g`, and `h` and `beta_h` prepared
+/// for the Miller loop. Worth building once on clients that verify many openings.
+pub struct PreparedVerifierKey<E: Pairing> {
+    pub vk: VerifierKey<E>,
+    window: usize,
+    g_table: Vec<Vec<E::G1Affine>>,
+    gamma_g_table: Vec<Vec<E::G1Affine>>,
+    prepared_h: E::G2Prepared,
+    prepared_beta_h: E::G2Prepared,
+}
+
+impl<E: Pairing> PreparedVerifierKey<E> {
+    pub fn new(vk: VerifierKey<E>) -> Self {
+        Self::with_window(vk, PREPARED_WINDOW)
+    }
+
+    pub fn with_window(vk: VerifierKey<E>, window: usize) -> Self {
+        let scalar_size = E::ScalarField::MODULUS_BIT_SIZE as usize;
+        let g_table = FixedBase::get_window_table(scalar_size, window, vk.g.into_group());
+        let gamma_g_table =
+            FixedBase::get_window_table(scalar_size, window, vk.gamma_g.into_group());
+        Self {
+            window,
+            g_table,
+            gamma_g_table,
+            prepared_h: vk.h.into(),
+            prepared_beta_h: vk.beta_h.into(),
+            vk,
+        }
+    }
+
+    fn mul_table(&self, table: &[Vec<E::G1Affine>], scalar: E::ScalarField) -> E::G1 {
+        let scalar_size = E::ScalarField::MODULUS_BIT_SIZE as usize;
+        FixedBase::msm::<E::G1>(scalar_size, self.window, table, &[scalar])[0]
+    }
+
+    /// Same check as `KZG10::check`, arranged so both G2 inputs are fixed:
+    /// e(C - v*g - r*gamma_g + z*w, h) == e(w, beta_h)
+    pub fn check(
+        &self,
+        commitment: &Commitment<E>,
+        point: E::ScalarField,
+        value: E::ScalarField,
+        proof: &Proof<E>,
+    ) -> bool {
+        let mut lhs =
+            commitment.0.into_group() - self.mul_table(&self.g_table, value) + proof.w * point;
+        if let Some(random_v) = proof.random_v {
+            lhs -= self.mul_table(&self.gamma_g_table, random_v);
+        }
+        let miller = E::multi_miller_loop(
+            [lhs.into_affine(), (-proof.w.into_group()).into_affine()],
+            [self.prepared_h.clone(), self.prepared_beta_h.clone()],
+        );
+        E::final_exponentiation(miller).map_or(false, |output| output.is_zero())
+    }
+
+    /// `verify_cell_proof` against the precomputed key
+    pub fn verify_cell_proof(
+        &self,
+        commitment: &Commitment<E>,
+        length: usize,
+        idx: usize,
+        value: E::ScalarField,
+        proof: &Proof<E>,
+    ) -> bool {
+        match Radix2EvaluationDomain::<E::ScalarField>::new(length) {
+            Some(domain) if idx < length => {
+                self.check(commitment, domain.element(idx), value, proof)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Leaf of a commitment under `RootVersion::V1`, the hash of its point alone
+pub fn hash_commitment<E: Pairing, H: Hasher>(com: &Commitment<E>) -> H::Hash {
+    H::hash(commitment_point_bytes(com).as_slice())
+}
+
+/// Leaf of the commitment to line `idx` along `axis` under `version`
+pub fn line_leaf<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    axis: LineAxis,
+    idx: usize,
+    com: &Commitment<E>,
+) -> H::Hash {
+    match version {
+        RootVersion::V1 => hash_commitment::<E, H>(com),
+        RootVersion::V2 | RootVersion::V3 => {
+            let axis = match axis {
+                LineAxis::Row => 0u8,
+                LineAxis::Col => 1u8,
+            };
+            let mut bytes = vec![];
+            if version == RootVersion::V3 {
+                bytes.push(LEAF_PREFIX);
+            }
+            put_u64(&mut bytes, idx as u64);
+            put_bytes(&mut bytes, &[axis]);
+            put_bytes(&mut bytes, &commitment_point_bytes(com));
+            H::hash(bytes.as_slice())
+        }
+    }
+}
+
+/// Leaves of the commitments to every line along `axis`, in line order
+pub fn line_leaves<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    axis: LineAxis,
+    commitments: &[Commitment<E>],
+) -> Vec<H::Hash> {
+    commitments
+        .iter()
+        .enumerate()
+        .map(|(idx, com)| line_leaf::<E, H>(version, axis, idx, com))
+        .collect()
+}
+
+fn commitment_point_bytes<E: Pairing>(com: &Commitment<E>) -> Vec<u8> {
+    let mut bytes: Vec<u8> = vec![];
+    com.0
+        .serialize_uncompressed(&mut bytes)
+        .expect("Serializing commitment point should not fail");
+    bytes
+}
+
+/// Check a KZG opening of a line polynomial at the evaluation point of index `idx`
+/// in a square of side `length`.
+pub fn verify_cell_proof<E: Pairing>(
+    vk: &VerifierKey<E>,
+    commitment: &Commitment<E>,
+    length: usize,
+    idx: usize,
+    value: E::ScalarField,
+    proof: &Proof<E>,
+) -> bool {
+    let domain = match Radix2EvaluationDomain::<E::ScalarField>::new(length) {
+        Some(domain) if idx < length => domain,
+        _ => return false,
+    };
+    KZG10::<E, DensePolynomial<E::ScalarField>>::check(
+        vk,
+        commitment,
+        domain.element(idx),
+        value,
+        proof,
+    )
+    .unwrap_or(false)
+}
+
+/// Check many cell openings at once with a single random linear combination
+/// pairing check. Each opening is (row commitment, index within the row, value, proof).
+/// Returns true only if every opening is valid.
+pub fn verify_cell_proofs<E: Pairing, R: RngCore>(
+    vk: &VerifierKey<E>,
+    length: usize,
+    openings: &[(Commitment<E>, usize, E::ScalarField, Proof<E>)],
+    rng: &mut R,
+) -> bool {
+    let domain = match Radix2EvaluationDomain::<E::ScalarField>::new(length) {
+        Some(domain) => domain,
+        None => return false,
+    };
+    if openings.iter().any(|(_, idx, _, _)| *idx >= length) {
+        return false;
+    }
+
+    let commitments: Vec<Commitment<E>> = openings.iter().map(|(com, _, _, _)| *com).collect();
+    let points: Vec<E::ScalarField> = openings
+        .iter()
+        .map(|(_, idx, _, _)| domain.element(*idx))
+        .collect();
+    let values: Vec<E::ScalarField> = openings.iter().map(|(_, _, value, _)| *value).collect();
+    let proofs: Vec<Proof<E>> = openings.iter().map(|(_, _, _, proof)| *proof).collect();
+    KZG10::<E, DensePolynomial<E::ScalarField>>::batch_check(
+        vk,
+        &commitments,
+        &points,
+        &values,
+        &proofs,
+        rng,
+    )
+    .unwrap_or(false)
+}
+
+/// Check that `com` is the commitment to line `idx` along `axis`, at position
+/// `idx` of a tree with `length` leaves and root `line_root` under `version`.
+pub fn verify_line_commitment<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    line_root: H::Hash,
+    axis: LineAxis,
+    length: usize,
+    idx: usize,
+    com: &Commitment<E>,
+    path: &MerkleProof<H>,
+) -> bool {
+    if idx >= length {
+        return false;
+    }
+    verify_line_path(
+        version,
+        axis,
+        path,
+        line_root,
+        &[idx],
+        &[line_leaf::<E, H>(version, axis, idx, com)],
+        length,
+    )
+}
+
+/// Check that `coms` are the commitments to the lines `idxs` along `axis`, one or
+/// more and in increasing order, in a tree with `length` leaves and root
+/// `line_root` under `version`, against a single multi-proof
+pub fn verify_line_commitments<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    line_root: H::Hash,
+    axis: LineAxis,
+    length: usize,
+    idxs: &[usize],
+    coms: &[Commitment<E>],
+    path: &MerkleProof<H>,
+) -> bool {
+    if idxs.is_empty()
+        || idxs.len() != coms.len()
+        || idxs.windows(2).any(|pair| pair[0] >= pair[1])
+        || idxs[idxs.len() - 1] >= length
+    {
+        return false;
+    }
+    let leaves: Vec<H::Hash> = idxs
+        .iter()
+        .zip(coms)
+        .map(|(idx, com)| line_leaf::<E, H>(version, axis, *idx, com))
+        .collect();
+    verify_line_path(version, axis, path, line_root, idxs, &leaves, length)
+}
+
+/// `verify_line_commitment` for many lines along `axis` at once, each given as its
+/// index, commitment and path. Nodes that several paths pass through are hashed
+/// once (see `verify_tagged_paths`), e.g. for the dozens of samples a light node
+/// checks per block.
+pub fn verify_line_commitment_batch<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    line_root: H::Hash,
+    axis: LineAxis,
+    length: usize,
+    lines: &[(usize, &Commitment<E>, &MerkleProof<H>)],
+) -> bool {
+    let paths: Vec<(usize, H::Hash, &MerkleProof<H>)> = lines
+        .iter()
+        .map(|&(idx, com, path)| {
+            let leaf = line_leaf::<E, H>(version, axis, idx, com);
+            (idx, leaf, path)
+        })
+        .collect();
+    verify_tagged_paths::<H>(version.line_tag(axis), line_root, length, &paths)
+}
+
+/// `verify_line_commitment` against a crate-owned proof, whose position and tree
+/// size travel with it
+pub fn verify_line_commitment_proof<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    line_root: &MerkleRoot,
+    axis: LineAxis,
+    com: &Commitment<E>,
+    proof: &merkle::MerkleProof,
+) -> bool {
+    let leaf: Vec<u8> = line_leaf::<E, H>(version, axis, proof.index, com).into();
+    proof.verify_tagged::<H>(version.line_tag(axis), line_root, &leaf)
+}
+
+/// Rules computing the data root of a square from its line commitments, fixed per
+/// version so that anyone holding the published commitments recomputes the root
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RootVersion {
+    /// 1. The leaf of a commitment is the hash of its point serialized
+    ///    uncompressed (see `hash_commitment`).
+    /// 2. The row root is the root of the `rs_merkle` tree over the leaves of the
+    ///    row commitments in row order: each level hashes the concatenation of
+    ///    pairs of nodes and promotes an odd last node as is. The column root is
+    ///    built the same way over the column commitments.
+    /// 3. The data root is the hash of the row root followed by the column root.
+    V1,
+    /// As `V1`, except that the leaf of the commitment to line `i` hashes `i` as a
+    /// length-prefixed little-endian `u64`, then the axis as a length-prefixed byte,
+    /// 0 for rows and 1 for columns, then the uncompressed point, length-prefixed
+    /// (see `line_leaf`). A path for one line then never proves the commitment to
+    /// another, wherever its leaf sits in the tree.
+    V2,
+    /// As `V2`, except that every hash is domain separated: a leaf hashes
+    /// `LEAF_PREFIX` ahead of its `V2` bytes, and an internal node hashes
+    /// `NODE_PREFIX`, the `TreeTag` id of its tree (0 for the row tree, 1 for the
+    /// column tree, 2 for the data root over both roots), then both children (see
+    /// `NodeHasher`). No leaf hashes like a node, and no node of one tree passes
+    /// for a node of another.
+    V3,
+}
+
+impl RootVersion {
+    /// Version data roots are computed with
+    pub const LATEST: RootVersion = RootVersion::V3;
+
+    pub fn id(&self) -> u8 {
+        match self {
+            RootVersion::V1 => 1,
+            RootVersion::V2 => 2,
+            RootVersion::V3 => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(RootVersion::V1),
+            2 => Some(RootVersion::V2),
+            3 => Some(RootVersion::V3),
+            _ => None,
+        }
+    }
+
+    /// Tree the commitments to the lines along `axis` are hashed into
+    pub fn line_tag(&self, axis: LineAxis) -> TreeTag {
+        match self {
+            RootVersion::V1 | RootVersion::V2 => TreeTag::Plain,
+            RootVersion::V3 => axis.into(),
+        }
+    }
+
+    /// Tree the row and column roots are hashed into
+    pub fn top_tag(&self) -> TreeTag {
+        match self {
+            RootVersion::V1 | RootVersion::V2 => TreeTag::Plain,
+            RootVersion::V3 => TreeTag::Top,
+        }
+    }
+}
+
+/// Data root of a square from its row and column commitments under `version`.
+/// `None` unless there are as many of each and at least one.
+pub fn compute_data_root<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    row_commitments: &[Commitment<E>],
+    col_commitments: &[Commitment<E>],
+) -> Option<H::Hash> {
+    if row_commitments.is_empty() || row_commitments.len() != col_commitments.len() {
+        return None;
+    }
+    Some(combine_line_roots::<H>(
+        version,
+        compute_line_root::<E, H>(version, LineAxis::Row, row_commitments),
+        compute_line_root::<E, H>(version, LineAxis::Col, col_commitments),
+    ))
+}
+
+/// Root over the commitments to every line along `axis` under `version`
+pub fn compute_line_root<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    axis: LineAxis,
+    commitments: &[Commitment<E>],
+) -> H::Hash {
+    let leaves = line_leaves::<E, H>(version, axis, commitments);
+    TaggedTree::<H>::new(version.line_tag(axis), &leaves)
+        .root()
+        .expect("Merkle root construction of lines should succeed")
+}
+
+/// Data root over a row and a column root under `version`
+pub fn combine_line_roots<H: Hasher>(
+    version: RootVersion,
+    row_root: H::Hash,
+    col_root: H::Hash,
+) -> H::Hash {
+    TaggedTree::<H>::new(version.top_tag(), &[row_root, col_root])
+        .root()
+        .expect("Merkle root construction from row and col roots should succeed")
+}
+
+/// Check that the data root commits to the given row and column roots under
+/// `version`
+pub fn verify_root<H: Hasher>(
+    version: RootVersion,
+    root: H::Hash,
+    row_root: H::Hash,
+    col_root: H::Hash,
+) -> bool {
+    let recomputed = combine_line_roots::<H>(version, row_root, col_root);
+    hashes_equal::<H>(recomputed, root)
+}
+
+/// Check that the data root is the one `row_commitments` and `col_commitments`
+/// compute under `version`, e.g. for an auditor who fetched every line commitment
+/// of a block but none of its cells
+pub fn verify_root_from_commitments<E: Pairing, H: Hasher>(
+    version: RootVersion,
+    root: H::Hash,
+    row_commitments: &[Commitment<E>],
+    col_commitments: &[Commitment<E>],
+) -> bool {
+    compute_data_root::<E, H>(version, row_commitments, col_commitments)
+        .is_some_and(|recomputed| hashes_equal::<H>(recomputed, root))
+}
+
+/// Whether a recomputed root, digest, commitment hash or token equals the one
+/// received. Takes the same time wherever the two differ, so that timing an
+/// acceptance decision leaks nothing about the expected value; every such decision
+/// of this crate and the full and light nodes goes through here.
+pub fn digests_equal(recomputed: &[u8], received: &[u8]) -> bool {
+    if recomputed.len() != received.len() {
+        return false;
+    }
+    let diff = recomputed
+        .iter()
+        .zip(received)
+        .fold(0u8, |diff, (a, b)| diff | (a ^ b));
+    std::hint::black_box(diff) == 0
+}
+
+/// `digests_equal` on hashes
+pub fn hashes_equal<H: Hasher>(recomputed: H::Hash, received: H::Hash) -> bool {
+    let (recomputed, received): (Vec<u8>, Vec<u8>) = (recomputed.into(), received.into());
+    digests_equal(&recomputed, &received)
+}
+
+/// `MerkleProof::verify`, comparing the recomputed root with `hashes_equal`
+pub fn verify_path<H: Hasher>(
+    path: &MerkleProof<H>,
+    root: H::Hash,
+    idxs: &[usize],
+    leaves: &[H::Hash],
+    total: usize,
+) -> bool {
+    path.root(idxs, leaves, total)
+        .map_or(false, |recomputed| hashes_equal::<H>(recomputed, root))
+}
+
+/// `verify_path` in the tree over the commitments along `axis` under `version`
+pub fn verify_line_path<H: Hasher>(
+    version: RootVersion,
+    axis: LineAxis,
+    path: &MerkleProof<H>,
+    root: H::Hash,
+    idxs: &[usize],
+    leaves: &[H::Hash],
+    total: usize,
+) -> bool {
+    tagged_path_root::<H>(version.line_tag(axis), path, idxs, leaves, total)
+        .map_or(false, |recomputed| hashes_equal::<H>(recomputed, root))
+}
+
+/// Full check of a sampled cell against a data root: the root covers the sample's
+/// row root, the row commitment sits under the row root, and the opening is valid.
+/// `version` is the root version of the square, as its header's layout gives it.
+pub fn verify_cell_sample<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    sample: &CellSample<E, H>,
+) -> bool {
+    verify_root::<H>(version, root, sample.row_root, sample.col_root)
+        && verify_line_commitment::<E, H>(
+            version,
+            sample.row_root,
+            LineAxis::Row,
+            length,
+            sample.rid,
+            &sample.row_commitment,
+            &sample.row_path,
+        )
+        && verify_cell_proof(
+            vk,
+            &sample.row_commitment,
+            length,
+            sample.cid,
+            sample.value,
+            &sample.opening,
+        )
+}
+
+/// Batched `verify_cell_sample`: the samples must share their line roots, their
+/// Merkle paths are checked together hashing shared nodes once, and all openings
+/// are checked together with one pairing check.
+pub fn verify_cell_samples<E: Pairing, H: Hasher, R: RngCore>(
+    vk: &VerifierKey<E>,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    samples: &[CellSample<E, H>],
+    rng: &mut R,
+) -> bool {
+    let paths_valid = match samples.first() {
+        Some(first) => {
+            let lines: Vec<_> = samples
+                .iter()
+                .map(|sample| (sample.rid, &sample.row_commitment, &sample.row_path))
+                .collect();
+            verify_root::<H>(version, root, first.row_root, first.col_root)
+                && samples.iter().all(|sample| {
+                    hashes_equal::<H>(sample.row_root, first.row_root)
+                        && hashes_equal::<H>(sample.col_root, first.col_root)
+                })
+                && verify_line_commitment_batch::<E, H>(
+                    version,
+                    first.row_root,
+                    LineAxis::Row,
+                    length,
+                    &lines,
+                )
+        }
+        None => true,
+    };
+    if !paths_valid {
+        return false;
+    }
+
+    let openings: Vec<_> = samples
+        .iter()
+        .map(|sample| {
+            (
+                sample.row_commitment,
+                sample.cid,
+                sample.value,
+                sample.opening,
+            )
+        })
+        .collect();
+    verify_cell_proofs(vk, length, &openings, rng)
+}
+
+/// Point at which a line commitment is opened to vouch for all of the line's values.
+/// Derived from the context, the commitment and the values, so the prover cannot
+/// choose it.
+pub fn line_challenge<E: Pairing, H: Hasher>(
+    context: &ChallengeContext,
+    commitment: &Commitment<E>,
+    values: &[E::ScalarField],
+) -> E::ScalarField {
+    let mut transcript = context.to_bytes();
+    transcript.extend(commitment_to_bytes(commitment));
+    for value in values {
+        transcript.extend(field_to_bytes(value));
+    }
+    let digest: Vec<u8> = H::hash(&transcript).into();
+    E::ScalarField::from_le_bytes_mod_order(&digest)
+}
+
+/// Point at which a range proof opens the row and quotient polynomials. Derived
+/// from the context, both commitments and the claimed span, so the prover cannot
+/// choose it.
+pub fn range_challenge<E: Pairing, H: Hasher>(
+    context: &ChallengeContext,
+    commitment: &Commitment<E>,
+    quotient: &Commitment<E>,
+    cids: &[usize],
+    values: &[E::ScalarField],
+) -> E::ScalarField {
+    let mut transcript = context.to_bytes();
+    transcript.extend(commitment_to_bytes(commitment));
+    transcript.extend(commitment_to_bytes(quotient));
+    for (cid, value) in cids.iter().zip(values) {
+        transcript.extend((*cid as u64).to_le_bytes());
+        transcript.extend(field_to_bytes(value));
+    }
+    let digest: Vec<u8> = H::hash(&transcript).into();
+    E::ScalarField::from_le_bytes_mod_order(&digest)
+}
+
+/// Weight folding the row and quotient openings of a range proof into one. Derived
+/// once both values at the range challenge are fixed.
+pub fn range_weight<E: Pairing, H: Hasher>(
+    point: E::ScalarField,
+    row_value: E::ScalarField,
+    quotient_value: E::ScalarField,
+) -> E::ScalarField {
+    let mut transcript = field_to_bytes(&point);
+    transcript.extend(field_to_bytes(&row_value));
+    transcript.extend(field_to_bytes(&quotient_value));
+    let digest: Vec<u8> = H::hash(&transcript).into();
+    E::ScalarField::from_le_bytes_mod_order(&digest)
+}
+
+/// Check that the row polynomial under `commitment` takes `values` at the points of
+/// columns `cids`, given in increasing order, of a square of side `length`. At the
+/// range challenge the row polynomial minus the values' interpolant must equal the
+/// quotient times the span's vanishing polynomial. `context` names the row and
+/// the block the proof is expected for.
+pub fn verify_range_proof<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    context: &ChallengeContext,
+    commitment: &Commitment<E>,
+    length: usize,
+    cids: &[usize],
+    values: &[E::ScalarField],
+    proof: &RangeProof<E>,
+) -> bool {
+    let domain = match Radix2EvaluationDomain::<E::ScalarField>::new(length) {
+        Some(domain) if !cids.is_empty() && cids.len() == values.len() => domain,
+        _ => return false,
+    };
+    if cids.windows(2).any(|pair| pair[0] >= pair[1]) || cids[cids.len() - 1] >= length {
+        return false;
+    }
+    let points: Vec<E::ScalarField> = cids.iter().map(|cid| domain.element(*cid)).collect();
+    let point = range_challenge::<E, H>(context, commitment, &proof.quotient, cids, values);
+    if points.contains(&point) {
+        return false;
+    }
+
+    // interpolant of the values at the challenge, in barycentric form
+    let vanishing: E::ScalarField = points.iter().map(|x| point - x).product();
+    let interpolant: E::ScalarField = vanishing
+        * points
+            .iter()
+            .zip(values)
+            .enumerate()
+            .map(|(j, (xj, value))| {
+                let weight = points
+                    .iter()
+                    .enumerate()
+                    .filter(|(k, _)| *k != j)
+                    .map(|(_, xk)| *xj - xk)
+                    .product::<E::ScalarField>()
+                    .inverse()
+                    .expect("Span points should be distinct");
+                weight * value / (point - xj)
+            })
+            .sum::<E::ScalarField>();
+    if proof.row_value - interpolant != proof.quotient_value * vanishing {
+        return false;
+    }
+
+    let weight = range_weight::<E, H>(point, proof.row_value, proof.quotient_value);
+    let combined =
+        Commitment((commitment.0.into_group() + proof.quotient.0 * weight).into_affine());
+    KZG10::<E, DensePolynomial<E::ScalarField>>::check(
+        vk,
+        &combined,
+        point,
+        proof.row_value + weight * proof.quotient_value,
+        &proof.opening,
+    )
+    .unwrap_or(false)
+}
+
+/// Check a whole row or column against a data root. The values fix a unique
+/// polynomial of degree below `length`; if it differs from the committed one, the
+/// two agree at the challenge point only with negligible probability. `height` is
+/// that of the trusted header carrying `root`, which the sample must be for.
+pub fn verify_line_sample<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    sample: &LineSample<E, H>,
+) -> bool {
+    if sample.height != height {
+        return false;
+    }
+    let line_root = match sample.axis {
+        LineAxis::Row => sample.row_root,
+        LineAxis::Col => sample.col_root,
+    };
+    if !verify_root::<H>(version, root, sample.row_root, sample.col_root)
+        || !verify_line_commitment::<E, H>(
+            version,
+            line_root,
+            sample.axis,
+            length,
+            sample.idx,
+            &sample.commitment,
+            &sample.path,
+        )
+    {
+        return false;
+    }
+    let data_root: Vec<u8> = root.into();
+    let context = ChallengeContext {
+        height,
+        data_root: &data_root,
+        axis: sample.axis,
+        idx: sample.idx,
+    };
+    verify_line_opening::<E, H>(
+        vk,
+        &context,
+        length,
+        &sample.commitment,
+        &sample.values,
+        &sample.opening,
+    )
+}
+
+/// Check a custody proof for a whole row against the row root alone, in one call.
+/// The proof's challenge is bound to `height` and `data_root`, those of a trusted
+/// header, which the caller checks commits to `row_root`.
+pub fn verify_row_proof<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    version: RootVersion,
+    data_root: H::Hash,
+    row_root: H::Hash,
+    length: usize,
+    proof: &RowProof<E, H>,
+) -> bool {
+    if proof.height != height {
+        return false;
+    }
+    let data_root: Vec<u8> = data_root.into();
+    let context = ChallengeContext {
+        height,
+        data_root: &data_root,
+        axis: LineAxis::Row,
+        idx: proof.rid,
+    };
+    verify_line_commitment::<E, H>(
+        version,
+        row_root,
+        LineAxis::Row,
+        length,
+        proof.rid,
+        &proof.commitment,
+        &proof.path,
+    ) && verify_line_opening::<E, H>(
+        vk,
+        &context,
+        length,
+        &proof.commitment,
+        &proof.values,
+        &proof.opening,
+    )
+}
+
+/// Check evidence that a square's rows and columns disagree: a cell sample and a
+/// sample of the cell's whole column, both valid under `root`, giving different
+/// values for the same cell. An honestly extended square never yields such a pair.
+pub fn verify_bad_encoding<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    height: u64,
+    version: RootVersion,
+    root: H::Hash,
+    length: usize,
+    cell: &CellSample<E, H>,
+    col: &LineSample<E, H>,
+) -> bool {
+    col.axis == LineAxis::Col
+        && col.idx == cell.cid
+        && col
+            .values
+            .get(cell.rid)
+            .map_or(false, |value| *value != cell.value)
+        && verify_cell_sample(vk, version, root, length, cell)
+        && verify_line_sample(vk, height, version, root, length, col)
+}
+
+/// Check that `values` are the evaluations of the committed line polynomial over
+/// the domain of size `length`, given its opening at the line challenge
+fn verify_line_opening<E: Pairing, H: Hasher>(
+    vk: &VerifierKey<E>,
+    context: &ChallengeContext,
+    length: usize,
+    commitment: &Commitment<E>,
+    values: &[E::ScalarField],
+    opening: &Proof<E>,
+) -> bool {
+    let domain = match Radix2EvaluationDomain::<E::ScalarField>::new(length) {
+        Some(domain) if values.len() == length => domain,
+        _ => return false,
+    };
+    let challenge = line_challenge::<E, H>(context, commitment, values);
+    let expected: E::ScalarField = domain
+        .evaluate_all_lagrange_coefficients(challenge)
+        .iter()
+        .zip(values)
+        .map(|(coeff, value)| *coeff * value)
+        .sum();
+    KZG10::<E, DensePolynomial<E::ScalarField>>::check(vk, commitment, challenge, expected, opening)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_data_root, compute_line_root, verify_root_from_commitments, RootVersion};
+    use super::{
+        digests_equal, verifier_key, verify_bad_encoding, verify_cell_proof, verify_cell_sample,
+        verify_cell_samples, verify_line_commitments, verify_line_sample, verify_range_proof,
+        verify_row_proof, PreparedVerifierKey,
+    };
+    use super::{hash_commitment, line_leaf};
+    use crate::fixtures::counting_square;
+    use crate::merkle::{TreeTag, NODE_PREFIX};
+    use crate::prover::RsSquareProver;
+    use crate::sample::{CellSample, ChallengeContext, LineAxis, LineSample, RangeProof, RowProof};
+
+    use ark_test_curves::bls12_381::Bls12_381;
+    use ark_test_curves::bls12_381::Fr;
+    use rs_merkle::algorithms::Sha256;
+    use rs_merkle::Hasher;
+
+    #[test]
+    pub fn data_roots_follow_the_spec() {
+        let shares = counting_square(2);
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, 2);
+        let (rows, cols) = (prover.commit_all_rows(), prover.commit_all_cols());
+        let root = compute_data_root::<Bls12_381, Sha256>(RootVersion::V3, &rows, &cols);
+        assert_eq!(root, Some(prover.root()));
+
+        // the data root hashes the node prefix and the top tree's tag, then the row
+        // root followed by the column root
+        let line_root = compute_line_root::<Bls12_381, Sha256>;
+        let mut roots = vec![NODE_PREFIX, TreeTag::Top.id().unwrap()];
+        roots.extend(line_root(RootVersion::V3, LineAxis::Row, &rows));
+        roots.extend(line_root(RootVersion::V3, LineAxis::Col, &cols));
+        assert_eq!(root, Some(Sha256::hash(&roots)));
+        let mut untagged = line_root(RootVersion::V2, LineAxis::Row, &rows).to_vec();
+        untagged.extend(line_root(RootVersion::V2, LineAxis::Col, &cols));
+        assert_eq!(
+            compute_data_root::<Bls12_381, Sha256>(RootVersion::V2, &rows, &cols),
+            Some(Sha256::hash(&untagged))
+        );
+
+        // V2 leaves bind the commitment to its line, V1 leaves hash it alone
+        let leaf = line_leaf::<Bls12_381, Sha256>;
+        assert_eq!(
+            leaf(RootVersion::V1, LineAxis::Col, 1, &rows[0]),
+            hash_commitment::<Bls12_381, Sha256>(&rows[0])
+        );
+        let bound = leaf(RootVersion::V2, LineAxis::Row, 0, &rows[0]);
+        assert_ne!(bound, leaf(RootVersion::V2, LineAxis::Row, 1, &rows[0]));
+        assert_ne!(bound, leaf(RootVersion::V2, LineAxis::Col, 0, &rows[0]));
+        assert_ne!(bound, leaf(RootVersion::V3, LineAxis::Row, 0, &rows[0]));
+        assert_ne!(
+            compute_data_root::<Bls12_381, Sha256>(RootVersion::V1, &rows, &cols),
+            root
+        );
+
+        assert_eq!(
+            compute_data_root::<Bls12_381, Sha256>(RootVersion::V1, &rows, &cols[1..]),
+            None
+        );
+        assert_eq!(
+            compute_data_root::<Bls12_381, Sha256>(RootVersion::V1, &[], &[]),
+            None
+        );
+
+        // auditors holding only the commitments confirm the published root
+        let verify = verify_root_from_commitments::<Bls12_381, Sha256>;
+        assert!(verify(RootVersion::V3, prover.root(), &rows, &cols));
+        assert!(!verify(RootVersion::V2, prover.root(), &rows, &cols));
+        assert!(!verify(RootVersion::V3, prover.root(), &cols, &rows));
+        assert!(!verify(
+            RootVersion::V3,
+            prover.root(),
+            &rows[1..],
+            &cols[1..]
+        ));
+        assert!(!verify(RootVersion::V3, prover.root(), &[], &[]));
+        assert_eq!(
+            RootVersion::from_id(RootVersion::LATEST.id()),
+            Some(RootVersion::LATEST)
+        );
+    }
+
+    #[test]
+    pub fn digests_compare_bytewise() {
+        assert!(digests_equal(&[1, 2, 3], &[1, 2, 3]));
+        assert!(digests_equal(&[], &[]));
+        assert!(!digests_equal(&[1, 2, 3], &[1, 2, 4]));
+        assert!(!digests_equal(&[1, 2, 3], &[1, 2]));
+    }
+
+    #[test]
+    pub fn sampled_cell_verifies() {
+        let shares = counting_square(4);
+        let scale = 2;
+        let length = shares.len() * scale;
+
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let vk = verifier_key(prover.params());
+        let root = prover.root();
+        let version = prover.root_version();
+
+        let sample = prover.sample_cell(3, 5);
+        assert!(verify_cell_sample(&vk, version, root, length, &sample));
+
+        // samples survive the wire encoding
+        let decoded = CellSample::<Bls12_381, Sha256>::from_bytes(&sample.to_bytes()).unwrap();
+        assert!(verify_cell_sample(&vk, version, root, length, &decoded));
+
+        // a wrong value must be rejected
+        let mut tampered = decoded;
+        tampered.value += Fr::from(1);
+        assert!(!verify_cell_sample(&vk, version, root, length, &tampered));
+    }
+
+    #[test]
+    pub fn batched_samples_verify() {
+        let shares = counting_square(4);
+        let scale = 2;
+        let length = shares.len() * scale;
+        let rng = &mut rand::thread_rng();
+
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let vk = verifier_key(prover.params());
+        let root = prover.root();
+        let version = prover.root_version();
+
+        let mut samples: Vec<_> = [(0, 0), (3, 5), (7, 2), (6, 6)]
+            .iter()
+            .map(|(rid, cid)| prover.sample_cell(*rid, *cid))
+            .collect();
+        assert!(verify_cell_samples(
+            &vk, version, root, length, &samples, rng
+        ));
+
+        // a path lent by another sample fails even where the batch shares nodes
+        let row_path =
+            std::mem::replace(&mut samples[3].row_path, prover.sample_cell(3, 5).row_path);
+        assert!(!verify_cell_samples(
+            &vk, version, root, length, &samples, rng
+        ));
+        samples[3].row_path = row_path;
+        assert!(verify_cell_samples(
+            &vk, version, root, length, &samples, rng
+        ));
+
+        // one bad opening spoils the whole batch
+        samples[2].value += Fr::from(1);
+        assert!(!verify_cell_samples(
+            &vk, version, root, length, &samples, rng
+        ));
+    }
+
+    #[test]
+    pub fn prepared_key_agrees_with_plain_check() {
+        let shares = counting_square(4);
+        let scale = 2;
+        let length = shares.len() * scale;
+
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let vk = verifier_key(prover.params());
+        let prepared = PreparedVerifierKey::new(vk.clone());
+
+        let sample = prover.sample_cell(5, 3);
+        for value in [sample.value, sample.value + Fr::from(1)] {
+            assert_eq!(
+                prepared.verify_cell_proof(
+                    &sample.row_commitment,
+                    length,
+                    3,
+                    value,
+                    &sample.opening
+                ),
+                verify_cell_proof(
+                    &vk,
+                    &sample.row_commitment,
+                    length,
+                    3,
+                    value,
+                    &sample.opening
+                ),
+            );
+        }
+        assert!(prepared.verify_cell_proof(
+            &sample.row_commitment,
+            length,
+            3,
+            sample.value,
+            &sample.opening
+        ));
+    }
+
+    #[test]
+    pub fn commitments_share_one_multiproof() {
+        let shares = counting_square(4);
+        let scale = 2;
+        let length = shares.len() * scale;
+
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let version = prover.root_version();
+        let rids = [1, 2, 3, 6];
+        let coms: Vec<_> = rids.iter().map(|rid| prover.commit_to_row(*rid)).collect();
+        let path = prover.row_commitments_proof(&rids);
+        let row_root = prover.row_root();
+        assert!(verify_line_commitments(
+            version,
+            row_root,
+            LineAxis::Row,
+            length,
+            &rids,
+            &coms,
+            &path
+        ));
+
+        // every commitment is bound to its own position
+        let mut swapped = coms.clone();
+        swapped.swap(0, 1);
+        assert!(!verify_line_commitments(
+            version,
+            row_root,
+            LineAxis::Row,
+            length,
+            &rids,
+            &swapped,
+            &path
+        ));
+        assert!(!verify_line_commitments(
+            version,
+            row_root,
+            LineAxis::Row,
+            length,
+            &rids[1..],
+            &coms[1..],
+            &path
+        ));
+        let cids = [0, 5];
+        let cols: Vec<_> = cids.iter().map(|cid| prover.commit_to_col(*cid)).collect();
+        let col_path = prover.col_commitments_proof(&cids);
+        assert!(verify_line_commitments(
+            version,
+            prover.col_root(),
+            LineAxis::Col,
+            length,
+            &cids,
+            &cols,
+            &col_path
+        ));
+        assert!(!verify_line_commitments(
+            version,
+            row_root,
+            LineAxis::Row,
+            length,
+            &cids,
+            &cols,
+            &col_path
+        ));
+        // a column's commitment does not pass for the row of the same index
+        assert!(!verify_line_commitments(
+            version,
+            prover.col_root(),
+            LineAxis::Row,
+            length,
+            &cids,
+            &cols,
+            &col_path
+        ));
+    }
+
+    #[test]
+    pub fn row_spans_verify_with_one_proof() {
+        let shares = counting_square(4);
+        let scale = 2;
+        let length = shares.len() * scale;
+
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let vk = verifier_key(prover.params());
+        let commitment = prover.commit_to_row(2);
+        let data_root: Vec<u8> = prover.root().into();
+        let row = |rid| ChallengeContext {
+            height: 0,
+            data_root: &data_root,
+            axis: LineAxis::Row,
+            idx: rid,
+        };
+        let cids = [2, 3, 4, 5, 6];
+        let values: Vec<Fr> = cids
+            .iter()
+            .map(|cid| prover.square().val_at(2, *cid))
+            .collect();
+        let proof =
+            RangeProof::<Bls12_381>::from_bytes(&prover.range_proof(2, &cids).to_bytes()).unwrap();
+        assert!(verify_range_proof::<_, Sha256>(
+            &vk,
+            &row(2),
+            &commitment,
+            length,
+            &cids,
+            &values,
+            &proof
+        ));
+
+        // the proof vouches for exactly the values and cells it was made for
+        let mut tampered = values.clone();
+        tampered[3] += Fr::from(1);
+        assert!(!verify_range_proof::<_, Sha256>(
+            &vk,
+            &row(2),
+            &commitment,
+            length,
+            &cids,
+            &tampered,
+            &proof
+        ));
+        assert!(!verify_range_proof::<_, Sha256>(
+            &vk,
+            &row(2),
+            &commitment,
+            length,
+            &cids[1..],
+            &values[1..],
+            &proof
+        ));
+        assert!(!verify_range_proof::<_, Sha256>(
+            &vk,
+            &row(2),
+            &prover.commit_to_row(3),
+            length,
+            &cids,
+            &values,
+            &proof
+        ));
+
+        // nor for another block or row
+        let replayed = ChallengeContext {
+            height: 1,
+            ..row(2)
+        };
+        for context in [replayed, row(3)] {
+            assert!(!verify_range_proof::<_, Sha256>(
+                &vk,
+                &context,
+                &commitment,
+                length,
+                &cids,
+                &values,
+                &proof
+            ));
+        }
+
+        // a single cell is a span too
+        let single = prover.range_proof(7, &[0]);
+        let value = prover.square().val_at(7, 0);
+        assert!(verify_range_proof::<_, Sha256>(
+            &vk,
+            &row(7),
+            &prover.commit_to_row(7),
+            length,
+            &[0],
+            &[value],
+            &single
+        ));
+    }
+
+    #[test]
+    pub fn whole_lines_verify() {
+        let shares = counting_square(4);
+        let scale = 2;
+        let length = shares.len() * scale;
+
+        let prover = RsSquareProver::<Bls12_381, Sha256>::new(&shares, scale);
+        let vk = verifier_key(prover.params());
+        let (height, root) = (prover.height(), prover.root());
+        let version = prover.root_version();
+
+        let row = prover.sample_row(3);
+        assert!(verify_line_sample(&vk, height, version, root, length, &row));
+        let col =
+            LineSample::<Bls12_381, Sha256>::from_bytes(&prover.sample_col(6).to_bytes()).unwrap();
+        assert!(verify_line_sample(&vk, height, version, root, length, &col));
+
+        let mut tampered = col;
+        tampered.values[0] += Fr::from(1);
+        assert!(!verify_line_sample(
+            &vk, height, version, root, length, &tampered
+        ));
+
+        let row_root = prover.row_root();
+        let custody =
+            RowProof::<Bls12_381, Sha256>::from_bytes(&prover.row_proof(5).to_bytes()).unwrap();
+        assert!(verify_row_proof(
+            &vk, height, version, root, row_root, length, &custody
+        ));
+        assert!(!verify_row_proof(
+            &vk,
+            height,
+            version,
+            root,
+            prover.col_root(),
+            length,
+            &custody
+        ));
+
+        // samples and proofs are bound to the block they were made for, whatever
+        // height they claim
+        let mut replayed = prover.sample_row(3);
+        assert!(!verify_line_sample(
+            &vk,
+            height + 1,
+            version,
+            root,
+            length,
+            &replayed
+        ));
+        replayed.height = height + 1;
+        assert!(!verify_line_sample(
+            &vk,
+            height + 1,
+            version,
+            root,
+            length,
+            &replayed
+        ));
+        assert!(!verify_row_proof(
+            &vk,
+            height + 1,
+            version,
+            root,
+            row_root,
+            length,
+            &custody
+        ));
+        let mut relabelled = custody;
+        relabelled.height = height + 1;
+        assert!(!verify_row_proof(
+            &vk,
+            height + 1,
+            version,
+            root,
+            row_root,
+            length,
+            &relabelled
+        ));
+
+        // rows and columns of an honest square always agree
+        let cell = prover.sample_cell(2, 6);
+        let col = prover.sample_col(6);
+        assert!(!verify_bad_encoding(
+            &vk, height, version, root, length, &cell, &col
+        ));
+    }
+}